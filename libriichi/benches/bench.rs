@@ -24,6 +24,7 @@ fn criterion_benchmark(c: &mut Criterion) {
                 jikaze: tu8!(N),
                 winning_tile: tu8!(9m),
                 is_ron: true,
+                allow_kuitan: true,
             };
             calc.search_yakus().unwrap();
         });
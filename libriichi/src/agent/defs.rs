@@ -36,6 +36,19 @@ pub trait Agent {
     }
 }
 
+/// `BatchAgent` serves many seats at once, possibly across many concurrent
+/// games, so that the decisions can be evaluated in batches.
+///
+/// Each seat is identified by `index`, fixed by the caller for the whole
+/// game. At every step, the caller first calls `set_scene` for every seat
+/// that can act, across all the games it runs, and only then calls
+/// `get_reaction` for each of them, in the same order as the `set_scene`s.
+/// No `set_scene` comes in between, so an implementor may defer the
+/// evaluation to the first `get_reaction` of a step.
+///
+/// The reactions are only the wishes of the seats. When several seats claim
+/// the same discard, such as a ron and a pon, the caller decides which one
+/// takes effect, not the agent.
 pub trait BatchAgent {
     fn name(&self) -> String;
     fn need_oracle_obs(&self) -> bool {
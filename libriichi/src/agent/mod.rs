@@ -2,10 +2,12 @@ mod akochan;
 mod batchify;
 mod defs;
 mod mortal;
+mod rule_based;
 mod tsumogiri;
 
 pub use akochan::AkochanAgent;
 pub use batchify::BatchifiedAgent;
 pub use defs::{Agent, BatchAgent, InvisibleState};
 pub use mortal::MortalBatchAgent;
+pub use rule_based::RuleBased;
 pub use tsumogiri::Tsumogiri;
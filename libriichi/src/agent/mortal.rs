@@ -1,8 +1,8 @@
 use super::{BatchAgent, InvisibleState};
 use crate::consts::ACTION_SPACE;
 use crate::mjai::{Event, EventExt, Metadata};
+use crate::must_tile;
 use crate::state::PlayerState;
-use crate::{must_tile, tu8};
 use std::time::{Duration, Instant};
 
 use anyhow::{ensure, Context, Result};
@@ -269,10 +269,6 @@ impl BatchAgent for MortalBatchAgent {
         let action_idx = self.action_idxs[index];
         let kan_select_idx = self.kan_action_idxs[index].take();
 
-        let actor = self.player_ids[index];
-        let akas_in_hand = state.akas_in_hand();
-        let cans = state.last_cans();
-
         let orig_action = self.actions[action_idx];
         let action =
             if self.enable_rule_based_agari_guard && orig_action == 43 && !state.rule_based_agari()
@@ -289,241 +285,10 @@ impl BatchAgent for MortalBatchAgent {
                 orig_action
             };
 
-        let event = match action {
-            0..=36 => {
-                ensure!(
-                    cans.can_discard,
-                    "failed discard check: {}",
-                    state.brief_info()
-                );
-
-                let pai = must_tile!(action);
-                let tsumogiri = matches!(state.last_self_tsumo(), Some(t) if t == pai);
-                Event::Dahai {
-                    actor,
-                    pai,
-                    tsumogiri,
-                }
-            }
-
-            37 => {
-                ensure!(
-                    cans.can_riichi,
-                    "failed riichi check: {}",
-                    state.brief_info()
-                );
-
-                Event::Reach { actor }
-            }
-
-            38 => {
-                ensure!(
-                    cans.can_chi_low,
-                    "failed chi low check: {}",
-                    state.brief_info()
-                );
-
-                let pai = state
-                    .last_kawa_tile()
-                    .context("invalid state: no last kawa tile")?;
-                let first = pai.next();
-
-                let can_akaize_consumed = match pai.as_u8() {
-                    tu8!(3m) | tu8!(4m) => akas_in_hand[0],
-                    tu8!(3p) | tu8!(4p) => akas_in_hand[1],
-                    tu8!(3s) | tu8!(4s) => akas_in_hand[2],
-                    _ => false,
-                };
-                let consumed = if can_akaize_consumed {
-                    [first.akaize(), first.next().akaize()]
-                } else {
-                    [first, first.next()]
-                };
-                Event::Chi {
-                    actor,
-                    target: cans.target_actor,
-                    pai,
-                    consumed,
-                }
-            }
-            39 => {
-                ensure!(
-                    cans.can_chi_mid,
-                    "failed chi mid check: {}",
-                    state.brief_info()
-                );
-
-                let pai = state
-                    .last_kawa_tile()
-                    .context("invalid state: no last kawa tile")?;
-
-                let can_akaize_consumed = match pai.as_u8() {
-                    tu8!(4m) | tu8!(6m) => akas_in_hand[0],
-                    tu8!(4p) | tu8!(6p) => akas_in_hand[1],
-                    tu8!(4s) | tu8!(6s) => akas_in_hand[2],
-                    _ => false,
-                };
-                let consumed = if can_akaize_consumed {
-                    [pai.prev().akaize(), pai.next().akaize()]
-                } else {
-                    [pai.prev(), pai.next()]
-                };
-                Event::Chi {
-                    actor,
-                    target: cans.target_actor,
-                    pai,
-                    consumed,
-                }
-            }
-            40 => {
-                ensure!(
-                    cans.can_chi_high,
-                    "failed chi high check: {}",
-                    state.brief_info()
-                );
-
-                let pai = state
-                    .last_kawa_tile()
-                    .context("invalid state: no last kawa tile")?;
-                let last = pai.prev();
-
-                let can_akaize_consumed = match pai.as_u8() {
-                    tu8!(6m) | tu8!(7m) => akas_in_hand[0],
-                    tu8!(6p) | tu8!(7p) => akas_in_hand[1],
-                    tu8!(6s) | tu8!(7s) => akas_in_hand[2],
-                    _ => false,
-                };
-                let consumed = if can_akaize_consumed {
-                    [last.prev().akaize(), last.akaize()]
-                } else {
-                    [last.prev(), last]
-                };
-                Event::Chi {
-                    actor,
-                    target: cans.target_actor,
-                    pai,
-                    consumed,
-                }
-            }
-
-            41 => {
-                ensure!(cans.can_pon, "failed pon check: {}", state.brief_info());
-
-                let pai = state
-                    .last_kawa_tile()
-                    .context("invalid state: no last kawa tile")?;
-
-                let can_akaize_consumed = match pai.as_u8() {
-                    tu8!(5m) => akas_in_hand[0],
-                    tu8!(5p) => akas_in_hand[1],
-                    tu8!(5s) => akas_in_hand[2],
-                    _ => false,
-                };
-                let consumed = if can_akaize_consumed {
-                    [pai.akaize(), pai.deaka()]
-                } else {
-                    [pai.deaka(); 2]
-                };
-                Event::Pon {
-                    actor,
-                    target: cans.target_actor,
-                    pai,
-                    consumed,
-                }
-            }
-
-            42 => {
-                ensure!(
-                    cans.can_daiminkan || cans.can_ankan || cans.can_kakan,
-                    "failed kan check: {}",
-                    state.brief_info()
-                );
-
-                let ankan_candidates = state.ankan_candidates();
-                let kakan_candidates = state.kakan_candidates();
-
-                let tile = if let Some(kan_idx) = kan_select_idx {
-                    let tile = must_tile!(self.actions[kan_idx]);
-                    ensure!(
-                        ankan_candidates.contains(&tile) || kakan_candidates.contains(&tile),
-                        "kan choice not in kan candidates: {}",
-                        state.brief_info()
-                    );
-                    tile
-                } else if cans.can_daiminkan {
-                    state
-                        .last_kawa_tile()
-                        .context("invalid state: no last kawa tile")?
-                } else if cans.can_ankan {
-                    ankan_candidates[0]
-                } else {
-                    kakan_candidates[0]
-                };
-
-                if cans.can_daiminkan {
-                    let consumed = if tile.is_aka() {
-                        [tile.deaka(); 3]
-                    } else {
-                        [tile.akaize(), tile, tile]
-                    };
-                    Event::Daiminkan {
-                        actor,
-                        target: cans.target_actor,
-                        pai: tile,
-                        consumed,
-                    }
-                } else if cans.can_ankan && ankan_candidates.contains(&tile.deaka()) {
-                    Event::Ankan {
-                        actor,
-                        consumed: [tile.akaize(), tile, tile, tile],
-                    }
-                } else {
-                    let can_akaize_target = match tile.as_u8() {
-                        tu8!(5m) => akas_in_hand[0],
-                        tu8!(5p) => akas_in_hand[1],
-                        tu8!(5s) => akas_in_hand[2],
-                        _ => false,
-                    };
-                    let (pai, consumed) = if can_akaize_target {
-                        (tile.akaize(), [tile.deaka(); 3])
-                    } else {
-                        (tile.deaka(), [tile.akaize(), tile.deaka(), tile.deaka()])
-                    };
-                    Event::Kakan {
-                        actor,
-                        pai,
-                        consumed,
-                    }
-                }
-            }
-
-            43 => {
-                ensure!(
-                    cans.can_tsumo_agari || cans.can_ron_agari,
-                    "failed hora check: {}",
-                    state.brief_info()
-                );
-
-                Event::Hora {
-                    actor,
-                    target: cans.target_actor,
-                    deltas: None,
-                    ura_markers: None,
-                }
-            }
-
-            44 => {
-                ensure!(
-                    cans.can_ryukyoku,
-                    "failed ryukyoku check: {}",
-                    state.brief_info()
-                );
-
-                Event::Ryukyoku { deltas: None }
-            }
-
-            // 45
-            _ => Event::None,
+        let event = if let Some(kan_idx) = kan_select_idx.filter(|_| action == 42) {
+            state.action_to_event(self.actions[kan_idx], true, true)?
+        } else {
+            state.action_to_event(action, false, true)?
         };
 
         let mut meta = self.gen_meta(state, action_idx);
@@ -0,0 +1,260 @@
+use super::{Agent, BatchifiedAgent, InvisibleState};
+use crate::algo::shanten;
+use crate::mjai::EventExt;
+use crate::must_tile;
+use crate::state::{PlayerState, Reaction};
+use crate::tile::Tile;
+use std::cmp::Reverse;
+
+use anyhow::{Context, Result};
+
+/// `RuleBased` is a deterministic baseline that needs no model.
+///
+/// In order of precedence, it:
+/// - agaris and ryukyokus as `rule_based_agari` and `rule_based_ryukyoku`
+///   suggest;
+/// - riichis if the wait after one of `recommended_riichi_discards` has at
+///   least `Self::RIICHI_MIN_LIVE_TILES` unseen copies;
+/// - after its riichi declaration, discards the tile leaving the most unseen
+///   copies of the waits, preferring `recommended_riichi_discards` so that
+///   the wait is not furiten;
+/// - folds when an opponent's riichi is accepted and its own shanten is
+///   greater than 1, discarding the tile with the lowest
+///   `danger_scores_against` the riichi players;
+/// - otherwise discards the tile that leaves the lowest shanten and then the
+///   most unseen tiles to advance with, avoiding the tiles of
+///   `yakuman_threat_tiles`;
+/// - pons or chis only if the call makes the hand tenpai with a yaku after
+///   some discard, as `discard_candidates_with_unconditional_tenpai` tells,
+///   and never kans.
+///
+/// Ties are broken by preferring the tile with the highest id (honors first)
+/// and keeping the akas. Every decision is one of `legal_reactions`.
+pub struct RuleBased(pub u8);
+
+impl RuleBased {
+    /// The minimum number of unseen copies of the waits to riichi with.
+    pub const RIICHI_MIN_LIVE_TILES: u8 = 4;
+
+    pub fn new_batched(player_ids: &[u8]) -> Result<BatchifiedAgent<Self>> {
+        BatchifiedAgent::new(|id| Ok(Self(id)), player_ids)
+    }
+}
+
+impl Agent for RuleBased {
+    fn name(&self) -> String {
+        "rule_based".to_owned()
+    }
+
+    fn react(
+        &mut self,
+        _: &[EventExt],
+        state: &PlayerState,
+        _: Option<InvisibleState>,
+    ) -> Result<EventExt> {
+        let reactions = state.legal_reactions();
+        let has = |r| reactions.contains(&r);
+        let discards: Vec<_> = reactions
+            .iter()
+            .filter_map(|&r| match r {
+                Reaction::Discard(tile) => Some(tile),
+                _ => None,
+            })
+            .collect();
+
+        let reaction = if state.rule_based_agari() {
+            if has(Reaction::Tsumo) {
+                Reaction::Tsumo
+            } else {
+                Reaction::Ron
+            }
+        } else if state.rule_based_ryukyoku() {
+            Reaction::Ryukyoku
+        } else if has(Reaction::Riichi) && riichi_live_tiles(state) >= Self::RIICHI_MIN_LIVE_TILES {
+            Reaction::Riichi
+        } else if state.last_cans().can_discard {
+            choose_discard(state, &discards)
+                .map(Reaction::Discard)
+                .context("no discard candidate")?
+        } else {
+            reactions
+                .iter()
+                .copied()
+                .find(|&r| is_tenpai_call(state, r))
+                .unwrap_or(Reaction::Pass)
+        };
+
+        Ok(EventExt::no_meta(state.reaction_to_event(reaction)))
+    }
+}
+
+fn choose_discard(state: &PlayerState, discards: &[Tile]) -> Option<Tile> {
+    // `min_by_key` picks the first of the minimums, so iterate backwards to
+    // prefer the higher tile id.
+    let candidates = discards.iter().rev().copied();
+
+    if state.self_riichi_declared() && !state.self_riichi_accepted() {
+        let recommended = state.recommended_riichi_discards().discards;
+        let tehai = state.tehai();
+        return candidates.min_by_key(|tile| {
+            let mut tehai_3n1 = tehai;
+            tehai_3n1[tile.deaka().as_usize()] -= 1;
+            (
+                !recommended[tile.as_usize()],
+                Reverse(live_tiles(state, &tehai_3n1)),
+                tile.is_aka(),
+            )
+        });
+    }
+
+    let riichi_players: Vec<_> = (1..4)
+        .filter(|&rel| state.riichi_accepted()[rel as usize])
+        .collect();
+    if !riichi_players.is_empty() && state.shanten() > 1 {
+        let dangers: Vec<_> = riichi_players
+            .iter()
+            .map(|&rel| state.danger_scores_against(rel))
+            .collect();
+        return candidates.min_by_key(|tile| {
+            let tid = tile.deaka().as_usize();
+            let danger = dangers.iter().map(|d| d[tid]).max();
+            (danger, tile.is_aka())
+        });
+    }
+
+    let shantens = state.shanten_after_discard();
+    let mut threats = [false; 34];
+    for threat in state.yakuman_threat_tiles() {
+        for (t, b) in threats.iter_mut().zip(threat.tiles) {
+            *t |= b;
+        }
+    }
+    let tehai = state.tehai();
+    candidates.min_by_key(|tile| {
+        let tid = tile.deaka().as_usize();
+        let mut tehai_3n1 = tehai;
+        tehai_3n1[tid] -= 1;
+        (
+            threats[tid],
+            shantens[tid],
+            Reverse(ukeire(state, &tehai_3n1)),
+            tile.is_aka(),
+        )
+    })
+}
+
+/// The most unseen copies of the waits among the `recommended_riichi_discards`.
+fn riichi_live_tiles(state: &PlayerState) -> u8 {
+    let tehai = state.tehai();
+    state
+        .recommended_riichi_discards()
+        .discards
+        .iter()
+        .enumerate()
+        .filter(|(_, &b)| b)
+        .map(|(idx, _)| {
+            let mut tehai_3n1 = tehai;
+            tehai_3n1[must_tile!(idx).deaka().as_usize()] -= 1;
+            live_tiles(state, &tehai_3n1)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The unseen copies of the tiles completing `tehai_3n1`.
+fn live_tiles(state: &PlayerState, tehai_3n1: &[u8; 34]) -> u8 {
+    let len_div3 = tehai_3n1.iter().sum::<u8>() / 3;
+    let unseen = state.tiles_unseen();
+    (0..34)
+        .filter(|&tid| unseen[tid] > 0)
+        .filter(|&tid| {
+            let mut tehai_3n2 = *tehai_3n1;
+            tehai_3n2[tid] += 1;
+            shanten::calc_all(&tehai_3n2, len_div3) == -1
+        })
+        .map(|tid| unseen[tid])
+        .sum()
+}
+
+/// The unseen copies of the tiles lowering the shanten of `tehai_3n1`.
+fn ukeire(state: &PlayerState, tehai_3n1: &[u8; 34]) -> u8 {
+    let len_div3 = tehai_3n1.iter().sum::<u8>() / 3;
+    let shanten = shanten::calc_all(tehai_3n1, len_div3);
+    let unseen = state.tiles_unseen();
+    (0..34)
+        .filter(|&tid| unseen[tid] > 0)
+        .filter(|&tid| {
+            let mut tehai_3n2 = *tehai_3n1;
+            tehai_3n2[tid] += 1;
+            shanten::calc_all(&tehai_3n2, len_div3) < shanten
+        })
+        .map(|tid| unseen[tid])
+        .sum()
+}
+
+/// Whether `reaction` is a pon or chi that makes the hand tenpai with a yaku
+/// after some discard, while it is not tenpai now.
+fn is_tenpai_call(state: &PlayerState, reaction: Reaction) -> bool {
+    if !matches!(reaction, Reaction::Pon { .. } | Reaction::Chi { .. }) || state.shanten() <= 0 {
+        return false;
+    }
+    let mut after = state.clone();
+    after.update(&state.reaction_to_event(reaction));
+    after
+        .discard_candidates_with_unconditional_tenpai()
+        .contains(&true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Tsumogiri;
+    use crate::arena::DuplicateSession;
+    use crate::mjai::Event;
+    use crate::t;
+
+    #[test]
+    fn beats_tsumogiri() {
+        let session = DuplicateSession {
+            disable_progress_bar: true,
+            ..Default::default()
+        };
+        let seeds: Vec<_> = (0..25).collect();
+        let stats = session
+            .run(|id| Ok(RuleBased(id)), |id| Ok(Tsumogiri(id)), &seeds)
+            .unwrap();
+        assert_eq!(stats.games(), 100);
+        assert!(stats.avg_rank() < 1.7, "avg rank {}", stats.avg_rank());
+    }
+
+    #[test]
+    fn riichi_discard_avoids_furiten() {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","2s","3s","4s","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"1p"}
+            {"type":"dahai","actor":0,"pai":"5s","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"?"}
+            {"type":"dahai","actor":1,"pai":"E","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"S","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"5s"}
+            {"type":"reach","actor":0}
+        "#;
+        let (state, cans) = PlayerState::from_log_str(0, log).unwrap();
+        assert!(cans.can_discard);
+
+        // Discarding 1p waits on 2s and 5s, which have more unseen copies than
+        // the 1p tanki left by discarding 5s, but 5s is already discarded.
+        let ev = RuleBased(0).react(&[], &state, None).unwrap().event;
+        assert_eq!(
+            ev,
+            Event::Dahai {
+                actor: 0,
+                pai: t!(5s),
+                tsumogiri: true,
+            },
+        );
+    }
+}
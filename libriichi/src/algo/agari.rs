@@ -6,8 +6,9 @@
 //! * Java: <http://hp.vector.co.jp/authors/VA046927/mjscore/AgariIndex.java>
 //! * Algorithm: <http://hp.vector.co.jp/authors/VA046927/mjscore/mjalgorism.html>
 
-use super::point::Point;
+use super::point::{Limit, Point};
 use super::shanten;
+use super::yaku::Yaku;
 use crate::tile::Tile;
 use crate::{matches_tu8, must_tile, tu8};
 use std::cmp::Ordering;
@@ -18,6 +19,7 @@ use boomphf::hashmap::BoomHashMap;
 use byteorder::{LittleEndian, ReadBytesExt};
 use flate2::read::GzDecoder;
 use once_cell::sync::Lazy;
+use tinyvec::{array_vec, ArrayVec};
 
 const AGARI_TABLE_SIZE: usize = 9_362;
 
@@ -57,6 +59,9 @@ struct Div {
     has_ipeikou: bool,
 }
 
+/// The yakus that make up the han of an `Agari`, with 13 for each yakuman.
+pub type YakuList = ArrayVec<[(Yaku, u8); 16]>;
+
 #[derive(Debug, Clone, Copy, Eq)]
 pub enum Agari {
     /// `fu` may be 0 if `han` is greater than 4.
@@ -87,6 +92,8 @@ pub struct AgariCalculator<'a> {
     /// ankou/ankan-related yakus like 三/四暗刻. It will not be used to
     /// determine 門前清自摸和.
     pub is_ron: bool,
+    /// Whether 断幺九 counts in an open hand (喰いタン).
+    pub allow_kuitan: bool,
 }
 
 struct DivWorker<'sup, 'a> {
@@ -184,6 +191,15 @@ impl Agari {
             Agari::Yakuman(n) => Point::yakuman(is_oya, n as i32),
         }
     }
+
+    #[inline]
+    #[must_use]
+    pub fn limit(self) -> Option<Limit> {
+        match self {
+            Agari::Normal { fu, han } => Limit::of(fu, han),
+            Agari::Yakuman(_) => Some(Limit::Yakuman),
+        }
+    }
 }
 
 impl AgariCalculator<'_> {
@@ -196,6 +212,13 @@ impl AgariCalculator<'_> {
     #[inline]
     #[must_use]
     pub fn search_yakus(&self) -> Option<Agari> {
+        self.search_yakus_detail().map(|(agari, _)| agari)
+    }
+
+    /// Same as `search_yakus`, but also returns the yakus found.
+    #[inline]
+    #[must_use]
+    pub fn search_yakus_detail(&self) -> Option<(Agari, YakuList)> {
         self.search_yakus_impl(false)
     }
 
@@ -207,23 +230,34 @@ impl AgariCalculator<'_> {
     ///
     /// This function is designed to be called by only callers who have the
     /// knowledge of the ura doras.
+    #[inline]
     #[must_use]
     pub fn agari(&self, additional_hans: u8, doras: u8) -> Option<Agari> {
-        if let Some(agari) = self.search_yakus() {
-            Some(match agari {
+        self.agari_detail(additional_hans, doras)
+            .map(|(agari, _)| agari)
+    }
+
+    /// Same as `agari`, but also returns the yakus found in the hand, which do
+    /// not include `additional_hans` and `doras`.
+    #[must_use]
+    pub fn agari_detail(&self, additional_hans: u8, doras: u8) -> Option<(Agari, YakuList)> {
+        if let Some((agari, yakus)) = self.search_yakus_detail() {
+            let agari = match agari {
                 Agari::Normal { fu, han } => Agari::Normal {
                     fu,
                     han: han + additional_hans + doras,
                 },
                 _ => agari,
-            })
+            };
+            Some((agari, yakus))
         } else if additional_hans == 0 {
             None
         } else if additional_hans + doras >= 5 {
-            Some(Agari::Normal {
+            let agari = Agari::Normal {
                 fu: 0,
                 han: additional_hans + doras,
-            })
+            };
+            Some((agari, array_vec!()))
         } else {
             let (tile14, key) = get_tile14_and_key(self.tehai);
             let divs = AGARI_TABLE.get(&key)?;
@@ -233,14 +267,15 @@ impl AgariCalculator<'_> {
                 .map(|div| DivWorker::new(self, &tile14, div))
                 .map(|w| w.calc_fu(false))
                 .max()?;
-            Some(Agari::Normal {
+            let agari = Agari::Normal {
                 fu,
                 han: additional_hans + doras,
-            })
+            };
+            Some((agari, array_vec!()))
         }
     }
 
-    fn search_yakus_impl(&self, return_if_any: bool) -> Option<Agari> {
+    fn search_yakus_impl(&self, return_if_any: bool) -> Option<(Agari, YakuList)> {
         assert_eq!(
             self.is_menzen,
             self.chis.is_empty() && self.pons.is_empty() && self.minkans.is_empty(),
@@ -250,7 +285,10 @@ impl AgariCalculator<'_> {
         // with other div-han.
         if self.is_menzen && shanten::calc_kokushi(self.tehai) == -1 {
             // 国士無双
-            return Some(Agari::Yakuman(1));
+            return Some((
+                Agari::Yakuman(1),
+                array_vec!([(Yaku, u8); 16] => (Yaku::Kokushi, 13)),
+            ));
         }
 
         let (tile14, key) = get_tile14_and_key(self.tehai);
@@ -265,7 +303,7 @@ impl AgariCalculator<'_> {
             divs.iter()
                 .map(|div| DivWorker::new(self, &tile14, div))
                 .filter_map(|w| w.search_yakus::<false>())
-                .max()
+                .max_by_key(|&(agari, _)| agari)
         }
     }
 }
@@ -428,9 +466,10 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
         ((fu - 1) / 10 + 1) * 10
     }
 
-    fn search_yakus<const RETURN_IF_ANY: bool>(&self) -> Option<Agari> {
+    fn search_yakus<const RETURN_IF_ANY: bool>(&self) -> Option<(Agari, YakuList)> {
         let mut han = 0;
         let mut yakuman = 0;
+        let mut yakus = YakuList::new();
 
         let has_pinfu = self.menzen_shuntsu.len() == 4
             && !matches_tu8!(self.pair_tile, P | F | C)
@@ -444,22 +483,34 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
         macro_rules! make_return {
             () => {
                 return if yakuman > 0 {
-                    Some(Agari::Yakuman(yakuman))
+                    yakus.retain(|(y, _)| y.is_yakuman());
+                    Some((Agari::Yakuman(yakuman), yakus))
                 } else if han > 0 {
                     let fu = if RETURN_IF_ANY || han >= 5 {
                         0
                     } else {
                         self.calc_fu(has_pinfu)
                     };
-                    Some(Agari::Normal { fu, han })
+                    Some((Agari::Normal { fu, han }, yakus))
                 } else {
                     None
                 };
             };
         }
-        macro_rules! check_early_return {
-            ($($block:tt)*) => {{
-                $($block)*;
+        macro_rules! add_han {
+            ($yaku:expr, $n:expr) => {{
+                let n = $n;
+                han += n;
+                yakus.push(($yaku, n));
+                if RETURN_IF_ANY {
+                    make_return!();
+                }
+            }};
+        }
+        macro_rules! add_yakuman {
+            ($yaku:expr) => {{
+                yakuman += 1;
+                yakus.push(($yaku, 13));
                 if RETURN_IF_ANY {
                     make_return!();
                 }
@@ -468,19 +519,19 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
 
         if has_pinfu {
             // 平和
-            check_early_return! { han += 1 };
+            add_han!(Yaku::Pinfu, 1);
         }
         if self.div.has_chitoi {
             // 七対子
-            check_early_return! { han += 2 };
+            add_han!(Yaku::Chiitoitsu, 2);
         }
         if self.div.has_ryanpeikou {
             // 二盃口
-            check_early_return! { han += 3 };
+            add_han!(Yaku::Ryanpeikou, 3);
         }
         if self.div.has_chuuren {
             // 九蓮宝燈
-            check_early_return! { yakuman += 1 };
+            add_yakuman!(Yaku::Chuuren);
         }
 
         let has_tanyao = if self.div.has_chitoi {
@@ -502,16 +553,16 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                     kind < 3 && num > 0 && num < 8
                 })
         };
-        if has_tanyao {
+        if has_tanyao && (self.sup.is_menzen || self.sup.allow_kuitan) {
             // 断幺九
-            check_early_return! { han += 1 };
+            add_han!(Yaku::Tanyao, 1);
         }
 
         let has_toitoi =
             !self.div.has_chitoi && self.menzen_shuntsu.is_empty() && self.sup.chis.is_empty();
         if has_toitoi {
             // 対々和
-            check_early_return! { han += 2 };
+            add_han!(Yaku::Toitoi, 2);
         }
 
         let mut isou_kind = None;
@@ -543,17 +594,22 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
         }
         if isou_kind.is_none() {
             // 字一色
-            check_early_return! { yakuman += 1 };
+            add_yakuman!(Yaku::Tsuuiisou);
         } else if is_chinitsu_or_honitsu {
             // 混一色, 清一色
             let n = if has_jihai { 2 } else { 5 } + self.sup.is_menzen as u8;
-            check_early_return! { han += n };
+            let yaku = if has_jihai {
+                Yaku::Honitsu
+            } else {
+                Yaku::Chinitsu
+            };
+            add_han!(yaku, n);
         }
 
         if !self.div.has_chitoi {
             // 一盃口
             if self.div.has_ipeikou {
-                check_early_return! { han += 1 };
+                add_han!(Yaku::Iipeikou, 1);
             } else if !self.sup.ankans.is_empty()
                 && self.sup.is_menzen
                 && self.menzen_shuntsu.len() >= 2
@@ -571,15 +627,15 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                     }
                 });
                 if has_ipeikou {
-                    check_early_return! { han += 1 };
+                    add_han!(Yaku::Iipeikou, 1);
                 }
             }
 
             // 一気通貫
             if self.sup.is_menzen && self.div.has_ittsuu {
-                check_early_return! { han += 2 };
+                add_han!(Yaku::Ittsuu, 2);
             } else if self.sup.chis.is_empty() && self.div.has_ittsuu {
-                check_early_return! { han += 1 };
+                add_han!(Yaku::Ittsuu, 1);
             } else if self.menzen_shuntsu.len() + self.sup.chis.len() >= 3 {
                 let mut kinds = [0; 3];
                 for s in self.all_shuntsu() {
@@ -593,7 +649,7 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                     };
                 }
                 if kinds.contains(&0b111) {
-                    check_early_return! { han += 1 };
+                    add_han!(Yaku::Ittsuu, 1);
                 }
             }
 
@@ -606,7 +662,7 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
             if s_counter.contains(&0b111) {
                 // 三色同順
                 let n = if self.sup.is_menzen { 2 } else { 1 };
-                check_early_return! { han += n };
+                add_han!(Yaku::SanshokuDoujun, n);
             } else {
                 let mut k_counter = [0; 9];
                 for k in self.all_kotsu_and_kantsu() {
@@ -618,7 +674,7 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                 }
                 if k_counter.contains(&0b111) {
                     // 三色同刻
-                    check_early_return! { han += 2 };
+                    add_han!(Yaku::SanshokuDoukou, 2);
                 }
             }
 
@@ -626,18 +682,18 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                 - self.winning_tile_makes_minkou as usize;
             match ankous_count {
                 // 四暗刻
-                4 => check_early_return! { yakuman += 1 },
+                4 => add_yakuman!(Yaku::Suuankou),
                 // 三暗刻
-                3 => check_early_return! { han += 2 },
+                3 => add_han!(Yaku::Sanankou, 2),
                 _ => (),
             };
 
             let kans_count = self.sup.ankans.len() + self.sup.minkans.len();
             match kans_count {
                 // 四槓子
-                4 => check_early_return! { yakuman += 1 },
+                4 => add_yakuman!(Yaku::Suukantsu),
                 // 三槓子
-                3 => check_early_return! { han += 2 },
+                3 => add_han!(Yaku::Sankantsu, 2),
                 _ => (),
             };
 
@@ -653,7 +709,7 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                 && self.all_shuntsu().all(|s| s == tu8!(2s)); // only 234s is possible for shuntsu in ryuisou
             if has_ryuisou {
                 // 緑一色
-                check_early_return! { yakuman += 1 };
+                add_yakuman!(Yaku::Ryuuiisou);
             }
 
             if !has_tanyao {
@@ -665,35 +721,36 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                     }
                 }
                 if has_jihai[self.sup.bakaze as usize - 3 * 9] {
-                    // 役牌:門風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:場風牌
+                    add_han!(Yaku::Bakaze, 1);
                 }
                 if has_jihai[self.sup.jikaze as usize - 3 * 9] {
-                    // 役牌:場風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:門風牌
+                    add_han!(Yaku::Jikaze, 1);
                 }
 
-                let saneins = (4..7).filter(|&i| has_jihai[i]).count() as u8;
-                if saneins > 0 {
-                    // 役牌:三元牌
-                    check_early_return! { han += saneins };
-                    if saneins == 3 {
-                        // 大三元
-                        check_early_return! { yakuman += 1 };
-                    } else if saneins == 2 && matches_tu8!(self.pair_tile, P | F | C) {
-                        // 小三元
-                        check_early_return! { han += 2 };
+                // 役牌:三元牌
+                for (i, yaku) in [(4, Yaku::Haku), (5, Yaku::Hatsu), (6, Yaku::Chun)] {
+                    if has_jihai[i] {
+                        add_han!(yaku, 1);
                     }
                 }
+                let saneins = (4..7).filter(|&i| has_jihai[i]).count();
+                if saneins == 3 {
+                    // 大三元
+                    add_yakuman!(Yaku::Daisangen);
+                } else if saneins == 2 && matches_tu8!(self.pair_tile, P | F | C) {
+                    // 小三元
+                    add_han!(Yaku::Shousangen, 2);
+                }
 
                 let winds = (0..4).filter(|&i| has_jihai[i]).count() as u8;
-                #[allow(clippy::if_same_then_else)]
                 if winds == 4 {
                     // 大四喜
-                    check_early_return! { yakuman += 1 };
+                    add_yakuman!(Yaku::Daisuushii);
                 } else if winds == 3 && matches_tu8!(self.pair_tile, E | S | W | N) {
                     // 小四喜
-                    check_early_return! { yakuman += 1 };
+                    add_yakuman!(Yaku::Shousuushii);
                 }
             }
         }
@@ -721,10 +778,10 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                 if self.div.has_chitoi || has_toitoi {
                     if has_jihai {
                         // 混老頭
-                        check_early_return! { han += 2 };
+                        add_han!(Yaku::Honroutou, 2);
                     } else {
                         // 清老頭
-                        check_early_return! { yakuman += 1 };
+                        add_yakuman!(Yaku::Chinroutou);
                     }
                 } else {
                     let is_junchan_or_chanta = self.all_shuntsu().all(|s| {
@@ -734,7 +791,12 @@ impl<'sup, 'a> DivWorker<'sup, 'a> {
                     if is_junchan_or_chanta {
                         // 混全帯幺九, 純全帯幺九
                         let n = if has_jihai { 1 } else { 2 } + self.sup.is_menzen as u8;
-                        check_early_return! { han += n };
+                        let yaku = if has_jihai {
+                            Yaku::Chanta
+                        } else {
+                            Yaku::Junchan
+                        };
+                        add_han!(yaku, n);
                     }
                 }
             }
@@ -960,6 +1022,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(3m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 40, han: 4 });
@@ -976,6 +1039,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(3m),
             is_ron: false,
+            allow_kuitan: true,
         };
         let points = calc.agari(2, 0).unwrap().into_point(true);
         // 立直, 門前清自摸和
@@ -1000,6 +1064,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5p),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 25, han: 3 });
@@ -1017,6 +1082,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(4m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 1 });
@@ -1033,6 +1099,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(3m),
             is_ron: false,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 4 });
@@ -1049,6 +1116,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            allow_kuitan: true,
         };
         assert_eq!(calc.search_yakus(), None);
 
@@ -1064,6 +1132,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (without ankan)
@@ -1081,6 +1150,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (with ankan)
@@ -1098,6 +1168,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(7m),
             is_ron: false,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 四暗刻
@@ -1120,6 +1191,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 平和, 二盃口
@@ -1142,6 +1214,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1159,6 +1232,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1176,6 +1250,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: false,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 門前清自摸和 is not accounted.
@@ -1193,6 +1268,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(C),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Yakuman(3));
@@ -1209,6 +1285,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: false,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 純全, 三色
@@ -1226,6 +1303,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5s),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻 (5s is ankou)
@@ -1245,6 +1323,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(E),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 混全帯幺九, 役牌*1
@@ -1263,6 +1342,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(9m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 混一色, 混老頭, 役牌*3, 対々和
@@ -1291,6 +1371,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1309,6 +1390,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(5p),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 断么九
@@ -1327,6 +1409,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1s),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1345,6 +1428,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1362,9 +1446,64 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(C),
             is_ron: true,
+            allow_kuitan: true,
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻, 対々和, 混一色, 混老頭, 小三元, double 南, 白, 中
         assert!(matches!(yaku, Agari::Normal { han: 15, .. }));
     }
+    #[test]
+    fn yaku_detail() {
+        let tehai = hand("111s 2225556677z 7z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(S),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(C),
+            is_ron: true,
+            allow_kuitan: true,
+        };
+        let (agari, yakus) = calc.search_yakus_detail().unwrap();
+        assert_eq!(agari, calc.search_yakus().unwrap());
+        let mut yakus = yakus.to_vec();
+        yakus.sort_unstable();
+        assert_eq!(
+            yakus,
+            [
+                (Yaku::Bakaze, 1),
+                (Yaku::Jikaze, 1),
+                (Yaku::Haku, 1),
+                (Yaku::Chun, 1),
+                (Yaku::Toitoi, 2),
+                (Yaku::Sanankou, 2),
+                (Yaku::Shousangen, 2),
+                (Yaku::Honroutou, 2),
+                (Yaku::Honitsu, 3),
+            ],
+        );
+        assert_eq!(yakus.iter().map(|&(_, han)| han).sum::<u8>(), 15);
+
+        let tehai = hand("19m 19p 19s 1234567z 1m").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(E),
+            winning_tile: tu8!(1m),
+            is_ron: false,
+            allow_kuitan: true,
+        };
+        let (agari, yakus) = calc.agari_detail(1, 2).unwrap();
+        assert_eq!(agari, Agari::Yakuman(1));
+        assert_eq!(yakus.as_slice(), [(Yaku::Kokushi, 13)]);
+    }
 }
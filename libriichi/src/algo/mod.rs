@@ -4,3 +4,4 @@
 pub mod agari;
 pub mod point;
 pub mod shanten;
+pub mod yaku;
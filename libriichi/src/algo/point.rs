@@ -51,13 +51,53 @@ const KODOMO_TSUMO_POINTS: &[&[(i32, i32)]] = &[
     &[(4, 7), (7, 13), (13, 26)],           // 40
     &[(4, 8), (8, 16), (16, 32)],           // 50
     &[],
-    &[(6, 12), (12, 13)], // 70
+    &[(6, 12), (12, 23)], // 70
     &[],
     &[(8, 15), (15, 29)], // 90
     &[],
     &[(i32::MIN, i32::MIN), (18, 36)], // 110
 ];
 
+/// Limit hands, whose points do not depend on fu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Limit {
+    /// 満貫
+    Mangan,
+    /// 跳満
+    Haneman,
+    /// 倍満
+    Baiman,
+    /// 三倍満
+    Sanbaiman,
+    /// 役満, including kazoe yakuman.
+    Yakuman,
+}
+
+impl Limit {
+    pub const ALL: [Self; 5] = [
+        Self::Mangan,
+        Self::Haneman,
+        Self::Baiman,
+        Self::Sanbaiman,
+        Self::Yakuman,
+    ];
+
+    /// `None` if the hand is below mangan.
+    #[must_use]
+    pub const fn of(fu: u8, han: u8) -> Option<Self> {
+        let limit = match han {
+            // The base points of mangan is 2000.
+            0..=4 if (fu as u32) << (han + 2) < 2000 => return None,
+            0..=5 => Self::Mangan,
+            6..=7 => Self::Haneman,
+            8..=10 => Self::Baiman,
+            11..=12 => Self::Sanbaiman,
+            _ => Self::Yakuman,
+        };
+        Some(limit)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Point {
     pub ron: i32,
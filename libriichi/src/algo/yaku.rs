@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+/// Yakus, including the situational ones and doras.
+///
+/// The serde names are stable and are used as keys in outputs such as the
+/// yaku statistics.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Yaku {
+    // Situational
+    /// 立直
+    #[default]
+    Riichi,
+    /// 両立直
+    DoubleRiichi,
+    /// 一発
+    Ippatsu,
+    /// 門前清自摸和
+    MenzenTsumo,
+    /// 海底摸月
+    Haitei,
+    /// 河底撈魚
+    Houtei,
+    /// 嶺上開花
+    Rinshan,
+    /// 槍槓
+    Chankan,
+
+    // Hand
+    /// 平和
+    Pinfu,
+    /// 断幺九
+    Tanyao,
+    /// 一盃口
+    Iipeikou,
+    /// 役牌:場風牌
+    Bakaze,
+    /// 役牌:自風牌
+    Jikaze,
+    /// 役牌:白
+    Haku,
+    /// 役牌:發
+    Hatsu,
+    /// 役牌:中
+    Chun,
+    /// 混全帯幺九
+    Chanta,
+    /// 一気通貫
+    Ittsuu,
+    /// 三色同順
+    SanshokuDoujun,
+    /// 三色同刻
+    SanshokuDoukou,
+    /// 三槓子
+    Sankantsu,
+    /// 対々和
+    Toitoi,
+    /// 三暗刻
+    Sanankou,
+    /// 小三元
+    Shousangen,
+    /// 混老頭
+    Honroutou,
+    /// 七対子
+    Chiitoitsu,
+    /// 純全帯幺九
+    Junchan,
+    /// 混一色
+    Honitsu,
+    /// 二盃口
+    Ryanpeikou,
+    /// 清一色
+    Chinitsu,
+
+    // Yakuman
+    /// 天和
+    Tenhou,
+    /// 地和
+    Chiihou,
+    /// 国士無双
+    Kokushi,
+    /// 九蓮宝燈
+    Chuuren,
+    /// 四暗刻
+    Suuankou,
+    /// 四槓子
+    Suukantsu,
+    /// 大三元
+    Daisangen,
+    /// 小四喜
+    Shousuushii,
+    /// 大四喜
+    Daisuushii,
+    /// 字一色
+    Tsuuiisou,
+    /// 緑一色
+    Ryuuiisou,
+    /// 清老頭
+    Chinroutou,
+
+    // Doras
+    Dora,
+    AkaDora,
+    UraDora,
+    /// 抜きドラ
+    NukiDora,
+}
+
+impl Yaku {
+    /// Includes doras.
+    pub const ALL: [Self; 46] = [
+        Self::Riichi,
+        Self::DoubleRiichi,
+        Self::Ippatsu,
+        Self::MenzenTsumo,
+        Self::Haitei,
+        Self::Houtei,
+        Self::Rinshan,
+        Self::Chankan,
+        Self::Pinfu,
+        Self::Tanyao,
+        Self::Iipeikou,
+        Self::Bakaze,
+        Self::Jikaze,
+        Self::Haku,
+        Self::Hatsu,
+        Self::Chun,
+        Self::Chanta,
+        Self::Ittsuu,
+        Self::SanshokuDoujun,
+        Self::SanshokuDoukou,
+        Self::Sankantsu,
+        Self::Toitoi,
+        Self::Sanankou,
+        Self::Shousangen,
+        Self::Honroutou,
+        Self::Chiitoitsu,
+        Self::Junchan,
+        Self::Honitsu,
+        Self::Ryanpeikou,
+        Self::Chinitsu,
+        Self::Tenhou,
+        Self::Chiihou,
+        Self::Kokushi,
+        Self::Chuuren,
+        Self::Suuankou,
+        Self::Suukantsu,
+        Self::Daisangen,
+        Self::Shousuushii,
+        Self::Daisuushii,
+        Self::Tsuuiisou,
+        Self::Ryuuiisou,
+        Self::Chinroutou,
+        Self::Dora,
+        Self::AkaDora,
+        Self::UraDora,
+        Self::NukiDora,
+    ];
+
+    #[inline]
+    #[must_use]
+    pub const fn is_yakuman(self) -> bool {
+        matches!(
+            self,
+            Self::Tenhou
+                | Self::Chiihou
+                | Self::Kokushi
+                | Self::Chuuren
+                | Self::Suuankou
+                | Self::Suukantsu
+                | Self::Daisangen
+                | Self::Shousuushii
+                | Self::Daisuushii
+                | Self::Tsuuiisou
+                | Self::Ryuuiisou
+                | Self::Chinroutou
+        )
+    }
+
+    /// Doras do not count as yaku on their own.
+    #[inline]
+    #[must_use]
+    pub const fn is_dora(self) -> bool {
+        matches!(
+            self,
+            Self::Dora | Self::AkaDora | Self::UraDora | Self::NukiDora
+        )
+    }
+}
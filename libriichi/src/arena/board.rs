@@ -1,14 +1,14 @@
 use super::result::KyokuResult;
 use crate::consts::ORACLE_OBS_SHAPE;
-use crate::mjai::{Event, EventExt};
-use crate::state::PlayerState;
+use crate::mjai::{Event, EventExt, Metadata, RyukyokuReason};
+use crate::state::{AkaRule, KanDoraRule, MultiRonRule, PlayerState, Rules, TripleRonRule};
 use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
 use crate::{matches_tu8, must_tile, t, tu8};
 use std::convert::TryInto;
 use std::mem;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use derivative::Derivative;
 use ndarray::prelude::*;
 use rand::prelude::*;
@@ -19,10 +19,9 @@ use sha3::{Digest, Sha3_256};
 /// yama, doras, scores directly.
 ///
 /// Other than what is mentioned below, everything else is identical to Tenhou's
-/// Rule.
+/// Rule with the default `Rules`.
 ///
-/// 1. No triple-ron ryukyoku.
-/// 2. Tenhou (the yaku) and chihou do not accumulate with other yakus; they are
+/// 1. Tenhou (the yaku) and chihou do not accumulate with other yakus; they are
 ///    always 1x yakuman.
 #[derive(Debug, Default)]
 pub struct Board {
@@ -43,6 +42,11 @@ pub struct Board {
     pub dora_indicators: Vec<Tile>,
     /// Goes forward (iter)
     pub ura_indicators: Vec<Tile>,
+
+    /// Decides the akas in the wall generated by `init_from_seed` and is
+    /// given to every `PlayerState`. The board only plays yonma, so the mode
+    /// must be `GameMode::Yonma`.
+    pub rules: Rules,
 }
 
 #[derive(Derivative)]
@@ -74,10 +78,15 @@ pub struct BoardState {
     accepted_riichis: u8,
     kans: u8,
     check_four_kan: bool,
-    paos: [Option<u8>; 4],
 
     log: Vec<EventExt>,
 
+    /// The number of reactions from each seat while it can act.
+    decisions: [u32; 4],
+    /// The number of reactions from each seat replaced by
+    /// `PlayerState::fallback_reaction`.
+    violations: [u32; 4],
+
     // For oracle_obs only
     dora_indicators_full: Vec<Tile>,
 }
@@ -105,6 +114,14 @@ impl Board {
             .unwrap();
         let mut rng = ChaCha12Rng::from_seed(kyoku_seed);
         let mut seq = UNSHUFFLED;
+        match self.rules.aka {
+            AkaRule::None => seq.iter_mut().for_each(|t| *t = t.deaka()),
+            AkaRule::Three => (),
+            AkaRule::FourWithDoublePin => {
+                let idx = seq.iter().position(|&t| t == t!(5p)).unwrap();
+                seq[idx] = t!(5pr);
+            }
+        }
         seq.shuffle(&mut rng);
 
         self.haipai = [
@@ -129,16 +146,12 @@ impl Board {
     pub fn into_state(self) -> BoardState {
         let oya = self.kyoku % 4;
         let dora_indicators_full = self.dora_indicators.clone();
+        let rules = self.rules;
 
         BoardState {
             board: self,
             oya,
-            player_states: [
-                PlayerState::new(0),
-                PlayerState::new(1),
-                PlayerState::new(2),
-                PlayerState::new(3),
-            ],
+            player_states: [0, 1, 2, 3].map(|id| PlayerState::with_rules(id, rules)),
             dora_indicators_full,
             ..Default::default()
         }
@@ -148,6 +161,7 @@ impl Board {
 impl BoardState {
     pub fn poll(&mut self, mut reactions: [EventExt; 4]) -> Result<Poll> {
         loop {
+            self.guard_reactions(&mut reactions);
             let poll = self.step(&reactions)?;
             match poll {
                 Poll::InGame => {
@@ -168,6 +182,69 @@ impl BoardState {
         }
     }
 
+    /// Forces the next draws from the live wall, for teaching scenarios like
+    /// "what if you draw the 4p now". With `seat`, `tiles` become the next
+    /// draws of that seat, assuming no call changes the turn order before
+    /// them, otherwise they are the next draws of whoever draws. Rinshan
+    /// draws are not affected.
+    ///
+    /// Each tile is swapped with a copy of it found in the live wall, so the
+    /// wall keeps the same tiles. Err is returned if there is no such copy
+    /// left, for example the tile has been drawn or is in the dead wall.
+    ///
+    /// A `none` event carrying `Metadata::stacked_draws` is logged, so that
+    /// the game is never taken as organic data.
+    pub fn stack_next_draws(&mut self, seat: Option<u8>, tiles: &[Tile]) -> Result<()> {
+        // The first draw of the kyoku is the oya's, otherwise the next draw
+        // comes after the discard of whoever is holding 3n+2 tiles.
+        let next_actor = if self.tiles_left == 70 {
+            self.oya
+        } else {
+            self.player_states
+                .iter()
+                .position(|s| s.last_cans().can_discard)
+                .map_or(self.tsumo_actor, |actor| (actor as u8 + 1) % 4)
+        };
+        let (first, step) = match seat {
+            Some(seat) => {
+                ensure!(seat < 4, "invalid seat {seat}");
+                ((seat + 4 - next_actor) % 4, 4)
+            }
+            None => (0, 1),
+        };
+
+        // The k-th draw from now pops `yama[yama.len() - 1 - k]`, and the
+        // ones below `live_start` are never drawn because of kans.
+        let mut yama = self.board.yama.clone();
+        let live_start = yama.len() - self.tiles_left as usize;
+        let mut fixed = vec![];
+        for (i, &tile) in tiles.iter().enumerate() {
+            let k = first as usize + step * i;
+            ensure!(
+                k < self.tiles_left as usize,
+                "only {} tiles left, cannot stack {tile} as the draw {k} from now",
+                self.tiles_left,
+            );
+            let idx = yama.len() - 1 - k;
+            let found = (live_start..yama.len())
+                .filter(|j| !fixed.contains(j))
+                .find(|&j| yama[j] == tile)
+                .with_context(|| format!("{tile} is not in the live wall"))?;
+            yama.swap(idx, found);
+            fixed.push(idx);
+        }
+        self.board.yama = yama;
+
+        self.add_log(EventExt {
+            event: Event::None,
+            meta: Some(Metadata {
+                stacked_draws: Some(tiles.to_vec()),
+                ..Default::default()
+            }),
+        });
+        Ok(())
+    }
+
     #[inline]
     pub fn agent_context(&self) -> AgentContext<'_> {
         AgentContext {
@@ -177,7 +254,7 @@ impl BoardState {
     }
 
     #[inline]
-    pub const fn end(&self) -> KyokuResult {
+    pub fn end(&self) -> KyokuResult {
         KyokuResult {
             kyoku: self.board.kyoku,
             honba: self.board.honba,
@@ -186,6 +263,11 @@ impl BoardState {
             has_abortive_ryukyoku: self.has_abortive_ryukyoku,
             kyotaku_left: self.board.kyotaku,
             scores: self.board.scores,
+            first_call_junme: [0, 1, 2, 3].map(|i| self.player_states[i].first_call_junme()),
+            call_counts: [0, 1, 2, 3].map(|i| self.player_states[i].call_count()),
+            is_menzen: [0, 1, 2, 3].map(|i| self.player_states[i].is_menzen()),
+            decisions: self.decisions,
+            violations: self.violations,
         }
     }
 
@@ -294,8 +376,18 @@ impl BoardState {
         }
 
         vec_add_assign(&mut self.kyoku_deltas, &deltas);
-        let ryukyoku = Event::Ryukyoku {
-            deltas: Some(deltas),
+        let ryukyoku = if has_nagashi_mangan {
+            Event::Ryukyoku {
+                reason: Some(RyukyokuReason::NagashiMangan),
+                deltas: Some(deltas),
+                tenpais: None,
+            }
+        } else {
+            Event::Ryukyoku {
+                reason: Some(RyukyokuReason::Exhaustive),
+                deltas: Some(deltas),
+                tenpais: Some([0, 1, 2, 3].map(|i| self.player_states[i].shanten() == 0)),
+            }
         };
         self.add_log_no_meta(ryukyoku);
         // no need to broadcast
@@ -393,9 +485,9 @@ impl BoardState {
             .map(|ev| match ev.event {
                 Event::Hora { actor, .. } => {
                     self.can_renchan |= actor == self.oya;
-                    let point =
-                        self.player_states[actor as usize].agari_points(is_ron, &ura_indicators);
-                    Some(point).transpose()
+                    let state = &self.player_states[actor as usize];
+                    let point = state.agari_points(is_ron, &ura_indicators)?;
+                    Ok(Some((point, state.pao_liable_seat())))
                 }
                 _ => Ok(None),
             })
@@ -409,15 +501,16 @@ impl BoardState {
                 .cycle()
                 .skip(single_target as usize + 1)
                 .take(3)
-                .filter_map(|(actor, v)| v.map(|point| (actor, point)))
-                .for_each(|(actor, point)| {
+                .filter_map(|(actor, v)| v.map(|(point, pao)| (actor, point, pao)))
+                .for_each(|(actor, point, pao)| {
                     let mut deltas = [0; 4];
-                    if let Some(pao_target) = self.paos[actor] {
+                    if let Some(pao) = pao {
+                        let pao_target = (actor + pao as usize) % 4;
                         // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
                         //
                         // > 複合役満を含む得点を、ツモ＝全額・ロン＝折半で支払
                         // > う。積み棒は包。
-                        deltas[pao_target as usize] = -point.ron / 2 - honba_left * 300;
+                        deltas[pao_target] = -point.ron / 2 - honba_left * 300;
                         deltas[single_target as usize] -= point.ron / 2; // they may be the same person
                     } else {
                         deltas[single_target as usize] = -point.ron - honba_left * 300;
@@ -425,7 +518,9 @@ impl BoardState {
                     deltas[actor] = point.ron + kyotaku_point + honba_left * 300;
 
                     kyotaku_point = 0;
-                    honba_left = 0;
+                    if self.board.rules.multi_ron == MultiRonRule::HonbaToFirst {
+                        honba_left = 0;
+                    }
 
                     vec_add_assign(&mut self.kyoku_deltas, &deltas);
                     let ura_markers = self.player_states[actor as usize]
@@ -445,11 +540,13 @@ impl BoardState {
             return Ok(());
         }
 
-        let point = points[single_actor as usize].unwrap();
+        let (point, pao) = points[single_actor as usize].unwrap();
         let mut deltas = [0; 4];
-        if let Some(pao_target) = self.paos[single_actor as usize] {
-            // For pao to happen, the agari must have at least 1 yakuman so ron
-            // point and sum of tsumo point should be equal.
+        if let Some(pao) = pao {
+            // The liable player pays the ron points, which equal the sum of
+            // the tsumo points for a yakuman, though not always for the
+            // rinshan kaihou of `DaiminkanPaoRule::RinshanKaihou`.
+            let pao_target = (single_actor + pao) % 4;
             deltas[pao_target as usize] = -point.ron - honba_left * 300;
         } else {
             deltas.fill(-point.tsumo_ko - honba_left * 100);
@@ -457,8 +554,12 @@ impl BoardState {
                 deltas[self.oya as usize] = -point.tsumo_oya - honba_left * 100;
             }
         };
-        deltas[single_actor as usize] =
-            point.tsumo_total(single_actor == self.oya) + kyotaku_point + honba_left * 300;
+        let gain = if pao.is_some() {
+            point.ron
+        } else {
+            point.tsumo_total(single_actor == self.oya)
+        };
+        deltas[single_actor as usize] = gain + kyotaku_point + honba_left * 300;
 
         vec_add_assign(&mut self.kyoku_deltas, &deltas);
         let ura_markers = self.player_states[single_actor as usize]
@@ -478,44 +579,50 @@ impl BoardState {
         Ok(())
     }
 
-    fn update_paos(&mut self, ev: &Event) {
-        match *ev {
-            Event::Pon {
-                target, actor, pai, ..
-            }
-            | Event::Daiminkan {
-                target, actor, pai, ..
-            } if pai.is_jihai() => {
-                let mut jihais = 0u8;
-                self.player_states[actor as usize]
-                    .pons()
-                    .iter()
-                    .chain(self.player_states[actor as usize].minkans())
-                    .copied()
-                    .filter(|&t| t >= tu8!(E))
-                    .for_each(|t| jihais |= 1 << (t - tu8!(E)));
-                let daisanein_confirmed = (jihais & 0b1110000) == 0b1110000;
-                let daisuushi_confirmed = (jihais & 0b0001111) == 0b0001111;
-                if daisanein_confirmed && matches_tu8!(pai.as_u8(), P | F | C)
-                    || daisuushi_confirmed && matches_tu8!(pai.as_u8(), E | S | W | N)
-                {
-                    self.paos[actor as usize] = Some(target);
-                }
-            }
-            _ => (),
-        }
-    }
-
     #[inline]
-    fn abortive_ryukyoku(&mut self) {
+    fn abortive_ryukyoku(&mut self, reason: RyukyokuReason) {
         let ryukyoku = Event::Ryukyoku {
+            reason: Some(reason),
             deltas: Some([0; 4]),
+            tenpais: None,
         };
         self.add_log_no_meta(ryukyoku);
         self.has_abortive_ryukyoku = true;
         // No need to broadcast
     }
 
+    /// Replaces every reaction that fails `validate_reaction` with
+    /// `fallback_reaction`, so that a buggy agent cannot break the game, and
+    /// counts it as a violation of the seat.
+    fn guard_reactions(&mut self, reactions: &mut [EventExt; 4]) {
+        for (actor, ev) in reactions.iter_mut().enumerate() {
+            let state = &self.player_states[actor];
+            let cans = state.last_cans();
+            if !cans.can_act() && ev.event == Event::None {
+                continue;
+            }
+
+            self.decisions[actor] += 1;
+            let checked = if !cans.can_act() {
+                Err(anyhow!("cannot act"))
+            } else if cans.can_discard && ev.event == Event::None {
+                Err(anyhow!("must discard"))
+            } else {
+                state.validate_reaction(&ev.event)
+            };
+            if let Err(err) = checked {
+                self.violations[actor] += 1;
+                let fallback = state.reaction_to_event(state.fallback_reaction());
+                log::warn!(
+                    "invalid reaction {:?}: {err:#}, taking {fallback:?} instead\nstate:\n{}",
+                    ev.event,
+                    state.brief_info(),
+                );
+                *ev = EventExt::no_meta(fallback);
+            }
+        }
+    }
+
     fn step(&mut self, reactions: &[EventExt; 4]) -> Result<Poll> {
         if self.tiles_left == 70 {
             self.haipai()?;
@@ -524,22 +631,10 @@ impl BoardState {
 
         if self.accepted_riichis == 4 {
             // 四家立直
-            self.abortive_ryukyoku();
+            self.abortive_ryukyoku(RyukyokuReason::SuuchaRiichi);
             return Ok(Poll::End);
         }
 
-        // Validate reactions
-        for (actor, ev) in reactions.iter().enumerate() {
-            self.player_states[actor]
-                .validate_reaction(&ev.event)
-                .with_context(|| {
-                    format!(
-                        "invalid action: {ev:?}\nstate:\n{}",
-                        self.player_states[actor].brief_info(),
-                    )
-                })?;
-        }
-
         let ev = reactions
             .iter()
             .min_by_key(|ev| match ev.event {
@@ -552,7 +647,7 @@ impl BoardState {
 
         if self.check_four_kan && !matches!(ev.event, Event::Hora { .. }) {
             // 四槓散了
-            self.abortive_ryukyoku();
+            self.abortive_ryukyoku(RyukyokuReason::Suukaikan);
             return Ok(Poll::End);
         }
 
@@ -603,7 +698,7 @@ impl BoardState {
 
                 // 四風連打
                 if self.can_four_wind && self.check_four_wind(pai)? {
-                    self.abortive_ryukyoku();
+                    self.abortive_ryukyoku(RyukyokuReason::SuufonRenda);
                     return Ok(Poll::End);
                 }
 
@@ -648,7 +743,10 @@ impl BoardState {
                 self.broadcast(&ev.event);
                 self.add_log(ev.clone());
 
-                self.need_new_dora_at_discard = Some(());
+                match self.board.rules.kan_dora {
+                    KanDoraRule::AfterDiscard => self.need_new_dora_at_discard = Some(()),
+                    KanDoraRule::Immediate => self.add_new_dora()?,
+                }
 
                 self.tsumo_actor = actor;
                 self.deal_from_rinshan = Some(());
@@ -662,13 +760,34 @@ impl BoardState {
             }
 
             Event::Hora { actor, target, .. } => {
+                let horas = reactions
+                    .iter()
+                    .filter(|ev| matches!(ev.event, Event::Hora { .. }))
+                    .count();
+                if horas == 3 && self.board.rules.triple_ron == TripleRonRule::Abortive {
+                    // 三家和了
+                    self.abortive_ryukyoku(RyukyokuReason::Sanchahou);
+                    return Ok(Poll::End);
+                }
+                if horas > 1 && self.board.rules.multi_ron == MultiRonRule::Atamahane {
+                    // 頭ハネ: the rons after the first one in turn order are
+                    // void.
+                    let first = (1..4)
+                        .map(|i| (target as usize + i) % 4)
+                        .find(|&i| matches!(reactions[i].event, Event::Hora { .. }))
+                        .unwrap();
+                    let mut first_only: [EventExt; 4] = Default::default();
+                    first_only[first] = reactions[first].clone();
+                    self.handle_hora(first as u8, target, &first_only)?;
+                    return Ok(Poll::End);
+                }
                 self.handle_hora(actor, target, reactions)?;
                 return Ok(Poll::End);
             }
 
             Event::Ryukyoku { .. } => {
                 // 九種九牌
-                self.abortive_ryukyoku();
+                self.abortive_ryukyoku(RyukyokuReason::Kyuushukyuuhai);
                 return Ok(Poll::End);
             }
 
@@ -677,11 +796,6 @@ impl BoardState {
             }
         };
 
-        // The pao check cannot be done before the current event (Pon or
-        // Daiminkan) gets processed because it requires to read `.pons()` and
-        // `.minkans()`.
-        self.update_paos(&ev.event);
-
         Ok(Poll::InGame)
     }
 
@@ -819,3 +933,349 @@ const UNSHUFFLED: [Tile; 136] = [
     t!(F), t!(F), t!(F), t!(F),
     t!(C), t!(C), t!(C), t!(C),
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::UpdateError;
+
+    use serde_json as json;
+
+    fn tiles(s: &[&str]) -> Vec<Tile> {
+        s.iter().map(|t| t.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn four_kans_consume_dead_wall() {
+        let mut yama = vec![t!(C); 68];
+        yama.push(t!(9s));
+        yama.push(t!(4m));
+        let haipai = [
+            [
+                "1m", "1m", "1m", "1m", "2m", "2m", "2m", "2m", "3m", "3m", "3m", "3m", "E",
+            ],
+            [
+                "9p", "9p", "9p", "9p", "1s", "2s", "3s", "4s", "5s", "6s", "7s", "8s", "N",
+            ],
+            [
+                "1p", "2p", "3p", "4p", "5p", "6p", "7p", "8p", "2s", "3s", "4s", "5s", "6s",
+            ],
+            [
+                "6m", "7m", "8m", "6m", "7m", "8m", "9m", "9m", "S", "S", "W", "W", "P",
+            ],
+        ]
+        .map(|h| tiles(&h).try_into().unwrap());
+        let board = Board {
+            scores: [25000; 4],
+            haipai,
+            yama,
+            rinshan: tiles(&["5m", "4m", "4m", "4m"]),
+            dora_indicators: tiles(&["F"; 5]),
+            ura_indicators: tiles(&["F"; 5]),
+            ..Default::default()
+        };
+        let mut state = board.into_state();
+        let act = |state: &mut BoardState, ev: Event| {
+            let mut reactions: [EventExt; 4] = Default::default();
+            reactions[0] = EventExt::no_meta(ev);
+            state.poll(reactions).unwrap();
+        };
+
+        state.poll(Default::default()).unwrap();
+        for (i, tile) in [t!(1m), t!(2m), t!(3m), t!(4m)].into_iter().enumerate() {
+            assert!(state.player_states[0].last_cans().can_ankan);
+            assert_eq!(state.player_states[0].rinshan_tiles_left(), 4 - i as u8);
+            act(
+                &mut state,
+                Event::Ankan {
+                    actor: 0,
+                    consumed: [tile; 4],
+                },
+            );
+        }
+        assert_eq!(state.player_states[0].last_self_tsumo(), Some(t!(5m)));
+        assert!(!state.player_states[0].last_cans().can_ankan);
+        assert!(state.board.rinshan.is_empty());
+
+        act(
+            &mut state,
+            Event::Dahai {
+                actor: 0,
+                pai: t!(E),
+                tsumogiri: false,
+            },
+        );
+        // Seat 1 holds four 9p but there is no rinshan tile for a fifth kan.
+        let ps = &state.player_states[1];
+        assert_eq!(ps.last_self_tsumo(), Some(t!(9s)));
+        assert_eq!(ps.rinshan_tiles_left(), 0);
+        assert!(!ps.last_cans().can_ankan);
+        assert!(state
+            .player_states
+            .iter()
+            .all(|s| s.rinshan_tiles_left() == 0));
+
+        // The haitei moved forward by four: four tiles of the live wall are
+        // never drawn.
+        assert_eq!(state.tiles_left, 70 - 2 - 4);
+        assert_eq!(state.board.yama.len(), 70 - 2);
+        assert_eq!(state.board.yama.len() - state.tiles_left as usize, 4);
+
+        let mut reactions: [EventExt; 4] = Default::default();
+        reactions[1] = EventExt::no_meta(Event::Ankan {
+            actor: 1,
+            consumed: [t!(9p); 4],
+        });
+        // The fifth kan is rejected and replaced by a tsumogiri.
+        let log_len = state.log.len();
+        state.poll(reactions).unwrap();
+        assert_eq!(state.violations, [0, 1, 0, 0]);
+        assert_eq!(
+            state.log[log_len].event,
+            Event::Dahai {
+                actor: 1,
+                pai: t!(9s),
+                tsumogiri: true,
+            },
+        );
+    }
+
+    #[test]
+    fn stack_next_draws() {
+        let mut yama = vec![t!(4p), t!(5sr)];
+        yama.extend([t!(C); 66]);
+        yama.push(t!(9s));
+        yama.push(t!(4m));
+        let haipai = [
+            [
+                "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "1p", "2p", "3p", "E",
+            ],
+            [
+                "1s", "2s", "3s", "4s", "5s", "6s", "7s", "8s", "9s", "1p", "2p", "3p", "S",
+            ],
+            [
+                "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "7p", "8p", "9p", "W",
+            ],
+            [
+                "1s", "2s", "3s", "4s", "5s", "6s", "7s", "8s", "9s", "7p", "8p", "9p", "N",
+            ],
+        ]
+        .map(|h| tiles(&h).try_into().unwrap());
+        let board = Board {
+            scores: [25000; 4],
+            haipai,
+            yama,
+            rinshan: tiles(&["F"; 4]),
+            dora_indicators: tiles(&["F"; 5]),
+            ura_indicators: tiles(&["F"; 5]),
+            ..Default::default()
+        };
+        let mut state = board.into_state();
+        state.poll(Default::default()).unwrap();
+        assert_eq!(state.player_states[0].last_self_tsumo(), Some(t!(4m)));
+
+        let sorted = |yama: &[Tile]| {
+            let mut ret = yama.to_vec();
+            ret.sort_by_key(|t| t.as_u8());
+            ret
+        };
+        let yama_before = sorted(&state.board.yama);
+        state.stack_next_draws(Some(1), &[t!(4p)]).unwrap();
+        // Not in the live wall anymore.
+        state.stack_next_draws(None, &[t!(4m)]).unwrap_err();
+        state
+            .stack_next_draws(Some(2), &[t!(5sr), t!(5sr)])
+            .unwrap_err();
+
+        assert_eq!(sorted(&state.board.yama), yama_before);
+        assert_eq!(state.board.yama.first(), Some(&t!(9s)));
+
+        let mut reactions: [EventExt; 4] = Default::default();
+        reactions[0] = EventExt::no_meta(Event::Dahai {
+            actor: 0,
+            pai: t!(E),
+            tsumogiri: false,
+        });
+        state.poll(reactions).unwrap();
+        assert_eq!(state.player_states[1].last_self_tsumo(), Some(t!(4p)));
+
+        let log = state.take_log();
+        assert!(log.iter().any(|ev| matches!(
+            &ev.meta,
+            Some(Metadata {
+                stacked_draws: Some(tiles),
+                ..
+            }) if tiles == &[t!(4p)],
+        )));
+        assert_eq!(
+            log.last().unwrap().event,
+            Event::Tsumo {
+                actor: 1,
+                pai: t!(4p),
+            },
+        );
+        let line = json::to_string(&log[log.len() - 3]).unwrap();
+        assert!(EventExt::is_stacked_draws_marker(
+            &line,
+            &log[log.len() - 3].event
+        ));
+    }
+
+    #[test]
+    fn kan_dora_rules() {
+        let mut yama = vec![t!(C); 69];
+        yama.push(t!(9s));
+        let haipai = [
+            [
+                "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "1p", "2p", "3p", "E",
+            ],
+            [
+                "E", "E", "E", "1s", "2s", "3s", "4s", "5s", "6s", "7s", "8s", "9s", "N",
+            ],
+            [
+                "1p", "2p", "3p", "4p", "5p", "6p", "7p", "8p", "2s", "3s", "4s", "5s", "6s",
+            ],
+            [
+                "6m", "7m", "8m", "6m", "7m", "8m", "9m", "9m", "S", "S", "W", "W", "P",
+            ],
+        ]
+        .map(|h| tiles(&h).try_into().unwrap());
+
+        for kan_dora in [KanDoraRule::AfterDiscard, KanDoraRule::Immediate] {
+            let board = Board {
+                scores: [25000; 4],
+                haipai,
+                yama: yama.clone(),
+                rinshan: tiles(&["5m"; 4]),
+                dora_indicators: tiles(&["F"; 5]),
+                ura_indicators: tiles(&["F"; 5]),
+                rules: Rules {
+                    kan_dora,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut state = board.into_state();
+            state.poll(Default::default()).unwrap();
+
+            let mut reactions: [EventExt; 4] = Default::default();
+            reactions[0] = EventExt::no_meta(Event::Dahai {
+                actor: 0,
+                pai: t!(E),
+                tsumogiri: false,
+            });
+            state.poll(reactions).unwrap();
+            assert!(state.player_states[1].last_cans().can_daiminkan);
+
+            let mut reactions: [EventExt; 4] = Default::default();
+            reactions[1] = EventExt::no_meta(Event::Daiminkan {
+                actor: 1,
+                target: 0,
+                pai: t!(E),
+                consumed: [t!(E); 3],
+            });
+            state.poll(reactions).unwrap();
+            assert_eq!(state.player_states[1].last_self_tsumo(), Some(t!(5m)));
+
+            let log = state.take_log();
+            let kan = log
+                .iter()
+                .position(|ev| matches!(ev.event, Event::Daiminkan { .. }))
+                .unwrap();
+            let revealed = matches!(log[kan + 1].event, Event::Dora { .. });
+            assert_eq!(revealed, kan_dora == KanDoraRule::Immediate, "{kan_dora:?}");
+        }
+    }
+
+    #[test]
+    fn atamahane() {
+        let mut yama = vec![t!(C); 69];
+        yama.push(t!(9s));
+        let haipai = [
+            [
+                "1m", "1m", "1m", "4m", "9m", "9m", "9m", "1p", "1p", "1p", "E", "E", "S",
+            ],
+            [
+                "2m", "3m", "2p", "3p", "4p", "5p", "6p", "7p", "2s", "3s", "4s", "8s", "8s",
+            ],
+            [
+                "5m", "6m", "3p", "4p", "5p", "6p", "6p", "6p", "4s", "5s", "6s", "2s", "2s",
+            ],
+            [
+                "N", "N", "N", "W", "W", "W", "P", "P", "P", "F", "F", "7s", "7s",
+            ],
+        ]
+        .map(|h| tiles(&h).try_into().unwrap());
+
+        let play = |multi_ron| {
+            let board = Board {
+                scores: [25000; 4],
+                haipai,
+                yama: yama.clone(),
+                rinshan: tiles(&["F"; 4]),
+                dora_indicators: tiles(&["F"; 5]),
+                ura_indicators: tiles(&["F"; 5]),
+                rules: Rules {
+                    multi_ron,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut state = board.into_state();
+            state.poll(Default::default()).unwrap();
+
+            let mut reactions: [EventExt; 4] = Default::default();
+            reactions[0] = EventExt::no_meta(Event::Dahai {
+                actor: 0,
+                pai: t!(4m),
+                tsumogiri: false,
+            });
+            state.poll(reactions).unwrap();
+
+            let mut reactions: [EventExt; 4] = Default::default();
+            for actor in [1, 2] {
+                assert!(state.player_states[actor].last_cans().can_ron_agari);
+                reactions[actor] = EventExt::no_meta(Event::Hora {
+                    actor: actor as u8,
+                    target: 0,
+                    deltas: None,
+                    ura_markers: None,
+                });
+            }
+            state.poll(reactions).unwrap();
+            state.take_log()
+        };
+        let winners = |log: &[EventExt]| {
+            log.iter()
+                .filter_map(|ev| match ev.event {
+                    Event::Hora { actor, .. } => Some(actor),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let log = play(MultiRonRule::HonbaToFirst);
+        assert_eq!(winners(&log), [1, 2]);
+        let log_atamahane = play(MultiRonRule::Atamahane);
+        assert_eq!(winners(&log_atamahane), [1]);
+
+        // The second hora is rejected by a state under the rule.
+        let rules = Rules {
+            multi_ron: MultiRonRule::Atamahane,
+            ..Default::default()
+        };
+        let mut ps = PlayerState::with_rules(3, rules);
+        let second = log
+            .iter()
+            .rposition(|ev| matches!(ev.event, Event::Hora { .. }))
+            .unwrap();
+        for ev in &log[..second] {
+            ps.update_checked(&ev.event).unwrap();
+        }
+        let err = ps.update_checked(&log[second].event).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UpdateError>(),
+            Some(&UpdateError::Atamahane { actor: 2 }),
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use super::game::{BatchGame, Index};
+use crate::agent::{Agent, BatchAgent, BatchifiedAgent};
+
+use anyhow::{ensure, Result};
+
+/// `DuplicateSession` plays duplicate games to reduce the variance of
+/// evaluation: every seed is played four times with the same walls, once
+/// with the challenger at each seat while the baseline takes the other three.
+///
+/// The walls of every kyoku are generated deterministically from
+/// `(seed, seed_key)` by `Board::init_from_seed`, and every reaction that
+/// fails `validate_reaction` is replaced by `fallback_reaction`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DuplicateSession {
+    pub seed_key: u64,
+    pub disable_progress_bar: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DuplicateStats {
+    /// `rankings[i]` is the number of games the challenger finished at rank
+    /// `i`, counting from 0.
+    pub rankings: [u32; 4],
+    /// In the order of the seeds.
+    pub seeds: Vec<SeedStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeedStats {
+    pub seed: u64,
+    /// Indexed by the seat of the challenger, counting from 0.
+    pub ranks: [u8; 4],
+    /// Final score minus initial score of the challenger, indexed by its seat.
+    pub score_deltas: [i32; 4],
+}
+
+impl DuplicateSession {
+    /// `new_challenger_agent` and `new_baseline_agent` are called with the
+    /// player ID of every seat the agent takes.
+    pub fn run<C, B, CA, BA>(
+        &self,
+        new_challenger_agent: C,
+        new_baseline_agent: B,
+        seeds: &[u64],
+    ) -> Result<DuplicateStats>
+    where
+        C: FnMut(u8) -> Result<CA>,
+        B: FnMut(u8) -> Result<BA>,
+        CA: Agent + 'static,
+        BA: Agent + 'static,
+    {
+        ensure!(!seeds.is_empty(), "no seed to play");
+
+        let challenger_player_ids: Vec<_> = (0..4).cycle().take(seeds.len() * 4).collect();
+        let baseline_player_ids: Vec<_> = (0..4)
+            .flat_map(|seat| (0..4).filter(move |&id| id != seat))
+            .cycle()
+            .take(seeds.len() * 4 * 3)
+            .collect();
+        let mut agents: [Box<dyn BatchAgent>; 2] = [
+            Box::new(BatchifiedAgent::new(
+                new_challenger_agent,
+                &challenger_player_ids,
+            )?),
+            Box::new(BatchifiedAgent::new(
+                new_baseline_agent,
+                &baseline_player_ids,
+            )?),
+        ];
+
+        let mut player_id_idxs = [0; 2];
+        let mut indexes = Vec::with_capacity(seeds.len() * 4);
+        for _ in seeds {
+            for seat in 0..4 {
+                let mut idx_group = [Index::default(); 4];
+                for (player_id, idx) in idx_group.iter_mut().enumerate() {
+                    let agent_idx = usize::from(player_id != seat);
+                    *idx = Index {
+                        agent_idx,
+                        player_id_idx: player_id_idxs[agent_idx],
+                    };
+                    player_id_idxs[agent_idx] += 1;
+                }
+                indexes.push(idx_group);
+            }
+        }
+        let game_seeds: Vec<_> = seeds
+            .iter()
+            .flat_map(|&seed| [(seed, self.seed_key); 4])
+            .collect();
+
+        let batch_game = BatchGame::tenhou_hanchan(self.disable_progress_bar);
+        let results = batch_game.run(&mut agents, &indexes, &game_seeds)?;
+
+        let mut stats = DuplicateStats::default();
+        for (&seed, results) in seeds.iter().zip(results.chunks_exact(4)) {
+            let mut seed_stats = SeedStats {
+                seed,
+                ranks: [0; 4],
+                score_deltas: [0; 4],
+            };
+            for (seat, result) in results.iter().enumerate() {
+                let rank = result.rankings().rank_by_player[seat];
+                stats.rankings[rank as usize] += 1;
+                seed_stats.ranks[seat] = rank;
+                seed_stats.score_deltas[seat] = result.scores[seat] - batch_game.init_scores[seat];
+            }
+            stats.seeds.push(seed_stats);
+        }
+        Ok(stats)
+    }
+}
+
+impl DuplicateStats {
+    #[must_use]
+    pub fn games(&self) -> u32 {
+        self.rankings.iter().sum()
+    }
+
+    /// Average rank of the challenger, counting from 1.
+    #[must_use]
+    pub fn avg_rank(&self) -> f64 {
+        let weighted: u32 = self
+            .rankings
+            .iter()
+            .zip(1..)
+            .map(|(&count, rank)| count * rank)
+            .sum();
+        weighted as f64 / self.games() as f64
+    }
+
+    /// Average score delta of the challenger over all the games.
+    #[must_use]
+    pub fn avg_score_delta(&self) -> f64 {
+        let sum: i64 = self
+            .seeds
+            .iter()
+            .map(|s| s.score_deltas.iter().map(|&d| d as i64).sum::<i64>())
+            .sum();
+        sum as f64 / self.games() as f64
+    }
+}
+
+impl SeedStats {
+    /// Average score delta of the challenger over the four seats.
+    #[must_use]
+    pub fn avg_score_delta(&self) -> f64 {
+        self.score_deltas.iter().sum::<i32>() as f64 / 4.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{RuleBased, Tsumogiri};
+
+    #[test]
+    fn identical_agents() {
+        let session = DuplicateSession {
+            disable_progress_bar: true,
+            ..Default::default()
+        };
+        let seeds = [1009, 1021];
+        let stats = session
+            .run(|id| Ok(Tsumogiri(id)), |id| Ok(Tsumogiri(id)), &seeds)
+            .unwrap();
+        assert_eq!(stats.games(), 8);
+        assert_eq!(stats.rankings, [2; 4]);
+
+        // With the same agent everywhere, the four games of a seed are the
+        // same game seen from each seat.
+        for (seed_stats, &seed) in stats.seeds.iter().zip(&seeds) {
+            assert_eq!(seed_stats.seed, seed);
+            let mut ranks = seed_stats.ranks;
+            ranks.sort_unstable();
+            assert_eq!(ranks, [0, 1, 2, 3]);
+            assert_eq!(seed_stats.score_deltas.iter().sum::<i32>(), 0);
+        }
+
+        let again = session
+            .run(|id| Ok(Tsumogiri(id)), |id| Ok(Tsumogiri(id)), &seeds)
+            .unwrap();
+        assert_eq!(again, stats);
+    }
+
+    #[test]
+    fn rule_based_beats_tsumogiri() {
+        let session = DuplicateSession {
+            disable_progress_bar: true,
+            ..Default::default()
+        };
+        let stats = session
+            .run(|id| Ok(RuleBased(id)), |id| Ok(Tsumogiri(id)), &[1, 2])
+            .unwrap();
+        assert_eq!(stats.games(), 8);
+        assert!(stats.avg_rank() < 2.);
+        assert!(stats.avg_score_delta() > 0.);
+    }
+}
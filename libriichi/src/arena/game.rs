@@ -2,6 +2,8 @@ use super::board::{Board, BoardState, Poll};
 use super::result::GameResult;
 use crate::agent::BatchAgent;
 use crate::mjai::EventExt;
+use crate::state::Rules;
+use crate::vec_ops::vec_add_assign;
 use std::collections::VecDeque;
 use std::mem;
 
@@ -13,9 +15,36 @@ pub struct BatchGame {
     /// 8 for hanchan and 4 for tonpuu
     pub length: u8,
     pub init_scores: [i32; 4],
+    pub rules: Rules,
+    pub leftover_kyotaku: LeftoverKyotaku,
     pub disable_progress_bar: bool,
 }
 
+/// Where the kyotakus left on the table at the end of the game go. Either way
+/// `GameResult::scores`, and the rankings and points from it, are settled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeftoverKyotaku {
+    /// To the top, who is the one closest to the first oya among those tied,
+    /// as in Tenhou.
+    #[default]
+    ToTop,
+    /// To nobody, so the final scores add up to less than the initial ones.
+    Discarded,
+}
+
+impl LeftoverKyotaku {
+    /// Gives out the `kyotaku` sticks left at the end of the game.
+    pub fn settle(self, scores: &mut [i32; 4], kyotaku: u8) {
+        match self {
+            Self::ToTop => {
+                // `min_by_key` picks the first one among the ties.
+                *scores.iter_mut().min_by_key(|s| -**s).unwrap() += kyotaku as i32 * 1000;
+            }
+            Self::Discarded => (),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct Index {
     /// For `Game` to find a specific `Agent`.
@@ -27,6 +56,8 @@ pub struct Index {
 #[derive(Default)]
 struct Game {
     length: u8,
+    rules: Rules,
+    leftover_kyotaku: LeftoverKyotaku,
     seed: (u64, u64),
     indexes: [Index; 4],
 
@@ -41,6 +72,8 @@ struct Game {
     kyotaku: u8,
     scores: [i32; 4],
     game_log: Vec<Vec<EventExt>>,
+    decisions: [u32; 4],
+    violations: [u32; 4],
 
     kyoku_started: bool,
     ended: bool,
@@ -75,6 +108,7 @@ impl Game {
                 honba: self.honba,
                 kyotaku: self.kyotaku,
                 scores: self.scores,
+                rules: self.rules,
                 ..Default::default()
             };
             next_board.init_from_seed(self.seed);
@@ -117,6 +151,8 @@ impl Game {
                 let kyoku_result = self.board.end();
                 self.kyotaku = kyoku_result.kyotaku_left;
                 self.scores = kyoku_result.scores;
+                vec_add_assign(&mut self.decisions, &kyoku_result.decisions);
+                vec_add_assign(&mut self.violations, &kyoku_result.violations);
 
                 let logs = self.board.take_log();
                 self.game_log.push(logs);
@@ -175,9 +211,8 @@ impl Game {
 
     fn commit(&mut self, agents: &mut [Box<dyn BatchAgent>]) -> Result<Option<GameResult>> {
         if self.ended {
-            if self.kyotaku > 0 {
-                *self.scores.iter_mut().min_by_key(|s| -**s).unwrap() += self.kyotaku as i32 * 1000;
-            }
+            self.leftover_kyotaku
+                .settle(&mut self.scores, mem::take(&mut self.kyotaku));
 
             let names = [
                 agents[self.indexes[0].agent_idx].name(),
@@ -190,6 +225,8 @@ impl Game {
                 scores: self.scores,
                 seed: self.seed,
                 game_log: mem::take(&mut self.game_log),
+                decisions: self.decisions,
+                violations: self.violations,
             };
 
             for idx in &self.indexes {
@@ -220,10 +257,13 @@ impl Game {
 }
 
 impl BatchGame {
-    pub const fn tenhou_hanchan(disable_progress_bar: bool) -> Self {
+    pub fn tenhou_hanchan(disable_progress_bar: bool) -> Self {
+        let rules = Rules::default();
         Self {
             length: 8,
-            init_scores: [25000; 4],
+            init_scores: [rules.mode.starting_score(); 4],
+            rules,
+            leftover_kyotaku: LeftoverKyotaku::ToTop,
             disable_progress_bar,
         }
     }
@@ -256,6 +296,8 @@ impl BatchGame {
 
                 let game = Box::new(Game {
                     length: self.length,
+                    rules: self.rules,
+                    leftover_kyotaku: self.leftover_kyotaku,
                     seed,
                     indexes: *idxs,
                     scores: self.init_scores,
@@ -319,7 +361,115 @@ impl BatchGame {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::agent::{BatchAgent, Tsumogiri};
+    use crate::agent::{Agent, BatchAgent, BatchifiedAgent, InvisibleState, Tsumogiri};
+    use crate::arena::UmaOka;
+    use crate::mjai::Event;
+    use crate::state::PlayerState;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records the number of reactions it is asked for at each step, and
+    /// checks the order of the calls.
+    struct Recorder {
+        inner: BatchifiedAgent<Tsumogiri>,
+        pending: VecDeque<usize>,
+        reacting: bool,
+        batch_sizes: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl BatchAgent for Recorder {
+        fn name(&self) -> String {
+            "recorder".to_owned()
+        }
+
+        fn set_scene(
+            &mut self,
+            index: usize,
+            log: &[EventExt],
+            state: &PlayerState,
+            invisible_state: Option<InvisibleState>,
+        ) -> Result<()> {
+            ensure!(!self.reacting, "set_scene in the middle of a batch");
+            self.pending.push_back(index);
+            self.inner.set_scene(index, log, state, invisible_state)
+        }
+
+        fn get_reaction(
+            &mut self,
+            index: usize,
+            log: &[EventExt],
+            state: &PlayerState,
+            invisible_state: Option<InvisibleState>,
+        ) -> Result<EventExt> {
+            if !self.reacting {
+                self.reacting = true;
+                self.batch_sizes.borrow_mut().push(self.pending.len());
+            }
+            ensure!(
+                self.pending.pop_front() == Some(index),
+                "get_reaction out of the order of set_scene",
+            );
+            self.reacting = !self.pending.is_empty();
+            self.inner.get_reaction(index, log, state, invisible_state)
+        }
+    }
+
+    #[test]
+    fn batches_across_games() {
+        let games = 3;
+        let batch_sizes = Rc::new(RefCell::new(vec![]));
+        let player_ids: Vec<_> = (0..4).cycle().take(games * 4).collect();
+        let mut agents: Vec<Box<dyn BatchAgent>> = vec![Box::new(Recorder {
+            inner: Tsumogiri::new_batched(&player_ids).unwrap(),
+            pending: VecDeque::new(),
+            reacting: false,
+            batch_sizes: Rc::clone(&batch_sizes),
+        })];
+        let indexes: Vec<_> = (0..games)
+            .map(|game| {
+                [0, 1, 2, 3].map(|player_id| Index {
+                    agent_idx: 0,
+                    player_id_idx: game * 4 + player_id,
+                })
+            })
+            .collect();
+        let seeds: Vec<_> = (0..games as u64).map(|seed| (seed, 0)).collect();
+
+        BatchGame::tenhou_hanchan(true)
+            .run(&mut agents, &indexes, &seeds)
+            .unwrap();
+
+        // Every game starts with the oya to discard, which are evaluated
+        // together.
+        let batch_sizes = batch_sizes.borrow();
+        assert_eq!(batch_sizes[0], games);
+        assert!(batch_sizes.iter().all(|&n| n > 0));
+    }
+
+    #[test]
+    fn leftover_kyotaku() {
+        // Two riichi sticks are left, with player 0 and 2 tied for the top.
+        let scores = [31000, 24000, 31000, 12000];
+
+        let mut to_top = GameResult {
+            scores,
+            ..Default::default()
+        };
+        LeftoverKyotaku::ToTop.settle(&mut to_top.scores, 2);
+        assert_eq!(to_top.scores, [33000, 24000, 31000, 12000]);
+        assert_eq!(to_top.rankings().player_by_rank, [0, 2, 1, 3]);
+        assert_eq!(to_top.points(UmaOka::default()), [43., -16., 11., -38.]);
+
+        let mut discarded = GameResult {
+            scores,
+            ..Default::default()
+        };
+        LeftoverKyotaku::Discarded.settle(&mut discarded.scores, 2);
+        assert_eq!(discarded.scores, scores);
+        assert_eq!(discarded.scores.iter().sum::<i32>(), 98000);
+        assert_eq!(discarded.rankings().player_by_rank, [0, 2, 1, 3]);
+        assert_eq!(discarded.points(UmaOka::default()), [41., -16., 11., -38.]);
+    }
 
     #[test]
     fn tsumogiri() {
@@ -370,4 +520,71 @@ mod test {
         g.run(&mut agents, indexes, &[(1009, 0), (1021, 0)])
             .unwrap();
     }
+
+    /// Never discards, which is an illegal reaction whenever it must.
+    struct Silent;
+
+    impl Agent for Silent {
+        fn name(&self) -> String {
+            "silent".to_owned()
+        }
+
+        fn react(
+            &mut self,
+            _: &[EventExt],
+            _: &PlayerState,
+            _: Option<InvisibleState>,
+        ) -> Result<EventExt> {
+            Ok(EventExt::no_meta(Event::None))
+        }
+    }
+
+    #[test]
+    fn guard_reactions() {
+        let g = BatchGame::tenhou_hanchan(true);
+        let seeds = &[(1009, 0)];
+
+        let mut agents: Vec<Box<dyn BatchAgent>> =
+            vec![Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap())];
+        let indexes = &[[0, 1, 2, 3].map(|player_id_idx| Index {
+            agent_idx: 0,
+            player_id_idx,
+        })];
+        let expected = g.run(&mut agents, indexes, seeds).unwrap().remove(0);
+        assert_eq!(expected.violations, [0; 4]);
+        assert!(expected.decisions.iter().all(|&n| n > 0));
+
+        // The discards of player 0 are all replaced by the tsumogiri of
+        // `fallback_reaction`, so the game goes the same.
+        let mut agents: Vec<Box<dyn BatchAgent>> = vec![
+            Box::new(BatchifiedAgent::new(|_| Ok(Silent), &[0]).unwrap()),
+            Box::new(Tsumogiri::new_batched(&[1, 2, 3]).unwrap()),
+        ];
+        let indexes = &[
+            [(0, 0), (1, 0), (1, 1), (1, 2)].map(|(agent_idx, player_id_idx)| Index {
+                agent_idx,
+                player_id_idx,
+            }),
+        ];
+        let actual = g.run(&mut agents, indexes, seeds).unwrap().remove(0);
+        let events = |res: &GameResult| -> Vec<_> {
+            res.game_log
+                .iter()
+                .flatten()
+                .map(|ev| ev.event.clone())
+                .collect()
+        };
+        assert_eq!(events(&actual), events(&expected));
+        assert_eq!(actual.scores, expected.scores);
+        assert_eq!(actual.decisions, expected.decisions);
+
+        let discards = events(&actual)
+            .iter()
+            .filter(|ev| matches!(ev, Event::Dahai { actor: 0, .. }))
+            .count() as u32;
+        assert_eq!(actual.violations, [discards, 0, 0, 0]);
+        let expected_rate = discards as f64 * 1000. / actual.decisions[0] as f64;
+        assert!((actual.violations_per_1000(0) - expected_rate).abs() < 1e-9);
+        assert_eq!(actual.violations_per_1000(1), 0.);
+    }
 }
@@ -1,14 +1,19 @@
 mod board;
+mod duplicate;
 mod game;
 mod one_vs_three;
 mod result;
 mod two_vs_two;
 
 pub use board::Board;
-pub use result::{GameResult, KyokuEndState};
+pub use duplicate::{DuplicateSession, DuplicateStats, SeedStats};
+pub use game::LeftoverKyotaku;
+pub use one_vs_three::OneVsThree;
+pub use result::{GameResult, KyokuEndState, UmaOka};
+
+pub(crate) use game::{BatchGame, Index};
 
 use crate::py_helper::add_submodule;
-use one_vs_three::OneVsThree;
 use two_vs_two::TwoVsTwo;
 
 use pyo3::prelude::*;
@@ -12,6 +12,18 @@ pub struct KyokuResult {
     pub has_abortive_ryukyoku: bool,
     pub kyotaku_left: u8,
     pub scores: [i32; 4],
+    /// The number of each player's own discards before their first call,
+    /// `None` if they made no call.
+    pub first_call_junme: [Option<u8>; 4],
+    /// Chi, pon and daiminkan of each player.
+    pub call_counts: [u8; 4],
+    /// Whether each player ended the kyoku with a closed hand.
+    pub is_menzen: [bool; 4],
+    /// The number of reactions from each player while they can act.
+    pub decisions: [u32; 4],
+    /// The number of reactions from each player replaced by
+    /// `PlayerState::fallback_reaction`.
+    pub violations: [u32; 4],
 }
 
 #[derive(Debug, Clone, Default)]
@@ -20,6 +32,10 @@ pub struct GameResult {
     pub scores: [i32; 4],
     pub seed: (u64, u64),
     pub game_log: Vec<Vec<EventExt>>,
+    /// Summed over the kyokus, see `KyokuResult::decisions`.
+    pub decisions: [u32; 4],
+    /// Summed over the kyokus, see `KyokuResult::violations`.
+    pub violations: [u32; 4],
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +44,31 @@ pub struct Rankings {
     pub rank_by_player: [u8; 4],
 }
 
+/// Converts the final scores of a game into its points, in thousands of
+/// score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UmaOka {
+    /// The score every player starts with.
+    pub init_score: i32,
+    /// The score the points are counted from. The top takes the difference
+    /// from `init_score` of all the four players as the oka.
+    pub return_score: i32,
+    /// Added to the score by rank, counting from the top.
+    pub uma: [i32; 4],
+}
+
+impl Default for UmaOka {
+    /// 25000 to start and 30000 to return, with the uma of 10-20, as in
+    /// Tenhou.
+    fn default() -> Self {
+        Self {
+            init_score: 25000,
+            return_score: 30000,
+            uma: [20000, 10000, -10000, -20000],
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum KyokuEndState {
     Passive = 0,
@@ -58,6 +99,51 @@ impl GameResult {
         }
     }
 
+    /// `violations` of `player` per 1000 `decisions`, or 0 if there is no
+    /// decision.
+    pub fn violations_per_1000(&self, player: u8) -> f64 {
+        let decisions = self.decisions[player as usize];
+        if decisions == 0 {
+            return 0.;
+        }
+        self.violations[player as usize] as f64 * 1000. / decisions as f64
+    }
+
+    /// Returns the points of each player. The scores have the leftover
+    /// kyotakus settled already, so under `LeftoverKyotaku::Discarded` the
+    /// points add up to less than zero.
+    pub fn points(&self, uma_oka: UmaOka) -> [f64; 4] {
+        let UmaOka {
+            init_score,
+            return_score,
+            uma,
+        } = uma_oka;
+        let rank_by_player = self.rankings().rank_by_player;
+
+        let mut ret = [0.; 4];
+        for (player, p) in ret.iter_mut().enumerate() {
+            let rank = rank_by_player[player] as usize;
+            let mut score = self.scores[player] - return_score + uma[rank];
+            if rank == 0 {
+                score += (return_score - init_score) * 4;
+            }
+            *p = score as f64 / 1000.;
+        }
+        ret
+    }
+
+    /// Returns the complete log of the game, from `start_game` to
+    /// `end_game`, without the metadata.
+    pub fn events(&self) -> Vec<Event> {
+        let mut ret = vec![Event::StartGame {
+            names: self.names.clone(),
+            seed: Some(self.seed),
+        }];
+        ret.extend(self.game_log.iter().flatten().map(|ev| ev.event.clone()));
+        ret.push(Event::EndGame);
+        ret
+    }
+
     pub fn dump_json_log(&self) -> Result<String> {
         let mut ret = json::to_string(&Event::StartGame {
             names: self.names.clone(),
@@ -1,4 +1,4 @@
-use super::game::{BatchGame, Index};
+use super::game::{BatchGame, Index, LeftoverKyotaku};
 use super::result::GameResult;
 use crate::agent::{AkochanAgent, BatchAgent, MortalBatchAgent};
 use std::fs::{self, File};
@@ -18,21 +18,38 @@ use rayon::prelude::*;
     *,
     disable_progress_bar = False,
     log_dir = None,
+    discard_leftover_kyotaku = False,
 )")]
 #[derive(Clone, Default)]
 pub struct TwoVsTwo {
     pub disable_progress_bar: bool,
     pub log_dir: Option<String>,
+    pub leftover_kyotaku: LeftoverKyotaku,
 }
 
 #[pymethods]
 impl TwoVsTwo {
     #[new]
-    #[args("*", disable_progress_bar = "false", log_dir = "None")]
-    const fn new(disable_progress_bar: bool, log_dir: Option<String>) -> Self {
+    #[args(
+        "*",
+        disable_progress_bar = "false",
+        log_dir = "None",
+        discard_leftover_kyotaku = "false"
+    )]
+    const fn new(
+        disable_progress_bar: bool,
+        log_dir: Option<String>,
+        discard_leftover_kyotaku: bool,
+    ) -> Self {
+        let leftover_kyotaku = if discard_leftover_kyotaku {
+            LeftoverKyotaku::Discarded
+        } else {
+            LeftoverKyotaku::ToTop
+        };
         Self {
             disable_progress_bar,
             log_dir,
+            leftover_kyotaku,
         }
     }
 
@@ -169,7 +186,10 @@ impl TwoVsTwo {
             Box::new(new_challenger_agent(&challenger_player_ids)?),
             Box::new(new_champion_agent(&champion_player_ids)?),
         ];
-        let batch_game = BatchGame::tenhou_hanchan(self.disable_progress_bar);
+        let batch_game = BatchGame {
+            leftover_kyotaku: self.leftover_kyotaku,
+            ..BatchGame::tenhou_hanchan(self.disable_progress_bar)
+        };
 
         let mut challenger_idx = 0;
         let mut champion_idx = 0;
@@ -281,7 +301,10 @@ impl TwoVsTwo {
             Box::new(new_challenger_agent(&challenger_player_ids)?),
             Box::new(new_champion_agent(&champion_player_ids)?),
         ];
-        let batch_game = BatchGame::tenhou_hanchan(self.disable_progress_bar);
+        let batch_game = BatchGame {
+            leftover_kyotaku: self.leftover_kyotaku,
+            ..BatchGame::tenhou_hanchan(self.disable_progress_bar)
+        };
 
         let indexes = if split == 0 {
             [[
@@ -0,0 +1,43 @@
+use riichi::convert::tenhou::tenhou_to_mjai;
+use riichi::logs::open_log;
+use std::env;
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const USAGE: &str = "Usage: convert_tenhou <TENHOU_LOG> [MJAI_LOG]
+
+Converts a tenhou.net/6 JSON log into mjai events, one JSON per line, which
+are written to MJAI_LOG, or stdout if it is not given. TENHOU_LOG may be
+compressed like the logs of validate_logs, and MJAI_LOG is gzip compressed
+if it ends with .gz.
+
+Sanma logs are not supported.";
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().collect();
+    let input = args.get(1).context(USAGE)?;
+
+    let mut raw = String::new();
+    open_log(Path::new(input))?
+        .read_to_string(&mut raw)
+        .with_context(|| format!("failed to read {input}"))?;
+    let events = tenhou_to_mjai(&raw).with_context(|| format!("failed to convert {input}"))?;
+    let mjai: String = events.iter().map(|ev| ev.to_line() + "\n").collect();
+
+    match args.get(2) {
+        Some(output) if output.ends_with(".gz") => {
+            let mut gz = GzEncoder::new(File::create(output)?, Compression::default());
+            gz.write_all(mjai.as_bytes())?;
+            gz.finish()?;
+        }
+        Some(output) => File::create(output)?.write_all(mjai.as_bytes())?,
+        None => io::stdout().lock().write_all(mjai.as_bytes())?,
+    }
+
+    Ok(())
+}
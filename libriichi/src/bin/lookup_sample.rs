@@ -0,0 +1,50 @@
+use riichi::dataset::{hash_from_hex, render_stamp, SampleStamp, ShardIndex, INDEX_FILENAME};
+use riichi::logs::{open_log, parse_log};
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json as json;
+
+const USAGE: &str = "Usage: lookup_sample <DATASET_DIR> <LOG_HASH> <KYOKU> <EVENT> <SEAT> [CONTEXT]
+
+Prints the events around a sample stamped by make_dataset, along with the
+state of SEAT at that point. EVENT is the 1-based index of the stamped event in
+the log. CONTEXT is the number of events shown before and after, 5 by default.";
+
+const DEFAULT_CONTEXT: usize = 5;
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().collect();
+    let dataset_dir = args.get(1).context(USAGE)?;
+    let stamp = SampleStamp {
+        log_hash: hash_from_hex(args.get(2).context(USAGE)?)?,
+        kyoku: args.get(3).context(USAGE)?.parse().context(USAGE)?,
+        event: args.get(4).context(USAGE)?.parse().context(USAGE)?,
+        seat: args.get(5).context(USAGE)?.parse().context(USAGE)?,
+    };
+    let context = match args.get(6) {
+        Some(n) => n.parse().context(USAGE)?,
+        None => DEFAULT_CONTEXT,
+    };
+
+    let index_path = Path::new(dataset_dir).join(INDEX_FILENAME);
+    let index: ShardIndex = json::from_reader(File::open(&index_path)?)
+        .with_context(|| format!("failed to parse {}", index_path.display()))?;
+    let source = index
+        .source_of(&stamp)
+        .with_context(|| format!("no source has log hash {}", stamp.log_hash_hex()))?;
+
+    let mut raw = String::new();
+    open_log(Path::new(&source.source))?
+        .read_to_string(&mut raw)
+        .with_context(|| format!("failed to read {}", source.source))?;
+    let events = parse_log(&raw).with_context(|| format!("failed to parse {}", source.source))?;
+
+    println!("{}\n", source.source);
+    println!("{}", render_stamp(&events, &stamp, context)?);
+
+    Ok(())
+}
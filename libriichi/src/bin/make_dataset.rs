@@ -0,0 +1,87 @@
+use riichi::dataset::{DatasetWriter, GameplayLoader};
+use std::env;
+
+use anyhow::{Context, Result};
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+
+const USAGE: &str = "Usage: make_dataset [--obs-stats] <LOG_DIR> <OUT_DIR> [SAMPLES_PER_SHARD]
+
+With --obs-stats, the per-channel mean, std, min and max of the obs are
+written as well, which can be loaded with `riichi.dataset.ObsStats.load`.
+
+Run it again with the same arguments to resume an interrupted run.";
+
+const DEFAULT_SAMPLES_PER_SHARD: usize = 8192;
+
+/// The number of logs loaded in parallel before being written in order.
+const LOGS_PER_BATCH: usize = 256;
+
+fn main() -> Result<()> {
+    let mut args: Vec<_> = env::args().collect();
+    let obs_stats = args.iter().any(|a| a == "--obs-stats");
+    args.retain(|a| a != "--obs-stats");
+    let log_dir = args.get(1).context(USAGE)?;
+    let out_dir = args.get(2).context(USAGE)?;
+    let samples_per_shard = match args.get(3) {
+        Some(n) => n.parse().context(USAGE)?,
+        None => DEFAULT_SAMPLES_PER_SHARD,
+    };
+
+    // Sorted so that a resumed run sees the logs in the same order.
+    let mut logs = glob(&format!("{log_dir}/**/*.json.gz"))?
+        .map(|path| Ok(path?.to_str().context("non UTF-8 path")?.to_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    logs.sort();
+
+    let mut writer = DatasetWriter::open(out_dir, samples_per_shard)?;
+    if obs_stats {
+        writer.enable_obs_stats()?;
+    }
+    let todo: Vec<_> = logs.iter().filter(|l| !writer.contains(l)).collect();
+    println!(
+        "{} logs in total, {} already written, {} to go",
+        logs.len(),
+        logs.len() - todo.len(),
+        todo.len(),
+    );
+
+    let loader = GameplayLoader {
+        oracle: true,
+        always_include_kan_select: true,
+        ..Default::default()
+    };
+
+    let bar = ProgressBar::new(todo.len() as u64).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan} [{elapsed_precise}] [{wide_bar}] {pos}/{len} {percent:>3}%")
+            .tick_chars(".oOo")
+            .progress_chars("#-"),
+    );
+    bar.enable_steady_tick(150);
+
+    for batch in todo.chunks(LOGS_PER_BATCH) {
+        let loaded: Vec<_> = batch
+            .par_iter()
+            .map(|&log| loader.load_gz_log_files(vec![log.as_str()]))
+            .collect();
+        for (log, gameplays) in batch.iter().zip(loaded) {
+            bar.inc(1);
+            match gameplays {
+                Ok(gameplays) => writer.write_source(log, &gameplays)?,
+                // The log is not recorded and will be tried again in a
+                // resumed run.
+                Err(err) => println!("\n{err:?}"),
+            }
+        }
+    }
+
+    let index = writer.finish()?;
+    bar.abandon();
+
+    let samples: usize = index.shards.iter().map(|s| s.samples).sum();
+    println!("{} shards, {samples} samples", index.shards.len());
+
+    Ok(())
+}
@@ -1,13 +1,39 @@
+use std::collections::BTreeMap;
 use std::env;
 
 use anyhow::{Context, Result};
-use riichi::stat::Stat;
+use riichi::stat::{AnomalyStat, Stat, YakuStat};
 
-const USAGE: &str = "Usage: stat <DIR> <PLAYER_NAME>";
+const USAGE: &str = "Usage: stat <DIR> <PLAYER_NAME>
+       stat --yakus <DIR>
+       stat --table <DIR>
+       stat --anomalies <DIR>";
 
 fn main() -> Result<()> {
     let args: Vec<_> = env::args().collect();
     let dir = args.get(1).context(USAGE)?;
+
+    if dir == "--yakus" {
+        let dir = args.get(2).context(USAGE)?;
+        let stat = YakuStat::from_dir(dir, false)?;
+        println!("{}", stat.to_json()?);
+        return Ok(());
+    }
+
+    if dir == "--anomalies" {
+        let dir = args.get(2).context(USAGE)?;
+        let stat = AnomalyStat::from_dir(dir, false)?;
+        println!("{}", stat.to_json()?);
+        return Ok(());
+    }
+
+    if dir == "--table" {
+        let dir = args.get(2).context(USAGE)?;
+        let stats = Stat::from_dir_by_name(dir, false)?;
+        print_table(&stats);
+        return Ok(());
+    }
+
     let player_name = args.get(2).context(USAGE)?;
 
     let stat = Stat::from_dir(dir, player_name, false)?;
@@ -15,3 +41,46 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn print_table(stats: &BTreeMap<String, Stat>) {
+    let name_width = stats
+        .keys()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<name_width$} {:>7} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>9}",
+        "Name",
+        "Games",
+        "Rounds",
+        "Avg rank",
+        "1st",
+        "2nd",
+        "3rd",
+        "4th",
+        "Agari",
+        "Deal-in",
+        "Riichi",
+        "Call",
+        "Agari pt",
+    );
+    for (name, stat) in stats {
+        println!(
+            "{:<name_width$} {:>7} {:>7} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>8.4} {:>9.1}",
+            name,
+            stat.game,
+            stat.round,
+            stat.avg_rank(),
+            stat.rank_1_rate(),
+            stat.rank_2_rate(),
+            stat.rank_3_rate(),
+            stat.rank_4_rate(),
+            stat.agari_rate(),
+            stat.houjuu_rate(),
+            stat.riichi_rate(),
+            stat.fuuro_rate(),
+            stat.avg_point_per_agari(),
+        );
+    }
+}
@@ -1,23 +1,369 @@
 use riichi::chi_type::ChiType;
-use riichi::mjai::Event;
-use riichi::state::{ActionCandidate, PlayerState};
+use riichi::convert::majsoul::majsoul_to_mjai;
+use riichi::dataset::canonical_log_hash;
+use riichi::logs::{find_logs, open_log, LogIndex};
+use riichi::mjai::{Event, EventExt, RyukyokuReason};
+use riichi::state::{ActionCandidate, DoraBreakdown, Effect, PlayerState};
 use std::env;
-use std::fs::File;
+use std::fs;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
-use anyhow::{ensure, Context, Result};
-use flate2::read::GzDecoder;
-use glob::glob;
+use anyhow::{bail, ensure, Context, Result};
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use serde_json as json;
+use serde::{Deserialize, Serialize};
 
-const USAGE: &str = "Usage: validate_logs <DIR>";
+const USAGE: &str = "Usage: validate_logs [--format <FORMAT>] [--json-errors]
+                     [--dump-states <STATE_DIR>]
+                     [--checks <CHECKS> | --skip-checks <CHECKS>]
+                     [--max-errors <N>] [--strict]
+                     [--index <INDEX_FILE> [--rebuild-index]] <DIR>
+
+--format        The format of the logs, either mjai (default) or majsoul,
+                the JSON of the records of a majsoul game.
+--json-errors   Print the states in JSON instead of the text of brief_info.
+--dump-states   Save the state before the failing event to STATE_DIR, which
+                can be reloaded with PlayerState::from_bytes.
+--checks        Only run the comma separated CHECKS.
+--skip-checks   Run all checks except the comma separated CHECKS.
+--max-errors    Stop after N failures.
+--strict        Require the deltas of each hora to be exactly those computed
+                from the winner's state, instead of only covering the points.
+--index         Keep the results of each log in INDEX_FILE, so that the logs
+                unchanged since the previous run with the same format and
+                checks are not checked again. Their failures are counted
+                but not printed again.
+--rebuild-index Ignore the existing INDEX_FILE and check every log.
+
+Logs are found recursively in DIR as *.json, or compressed as *.json.gz,
+*.json.zst or *.json.xz.
+
+A failed check skips the rest of its kyoku. The exit code is nonzero if
+anything fails.
+
+All checks are run by default. Available checks:";
+
+/// Bumped whenever the outcome of a check may change, so that an index made
+/// by older checks is not reused.
+const CHECKS_VERSION: u32 = 1;
+
+/// The number of logs listed in the summary as the worst offenders.
+const WORST_LOGS: usize = 10;
+
+/// Each kyoku ends with an `end_kyoku`, which is not tracked by
+/// `PlayerState`. A log with more ignored events than that likely contains
+/// events the state does not understand.
+const MAX_IGNORED_PER_KYOKU: usize = 1;
+
+/// An event to check, along with the states and action candidates right
+/// before it.
+struct EventCtx<'a> {
+    line: usize,
+    raw_line: &'a str,
+    ev: &'a Event,
+    states: &'a [PlayerState; 4],
+    cans: &'a [ActionCandidate; 4],
+    /// Whether the states have tracked the whole previous kyoku of the log,
+    /// which is not the case for the first kyoku or after a failed check.
+    prev_kyoku_tracked: bool,
+    /// See `Options::strict`.
+    strict: bool,
+    /// Describes a state in an error message.
+    info: &'a dyn Fn(&PlayerState) -> String,
+}
+
+/// Counts of a whole log.
+struct LogCtx {
+    ignored: usize,
+    kyokus: usize,
+}
+
+#[derive(Clone, Copy)]
+enum CheckFn {
+    Event(fn(&EventCtx<'_>) -> Result<()>),
+    Log(fn(&LogCtx) -> Result<()>),
+    /// Done by `PlayerState::update_checked` when the states are updated,
+    /// after the other checks of the event pass.
+    Update,
+}
+
+struct Check {
+    name: &'static str,
+    desc: &'static str,
+    run: CheckFn,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "consistent",
+        desc: "every event is possible from the view of each player",
+        run: CheckFn::Update,
+    },
+    Check {
+        name: "organic",
+        desc: "the log is not made with stacked draws",
+        run: CheckFn::Event(check_organic),
+    },
+    Check {
+        name: "discard",
+        desc: "dahai is allowed and the tile is a discard candidate",
+        run: CheckFn::Event(check_discard),
+    },
+    Check {
+        name: "chi",
+        desc: "chi is from kamicha and allowed in its type",
+        run: CheckFn::Event(check_chi),
+    },
+    Check {
+        name: "pon",
+        desc: "pon is allowed",
+        run: CheckFn::Event(check_pon),
+    },
+    Check {
+        name: "daiminkan",
+        desc: "daiminkan is allowed",
+        run: CheckFn::Event(check_daiminkan),
+    },
+    Check {
+        name: "ankan",
+        desc: "ankan is allowed and the tile is an ankan candidate",
+        run: CheckFn::Event(check_ankan),
+    },
+    Check {
+        name: "kakan",
+        desc: "kakan is allowed and the tile is a kakan candidate",
+        run: CheckFn::Event(check_kakan),
+    },
+    Check {
+        name: "riichi",
+        desc: "reach is allowed",
+        run: CheckFn::Event(check_riichi),
+    },
+    Check {
+        name: "hora",
+        desc: "hora is allowed and its deltas cover the points and pao",
+        run: CheckFn::Event(check_hora),
+    },
+    Check {
+        name: "tenpai",
+        desc: "players declared tenpai in an exhaustive ryukyoku are tenpai",
+        run: CheckFn::Event(check_tenpai),
+    },
+    Check {
+        name: "carryover",
+        desc: "start_kyoku carries over the scores, honba and kyotaku",
+        run: CheckFn::Event(check_carryover),
+    },
+    Check {
+        name: "dora",
+        desc: "the doras owned by each player agree with a recount",
+        run: CheckFn::Event(check_dora),
+    },
+    Check {
+        name: "ignored",
+        desc: "there are no more ignored events than expected",
+        run: CheckFn::Log(check_ignored),
+    },
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Mjai,
+    Majsoul,
+}
+
+struct Options {
+    format: LogFormat,
+    json_errors: bool,
+    dump_states: Option<String>,
+    /// Indices into `CHECKS`.
+    checks: Vec<usize>,
+    max_errors: Option<usize>,
+    /// Checks the deltas of hora by exact equality.
+    strict: bool,
+    index: Option<String>,
+    rebuild_index: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Mjai,
+            json_errors: false,
+            dump_states: None,
+            checks: (0..CHECKS.len()).collect(),
+            max_errors: None,
+            strict: false,
+            index: None,
+            rebuild_index: false,
+        }
+    }
+}
+
+/// The results of checking a log, kept in the index of `--index`.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct LogOutcome {
+    events: usize,
+    unknown_events: usize,
+    parse_error: bool,
+    /// The name and number of failures of each failed check.
+    failed_checks: Vec<(String, usize)>,
+}
+
+impl LogOutcome {
+    fn failures(&self) -> usize {
+        usize::from(self.parse_error) + self.failed_checks.iter().map(|(_, n)| n).sum::<usize>()
+    }
+}
+
+/// Counts of a run, shared by the rayon workers.
+#[derive(Default)]
+struct Summary {
+    logs: AtomicUsize,
+    events: AtomicUsize,
+    /// Events of types unknown to `Event`, which are skipped.
+    unknown_events: AtomicUsize,
+    /// Logs that cannot be read or parsed.
+    parse_errors: AtomicUsize,
+    /// Indexed by the index in `CHECKS`.
+    failed_checks: Vec<AtomicUsize>,
+    /// Each failed log with its number of failures.
+    failed_logs: Mutex<Vec<(PathBuf, usize)>>,
+}
+
+impl Summary {
+    fn new() -> Self {
+        Self {
+            failed_checks: CHECKS.iter().map(|_| AtomicUsize::new(0)).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds the outcome of a log checked in a previous run, returning its
+    /// number of failures.
+    fn add_reused(&self, outcome: &LogOutcome) -> usize {
+        self.events.fetch_add(outcome.events, Ordering::Relaxed);
+        self.unknown_events
+            .fetch_add(outcome.unknown_events, Ordering::Relaxed);
+        if outcome.parse_error {
+            self.parse_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        for (name, n) in &outcome.failed_checks {
+            if let Some(idx) = CHECKS.iter().position(|c| c.name == *name) {
+                self.failed_checks[idx].fetch_add(*n, Ordering::Relaxed);
+            }
+        }
+        outcome.failures()
+    }
+
+    fn failures(&self) -> usize {
+        self.parse_errors.load(Ordering::Relaxed)
+            + self
+                .failed_checks
+                .iter()
+                .map(|n| n.load(Ordering::Relaxed))
+                .sum::<usize>()
+    }
+
+    fn reached(&self, max_errors: Option<usize>) -> bool {
+        matches!(max_errors, Some(max) if self.failures() >= max)
+    }
+
+    /// Returns the logs with the most failures, at most `n` of them.
+    fn worst_logs(&self, n: usize) -> Vec<(PathBuf, usize)> {
+        let mut logs = self.failed_logs.lock().unwrap().clone();
+        logs.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+        logs.truncate(n);
+        logs
+    }
+
+    fn print(&self, opts: &Options) {
+        println!(
+            "\n{} logs, {} events, {} failed logs",
+            self.logs.load(Ordering::Relaxed),
+            self.events.load(Ordering::Relaxed),
+            self.failed_logs.lock().unwrap().len(),
+        );
+        let unknown_events = self.unknown_events.load(Ordering::Relaxed);
+        if unknown_events > 0 {
+            println!("{unknown_events} events of unknown types skipped");
+        }
+
+        println!("\nFailures:");
+        println!(
+            "  {:<12}{}",
+            "parse",
+            self.parse_errors.load(Ordering::Relaxed),
+        );
+        for &idx in &opts.checks {
+            println!(
+                "  {:<12}{}",
+                CHECKS[idx].name,
+                self.failed_checks[idx].load(Ordering::Relaxed),
+            );
+        }
+
+        let worst_logs = self.worst_logs(WORST_LOGS);
+        if !worst_logs.is_empty() {
+            println!("\nWorst logs:");
+            for (path, failures) in worst_logs {
+                println!("  {failures:<6}{}", path.display());
+            }
+        }
+    }
+}
 
 fn main() -> Result<()> {
-    let args: Vec<_> = env::args().collect();
-    let dir = args.get(1).context(USAGE)?;
+    let usage = || {
+        let mut usage = USAGE.to_owned();
+        for check in CHECKS {
+            usage += &format!("\n  {:<12}{}", check.name, check.desc);
+        }
+        usage
+    };
+
+    let mut opts = Options::default();
+    let mut dir = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                opts.format = match args.next().with_context(usage)?.as_str() {
+                    "mjai" => LogFormat::Mjai,
+                    "majsoul" => LogFormat::Majsoul,
+                    format => bail!("unknown format {format:?}\n\n{}", usage()),
+                };
+            }
+            "--json-errors" => opts.json_errors = true,
+            "--dump-states" => opts.dump_states = Some(args.next().with_context(usage)?),
+            "--checks" => {
+                let names = args.next().with_context(usage)?;
+                opts.checks = parse_check_names(&names).with_context(usage)?;
+            }
+            "--skip-checks" => {
+                let names = args.next().with_context(usage)?;
+                let skipped = parse_check_names(&names).with_context(usage)?;
+                opts.checks.retain(|idx| !skipped.contains(idx));
+            }
+            "--max-errors" => {
+                let n = args.next().with_context(usage)?;
+                opts.max_errors = Some(n.parse().with_context(usage)?);
+            }
+            "--strict" => opts.strict = true,
+            "--index" => opts.index = Some(args.next().with_context(usage)?),
+            "--rebuild-index" => opts.rebuild_index = true,
+            flag if flag.starts_with("--") => bail!("unknown option {flag:?}\n\n{}", usage()),
+            _ if dir.is_some() => bail!("unexpected argument {arg:?}\n\n{}", usage()),
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.with_context(usage)?;
+    if let Some(state_dir) = &opts.dump_states {
+        fs::create_dir_all(state_dir)?;
+    }
 
     let bar = ProgressBar::new_spinner().with_style(
         ProgressStyle::default_spinner()
@@ -26,39 +372,183 @@ fn main() -> Result<()> {
     );
     bar.enable_steady_tick(150);
 
-    glob(&format!("{dir}/**/*.json"))?
-        .chain(glob(&format!("{dir}/**/*.json.gz"))?)
-        .par_bridge()
-        .try_for_each(|path| {
-            bar.inc(1);
-            let path = path?;
-
-            let result = process_path(&path).with_context(|| format!("in log {}", path.display()));
-            if let Err(err) = result {
-                println!("\n{err:?}");
-            }
+    let index = opts
+        .index
+        .as_ref()
+        .map(|path| LogIndex::open(path, &index_context(&opts), opts.rebuild_index))
+        .transpose()?;
 
-            anyhow::Ok(())
-        })?;
+    let summary = Summary::new();
+    find_logs(&dir)?.par_bridge().try_for_each(|path| {
+        bar.inc(1);
+        if !summary.reached(opts.max_errors) {
+            process_path(&path?, &opts, &summary, index.as_ref());
+        }
+        anyhow::Ok(())
+    })?;
 
     bar.abandon();
 
+    summary.print(&opts);
+    if let Some(index) = &index {
+        index.save()?;
+        let report = index.report();
+        println!(
+            "\nIndex: {} logs skipped, {} parsed, of which {} unchanged",
+            report.skipped, report.parsed, report.same_hash,
+        );
+    }
+    let failures = summary.failures();
+    if failures > 0 {
+        if summary.reached(opts.max_errors) {
+            println!("\nstopped early after {failures} failures");
+        }
+        bail!("{failures} failures");
+    }
+
     Ok(())
 }
 
-fn process_path(path: &Path) -> Result<()> {
+/// Identifies the options affecting the outcomes kept in the index, along
+/// with the version of the checks.
+fn index_context(opts: &Options) -> String {
+    let format = match opts.format {
+        LogFormat::Mjai => "mjai",
+        LogFormat::Majsoul => "majsoul",
+    };
+    let checks: Vec<_> = opts.checks.iter().map(|&idx| CHECKS[idx].name).collect();
+    format!(
+        "validate_logs v{CHECKS_VERSION} format={format} checks={} strict={}",
+        checks.join(","),
+        opts.strict,
+    )
+}
+
+/// Returns the indices into `CHECKS` of the comma separated names.
+fn parse_check_names(names: &str) -> Result<Vec<usize>> {
+    names
+        .split(',')
+        .map(|name| {
+            CHECKS
+                .iter()
+                .position(|check| check.name == name.trim())
+                .with_context(|| format!("unknown check {name:?}"))
+        })
+        .collect()
+}
+
+/// Checks a log and adds the results to `summary`, printing every failure.
+/// With `index`, a log unchanged since it is indexed is not checked again,
+/// and the outcome of a newly checked log is recorded.
+fn process_path(path: &Path, opts: &Options, summary: &Summary, index: Option<&LogIndex>) {
+    summary.logs.fetch_add(1, Ordering::Relaxed);
+    if let Some(outcome) = index.and_then(|index| index.lookup::<LogOutcome>(path)) {
+        let failures = summary.add_reused(&outcome);
+        if failures > 0 {
+            summary
+                .failed_logs
+                .lock()
+                .unwrap()
+                .push((path.to_owned(), failures));
+        }
+        return;
+    }
+
+    let failures = match check_log(path, opts, summary, index) {
+        Ok(failures) => failures,
+        Err(err) => {
+            summary.parse_errors.fetch_add(1, Ordering::Relaxed);
+            println!("\n{:?}", err.context(format!("in log {}", path.display())));
+            if let Some(index) = index {
+                let outcome = LogOutcome {
+                    parse_error: true,
+                    ..Default::default()
+                };
+                index_outcome(index, path, None, &outcome);
+            }
+            1
+        }
+    };
+    if failures > 0 {
+        summary
+            .failed_logs
+            .lock()
+            .unwrap()
+            .push((path.to_owned(), failures));
+    }
+}
+
+/// Returns the number of failures. Once a check fails, the rest of the kyoku
+/// is skipped, as the states can no longer be trusted.
+///
+/// With `index`, the outcome is recorded unless the run is stopped by
+/// `max_errors` in the middle of the log. A log of the same events as indexed
+/// is not checked again.
+///
+/// Err is returned only if the log cannot be read or parsed.
+fn check_log(
+    path: &Path,
+    opts: &Options,
+    summary: &Summary,
+    index: Option<&LogIndex>,
+) -> Result<usize> {
     let mut raw_log = String::new();
-    if matches!(path.extension(), Some(s) if s.eq_ignore_ascii_case("gz")) {
-        let mut gz = GzDecoder::new(File::open(path)?);
-        gz.read_to_string(&mut raw_log)?;
-    } else {
-        let mut f = File::open(path)?;
-        f.read_to_string(&mut raw_log)?;
+    open_log(path)?
+        .read_to_string(&mut raw_log)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    if opts.format == LogFormat::Majsoul {
+        // Converted into mjai lines, to which the line numbers refer.
+        raw_log = majsoul_to_mjai(&raw_log)
+            .with_context(|| format!("failed to convert {}", path.display()))?
+            .iter()
+            .map(Event::to_line)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    // Both the 13-tile and 14-tile oya conventions of start_kyoku are
+    // accepted, and the events keep the lines they come from.
+    let mut events = vec![];
+    for (idx, l) in raw_log.lines().enumerate() {
+        let line = idx + 1;
+        let parsed =
+            Event::parse_line(l).with_context(|| format!("failed to parse line {line}"))?;
+        events.extend(parsed.into_iter().map(|ev| (line, l, ev)));
+    }
+    let hash = index.map(|index| {
+        let evs: Vec<_> = events.iter().map(|(_, _, ev)| ev.clone()).collect();
+        (index, canonical_log_hash(&evs))
+    });
+    if let Some((index, hash)) = &hash {
+        if let Some(outcome) = index.lookup_hash::<LogOutcome>(path, hash) {
+            return Ok(summary.add_reused(&outcome));
+        }
     }
-    let events: Vec<Event> = raw_log
-        .lines()
-        .map(|l| Ok(json::from_str(l)?))
-        .collect::<Result<_>>()?;
+    summary.events.fetch_add(events.len(), Ordering::Relaxed);
+
+    let info = |state: &PlayerState| {
+        let mut ret = if opts.json_errors {
+            state.dump_json()
+        } else {
+            state.brief_info()
+        };
+        if let Some(state_dir) = &opts.dump_states {
+            let filename = path.file_name().unwrap_or_default().to_string_lossy();
+            let state_path =
+                Path::new(state_dir).join(format!("{filename}.p{}.bin", state.player_id()));
+            match fs::write(&state_path, state.to_bytes()) {
+                Ok(()) => ret += &format!("\nstate saved to {}", state_path.display()),
+                Err(err) => ret += &format!("\nfailed to save the state: {err}"),
+            }
+        }
+        ret
+    };
+    let report = |idx: usize, err: anyhow::Error| {
+        summary.failed_checks[idx].fetch_add(1, Ordering::Relaxed);
+        let err = err
+            .context(format!("failed check `{}`", CHECKS[idx].name))
+            .context(format!("in log {}", path.display()));
+        println!("\n{err:?}");
+    };
 
     let mut states = [
         PlayerState::new(0),
@@ -67,165 +557,912 @@ fn process_path(path: &Path) -> Result<()> {
         PlayerState::new(3),
     ];
     let mut cans = [ActionCandidate::default(); 4];
+    let mut log_ctx = LogCtx {
+        ignored: 0,
+        kyokus: 0,
+    };
+    let mut outcome = LogOutcome {
+        events: events.len(),
+        ..Default::default()
+    };
+    let mut failed_checks = vec![0; CHECKS.len()];
+    let mut skipping_kyoku = false;
+    let mut prev_kyoku_tracked = false;
+    let update_check = opts
+        .checks
+        .iter()
+        .copied()
+        .find(|&idx| matches!(CHECKS[idx].run, CheckFn::Update));
 
-    for (idx, ev) in events.iter().enumerate() {
-        let line = idx + 1;
-        match ev {
-            Event::Dahai { actor, pai, .. } => {
-                ensure!(
-                    cans[*actor as usize].can_discard,
-                    "fails can_discard at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-
-                let discard_candidates = states[*actor as usize].discard_candidates_aka();
-                ensure!(
-                    discard_candidates[pai.as_usize()],
-                    "fails discard_candidates at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-            }
-            Event::Chi {
-                actor,
-                consumed,
-                pai,
-                target,
-            } => {
-                ensure!(
-                    (target + 1) % 4 == *actor,
-                    "chi from non-kamicha at line {}\nstate:\n{}",
-                    line,
-                    states[*actor as usize].brief_info(),
-                );
-
-                match ChiType::new(*consumed, *pai) {
-                    ChiType::Low => {
-                        ensure!(
-                            cans[*actor as usize].can_chi_low,
-                            "fails can_chi_low at line {}\nstate:\n{}",
-                            line,
-                            states[*actor as usize].brief_info(),
-                        );
-                    }
-                    ChiType::Mid => {
-                        ensure!(
-                            cans[*actor as usize].can_chi_mid,
-                            "fails can_chi_mid at line {}\nstate:\n{}",
-                            line,
-                            states[*actor as usize].brief_info(),
-                        );
-                    }
-                    ChiType::High => {
-                        ensure!(
-                            cans[*actor as usize].can_chi_high,
-                            "fails can_chi_high at line {}\nstate:\n{}",
-                            line,
-                            states[*actor as usize].brief_info(),
-                        );
-                    }
+    for (line, raw_line, ev) in &events {
+        if let Event::Unknown(_) = ev {
+            summary.unknown_events.fetch_add(1, Ordering::Relaxed);
+            outcome.unknown_events += 1;
+            continue;
+        }
+        if Effect::of(ev) == Effect::Ignored {
+            log_ctx.ignored += 1;
+        }
+        if matches!(ev, Event::StartKyoku { .. }) {
+            prev_kyoku_tracked = log_ctx.kyokus > 0 && !skipping_kyoku;
+            log_ctx.kyokus += 1;
+            skipping_kyoku = false;
+        }
+        if skipping_kyoku {
+            continue;
+        }
+
+        let ctx = EventCtx {
+            line: *line,
+            raw_line,
+            ev,
+            states: &states,
+            cans: &cans,
+            prev_kyoku_tracked,
+            strict: opts.strict,
+            info: &info,
+        };
+        let mut failed = opts.checks.iter().find_map(|&idx| match CHECKS[idx].run {
+            CheckFn::Event(check) => check(&ctx).err().map(|err| (idx, err)),
+            CheckFn::Log(_) | CheckFn::Update => None,
+        });
+        if failed.is_none() {
+            failed = match update_check {
+                Some(idx) => update_checked(&mut states, &mut cans, ev, *line, &info)
+                    .err()
+                    .map(|err| (idx, err)),
+                None => {
+                    states
+                        .iter_mut()
+                        .zip(&mut cans)
+                        .for_each(|(s, c)| *c = s.update_with_skip(ev, true));
+                    None
                 }
+            };
+        }
+        if let Some((idx, err)) = failed {
+            report(idx, err);
+            failed_checks[idx] += 1;
+            if summary.reached(opts.max_errors) {
+                return Ok(failed_checks.iter().sum());
             }
-            Event::Pon { actor, .. } => {
-                ensure!(
-                    cans[*actor as usize].can_pon,
-                    "fails can_pon at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-            }
-            Event::Daiminkan { actor, .. } => {
-                ensure!(
-                    cans[*actor as usize].can_daiminkan,
-                    "fails can_daiminkan at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-            }
-            Event::Ankan { actor, consumed } => {
-                ensure!(
-                    cans[*actor as usize].can_ankan,
-                    "fails can_ankan at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-
-                let ankan_candidates = states[*actor as usize].ankan_candidates();
-                ensure!(
-                    ankan_candidates.contains(&consumed[0].deaka()),
-                    "fails ankan_candidates at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-            }
-            Event::Kakan { actor, pai, .. } => {
-                ensure!(
-                    cans[*actor as usize].can_kakan,
-                    "fails can_kakan at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
-
-                let kakan_candidates = states[*actor as usize].kakan_candidates();
-                ensure!(
-                    kakan_candidates.contains(&pai.deaka()),
-                    "fails kakan_candidates at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
+            skipping_kyoku = true;
+        }
+    }
+
+    for &idx in &opts.checks {
+        if let CheckFn::Log(check) = CHECKS[idx].run {
+            if let Err(err) = check(&log_ctx) {
+                report(idx, err);
+                failed_checks[idx] += 1;
             }
-            Event::Reach { actor } => {
-                ensure!(
-                    cans[*actor as usize].can_riichi,
-                    "fails can_riichi at line {line}\nstate:\n{}",
-                    states[*actor as usize].brief_info(),
-                );
+        }
+    }
+
+    outcome.failed_checks = CHECKS
+        .iter()
+        .zip(failed_checks)
+        .filter(|&(_, n)| n > 0)
+        .map(|(check, n)| (check.name.to_owned(), n))
+        .collect();
+    let failures = outcome.failures();
+    if let Some((index, hash)) = &hash {
+        index_outcome(index, path, Some(hash), &outcome);
+    }
+    Ok(failures)
+}
+
+/// Records `outcome` in `index`. A failure only costs checking the log again
+/// next time, so it is printed rather than counted.
+fn index_outcome(index: &LogIndex, path: &Path, hash: Option<&[u8; 32]>, outcome: &LogOutcome) {
+    if let Err(err) = index.insert(path, hash, outcome) {
+        println!("\nfailed to index {}: {err:?}", path.display());
+    }
+}
+
+/// Updates the states with `PlayerState::update_checked`, stopping at the
+/// first player the event is impossible for, whose state is left untouched.
+fn update_checked(
+    states: &mut [PlayerState; 4],
+    cans: &mut [ActionCandidate; 4],
+    ev: &Event,
+    line: usize,
+    info: &dyn Fn(&PlayerState) -> String,
+) -> Result<()> {
+    for (state, c) in states.iter_mut().zip(cans) {
+        *c = state.update_checked_with_skip(ev, true).with_context(|| {
+            format!(
+                "inconsistent event for player {} at line {line}\nstate:\n{}",
+                state.player_id(),
+                info(state),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn check_organic(ctx: &EventCtx<'_>) -> Result<()> {
+    ensure!(
+        !EventExt::is_stacked_draws_marker(ctx.raw_line, ctx.ev),
+        "stacked draws at line {}, the log is not organic",
+        ctx.line,
+    );
+    Ok(())
+}
+
+fn check_discard(ctx: &EventCtx<'_>) -> Result<()> {
+    let &EventCtx {
+        line, states, cans, ..
+    } = ctx;
+    if let Event::Dahai { actor, pai, .. } = *ctx.ev {
+        let state = &states[actor as usize];
+        ensure!(
+            cans[actor as usize].can_discard,
+            "fails can_discard at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+
+        let discard_candidates = state.discard_candidates_aka();
+        ensure!(
+            discard_candidates[pai.as_usize()],
+            "fails discard_candidates at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    }
+    Ok(())
+}
+
+fn check_chi(ctx: &EventCtx<'_>) -> Result<()> {
+    let &EventCtx {
+        line, states, cans, ..
+    } = ctx;
+    if let Event::Chi {
+        actor,
+        consumed,
+        pai,
+        target,
+    } = *ctx.ev
+    {
+        let state = &states[actor as usize];
+        ensure!(
+            (target + 1) % 4 == actor,
+            "chi from non-kamicha at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+
+        let (can, chi_type) = match ChiType::new(consumed, pai) {
+            ChiType::Low => (cans[actor as usize].can_chi_low, "low"),
+            ChiType::Mid => (cans[actor as usize].can_chi_mid, "mid"),
+            ChiType::High => (cans[actor as usize].can_chi_high, "high"),
+        };
+        ensure!(
+            can,
+            "fails can_chi_{chi_type} at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    }
+    Ok(())
+}
+
+fn check_pon(ctx: &EventCtx<'_>) -> Result<()> {
+    if let Event::Pon { actor, .. } = *ctx.ev {
+        ensure!(
+            ctx.cans[actor as usize].can_pon,
+            "fails can_pon at line {}\nstate:\n{}",
+            ctx.line,
+            (ctx.info)(&ctx.states[actor as usize]),
+        );
+    }
+    Ok(())
+}
+
+fn check_daiminkan(ctx: &EventCtx<'_>) -> Result<()> {
+    if let Event::Daiminkan { actor, .. } = *ctx.ev {
+        ensure!(
+            ctx.cans[actor as usize].can_daiminkan,
+            "fails can_daiminkan at line {}\nstate:\n{}",
+            ctx.line,
+            (ctx.info)(&ctx.states[actor as usize]),
+        );
+    }
+    Ok(())
+}
+
+fn check_ankan(ctx: &EventCtx<'_>) -> Result<()> {
+    let &EventCtx {
+        line, states, cans, ..
+    } = ctx;
+    if let Event::Ankan { actor, consumed } = *ctx.ev {
+        let state = &states[actor as usize];
+        ensure!(
+            cans[actor as usize].can_ankan,
+            "fails can_ankan at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+
+        let ankan_candidates = state.ankan_candidates();
+        ensure!(
+            ankan_candidates.contains(&consumed[0].deaka()),
+            "fails ankan_candidates at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    }
+    Ok(())
+}
+
+fn check_kakan(ctx: &EventCtx<'_>) -> Result<()> {
+    let &EventCtx {
+        line, states, cans, ..
+    } = ctx;
+    if let Event::Kakan { actor, pai, .. } = *ctx.ev {
+        let state = &states[actor as usize];
+        ensure!(
+            cans[actor as usize].can_kakan,
+            "fails can_kakan at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+
+        let kakan_candidates = state.kakan_candidates();
+        ensure!(
+            kakan_candidates.contains(&pai.deaka()),
+            "fails kakan_candidates at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    }
+    Ok(())
+}
+
+fn check_riichi(ctx: &EventCtx<'_>) -> Result<()> {
+    if let Event::Reach { actor } = *ctx.ev {
+        ensure!(
+            ctx.cans[actor as usize].can_riichi,
+            "fails can_riichi at line {}\nstate:\n{}",
+            ctx.line,
+            (ctx.info)(&ctx.states[actor as usize]),
+        );
+    }
+    Ok(())
+}
+
+fn check_hora(ctx: &EventCtx<'_>) -> Result<()> {
+    let &EventCtx {
+        line, states, cans, ..
+    } = ctx;
+    let (actor, target, ura_markers, deltas) = match ctx.ev {
+        Event::Hora {
+            actor,
+            target,
+            ura_markers,
+            deltas,
+        } => (*actor, *target, ura_markers, deltas),
+        _ => return Ok(()),
+    };
+    let state = &states[actor as usize];
+
+    let is_ron = actor != target;
+    if is_ron {
+        ensure!(
+            cans[actor as usize].can_ron_agari,
+            "fails can_ron_agari at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    } else {
+        ensure!(
+            cans[actor as usize].can_tsumo_agari,
+            "fails can_tsumo_agari at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+    }
+
+    let ura = ura_markers
+        .as_ref()
+        .context("missing field `ura_markers`")?;
+    let deltas = deltas.context("missing field `deltas`")?;
+    if ctx.strict {
+        let from = (target + 4 - actor) % 4;
+        let mut expected = state.agari_deltas(is_ron, from, ura).with_context(|| {
+            format!(
+                "failed to get agari deltas at line {line}\nstate:\n{}",
+                (ctx.info)(state)
+            )
+        })?;
+        expected.rotate_right(actor as usize);
+        ensure!(
+            deltas == expected,
+            "deltas {deltas:?} differ from {expected:?} at line {line}\nstate:\n{}",
+            (ctx.info)(state),
+        );
+        return Ok(());
+    }
+
+    // This is a rough test
+    let points = state.agari_points(is_ron, ura).with_context(|| {
+        format!(
+            "failed to get agari points at line {line}\nstate:\n{}",
+            (ctx.info)(state)
+        )
+    })?;
+
+    if is_ron {
+        ensure!(deltas[actor as usize] >= points.ron);
+    } else if state.is_oya() {
+        ensure!(deltas[actor as usize] >= points.tsumo_oya);
+    } else {
+        ensure!(deltas[actor as usize] >= points.tsumo_ko);
+    }
+
+    if let Some(pao) = state.pao_liable_seat() {
+        let pao_target = (actor + pao) % 4;
+        let liability = if is_ron { points.ron / 2 } else { points.ron };
+        ensure!(
+            deltas[pao_target as usize] <= -liability,
+            "pao target {pao_target} does not pay {liability} at line {line}",
+        );
+    }
+
+    Ok(())
+}
+
+fn check_tenpai(ctx: &EventCtx<'_>) -> Result<()> {
+    if let Event::Ryukyoku {
+        reason: None | Some(RyukyokuReason::Exhaustive),
+        tenpais: Some(tenpais),
+        ..
+    } = ctx.ev
+    {
+        for (state, &tenpai) in ctx.states.iter().zip(tenpais) {
+            ensure!(
+                !tenpai || state.shanten() == 0,
+                "declared tenpai but is not at line {}\nstate:\n{}",
+                ctx.line,
+                (ctx.info)(state),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The final scores of a game are not in the log, so how the kyotakus left at
+/// the end are given out is not checked here.
+fn check_carryover(ctx: &EventCtx<'_>) -> Result<()> {
+    if let Event::StartKyoku {
+        honba,
+        kyotaku,
+        scores,
+        ..
+    } = *ctx.ev
+    {
+        if !ctx.prev_kyoku_tracked {
+            return Ok(());
+        }
+
+        // `states[0]` has the scores in absolute seats.
+        let prev = &ctx.states[0];
+        ensure!(
+            scores == prev.scores(),
+            "scores {scores:?} do not follow {:?} of the previous kyoku at line {}",
+            prev.scores(),
+            ctx.line,
+        );
+        ensure!(
+            kyotaku == prev.kyotaku(),
+            "kyotaku {kyotaku} does not follow {} of the previous kyoku at line {}",
+            prev.kyotaku(),
+            ctx.line,
+        );
+        // After an agari, the honba is reset unless the oya is one of the
+        // winners, which the states do not tell.
+        ensure!(
+            honba == prev.honba() + 1 || honba == 0 && !prev.last_kyoku_ended_in_draw(),
+            "honba {honba} does not follow {} of the previous kyoku at line {}",
+            prev.honba(),
+            ctx.line,
+        );
+    }
+    Ok(())
+}
+
+/// The states are checked before the event, which is after the previous
+/// one, so the last event of a log is not covered, which is an `end_game`
+/// anyway.
+fn check_dora(ctx: &EventCtx<'_>) -> Result<()> {
+    for state in ctx.states {
+        let DoraBreakdown { dora, aka, .. } = state.dora_breakdown(&[]);
+        ensure!(
+            dora + aka == state.doras_owned(),
+            "doras_owned {} of player {} differs from the recount of {dora} doras and {aka} akas at line {}\nstate:\n{}",
+            state.doras_owned(),
+            state.player_id(),
+            ctx.line,
+            (ctx.info)(state),
+        );
+    }
+    Ok(())
+}
+
+fn check_ignored(ctx: &LogCtx) -> Result<()> {
+    // `start_game` and `end_game` are ignored as well.
+    let LogCtx { ignored, kyokus } = *ctx;
+    if ignored > 2 + kyokus * MAX_IGNORED_PER_KYOKU {
+        bail!("too many ignored events: {ignored} in {kyokus} kyokus");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use riichi::logs::IndexReport;
+    use riichi::state::UpdateError;
+    use riichi::t;
+    use serde_json as json;
+
+    /// Runs the check on each event of `log`, returning the first failure.
+    fn run_check(name: &str, log: &str) -> Result<()> {
+        run_check_with(name, log, false)
+    }
+
+    /// Same as `run_check`, with `--strict` or not.
+    fn run_check_with(name: &str, log: &str, strict: bool) -> Result<()> {
+        let check = CHECKS.iter().find(|c| c.name == name).unwrap();
+        let check = match check.run {
+            CheckFn::Event(check) => Some(check),
+            CheckFn::Update => None,
+            CheckFn::Log(_) => unreachable!(),
+        };
+
+        let mut states = [0, 1, 2, 3].map(PlayerState::new);
+        let mut cans = [ActionCandidate::default(); 4];
+        let mut kyokus = 0;
+        for (idx, raw_line) in log.lines().enumerate() {
+            let raw_line = raw_line.trim();
+            if raw_line.is_empty() {
+                continue;
             }
-            Event::Hora {
-                actor,
-                target,
-                ura_markers,
-                deltas,
-            } => {
-                let is_ron = actor != target;
-                if is_ron {
-                    ensure!(
-                        cans[*actor as usize].can_ron_agari,
-                        "fails can_ron_agari at line {line}\nstate:\n{}",
-                        states[*actor as usize].brief_info(),
-                    );
-                } else {
-                    ensure!(
-                        cans[*actor as usize].can_tsumo_agari,
-                        "fails can_tsumo_agari at line {line}\nstate:\n{}",
-                        states[*actor as usize].brief_info(),
-                    );
+            for ev in Event::parse_line(raw_line)? {
+                if matches!(ev, Event::StartKyoku { .. }) {
+                    kyokus += 1;
                 }
-
-                // This is a rough test
-                // TODO: fix bug for double chankan ron
-                let ura = ura_markers
-                    .as_ref()
-                    .context("missing field `ura_markers`")?;
-                let deltas = deltas.context("missing field `deltas`")?;
-                let points = states[*actor as usize]
-                    .agari_points(is_ron, ura)
-                    .with_context(|| {
-                        format!(
-                            "failed to get agari points at line {line}\nstate:\n{}",
-                            states[*actor as usize].brief_info()
-                        )
-                    })?;
-
-                if is_ron {
-                    ensure!(deltas[*actor as usize] >= points.ron);
-                } else if states[*actor as usize].is_oya() {
-                    ensure!(deltas[*actor as usize] >= points.tsumo_oya);
+                let ctx = EventCtx {
+                    line: idx + 1,
+                    raw_line,
+                    ev: &ev,
+                    states: &states,
+                    cans: &cans,
+                    prev_kyoku_tracked: kyokus > 1,
+                    strict,
+                    info: &PlayerState::brief_info,
+                };
+                if let Some(check) = check {
+                    check(&ctx)?;
+                    states
+                        .iter_mut()
+                        .zip(&mut cans)
+                        .for_each(|(s, c)| *c = s.update_with_skip(&ev, true));
                 } else {
-                    ensure!(deltas[*actor as usize] >= points.tsumo_ko);
+                    update_checked(
+                        &mut states,
+                        &mut cans,
+                        &ev,
+                        idx + 1,
+                        &PlayerState::brief_info,
+                    )?;
                 }
             }
-            _ => (),
         }
+        Ok(())
+    }
+
+    const START: &str = r#"
+        {"type":"start_game"}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","1m","2p","3p","4p","5s","6s","7s","E","E","8p"],["9p","9p","2m","3m","4m","2s","3s","4s","5p","6p","W","N","C"],["5m","6m","7m","8m","6p","7p","8p","8s","9s","P","P","F","F"],["1p","1p","1s","1s","9m","9m","W","W","N","C","C","F","8s"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
 
-        states
-            .iter_mut()
-            .zip(&mut cans)
-            .for_each(|(s, c)| *c = s.update_with_skip(ev, true));
+    /// The `start_kyoku` of `START` with another honba, kyotaku and scores.
+    fn restart(honba: u8, kyotaku: u8, scores: [i32; 4]) -> String {
+        let line = START.trim().lines().nth(1).unwrap().trim();
+        let mut ev: json::Value = json::from_str(line).unwrap();
+        ev["honba"] = honba.into();
+        ev["kyotaku"] = kyotaku.into();
+        ev["scores"] = scores.to_vec().into();
+        ev.to_string()
     }
 
-    Ok(())
+    #[test]
+    fn carryover() {
+        // Player 0 riichis into an exhaustive ryukyoku, and the riichi stick
+        // is carried over until the agari of player 1.
+        let draw = format!(
+            "{START}{}",
+            r#"
+            {"type":"reach","actor":0}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+            {"type":"reach_accepted","actor":0}
+            {"type":"ryukyoku","reason":"fanpai","deltas":[3000,-1000,-1000,-1000],"tenpais":[true,false,false,false]}
+            {"type":"end_kyoku"}
+            "#,
+        );
+        let after_draw = [27000, 24000, 24000, 24000];
+        run_check("carryover", &format!("{draw}{}", restart(1, 1, after_draw))).unwrap();
+        for wrong in [
+            restart(0, 1, after_draw),
+            restart(1, 0, after_draw),
+            restart(1, 2, after_draw),
+            restart(1, 1, [28000, 24000, 24000, 24000]),
+        ] {
+            assert!(run_check("carryover", &format!("{draw}{wrong}")).is_err());
+        }
+
+        let agari = format!(
+            "{draw}{}{}",
+            restart(1, 1, after_draw),
+            r#"
+            {"type":"tsumo","actor":0,"pai":"N"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+            {"type":"hora","actor":1,"target":0,"deltas":[-1300,2300,0,0]}
+            {"type":"end_kyoku"}
+            "#,
+        );
+        let after_agari = [25700, 26300, 24000, 24000];
+        // The honba is either reset, or increased by the renchan of the oya.
+        for honba in [0, 2] {
+            let log = format!("{agari}{}", restart(honba, 0, after_agari));
+            run_check("carryover", &log).unwrap();
+        }
+        for wrong in [restart(1, 0, after_agari), restart(0, 1, after_agari)] {
+            assert!(run_check("carryover", &format!("{agari}{wrong}")).is_err());
+        }
+    }
+
+    #[test]
+    fn consistent() {
+        let with = |tail: &str| format!("{START}{tail}");
+        run_check(
+            "consistent",
+            &with(r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#),
+        )
+        .unwrap();
+
+        for (tail, expected) in [
+            (
+                r#"{"type":"dahai","actor":0,"pai":"9m","tsumogiri":false}"#,
+                UpdateError::TileNotInHand { tile: t!(9m) },
+            ),
+            (
+                r#"{"type":"dahai","actor":1,"pai":"9m","tsumogiri":false}"#,
+                UpdateError::OutOfTurn {
+                    actor: 1,
+                    expected: 0,
+                },
+            ),
+        ] {
+            let err = run_check("consistent", &with(tail)).unwrap_err();
+            assert!(err.to_string().contains("at line 5"), "{err}");
+            assert_eq!(err.root_cause().downcast_ref(), Some(&expected));
+        }
+    }
+
+    #[test]
+    fn discard() {
+        let log = format!(
+            "{START}{}",
+            r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#
+        );
+        run_check("discard", &log).unwrap();
+
+        let log = format!(
+            "{START}{}",
+            r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":false}"#
+        );
+        assert!(run_check("discard", &log).is_err());
+    }
+
+    #[test]
+    fn ankan() {
+        let log = format!(
+            "{START}{}",
+            r#"{"type":"ankan","actor":0,"consumed":["1m","1m","1m","1m"]}"#
+        );
+        run_check("ankan", &log).unwrap();
+
+        let log = format!(
+            "{START}{}",
+            r#"{"type":"ankan","actor":0,"consumed":["E","E","E","E"]}"#
+        );
+        assert!(run_check("ankan", &log).is_err());
+    }
+
+    #[test]
+    fn dora() {
+        // The kan dora comes after the ankan and turns all of its tiles into
+        // doras, and the rinshan tile brings an aka.
+        let log = format!(
+            "{START}{}",
+            r#"
+            {"type":"ankan","actor":0,"consumed":["1m","1m","1m","1m"]}
+            {"type":"dora","dora_marker":"9m"}
+            {"type":"tsumo","actor":0,"pai":"5mr"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+            "#
+        );
+        run_check("dora", &log).unwrap();
+    }
+
+    #[test]
+    fn hora() {
+        // Player 1 rons 5m with tanyao, 1 han 40 fu for the tanki, which is
+        // 1300 plus 1 honba and 1 kyotaku.
+        let log = |deltas: &str| {
+            format!(
+                r#"
+                {{"type":"start_game"}}
+                {{"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":1,"kyotaku":1,"oya":0,"scores":[25000,24000,25000,25000],"tehais":[["1m","1m","1m","1m","2p","3p","4p","5s","6s","7s","E","E","5m"],["2m","3m","4m","5p","6p","7p","2s","3s","4s","6s","7s","8s","5m"],["5m","6m","7m","8m","6p","7p","8p","8s","9s","P","P","F","F"],["1p","1p","1s","1s","9m","9m","W","W","N","C","C","F","8s"]]}}
+                {{"type":"tsumo","actor":0,"pai":"N"}}
+                {{"type":"dahai","actor":0,"pai":"5m","tsumogiri":false}}
+                {{"type":"hora","actor":1,"target":0,"deltas":{deltas},"ura_markers":[]}}
+                "#,
+            )
+        };
+        for strict in [false, true] {
+            run_check_with("hora", &log("[-1600,2600,0,0]"), strict).unwrap();
+        }
+
+        // Missing the honba only passes the rough check.
+        let missing_honba = log("[-1300,2300,0,0]");
+        run_check_with("hora", &missing_honba, false).unwrap();
+        let err = run_check_with("hora", &missing_honba, true).unwrap_err();
+        assert!(err.to_string().contains("at line 6"), "{err}");
+
+        // Short of the points is caught either way.
+        for strict in [false, true] {
+            assert!(run_check_with("hora", &log("[-1000,1000,0,0]"), strict).is_err());
+        }
+    }
+
+    #[test]
+    fn organic() {
+        run_check("organic", START).unwrap();
+
+        let log = format!(
+            "{START}{}",
+            r#"{"type":"none","meta":{"stacked_draws":["E"]}}"#
+        );
+        assert!(run_check("organic", &log).is_err());
+    }
+
+    #[test]
+    fn ignored() {
+        assert!(check_ignored(&LogCtx {
+            ignored: 4,
+            kyokus: 2
+        })
+        .is_ok());
+        assert!(check_ignored(&LogCtx {
+            ignored: 5,
+            kyokus: 2
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn summary() {
+        let dir = env::temp_dir().join(format!("validate_logs_summary_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            let content: Vec<_> = content.lines().map(str::trim).collect();
+            fs::write(&path, content.join("\n").trim()).unwrap();
+            path
+        };
+
+        let good = write(
+            "good.json",
+            &format!(
+                "{START}{}",
+                r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#
+            ),
+        );
+        // Both kyokus fail, while the tsumo after the bad discard is skipped.
+        let start_kyoku = START.trim().lines().nth(1).unwrap();
+        let bad_discard = format!(
+            "{START}{}\n{}\n{start_kyoku}\n{}",
+            r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":false}"#,
+            r#"{"type":"tsumo","actor":1,"pai":"E"}"#,
+            r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":false}"#,
+        );
+        let bad_discard = write("bad_discard.json", &bad_discard);
+        let bad_parse = write("bad_parse.json", "{\"type\":\"dahai\"}");
+
+        let opts = Options::default();
+        let summary = Summary::new();
+        for path in [&good, &bad_discard, &bad_parse] {
+            process_path(path, &opts, &summary, None);
+        }
+        assert_eq!(summary.logs.load(Ordering::Relaxed), 3);
+        assert_eq!(summary.events.load(Ordering::Relaxed), 4 + 7);
+        assert_eq!(summary.parse_errors.load(Ordering::Relaxed), 1);
+        let failed_checks: Vec<_> = CHECKS
+            .iter()
+            .zip(&summary.failed_checks)
+            .map(|(check, n)| (check.name, n.load(Ordering::Relaxed)))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        assert_eq!(failed_checks, [("discard", 2)]);
+        assert_eq!(summary.failures(), 3);
+        assert_eq!(
+            summary.worst_logs(WORST_LOGS),
+            [(bad_discard.clone(), 2), (bad_parse, 1)],
+        );
+        assert_eq!(summary.worst_logs(1), [(bad_discard.clone(), 2)]);
+
+        let opts = Options {
+            max_errors: Some(1),
+            ..Default::default()
+        };
+        let summary = Summary::new();
+        process_path(&bad_discard, &opts, &summary, None);
+        assert_eq!(summary.failures(), 1);
+        assert!(summary.reached(opts.max_errors));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn index() {
+        let dir = env::temp_dir().join(format!("validate_logs_index_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("index.jsonl");
+        let start: Vec<_> = START.trim().lines().map(str::trim).collect();
+        let start = start.join("\n");
+        let good = dir.join("good.json");
+        let bad = dir.join("bad.json");
+        let broken = dir.join("broken.json");
+        fs::write(
+            &good,
+            format!(
+                "{start}\n{}",
+                r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#,
+            ),
+        )
+        .unwrap();
+        fs::write(
+            &bad,
+            format!(
+                "{start}\n{}",
+                r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":false}"#,
+            ),
+        )
+        .unwrap();
+        fs::write(&broken, "{\"type\":\"dahai\"}").unwrap();
+
+        let opts = Options::default();
+        let run = |rebuild| {
+            let index = LogIndex::open(&index_path, &index_context(&opts), rebuild).unwrap();
+            let summary = Summary::new();
+            for path in [&good, &bad, &broken] {
+                process_path(path, &opts, &summary, Some(&index));
+            }
+            index.save().unwrap();
+            // The outcomes are the same whether the logs are checked or not.
+            assert_eq!(summary.logs.load(Ordering::Relaxed), 3);
+            assert_eq!(summary.parse_errors.load(Ordering::Relaxed), 1);
+            assert_eq!(summary.failures(), 2);
+            assert_eq!(
+                summary.worst_logs(WORST_LOGS),
+                [(bad.clone(), 1), (broken.clone(), 1)],
+            );
+            (summary.events.load(Ordering::Relaxed), index.report())
+        };
+        let report = |skipped, parsed, same_hash| IndexReport {
+            skipped,
+            parsed,
+            same_hash,
+        };
+
+        assert_eq!(run(false), (8, report(0, 3, 0)));
+        assert_eq!(run(false), (8, report(3, 0, 0)));
+
+        // Only the changed log is checked again.
+        fs::write(
+            &good,
+            format!(
+                "{}\n{}",
+                fs::read_to_string(&good).unwrap(),
+                r#"{"type":"tsumo","actor":1,"pai":"E"}"#,
+            ),
+        )
+        .unwrap();
+        assert_eq!(run(false), (9, report(2, 1, 0)));
+
+        // Reformatted but the same events, so the outcome is reused.
+        let reformatted = fs::read_to_string(&bad).unwrap().replace(':', ": ");
+        fs::write(&bad, reformatted).unwrap();
+        assert_eq!(run(false), (9, report(2, 1, 1)));
+        assert_eq!(run(false), (9, report(3, 0, 0)));
+
+        assert_eq!(run(true), (9, report(0, 3, 0)));
+        // Other checks make another context.
+        let opts = Options {
+            checks: vec![0],
+            ..Default::default()
+        };
+        let index = LogIndex::open(&index_path, &index_context(&opts), false).unwrap();
+        process_path(&good, &opts, &Summary::new(), Some(&index));
+        assert_eq!(index.report(), report(0, 1, 0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_logs() {
+        let dir = env::temp_dir().join(format!("validate_logs_compressed_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let log = format!(
+            "{}\n{}",
+            START
+                .trim()
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#,
+        );
+        let raw = log.as_bytes();
+
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(raw).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(vec![], 6);
+        xz.write_all(raw).unwrap();
+        let files = [
+            ("a.json", raw.to_vec()),
+            ("sub/b.json.gz", gz.finish().unwrap()),
+            ("sub/c.json.zst", zstd::encode_all(raw, 0).unwrap()),
+            ("d.json.xz", xz.finish().unwrap()),
+            ("e.txt", raw.to_vec()),
+        ];
+        for (name, data) in &files {
+            fs::write(dir.join(name), data).unwrap();
+        }
+
+        let mut paths = find_logs(dir.to_str().unwrap())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        paths.sort();
+        let mut expected: Vec<_> = files[..4].iter().map(|(name, _)| dir.join(name)).collect();
+        expected.sort();
+        assert_eq!(paths, expected);
+
+        let opts = Options::default();
+        let summary = Summary::new();
+        for path in &paths {
+            process_path(path, &opts, &summary, None);
+        }
+        assert_eq!(summary.logs.load(Ordering::Relaxed), 4);
+        assert_eq!(summary.events.load(Ordering::Relaxed), 4 * 4);
+        assert_eq!(summary.failures(), 0);
+
+        // A broken archive is an error naming the log.
+        let broken = dir.join("broken.json.zst");
+        fs::write(&broken, b"not zstd").unwrap();
+        let err = check_log(&broken, &opts, &summary, None).unwrap_err();
+        assert!(
+            format!("{err:#}").contains(&broken.display().to_string()),
+            "{err:#}",
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_names() {
+        assert_eq!(parse_check_names("pon, hora").unwrap(), [3, 8]);
+        assert!(parse_check_names("pon,nope").is_err());
+
+        let mut names: Vec<_> = CHECKS.iter().map(|c| c.name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), CHECKS.len());
+    }
 }
@@ -1,10 +1,30 @@
 use crate::py_helper::add_submodule;
+use crate::state::{
+    obs_plane_names, oracle_obs_plane_names, sanma_obs_plane_names, score_pressure_plane_names,
+    seen_delta_plane_names, turn_order_plane_names,
+};
 
 use pyo3::prelude::*;
 use static_assertions::const_assert;
 
-pub const OBS_SHAPE: (usize, usize) = (938, 34);
+/// The number of planes of `PlayerState::encode_obs`.
+pub const OBS_CHANNELS: usize = 938;
+pub const OBS_SHAPE: (usize, usize) = (OBS_CHANNELS, 34);
+/// The number of planes of `PlayerState::encode_obs` in sanma, which appends
+/// the nukidora planes to those of yonma.
+pub const SANMA_OBS_CHANNELS: usize = OBS_CHANNELS + 4 * 4 + 1;
+pub const SANMA_OBS_SHAPE: (usize, usize) = (SANMA_OBS_CHANNELS, 34);
+/// The number of planes of `PlayerState::encode_seen_delta`.
+pub const SEEN_DELTA_CHANNELS: usize = 4 + 4;
+pub const SEEN_DELTA_SHAPE: (usize, usize) = (SEEN_DELTA_CHANNELS, 34);
+/// The number of planes of `PlayerState::encode_turn_order`.
+pub const TURN_ORDER_CHANNELS: usize = 3;
+pub const TURN_ORDER_SHAPE: (usize, usize) = (TURN_ORDER_CHANNELS, 34);
+/// The number of planes of `PlayerState::encode_score_pressure`.
+pub const SCORE_PRESSURE_CHANNELS: usize = 2;
+pub const SCORE_PRESSURE_SHAPE: (usize, usize) = (SCORE_PRESSURE_CHANNELS, 34);
 pub const ORACLE_OBS_SHAPE: (usize, usize) = (211, 34);
+/// The actions of `PlayerState::legal_action_mask`.
 pub const ACTION_SPACE: usize = 37 // discard | kan (choice)
                               + 1  // riichi
                               + 3  // chi
@@ -14,15 +34,34 @@ pub const ACTION_SPACE: usize = 37 // discard | kan (choice)
                               + 1  // ryukyoku
                               + 1; // pass
                                    // = 46
+/// The actions of `PlayerState::legal_action_mask` in sanma, which appends
+/// nukidora to those of yonma.
+pub const SANMA_ACTION_SPACE: usize = ACTION_SPACE + 1;
 pub const GRP_SIZE: usize = 7;
 
-const_assert!(ACTION_SPACE <= u64::BITS as usize);
+const_assert!(SANMA_ACTION_SPACE <= u64::BITS as usize);
 
 pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule) -> PyResult<()> {
     let m = PyModule::new(py, "consts")?;
+    m.add("OBS_CHANNELS", OBS_CHANNELS)?;
     m.add("OBS_SHAPE", OBS_SHAPE)?;
+    m.add("OBS_PLANE_NAMES", obs_plane_names())?;
+    m.add("SANMA_OBS_CHANNELS", SANMA_OBS_CHANNELS)?;
+    m.add("SANMA_OBS_SHAPE", SANMA_OBS_SHAPE)?;
+    m.add("SANMA_OBS_PLANE_NAMES", sanma_obs_plane_names())?;
+    m.add("SEEN_DELTA_CHANNELS", SEEN_DELTA_CHANNELS)?;
+    m.add("SEEN_DELTA_SHAPE", SEEN_DELTA_SHAPE)?;
+    m.add("SEEN_DELTA_PLANE_NAMES", seen_delta_plane_names())?;
+    m.add("TURN_ORDER_CHANNELS", TURN_ORDER_CHANNELS)?;
+    m.add("TURN_ORDER_SHAPE", TURN_ORDER_SHAPE)?;
+    m.add("TURN_ORDER_PLANE_NAMES", turn_order_plane_names())?;
+    m.add("SCORE_PRESSURE_CHANNELS", SCORE_PRESSURE_CHANNELS)?;
+    m.add("SCORE_PRESSURE_SHAPE", SCORE_PRESSURE_SHAPE)?;
+    m.add("SCORE_PRESSURE_PLANE_NAMES", score_pressure_plane_names())?;
     m.add("ORACLE_OBS_SHAPE", ORACLE_OBS_SHAPE)?;
+    m.add("ORACLE_OBS_PLANE_NAMES", oracle_obs_plane_names())?;
     m.add("ACTION_SPACE", ACTION_SPACE)?;
+    m.add("SANMA_ACTION_SPACE", SANMA_ACTION_SPACE)?;
     m.add("GRP_SIZE", GRP_SIZE)?;
     add_submodule(py, prefix, super_mod, m)
 }
@@ -0,0 +1,690 @@
+//! Mahjong Soul (majsoul) logs, as the records of a game decoded from
+//! protobuf into JSON:
+//!
+//! ```text
+//! {"head": {"accounts": [{"seat": 0, "nickname": "A"}, ...]},
+//!  "records": [{"name": ".lq.RecordNewRound", "data": {...}}, ...]}
+//! ```
+//!
+//! Fields with default values may be omitted, as protobuf does. Tiles are
+//! written like `1m`, with `0m` for the aka and `1z`-`7z` for ESWNPFC. The
+//! oya is dealt 14 tiles, the last of which is taken as its first tsumo.
+//!
+//! Riichi is accepted at the next draw or call, and the doras revealed by a
+//! kan are taken from the `doras` of the records, which list every dora
+//! indicator so far.
+
+use super::parse_tile;
+use crate::mjai::{Event, RyukyokuReason};
+use crate::t;
+use crate::tile::Tile;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{self as json, Value};
+
+#[derive(Deserialize)]
+struct RawLog {
+    #[serde(default)]
+    head: Head,
+    records: Vec<Record>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Head {
+    accounts: Vec<Account>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Account {
+    seat: u8,
+    nickname: String,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    name: String,
+    #[serde(default)]
+    data: Value,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NewRound {
+    chang: u8,
+    ju: u8,
+    ben: u8,
+    liqibang: u8,
+    scores: [i32; 4],
+    tiles0: Vec<String>,
+    tiles1: Vec<String>,
+    tiles2: Vec<String>,
+    tiles3: Vec<String>,
+    doras: Vec<String>,
+    /// Used by older logs instead of `doras`.
+    dora: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct DealTile {
+    seat: u8,
+    tile: String,
+    doras: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct DiscardTile {
+    seat: u8,
+    tile: String,
+    is_liqi: bool,
+    is_wliqi: bool,
+    moqie: bool,
+    doras: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ChiPengGang {
+    seat: u8,
+    /// 0 for chi, 1 for pon and 2 for daiminkan.
+    #[serde(rename = "type")]
+    kind: u8,
+    tiles: Vec<String>,
+    /// The seat each of `tiles` comes from.
+    froms: Vec<u8>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct AnGangAddGang {
+    seat: u8,
+    /// 2 for kakan and 3 for ankan.
+    #[serde(rename = "type")]
+    kind: u8,
+    tiles: String,
+    doras: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Hule {
+    hules: Vec<HuleInfo>,
+    delta_scores: [i32; 4],
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct HuleInfo {
+    seat: u8,
+    zimo: bool,
+    li_doras: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NoTile {
+    liujumanguan: bool,
+    players: Vec<NoTilePlayer>,
+    scores: Vec<NoTileScore>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NoTilePlayer {
+    tingpai: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NoTileScore {
+    delta_scores: [i32; 4],
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct LiuJu {
+    #[serde(rename = "type")]
+    kind: u8,
+}
+
+/// The state of the kyoku being converted.
+#[derive(Default)]
+struct KyokuConverter {
+    events: Vec<Event>,
+    doras_revealed: usize,
+    riichi_to_be_accepted: Option<u8>,
+    /// The tiles of every pon, for a later kakan of it.
+    pons: [Vec<[Tile; 3]>; 4],
+    /// Of the latest dahai or kakan, which may be robbed.
+    last_discarder: Option<u8>,
+}
+
+/// Converts a majsoul log into mjai events, from `start_game` to `end_game`.
+///
+/// Sanma logs are not supported.
+pub fn majsoul_to_mjai(raw: &str) -> Result<Vec<Event>> {
+    let log: RawLog = json::from_str(raw).context("failed to parse majsoul log")?;
+
+    let mut names: [String; 4] = Default::default();
+    for account in log.head.accounts {
+        let name = names
+            .get_mut(account.seat as usize)
+            .with_context(|| format!("invalid seat {}", account.seat))?;
+        *name = account.nickname;
+    }
+
+    let mut events = vec![Event::StartGame { names, seed: None }];
+    let mut conv = None;
+    for (idx, record) in log.records.iter().enumerate() {
+        let name = record.name.trim_start_matches(".lq.");
+        if name == "RecordNewRound" {
+            conv = Some(KyokuConverter::default());
+        }
+        let kyoku = conv
+            .as_mut()
+            .with_context(|| format!("{name} before any RecordNewRound"))?;
+        let ended = kyoku
+            .convert(name, &record.data)
+            .with_context(|| format!("in record #{idx} {name}"))?;
+        if ended {
+            let mut kyoku = conv.take().unwrap_or_default();
+            kyoku.events.push(Event::EndKyoku);
+            events.append(&mut kyoku.events);
+        }
+    }
+    ensure!(conv.is_none(), "the last kyoku does not end");
+    events.push(Event::EndGame);
+
+    Ok(events)
+}
+
+impl KyokuConverter {
+    /// Returns `true` if the record ends the kyoku.
+    fn convert(&mut self, name: &str, data: &Value) -> Result<bool> {
+        match name {
+            "RecordNewRound" => self.new_round(parse(data)?)?,
+            "RecordDealTile" => {
+                let deal: DealTile = parse(data)?;
+                let actor = seat_of(deal.seat)?;
+                self.accept_riichi();
+                self.add_new_doras(&deal.doras)?;
+                self.events.push(Event::Tsumo {
+                    actor,
+                    pai: parse_tile(&deal.tile)?,
+                });
+            }
+            "RecordDiscardTile" => {
+                let discard: DiscardTile = parse(data)?;
+                let actor = seat_of(discard.seat)?;
+                if discard.is_liqi || discard.is_wliqi {
+                    self.events.push(Event::Reach { actor });
+                    self.riichi_to_be_accepted = Some(actor);
+                }
+                self.add_new_doras(&discard.doras)?;
+                self.events.push(Event::Dahai {
+                    actor,
+                    pai: parse_tile(&discard.tile)?,
+                    tsumogiri: discard.moqie,
+                });
+                self.last_discarder = Some(actor);
+            }
+            "RecordChiPengGang" => self.chi_peng_gang(parse(data)?)?,
+            "RecordAnGangAddGang" => self.an_gang_add_gang(parse(data)?)?,
+            "RecordHule" => {
+                self.hule(parse(data)?)?;
+                return Ok(true);
+            }
+            "RecordNoTile" => {
+                let NoTile {
+                    liujumanguan,
+                    players,
+                    scores,
+                } = parse(data)?;
+                let reason = if liujumanguan {
+                    RyukyokuReason::NagashiMangan
+                } else {
+                    RyukyokuReason::Exhaustive
+                };
+                let tenpais = match players.as_slice() {
+                    [] => None,
+                    [a, b, c, d] => Some([a, b, c, d].map(|p| p.tingpai)),
+                    _ => bail!("expected 4 players, got {}", players.len()),
+                };
+                let deltas = scores.first().map_or([0; 4], |s| s.delta_scores);
+                self.events.push(Event::Ryukyoku {
+                    reason: Some(reason),
+                    deltas: Some(deltas),
+                    tenpais,
+                });
+                return Ok(true);
+            }
+            "RecordLiuJu" => {
+                let LiuJu { kind } = parse(data)?;
+                let reason = match kind {
+                    1 => RyukyokuReason::Kyuushukyuuhai,
+                    2 => RyukyokuReason::SuufonRenda,
+                    3 => RyukyokuReason::Suukaikan,
+                    4 => RyukyokuReason::SuuchaRiichi,
+                    5 => RyukyokuReason::Sanchahou,
+                    _ => RyukyokuReason::Other(format!("liuju type {kind}")),
+                };
+                if reason == RyukyokuReason::SuuchaRiichi {
+                    self.accept_riichi();
+                }
+                self.events.push(Event::Ryukyoku {
+                    reason: Some(reason),
+                    deltas: Some([0; 4]),
+                    tenpais: None,
+                });
+                return Ok(true);
+            }
+            "RecordBaBei" => bail!("nukidora is not supported"),
+            _ => bail!("unknown record"),
+        }
+        Ok(false)
+    }
+
+    fn new_round(&mut self, round: NewRound) -> Result<()> {
+        let bakaze = *[t!(E), t!(S), t!(W), t!(N)]
+            .get(round.chang as usize)
+            .with_context(|| format!("invalid chang {}", round.chang))?;
+        let oya = seat_of(round.ju)?;
+
+        let doras = match round.dora {
+            Some(dora) if round.doras.is_empty() => vec![dora],
+            _ => round.doras,
+        };
+        let dora_marker = parse_tile(doras.first().context("no dora indicator")?)?;
+        self.doras_revealed = 1;
+
+        let mut tehais = [[t!(?); 13]; 4];
+        let mut first_tsumo = None;
+        for (seat, tiles) in [round.tiles0, round.tiles1, round.tiles2, round.tiles3]
+            .iter()
+            .enumerate()
+        {
+            let mut tiles = tiles
+                .iter()
+                .map(|s| parse_tile(s))
+                .collect::<Result<Vec<_>>>()?;
+            if seat == oya as usize {
+                ensure!(tiles.len() == 14, "the oya is not dealt 14 tiles");
+                first_tsumo = tiles.pop();
+            }
+            tehais[seat] = tiles
+                .try_into()
+                .ok()
+                .with_context(|| format!("tiles of seat {seat} are not 13 tiles"))?;
+        }
+
+        self.events.push(Event::StartKyoku {
+            bakaze,
+            dora_marker,
+            kyoku: oya + 1,
+            honba: round.ben,
+            kyotaku: round.liqibang,
+            oya,
+            scores: round.scores,
+            tehais,
+        });
+        if let Some(pai) = first_tsumo {
+            self.events.push(Event::Tsumo { actor: oya, pai });
+        }
+        Ok(())
+    }
+
+    fn chi_peng_gang(&mut self, call: ChiPengGang) -> Result<()> {
+        let actor = seat_of(call.seat)?;
+        ensure!(
+            call.tiles.len() == call.froms.len(),
+            "{} tiles but {} froms",
+            call.tiles.len(),
+            call.froms.len(),
+        );
+        let mut pai = None;
+        let mut consumed = vec![];
+        for (s, &from) in call.tiles.iter().zip(&call.froms) {
+            let t = parse_tile(s)?;
+            if from == call.seat {
+                consumed.push(t);
+            } else {
+                ensure!(pai.is_none(), "more than one called tile");
+                pai = Some((t, seat_of(from)?));
+            }
+        }
+        let (pai, target) = pai.context("no called tile")?;
+
+        self.accept_riichi();
+        let consumed_err = || format!("unexpected tiles {:?}", call.tiles);
+        let event = match call.kind {
+            0 => Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed: consumed.try_into().ok().with_context(consumed_err)?,
+            },
+            1 => {
+                let consumed: [Tile; 2] = consumed.try_into().ok().with_context(consumed_err)?;
+                self.pons[actor as usize].push([pai, consumed[0], consumed[1]]);
+                Event::Pon {
+                    actor,
+                    target,
+                    pai,
+                    consumed,
+                }
+            }
+            2 => Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed: consumed.try_into().ok().with_context(consumed_err)?,
+            },
+            kind => bail!("unknown call type {kind}"),
+        };
+        self.events.push(event);
+        Ok(())
+    }
+
+    fn an_gang_add_gang(&mut self, kan: AnGangAddGang) -> Result<()> {
+        let actor = seat_of(kan.seat)?;
+        let pai = parse_tile(&kan.tiles)?;
+        match kan.kind {
+            2 => {
+                let pons = &mut self.pons[actor as usize];
+                let idx = pons
+                    .iter()
+                    .position(|pon| pon[0].deaka() == pai.deaka())
+                    .with_context(|| format!("kakan of {pai} without a pon"))?;
+                let consumed = pons.swap_remove(idx);
+                self.events.push(Event::Kakan {
+                    actor,
+                    pai,
+                    consumed,
+                });
+                self.last_discarder = Some(actor);
+                // The dora of a kakan is revealed after the discard from the
+                // rinshan in mjai, which will be found in the `doras` of that
+                // discard.
+                Ok(())
+            }
+            3 => {
+                // Every aka rule of majsoul has one aka of each suit.
+                let mut consumed = [pai.deaka(); 4];
+                consumed[0] = pai.akaize();
+                self.events.push(Event::Ankan { actor, consumed });
+                self.add_new_doras(&kan.doras)
+            }
+            kind => bail!("unknown kan type {kind}"),
+        }
+    }
+
+    fn hule(&mut self, hule: Hule) -> Result<()> {
+        ensure!(!hule.hules.is_empty(), "no hule");
+        let is_multiple = hule.hules.len() > 1;
+        for info in &hule.hules {
+            let actor = seat_of(info.seat)?;
+            let target = if info.zimo {
+                actor
+            } else {
+                self.last_discarder.context("ron without a discard")?
+            };
+            // The deltas of a multiple ron are only recorded in total, so
+            // each winner is written as paid by the discarder alone.
+            let deltas = if is_multiple {
+                let mut deltas = [0; 4];
+                deltas[actor as usize] = hule.delta_scores[actor as usize];
+                deltas[target as usize] = -hule.delta_scores[actor as usize];
+                deltas
+            } else {
+                hule.delta_scores
+            };
+            let ura_markers = info
+                .li_doras
+                .iter()
+                .map(|s| parse_tile(s))
+                .collect::<Result<_>>()?;
+            self.events.push(Event::Hora {
+                actor,
+                target,
+                deltas: Some(deltas),
+                ura_markers: Some(ura_markers),
+            });
+        }
+        Ok(())
+    }
+
+    fn accept_riichi(&mut self) {
+        if let Some(actor) = self.riichi_to_be_accepted.take() {
+            self.events.push(Event::ReachAccepted { actor });
+        }
+    }
+
+    /// `doras` lists every dora indicator revealed so far, or is empty if
+    /// nothing changes.
+    fn add_new_doras(&mut self, doras: &[String]) -> Result<()> {
+        for dora in doras.iter().skip(self.doras_revealed) {
+            self.events.push(Event::Dora {
+                dora_marker: parse_tile(dora)?,
+            });
+        }
+        self.doras_revealed = self.doras_revealed.max(doras.len());
+        Ok(())
+    }
+}
+
+fn parse<T: DeserializeOwned>(data: &Value) -> Result<T> {
+    Ok(T::deserialize(data)?)
+}
+
+fn seat_of(seat: u8) -> Result<u8> {
+    ensure!(seat < 4, "invalid seat {seat}");
+    Ok(seat)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::convert::test::validate;
+
+    /// E1 with a pon, a chi, a riichi and a ron; E1-1 with an ankan and a
+    /// rinshan tsumo agari; E2 with a kyuushukyuuhai.
+    const LOG: &str = r#"{
+        "head": {"accounts": [
+            {"seat": 0, "nickname": "A"},
+            {"seat": 1, "nickname": "B"},
+            {"seat": 2, "nickname": "C"},
+            {"seat": 3, "nickname": "D"}
+        ]},
+        "records": [
+            {"name": ".lq.RecordNewRound", "data": {
+                "scores": [25000, 25000, 25000, 25000],
+                "tiles0": ["1m", "2m", "3m", "4p", "0p", "6p", "7s", "8s", "9s", "1z", "1z", "2z", "3z", "4z"],
+                "tiles1": ["2m", "3m", "4m", "6p", "7p", "8p", "2s", "3s", "4s", "5s", "6s", "3z", "3z"],
+                "tiles2": ["1p", "1p", "1p", "3m", "4m", "5s", "5s", "9m", "9m", "7z", "7z", "6z", "5z"],
+                "tiles3": ["4m", "5m", "6m", "1s", "2s", "3s", "7p", "8p", "5z", "5z", "6z", "6z", "9p"],
+                "doras": ["9s"]
+            }},
+            {"name": ".lq.RecordDiscardTile", "data": {"tile": "4z", "moqie": true}},
+            {"name": ".lq.RecordDealTile", "data": {"seat": 1, "tile": "1s"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 1, "tile": "1s", "moqie": true}},
+            {"name": ".lq.RecordDealTile", "data": {"seat": 2, "tile": "6m"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 2, "tile": "5z"}},
+            {"name": ".lq.RecordChiPengGang", "data": {"seat": 3, "type": 1, "tiles": ["5z", "5z", "5z"], "froms": [3, 3, 2]}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 3, "tile": "9p"}},
+            {"name": ".lq.RecordDealTile", "data": {"tile": "5m"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"tile": "5m", "moqie": true}},
+            {"name": ".lq.RecordChiPengGang", "data": {"seat": 1, "tiles": ["3m", "4m", "5m"], "froms": [1, 1, 0]}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 1, "tile": "8p"}},
+            {"name": ".lq.RecordDealTile", "data": {"seat": 2, "tile": "7m"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 2, "tile": "7m", "moqie": true}},
+            {"name": ".lq.RecordDealTile", "data": {"seat": 3, "tile": "4z"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 3, "tile": "4z", "moqie": true}},
+            {"name": ".lq.RecordDealTile", "data": {"tile": "1z"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"tile": "3z", "is_liqi": true}},
+            {"name": ".lq.RecordDealTile", "data": {"seat": 1, "tile": "2z"}},
+            {"name": ".lq.RecordDiscardTile", "data": {"seat": 1, "tile": "2z", "moqie": true}},
+            {"name": ".lq.RecordHule", "data": {
+                "hules": [{"li_doras": ["1p"]}],
+                "delta_scores": [12600, -11600, 0, 0]
+            }},
+            {"name": "RecordNewRound", "data": {
+                "ben": 1,
+                "scores": [36600, 13400, 25000, 25000],
+                "tiles0": ["1m", "1m", "2p", "5p", "8p", "3s", "6s", "9s", "1z", "2z", "3z", "4z", "5z", "6z"],
+                "tiles1": ["2m", "5m", "8m", "1p", "4p", "7p", "2s", "5s", "8s", "7z", "7z", "7z", "6z"],
+                "tiles2": ["9m", "9m", "9m", "9m", "1p", "2p", "3p", "4s", "5s", "6s", "7s", "8s", "1z"],
+                "tiles3": ["3m", "4m", "6m", "7m", "3p", "4p", "6p", "7p", "3s", "4s", "6s", "7s", "2z"],
+                "doras": ["2z"]
+            }},
+            {"name": "RecordDiscardTile", "data": {"tile": "6z", "moqie": true}},
+            {"name": "RecordDealTile", "data": {"seat": 1, "tile": "9p"}},
+            {"name": "RecordDiscardTile", "data": {"seat": 1, "tile": "9p", "moqie": true}},
+            {"name": "RecordDealTile", "data": {"seat": 2, "tile": "9s"}},
+            {"name": "RecordAnGangAddGang", "data": {"seat": 2, "type": 3, "tiles": "9m", "doras": ["2z", "4z"]}},
+            {"name": "RecordDealTile", "data": {"seat": 2, "tile": "1z"}},
+            {"name": "RecordHule", "data": {
+                "hules": [{"seat": 2, "zimo": true}],
+                "delta_scores": [-4100, -2100, 8300, -2100]
+            }},
+            {"name": "RecordNewRound", "data": {
+                "ju": 1,
+                "scores": [32500, 11300, 33300, 22900],
+                "tiles0": ["2p", "3p", "4p", "5p", "6p", "7p", "8p", "2s", "3s", "4s", "5s", "6s", "7s"],
+                "tiles1": ["1m", "9m", "1p", "9p", "1s", "9s", "1z", "2z", "3z", "4z", "2m", "3m", "4m", "5z"],
+                "tiles2": ["2p", "3p", "4p", "5p", "6p", "7p", "8p", "2s", "3s", "4s", "5s", "6s", "7s"],
+                "tiles3": ["2m", "3m", "4m", "5m", "6m", "7m", "8m", "4m", "6m", "4s", "4s", "8s", "8s"],
+                "doras": ["3p"]
+            }},
+            {"name": "RecordLiuJu", "data": {"type": 1}}
+        ]
+    }"#;
+
+    #[test]
+    fn convert() {
+        let events = majsoul_to_mjai(LOG).unwrap();
+        validate(&events);
+
+        assert_eq!(
+            events[0],
+            Event::StartGame {
+                names: ["A", "B", "C", "D"].map(str::to_owned),
+                seed: None,
+            },
+        );
+        let kyokus: Vec<_> = events[1..]
+            .split(|ev| matches!(ev, Event::EndKyoku))
+            .map(|evs| evs.to_vec())
+            .collect();
+        assert_eq!(kyokus.len(), 4);
+
+        // The oya's 14th tile is its first tsumo.
+        let e1 = &kyokus[0];
+        assert!(matches!(
+            e1[0],
+            Event::StartKyoku {
+                bakaze: t!(E),
+                dora_marker: t!(9s),
+                kyoku: 1,
+                oya: 0,
+                ..
+            },
+        ));
+        assert_eq!(
+            e1[1],
+            Event::Tsumo {
+                actor: 0,
+                pai: t!(N),
+            },
+        );
+        let pos = |target: &Event| e1.iter().position(|ev| ev == target).unwrap();
+        pos(&Event::Pon {
+            actor: 3,
+            target: 2,
+            pai: t!(P),
+            consumed: [t!(P); 2],
+        });
+        pos(&Event::Chi {
+            actor: 1,
+            target: 0,
+            pai: t!(5m),
+            consumed: [t!(3m), t!(4m)],
+        });
+
+        // The riichi is accepted at the next tsumo.
+        let reach = pos(&Event::Reach { actor: 0 });
+        assert_eq!(
+            e1[reach + 1..reach + 4],
+            [
+                Event::Dahai {
+                    actor: 0,
+                    pai: t!(W),
+                    tsumogiri: false,
+                },
+                Event::ReachAccepted { actor: 0 },
+                Event::Tsumo {
+                    actor: 1,
+                    pai: t!(S),
+                },
+            ],
+        );
+        assert_eq!(
+            e1.last().unwrap(),
+            &Event::Hora {
+                actor: 0,
+                target: 1,
+                deltas: Some([12600, -11600, 0, 0]),
+                ura_markers: Some(vec![t!(1p)]),
+            },
+        );
+
+        // The dora of the ankan comes right after it.
+        let e2 = &kyokus[1];
+        let ankan = e2
+            .iter()
+            .position(|ev| {
+                *ev == Event::Ankan {
+                    actor: 2,
+                    consumed: [t!(9m); 4],
+                }
+            })
+            .unwrap();
+        assert_eq!(
+            e2[ankan + 1..],
+            [
+                Event::Dora { dora_marker: t!(N) },
+                Event::Tsumo {
+                    actor: 2,
+                    pai: t!(E),
+                },
+                Event::Hora {
+                    actor: 2,
+                    target: 2,
+                    deltas: Some([-4100, -2100, 8300, -2100]),
+                    ura_markers: Some(vec![]),
+                },
+            ],
+        );
+
+        let e3 = &kyokus[2];
+        assert_eq!(
+            e3[1..],
+            [
+                Event::Tsumo {
+                    actor: 1,
+                    pai: t!(P),
+                },
+                Event::Ryukyoku {
+                    reason: Some(RyukyokuReason::Kyuushukyuuhai),
+                    deltas: Some([0; 4]),
+                    tenpais: None,
+                },
+            ],
+        );
+        assert_eq!(kyokus[3], [Event::EndGame]);
+    }
+}
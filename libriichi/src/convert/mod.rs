@@ -0,0 +1,53 @@
+//! Conversions from other log formats into mjai events.
+
+pub mod majsoul;
+pub mod paifu;
+pub mod tenhou;
+
+#[cfg(test)]
+mod test;
+
+use crate::hand::tiles_to_string;
+use crate::tile::Tile;
+use crate::{t, tu8, tuz};
+
+use anyhow::{bail, Result};
+
+/// Parses a single tile written like `1m`, with `0m` for the aka and `1z`-`7z`
+/// for ESWNPFC, or `?` for an unknown one.
+fn parse_tile(s: &str) -> Result<Tile> {
+    let id = match *s.as_bytes() {
+        [b'?'] => return Ok(t!(?)),
+        [b'0', b'm'] => tu8!(5mr),
+        [b'0', b'p'] => tu8!(5pr),
+        [b'0', b's'] => tu8!(5sr),
+        [n @ b'1'..=b'9', b'm'] => n - b'1' + tu8!(1m),
+        [n @ b'1'..=b'9', b'p'] => n - b'1' + tu8!(1p),
+        [n @ b'1'..=b'9', b's'] => n - b'1' + tu8!(1s),
+        [n @ b'1'..=b'7', b'z'] => n - b'1' + tu8!(E),
+        _ => bail!("invalid tile {s:?}"),
+    };
+    Ok(Tile::try_from(id)?)
+}
+
+/// The inverse of `parse_tile`.
+fn tile_str(tile: Tile) -> String {
+    if tile == t!(?) {
+        "?".to_owned()
+    } else {
+        tiles_str(&[tile])
+    }
+}
+
+/// Writes known tiles in the notation of [`crate::hand`], like `123m 0p`.
+fn tiles_str(tiles: &[Tile]) -> String {
+    let mut counts = [0; 34];
+    let mut akas = [false; 3];
+    for &tile in tiles {
+        counts[tile.deaka().as_usize()] += 1;
+        if tile.is_aka() {
+            akas[tile.as_usize() - tuz!(5mr)] = true;
+        }
+    }
+    tiles_to_string(&counts, akas)
+}
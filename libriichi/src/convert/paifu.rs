@@ -0,0 +1,536 @@
+//! The paifu CSV format, a hand-written record of a single kyoku from the
+//! view of one player:
+//!
+//! ```text
+//! bakaze,kyoku,honba,kyotaku,player,dora,scores,haipai
+//! 1z,1,0,0,0,9s,25000 25000 25000 25000,123456789m 2345p
+//! seat,draw,call,discard,tsumogiri,riichi,hand
+//! 0,1z,,1z,1,1,123456789m 2345p
+//! 1,?,,9p,,,
+//! 2,,chi 78p,1s,,,
+//! ...
+//! 0,,ron,,,,
+//! ```
+//!
+//! Tiles are written in the notation of [`crate::hand`], like `1m`, with `0m`
+//! for the aka and `1z`-`7z` for ESWNPFC. The first two rows hold the kyoku
+//! and the 13 tiles dealt to `player`; the oya is deduced from `kyoku`. Each
+//! following row is a turn of `seat`, which draws a tile or calls the last
+//! discard, then discards, possibly declaring riichi. `hand` is the concealed
+//! hand of `player` after its discard, and is checked on import.
+//!
+//! `call` is one of `chi`, `pon` followed by the two tiles taken from the hand,
+//! `tsumo`, `ron` or `ryukyoku`, the last three ending the kyoku without a
+//! discard. Several `ron` rows make a multiple ron. The tiles that `player`
+//! cannot know, namely the draws and the hands of the others, are written `?`.
+//! Kans and nukidora are not supported. Lines starting with `#` and blank
+//! lines are ignored.
+
+use super::{parse_tile, tile_str, tiles_str};
+use crate::hand::{hand, hand_with_aka, tile37_to_vec, tiles_to_string};
+use crate::mjai::Event;
+use crate::state::PlayerState;
+use crate::tile::Tile;
+use crate::{t, tuz};
+use std::fmt::Write;
+
+use anyhow::{bail, ensure, Context, Result};
+
+const KYOKU_HEADER: &str = "bakaze,kyoku,honba,kyotaku,player,dora,scores,haipai";
+const TURN_HEADER: &str = "seat,draw,call,discard,tsumogiri,riichi,hand";
+
+/// A row after the headers, with every column as written.
+#[derive(Default)]
+struct Turn {
+    seat: String,
+    draw: String,
+    call: String,
+    discard: String,
+    tsumogiri: bool,
+    riichi: bool,
+    hand: String,
+}
+
+impl Turn {
+    fn parse(line: &str) -> Result<Self> {
+        let [seat, draw, call, discard, tsumogiri, riichi, hand] = columns(line)?;
+        Ok(Self {
+            seat: seat.to_owned(),
+            draw: draw.to_owned(),
+            call: call.to_owned(),
+            discard: discard.to_owned(),
+            tsumogiri: flag(tsumogiri)?,
+            riichi: flag(riichi)?,
+            hand: hand.to_owned(),
+        })
+    }
+
+    fn write_to(&self, out: &mut String) {
+        let flag = |b| if b { "1" } else { "" };
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            self.seat,
+            self.draw,
+            self.call,
+            self.discard,
+            flag(self.tsumogiri),
+            flag(self.riichi),
+            self.hand,
+        )
+        .unwrap();
+    }
+}
+
+/// Converts a paifu CSV into the mjai events of its kyoku, from `start_kyoku`
+/// to `end_kyoku`.
+///
+/// The events are replayed with `PlayerState::update_checked` from the view
+/// of `player`, so an impossible record or a `hand` which does not match the
+/// replay is an error.
+pub fn paifu_to_mjai(raw: &str) -> Result<Vec<Event>> {
+    let mut lines = raw
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let mut next_line = |expected| lines.next().with_context(|| format!("missing {expected}"));
+
+    let (_, header) = next_line("kyoku header")?;
+    ensure!(header == KYOKU_HEADER, "invalid kyoku header {header:?}");
+    let (line_no, kyoku_row) = next_line("kyoku row")?;
+    let (player, start) = parse_kyoku(kyoku_row).with_context(|| format!("in line {line_no}"))?;
+    let (_, header) = next_line("turn header")?;
+    ensure!(header == TURN_HEADER, "invalid turn header {header:?}");
+
+    let turns = lines
+        .map(|(line_no, line)| {
+            Turn::parse(line)
+                .map(|turn| (line_no, turn))
+                .with_context(|| format!("in line {line_no}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut conv = Importer {
+        state: PlayerState::new(player),
+        events: vec![],
+        last_discard: None,
+        ended: false,
+    };
+    conv.push(start)?;
+    for (idx, (line_no, turn)) in turns.iter().enumerate() {
+        let next_is_ron = turns.get(idx + 1).map_or(false, |(_, t)| t.call == "ron");
+        conv.turn(turn, next_is_ron)
+            .with_context(|| format!("in line {line_no}"))?;
+    }
+    ensure!(conv.ended, "the kyoku does not end");
+    conv.events.push(Event::EndKyoku);
+
+    Ok(conv.events)
+}
+
+fn parse_kyoku(line: &str) -> Result<(u8, Event)> {
+    let [bakaze, kyoku, honba, kyotaku, player, dora, scores, haipai] = columns(line)?;
+
+    let kyoku: u8 = kyoku.parse().context("invalid kyoku")?;
+    ensure!(kyoku >= 1, "kyoku counts from 1");
+    let player: u8 = player.parse().context("invalid player")?;
+    ensure!(player < 4, "invalid player {player}");
+    let scores: Vec<i32> = scores
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .context("invalid scores")?;
+
+    let mut tehais = [[t!(?); 13]; 4];
+    tehais[player as usize] = tile37_to_vec(&hand_with_aka(haipai)?)
+        .try_into()
+        .ok()
+        .context("haipai is not 13 tiles")?;
+
+    let start = Event::StartKyoku {
+        bakaze: parse_tile(bakaze)?,
+        dora_marker: parse_tile(dora)?,
+        kyoku,
+        honba: honba.parse().context("invalid honba")?,
+        kyotaku: kyotaku.parse().context("invalid kyotaku")?,
+        oya: (kyoku - 1) % 4,
+        scores: scores.try_into().ok().context("expected 4 scores")?,
+        tehais,
+    };
+    Ok((player, start))
+}
+
+struct Importer {
+    state: PlayerState,
+    events: Vec<Event>,
+    /// The seat and tile of the last discard, which can be called.
+    last_discard: Option<(u8, Tile)>,
+    ended: bool,
+}
+
+impl Importer {
+    fn push(&mut self, event: Event) -> Result<()> {
+        self.state
+            .update_checked(&event)
+            .with_context(|| format!("invalid event {event:?}"))?;
+        self.events.push(event);
+        Ok(())
+    }
+
+    fn turn(&mut self, turn: &Turn, next_is_ron: bool) -> Result<()> {
+        if self.ended {
+            let after_ron = matches!(
+                self.events.last(),
+                Some(Event::Hora { actor, target, .. }) if actor != target,
+            );
+            ensure!(
+                after_ron && turn.call == "ron",
+                "turn after the end of the kyoku",
+            );
+        }
+
+        if turn.call == "ryukyoku" {
+            ensure!(turn.seat.is_empty(), "ryukyoku with a seat");
+            self.push(Event::Ryukyoku {
+                reason: None,
+                deltas: None,
+                tenpais: None,
+            })?;
+            self.ended = true;
+            return Ok(());
+        }
+
+        let actor: u8 = turn.seat.parse().context("invalid seat")?;
+        ensure!(actor < 4, "invalid seat {actor}");
+        let (call, consumed) = turn.call.split_once(' ').unwrap_or((&turn.call, ""));
+        match call {
+            "" | "tsumo" => {
+                let pai = parse_tile(&turn.draw)?;
+                self.push(Event::Tsumo { actor, pai })?;
+                self.last_discard = None;
+                if call == "tsumo" {
+                    self.push(Event::Hora {
+                        actor,
+                        target: actor,
+                        deltas: None,
+                        ura_markers: None,
+                    })?;
+                }
+            }
+            "chi" | "pon" | "ron" => {
+                ensure!(turn.draw.is_empty(), "{call} with a draw");
+                let (target, pai) = self.last_discard.context("no discard to call")?;
+                let event = match call {
+                    "ron" => Event::Hora {
+                        actor,
+                        target,
+                        deltas: None,
+                        ura_markers: None,
+                    },
+                    _ => {
+                        let consumed = tile37_to_vec(&hand_with_aka(consumed)?)
+                            .try_into()
+                            .ok()
+                            .with_context(|| format!("{call} does not take 2 tiles"))?;
+                        if call == "chi" {
+                            Event::Chi {
+                                actor,
+                                target,
+                                pai,
+                                consumed,
+                            }
+                        } else {
+                            Event::Pon {
+                                actor,
+                                target,
+                                pai,
+                                consumed,
+                            }
+                        }
+                    }
+                };
+                self.push(event)?;
+            }
+            _ => bail!("invalid call {:?}", turn.call),
+        }
+
+        if matches!(call, "tsumo" | "ron") {
+            ensure!(turn.discard.is_empty(), "{call} with a discard");
+            self.ended = true;
+            return Ok(());
+        }
+
+        if turn.riichi {
+            self.push(Event::Reach { actor })?;
+        }
+        let pai = parse_tile(&turn.discard)?;
+        self.push(Event::Dahai {
+            actor,
+            pai,
+            tsumogiri: turn.tsumogiri,
+        })?;
+        self.last_discard = Some((actor, pai));
+        if turn.riichi && !next_is_ron {
+            self.push(Event::ReachAccepted { actor })?;
+        }
+
+        if actor == self.state.player_id() && !turn.hand.is_empty() {
+            let expected = hand(&turn.hand)?;
+            let akas = hand_with_aka(&turn.hand)?;
+            let expected_akas = [tuz!(5mr), tuz!(5pr), tuz!(5sr)].map(|i| akas[i] > 0);
+            ensure!(
+                expected == self.state.tehai() && expected_akas == self.state.akas_in_hand(),
+                "hand {:?} does not match the replayed {:?}",
+                turn.hand,
+                concealed(&self.state),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Converts the events of a kyoku, from `start_kyoku` to `end_kyoku`, into a
+/// paifu CSV from the view of `player_id`.
+///
+/// Kans and nukidora are not supported.
+pub fn mjai_to_paifu(events: &[Event], player_id: u8) -> Result<String> {
+    let mut state = PlayerState::new(player_id);
+    let mut out = String::new();
+    let mut turn: Option<Turn> = None;
+    let mut ended = false;
+
+    for (idx, event) in events.iter().enumerate() {
+        ensure!(
+            !ended || matches!(event, Event::Hora { .. } | Event::EndKyoku | Event::EndGame),
+            "event #{idx} {event:?} after the end of the kyoku",
+        );
+        state
+            .update_checked(event)
+            .with_context(|| format!("invalid event #{idx} {event:?}"))?;
+
+        match *event {
+            Event::StartGame { .. } | Event::EndGame => (),
+            Event::StartKyoku {
+                bakaze,
+                dora_marker,
+                kyoku,
+                honba,
+                kyotaku,
+                scores,
+                tehais,
+                ..
+            } => {
+                ensure!(out.is_empty(), "more than one kyoku");
+                let scores = scores.map(|s| s.to_string()).join(" ");
+                writeln!(out, "{KYOKU_HEADER}").unwrap();
+                writeln!(
+                    out,
+                    "{},{kyoku},{honba},{kyotaku},{player_id},{},{scores},{}",
+                    tile_str(bakaze),
+                    tile_str(dora_marker),
+                    tiles_str(&tehais[player_id as usize]),
+                )
+                .unwrap();
+                writeln!(out, "{TURN_HEADER}").unwrap();
+            }
+            Event::Tsumo { actor, pai } => {
+                turn = Some(Turn {
+                    seat: actor.to_string(),
+                    draw: if actor == player_id {
+                        tile_str(pai)
+                    } else {
+                        "?".to_owned()
+                    },
+                    ..Default::default()
+                });
+            }
+            Event::Chi {
+                actor, consumed, ..
+            }
+            | Event::Pon {
+                actor, consumed, ..
+            } => {
+                let call = if matches!(event, Event::Chi { .. }) {
+                    "chi"
+                } else {
+                    "pon"
+                };
+                turn = Some(Turn {
+                    seat: actor.to_string(),
+                    call: format!("{call} {}", tiles_str(&consumed)),
+                    ..Default::default()
+                });
+            }
+            Event::Reach { .. } => {
+                turn.as_mut().context("riichi out of turn")?.riichi = true;
+            }
+            Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            } => {
+                let mut t = turn.take().context("discard out of turn")?;
+                t.discard = tile_str(pai);
+                t.tsumogiri = tsumogiri;
+                if actor == player_id {
+                    t.hand = concealed(&state);
+                }
+                t.write_to(&mut out);
+            }
+            Event::ReachAccepted { .. } | Event::EndKyoku => (),
+            Event::Hora { actor, target, .. } => {
+                let t = if actor == target {
+                    let mut t = turn.take().context("tsumo out of turn")?;
+                    t.call = "tsumo".to_owned();
+                    t
+                } else {
+                    Turn {
+                        seat: actor.to_string(),
+                        call: "ron".to_owned(),
+                        ..Default::default()
+                    }
+                };
+                t.write_to(&mut out);
+                ended = true;
+            }
+            Event::Ryukyoku { .. } => {
+                Turn {
+                    call: "ryukyoku".to_owned(),
+                    ..Default::default()
+                }
+                .write_to(&mut out);
+                ended = true;
+            }
+            _ => bail!("unsupported event #{idx} {event:?}"),
+        }
+    }
+    ensure!(ended, "the kyoku does not end");
+
+    Ok(out)
+}
+
+fn columns<const N: usize>(line: &str) -> Result<[&str; N]> {
+    let cols: Vec<_> = line.split(',').map(str::trim).collect();
+    let len = cols.len();
+    cols.try_into()
+        .ok()
+        .with_context(|| format!("expected {N} columns, got {len}"))
+}
+
+fn flag(s: &str) -> Result<bool> {
+    match s {
+        "" | "0" => Ok(false),
+        "1" => Ok(true),
+        _ => bail!("invalid flag {s:?}"),
+    }
+}
+
+fn concealed(state: &PlayerState) -> String {
+    tiles_to_string(&state.tehai(), state.akas_in_hand())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// E1 from the view of the oya, with a riichi, a chi, a pon and a ron.
+    const RIICHI_RON: &str = include_str!("data/paifu_riichi_ron.csv");
+    /// E2 1-honba from the view of the oya, with an aka discarded and a tsumo
+    /// agari.
+    const MENZEN_TSUMO: &str = include_str!("data/paifu_menzen_tsumo.csv");
+
+    /// Replays the events with the checks of `PlayerState::update_checked`
+    /// and `validate_reaction` from the view of `player_id`.
+    fn validate(events: &[Event], player_id: u8) {
+        let mut state = PlayerState::new(player_id);
+        for (idx, ev) in events.iter().enumerate() {
+            let is_action = !matches!(ev, Event::Tsumo { .. } | Event::ReachAccepted { .. });
+            if ev.actor() == Some(player_id) && is_action {
+                if let Err(err) = state.validate_reaction(ev) {
+                    panic!("{err} at #{idx} {ev:?}\nstate:\n{}", state.brief_info());
+                }
+            }
+            if let Err(err) = state.update_checked(ev) {
+                panic!("{err:?} at #{idx} {ev:?}\nstate:\n{}", state.brief_info());
+            }
+        }
+    }
+
+    fn records(raw: &str) -> Vec<&str> {
+        raw.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for (raw, player_id) in [(RIICHI_RON, 0), (MENZEN_TSUMO, 1)] {
+            let events = paifu_to_mjai(raw).unwrap();
+            validate(&events, player_id);
+
+            let exported = mjai_to_paifu(&events, player_id).unwrap();
+            assert_eq!(records(&exported), records(raw));
+            assert_eq!(paifu_to_mjai(&exported).unwrap(), events);
+        }
+    }
+
+    #[test]
+    fn import() {
+        let events = paifu_to_mjai(RIICHI_RON).unwrap();
+        match &events[0] {
+            Event::StartKyoku { oya, tehais, .. } => {
+                assert_eq!(*oya, 0);
+                assert!(tehais[1..].iter().flatten().all(|&t| t == t!(?)));
+            }
+            ev => panic!("expected start_kyoku, got {ev:?}"),
+        }
+
+        // The riichi is accepted before the next draw, and the ron ends the
+        // kyoku.
+        assert_eq!(events[2], Event::Reach { actor: 0 });
+        assert_eq!(events[4], Event::ReachAccepted { actor: 0 });
+        assert_eq!(
+            events[5],
+            Event::Tsumo {
+                actor: 1,
+                pai: t!(?)
+            }
+        );
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                Event::Hora {
+                    actor: 0,
+                    target: 1,
+                    deltas: None,
+                    ura_markers: None,
+                },
+                Event::EndKyoku,
+            ],
+        );
+
+        let events = paifu_to_mjai(MENZEN_TSUMO).unwrap();
+        assert_eq!(
+            events[1],
+            Event::Tsumo {
+                actor: 1,
+                pai: t!(5mr)
+            }
+        );
+    }
+
+    #[test]
+    fn reject_mismatched_hand() {
+        let raw = RIICHI_RON.replace(",1,,123456789m 2345p", ",1,,123456789m 2346p");
+        paifu_to_mjai(&raw).unwrap_err();
+
+        // A discard the player does not hold breaks the replay.
+        let raw = MENZEN_TSUMO.replace("1,0m,,0m,", "1,0m,,9m,");
+        paifu_to_mjai(&raw).unwrap_err();
+
+        let raw = RIICHI_RON.replace("0,,ron,,,,", "");
+        paifu_to_mjai(&raw).unwrap_err();
+    }
+}
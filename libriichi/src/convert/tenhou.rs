@@ -0,0 +1,685 @@
+//! Tenhou's JSON logs, the format used by tenhou.net/6.
+//!
+//! A log holds one array per kyoku:
+//!
+//! ```text
+//! [[kyoku, honba, kyotaku], scores, dora_indicators, ura_indicators,
+//!  haipai_0, draws_0, discards_0, ..., haipai_3, draws_3, discards_3,
+//!  result]
+//! ```
+//!
+//! Tiles are numbered 11-19 for man, 21-29 for pin, 31-39 for sou, 41-47 for
+//! ESWNPFC and 51-53 for the akas. Draws and discards are kept per player
+//! without any ordering between players, so the order of the events has to be
+//! rebuilt by replaying the turns.
+
+use crate::mjai::{Event, RyukyokuReason};
+use crate::tile::Tile;
+use crate::{t, tu8};
+use std::collections::VecDeque;
+use std::mem;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+use serde_json::{self as json, Value};
+
+/// A discard of the tile just drawn.
+const TSUMOGIRI: u8 = 60;
+/// The discard slot taken by a daiminkan, where the rinshan is drawn instead.
+const DAIMINKAN_SLOT: u8 = 0;
+
+#[derive(Deserialize)]
+struct RawLog {
+    #[serde(default)]
+    name: [String; 4],
+    log: Vec<Vec<Value>>,
+}
+
+/// An entry of the draws or discards of a player.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Action {
+    Tile(u8),
+    /// A call, a riichi declaration (`r`) or a nukidora (`f`).
+    Call(String),
+}
+
+/// A call written like `c275226`, `45p4545` or `393939a39`.
+struct Meld {
+    kind: u8,
+    /// Number of tiles before the letter. The tile right after the letter is
+    /// the called one, or the added one for kakan, and for chi, pon and
+    /// daiminkan the position tells where it comes from.
+    pos: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Meld {
+    fn parse(s: &str) -> Result<Self> {
+        let mut kind = None;
+        let mut tiles = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            let c = rest.as_bytes()[0];
+            if c.is_ascii_alphabetic() {
+                ensure!(kind.is_none(), "more than one letter in call {s}");
+                kind = Some((c, tiles.len()));
+                rest = &rest[1..];
+            } else {
+                let n = rest
+                    .get(..2)
+                    .and_then(|n| n.parse().ok())
+                    .with_context(|| format!("invalid call {s}"))?;
+                tiles.push(tile(n)?);
+                rest = &rest[2..];
+            }
+        }
+        let (kind, pos) = kind.with_context(|| format!("no letter in call {s}"))?;
+        ensure!(pos < tiles.len(), "no tile after the letter in call {s}");
+
+        Ok(Self { kind, pos, tiles })
+    }
+
+    const fn target(&self, actor: u8) -> u8 {
+        let offset = match self.pos {
+            0 => 3,
+            1 => 2,
+            _ => 1,
+        };
+        (actor + offset) % 4
+    }
+
+    fn pai(&self) -> Tile {
+        self.tiles[self.pos]
+    }
+
+    fn consumed<const N: usize>(&self) -> Result<[Tile; N]> {
+        let consumed: Vec<_> = self
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != self.pos)
+            .map(|(_, &t)| t)
+            .collect();
+        consumed
+            .try_into()
+            .ok()
+            .with_context(|| format!("expected {N} consumed tiles, got {:?}", self.tiles))
+    }
+}
+
+/// Replays the draws and discards of a kyoku.
+#[derive(Default)]
+struct KyokuConverter {
+    events: Vec<Event>,
+    draws: [VecDeque<Action>; 4],
+    discards: [VecDeque<Action>; 4],
+    dora_indicators: VecDeque<Tile>,
+    last_tsumo: Option<Tile>,
+    riichi_to_be_accepted: Option<u8>,
+    riichi_accepted: [bool; 4],
+    need_new_dora_at_discard: bool,
+    need_new_dora_at_tsumo: bool,
+}
+
+/// Converts a Tenhou log into mjai events, from `start_game` to `end_game`.
+///
+/// Sanma logs are not supported.
+pub fn tenhou_to_mjai(raw: &str) -> Result<Vec<Event>> {
+    let log: RawLog = json::from_str(raw).context("failed to parse tenhou log")?;
+
+    let mut events = vec![Event::StartGame {
+        names: log.name,
+        seed: None,
+    }];
+    for (idx, kyoku) in log.log.iter().enumerate() {
+        let kyoku_events = convert_kyoku(kyoku).with_context(|| format!("in kyoku #{idx}"))?;
+        events.extend(kyoku_events);
+    }
+    events.push(Event::EndGame);
+
+    Ok(events)
+}
+
+fn convert_kyoku(raw: &[Value]) -> Result<Vec<Event>> {
+    ensure!(raw.len() == 17, "expected 17 entries, got {}", raw.len());
+
+    let [round, honba, kyotaku] = <[u8; 3]>::deserialize(&raw[0])?;
+    let scores = <[i32; 4]>::deserialize(&raw[1])?;
+    let dora_indicators = Vec::<u8>::deserialize(&raw[2])?
+        .into_iter()
+        .map(tile)
+        .collect::<Result<VecDeque<_>>>()?;
+    let ura_indicators = Vec::<u8>::deserialize(&raw[3])?
+        .into_iter()
+        .map(tile)
+        .collect::<Result<Vec<_>>>()?;
+    let result = raw[16].as_array().context("invalid kyoku result")?;
+
+    let bakaze = *[t!(E), t!(S), t!(W), t!(N)]
+        .get(round as usize / 4)
+        .with_context(|| format!("invalid kyoku {round}"))?;
+    let oya = round % 4;
+
+    let mut conv = KyokuConverter {
+        dora_indicators,
+        ..Default::default()
+    };
+    let dora_marker = conv
+        .dora_indicators
+        .pop_front()
+        .context("no dora indicator")?;
+
+    let mut tehais = [[t!(?); 13]; 4];
+    for (i, tehai) in tehais.iter_mut().enumerate() {
+        let haipai = Vec::<u8>::deserialize(&raw[4 + i * 3])?
+            .into_iter()
+            .map(tile)
+            .collect::<Result<Vec<_>>>()?;
+        *tehai = haipai
+            .try_into()
+            .ok()
+            .with_context(|| format!("haipai of player {i} is not 13 tiles"))?;
+        conv.draws[i] = Vec::<Action>::deserialize(&raw[5 + i * 3])?.into();
+        conv.discards[i] = Vec::<Action>::deserialize(&raw[6 + i * 3])?.into();
+    }
+
+    conv.events.push(Event::StartKyoku {
+        bakaze,
+        dora_marker,
+        kyoku: oya + 1,
+        honba,
+        kyotaku,
+        oya,
+        scores,
+        tehais,
+    });
+    conv.play(oya)?;
+    conv.end(result, &ura_indicators)?;
+    conv.events.push(Event::EndKyoku);
+
+    Ok(conv.events)
+}
+
+impl KyokuConverter {
+    fn play(&mut self, oya: u8) -> Result<()> {
+        let mut actor = oya;
+        let mut has_called = false;
+        loop {
+            if !has_called && !self.draw(actor)? {
+                return Ok(());
+            }
+
+            let discarded = loop {
+                let action = match self.discards[actor as usize].pop_front() {
+                    Some(action) => action,
+                    None => return Ok(()),
+                };
+                match action {
+                    Action::Tile(DAIMINKAN_SLOT) => {
+                        if !self.draw(actor)? {
+                            return Ok(());
+                        }
+                    }
+                    Action::Tile(n) => break self.dahai(actor, n)?,
+                    Action::Call(s) => {
+                        if let Some(n) = s.strip_prefix('r') {
+                            let n = n.parse().with_context(|| format!("invalid riichi {s}"))?;
+                            self.events.push(Event::Reach { actor });
+                            let pai = self.dahai(actor, n)?;
+                            self.riichi_to_be_accepted = Some(actor);
+                            break pai;
+                        }
+
+                        let meld = Meld::parse(&s)?;
+                        match meld.kind {
+                            b'a' => {
+                                // For continuous kan
+                                if mem::take(&mut self.need_new_dora_at_discard) {
+                                    self.add_new_dora()?;
+                                }
+                                let consumed = meld
+                                    .tiles
+                                    .try_into()
+                                    .ok()
+                                    .with_context(|| format!("invalid ankan {s}"))?;
+                                self.events.push(Event::Ankan { actor, consumed });
+                                self.add_new_dora()?;
+                            }
+                            b'k' => {
+                                self.events.push(Event::Kakan {
+                                    actor,
+                                    pai: meld.pai(),
+                                    consumed: meld.consumed()?,
+                                });
+                                self.delay_new_dora();
+                            }
+                            b'f' => bail!("nukidora is not supported"),
+                            _ => bail!("unexpected {s} in discards of player {actor}"),
+                        }
+
+                        // It is robbed by a chankan if there is no rinshan.
+                        if !self.draw(actor)? {
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            match self.find_caller(actor, discarded)? {
+                Some((caller, meld)) => {
+                    self.draws[caller as usize].pop_front();
+                    self.accept_riichi();
+
+                    let target = actor;
+                    let pai = meld.pai();
+                    let call = match meld.kind {
+                        b'c' => Event::Chi {
+                            actor: caller,
+                            target,
+                            pai,
+                            consumed: meld.consumed()?,
+                        },
+                        b'p' => Event::Pon {
+                            actor: caller,
+                            target,
+                            pai,
+                            consumed: meld.consumed()?,
+                        },
+                        _ => Event::Daiminkan {
+                            actor: caller,
+                            target,
+                            pai,
+                            consumed: meld.consumed()?,
+                        },
+                    };
+                    self.events.push(call);
+                    if meld.kind == b'm' {
+                        self.delay_new_dora();
+                    }
+
+                    actor = caller;
+                    has_called = true;
+                    self.last_tsumo = None;
+                }
+                None => {
+                    actor = (actor + 1) % 4;
+                    has_called = false;
+                }
+            }
+        }
+    }
+
+    /// Returns `false` if the player has nothing more to draw.
+    fn draw(&mut self, actor: u8) -> Result<bool> {
+        let pai = match self.draws[actor as usize].pop_front() {
+            Some(Action::Tile(n)) => tile(n)?,
+            Some(Action::Call(s)) => bail!("unexpected call {s} of player {actor}"),
+            None => return Ok(false),
+        };
+
+        self.accept_riichi();
+        // This is for kakan only because chankan is possible until an actual
+        // tsumo.
+        if mem::take(&mut self.need_new_dora_at_tsumo) {
+            self.add_new_dora()?;
+        }
+        self.events.push(Event::Tsumo { actor, pai });
+        self.last_tsumo = Some(pai);
+
+        Ok(true)
+    }
+
+    fn dahai(&mut self, actor: u8, n: u8) -> Result<Tile> {
+        let (pai, tsumogiri) = if n == TSUMOGIRI {
+            let pai = self
+                .last_tsumo
+                .with_context(|| format!("tsumogiri of player {actor} without a tsumo"))?;
+            (pai, true)
+        } else {
+            (tile(n)?, false)
+        };
+
+        if mem::take(&mut self.need_new_dora_at_discard) {
+            self.add_new_dora()?;
+        }
+        self.events.push(Event::Dahai {
+            actor,
+            pai,
+            tsumogiri,
+        });
+
+        Ok(pai)
+    }
+
+    /// Finds who calls the tile just discarded by `discarder` from the calls
+    /// waiting at the head of their draws, where a pon or daiminkan goes
+    /// before a chi.
+    fn find_caller(&self, discarder: u8, pai: Tile) -> Result<Option<(u8, Meld)>> {
+        let mut chi = None;
+        for seat in (1..4).map(|i| (discarder + i) % 4) {
+            let s = match self.draws[seat as usize].front() {
+                Some(Action::Call(s)) => s,
+                _ => continue,
+            };
+            let meld = Meld::parse(s)?;
+            ensure!(
+                matches!(meld.kind, b'c' | b'p' | b'm'),
+                "unexpected {s} in draws of player {seat}",
+            );
+            if meld.target(seat) != discarder || meld.pai() != pai {
+                continue;
+            }
+            if meld.kind == b'c' {
+                chi = Some((seat, meld));
+            } else {
+                return Ok(Some((seat, meld)));
+            }
+        }
+        Ok(chi)
+    }
+
+    fn accept_riichi(&mut self) {
+        if let Some(actor) = self.riichi_to_be_accepted.take() {
+            self.events.push(Event::ReachAccepted { actor });
+            self.riichi_accepted[actor as usize] = true;
+        }
+    }
+
+    fn delay_new_dora(&mut self) {
+        // For kakan only, the dora of the previous kan is revealed before the
+        // rinshan tsumo.
+        if self.need_new_dora_at_discard {
+            self.need_new_dora_at_tsumo = true;
+        }
+        self.need_new_dora_at_discard = true;
+    }
+
+    fn add_new_dora(&mut self) -> Result<()> {
+        let dora_marker = self
+            .dora_indicators
+            .pop_front()
+            .context("not enough dora indicators")?;
+        self.events.push(Event::Dora { dora_marker });
+        Ok(())
+    }
+
+    fn end(&mut self, result: &[Value], ura_indicators: &[Tile]) -> Result<()> {
+        let kind = result
+            .first()
+            .and_then(Value::as_str)
+            .context("missing kyoku result")?;
+
+        if kind == "和了" {
+            ensure!(result.len() % 2 == 1, "invalid hora result");
+            for pair in result[1..].chunks(2) {
+                let deltas = <[i32; 4]>::deserialize(&pair[0])?;
+                let info = pair[1].as_array().context("invalid hora info")?;
+                let (actor, target) = match info.as_slice() {
+                    [actor, target, ..] => (u8::deserialize(actor)?, u8::deserialize(target)?),
+                    _ => bail!("invalid hora info"),
+                };
+                ensure!(actor < 4 && target < 4, "invalid hora info");
+
+                let ura_markers = if self.riichi_accepted[actor as usize] {
+                    ura_indicators.to_vec()
+                } else {
+                    vec![]
+                };
+                self.events.push(Event::Hora {
+                    actor,
+                    target,
+                    deltas: Some(deltas),
+                    ura_markers: Some(ura_markers),
+                });
+            }
+            return Ok(());
+        }
+
+        let deltas = match result.get(1) {
+            Some(v) => <[i32; 4]>::deserialize(v)?,
+            None => [0; 4],
+        };
+        let (reason, tenpais) = match kind {
+            "流局" => (RyukyokuReason::Exhaustive, Some(deltas.map(|d| d > 0))),
+            "全員聴牌" => (RyukyokuReason::Exhaustive, Some([true; 4])),
+            "全員不聴" => (RyukyokuReason::Exhaustive, Some([false; 4])),
+            "流し満貫" => (RyukyokuReason::NagashiMangan, None),
+            "九種九牌" => (RyukyokuReason::Kyuushukyuuhai, None),
+            "四風連打" => (RyukyokuReason::SuufonRenda, None),
+            "四槓散了" => (RyukyokuReason::Suukaikan, None),
+            "四家立直" => (RyukyokuReason::SuuchaRiichi, None),
+            "三家和了" => (RyukyokuReason::Sanchahou, None),
+            _ => (RyukyokuReason::Other(kind.to_owned()), None),
+        };
+        if reason == RyukyokuReason::SuuchaRiichi {
+            self.accept_riichi();
+        }
+        self.events.push(Event::Ryukyoku {
+            reason: Some(reason),
+            deltas: Some(deltas),
+            tenpais,
+        });
+
+        Ok(())
+    }
+}
+
+fn tile(n: u8) -> Result<Tile> {
+    let id = match n {
+        11..=19 => n - 11 + tu8!(1m),
+        21..=29 => n - 21 + tu8!(1p),
+        31..=39 => n - 31 + tu8!(1s),
+        41..=47 => n - 41 + tu8!(E),
+        51..=53 => n - 51 + tu8!(5mr),
+        _ => bail!("invalid tile {n}"),
+    };
+    Ok(Tile::try_from(id)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::convert::test::validate;
+
+    /// E1 with an ankan, a pon then kakan of it, and a tsumo agari; E2 with a
+    /// riichi and a double ron; E3 with a kyuushukyuuhai; E4 with a chi, and
+    /// a daiminkan whose rinshan discard is ron.
+    const LOG: &str = include_str!("data/tenhou_sample.json");
+
+    #[test]
+    fn convert() {
+        let events = tenhou_to_mjai(LOG).unwrap();
+        validate(&events);
+
+        let kyokus: Vec<_> = events
+            .split(|ev| matches!(ev, Event::EndKyoku))
+            .map(|evs| evs.to_vec())
+            .collect();
+        assert_eq!(kyokus.len(), 5);
+
+        // The dora of the ankan comes right after it, and the one of the
+        // kakan right before the discard after the rinshan.
+        let e1 = &kyokus[0];
+        let pos = |target: &Event| e1.iter().position(|ev| ev == target).unwrap();
+        let ankan = pos(&Event::Ankan {
+            actor: 0,
+            consumed: [t!(1m); 4],
+        });
+        assert_eq!(e1[ankan + 1], Event::Dora { dora_marker: t!(F) });
+        let pon = pos(&Event::Pon {
+            actor: 1,
+            target: 0,
+            pai: t!(9p),
+            consumed: [t!(9p); 2],
+        });
+        let kakan = pos(&Event::Kakan {
+            actor: 1,
+            pai: t!(9p),
+            consumed: [t!(9p); 3],
+        });
+        assert!(pon < kakan);
+        assert_eq!(
+            e1[kakan + 1..kakan + 4],
+            [
+                Event::Tsumo {
+                    actor: 1,
+                    pai: t!(7m),
+                },
+                Event::Dora {
+                    dora_marker: t!(3s),
+                },
+                Event::Dahai {
+                    actor: 1,
+                    pai: t!(N),
+                    tsumogiri: false,
+                },
+            ],
+        );
+
+        // The riichi is accepted at the next tsumo, and only its ron gets the
+        // ura markers.
+        let e2 = &kyokus[1];
+        let reach = e2
+            .iter()
+            .position(|ev| *ev == Event::Reach { actor: 2 })
+            .unwrap();
+        assert_eq!(
+            e2[reach + 1..reach + 4],
+            [
+                Event::Dahai {
+                    actor: 2,
+                    pai: t!(9m),
+                    tsumogiri: true,
+                },
+                Event::ReachAccepted { actor: 2 },
+                Event::Tsumo {
+                    actor: 3,
+                    pai: t!(E),
+                },
+            ],
+        );
+        let horas: Vec<_> = e2
+            .iter()
+            .filter(|ev| matches!(ev, Event::Hora { .. }))
+            .collect();
+        assert_eq!(
+            horas,
+            [
+                &Event::Hora {
+                    actor: 2,
+                    target: 1,
+                    deltas: Some([0, -7700, 8700, 0]),
+                    ura_markers: Some(vec![t!(C)]),
+                },
+                &Event::Hora {
+                    actor: 3,
+                    target: 1,
+                    deltas: Some([0, -2000, 0, 2000]),
+                    ura_markers: Some(vec![]),
+                },
+            ],
+        );
+
+        let e3 = &kyokus[2];
+        assert_eq!(
+            e3[e3.len() - 2..],
+            [
+                Event::Tsumo {
+                    actor: 2,
+                    pai: t!(8p),
+                },
+                Event::Ryukyoku {
+                    reason: Some(RyukyokuReason::Kyuushukyuuhai),
+                    deltas: Some([0; 4]),
+                    tenpais: None,
+                },
+            ],
+        );
+
+        // The daiminkan takes the discard slot of its actor, and its dora
+        // comes right before the discard after the rinshan.
+        let e4 = &kyokus[3];
+        assert_eq!(
+            e4[2..5],
+            [
+                Event::Dahai {
+                    actor: 3,
+                    pai: t!(3p),
+                    tsumogiri: false,
+                },
+                Event::Chi {
+                    actor: 0,
+                    target: 3,
+                    pai: t!(3p),
+                    consumed: [t!(2p), t!(4p)],
+                },
+                Event::Dahai {
+                    actor: 0,
+                    pai: t!(1m),
+                    tsumogiri: false,
+                },
+            ],
+        );
+        assert_eq!(
+            e4[7..],
+            [
+                Event::Daiminkan {
+                    actor: 3,
+                    target: 1,
+                    pai: t!(9s),
+                    consumed: [t!(9s); 3],
+                },
+                Event::Tsumo {
+                    actor: 3,
+                    pai: t!(C),
+                },
+                Event::Dora { dora_marker: t!(C) },
+                Event::Dahai {
+                    actor: 3,
+                    pai: t!(6m),
+                    tsumogiri: false,
+                },
+                Event::Hora {
+                    actor: 1,
+                    target: 3,
+                    deltas: Some([0, 2000, 0, -2000]),
+                    ura_markers: Some(vec![]),
+                },
+            ],
+        );
+        assert_eq!(kyokus[4], [Event::EndGame]);
+    }
+
+    #[test]
+    fn meld() {
+        let chi = Meld::parse("c275226").unwrap();
+        assert_eq!(chi.target(1), 0);
+        assert_eq!(chi.pai(), t!(7p));
+        assert_eq!(chi.consumed().unwrap(), [t!(5pr), t!(6p)]);
+
+        for (s, target) in [("p454545", 3), ("45p4545", 2), ("4545p45", 1)] {
+            let pon = Meld::parse(s).unwrap();
+            assert_eq!(pon.kind, b'p');
+            assert_eq!(pon.target(0), target);
+            assert_eq!(pon.pai(), t!(P));
+        }
+
+        for (s, target) in [("m39393939", 3), ("39m393939", 2), ("393939m39", 1)] {
+            let daiminkan = Meld::parse(s).unwrap();
+            assert_eq!(daiminkan.target(2), (2 + target) % 4);
+            assert_eq!(daiminkan.consumed().unwrap(), [t!(9s); 3]);
+        }
+
+        let kakan = Meld::parse("1515k5115").unwrap();
+        assert_eq!(kakan.pai(), t!(5mr));
+        assert_eq!(kakan.consumed().unwrap(), [t!(5m); 3]);
+
+        assert!(Meld::parse("p4545").is_ok());
+        assert!(Meld::parse("454545").is_err());
+        assert!(Meld::parse("p45p45").is_err());
+        assert!(Meld::parse("p4").is_err());
+        assert!(tile(10).is_err());
+        assert!(tile(54).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Helpers shared by the tests of the converters, and the tests of the shared
+//! tile notation.
+
+use super::{parse_tile, tile_str, tiles_str};
+use crate::mjai::Event;
+use crate::state::PlayerState;
+use crate::t;
+
+/// Feeds the events to four `PlayerState`s with the checks of
+/// `validate_logs`.
+pub(super) fn validate(events: &[Event]) {
+    let mut states = [0, 1, 2, 3].map(PlayerState::new);
+    for (idx, ev) in events.iter().enumerate() {
+        let is_action = !matches!(
+            ev,
+            Event::Tsumo { .. } | Event::ReachAccepted { .. } | Event::Ryukyoku { .. }
+        );
+        if let Some(actor) = ev.actor().filter(|_| is_action) {
+            let state = &states[actor as usize];
+            if let Err(err) = state.validate_reaction(ev) {
+                panic!("{err} at #{idx} {ev:?}\nstate:\n{}", state.brief_info());
+            }
+
+            if let Event::Hora {
+                actor,
+                target,
+                deltas: Some(deltas),
+                ura_markers: Some(ura),
+            } = ev
+            {
+                let is_ron = actor != target;
+                let point = state.agari_points(is_ron, ura).unwrap();
+                let expected = if is_ron {
+                    point.ron
+                } else if state.is_oya() {
+                    point.tsumo_oya
+                } else {
+                    point.tsumo_ko
+                };
+                assert!(deltas[*actor as usize] >= expected, "#{idx} {ev:?}");
+            }
+        }
+        for state in &mut states {
+            state.update_with_skip(ev, true);
+        }
+    }
+}
+
+#[test]
+fn tiles() {
+    assert_eq!(parse_tile("1m").unwrap(), t!(1m));
+    assert_eq!(parse_tile("0p").unwrap(), t!(5pr));
+    assert_eq!(parse_tile("9s").unwrap(), t!(9s));
+    assert_eq!(parse_tile("7z").unwrap(), t!(C));
+    assert_eq!(parse_tile("?").unwrap(), t!(?));
+    parse_tile("8z").unwrap_err();
+    parse_tile("0z").unwrap_err();
+    parse_tile("1").unwrap_err();
+    parse_tile("11m").unwrap_err();
+
+    for s in ["1m", "0p", "5s", "7z", "?"] {
+        assert_eq!(tile_str(parse_tile(s).unwrap()), s);
+    }
+    assert_eq!(
+        tiles_str(&[t!(3m), t!(1m), t!(5pr), t!(E), t!(E)]),
+        "13m 0p 11z"
+    );
+}
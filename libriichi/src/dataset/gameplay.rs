@@ -1,8 +1,9 @@
 use super::invisible::Invisible;
 use super::player_list::{TENHOUI, TOP300_2K_GAMES};
+use super::stamp::{canonical_log_hash, hash_to_hex};
 use super::Grp;
 use crate::chi_type::ChiType;
-use crate::mjai::Event;
+use crate::mjai::{Event, EventExt};
 use crate::state::PlayerState;
 use std::fs::File;
 use std::io::prelude::*;
@@ -53,6 +54,9 @@ pub struct Gameplay {
     pub apply_gamma: Vec<bool>,
     pub at_turns: Vec<u8>,
     pub shantens: Vec<i8>,
+    /// 1-based indices of the events the moves are made at, see
+    /// `SampleStamp::event`.
+    pub event_indices: Vec<u32>,
 
     // one per kyoku
     pub grp: Grp,
@@ -61,6 +65,8 @@ pub struct Gameplay {
     pub player_id: u8,
     pub player_name: String,
     pub quality: Quality,
+    /// `canonical_log_hash` of the log.
+    pub log_hash: [u8; 32],
 }
 
 #[pyclass]
@@ -79,6 +85,8 @@ struct LoaderContext<'a> {
 
     state: PlayerState,
     kyoku_idx: usize,
+    /// 1-based index of the current event.
+    event: u32,
     // fields below are only used for oracle
     opponent_states: [PlayerState; 3],
     from_rinshan: bool,
@@ -119,8 +127,15 @@ impl GameplayLoader {
     fn load_log(&self, raw_log: &str) -> Result<Vec<Gameplay>> {
         let events = raw_log
             .lines()
-            .map(json::from_str)
-            .collect::<Result<Vec<Event>, _>>()
+            .map(|line| {
+                let ev = json::from_str(line)?;
+                ensure!(
+                    !EventExt::is_stacked_draws_marker(line, &ev),
+                    "the log has stacked draws and is not organic",
+                );
+                Ok(ev)
+            })
+            .collect::<Result<Vec<Event>>>()
             .context("failed to parse log")?;
         self.load_events(&events)
     }
@@ -160,7 +175,11 @@ impl GameplayLoader {
     }
 
     pub fn load_events(&self, events: &[Event]) -> Result<Vec<Gameplay>> {
-        let invisibles = self.oracle.then(|| Invisible::new(events, self.trust_seed));
+        let invisibles = self
+            .oracle
+            .then(|| Invisible::new(events, self.trust_seed))
+            .transpose()?;
+        let log_hash = canonical_log_hash(events);
 
         let idxs: ArrayVec<[u8; 4]> = match &events[0] {
             Event::StartGame { names, .. } => names
@@ -180,6 +199,7 @@ impl GameplayLoader {
         idxs.into_par_iter()
             .map(|&player_id| {
                 Gameplay::load_events_by_player(self, events, player_id, invisibles.as_deref())
+                    .map(|data| Gameplay { log_hash, ..data })
             })
             .collect()
     }
@@ -232,6 +252,10 @@ impl Gameplay {
     fn take_shantens(&mut self) -> Vec<i8> {
         mem::take(&mut self.shantens)
     }
+    #[pyo3(text_signature = "($self, /)")]
+    fn take_event_indices(&mut self) -> Vec<u32> {
+        mem::take(&mut self.event_indices)
+    }
 
     #[pyo3(text_signature = "($self, /)")]
     fn take_grp(&mut self) -> Grp {
@@ -246,6 +270,11 @@ impl Gameplay {
     const fn take_quality(&self) -> Quality {
         self.quality
     }
+    /// Hex encoded.
+    #[pyo3(text_signature = "($self, /)")]
+    fn take_log_hash(&self) -> String {
+        hash_to_hex(&self.log_hash)
+    }
 }
 
 impl Gameplay {
@@ -285,6 +314,7 @@ impl Gameplay {
             invisibles,
             state: PlayerState::new(player_id),
             kyoku_idx: 0,
+            event: 0,
             opponent_states: [
                 PlayerState::new((player_id + 1) % 4),
                 PlayerState::new((player_id + 2) % 4),
@@ -298,6 +328,7 @@ impl Gameplay {
         // It is guaranteed that there are at least 4 events.
         // tsumo/dahai -> ryukyoku/hora -> end kyoku -> end game
         for wnd in events.windows(4) {
+            ctx.event += 1;
             data.extend_from_event_window(&mut ctx, wnd.try_into().unwrap());
         }
 
@@ -319,6 +350,7 @@ impl Gameplay {
             from_rinshan,
             yama_idx,
             rinshan_idx,
+            ..
         } = ctx;
 
         let cur = &wnd[0];
@@ -461,6 +493,7 @@ impl Gameplay {
         self.apply_gamma.push(label <= 37);
         self.at_turns.push(ctx.state.at_turn());
         self.shantens.push(ctx.state.shanten());
+        self.event_indices.push(ctx.event);
 
         if let Some(invisibles) = ctx.invisibles {
             let invisible_obs = invisibles[ctx.kyoku_idx].encode(
@@ -61,11 +61,13 @@ impl Grp {
 
 impl Grp {
     #[inline]
+    #[must_use]
     pub fn len(&self) -> usize {
         self.feature.len_of(Axis(0))
     }
 
     #[inline]
+    #[must_use]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -3,15 +3,16 @@ use crate::consts::ORACLE_OBS_SHAPE;
 use crate::mjai::Event;
 use crate::state::PlayerState;
 use crate::tile::Tile;
-use crate::{must_tile, tu8, tuz};
+use crate::{must_tile, t, tu8, tuz};
 use std::iter;
 use std::mem;
 
+use anyhow::{ensure, Context, Result};
 use ndarray::prelude::*;
 use rand::prelude::*;
 
 /// All fields are sorted early -> late.
-#[derive(Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Invisible {
     pub yama: Vec<Tile>,
     pub rinshan: Vec<Tile>,
@@ -20,7 +21,9 @@ pub struct Invisible {
 }
 
 impl Invisible {
-    pub fn new(game: &[Event], trust_seed: bool) -> Vec<Self> {
+    /// Unless the seed is trusted, `game` must be a perfect-information log
+    /// with every kyoku ended by `end_kyoku`, otherwise an error is returned.
+    pub fn new(game: &[Event], trust_seed: bool) -> Result<Vec<Self>> {
         let mut ret = vec![];
         let mut cur = Self::default();
         let mut seed = None;
@@ -68,11 +71,10 @@ impl Invisible {
                         continue;
                     }
                     cur.dora_indicators.push(*dora_marker);
-                    unknown_tiles[dora_marker.as_usize()] -= 1;
-                    tehais
-                        .iter()
-                        .flatten()
-                        .for_each(|tile| unknown_tiles[tile.as_usize()] -= 1);
+                    take_unknown(&mut unknown_tiles, *dora_marker)?;
+                    for &tile in tehais.iter().flatten() {
+                        take_unknown(&mut unknown_tiles, tile)?;
+                    }
                 }
                 _ => (),
             };
@@ -88,16 +90,16 @@ impl Invisible {
                         from_rinshan = false;
                     } else {
                         cur.yama.push(*pai);
-                        assert!(cur.yama.len() <= 70, "yama size overflow");
+                        ensure!(cur.yama.len() <= 70, "yama size overflow");
                     }
-                    unknown_tiles[pai.as_usize()] -= 1;
+                    take_unknown(&mut unknown_tiles, *pai)?;
                 }
                 Event::Ankan { .. } | Event::Kakan { .. } | Event::Daiminkan { .. } => {
                     from_rinshan = true;
                 }
                 Event::Dora { dora_marker } => {
                     cur.dora_indicators.push(*dora_marker);
-                    unknown_tiles[dora_marker.as_usize()] -= 1;
+                    take_unknown(&mut unknown_tiles, *dora_marker)?;
                 }
                 Event::Hora {
                     ura_markers: Some(ura),
@@ -105,7 +107,7 @@ impl Invisible {
                 } if !ura_is_recorded => {
                     for &tile in ura {
                         cur.ura_indicators.push(tile);
-                        unknown_tiles[tile.as_usize()] -= 1;
+                        take_unknown(&mut unknown_tiles, tile)?;
                     }
                     ura_is_recorded = true;
                 }
@@ -118,19 +120,18 @@ impl Invisible {
                         .collect();
                     filler.shuffle(&mut thread_rng());
 
-                    while cur.yama.len() < 70 {
-                        cur.yama.push(filler.pop().unwrap());
-                    }
-                    while cur.rinshan.len() < 4 {
-                        cur.rinshan.push(filler.pop().unwrap());
-                    }
-                    while cur.dora_indicators.len() < 5 {
-                        cur.dora_indicators.push(filler.pop().unwrap());
+                    for (tiles, len) in [
+                        (&mut cur.yama, 70),
+                        (&mut cur.rinshan, 4),
+                        (&mut cur.dora_indicators, 5),
+                        (&mut cur.ura_indicators, 5),
+                    ] {
+                        ensure!(tiles.len() <= len, "more than {len} tiles in {tiles:?}");
+                        while tiles.len() < len {
+                            tiles.push(filler.pop().context("ran out of unknown tiles")?);
+                        }
                     }
-                    while cur.ura_indicators.len() < 5 {
-                        cur.ura_indicators.push(filler.pop().unwrap());
-                    }
-                    assert!(filler.is_empty());
+                    ensure!(filler.is_empty(), "{} unknown tiles left", filler.len());
 
                     ret.push(mem::take(&mut cur));
                     from_rinshan = false;
@@ -142,11 +143,12 @@ impl Invisible {
             };
         }
 
-        ret
+        Ok(ret)
     }
 
     // TODO: merge this this arena::board::BoardState::encode_oracle_obs; they
     // should be identical.
+    #[must_use]
     pub fn encode(
         &self,
         opponent_states: &[PlayerState; 3],
@@ -178,7 +180,8 @@ impl Invisible {
                 });
             idx += 3;
 
-            let n = state.shanten() as usize;
+            // Only an opponent that has just drawn a winning tile can be at -1.
+            let n = state.shanten().max(0) as usize;
             arr.slice_mut(s![idx..idx + n, ..]).fill(1.);
             idx += 6;
 
@@ -233,6 +236,18 @@ impl Invisible {
     }
 }
 
+fn take_unknown(unknown_tiles: &mut [u8; 37], tile: Tile) -> Result<()> {
+    ensure!(
+        tile != t!(?),
+        "{tile} is masked, which is not from a perfect-information log",
+    );
+    let count = &mut unknown_tiles[tile.as_usize()];
+    *count = count
+        .checked_sub(1)
+        .with_context(|| format!("too many {tile} in the log"))?;
+    Ok(())
+}
+
 const fn new_unknown_tiles() -> [u8; 37] {
     let mut ret = [4; 37];
     ret[tuz!(5m)] = 3;
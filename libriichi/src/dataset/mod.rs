@@ -3,12 +3,21 @@
 mod gameplay;
 mod grp;
 mod invisible;
+mod obs_stats;
 mod player_list;
+mod stamp;
+mod writer;
 
 use crate::py_helper::add_submodule;
 pub use gameplay::{Gameplay, GameplayLoader, Quality};
 pub use grp::Grp;
 pub use invisible::Invisible;
+pub use obs_stats::ObsStats;
+pub use stamp::{canonical_log_hash, hash_from_hex, hash_to_hex, render_stamp, SampleStamp};
+pub use writer::{
+    read_stamps, verify_shards, DatasetWriter, ShardEntry, ShardIndex, SourceEntry, INDEX_FILENAME,
+    SHARD_FORMAT_VERSION,
+};
 
 use pyo3::prelude::*;
 
@@ -18,5 +27,6 @@ pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule
     m.add_class::<GameplayLoader>()?;
     m.add_class::<Quality>()?;
     m.add_class::<Grp>()?;
+    m.add_class::<ObsStats>()?;
     add_submodule(py, prefix, super_mod, m)
 }
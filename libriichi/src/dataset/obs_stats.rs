@@ -0,0 +1,196 @@
+//! Per-channel statistics of the encoded observations over a corpus, for
+//! feature normalization.
+
+use super::writer::{ShardIndex, INDEX_FILENAME};
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ndarray::prelude::*;
+use numpy::PyArray1;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+/// Streaming mean, variance, min and max of every channel (row) of the obs,
+/// using Welford's algorithm. Each of the 34 values in a channel of an obs
+/// counts as one sample.
+#[pyclass]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObsStats {
+    /// The number of values per channel.
+    #[pyo3(get)]
+    pub count: u64,
+    pub mean: Vec<f64>,
+    /// The sum of squared differences from the mean.
+    pub m2: Vec<f64>,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+#[pymethods]
+impl ObsStats {
+    /// Loads the stats referenced by the index of a dataset made by
+    /// `make_dataset --obs-stats`.
+    #[staticmethod]
+    #[pyo3(text_signature = "(dataset_dir, /)")]
+    pub fn load(dataset_dir: &str) -> Result<Self> {
+        let dir = Path::new(dataset_dir);
+        let index: ShardIndex = json::from_reader(File::open(dir.join(INDEX_FILENAME))?)
+            .context("failed to parse the index")?;
+        let filename = index
+            .obs_stats
+            .context("the dataset is made without obs stats")?;
+        let path = dir.join(filename);
+        json::from_reader(File::open(&path)?)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    #[getter]
+    #[pyo3(name = "mean")]
+    fn mean_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray1::from_slice(py, &self.mean)
+    }
+    #[getter]
+    #[pyo3(name = "std")]
+    fn std_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray1::from_vec(py, self.std())
+    }
+    #[getter]
+    #[pyo3(name = "min")]
+    fn min_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f32> {
+        PyArray1::from_slice(py, &self.min)
+    }
+    #[getter]
+    #[pyo3(name = "max")]
+    fn max_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f32> {
+        PyArray1::from_slice(py, &self.max)
+    }
+}
+
+impl ObsStats {
+    #[must_use]
+    pub fn new(channels: usize) -> Self {
+        Self {
+            count: 0,
+            mean: vec![0.; channels],
+            m2: vec![0.; channels],
+            // Not infinities, which cannot be written in JSON.
+            min: vec![f32::MAX; channels],
+            max: vec![f32::MIN; channels],
+        }
+    }
+
+    /// Panics if `obs` has a different number of channels.
+    pub fn add(&mut self, obs: ArrayView2<'_, f32>) {
+        assert_eq!(obs.nrows(), self.mean.len(), "unexpected obs shape");
+        for (c, row) in obs.outer_iter().enumerate() {
+            let mut n = self.count;
+            for &v in row {
+                n += 1;
+                let x = f64::from(v);
+                let delta = x - self.mean[c];
+                self.mean[c] += delta / n as f64;
+                self.m2[c] = delta.mul_add(x - self.mean[c], self.m2[c]);
+                self.min[c] = self.min[c].min(v);
+                self.max[c] = self.max[c].max(v);
+            }
+        }
+        self.count += obs.ncols() as u64;
+    }
+
+    /// Combines the stats of another set of obs into `self`, as if they were
+    /// added one by one.
+    ///
+    /// Panics if `other` has a different number of channels.
+    pub fn merge(&mut self, other: &Self) {
+        assert_eq!(other.mean.len(), self.mean.len(), "unexpected channels");
+        if other.count == 0 {
+            return;
+        }
+        let n_a = self.count as f64;
+        let n_b = other.count as f64;
+        let n = n_a + n_b;
+        for c in 0..self.mean.len() {
+            let delta = other.mean[c] - self.mean[c];
+            self.mean[c] = delta.mul_add(n_b / n, self.mean[c]);
+            self.m2[c] = (delta * delta).mul_add(n_a * n_b / n, self.m2[c] + other.m2[c]);
+            self.min[c] = self.min[c].min(other.min[c]);
+            self.max[c] = self.max[c].max(other.max[c]);
+        }
+        self.count += other.count;
+    }
+
+    /// The population standard deviation of each channel.
+    #[must_use]
+    pub fn std(&self) -> Vec<f64> {
+        self.m2
+            .iter()
+            .map(|m2| (m2 / self.count as f64).sqrt())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn exact_values() {
+        // 2 channels of 3 values each.
+        let corpus = [
+            array![[1., 2., 3.], [0., 0., 0.]],
+            array![[4., 5., 6.], [1., 0., 1.]],
+            array![[0., 0., 0.], [1., 1., 1.]],
+        ];
+        let mut stats = ObsStats::new(2);
+        corpus.iter().for_each(|obs| stats.add(obs.view()));
+
+        assert_eq!(stats.count, 9);
+        // Computed in two passes.
+        let values = [
+            [1., 2., 3., 4., 5., 6., 0., 0., 0.],
+            [0., 0., 0., 1., 0., 1., 1., 1., 1.],
+        ];
+        let mean = values.map(|v| v.iter().sum::<f64>() / 9.);
+        let std = [0, 1].map(|c| {
+            let var = values[c].iter().map(|x| (x - mean[c]).powi(2)).sum::<f64>() / 9.;
+            var.sqrt()
+        });
+        assert_close(&stats.mean, &mean);
+        assert_close(&stats.std(), &std);
+        assert_eq!(stats.min, [0., 0.]);
+        assert_eq!(stats.max, [6., 1.]);
+
+        let mut merged = ObsStats::new(2);
+        let mut rest = ObsStats::new(2);
+        merged.add(corpus[0].view());
+        corpus[1..].iter().for_each(|obs| rest.add(obs.view()));
+        merged.merge(&rest);
+        merged.merge(&ObsStats::new(2));
+        assert_eq!(merged.count, stats.count);
+        assert_close(&merged.mean, &stats.mean);
+        assert_close(&merged.m2, &stats.m2);
+        assert_eq!(merged.min, stats.min);
+        assert_eq!(merged.max, stats.max);
+
+        let mut from_empty = ObsStats::new(2);
+        from_empty.merge(&stats);
+        assert_eq!(from_empty, stats);
+
+        let dumped = json::to_string(&stats).unwrap();
+        assert_eq!(json::from_str::<ObsStats>(&dumped).unwrap(), stats);
+        let empty = json::to_string(&ObsStats::new(2)).unwrap();
+        assert_eq!(
+            json::from_str::<ObsStats>(&empty).unwrap(),
+            ObsStats::new(2)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Cross-referencing between dataset samples and their source logs.
+//!
+//! Every sample written by `DatasetWriter` is stamped with a `SampleStamp`,
+//! which leads back to the exact event the sample was made at, see
+//! `render_stamp`.
+
+use crate::mjai::Event;
+use crate::state::PlayerState;
+use std::fmt::Write;
+
+use anyhow::{ensure, Context, Result};
+use sha3::{Digest, Sha3_256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleStamp {
+    /// `canonical_log_hash` of the source log.
+    pub log_hash: [u8; 32],
+    /// 0-based index of the kyoku in the log, the same as `Gameplay::at_kyoku`.
+    pub kyoku: u8,
+    /// 1-based index of the last event the player has seen when the sample
+    /// is made, that is, the event the action of the sample reacts to. It
+    /// counts the events of the log, which are not always its lines, see
+    /// `Event::parse_line`.
+    pub event: u32,
+    pub seat: u8,
+}
+
+impl SampleStamp {
+    #[must_use]
+    pub fn log_hash_hex(&self) -> String {
+        hash_to_hex(&self.log_hash)
+    }
+}
+
+/// Returns SHA3-256 of `events`, each serialized by `Event::to_line` and
+/// terminated by a newline.
+///
+/// Two logs of the same events have the same hash regardless of their
+/// formatting, such as the order of the keys or the spaces.
+#[must_use]
+pub fn canonical_log_hash(events: &[Event]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    for ev in events {
+        hasher.update(ev.to_line());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().into()
+}
+
+#[must_use]
+pub fn hash_to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().fold(String::with_capacity(64), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+pub fn hash_from_hex(hex: &str) -> Result<[u8; 32]> {
+    ensure!(
+        hex.len() == 64 && hex.is_ascii(),
+        "{hex} is not a hex encoded SHA3-256",
+    );
+    let mut hash = [0; 32];
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .with_context(|| format!("{hex} is not a hex encoded SHA3-256"))?;
+    }
+    Ok(hash)
+}
+
+/// Returns the events of `events` within `context` events around the stamped
+/// one, which is marked with `>`, followed by the `brief_info` of the
+/// stamped seat right after the stamped event.
+///
+/// Err is returned if `events` is not the source log of `stamp`.
+pub fn render_stamp(events: &[Event], stamp: &SampleStamp, context: usize) -> Result<String> {
+    ensure!(
+        canonical_log_hash(events) == stamp.log_hash,
+        "the log does not match the log hash {}",
+        stamp.log_hash_hex(),
+    );
+    let event = stamp.event as usize;
+    ensure!(
+        (1..=events.len()).contains(&event),
+        "event {event} is out of the log of {} events",
+        events.len(),
+    );
+    ensure!(stamp.seat < 4, "{} is not in range [0, 3]", stamp.seat);
+
+    let seen = &events[..event];
+    let kyoku = seen
+        .iter()
+        .filter(|ev| matches!(ev, Event::EndKyoku))
+        .count();
+    ensure!(
+        kyoku == stamp.kyoku as usize,
+        "event {event} is in kyoku {kyoku}, but the stamp says {}",
+        stamp.kyoku,
+    );
+    let (state, _) = PlayerState::from_events(stamp.seat, seen)?;
+
+    let mut ret = String::new();
+    let first = event.saturating_sub(context).max(1);
+    let last = (event + context).min(events.len());
+    for (i, ev) in events.iter().enumerate().take(last).skip(first - 1) {
+        let n = i + 1;
+        let marker = if n == event { '>' } else { ' ' };
+        writeln!(ret, "{marker} {n:>5} {}", ev.to_line()).unwrap();
+    }
+    writeln!(ret).unwrap();
+    ret.push_str(&state.brief_info());
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::t;
+
+    #[test]
+    fn hex() {
+        let events = [
+            Event::StartKyoku {
+                bakaze: t!(E),
+                dora_marker: t!(1m),
+                kyoku: 1,
+                honba: 0,
+                kyotaku: 0,
+                oya: 0,
+                scores: [25000; 4],
+                tehais: [[t!(?); 13]; 4],
+            },
+            Event::EndKyoku,
+        ];
+        let hash = canonical_log_hash(&events);
+        assert_ne!(hash, canonical_log_hash(&events[..1]));
+
+        let hex = hash_to_hex(&hash);
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hash_from_hex(&hex).unwrap(), hash);
+        hash_from_hex(&hex[1..]).unwrap_err();
+        hash_from_hex(&hex.replace(&hex[..1], "g")).unwrap_err();
+    }
+}
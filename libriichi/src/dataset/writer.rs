@@ -0,0 +1,634 @@
+//! Sharded, compressed and resumable storage of `Gameplay` samples.
+//!
+//! The output directory holds shards named `00000.bin.zst`, `00001.bin.zst`
+//! and so on, plus an `index.json` recording, for every finished shard, its
+//! sample count, checksum and the source logs it was made from. A shard is
+//! finished as soon as it holds at least `samples_per_shard` samples. The
+//! samples of a source log never span two shards, so that an interrupted run
+//! can be resumed by skipping the source logs recorded in the index, which
+//! produces exactly the same shards as an uninterrupted run.
+//!
+//! With `enable_obs_stats`, the per-channel statistics of the obs in the
+//! finished shards are kept in `obs_stats_{shards}.json`, which is named in
+//! the index, see `ObsStats`.
+//!
+//! Each shard is zstd compressed. Inside it, samples are laid out one after
+//! another, each in little endian as:
+//!
+//! | type                       | field                        |
+//! | -------------------------- | ---------------------------- |
+//! | `u8`                       | player id                    |
+//! | `u8`                       | `Quality`                    |
+//! | `u8`                       | at kyoku                     |
+//! | `u8`                       | at turn                      |
+//! | `i8`                       | shanten                      |
+//! | `u8`                       | done                         |
+//! | `u8`                       | apply gamma                  |
+//! | `u8`                       | action                       |
+//! | `u8` * 32                  | log hash                     |
+//! | `u32`                      | event index                  |
+//! | `u64`                      | mask, bit `i` for action `i` |
+//! | `f32` * `OBS_SHAPE`        | obs                          |
+//! | `u8`                       | 1 if invisible obs follows   |
+//! | `f32` * `ORACLE_OBS_SHAPE` | invisible obs, optional      |
+//!
+//! The player id, at kyoku, log hash and event index make the `SampleStamp`
+//! of the sample, which can be read back by `read_stamps`. Any change to this
+//! layout bumps `SHARD_FORMAT_VERSION`, recorded in the index.
+
+use super::{hash_to_hex, Gameplay, ObsStats, SampleStamp};
+use crate::consts::{ACTION_SPACE, OBS_SHAPE, ORACLE_OBS_SHAPE};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use sha3::{Digest, Sha3_256};
+use zstd::{Decoder, Encoder};
+
+pub const INDEX_FILENAME: &str = "index.json";
+/// The version of the layout of the samples in a shard.
+pub const SHARD_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardIndex {
+    /// `SHARD_FORMAT_VERSION` of the shards, 0 for an index made before it
+    /// was recorded.
+    #[serde(default)]
+    pub version: u32,
+    pub samples_per_shard: usize,
+    pub shards: Vec<ShardEntry>,
+    /// The file of the `ObsStats` of all the shards, relative to the output
+    /// directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obs_stats: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardEntry {
+    /// Relative to the output directory.
+    pub filename: String,
+    pub samples: usize,
+    /// Hex encoded SHA3-256 of the compressed shard file.
+    pub sha3_256: String,
+    pub sources: Vec<SourceEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceEntry {
+    pub source: String,
+    /// May be 0 if no sample was extracted from the source.
+    pub samples: usize,
+    /// Hex encoded `canonical_log_hash` of the source, for looking up the
+    /// source of a `SampleStamp`. `None` if no gameplay is loaded from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_hash: Option<String>,
+}
+
+impl ShardIndex {
+    /// Returns the source log `stamp` is made from.
+    #[must_use]
+    pub fn source_of(&self, stamp: &SampleStamp) -> Option<&SourceEntry> {
+        let log_hash = stamp.log_hash_hex();
+        self.shards
+            .iter()
+            .flat_map(|s| &s.sources)
+            .find(|s| s.log_hash.as_ref() == Some(&log_hash))
+    }
+}
+
+pub struct DatasetWriter {
+    dir: PathBuf,
+    index: ShardIndex,
+    done: HashSet<String>,
+    current: Option<OpenShard>,
+    /// Of the finished shards.
+    obs_stats: Option<ObsStats>,
+}
+
+struct OpenShard {
+    filename: String,
+    encoder: Encoder<'static, BufWriter<File>>,
+    samples: usize,
+    sources: Vec<SourceEntry>,
+    obs_stats: Option<ObsStats>,
+}
+
+impl DatasetWriter {
+    /// Creates `dir` if it does not exist yet. If `dir` already has an index,
+    /// the run is resumed from it after checking every finished shard
+    /// against its checksum. Shards not in the index are left over by an
+    /// interrupted run and will be overwritten.
+    ///
+    /// Err is returned if the index was made with a different
+    /// `SHARD_FORMAT_VERSION` or `samples_per_shard`, or any finished shard
+    /// is missing or corrupted.
+    pub fn open(dir: impl AsRef<Path>, samples_per_shard: usize) -> Result<Self> {
+        ensure!(samples_per_shard > 0, "samples_per_shard must be positive");
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let index_path = dir.join(INDEX_FILENAME);
+        let index = if index_path.exists() {
+            let index: ShardIndex = json::from_reader(File::open(&index_path)?)
+                .with_context(|| format!("failed to parse {}", index_path.display()))?;
+            ensure!(
+                index.version == SHARD_FORMAT_VERSION,
+                "the existing index is made with shard format version {}, expected {}",
+                index.version,
+                SHARD_FORMAT_VERSION,
+            );
+            ensure!(
+                index.samples_per_shard == samples_per_shard,
+                "samples_per_shard is {samples_per_shard}, but the existing index uses {}",
+                index.samples_per_shard,
+            );
+            verify_shards(&dir, &index)?;
+            index
+        } else {
+            ShardIndex {
+                version: SHARD_FORMAT_VERSION,
+                samples_per_shard,
+                shards: vec![],
+                obs_stats: None,
+            }
+        };
+        let obs_stats = match &index.obs_stats {
+            Some(filename) => {
+                let path = dir.join(filename);
+                let obs_stats = json::from_reader(File::open(&path)?)
+                    .with_context(|| format!("failed to parse {}", path.display()))?;
+                Some(obs_stats)
+            }
+            None => None,
+        };
+
+        let done = index
+            .shards
+            .iter()
+            .flat_map(|s| &s.sources)
+            .map(|s| s.source.clone())
+            .collect();
+
+        Ok(Self {
+            dir,
+            index,
+            done,
+            current: None,
+            obs_stats,
+        })
+    }
+
+    /// Accumulates `ObsStats` of the samples written from now on. It is kept
+    /// enabled when the run is resumed.
+    ///
+    /// Err is returned if any sample is already written without it.
+    pub fn enable_obs_stats(&mut self) -> Result<()> {
+        if self.obs_stats.is_some() {
+            return Ok(());
+        }
+        ensure!(
+            self.index.shards.is_empty() && self.current.is_none(),
+            "the existing samples are written without obs stats",
+        );
+        self.obs_stats = Some(ObsStats::new(OBS_SHAPE.0));
+        Ok(())
+    }
+
+    /// Whether the samples of `source` are already in a finished shard.
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, source: &str) -> bool {
+        self.done.contains(source)
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn index(&self) -> &ShardIndex {
+        &self.index
+    }
+
+    /// Appends every sample of `gameplays`, which are all loaded from
+    /// `source`. The index is updated on disk whenever a shard is finished.
+    pub fn write_source(&mut self, source: &str, gameplays: &[Gameplay]) -> Result<()> {
+        ensure!(!self.contains(source), "{source} is already written");
+
+        if self.current.is_none() {
+            let filename = format!("{:05}.bin.zst", self.index.shards.len());
+            let file = File::create(self.dir.join(&filename))?;
+            self.current = Some(OpenShard {
+                filename,
+                // Level 0 is zstd's default.
+                encoder: Encoder::new(BufWriter::new(file), 0)?,
+                samples: 0,
+                sources: vec![],
+                obs_stats: self.obs_stats.as_ref().map(|_| ObsStats::new(OBS_SHAPE.0)),
+            });
+        }
+        let shard = self.current.as_mut().unwrap();
+
+        let mut samples = 0;
+        for gameplay in gameplays {
+            for idx in 0..gameplay.actions.len() {
+                write_sample(&mut shard.encoder, gameplay, idx)?;
+            }
+            if let Some(obs_stats) = &mut shard.obs_stats {
+                gameplay
+                    .obs
+                    .iter()
+                    .for_each(|obs| obs_stats.add(obs.view()));
+            }
+            samples += gameplay.actions.len();
+        }
+        shard.samples += samples;
+        shard.sources.push(SourceEntry {
+            source: source.to_owned(),
+            samples,
+            log_hash: gameplays.first().map(|g| hash_to_hex(&g.log_hash)),
+        });
+        self.done.insert(source.to_owned());
+
+        if shard.samples >= self.index.samples_per_shard {
+            self.finish_shard()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the last shard even if it is not full yet, and returns the
+    /// final index.
+    ///
+    /// Dropping the writer without calling this leaves the last shard out of
+    /// the index, as if the run were interrupted.
+    pub fn finish(mut self) -> Result<ShardIndex> {
+        self.finish_shard()?;
+        Ok(self.index)
+    }
+
+    fn finish_shard(&mut self) -> Result<()> {
+        let shard = match self.current.take() {
+            Some(shard) => shard,
+            None => return Ok(()),
+        };
+
+        let file = shard
+            .encoder
+            .finish()?
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+
+        let sha3_256 = checksum(&self.dir.join(&shard.filename))?;
+        self.index.shards.push(ShardEntry {
+            filename: shard.filename,
+            samples: shard.samples,
+            sha3_256,
+            sources: shard.sources,
+        });
+
+        // The stats get a new file for every shard, so that the index always
+        // names the one matching its shards, even if the run is interrupted
+        // right after the stats are written.
+        let old_obs_stats = self.index.obs_stats.take();
+        if let (Some(obs_stats), Some(shard_stats)) = (&mut self.obs_stats, &shard.obs_stats) {
+            obs_stats.merge(shard_stats);
+            let filename = format!("obs_stats_{:05}.json", self.index.shards.len());
+            write_atomically(&self.dir.join(&filename), obs_stats)?;
+            self.index.obs_stats = Some(filename);
+        }
+
+        // Replace the index atomically, so that an interruption never leaves
+        // a broken one behind.
+        write_atomically(&self.dir.join(INDEX_FILENAME), &self.index)?;
+        if let Some(old) = old_obs_stats {
+            fs::remove_file(self.dir.join(old))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks every shard in `index` against its checksum.
+pub fn verify_shards(dir: impl AsRef<Path>, index: &ShardIndex) -> Result<()> {
+    for shard in &index.shards {
+        let path = dir.as_ref().join(&shard.filename);
+        let sha3_256 =
+            checksum(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        ensure!(
+            sha3_256 == shard.sha3_256,
+            "checksum mismatch for {}: expected {}, got {sha3_256}",
+            path.display(),
+            shard.sha3_256,
+        );
+    }
+    Ok(())
+}
+
+fn write_atomically<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    json::to_writer_pretty(&mut tmp, value)?;
+    tmp.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn checksum(path: &Path) -> Result<String> {
+    let mut hasher = Sha3_256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn write_sample<W: Write>(w: &mut W, gameplay: &Gameplay, idx: usize) -> Result<()> {
+    w.write_u8(gameplay.player_id)?;
+    w.write_u8(gameplay.quality as u8)?;
+    w.write_u8(gameplay.at_kyoku[idx])?;
+    w.write_u8(gameplay.at_turns[idx])?;
+    w.write_i8(gameplay.shantens[idx])?;
+    w.write_u8(gameplay.dones[idx] as u8)?;
+    w.write_u8(gameplay.apply_gamma[idx] as u8)?;
+    w.write_u8(gameplay.actions[idx] as u8)?;
+    w.write_all(&gameplay.log_hash)?;
+    w.write_u32::<LittleEndian>(gameplay.event_indices[idx])?;
+
+    let mask = &gameplay.masks[idx];
+    ensure!(
+        mask.len() == ACTION_SPACE,
+        "unexpected mask size {}",
+        mask.len()
+    );
+    let mask_bits = mask
+        .iter()
+        .enumerate()
+        .fold(0_u64, |acc, (i, &b)| acc | (b as u64) << i);
+    w.write_u64::<LittleEndian>(mask_bits)?;
+
+    write_array(w, gameplay.obs[idx].iter(), OBS_SHAPE)?;
+    match gameplay.invisible_obs.get(idx) {
+        Some(invisible_obs) => {
+            w.write_u8(1)?;
+            write_array(w, invisible_obs.iter(), ORACLE_OBS_SHAPE)?;
+        }
+        None => w.write_u8(0)?,
+    }
+
+    Ok(())
+}
+
+/// Reads the `SampleStamp` of every sample in the shard at `path`.
+pub fn read_stamps(path: impl AsRef<Path>) -> Result<Vec<SampleStamp>> {
+    let obs_len = (OBS_SHAPE.0 * OBS_SHAPE.1 * 4) as u64;
+    let invisible_obs_len = (ORACLE_OBS_SHAPE.0 * ORACLE_OBS_SHAPE.1 * 4) as u64;
+
+    let mut r = io::BufReader::new(Decoder::new(File::open(path)?)?);
+    let mut stamps = vec![];
+    while !r.fill_buf()?.is_empty() {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        let mut log_hash = [0; 32];
+        r.read_exact(&mut log_hash)?;
+        let event = r.read_u32::<LittleEndian>()?;
+        stamps.push(SampleStamp {
+            log_hash,
+            kyoku: header[2],
+            event,
+            seat: header[0],
+        });
+
+        // The mask and the obs.
+        skip(&mut r, 8 + obs_len)?;
+        if r.read_u8()? == 1 {
+            skip(&mut r, invisible_obs_len)?;
+        }
+    }
+    Ok(stamps)
+}
+
+fn skip<R: Read>(r: &mut R, len: u64) -> Result<()> {
+    let skipped = io::copy(&mut r.take(len), &mut io::sink())?;
+    ensure!(skipped == len, "unexpected end of the shard");
+    Ok(())
+}
+
+fn write_array<'a, W, I>(w: &mut W, values: I, shape: (usize, usize)) -> Result<()>
+where
+    W: Write,
+    I: ExactSizeIterator<Item = &'a f32>,
+{
+    ensure!(
+        values.len() == shape.0 * shape.1,
+        "expected {} values, got {}",
+        shape.0 * shape.1,
+        values.len(),
+    );
+    for &v in values {
+        w.write_f32::<LittleEndian>(v)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Tsumogiri;
+    use crate::arena::OneVsThree;
+    use crate::dataset::{render_stamp, GameplayLoader};
+    use crate::logs::{open_log, parse_log};
+    use crate::mjai::Event;
+    use std::env;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("riichi-{name}-{}", std::process::id()));
+            fs::remove_dir_all(&path).ok();
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    /// Returns the sorted filenames of the fixture logs.
+    fn make_fixture_logs(dir: &Path) -> Vec<String> {
+        let arena = OneVsThree {
+            disable_progress_bar: true,
+            log_dir: Some(dir.to_str().unwrap().to_owned()),
+            ..Default::default()
+        };
+        arena
+            .run_batch(Tsumogiri::new_batched, Tsumogiri::new_batched, (0, 0), 2)
+            .unwrap();
+
+        let mut logs: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path().to_str().unwrap().to_owned())
+            .collect();
+        logs.sort();
+        logs
+    }
+
+    fn write_all(writer: &mut DatasetWriter, logs: &[String]) {
+        let loader = GameplayLoader::default();
+        for log in logs {
+            if writer.contains(log) {
+                continue;
+            }
+            let gameplays = loader.load_gz_log_files(vec![log.as_str()]).unwrap();
+            writer.write_source(log, &gameplays).unwrap();
+        }
+    }
+
+    fn read_dir_sorted(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+                (name, fs::read(path).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn resume() {
+        let logs_dir = TempDir::new("dataset-logs");
+        let logs = make_fixture_logs(&logs_dir.0);
+        assert_eq!(logs.len(), 8);
+
+        let samples_per_log = {
+            let loader = GameplayLoader::default();
+            let gameplays = loader.load_gz_log_files(vec![logs[0].as_str()]).unwrap();
+            gameplays.iter().map(|g| g.actions.len()).sum::<usize>()
+        };
+        // Roughly three logs per shard.
+        let samples_per_shard = samples_per_log * 5 / 2;
+
+        let full_dir = TempDir::new("dataset-full");
+        let mut writer = DatasetWriter::open(&full_dir.0, samples_per_shard).unwrap();
+        writer.enable_obs_stats().unwrap();
+        write_all(&mut writer, &logs);
+        let full_index = writer.finish().unwrap();
+        assert!(full_index.shards.len() >= 2);
+        assert_eq!(
+            full_index
+                .shards
+                .iter()
+                .flat_map(|s| &s.sources)
+                .map(|s| &s.source)
+                .collect::<Vec<_>>(),
+            logs.iter().collect::<Vec<_>>(),
+        );
+        for shard in &full_index.shards {
+            assert_eq!(
+                shard.samples,
+                shard.sources.iter().map(|s| s.samples).sum::<usize>(),
+            );
+        }
+        let samples: usize = full_index.shards.iter().map(|s| s.samples).sum();
+        let obs_stats = ObsStats::load(full_dir.0.to_str().unwrap()).unwrap();
+        assert_eq!(obs_stats.count, (samples * OBS_SHAPE.1) as u64);
+        assert_eq!(obs_stats.mean.len(), OBS_SHAPE.0);
+
+        // Interrupted in the middle of the second shard.
+        let resumed_dir = TempDir::new("dataset-resumed");
+        let first_shard_len = full_index.shards[0].sources.len();
+        let mut writer = DatasetWriter::open(&resumed_dir.0, samples_per_shard).unwrap();
+        writer.enable_obs_stats().unwrap();
+        write_all(&mut writer, &logs[..first_shard_len + 1]);
+        drop(writer);
+
+        let mut writer = DatasetWriter::open(&resumed_dir.0, samples_per_shard).unwrap();
+        assert_eq!(writer.index().shards.len(), 1);
+        assert!(writer.contains(&logs[0]));
+        assert!(!writer.contains(&logs[first_shard_len]));
+        write_all(&mut writer, &logs);
+        let resumed_index = writer.finish().unwrap();
+
+        assert_eq!(resumed_index, full_index);
+        assert_eq!(
+            read_dir_sorted(&resumed_dir.0),
+            read_dir_sorted(&full_dir.0)
+        );
+
+        // Stats cannot be enabled once samples are written without them.
+        let no_stats_dir = TempDir::new("dataset-no-stats");
+        let mut writer = DatasetWriter::open(&no_stats_dir.0, samples_per_shard).unwrap();
+        write_all(&mut writer, &logs[..1]);
+        writer.enable_obs_stats().unwrap_err();
+
+        // A different shard size or format version, or a corrupted shard
+        // refuses to resume.
+        DatasetWriter::open(&resumed_dir.0, samples_per_shard + 1).unwrap_err();
+        let index_path = full_dir.0.join(INDEX_FILENAME);
+        let mut index: ShardIndex =
+            json::from_str(&fs::read_to_string(&index_path).unwrap()).unwrap();
+        assert_eq!(index.version, SHARD_FORMAT_VERSION);
+        index.version += 1;
+        fs::write(&index_path, json::to_string(&index).unwrap()).unwrap();
+        DatasetWriter::open(&full_dir.0, samples_per_shard).unwrap_err();
+        let first_shard = resumed_dir.0.join(&full_index.shards[0].filename);
+        let mut data = fs::read(&first_shard).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        fs::write(&first_shard, data).unwrap();
+        DatasetWriter::open(&resumed_dir.0, samples_per_shard).unwrap_err();
+    }
+
+    #[test]
+    fn stamps() {
+        let logs_dir = TempDir::new("dataset-stamp-logs");
+        let logs = make_fixture_logs(&logs_dir.0);
+        let out_dir = TempDir::new("dataset-stamps");
+        let mut writer = DatasetWriter::open(&out_dir.0, usize::MAX).unwrap();
+        write_all(&mut writer, &logs[..1]);
+        let index = writer.finish().unwrap();
+
+        let shard = &index.shards[0];
+        let stamps = read_stamps(out_dir.0.join(&shard.filename)).unwrap();
+        assert_eq!(stamps.len(), shard.samples);
+
+        // The first sample is the oya of E1 at its first tsumo, which follows
+        // start_game and start_kyoku.
+        let stamp = stamps[0];
+        assert_eq!((stamp.kyoku, stamp.event, stamp.seat), (0, 3, 0));
+        assert_eq!(index.source_of(&stamp).unwrap().source, logs[0]);
+
+        let mut raw = String::new();
+        open_log(Path::new(&logs[0]))
+            .unwrap()
+            .read_to_string(&mut raw)
+            .unwrap();
+        let events = parse_log(&raw).unwrap();
+        assert!(matches!(events[2], Event::Tsumo { actor: 0, .. }));
+
+        let rendered = render_stamp(&events, &stamp, 1).unwrap();
+        let lines: Vec<_> = rendered.lines().take(3).collect();
+        assert_eq!(
+            lines,
+            [
+                format!("      2 {}", events[1].to_line()),
+                format!(">     3 {}", events[2].to_line()),
+                format!("      4 {}", events[3].to_line()),
+            ],
+        );
+
+        // Every stamp resolves, and a wrong kyoku or log does not.
+        for stamp in &stamps {
+            render_stamp(&events, stamp, 0).unwrap();
+        }
+        let wrong_kyoku = SampleStamp { kyoku: 1, ..stamp };
+        render_stamp(&events, &wrong_kyoku, 0).unwrap_err();
+        render_stamp(&events[..10], &stamp, 0).unwrap_err();
+    }
+}
@@ -143,6 +143,8 @@ pub fn tiles_to_string(tiles: &[u8; 34], aka: [bool; 3]) -> String {
 
     if jihai.is_empty() {
         suhai
+    } else if suhai.is_empty() {
+        format!("{jihai}z")
     } else {
         format!("{suhai} {jihai}z")
     }
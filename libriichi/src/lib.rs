@@ -43,26 +43,34 @@
 )]
 
 mod arena;
-mod consts;
-mod dataset;
 mod macros;
 mod py_helper;
 mod vec_ops;
 
 // pub for bins
 pub mod chi_type;
+pub mod consts;
+pub mod convert;
+pub mod dataset;
+pub mod logs;
 pub mod mjai;
+pub mod names;
 pub mod stat;
 pub mod state;
 
 // pub for non-cfg(test) tests
 pub mod agent;
+pub mod simulate;
 pub mod tile;
 
 // pub for benchmarks
 pub mod algo;
 pub mod hand;
 
+// pub for downstream tests
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 use pyo3::prelude::*;
 
 #[cfg(feature = "mimalloc")]
@@ -102,6 +110,7 @@ fn libriichi(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     arena::register_module(py, name, m)?;
     stat::register_module(py, name, m)?;
     mjai::register_module(py, name, m)?;
+    names::register_module(py, name, m)?;
 
     Ok(())
 }
@@ -0,0 +1,287 @@
+//! Finding and reading the logs of a corpus, and `LogIndex`, a cache of the
+//! per-log results of a binary run over the corpus.
+//!
+//! The index is a JSON lines file with one `IndexEntry` per log. On the next
+//! run, a log whose size and mtime are unchanged is skipped without being
+//! read at all. A log whose size or mtime changed is parsed again, but if its
+//! `canonical_log_hash` is still the same, such as when it is only touched or
+//! reformatted, its record is reused.
+
+use crate::dataset::hash_to_hex;
+use crate::mjai::Event;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use glob::{glob, GlobResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use xz2::read::XzDecoder;
+
+/// Patterns of the logs under a directory, see `open_log`.
+pub const LOG_PATTERNS: &[&str] = &["*.json", "*.json.gz", "*.json.zst", "*.json.xz"];
+
+/// Finds the logs recursively under `dir`, matching `LOG_PATTERNS`.
+pub fn find_logs(dir: &str) -> Result<impl Iterator<Item = GlobResult>> {
+    let paths = LOG_PATTERNS
+        .iter()
+        .map(|pattern| glob(&format!("{dir}/**/{pattern}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(paths.into_iter().flatten())
+}
+
+/// Opens a log for reading, decompressing it according to its extension.
+pub fn open_log(path: &Path) -> Result<Box<dyn Read>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let reader: Box<dyn Read> = match ext.as_str() {
+        "json" => Box::new(file),
+        "gz" => Box::new(GzDecoder::new(file)),
+        "zst" => Box::new(zstd::Decoder::new(file)?),
+        "xz" => Box::new(XzDecoder::new(file)),
+        _ => bail!("unsupported log format {}", path.display()),
+    };
+    Ok(reader)
+}
+
+/// Parses a log of mjai events, one JSON per line, with `Event::parse_line`.
+/// Blank lines are skipped.
+pub fn parse_log(raw: &str) -> Result<Vec<Event>> {
+    let mut events = vec![];
+    for (idx, l) in raw.lines().enumerate() {
+        if l.trim().is_empty() {
+            continue;
+        }
+        let parsed =
+            Event::parse_line(l).with_context(|| format!("failed to parse line {}", idx + 1))?;
+        events.extend(parsed);
+    }
+    Ok(events)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub mtime_ns: u64,
+    /// Hex encoded `canonical_log_hash` of the log. `None` if it cannot be
+    /// parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// See `LogIndex::open`.
+    pub context: String,
+    /// Whatever the binary records for the log.
+    pub record: json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexReport {
+    /// Logs with the same size and mtime as indexed, which are not read.
+    pub skipped: usize,
+    /// Logs read and parsed again.
+    pub parsed: usize,
+    /// Of the parsed logs, those with the same hash as indexed, whose records
+    /// are reused.
+    pub same_hash: usize,
+}
+
+pub struct LogIndex {
+    path: PathBuf,
+    context: String,
+    /// By `IndexEntry::path`.
+    entries: Mutex<HashMap<String, IndexEntry>>,
+    report: Mutex<IndexReport>,
+}
+
+impl LogIndex {
+    /// Loads the index at `path` if it exists, unless `rebuild` is true, in
+    /// which case every log is processed again.
+    ///
+    /// `context` describes whatever affects the records other than the logs,
+    /// such as the options of the binary. Entries made in a different context
+    /// are dropped.
+    pub fn open(path: impl AsRef<Path>, context: &str, rebuild: bool) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut entries = HashMap::new();
+        if !rebuild && path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for (idx, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: IndexEntry = json::from_str(&line).with_context(|| {
+                    format!("failed to parse line {} of {}", idx + 1, path.display())
+                })?;
+                if entry.context == context {
+                    entries.insert(entry.path.clone(), entry);
+                }
+            }
+        }
+
+        Ok(Self {
+            path,
+            context: context.to_owned(),
+            entries: Mutex::new(entries),
+            report: Mutex::new(IndexReport::default()),
+        })
+    }
+
+    /// Returns the record of the log at `log` if its size and mtime are the
+    /// same as indexed. Otherwise the log is counted as parsed, as the caller
+    /// is expected to parse it, then call `lookup_hash` and `insert`.
+    #[must_use]
+    pub fn lookup<T: DeserializeOwned>(&self, log: &Path) -> Option<T> {
+        let record = stat(log).ok().and_then(|(size, mtime_ns)| {
+            let entries = self.entries.lock().unwrap();
+            let entry = entries.get(&*log.to_string_lossy())?;
+            if entry.size != size || entry.mtime_ns != mtime_ns {
+                return None;
+            }
+            json::from_value(entry.record.clone()).ok()
+        });
+
+        let mut report = self.report.lock().unwrap();
+        if record.is_some() {
+            report.skipped += 1;
+        } else {
+            report.parsed += 1;
+        }
+        record
+    }
+
+    /// Returns the record of the log at `log` if `hash`, its
+    /// `canonical_log_hash`, is the same as indexed. The size and mtime of
+    /// the entry are updated, so that the log is skipped next time.
+    #[must_use]
+    pub fn lookup_hash<T: DeserializeOwned>(&self, log: &Path, hash: &[u8; 32]) -> Option<T> {
+        let (size, mtime_ns) = stat(log).ok()?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&*log.to_string_lossy())?;
+        if entry.hash.as_deref() != Some(hash_to_hex(hash).as_str()) {
+            return None;
+        }
+        let record = json::from_value(entry.record.clone()).ok()?;
+        entry.size = size;
+        entry.mtime_ns = mtime_ns;
+        self.report.lock().unwrap().same_hash += 1;
+        Some(record)
+    }
+
+    /// Records `record` for the log at `log`, with its current size and
+    /// mtime. `hash` is `None` if the log cannot be parsed.
+    pub fn insert<T: Serialize>(
+        &self,
+        log: &Path,
+        hash: Option<&[u8; 32]>,
+        record: &T,
+    ) -> Result<()> {
+        let (size, mtime_ns) = stat(log)?;
+        let path = log.to_string_lossy().into_owned();
+        let entry = IndexEntry {
+            path: path.clone(),
+            size,
+            mtime_ns,
+            hash: hash.map(hash_to_hex),
+            context: self.context.clone(),
+            record: json::to_value(record)?,
+        };
+        self.entries.lock().unwrap().insert(path, entry);
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn report(&self) -> IndexReport {
+        *self.report.lock().unwrap()
+    }
+
+    /// Writes the index, sorted by path, replacing the old one atomically.
+    /// Entries of logs not visited in this run are kept.
+    pub fn save(&self) -> Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let mut entries: Vec<_> = entries.values().collect();
+        entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for entry in entries {
+            json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(tmp_path, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Returns the size and mtime in nanoseconds of the file at `path`.
+fn stat(path: &Path) -> Result<(u64, u64)> {
+    let meta = fs::metadata(path)?;
+    let mtime_ns = meta.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    Ok((meta.len(), mtime_ns))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn index_round_trip() {
+        let dir = env::temp_dir().join(format!("logs_index_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("index.jsonl");
+        let log = dir.join("a.json");
+        fs::write(&log, "{}").unwrap();
+        let hash = [7; 32];
+
+        let index = LogIndex::open(&index_path, "ctx", false).unwrap();
+        assert_eq!(index.lookup::<u32>(&log), None);
+        assert_eq!(index.lookup_hash::<u32>(&log, &hash), None);
+        index.insert(&log, Some(&hash), &42_u32).unwrap();
+        index.save().unwrap();
+        assert_eq!(
+            index.report(),
+            IndexReport {
+                parsed: 1,
+                ..Default::default()
+            },
+        );
+
+        let index = LogIndex::open(&index_path, "ctx", false).unwrap();
+        assert_eq!(index.lookup::<u32>(&log), Some(42));
+        // A record of another type is a miss rather than an error.
+        assert_eq!(index.lookup::<String>(&log), None);
+        assert_eq!(index.lookup_hash::<u32>(&log, &[8; 32]), None);
+        assert_eq!(index.lookup_hash::<u32>(&log, &hash), Some(42));
+        assert_eq!(
+            index.report(),
+            IndexReport {
+                skipped: 1,
+                parsed: 1,
+                same_hash: 1,
+            },
+        );
+
+        // Another context or a rebuild starts from scratch.
+        let index = LogIndex::open(&index_path, "other", false).unwrap();
+        assert_eq!(index.lookup::<u32>(&log), None);
+        let index = LogIndex::open(&index_path, "ctx", true).unwrap();
+        assert_eq!(index.lookup::<u32>(&log), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,688 @@
+use super::Event;
+use crate::state::{PlayerState, Rules};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpStream, ToSocketAddrs};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde_json::{self as json, Value};
+
+/// Reacts to the events received by `MjaiClient`.
+pub trait MjaiAgent {
+    /// Returns the reaction to `event`, which has just been applied to
+    /// `state`, or `None` to pass.
+    ///
+    /// It is called for every event, except those replayed from a backlog,
+    /// see `fast_forward`. A reaction `state` does not allow, including
+    /// reacting when `state.last_cans()` does not allow any action, or
+    /// passing when a discard is mandatory, is replaced by
+    /// `PlayerState::fallback_reaction` before being sent.
+    fn react(&mut self, event: &Event, state: &PlayerState) -> Result<Option<Event>>;
+
+    /// Called instead of `react` for an event replayed from a backlog, which
+    /// has just been applied to `state` and needs no reaction, because it is
+    /// not the last one, or the player cannot act on it.
+    ///
+    /// The agent may have seen some of these events before the reconnect.
+    fn fast_forward(&mut self, event: &Event, state: &PlayerState) -> Result<()> {
+        let _ = (event, state);
+        Ok(())
+    }
+}
+
+/// A blocking client of an mjai server, speaking newline-delimited JSON over
+/// TCP.
+///
+/// A message is either an event or a JSON array of events. An array starting
+/// with `start_kyoku`, optionally after `start_game`, is a backlog, which is
+/// the server resending the whole kyoku so far, typically after a reconnect.
+/// The state is rebuilt from the backlog, and only its last event may be
+/// passed to `MjaiAgent::react`, if the player can act on it. If the player
+/// has already reacted to that very event before the reconnect, the same
+/// reaction is sent again without asking the agent.
+///
+/// Every reaction is checked by `PlayerState::validate_reaction` before
+/// being sent, and one that fails is logged and replaced by the tsumogiri or
+/// pass of `PlayerState::fallback_reaction`, since servers tend to punish an
+/// illegal action much harder than a bad one. See `violations`.
+pub struct MjaiClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// The rules of the states created on `start_game`.
+    rules: Rules,
+    state: Option<PlayerState>,
+    /// Events of the current kyoku applied to `state`, from `start_kyoku`.
+    kyoku: Vec<Event>,
+    /// The last reaction sent, along with the length of `kyoku` at that time.
+    last_reaction: Option<(usize, String)>,
+    /// The number of events the player could act on, plus the reactions to
+    /// ones it could not.
+    decisions: u64,
+    /// The number of reactions replaced by `fallback_reaction`.
+    violations: u64,
+}
+
+enum Response {
+    Pass,
+    React(Event),
+    /// A reaction already sent before the reconnect.
+    Resend(String),
+}
+
+impl MjaiClient {
+    /// Connects to the server and joins `room` as `name`.
+    pub fn connect(addr: impl ToSocketAddrs, name: &str, room: &str) -> Result<Self> {
+        let (reader, writer) = join(addr, name, room)?;
+        Ok(Self {
+            reader,
+            writer,
+            rules: Rules::default(),
+            state: None,
+            kyoku: vec![],
+            last_reaction: None,
+            decisions: 0,
+            violations: 0,
+        })
+    }
+
+    /// Replaces the connection with a new one, keeping the state of the game,
+    /// so that `run` can continue with the backlog resent by the server.
+    pub fn reconnect(&mut self, addr: impl ToSocketAddrs, name: &str, room: &str) -> Result<()> {
+        let (reader, writer) = join(addr, name, room)?;
+        self.reader = reader;
+        self.writer = writer;
+        Ok(())
+    }
+
+    /// Sets the rules of the games started from now on, such as
+    /// `GameMode::Sanma` for a sanma server. The default is Tenhou's rule.
+    #[inline]
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    /// The number of decisions made so far, over all games, which counts
+    /// every event the player could act on, and every reaction to one it
+    /// could not.
+    #[inline]
+    #[must_use]
+    pub const fn decisions(&self) -> u64 {
+        self.decisions
+    }
+    /// The number of reactions replaced by `PlayerState::fallback_reaction`
+    /// so far, over all games.
+    #[inline]
+    #[must_use]
+    pub const fn violations(&self) -> u64 {
+        self.violations
+    }
+    /// `violations` per 1000 `decisions`, or 0 if there is no decision yet.
+    #[must_use]
+    pub fn violations_per_1000(&self) -> f64 {
+        if self.decisions == 0 {
+            return 0.;
+        }
+        self.violations as f64 * 1000. / self.decisions as f64
+    }
+
+    /// Plays a game with `agent` until `end_game`.
+    ///
+    /// Every message from the server is answered with exactly one line,
+    /// which is `none` unless the agent reacts. A reaction the local state
+    /// does not allow is replaced before being sent, see `MjaiClient`.
+    ///
+    /// If the connection is lost, the game can be continued by calling `run`
+    /// again after `reconnect`.
+    pub fn run(&mut self, agent: &mut impl MjaiAgent) -> Result<()> {
+        loop {
+            let line = read_line(&mut self.reader)?.context("connection closed before end_game")?;
+            let messages = match json::from_str(&line) {
+                Ok(Value::Array(messages)) => messages,
+                Ok(value) => vec![value],
+                Err(err) => return Err(err).with_context(|| format!("invalid message {line}")),
+            };
+
+            let mut events = vec![];
+            for msg in &messages {
+                match msg["type"].as_str() {
+                    Some("error") => bail!("error from the server: {line}"),
+                    // A backlog of the same game keeps the state, which is
+                    // rebuilt anyway.
+                    Some("start_game") if messages.len() == 1 || self.state.is_none() => {
+                        let id = msg["id"]
+                            .as_u64()
+                            .filter(|&id| id < self.rules.mode.num_players() as u64)
+                            .with_context(|| format!("start_game without a valid id: {line}"))?;
+                        self.state = Some(PlayerState::with_rules(id as u8, self.rules));
+                        self.kyoku.clear();
+                        self.last_reaction = None;
+                    }
+                    _ => (),
+                }
+                events.extend(
+                    Event::parse_line(&msg.to_string())
+                        .with_context(|| format!("failed to parse {line}"))?,
+                );
+            }
+            ensure!(self.state.is_some(), "event before start_game");
+            ensure!(!events.is_empty(), "no event in {line}");
+
+            let is_backlog = messages.len() > 1
+                && matches!(
+                    events
+                        .iter()
+                        .find(|ev| !matches!(ev, Event::StartGame { .. })),
+                    Some(Event::StartKyoku { .. }),
+                );
+            let response = if is_backlog {
+                self.fast_forward(&events, agent)?
+            } else {
+                self.apply(&events, agent, &line)?
+            };
+
+            let response = match response {
+                Response::Pass => self.check_reaction(Event::None, &line)?,
+                Response::React(reaction) => self.check_reaction(reaction, &line)?,
+                Response::Resend(response) => response,
+            };
+            write_line(&mut self.writer, &response)?;
+
+            if matches!(events.last(), Some(Event::EndGame)) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks `reaction` to `line` against the state, replacing it with
+    /// `fallback_reaction` if it is not allowed, and returns the line to send.
+    fn check_reaction(&mut self, reaction: Event, line: &str) -> Result<String> {
+        let state = self.state.as_ref().unwrap();
+        let cans = state.last_cans();
+        if !cans.can_act() && reaction == Event::None {
+            return Ok(json::to_string(&Event::None)?);
+        }
+
+        self.decisions += 1;
+        let checked = if !cans.can_act() {
+            Err(anyhow!("cannot act"))
+        } else if cans.can_discard && reaction == Event::None {
+            Err(anyhow!("must discard"))
+        } else {
+            state.validate_reaction(&reaction)
+        };
+        let reaction = match checked {
+            Ok(()) => reaction,
+            Err(err) => {
+                self.violations += 1;
+                let fallback = state.reaction_to_event(state.fallback_reaction());
+                log::warn!(
+                    "invalid reaction {reaction:?} to {line}: {err:#}, sending {fallback:?} instead\n{}",
+                    state.brief_info(),
+                );
+                fallback
+            }
+        };
+
+        let response = json::to_string(&reaction)?;
+        if reaction != Event::None {
+            self.last_reaction = Some((self.kyoku.len(), response.clone()));
+        }
+        Ok(response)
+    }
+
+    /// Applies the events of a message, asking `agent` to react to each.
+    fn apply(
+        &mut self,
+        events: &[Event],
+        agent: &mut impl MjaiAgent,
+        line: &str,
+    ) -> Result<Response> {
+        let state = self.state.as_mut().unwrap();
+        let mut reaction = None;
+        for (idx, ev) in events.iter().enumerate() {
+            state.update(ev);
+            if matches!(ev, Event::StartKyoku { .. }) {
+                self.kyoku.clear();
+            }
+            self.kyoku.push(ev.clone());
+
+            reaction = agent
+                .react(ev, state)?
+                .filter(|reaction| *reaction != Event::None);
+            ensure!(
+                reaction.is_none() || idx == events.len() - 1,
+                "reacted to {ev:?}, which is not the last event of {line}",
+            );
+        }
+        Ok(reaction.map_or(Response::Pass, Response::React))
+    }
+
+    /// Rebuilds the state from a backlog, see `MjaiClient`.
+    fn fast_forward(&mut self, events: &[Event], agent: &mut impl MjaiAgent) -> Result<Response> {
+        let start = events
+            .iter()
+            .position(|ev| matches!(ev, Event::StartKyoku { .. }))
+            .unwrap();
+        let backlog = &events[start..];
+
+        let answered = match &self.last_reaction {
+            Some((len, response))
+                if *len == backlog.len() && self.kyoku.first() == backlog.first() =>
+            {
+                Some(response.clone())
+            }
+            _ => None,
+        };
+
+        let state = self.state.as_mut().unwrap();
+        *state = PlayerState::with_rules(state.player_id(), state.rules());
+        self.kyoku.clear();
+        let (last, history) = backlog.split_last().unwrap();
+        for ev in history {
+            state.update(ev);
+            self.kyoku.push(ev.clone());
+            agent.fast_forward(ev, state)?;
+        }
+        state.update(last);
+        self.kyoku.push(last.clone());
+
+        if let Some(response) = answered {
+            return Ok(Response::Resend(response));
+        }
+        if !state.last_cans().can_act() {
+            agent.fast_forward(last, state)?;
+            return Ok(Response::Pass);
+        }
+        let reaction = agent
+            .react(last, state)?
+            .filter(|reaction| *reaction != Event::None);
+        Ok(reaction.map_or(Response::Pass, Response::React))
+    }
+}
+
+/// Connects to the server and joins `room` as `name`.
+fn join(
+    addr: impl ToSocketAddrs,
+    name: &str,
+    room: &str,
+) -> Result<(BufReader<TcpStream>, TcpStream)> {
+    let stream = TcpStream::connect(addr).context("failed to connect")?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let hello = read_line(&mut reader)?.context("connection closed before hello")?;
+    let value: Value =
+        json::from_str(&hello).with_context(|| format!("invalid message {hello}"))?;
+    ensure!(value["type"] == "hello", "expected hello, got {hello}");
+
+    let join = json::json!({
+        "type": "join",
+        "name": name,
+        "room": room,
+    });
+    write_line(&mut writer, &join.to_string())?;
+
+    Ok((reader, writer))
+}
+
+/// Returns `None` if the server has closed the connection.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end().to_owned()))
+}
+
+fn write_line(writer: &mut impl Write, line: &str) -> Result<()> {
+    writeln!(writer, "{line}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::t;
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread::{self, JoinHandle};
+
+    const SCRIPT: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"{"type":"start_game","id":0,"names":["bot","b","c","d"]}"#,
+        r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"C"}"#,
+        r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"ryukyoku","deltas":[0,0,0,0]}"#,
+        r#"{"type":"end_kyoku"}"#,
+        r#"{"type":"end_game"}"#,
+    ];
+
+    /// The player riichis with a W tsumo, waiting on 6s and 9s.
+    const RIICHI: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"{"type":"start_game","id":0,"names":["bot","b","c","d"]}"#,
+        r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","7s","8s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"W"}"#,
+        r#"{"type":"reach","actor":0}"#,
+        r#"{"type":"dahai","actor":0,"pai":"W","tsumogiri":true}"#,
+        r#"{"type":"reach_accepted","actor":0,"deltas":[-1000,0,0,0],"scores":[24000,25000,25000,25000]}"#,
+        r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":1,"pai":"N","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":2,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":2,"pai":"N","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":3,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":3,"pai":"N","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"C"}"#,
+        r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}"#,
+        r#"{"type":"ryukyoku","deltas":[0,0,0,0]}"#,
+        r#"{"type":"end_kyoku"}"#,
+        r#"{"type":"end_game"}"#,
+    ];
+
+    const DISCONNECTED_MID_TURN: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"{"type":"start_game","id":0,"names":["bot","b","c","d"]}"#,
+        r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"C"}"#,
+    ];
+
+    const RESUMED_MID_TURN: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"[{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]},{"type":"tsumo","actor":0,"pai":"C"}]"#,
+        r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"ryukyoku","deltas":[0,0,0,0]}"#,
+        r#"{"type":"end_kyoku"}"#,
+        r#"{"type":"end_game"}"#,
+    ];
+
+    /// Joins in the middle of a game, so there is no state to resume.
+    const JOINED_MID_TURN: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"[{"type":"start_game","id":0,"names":["bot","b","c","d"]},{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]},{"type":"tsumo","actor":0,"pai":"C"}]"#,
+        r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}"#,
+        r#"{"type":"ryukyoku","deltas":[0,0,0,0]}"#,
+        r#"{"type":"end_kyoku"}"#,
+        r#"{"type":"end_game"}"#,
+    ];
+
+    const DISCONNECTED_BEFORE_CALL: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"{"type":"start_game","id":0,"names":["bot","b","c","d"]}"#,
+        r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"C"}"#,
+        r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+    ];
+
+    const RESUMED_IN_CALL_WINDOW: &[&str] = &[
+        r#"{"type":"hello","protocol":"mjsonp","protocol_version":3}"#,
+        r#"[{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]},{"type":"tsumo","actor":0,"pai":"C"},{"type":"dahai","actor":0,"pai":"C","tsumogiri":true},{"type":"tsumo","actor":1,"pai":"?"},{"type":"dahai","actor":1,"pai":"E","tsumogiri":true}]"#,
+        r#"{"type":"tsumo","actor":2,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"ryukyoku","deltas":[0,0,0,0]}"#,
+        r#"{"type":"end_kyoku"}"#,
+        r#"{"type":"end_game"}"#,
+    ];
+
+    /// Sends the script line by line, waiting for a response after each one,
+    /// and returns the responses.
+    fn serve(script: &'static [&'static str]) -> (SocketAddr, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut responses = vec![];
+            for msg in script {
+                writeln!(writer, "{msg}").unwrap();
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                responses.push(line.trim_end().to_owned());
+            }
+            responses
+        });
+        (addr, handle)
+    }
+
+    struct TsumogiriAgent;
+
+    impl MjaiAgent for TsumogiriAgent {
+        fn react(&mut self, _: &Event, state: &PlayerState) -> Result<Option<Event>> {
+            if !state.last_cans().can_discard {
+                return Ok(None);
+            }
+            let pai = state.last_self_tsumo().context("no tsumo")?;
+            Ok(Some(Event::Dahai {
+                actor: state.player_id(),
+                pai,
+                tsumogiri: true,
+            }))
+        }
+    }
+
+    /// Discards an E whenever it gets an event.
+    struct EagerAgent;
+
+    impl MjaiAgent for EagerAgent {
+        fn react(&mut self, _: &Event, state: &PlayerState) -> Result<Option<Event>> {
+            Ok(Some(Event::Dahai {
+                actor: state.player_id(),
+                pai: t!(E),
+                tsumogiri: false,
+            }))
+        }
+    }
+
+    /// Riichis whenever it can, and always discards the 1m otherwise, even
+    /// after the riichi.
+    struct StubbornAgent;
+
+    impl MjaiAgent for StubbornAgent {
+        fn react(&mut self, _: &Event, state: &PlayerState) -> Result<Option<Event>> {
+            let actor = state.player_id();
+            let cans = state.last_cans();
+            if cans.can_riichi {
+                return Ok(Some(Event::Reach { actor }));
+            }
+            if !cans.can_discard {
+                return Ok(None);
+            }
+            Ok(Some(Event::Dahai {
+                actor,
+                pai: t!(1m),
+                tsumogiri: false,
+            }))
+        }
+    }
+
+    /// Tsumogiris like `TsumogiriAgent`, recording the events it is given.
+    #[derive(Default)]
+    struct RecordingAgent {
+        reacted: Vec<Event>,
+        fast_forwarded: Vec<Event>,
+    }
+
+    impl MjaiAgent for RecordingAgent {
+        fn react(&mut self, event: &Event, state: &PlayerState) -> Result<Option<Event>> {
+            self.reacted.push(event.clone());
+            TsumogiriAgent.react(event, state)
+        }
+
+        fn fast_forward(&mut self, event: &Event, _: &PlayerState) -> Result<()> {
+            self.fast_forwarded.push(event.clone());
+            Ok(())
+        }
+    }
+
+    fn parse(line: &str) -> Event {
+        json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn scripted_kyoku() {
+        let (addr, server) = serve(SCRIPT);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        client.run(&mut TsumogiriAgent).unwrap();
+
+        let responses = server.join().unwrap();
+        assert_eq!(responses.len(), SCRIPT.len());
+
+        let join: Value = json::from_str(&responses[0]).unwrap();
+        assert_eq!(join["type"], "join");
+        assert_eq!(join["name"], "bot");
+        assert_eq!(join["room"], "default");
+
+        let events: Vec<Event> = responses[1..]
+            .iter()
+            .map(|r| json::from_str(r).unwrap())
+            .collect();
+        for (idx, ev) in events.iter().enumerate() {
+            if idx == 2 {
+                assert_eq!(
+                    *ev,
+                    Event::Dahai {
+                        actor: 0,
+                        pai: t!(C),
+                        tsumogiri: true,
+                    },
+                );
+            } else {
+                assert_eq!(*ev, Event::None);
+            }
+        }
+    }
+
+    #[test]
+    fn protocol_violation() {
+        let (addr, server) = serve(SCRIPT);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        client.run(&mut EagerAgent).unwrap();
+
+        // Only the E discarded at the tsumo is sent, every other reaction is
+        // made when the player cannot act, and is replaced by a pass.
+        let responses = server.join().unwrap();
+        assert_eq!(responses.len(), SCRIPT.len());
+        for (idx, response) in responses[1..].iter().enumerate() {
+            if idx == 2 {
+                assert_eq!(
+                    parse(response),
+                    Event::Dahai {
+                        actor: 0,
+                        pai: t!(E),
+                        tsumogiri: false,
+                    },
+                );
+            } else {
+                assert_eq!(parse(response), Event::None);
+            }
+        }
+        assert_eq!(client.decisions(), SCRIPT.len() as u64 - 1);
+        assert_eq!(client.violations(), SCRIPT.len() as u64 - 2);
+    }
+
+    #[test]
+    fn riichi_violation() {
+        let (addr, server) = serve(RIICHI);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        client.run(&mut StubbornAgent).unwrap();
+
+        let responses = server.join().unwrap();
+        assert_eq!(responses.len(), RIICHI.len());
+        let tsumogiri = |pai| Event::Dahai {
+            actor: 0,
+            pai,
+            tsumogiri: true,
+        };
+        for (idx, response) in responses[1..].iter().enumerate() {
+            let expected = match idx {
+                2 => Event::Reach { actor: 0 },
+                // The 1m breaks the tenpai of the riichi declaration.
+                3 => tsumogiri(t!(W)),
+                // Only the tsumo can be discarded after the riichi.
+                12 => tsumogiri(t!(C)),
+                _ => Event::None,
+            };
+            assert_eq!(parse(response), expected);
+        }
+        assert_eq!(client.decisions(), 3);
+        assert_eq!(client.violations(), 2);
+        assert!((client.violations_per_1000() - 2000. / 3.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reconnect_mid_turn() {
+        let dahai = Event::Dahai {
+            actor: 0,
+            pai: t!(C),
+            tsumogiri: true,
+        };
+        let mut agent = RecordingAgent::default();
+
+        let (addr, server) = serve(DISCONNECTED_MID_TURN);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        let err = client.run(&mut agent).unwrap_err();
+        assert!(err.to_string().contains("connection closed"));
+        let responses = server.join().unwrap();
+        assert_eq!(parse(responses.last().unwrap()), dahai);
+        assert_eq!(agent.reacted.len(), 3);
+
+        // The server has not received the dahai, so it asks again, and the
+        // same dahai is sent without asking the agent.
+        let (addr, server) = serve(RESUMED_MID_TURN);
+        client.reconnect(addr, "bot", "default").unwrap();
+        client.run(&mut agent).unwrap();
+        let responses = server.join().unwrap();
+        assert_eq!(responses.len(), RESUMED_MID_TURN.len());
+        assert_eq!(parse(&responses[1]), dahai);
+        assert!(responses[2..].iter().all(|r| parse(r) == Event::None));
+        assert_eq!(agent.reacted.len(), 3 + RESUMED_MID_TURN.len() - 2);
+        assert_eq!(agent.fast_forwarded, [parse(SCRIPT[2])]);
+
+        // A new client has never answered the tsumo, so the agent is asked.
+        let mut agent = RecordingAgent::default();
+        let (addr, server) = serve(JOINED_MID_TURN);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        client.run(&mut agent).unwrap();
+        let responses = server.join().unwrap();
+        assert_eq!(parse(&responses[1]), dahai);
+        assert_eq!(agent.reacted[0], parse(SCRIPT[3]));
+        assert_eq!(agent.fast_forwarded, [parse(SCRIPT[2])]);
+    }
+
+    #[test]
+    fn reconnect_in_call_window() {
+        let mut agent = RecordingAgent::default();
+
+        let (addr, server) = serve(DISCONNECTED_BEFORE_CALL);
+        let mut client = MjaiClient::connect(addr, "bot", "default").unwrap();
+        client.run(&mut agent).unwrap_err();
+        server.join().unwrap();
+        let reacted = agent.reacted.len();
+
+        // The discarded E can be ponned, which is only decided after the
+        // backlog has been fast-forwarded.
+        let (addr, server) = serve(RESUMED_IN_CALL_WINDOW);
+        client.reconnect(addr, "bot", "default").unwrap();
+        client.run(&mut agent).unwrap();
+        let responses = server.join().unwrap();
+        assert_eq!(responses.len(), RESUMED_IN_CALL_WINDOW.len());
+        assert!(responses[1..].iter().all(|r| parse(r) == Event::None));
+
+        let backlog: Vec<Event> = json::from_str(RESUMED_IN_CALL_WINDOW[1]).unwrap();
+        let (last, history) = backlog.split_last().unwrap();
+        assert_eq!(agent.fast_forwarded, history);
+        assert_eq!(agent.reacted[reacted], *last);
+        assert_eq!(
+            agent.reacted.len(),
+            reacted + RESUMED_IN_CALL_WINDOW.len() - 1,
+        );
+    }
+}
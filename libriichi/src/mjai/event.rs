@@ -2,9 +2,12 @@ use crate::tile::Tile;
 use std::error::Error;
 use std::fmt;
 
+use anyhow::{Context, Result};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
+use serde_json::{self as json, Value};
 use serde_with::{serde_as, skip_serializing_none, TryFromInto};
+use tinyvec::ArrayVec;
 
 /// Describes an event in mjai format.
 ///
@@ -34,8 +37,11 @@ pub enum Event {
     StartKyoku {
         bakaze: Tile,
         dora_marker: Tile,
-        /// Counts from 1
-        #[serde_as(deserialize_as = "TryFromInto<BoundedU8<1, 4>>")]
+        /// Counts from 1.
+        ///
+        /// It may exceed 4 with the same `bakaze` in logs that keep counting
+        /// when the oya repeats, for example E5.
+        #[serde_as(deserialize_as = "TryFromInto<BoundedU8<1, 255>>")]
         kyoku: u8,
         honba: u8,
         kyotaku: u8,
@@ -92,6 +98,12 @@ pub enum Event {
         actor: u8,
         consumed: [Tile; 4],
     },
+    /// Extracts a north tile as a dora in sanma.
+    Nukidora {
+        #[serde_as(deserialize_as = "TryFromInto<Actor>")]
+        actor: u8,
+        pai: Tile,
+    },
     Dora {
         dora_marker: Tile,
     },
@@ -116,12 +128,75 @@ pub enum Event {
         ura_markers: Option<Vec<Tile>>,
     },
     Ryukyoku {
+        #[serde(default)]
+        reason: Option<RyukyokuReason>,
         #[serde(default)]
         deltas: Option<[i32; 4]>,
+        /// Whether each player is tenpai, usually only given for an
+        /// exhaustive draw.
+        #[serde(default)]
+        tenpais: Option<[bool; 4]>,
     },
 
     EndKyoku,
     EndGame,
+
+    /// An event of a type not listed above, such as the annotations emitted
+    /// by some engines. It is only produced by `parse_line`, and ignored by
+    /// `PlayerState`.
+    #[serde(skip)]
+    Unknown(UnknownEvent),
+}
+
+/// The `type` tags of `Event`, except for `Unknown`.
+const EVENT_TYPES: &[&str] = &[
+    "none",
+    "start_game",
+    "start_kyoku",
+    "tsumo",
+    "dahai",
+    "chi",
+    "pon",
+    "daiminkan",
+    "kakan",
+    "ankan",
+    "nukidora",
+    "dora",
+    "reach",
+    "reach_accepted",
+    "hora",
+    "ryukyoku",
+    "end_kyoku",
+    "end_game",
+];
+
+/// The line an `Event::Unknown` is parsed from, kept as is so that it can be
+/// written back byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownEvent(String);
+
+/// The reason of a `ryukyoku`.
+///
+/// Any reason string not listed here is kept as is in `Other`, so that it
+/// survives a round trip.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum RyukyokuReason {
+    /// 荒牌平局, written as `fanpai` following the original mjai.
+    Exhaustive,
+    /// 流し満貫
+    NagashiMangan,
+    /// 九種九牌
+    Kyuushukyuuhai,
+    /// 四風連打
+    SuufonRenda,
+    /// 四槓散了
+    Suukaikan,
+    /// 四家立直
+    SuuchaRiichi,
+    /// 三家和
+    Sanchahou,
+    Other(String),
 }
 
 #[derive(Deserialize)]
@@ -152,6 +227,9 @@ pub struct Metadata {
     pub shanten: Option<i8>,
     pub at_furiten: Option<bool>,
     pub kan_select: Option<Box<Metadata>>,
+    /// Draws forced by `BoardState::stack_next_draws`, carried by a `none`
+    /// event. A log with it is not organic and must not be used as data.
+    pub stacked_draws: Option<Vec<Tile>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -173,12 +251,114 @@ impl Event {
             | Self::Daiminkan { actor, .. }
             | Self::Kakan { actor, .. }
             | Self::Ankan { actor, .. }
+            | Self::Nukidora { actor, .. }
             | Self::Reach { actor, .. }
             | Self::ReachAccepted { actor, .. }
             | Self::Hora { actor, .. } => Some(actor),
             _ => None,
         }
     }
+
+    /// Parses a line of mjai log.
+    ///
+    /// Some log converters put the oya's first tsumo into the oya's `tehais`
+    /// of `start_kyoku`, making it 14 tiles. In that case the line is split
+    /// into a standard 13-tile `start_kyoku` followed by an explicit `tsumo`,
+    /// so that both conventions produce identical states.
+    ///
+    /// A line with an unrecognized `type` gives an `Unknown` instead of an
+    /// error.
+    pub fn parse_line(line: &str) -> Result<ArrayVec<[Self; 2]>> {
+        let mut ret = ArrayVec::new();
+        match json::from_str(line) {
+            Ok(ev) => ret.push(ev),
+            Err(err) => {
+                let mut value: Value = json::from_str(line)?;
+                if matches!(value["type"].as_str(), Some(ty) if !EVENT_TYPES.contains(&ty)) {
+                    ret.push(Self::Unknown(UnknownEvent(line.to_owned())));
+                    return Ok(ret);
+                }
+                if value["type"] != "start_kyoku" {
+                    return Err(err.into());
+                }
+
+                let oya = value["oya"].as_u64().context("invalid oya")? as usize;
+                let oya_tehai = value["tehais"]
+                    .get_mut(oya)
+                    .and_then(Value::as_array_mut)
+                    .context("invalid tehais")?;
+                if oya_tehai.len() != 14 {
+                    return Err(err.into());
+                }
+                let pai = json::from_value(oya_tehai.pop().unwrap())?;
+
+                ret.push(json::from_value(value)?);
+                ret.push(Self::Tsumo {
+                    actor: oya as u8,
+                    pai,
+                });
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Serializes the event into a line of mjai log. An `Unknown` gives back
+    /// the exact line it is parsed from.
+    #[must_use]
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Unknown(unknown) => unknown.0.clone(),
+            _ => json::to_string(self).expect("failed to serialize the event"),
+        }
+    }
+}
+
+impl UnknownEvent {
+    #[inline]
+    #[must_use]
+    pub fn raw(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RyukyokuReason {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Exhaustive => "fanpai",
+            Self::NagashiMangan => "nagashi_mangan",
+            Self::Kyuushukyuuhai => "kyuushukyuuhai",
+            Self::SuufonRenda => "suufon_renda",
+            Self::Suukaikan => "suukaikan",
+            Self::SuuchaRiichi => "suucha_riichi",
+            Self::Sanchahou => "sanchahou",
+            Self::Other(s) => s,
+        }
+    }
+}
+
+impl From<String> for RyukyokuReason {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "fanpai" => Self::Exhaustive,
+            "nagashi_mangan" => Self::NagashiMangan,
+            "kyuushukyuuhai" => Self::Kyuushukyuuhai,
+            "suufon_renda" => Self::SuufonRenda,
+            "suukaikan" => Self::Suukaikan,
+            "suucha_riichi" => Self::SuuchaRiichi,
+            "sanchahou" => Self::Sanchahou,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<RyukyokuReason> for String {
+    fn from(reason: RyukyokuReason) -> Self {
+        match reason {
+            RyukyokuReason::Other(s) => s,
+            _ => reason.as_str().to_owned(),
+        }
+    }
 }
 
 impl<const MIN: u8, const MAX: u8> TryFrom<BoundedU8<MIN, MAX>> for u8 {
@@ -207,6 +387,19 @@ impl EventExt {
     pub const fn no_meta(event: Event) -> Self {
         Self { event, meta: None }
     }
+
+    /// Whether `line` is the marker left by `BoardState::stack_next_draws`.
+    ///
+    /// Only a `none` event is parsed again, so it is cheap to call on every
+    /// line of a log.
+    #[must_use]
+    pub fn is_stacked_draws_marker(line: &str, event: &Event) -> bool {
+        matches!(event, Event::None)
+            && json::from_str::<Self>(line)
+                .ok()
+                .and_then(|ev| ev.meta?.stacked_draws)
+                .is_some()
+    }
 }
 
 impl From<Event> for EventExt {
@@ -240,6 +433,9 @@ mod test {
             {"type":"hora","actor":3,"target":1,"deltas":[0,-8000,0,9000],"ura_markers":["4p"]}
             {"type":"hora","actor":3,"target":1}
             {"type":"ryukyoku","deltas":[0,1500,0,-1500]}
+            {"type":"ryukyoku","reason":"fanpai","deltas":[-1500,1500,1500,-1500],"tenpais":[false,true,true,false]}
+            {"type":"ryukyoku","reason":"kyuushukyuuhai","deltas":[0,0,0,0]}
+            {"type":"ryukyoku","reason":"some_local_rule"}
             {"type":"ryukyoku"}
             {"type":"end_kyoku"}
             {"type":"end_game"}
@@ -257,6 +453,31 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn ryukyoku_reason() {
+        let ev: Event = json::from_str(r#"{"type":"ryukyoku","reason":"suufon_renda"}"#).unwrap();
+        assert!(matches!(
+            ev,
+            Event::Ryukyoku {
+                reason: Some(RyukyokuReason::SuufonRenda),
+                ..
+            },
+        ));
+
+        let ev: Event = json::from_str(r#"{"type":"ryukyoku","reason":"yao9"}"#).unwrap();
+        assert!(matches!(
+            &ev,
+            Event::Ryukyoku {
+                reason: Some(RyukyokuReason::Other(s)),
+                ..
+            } if s == "yao9",
+        ));
+        assert_eq!(
+            json::to_string(&ev).unwrap(),
+            r#"{"type":"ryukyoku","reason":"yao9"}"#,
+        );
+    }
+
     #[test]
     fn bound_check() {
         let value = json! ({
@@ -297,6 +518,57 @@ mod test {
 
         let mut test_obj = obj;
         test_obj["kyoku"] = Value::Number(Number::from(5));
-        json::from_value::<Event>(Value::Object(test_obj)).unwrap_err();
+        json::from_value::<Event>(Value::Object(test_obj)).unwrap();
+    }
+
+    #[test]
+    fn oya_with_14_tiles() {
+        let line = r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s","5pr"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}"#;
+        let events = Event::parse_line(line).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], Event::StartKyoku { oya: 1, .. }));
+        assert_eq!(
+            events[1],
+            Event::Tsumo {
+                actor: 1,
+                pai: crate::t!(5pr),
+            },
+        );
+
+        // 14 tiles for a non-oya is still an error.
+        let line = line.replace(r#""oya":1"#, r#""oya":0"#);
+        Event::parse_line(&line).unwrap_err();
+
+        let events = Event::parse_line(r#"{"type":"tsumo","actor":0,"pai":"1m"}"#).unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn unknown_event() {
+        let line = r#"{ "type": "dora_owned_change", "doras": [1, 0, 0, 0], "actor": 2 }"#;
+        let events = Event::parse_line(line).unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            Event::Unknown(unknown) => assert_eq!(unknown.raw(), line),
+            ev => panic!("unexpected {ev:?}"),
+        }
+        assert_eq!(events[0].actor(), None);
+        assert_eq!(events[0].to_line(), line);
+
+        // Only the type is forgiven.
+        Event::parse_line(r#"{"type":"tsumo","actor":0}"#).unwrap_err();
+        Event::parse_line(r#"{"actor":0}"#).unwrap_err();
+        Event::parse_line(r#"{"type":0}"#).unwrap_err();
+        json::from_str::<Event>(line).unwrap_err();
+
+        for ty in EVENT_TYPES {
+            let err = json::from_value::<Event>(json::json!({ "type": ty })).err();
+            assert!(
+                !matches!(&err, Some(err) if err.to_string().contains("unknown variant")),
+                "{ty}",
+            );
+        }
+        let line = r#"{"type":"dahai","actor":0,"pai":"5mr","tsumogiri":false}"#;
+        assert_eq!(Event::parse_line(line).unwrap()[0].to_line(), line);
     }
 }
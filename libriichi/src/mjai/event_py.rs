@@ -0,0 +1,482 @@
+use super::Event;
+use crate::tile::{InvalidTile, Tile};
+use std::fmt;
+
+use pyo3::exceptions::{PyAttributeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// An mjai event for Python, built from a `dict` with `from_dict` and passed
+/// to `PlayerState.update_event` without going through a JSON line.
+///
+/// The fields are accessible as attributes, for example `event.actor`. Tiles
+/// are in mjai notation, such as `5mr` for the red 5m.
+#[pyclass(name = "Event")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventPy {
+    pub event: Event,
+}
+
+#[pymethods]
+impl EventPy {
+    /// Raises `TypeError` naming the field if any field is invalid.
+    #[staticmethod]
+    #[pyo3(text_signature = "(data, /)")]
+    fn from_dict(data: &PyDict) -> PyResult<Self> {
+        let fields = Fields(data);
+        let event = match fields.get::<&str>("type")? {
+            "none" => Event::None,
+            "start_game" => Event::StartGame {
+                names: fields.get_opt("names")?.unwrap_or_default(),
+                seed: fields.get_opt::<[u64; 2]>("seed")?.map(|[a, b]| (a, b)),
+            },
+            "start_kyoku" => Event::StartKyoku {
+                bakaze: fields.get("bakaze")?,
+                dora_marker: fields.get("dora_marker")?,
+                kyoku: fields.bounded("kyoku", 1, 255)?,
+                honba: fields.get("honba")?,
+                kyotaku: fields.get("kyotaku")?,
+                oya: fields.actor("oya")?,
+                scores: fields.get("scores")?,
+                tehais: fields.get("tehais")?,
+            },
+            "tsumo" => Event::Tsumo {
+                actor: fields.actor("actor")?,
+                pai: fields.get("pai")?,
+            },
+            "dahai" => Event::Dahai {
+                actor: fields.actor("actor")?,
+                pai: fields.get("pai")?,
+                tsumogiri: fields.get("tsumogiri")?,
+            },
+            "chi" => Event::Chi {
+                actor: fields.actor("actor")?,
+                target: fields.actor("target")?,
+                pai: fields.get("pai")?,
+                consumed: fields.get("consumed")?,
+            },
+            "pon" => Event::Pon {
+                actor: fields.actor("actor")?,
+                target: fields.actor("target")?,
+                pai: fields.get("pai")?,
+                consumed: fields.get("consumed")?,
+            },
+            "daiminkan" => Event::Daiminkan {
+                actor: fields.actor("actor")?,
+                target: fields.actor("target")?,
+                pai: fields.get("pai")?,
+                consumed: fields.get("consumed")?,
+            },
+            "kakan" => Event::Kakan {
+                actor: fields.actor("actor")?,
+                pai: fields.get("pai")?,
+                consumed: fields.get("consumed")?,
+            },
+            "ankan" => Event::Ankan {
+                actor: fields.actor("actor")?,
+                consumed: fields.get("consumed")?,
+            },
+            "nukidora" => Event::Nukidora {
+                actor: fields.actor("actor")?,
+                pai: fields.get("pai")?,
+            },
+            "dora" => Event::Dora {
+                dora_marker: fields.get("dora_marker")?,
+            },
+            "reach" => Event::Reach {
+                actor: fields.actor("actor")?,
+            },
+            "reach_accepted" => Event::ReachAccepted {
+                actor: fields.actor("actor")?,
+            },
+            "hora" => Event::Hora {
+                actor: fields.actor("actor")?,
+                target: fields.actor("target")?,
+                deltas: fields.get_opt("deltas")?,
+                ura_markers: fields.get_opt("ura_markers")?,
+            },
+            "ryukyoku" => Event::Ryukyoku {
+                reason: fields.get_opt::<String>("reason")?.map(Into::into),
+                deltas: fields.get_opt("deltas")?,
+                tenpais: fields.get_opt("tenpais")?,
+            },
+            "end_kyoku" => Event::EndKyoku,
+            "end_game" => Event::EndGame,
+            ty => return Err(invalid_field("type", format!("unknown variant `{ty}`"))),
+        };
+        Ok(Self { event })
+    }
+
+    /// Returns the event as a `dict` in the same shape as its mjai line, in
+    /// which the absent optional fields are omitted.
+    #[pyo3(text_signature = "($self, /)")]
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(dict.set_item(stringify!($field), $field)?;)*
+            };
+        }
+        macro_rules! set_opt {
+            ($($field:ident),*) => {
+                $(if let Some($field) = $field {
+                    dict.set_item(stringify!($field), $field)?;
+                })*
+            };
+        }
+
+        let ty = match &self.event {
+            Event::None => "none",
+            Event::StartGame { names, seed } => {
+                set!(names);
+                if let Some((nonce, key)) = seed {
+                    dict.set_item("seed", [nonce, key])?;
+                }
+                "start_game"
+            }
+            Event::StartKyoku {
+                bakaze,
+                dora_marker,
+                kyoku,
+                honba,
+                kyotaku,
+                oya,
+                scores,
+                tehais,
+            } => {
+                set!(
+                    bakaze,
+                    dora_marker,
+                    kyoku,
+                    honba,
+                    kyotaku,
+                    oya,
+                    scores,
+                    tehais
+                );
+                "start_kyoku"
+            }
+            Event::Tsumo { actor, pai } => {
+                set!(actor, pai);
+                "tsumo"
+            }
+            Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            } => {
+                set!(actor, pai, tsumogiri);
+                "dahai"
+            }
+            Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                set!(actor, target, pai, consumed);
+                "chi"
+            }
+            Event::Pon {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                set!(actor, target, pai, consumed);
+                "pon"
+            }
+            Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                set!(actor, target, pai, consumed);
+                "daiminkan"
+            }
+            Event::Kakan {
+                actor,
+                pai,
+                consumed,
+            } => {
+                set!(actor, pai, consumed);
+                "kakan"
+            }
+            Event::Ankan { actor, consumed } => {
+                set!(actor, consumed);
+                "ankan"
+            }
+            Event::Nukidora { actor, pai } => {
+                set!(actor, pai);
+                "nukidora"
+            }
+            Event::Dora { dora_marker } => {
+                set!(dora_marker);
+                "dora"
+            }
+            Event::Reach { actor } => {
+                set!(actor);
+                "reach"
+            }
+            Event::ReachAccepted { actor } => {
+                set!(actor);
+                "reach_accepted"
+            }
+            Event::Hora {
+                actor,
+                target,
+                deltas,
+                ura_markers,
+            } => {
+                set!(actor, target);
+                set_opt!(deltas, ura_markers);
+                "hora"
+            }
+            Event::Ryukyoku {
+                reason,
+                deltas,
+                tenpais,
+            } => {
+                if let Some(reason) = reason {
+                    dict.set_item("reason", reason.as_str())?;
+                }
+                set_opt!(deltas, tenpais);
+                "ryukyoku"
+            }
+            Event::EndKyoku => "end_kyoku",
+            Event::EndGame => "end_game",
+            // Only `Event::parse_line` produces it, from a line of an
+            // unknown type, which is all there is to it.
+            Event::Unknown(unknown) => {
+                return Ok(py
+                    .import("json")?
+                    .call_method1("loads", (unknown.raw(),))?
+                    .downcast()?);
+            }
+        };
+        dict.set_item("type", ty)?;
+        Ok(dict)
+    }
+
+    /// Fields omitted in the mjai line, like the absent `deltas` of a
+    /// `hora`, raise `AttributeError` as well.
+    fn __getattr__(&self, name: &str, py: Python<'_>) -> PyResult<PyObject> {
+        self.to_dict(py)?
+            .get_item(name)
+            .map(Into::into)
+            .ok_or_else(|| PyAttributeError::new_err(format!("no field `{name}`")))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Event({})", self.event.to_line())
+    }
+}
+
+impl From<Event> for EventPy {
+    fn from(event: Event) -> Self {
+        Self { event }
+    }
+}
+
+/// The fields of an event `dict`. A failure on a field is raised as
+/// `TypeError` with the name of the field.
+struct Fields<'py>(&'py PyDict);
+
+impl<'py> Fields<'py> {
+    fn get<T: FromPyObject<'py>>(&self, name: &str) -> PyResult<T> {
+        self.0
+            .get_item(name)
+            .ok_or_else(|| PyTypeError::new_err(format!("missing field `{name}`")))?
+            .extract()
+            .map_err(|err| invalid_field(name, err))
+    }
+
+    /// An absent field and `None` are both taken as `None`.
+    fn get_opt<T: FromPyObject<'py>>(&self, name: &str) -> PyResult<Option<T>> {
+        match self.0.get_item(name) {
+            Some(value) if !value.is_none() => value
+                .extract()
+                .map(Some)
+                .map_err(|err| invalid_field(name, err)),
+            _ => Ok(None),
+        }
+    }
+
+    fn bounded(&self, name: &str, min: u8, max: u8) -> PyResult<u8> {
+        let value = self.get(name)?;
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            let msg = format!("{value} is not in range [{min}, {max}]");
+            Err(invalid_field(name, msg))
+        }
+    }
+
+    fn actor(&self, name: &str) -> PyResult<u8> {
+        self.bounded(name, 0, 3)
+    }
+}
+
+fn invalid_field(name: &str, err: impl fmt::Display) -> PyErr {
+    PyTypeError::new_err(format!("invalid field `{name}`: {err}"))
+}
+
+impl<'source> FromPyObject<'source> for Tile {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        ob.extract::<&str>()?
+            .parse()
+            .map_err(|err: InvalidTile| PyValueError::new_err(err.to_string()))
+    }
+}
+
+impl ToPyObject for Tile {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        self.to_string().to_object(py)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::PlayerState;
+
+    use serde_json as json;
+
+    fn from_dict<'py>(py: Python<'py>, dict: &'py PyAny) -> PyResult<&'py PyAny> {
+        py.get_type::<EventPy>().call_method1("from_dict", (dict,))
+    }
+
+    #[test]
+    fn every_type() {
+        let lines = r#"
+            {"type":"none"}
+            {"type":"start_game","names":["a","b","c","d"],"seed":[1,2]}
+            {"type":"start_kyoku","bakaze":"S","dora_marker":"5sr","kyoku":2,"honba":1,"kyotaku":1,"oya":1,"scores":[25000,25000,24000,25000],"tehais":[["1m","2m","3m","4m","5mr","6m","7m","8m","9m","1p","2p","3p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":1,"pai":"5pr"}
+            {"type":"dahai","actor":1,"pai":"5pr","tsumogiri":true}
+            {"type":"chi","actor":2,"target":1,"pai":"6s","consumed":["5sr","7s"]}
+            {"type":"pon","actor":0,"target":2,"pai":"C","consumed":["C","C"]}
+            {"type":"daiminkan","actor":2,"target":0,"pai":"5p","consumed":["5pr","5p","5p"]}
+            {"type":"kakan","actor":3,"pai":"5mr","consumed":["5m","5m","5m"]}
+            {"type":"ankan","actor":0,"consumed":["9m","9m","9m","9m"]}
+            {"type":"nukidora","actor":1,"pai":"N"}
+            {"type":"dora","dora_marker":"3s"}
+            {"type":"reach","actor":1}
+            {"type":"reach_accepted","actor":1}
+            {"type":"hora","actor":3,"target":1,"deltas":[0,-8000,0,9000],"ura_markers":["4p"]}
+            {"type":"hora","actor":3,"target":3}
+            {"type":"ryukyoku","reason":"fanpai","deltas":[-1500,1500,1500,-1500],"tenpais":[false,true,true,false]}
+            {"type":"ryukyoku"}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#;
+        Python::with_gil(|py| {
+            let loads = py.import("json").unwrap().getattr("loads").unwrap();
+            for line in lines.trim().lines().map(str::trim) {
+                let dict = loads.call1((line,)).unwrap();
+                let ev = from_dict(py, dict).unwrap();
+                let expected: Event = json::from_str(line).unwrap();
+                assert_eq!(ev.extract::<EventPy>().unwrap().event, expected, "{line}");
+
+                let to_dict = ev.call_method0("to_dict").unwrap();
+                assert!(to_dict.eq(dict).unwrap(), "{line}");
+                for (key, value) in dict.downcast::<PyDict>().unwrap() {
+                    let attr = ev.getattr(key.extract::<&str>().unwrap()).unwrap();
+                    assert!(attr.eq(value).unwrap(), "{line}");
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn getattr() {
+        Python::with_gil(|py| {
+            let dict = py
+                .eval(
+                    "{'type': 'dahai', 'actor': 2, 'pai': '5sr', 'tsumogiri': False}",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let ev = from_dict(py, dict).unwrap();
+            let get = |name: &str| ev.getattr(name);
+
+            assert_eq!(get("actor").unwrap().extract::<u8>().unwrap(), 2);
+            assert_eq!(get("pai").unwrap().extract::<&str>().unwrap(), "5sr");
+            assert!(!get("tsumogiri").unwrap().extract::<bool>().unwrap());
+            let err = get("target").unwrap_err();
+            assert!(err.is_instance_of::<PyAttributeError>(py));
+
+            let dict = py
+                .eval("{'type': 'hora', 'actor': 0, 'target': 0}", None, None)
+                .unwrap();
+            let ev = from_dict(py, dict).unwrap();
+            let err = ev.getattr("deltas").unwrap_err();
+            assert!(err.is_instance_of::<PyAttributeError>(py));
+        });
+    }
+
+    #[test]
+    fn field_errors() {
+        Python::with_gil(|py| {
+            let err = |dict: &str| {
+                let dict = py.eval(dict, None, None).unwrap();
+                let err = from_dict(py, dict).unwrap_err();
+                assert!(err.is_instance_of::<PyTypeError>(py));
+                err.value(py).to_string()
+            };
+
+            let msg = err("{'type': 'dahai', 'actor': 0, 'pai': 5, 'tsumogiri': True}");
+            assert!(msg.starts_with("invalid field `pai`"), "{msg}");
+            let msg = err("{'type': 'dahai', 'actor': 0, 'pai': '5x', 'tsumogiri': True}");
+            assert!(msg.starts_with("invalid field `pai`"), "{msg}");
+            let msg = err("{'type': 'dahai', 'actor': 4, 'pai': 'E', 'tsumogiri': True}");
+            assert!(msg.starts_with("invalid field `actor`"), "{msg}");
+            let msg = err("{'type': 'dahai', 'actor': 0, 'pai': 'E', 'tsumogiri': 'yes'}");
+            assert!(msg.starts_with("invalid field `tsumogiri`"), "{msg}");
+            let msg =
+                err("{'type': 'chi', 'actor': 1, 'target': 0, 'pai': '3m', 'consumed': ['1m']}");
+            assert!(msg.starts_with("invalid field `consumed`"), "{msg}");
+            let msg =
+                err("{'type': 'start_kyoku', 'bakaze': 'E', 'dora_marker': '1m', 'kyoku': 0}");
+            assert!(msg.starts_with("invalid field `kyoku`"), "{msg}");
+
+            let msg = err("{'type': 'dahai', 'actor': 0, 'pai': 'E'}");
+            assert!(msg.contains("missing field `tsumogiri`"), "{msg}");
+            let msg = err("{'type': 'foo'}");
+            assert!(msg.contains("unknown variant"), "{msg}");
+            let msg = err("{'actor': 0}");
+            assert!(msg.contains("missing field `type`"), "{msg}");
+
+            let list = py.eval("['dahai']", None, None).unwrap();
+            let err = from_dict(py, list).unwrap_err();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn update_event() {
+        let lines = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5mr","6m","7m","8m","9m","2p","3p","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"N"}
+        "#;
+        Python::with_gil(|py| {
+            let loads = py.import("json").unwrap().getattr("loads").unwrap();
+            let by_event = PyCell::new(py, PlayerState::new(0)).unwrap();
+            let by_json = PyCell::new(py, PlayerState::new(0)).unwrap();
+            for line in lines.trim().lines().map(str::trim) {
+                let ev = from_dict(py, loads.call1((line,)).unwrap()).unwrap();
+                let cans = by_event.call_method1("update_event", (ev,)).unwrap();
+                let expected = by_json.call_method1("update", (line,)).unwrap();
+                assert!(cans
+                    .getattr("can_act")
+                    .unwrap()
+                    .eq(expected.getattr("can_act").unwrap())
+                    .unwrap());
+            }
+            assert_eq!(by_event.borrow().tehai(), by_json.borrow().tehai());
+            assert!(by_event.borrow().akas_in_hand()[0]);
+        });
+    }
+}
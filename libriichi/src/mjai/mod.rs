@@ -1,7 +1,13 @@
 mod bot;
+mod client;
 mod event;
+mod event_py;
 
-pub use event::{Event, EventExt, EventWithCanAct, Metadata, OutOfBoundError};
+pub use client::{MjaiAgent, MjaiClient};
+pub use event::{
+    Event, EventExt, EventWithCanAct, Metadata, OutOfBoundError, RyukyokuReason, UnknownEvent,
+};
+pub use event_py::EventPy;
 
 use crate::py_helper::add_submodule;
 use bot::Bot;
@@ -11,5 +17,6 @@ use pyo3::prelude::*;
 pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule) -> PyResult<()> {
     let m = PyModule::new(py, "mjai")?;
     m.add_class::<Bot>()?;
+    m.add_class::<EventPy>()?;
     add_submodule(py, prefix, super_mod, m)
 }
@@ -0,0 +1,259 @@
+//! Human readable names of tiles, yakus and limits in English, Japanese and
+//! romaji.
+
+use crate::algo::point::Limit;
+use crate::algo::yaku::Yaku;
+use crate::py_helper::add_submodule;
+use crate::tile::Tile;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use pyo3::prelude::*;
+use serde_json as json;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+    Romaji,
+}
+
+impl Lang {
+    pub const ALL: [Self; 3] = [Self::En, Self::Ja, Self::Romaji];
+}
+
+impl FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lang = match s {
+            "en" => Self::En,
+            "ja" => Self::Ja,
+            "romaji" => Self::Romaji,
+            _ => bail!("unknown language {s}, expected one of en, ja or romaji"),
+        };
+        Ok(lang)
+    }
+}
+
+/// Indexed by tile id, in the order of en, ja and romaji.
+const TILE_NAMES: [[&str; 3]; 38] = [
+    ["1 man", "一萬", "iiwan"],
+    ["2 man", "二萬", "ryanwan"],
+    ["3 man", "三萬", "sanwan"],
+    ["4 man", "四萬", "suuwan"],
+    ["5 man", "五萬", "uuwan"],
+    ["6 man", "六萬", "roowan"],
+    ["7 man", "七萬", "chiiwan"],
+    ["8 man", "八萬", "paawan"],
+    ["9 man", "九萬", "chuuwan"],
+    ["1 pin", "一筒", "iipin"],
+    ["2 pin", "二筒", "ryanpin"],
+    ["3 pin", "三筒", "sanpin"],
+    ["4 pin", "四筒", "suupin"],
+    ["5 pin", "五筒", "uupin"],
+    ["6 pin", "六筒", "roopin"],
+    ["7 pin", "七筒", "chiipin"],
+    ["8 pin", "八筒", "paapin"],
+    ["9 pin", "九筒", "chuupin"],
+    ["1 sou", "一索", "iisou"],
+    ["2 sou", "二索", "ryansou"],
+    ["3 sou", "三索", "sansou"],
+    ["4 sou", "四索", "suusou"],
+    ["5 sou", "五索", "uusou"],
+    ["6 sou", "六索", "roosou"],
+    ["7 sou", "七索", "chiisou"],
+    ["8 sou", "八索", "paasou"],
+    ["9 sou", "九索", "chuusou"],
+    ["east", "東", "ton"],
+    ["south", "南", "nan"],
+    ["west", "西", "shaa"],
+    ["north", "北", "pei"],
+    ["white dragon", "白", "haku"],
+    ["green dragon", "發", "hatsu"],
+    ["red dragon", "中", "chun"],
+    ["red 5 man", "赤五萬", "aka uuwan"],
+    ["red 5 pin", "赤五筒", "aka uupin"],
+    ["red 5 sou", "赤五索", "aka uusou"],
+    ["unknown", "不明", "fumei"],
+];
+
+impl Tile {
+    #[inline]
+    #[must_use]
+    pub const fn name(self, lang: Lang) -> &'static str {
+        TILE_NAMES[self.as_usize()][lang as usize]
+    }
+}
+
+impl Yaku {
+    #[must_use]
+    pub const fn name(self, lang: Lang) -> &'static str {
+        let names = match self {
+            Self::Riichi => ["riichi", "立直", "riichi"],
+            Self::DoubleRiichi => ["double riichi", "両立直", "daburu riichi"],
+            Self::Ippatsu => ["ippatsu", "一発", "ippatsu"],
+            Self::MenzenTsumo => ["fully concealed hand", "門前清自摸和", "menzen tsumo"],
+            Self::Haitei => ["under the sea", "海底摸月", "haitei raoyue"],
+            Self::Houtei => ["under the river", "河底撈魚", "houtei raoyui"],
+            Self::Rinshan => ["after a kan", "嶺上開花", "rinshan kaihou"],
+            Self::Chankan => ["robbing a kan", "槍槓", "chankan"],
+            Self::Pinfu => ["pinfu", "平和", "pinfu"],
+            Self::Tanyao => ["all simples", "断幺九", "tanyao"],
+            Self::Iipeikou => ["pure double sequence", "一盃口", "iipeikou"],
+            Self::Bakaze => ["prevalent wind", "役牌:場風牌", "bakaze"],
+            Self::Jikaze => ["seat wind", "役牌:自風牌", "jikaze"],
+            Self::Haku => ["white dragon", "役牌:白", "haku"],
+            Self::Hatsu => ["green dragon", "役牌:發", "hatsu"],
+            Self::Chun => ["red dragon", "役牌:中", "chun"],
+            Self::Chanta => ["half outside hand", "混全帯幺九", "chanta"],
+            Self::Ittsuu => ["pure straight", "一気通貫", "ittsuu"],
+            Self::SanshokuDoujun => ["mixed triple sequence", "三色同順", "sanshoku doujun"],
+            Self::SanshokuDoukou => ["triple triplets", "三色同刻", "sanshoku doukou"],
+            Self::Sankantsu => ["three quads", "三槓子", "sankantsu"],
+            Self::Toitoi => ["all triplets", "対々和", "toitoi"],
+            Self::Sanankou => ["three concealed triplets", "三暗刻", "sanankou"],
+            Self::Shousangen => ["little three dragons", "小三元", "shousangen"],
+            Self::Honroutou => ["all terminals and honors", "混老頭", "honroutou"],
+            Self::Chiitoitsu => ["seven pairs", "七対子", "chiitoitsu"],
+            Self::Junchan => ["fully outside hand", "純全帯幺九", "junchan"],
+            Self::Honitsu => ["half flush", "混一色", "honitsu"],
+            Self::Ryanpeikou => ["twice pure double sequence", "二盃口", "ryanpeikou"],
+            Self::Chinitsu => ["full flush", "清一色", "chinitsu"],
+            Self::Tenhou => ["blessing of heaven", "天和", "tenhou"],
+            Self::Chiihou => ["blessing of earth", "地和", "chiihou"],
+            Self::Kokushi => ["thirteen orphans", "国士無双", "kokushi musou"],
+            Self::Chuuren => ["nine gates", "九蓮宝燈", "chuuren poutou"],
+            Self::Suuankou => ["four concealed triplets", "四暗刻", "suuankou"],
+            Self::Suukantsu => ["four quads", "四槓子", "suukantsu"],
+            Self::Daisangen => ["big three dragons", "大三元", "daisangen"],
+            Self::Shousuushii => ["little four winds", "小四喜", "shousuushii"],
+            Self::Daisuushii => ["big four winds", "大四喜", "daisuushii"],
+            Self::Tsuuiisou => ["all honors", "字一色", "tsuuiisou"],
+            Self::Ryuuiisou => ["all green", "緑一色", "ryuuiisou"],
+            Self::Chinroutou => ["all terminals", "清老頭", "chinroutou"],
+            Self::Dora => ["dora", "ドラ", "dora"],
+            Self::AkaDora => ["red five", "赤ドラ", "aka dora"],
+            Self::UraDora => ["ura dora", "裏ドラ", "ura dora"],
+            Self::NukiDora => ["north dora", "抜きドラ", "nuki dora"],
+        };
+        names[lang as usize]
+    }
+}
+
+impl Limit {
+    #[must_use]
+    pub const fn name(self, lang: Lang) -> &'static str {
+        let names = match self {
+            Self::Mangan => ["mangan", "満貫", "mangan"],
+            Self::Haneman => ["haneman", "跳満", "haneman"],
+            Self::Baiman => ["baiman", "倍満", "baiman"],
+            Self::Sanbaiman => ["sanbaiman", "三倍満", "sanbaiman"],
+            Self::Yakuman => ["yakuman", "役満", "yakuman"],
+        };
+        names[lang as usize]
+    }
+}
+
+/// `tile` is in mjai notation, such as `5mr`.
+#[pyfunction]
+#[pyo3(text_signature = "(tile, lang, /)")]
+fn tile_name(tile: &str, lang: &str) -> Result<&'static str> {
+    Ok(tile.parse::<Tile>()?.name(lang.parse()?))
+}
+
+/// `yaku` is the snake_case name used in the yaku statistics, such as
+/// `menzen_tsumo`.
+#[pyfunction]
+#[pyo3(text_signature = "(yaku, lang, /)")]
+fn yaku_name(yaku: &str, lang: &str) -> Result<&'static str> {
+    let yaku: Yaku = json::from_value(json::Value::String(yaku.to_owned()))?;
+    Ok(yaku.name(lang.parse()?))
+}
+
+/// Returns `None` if the hand is below mangan.
+#[pyfunction]
+#[pyo3(text_signature = "(fu, han, lang, /)")]
+fn limit_name(fu: u8, han: u8, lang: &str) -> Result<Option<&'static str>> {
+    let lang = lang.parse()?;
+    Ok(Limit::of(fu, han).map(|l| l.name(lang)))
+}
+
+pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule) -> PyResult<()> {
+    let m = PyModule::new(py, "names")?;
+    m.add_function(wrap_pyfunction!(tile_name, m)?)?;
+    m.add_function(wrap_pyfunction!(yaku_name, m)?)?;
+    m.add_function(wrap_pyfunction!(limit_name, m)?)?;
+    add_submodule(py, prefix, super_mod, m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::t;
+    use std::collections::HashSet;
+
+    #[test]
+    fn full_coverage() {
+        for lang in Lang::ALL {
+            let tiles = (0..38)
+                .map(|id| Tile::try_from(id).unwrap().name(lang))
+                .collect::<HashSet<_>>();
+            assert_eq!(tiles.len(), 38);
+
+            let yakus = Yaku::ALL
+                .iter()
+                .map(|y| y.name(lang))
+                .collect::<HashSet<_>>();
+            assert_eq!(yakus.len(), Yaku::ALL.len());
+
+            let limits = Limit::ALL
+                .iter()
+                .map(|l| l.name(lang))
+                .collect::<HashSet<_>>();
+            assert_eq!(limits.len(), Limit::ALL.len());
+
+            assert!(tiles
+                .iter()
+                .chain(&yakus)
+                .chain(&limits)
+                .all(|s| !s.is_empty() && s.trim() == *s));
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(t!(1m).name(Lang::En), "1 man");
+        assert_eq!(t!(1m).name(Lang::Ja), "一萬");
+        assert_eq!(t!(1m).name(Lang::Romaji), "iiwan");
+        assert_eq!(t!(5pr).name(Lang::Ja), "赤五筒");
+        assert_eq!(t!(N).name(Lang::Romaji), "pei");
+        assert_eq!(t!(F).name(Lang::Ja), "發");
+        assert_eq!(Yaku::Riichi.name(Lang::Ja), "立直");
+        assert_eq!(Yaku::Kokushi.name(Lang::Romaji), "kokushi musou");
+        assert_eq!(Limit::Haneman.name(Lang::En), "haneman");
+        assert_eq!(Limit::Sanbaiman.name(Lang::Ja), "三倍満");
+
+        assert_eq!("ja".parse::<Lang>().unwrap(), Lang::Ja);
+        "jp".parse::<Lang>().unwrap_err();
+        assert_eq!(tile_name("5sr", "romaji").unwrap(), "aka uusou");
+        assert_eq!(yaku_name("menzen_tsumo", "ja").unwrap(), "門前清自摸和");
+        yaku_name("nagashi_mangan", "en").unwrap_err();
+        assert_eq!(limit_name(30, 6, "ja").unwrap(), Some("跳満"));
+        assert_eq!(limit_name(30, 2, "en").unwrap(), None);
+    }
+
+    #[test]
+    fn limit_of() {
+        assert_eq!(Limit::of(30, 3), None);
+        assert_eq!(Limit::of(70, 3), Some(Limit::Mangan));
+        assert_eq!(Limit::of(30, 4), None);
+        assert_eq!(Limit::of(40, 4), Some(Limit::Mangan));
+        assert_eq!(Limit::of(0, 5), Some(Limit::Mangan));
+        assert_eq!(Limit::of(0, 7), Some(Limit::Haneman));
+        assert_eq!(Limit::of(0, 10), Some(Limit::Baiman));
+        assert_eq!(Limit::of(0, 12), Some(Limit::Sanbaiman));
+        assert_eq!(Limit::of(0, 13), Some(Limit::Yakuman));
+    }
+}
@@ -0,0 +1,104 @@
+//! Complete games played in-process by Rust agents, producing mjai logs.
+//!
+//! This is a thin wrapper of the arena, which does the dealing, the rinshan
+//! draws, the abortive and exhaustive draws, the payments and the carryover
+//! of honba and kyotaku following Tenhou's rule, see `arena::Board`.
+
+use crate::agent::{Agent, BatchAgent, BatchifiedAgent};
+use crate::arena::{BatchGame, Index};
+use crate::mjai::Event;
+use crate::state::Rules;
+
+use anyhow::Result;
+
+/// Plays a hanchan seeded by `seed` with an agent from `new_agent` at each
+/// seat, and returns its complete log from `start_game` to `end_game`.
+///
+/// The walls are generated deterministically from `seed`, with the akas
+/// decided by `rules.aka`, so the same agents always play the same game.
+pub fn simulate_game<F, A>(new_agent: F, seed: (u64, u64), rules: Rules) -> Result<Vec<Event>>
+where
+    F: FnMut(u8) -> Result<A>,
+    A: Agent + 'static,
+{
+    let mut agents: [Box<dyn BatchAgent>; 1] =
+        [Box::new(BatchifiedAgent::new(new_agent, &[0, 1, 2, 3])?)];
+    let indexes = [[0, 1, 2, 3].map(|player_id_idx| Index {
+        agent_idx: 0,
+        player_id_idx,
+    })];
+    let batch_game = BatchGame {
+        init_scores: [rules.mode.starting_score(); 4],
+        rules,
+        ..BatchGame::tenhou_hanchan(true)
+    };
+
+    let results = batch_game.run(&mut agents, &indexes, &[seed])?;
+    Ok(results[0].events())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{RuleBased, Tsumogiri};
+    use crate::state::{AkaRule, PlayerState};
+
+    fn replay(events: &[Event], rules: Rules) {
+        let mut states = [0, 1, 2, 3].map(|id| PlayerState::with_rules(id, rules));
+        for ev in events {
+            for state in &mut states {
+                state.update(ev);
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let a = simulate_game(|id| Ok(RuleBased(id)), (1009, 0), Rules::default()).unwrap();
+        let b = simulate_game(|id| Ok(RuleBased(id)), (1009, 0), Rules::default()).unwrap();
+        assert_eq!(
+            a.iter().map(Event::to_line).collect::<Vec<_>>(),
+            b.iter().map(Event::to_line).collect::<Vec<_>>(),
+        );
+        assert!(matches!(a.first(), Some(Event::StartGame { .. })));
+        assert!(matches!(a.last(), Some(Event::EndGame)));
+        replay(&a, Rules::default());
+    }
+
+    #[test]
+    fn aka_rules() {
+        for aka in [AkaRule::None, AkaRule::Three, AkaRule::FourWithDoublePin] {
+            let rules = Rules {
+                aka,
+                ..Default::default()
+            };
+            let events = simulate_game(|id| Ok(Tsumogiri(id)), (1021, 0), rules).unwrap();
+            replay(&events, rules);
+
+            // Every tile of the first kyoku is in either a haipai or a tsumo
+            // or the revealed dora indicator, none of which may hold more
+            // akas than the rule allows.
+            let mut akas = 0;
+            for ev in events
+                .iter()
+                .take_while(|ev| !matches!(ev, Event::EndKyoku))
+            {
+                match ev {
+                    Event::StartKyoku {
+                        tehais,
+                        dora_marker,
+                        ..
+                    } => {
+                        akas += tehais.iter().flatten().filter(|t| t.is_aka()).count();
+                        akas += usize::from(dora_marker.is_aka());
+                    }
+                    Event::Tsumo { pai, .. } | Event::Dora { dora_marker: pai } => {
+                        akas += usize::from(pai.is_aka());
+                    }
+                    _ => (),
+                }
+            }
+            assert!(akas <= aka.count() as usize, "{aka:?}: {akas} akas");
+        }
+    }
+}
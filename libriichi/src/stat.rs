@@ -1,18 +1,27 @@
 use crate::algo::point::Point;
+use crate::algo::shanten;
+use crate::algo::yaku::Yaku;
+use crate::arena::LeftoverKyotaku;
+use crate::logs::{find_logs, open_log, parse_log};
 use crate::mjai::Event;
 use crate::py_helper::add_submodule;
+use crate::state::PlayerState;
+use crate::t;
+use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
+use std::collections::BTreeMap;
 use std::fmt;
-use std::fs::File;
 use std::io::prelude::*;
+use std::iter::Sum;
+use std::ops::Add;
+use std::path::Path;
 
 use anyhow::{bail, Context, Result};
 use derive_more::{Add, AddAssign, Sum};
-use flate2::read::GzDecoder;
-use glob::glob;
 use indicatif::{ProgressBar, ProgressStyle};
 use pyo3::prelude::*;
 use rayon::prelude::*;
+use serde::Serialize;
 use serde_json as json;
 
 /// Notes:
@@ -22,7 +31,11 @@ use serde_json as json;
 /// - Deal-in After Riichi is recognized at the moment the sengenhai is
 ///   discarded.
 /// - Every other Δscore cover kyotakus.
+/// - A double ron is one deal-in, whose Δscore covers both agaris.
 /// - Ankan is not recognized as fuuro.
+/// - The turn of fuuro is the turn of the first call in the kyoku.
+/// - Hot tiles passed is only counted on full-information logs, see
+///   `HotTileTracker` for the definition.
 #[pyclass]
 #[derive(Debug, Clone, Default, PartialEq, Eq, Add, AddAssign, Sum)]
 pub struct Stat {
@@ -51,6 +64,8 @@ pub struct Stat {
     #[pyo3(get, set)]
     pub fuuro_num: i64,
     #[pyo3(get, set)]
+    pub fuuro_jun: i64,
+    #[pyo3(get, set)]
     pub fuuro_point: i64,
     #[pyo3(get, set)]
     pub fuuro_agari: i64,
@@ -121,6 +136,152 @@ pub struct Stat {
     pub yakuman: i64,
     #[pyo3(get, set)]
     pub nagashi_mangan: i64,
+
+    #[pyo3(get, set)]
+    pub hot_tiles_passed: i64,
+}
+
+/// Counts the hot tiles passed post hoc, that is, my discards that were in the
+/// eventual winner's waits at the time, but did not deal in.
+///
+/// The winner's waits over time can only be approximated, as their hand is
+/// known for sure only at the end. The approximation used here is: the final
+/// waits of the winner (computed from the winning hand without the winning
+/// tile) are considered to be their waits ever since their last hand change,
+/// which is the latest tedashi or call of theirs. Therefore only my discards
+/// after that point are checked against the final waits. The discard that
+/// deals in is not counted.
+///
+/// The hands of all the players are tracked from the events, which requires
+/// the log to be full-information. A player whose hand contains any `?` is
+/// ignored until the next kyoku.
+#[derive(Default)]
+struct HotTileTracker {
+    tehais: [[u8; 34]; 4],
+    tehai_known: [bool; 4],
+    last_tsumos: [Option<Tile>; 4],
+    /// My discards since each player's last hand change.
+    discards_since_change: [Vec<Tile>; 4],
+    last_is_my_dahai: bool,
+}
+
+impl HotTileTracker {
+    /// Returns the number of hot tiles passed when `ev` is a hora.
+    fn update(&mut self, ev: &Event, player_id: u8) -> i64 {
+        // Kept across the horas of a double ron, which all follow the same
+        // deal-in tile.
+        if !matches!(ev, Event::Hora { .. }) {
+            self.last_is_my_dahai = false;
+        }
+
+        match *ev {
+            Event::StartKyoku { tehais, .. } => {
+                for (i, tehai) in tehais.iter().enumerate() {
+                    self.tehais[i].fill(0);
+                    self.tehai_known[i] = !tehai.contains(&t!(?));
+                    if self.tehai_known[i] {
+                        tehai
+                            .iter()
+                            .for_each(|t| self.tehais[i][t.deaka().as_usize()] += 1);
+                    }
+                    self.last_tsumos[i] = None;
+                    self.discards_since_change[i].clear();
+                }
+            }
+            Event::Tsumo { actor, pai } => {
+                let actor = actor as usize;
+                if pai == t!(?) {
+                    self.tehai_known[actor] = false;
+                } else if self.tehai_known[actor] {
+                    self.tehais[actor][pai.deaka().as_usize()] += 1;
+                }
+                self.last_tsumos[actor] = Some(pai);
+            }
+            Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            } => {
+                self.remove(actor, &[pai]);
+                if actor == player_id {
+                    self.discards_since_change
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|&(i, _)| i != player_id as usize)
+                        .for_each(|(_, d)| d.push(pai));
+                    self.last_is_my_dahai = true;
+                } else if !tsumogiri {
+                    self.discards_since_change[actor as usize].clear();
+                }
+            }
+            Event::Chi {
+                actor, consumed, ..
+            }
+            | Event::Pon {
+                actor, consumed, ..
+            } => {
+                self.remove(actor, &consumed);
+                self.discards_since_change[actor as usize].clear();
+            }
+            Event::Daiminkan {
+                actor, consumed, ..
+            } => {
+                self.remove(actor, &consumed);
+                self.discards_since_change[actor as usize].clear();
+            }
+            Event::Kakan { actor, pai, .. } => {
+                self.remove(actor, &[pai]);
+                self.discards_since_change[actor as usize].clear();
+            }
+            Event::Ankan { actor, consumed } => {
+                self.remove(actor, &consumed);
+                self.discards_since_change[actor as usize].clear();
+            }
+            Event::Hora { actor, target, .. } if actor != player_id => {
+                let actor = actor as usize;
+                if !self.tehai_known[actor] {
+                    return 0;
+                }
+
+                let mut tehai = self.tehais[actor];
+                if actor as u8 == target {
+                    if let Some(tsumo) = self.last_tsumos[actor] {
+                        tehai[tsumo.deaka().as_usize()] -= 1;
+                    }
+                }
+                let len_div3 = tehai.iter().sum::<u8>() / 3;
+                let waits: Vec<_> = (0..34)
+                    .filter(|&t| {
+                        let mut tehai_after = tehai;
+                        tehai_after[t] += 1;
+                        tehai[t] < 4 && shanten::calc_all(&tehai_after, len_div3) == -1
+                    })
+                    .collect();
+
+                let mut discards = self.discards_since_change[actor].as_slice();
+                if target == player_id && self.last_is_my_dahai {
+                    // Exclude the deal-in tile.
+                    discards = &discards[..discards.len() - 1];
+                }
+                return discards
+                    .iter()
+                    .filter(|t| waits.contains(&t.deaka().as_usize()))
+                    .count() as i64;
+            }
+            _ => (),
+        };
+
+        0
+    }
+
+    fn remove(&mut self, actor: u8, tiles: &[Tile]) {
+        let actor = actor as usize;
+        if self.tehai_known[actor] {
+            tiles
+                .iter()
+                .for_each(|t| self.tehais[actor][t.deaka().as_usize()] -= 1);
+        }
+    }
 }
 
 impl fmt::Display for Stat {
@@ -176,6 +337,7 @@ Avg riichi turn           {:.6}
 Avg riichi Δscore         {:.6}
 
 Avg number of calls     {:.6}
+Avg first call turn     {:.6}
 Winning rate after call {:.6}
 Deal-in rate after call {:.6}
 Avg call Δscore         {:.6}
@@ -185,7 +347,9 @@ Dealer wins/all wins           {:.6}
 Deal-in to dealer/all deal-ins {:.6}
 
 Yakuman (rate)        {} ({:.9})
-Nagashi mangan (rate) {} ({:.9})"#,
+Nagashi mangan (rate) {} ({:.9})
+
+Hot tiles passed (per game) {} ({:.6})"#,
             self.game,
             self.round,
             self.oya,
@@ -239,6 +403,7 @@ Nagashi mangan (rate) {} ({:.9})"#,
             self.avg_riichi_point(),
             //
             self.avg_fuuro_num(),
+            self.avg_fuuro_jun(),
             self.agari_rate_after_fuuro(),
             self.houjuu_rate_after_fuuro(),
             self.avg_fuuro_point(),
@@ -251,6 +416,9 @@ Nagashi mangan (rate) {} ({:.9})"#,
             self.yakuman_rate(),
             self.nagashi_mangan,
             self.nagashi_mangan_rate(),
+            //
+            self.hot_tiles_passed,
+            self.avg_hot_tiles_passed(),
         )
     }
 }
@@ -273,158 +441,179 @@ impl Stat {
         let mut cur_kyotaku = 0;
         let mut jun = 0;
         let mut fuuro_num = 0;
-        events.iter().for_each(|ev| match *ev {
-            Event::StartKyoku {
-                oya,
-                scores,
-                kyotaku,
-                ..
-            } => {
-                stat.round += 1;
-                cur_scores = scores;
-                cur_kyotaku = kyotaku;
-                riichi_declared = false;
-                riichi_accepted = false;
-                others_riichi_declared = false;
-                cur_oya = oya;
-                if cur_oya == player_id {
-                    stat.oya += 1;
-                }
-                jun = 0;
-                fuuro_num = 0;
-            }
-
-            Event::Dahai { actor, .. } if actor == player_id => {
-                jun += 1;
-            }
-
-            Event::Chi { actor, .. }
-            | Event::Pon { actor, .. }
-            | Event::Daiminkan { actor, .. }
-                if actor == player_id =>
-            {
-                fuuro_num += 1;
-            }
-
-            Event::Reach { actor } => {
-                if actor == player_id {
-                    riichi_declared = true;
-                    stat.riichi += 1;
-                    stat.riichi_jun += jun;
+        let mut fuuro_jun = 0;
+        let mut houjuu_to_oya = None;
+        let mut hot_tile_tracker = HotTileTracker::default();
+        events.iter().for_each(|ev| {
+            stat.hot_tiles_passed += hot_tile_tracker.update(ev, player_id);
+            match *ev {
+                Event::StartKyoku {
+                    oya,
+                    scores,
+                    kyotaku,
+                    ..
+                } => {
+                    stat.round += 1;
+                    cur_scores = scores;
+                    cur_kyotaku = kyotaku;
+                    riichi_declared = false;
+                    riichi_accepted = false;
+                    others_riichi_declared = false;
+                    cur_oya = oya;
                     if cur_oya == player_id {
-                        stat.riichi_as_oya += 1;
+                        stat.oya += 1;
                     }
-                    if others_riichi_declared {
-                        stat.chasing_riichi += 1;
-                    }
-                } else if riichi_declared {
-                    stat.riichi_got_chased += 1;
-                } else {
-                    others_riichi_declared = true;
+                    jun = 0;
+                    fuuro_num = 0;
+                    fuuro_jun = 0;
+                    houjuu_to_oya = None;
                 }
-            }
 
-            Event::ReachAccepted { actor } => {
-                cur_scores[actor as usize] -= 1000;
-                cur_kyotaku += 1;
-                if actor == player_id {
-                    riichi_accepted = true;
+                Event::Dahai { actor, .. } if actor == player_id => {
+                    jun += 1;
                 }
-            }
-
-            Event::Hora {
-                actor,
-                target,
-                deltas,
-                ..
-            } => {
-                let deltas = deltas.expect("deltas is required for analyzing");
-                vec_add_assign(&mut cur_scores, &deltas);
-                cur_kyotaku = 0;
 
-                if actor == player_id {
-                    let point = deltas[player_id as usize] as i64 - riichi_accepted as i64 * 1000;
-                    stat.agari += 1;
-                    stat.agari_jun += jun;
-                    if cur_oya == player_id {
-                        stat.agari_as_oya += 1;
-                        stat.agari_point_oya += point;
-                    } else {
-                        stat.agari_point_ko += point;
+                Event::Chi { actor, .. }
+                | Event::Pon { actor, .. }
+                | Event::Daiminkan { actor, .. }
+                    if actor == player_id =>
+                {
+                    if fuuro_num == 0 {
+                        fuuro_jun = jun;
                     }
+                    fuuro_num += 1;
+                }
 
-                    if riichi_accepted {
-                        stat.riichi_agari += 1;
-                        stat.riichi_agari_jun += jun;
-                        stat.riichi_agari_point += point;
-                        stat.riichi_point += point;
-                    } else if fuuro_num > 0 {
-                        stat.fuuro_agari += 1;
-                        stat.fuuro_agari_jun += jun;
-                        stat.fuuro_agari_point += point;
-                        stat.fuuro_point += point;
+                Event::Reach { actor } => {
+                    if actor == player_id {
+                        riichi_declared = true;
+                        stat.riichi += 1;
+                        stat.riichi_jun += jun;
+                        if cur_oya == player_id {
+                            stat.riichi_as_oya += 1;
+                        }
+                        if others_riichi_declared {
+                            stat.chasing_riichi += 1;
+                        }
+                    } else if riichi_declared {
+                        stat.riichi_got_chased += 1;
                     } else {
-                        stat.dama_agari += 1;
-                        stat.dama_agari_jun += jun;
-                        stat.dama_agari_point += point;
+                        others_riichi_declared = true;
                     }
+                }
 
-                    if point >= Point::yakuman(cur_oya == player_id, 1).ron as i64 {
-                        stat.yakuman += 1;
+                Event::ReachAccepted { actor } => {
+                    cur_scores[actor as usize] -= 1000;
+                    cur_kyotaku += 1;
+                    if actor == player_id {
+                        riichi_accepted = true;
                     }
-                } else if target == player_id {
-                    let point = deltas[player_id as usize] as i64;
-                    stat.houjuu += 1;
-                    stat.houjuu_jun += jun;
-                    if cur_oya == actor {
-                        stat.houjuu_to_oya += 1;
-                        stat.houjuu_point_to_oya += point;
-                    } else {
-                        stat.houjuu_point_to_ko += point;
+                }
+
+                Event::Hora {
+                    actor,
+                    target,
+                    deltas,
+                    ..
+                } => {
+                    let deltas = deltas.expect("deltas is required for analyzing");
+                    vec_add_assign(&mut cur_scores, &deltas);
+                    cur_kyotaku = 0;
+
+                    if actor == player_id {
+                        let point =
+                            deltas[player_id as usize] as i64 - riichi_accepted as i64 * 1000;
+                        stat.agari += 1;
+                        stat.agari_jun += jun;
+                        if cur_oya == player_id {
+                            stat.agari_as_oya += 1;
+                            stat.agari_point_oya += point;
+                        } else {
+                            stat.agari_point_ko += point;
+                        }
+
+                        if riichi_accepted {
+                            stat.riichi_agari += 1;
+                            stat.riichi_agari_jun += jun;
+                            stat.riichi_agari_point += point;
+                            stat.riichi_point += point;
+                        } else if fuuro_num > 0 {
+                            stat.fuuro_agari += 1;
+                            stat.fuuro_agari_jun += jun;
+                            stat.fuuro_agari_point += point;
+                            stat.fuuro_point += point;
+                        } else {
+                            stat.dama_agari += 1;
+                            stat.dama_agari_jun += jun;
+                            stat.dama_agari_point += point;
+                        }
+
+                        if point >= Point::yakuman(cur_oya == player_id, 1).ron as i64 {
+                            stat.yakuman += 1;
+                        }
+                    } else if target == player_id {
+                        let point = deltas[player_id as usize] as i64;
+                        // The second agari of a double ron goes to the same
+                        // deal-in as the first one.
+                        let to_oya = *houjuu_to_oya.get_or_insert_with(|| {
+                            stat.houjuu += 1;
+                            stat.houjuu_jun += jun;
+                            if cur_oya == actor {
+                                stat.houjuu_to_oya += 1;
+                            }
+                            if riichi_declared {
+                                stat.riichi_houjuu += 1;
+                            } else if fuuro_num > 0 {
+                                stat.fuuro_houjuu += 1;
+                            }
+                            cur_oya == actor
+                        });
+                        if to_oya {
+                            stat.houjuu_point_to_oya += point;
+                        } else {
+                            stat.houjuu_point_to_ko += point;
+                        }
+
+                        if riichi_declared {
+                            stat.riichi_point += point;
+                        } else if fuuro_num > 0 {
+                            stat.fuuro_point += point;
+                        }
                     }
+                }
+
+                Event::Ryukyoku { deltas, .. } => {
+                    let deltas = deltas.expect("deltas is required for analyzing");
+                    vec_add_assign(&mut cur_scores, &deltas);
 
-                    if riichi_declared {
-                        stat.riichi_houjuu += 1;
-                        stat.riichi_point += point;
+                    let point = deltas[player_id as usize] as i64;
+                    stat.ryukyoku += 1;
+                    stat.ryukyoku_point += point;
+                    if riichi_accepted {
+                        stat.riichi_ryukyoku += 1;
+                        stat.riichi_point += point - 1000;
                     } else if fuuro_num > 0 {
-                        stat.fuuro_houjuu += 1;
                         stat.fuuro_point += point;
                     }
-                }
-            }
 
-            Event::Ryukyoku { deltas } => {
-                let deltas = deltas.expect("deltas is required for analyzing");
-                vec_add_assign(&mut cur_scores, &deltas);
-
-                let point = deltas[player_id as usize] as i64;
-                stat.ryukyoku += 1;
-                stat.ryukyoku_point += point;
-                if riichi_accepted {
-                    stat.riichi_ryukyoku += 1;
-                    stat.riichi_point += point - 1000;
-                } else if fuuro_num > 0 {
-                    stat.fuuro_point += point;
+                    if point >= 8000 {
+                        stat.nagashi_mangan += 1;
+                    }
                 }
 
-                if point >= 8000 {
-                    stat.nagashi_mangan += 1;
+                Event::EndKyoku => {
+                    if fuuro_num > 0 {
+                        stat.fuuro += 1;
+                        stat.fuuro_num += fuuro_num;
+                        stat.fuuro_jun += fuuro_jun;
+                    }
                 }
-            }
 
-            Event::EndKyoku => {
-                if fuuro_num > 0 {
-                    stat.fuuro += 1;
-                    stat.fuuro_num += fuuro_num;
-                }
+                _ => (),
             }
-
-            _ => (),
         });
 
-        if cur_kyotaku > 0 {
-            *cur_scores.iter_mut().min_by_key(|s| -**s).unwrap() += cur_kyotaku as i32 * 1000;
-        }
+        LeftoverKyotaku::ToTop.settle(&mut cur_scores, cur_kyotaku);
 
         let final_score = cur_scores[player_id as usize];
         stat.point = final_score as i64 - 25000;
@@ -449,6 +638,40 @@ impl Stat {
 
         stat
     }
+
+    /// The `Stat` of every player in the logs under `dir`, by the names in
+    /// `start_game`.
+    pub fn from_dir_by_name(
+        dir: &str,
+        disable_progress_bar: bool,
+    ) -> Result<BTreeMap<String, Self>> {
+        let bar = progress_bar(disable_progress_bar);
+
+        let stats = find_logs(dir)?
+            .par_bridge()
+            .map(|path| {
+                bar.inc(1);
+                let events = load_events(&path?)?;
+
+                match events.get(0) {
+                    Some(Event::StartGame { names, .. }) => Ok(names
+                        .iter()
+                        .enumerate()
+                        .map(|(i, name)| (name.clone(), Self::from_game(&events, i as u8)))
+                        .collect::<Vec<_>>()),
+                    ev => bail!("first event is not start_game, got {:?}", ev),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        bar.abandon();
+
+        let mut by_name = BTreeMap::<_, Self>::new();
+        for (name, stat) in stats.into_iter().flatten() {
+            *by_name.entry(name).or_default() += stat;
+        }
+        Ok(by_name)
+    }
 }
 
 #[pymethods]
@@ -457,38 +680,13 @@ impl Stat {
     #[pyo3(text_signature = "(dir, player_name, disable_progress_bar)")]
     #[args("*", disable_progress_bar = "false")]
     pub fn from_dir(dir: &str, player_name: &str, disable_progress_bar: bool) -> Result<Self> {
-        let bar = if disable_progress_bar {
-            ProgressBar::hidden()
-        } else {
-            ProgressBar::new_spinner().with_style(
-                ProgressStyle::default_spinner()
-                    .template("{spinner:.cyan} [{elapsed_precise}] {pos} ({per_sec})")
-                    .tick_chars(".oOo"),
-            )
-        };
-        bar.enable_steady_tick(150);
+        let bar = progress_bar(disable_progress_bar);
 
-        let stat = glob(&format!("{dir}/**/*.json"))?
-            .chain(glob(&format!("{dir}/**/*.json.gz"))?)
+        let stat = find_logs(dir)?
             .par_bridge()
             .map(|path| {
                 bar.inc(1);
-                let path = path?;
-
-                let mut raw_log = String::new();
-                if matches!(path.extension(), Some(s) if s.eq_ignore_ascii_case("gz")) {
-                    let mut gz = GzDecoder::new(File::open(path)?);
-                    gz.read_to_string(&mut raw_log)?;
-                } else {
-                    let mut f = File::open(path)?;
-                    f.read_to_string(&mut raw_log)?;
-                }
-
-                let events = raw_log
-                    .lines()
-                    .map(json::from_str)
-                    .collect::<Result<Vec<Event>, _>>()
-                    .context("failed to parse log")?;
+                let events = load_events(&path?)?;
 
                 match events.get(0) {
                     Some(Event::StartGame { names, .. }) => {
@@ -512,11 +710,7 @@ impl Stat {
     #[staticmethod]
     #[pyo3(text_signature = "(log, player_id)")]
     pub fn from_log(log: &str, player_id: u8) -> Result<Self> {
-        let events = log
-            .lines()
-            .map(json::from_str)
-            .collect::<Result<Vec<Event>, _>>()
-            .context("failed to parse log")?;
+        let events = parse_log(log)?;
         Ok(Self::from_game(&events, player_id))
     }
 
@@ -771,6 +965,12 @@ impl Stat {
     #[getter]
     #[inline]
     #[must_use]
+    pub fn avg_fuuro_jun(&self) -> f64 {
+        self.fuuro_jun as f64 / self.fuuro as f64
+    }
+    #[getter]
+    #[inline]
+    #[must_use]
     pub fn agari_rate_after_fuuro(&self) -> f64 {
         self.fuuro_agari as f64 / self.fuuro as f64
     }
@@ -800,6 +1000,13 @@ impl Stat {
         self.nagashi_mangan as f64 / self.round as f64
     }
 
+    #[getter]
+    #[inline]
+    #[must_use]
+    pub fn avg_hot_tiles_passed(&self) -> f64 {
+        self.hot_tiles_passed as f64 / self.game as f64
+    }
+
     fn __str__(&self) -> String {
         self.to_string()
     }
@@ -808,8 +1015,905 @@ impl Stat {
     }
 }
 
+/// Per-yaku aggregate, the value of a yaku is its han.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct YakuEntry {
+    pub count: u64,
+    pub total_han: u64,
+}
+
+/// Yaku statistics of all the agaris in a set of games.
+///
+/// The yakus of each agari are recomputed by the scorer from the winner's
+/// `PlayerState`, because `Event::Hora` does not carry them. Agaris whose
+/// winner's hand is unknown (not a full-information log) or that cannot be
+/// recomputed are counted in `skipped` instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct YakuStat {
+    pub agari: u64,
+    pub skipped: u64,
+    pub global: BTreeMap<Yaku, YakuEntry>,
+    /// Indexed by absolute seat.
+    pub per_seat: [BTreeMap<Yaku, YakuEntry>; 4],
+    /// Number of agaris in which both yakus appear, the pair is ordered.
+    pub co_occurrence: BTreeMap<(Yaku, Yaku), u64>,
+}
+
+#[derive(Serialize)]
+struct YakuEntryOutput {
+    count: u64,
+    total_han: u64,
+    avg_han: f64,
+}
+
+#[derive(Serialize)]
+struct YakuStatOutput {
+    agari: u64,
+    skipped: u64,
+    global: BTreeMap<Yaku, YakuEntryOutput>,
+    per_seat: Vec<BTreeMap<Yaku, YakuEntryOutput>>,
+    co_occurrence: BTreeMap<String, u64>,
+}
+
+impl YakuStat {
+    #[must_use]
+    pub fn from_game(events: &[Event]) -> Self {
+        let mut stat = Self::default();
+        let mut states: [PlayerState; 4] = [0, 1, 2, 3].map(PlayerState::new);
+        let mut known = [false; 4];
+
+        for ev in events {
+            match ev {
+                Event::StartKyoku { tehais, .. } => {
+                    for (k, tehai) in known.iter_mut().zip(tehais) {
+                        *k = !tehai.contains(&t!(?));
+                    }
+                }
+                Event::Hora {
+                    actor,
+                    target,
+                    ura_markers,
+                    ..
+                } => {
+                    let seat = *actor as usize;
+                    let ura = ura_markers.as_deref().unwrap_or_default();
+                    let detail = if known[seat] {
+                        states[seat].agari_detail(actor != target, ura)
+                    } else {
+                        None
+                    };
+                    match detail {
+                        Some(detail) => stat.add_agari(seat, &detail.yakus),
+                        None => stat.skipped += 1,
+                    }
+                }
+                _ => (),
+            }
+
+            for (state, _) in states.iter_mut().zip(known).filter(|(_, k)| *k) {
+                state.update(ev);
+            }
+        }
+
+        stat
+    }
+
+    fn add_agari(&mut self, seat: usize, yakus: &[(Yaku, u8)]) {
+        self.agari += 1;
+        for &(yaku, han) in yakus {
+            for map in [&mut self.global, &mut self.per_seat[seat]] {
+                let entry = map.entry(yaku).or_default();
+                entry.count += 1;
+                entry.total_han += u64::from(han);
+            }
+        }
+        for (i, &(a, _)) in yakus.iter().enumerate() {
+            for &(b, _) in &yakus[i + 1..] {
+                let key = if a <= b { (a, b) } else { (b, a) };
+                *self.co_occurrence.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn from_dir(dir: &str, disable_progress_bar: bool) -> Result<Self> {
+        let bar = progress_bar(disable_progress_bar);
+
+        let stat = find_logs(dir)?
+            .par_bridge()
+            .map(|path| {
+                bar.inc(1);
+                let events = load_events(&path?)?;
+                Ok(Self::from_game(&events))
+            })
+            .sum::<Result<_>>()?;
+
+        bar.abandon();
+        Ok(stat)
+    }
+
+    pub fn from_log(log: &str) -> Result<Self> {
+        let events = parse_log(log)?;
+        Ok(Self::from_game(&events))
+    }
+
+    /// Keys are the serde names of `Yaku`, a co-occurrence key is the two
+    /// names joined by `+`.
+    pub fn to_json(&self) -> Result<String> {
+        fn entries(map: &BTreeMap<Yaku, YakuEntry>) -> BTreeMap<Yaku, YakuEntryOutput> {
+            map.iter()
+                .map(|(&yaku, e)| {
+                    let output = YakuEntryOutput {
+                        count: e.count,
+                        total_han: e.total_han,
+                        avg_han: e.total_han as f64 / e.count as f64,
+                    };
+                    (yaku, output)
+                })
+                .collect()
+        }
+        fn name(yaku: Yaku) -> Result<String> {
+            match json::to_value(yaku)? {
+                json::Value::String(s) => Ok(s),
+                v => bail!("unexpected yaku name {v}"),
+            }
+        }
+
+        let co_occurrence = self
+            .co_occurrence
+            .iter()
+            .map(|(&(a, b), &n)| Ok((format!("{}+{}", name(a)?, name(b)?), n)))
+            .collect::<Result<_>>()?;
+        let output = YakuStatOutput {
+            agari: self.agari,
+            skipped: self.skipped,
+            global: entries(&self.global),
+            per_seat: self.per_seat.iter().map(entries).collect(),
+            co_occurrence,
+        };
+        Ok(json::to_string_pretty(&output)?)
+    }
+}
+
+impl Add for YakuStat {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        fn merge(lhs: &mut BTreeMap<Yaku, YakuEntry>, rhs: BTreeMap<Yaku, YakuEntry>) {
+            for (yaku, e) in rhs {
+                let entry = lhs.entry(yaku).or_default();
+                entry.count += e.count;
+                entry.total_han += e.total_han;
+            }
+        }
+
+        self.agari += rhs.agari;
+        self.skipped += rhs.skipped;
+        merge(&mut self.global, rhs.global);
+        for (lhs, rhs) in self.per_seat.iter_mut().zip(rhs.per_seat) {
+            merge(lhs, rhs);
+        }
+        for (key, n) in rhs.co_occurrence {
+            *self.co_occurrence.entry(key).or_default() += n;
+        }
+        self
+    }
+}
+
+impl Sum for YakuStat {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+/// Anomalies whose p-value against the corpus baseline is below this are
+/// flagged.
+const IMPROBABLE_P_VALUE: f64 = 1e-4;
+
+/// The number of tsumos in a yonma kyoku, the last of which is haitei.
+const TSUMOS_PER_KYOKU: u8 = 70;
+
+/// Per-game tallies of `AnomalyStat`, counted without any baseline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnomalyTally {
+    /// Exact count violations, such as a fifth copy of a tile, which are
+    /// anomalies on their own.
+    pub violations: Vec<String>,
+    /// Indexed by absolute seat.
+    pub agari: [u32; 4],
+    /// Tsumo agaris by haitei or rinshan, indexed by absolute seat.
+    pub haitei_rinshan: [u32; 4],
+}
+
+impl AnomalyTally {
+    /// Each tile is counted once in its kyoku, when it enters a hand known
+    /// since `start_kyoku`, is revealed as an indicator, or leaves an unknown
+    /// hand by a discard or a call.
+    #[must_use]
+    pub fn from_game(events: &[Event]) -> Self {
+        let mut tally = Self::default();
+        let mut known = [false; 4];
+        let mut revealed = [0_u8; 34];
+        let mut reported = [false; 34];
+        let mut tsumos = 0;
+        let mut kan_actor = None;
+        let mut rinshan_draw = false;
+        let mut uras_revealed = false;
+
+        for (idx, ev) in events.iter().enumerate() {
+            let mut new_tiles = vec![];
+            match ev {
+                Event::StartKyoku {
+                    dora_marker,
+                    tehais,
+                    ..
+                } => {
+                    revealed.fill(0);
+                    reported.fill(false);
+                    tsumos = 0;
+                    kan_actor = None;
+                    uras_revealed = false;
+                    for (k, tehai) in known.iter_mut().zip(tehais) {
+                        *k = !tehai.contains(&t!(?));
+                        if *k {
+                            new_tiles.extend_from_slice(tehai);
+                        }
+                    }
+                    new_tiles.push(*dora_marker);
+                }
+                &Event::Tsumo { actor, pai } => {
+                    tsumos += 1;
+                    rinshan_draw = kan_actor.take() == Some(actor);
+                    if known[actor as usize] {
+                        new_tiles.push(pai);
+                    }
+                }
+                &Event::Dahai { actor, pai, .. } if !known[actor as usize] => {
+                    new_tiles.push(pai);
+                }
+                Event::Chi {
+                    actor, consumed, ..
+                }
+                | Event::Pon {
+                    actor, consumed, ..
+                } if !known[*actor as usize] => new_tiles.extend_from_slice(consumed),
+                Event::Daiminkan {
+                    actor, consumed, ..
+                } => {
+                    if !known[*actor as usize] {
+                        new_tiles.extend_from_slice(consumed);
+                    }
+                    kan_actor = Some(*actor);
+                }
+                Event::Ankan { actor, consumed } => {
+                    if !known[*actor as usize] {
+                        new_tiles.extend_from_slice(consumed);
+                    }
+                    kan_actor = Some(*actor);
+                }
+                &(Event::Kakan { actor, pai, .. } | Event::Nukidora { actor, pai }) => {
+                    if !known[actor as usize] {
+                        new_tiles.push(pai);
+                    }
+                    kan_actor = Some(actor);
+                }
+                &Event::Dora { dora_marker } => new_tiles.push(dora_marker),
+                Event::Hora {
+                    actor,
+                    target,
+                    ura_markers,
+                    ..
+                } => {
+                    let seat = *actor as usize;
+                    tally.agari[seat] += 1;
+                    if actor == target && (rinshan_draw || tsumos == TSUMOS_PER_KYOKU) {
+                        tally.haitei_rinshan[seat] += 1;
+                    }
+                    // The agaris of a double ron share the same ura markers.
+                    if !uras_revealed {
+                        new_tiles.extend(ura_markers.iter().flatten());
+                        uras_revealed = true;
+                    }
+                }
+                _ => (),
+            }
+
+            for tile in new_tiles {
+                if tile == t!(?) {
+                    continue;
+                }
+                let tid = tile.deaka().as_usize();
+                revealed[tid] += 1;
+                if revealed[tid] > 4 && !reported[tid] {
+                    reported[tid] = true;
+                    tally.violations.push(format!(
+                        "a fifth copy of {} is revealed at line {}",
+                        tile.deaka(),
+                        idx + 1,
+                    ));
+                }
+            }
+        }
+
+        tally
+    }
+}
+
+/// A game flagged by `AnomalyStat`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlaggedGame {
+    pub game: String,
+    pub reasons: Vec<String>,
+}
+
+/// A data-quality screen over a set of games, which flags the games with
+/// exact count violations, or with a player winning by haitei or rinshan
+/// improbably often against the baseline of the same set of games.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnomalyStat {
+    pub games: u64,
+    pub agari: u64,
+    pub haitei_rinshan: u64,
+    /// In ascending order of `game`.
+    pub flagged: Vec<FlaggedGame>,
+}
+
+#[derive(Serialize)]
+struct AnomalyStatOutput<'a> {
+    games: u64,
+    agari: u64,
+    haitei_rinshan: u64,
+    haitei_rinshan_rate: f64,
+    flagged: &'a [FlaggedGame],
+}
+
+impl AnomalyStat {
+    /// Computes the baselines over all of `tallies`, then flags each game
+    /// against them.
+    #[must_use]
+    pub fn from_tallies(tallies: Vec<(String, AnomalyTally)>) -> Self {
+        let mut stat = Self {
+            games: tallies.len() as u64,
+            ..Default::default()
+        };
+        for (_, tally) in &tallies {
+            stat.agari += tally.agari.iter().map(|&n| u64::from(n)).sum::<u64>();
+            stat.haitei_rinshan += tally
+                .haitei_rinshan
+                .iter()
+                .map(|&n| u64::from(n))
+                .sum::<u64>();
+        }
+        let rate = stat.haitei_rinshan_rate();
+
+        stat.flagged = tallies
+            .into_iter()
+            .filter_map(|(game, tally)| {
+                let mut reasons = tally.violations;
+                for (seat, (&n, &k)) in tally.agari.iter().zip(&tally.haitei_rinshan).enumerate() {
+                    if k == 0 {
+                        continue;
+                    }
+                    let p = binomial_tail(n, k, rate);
+                    if p < IMPROBABLE_P_VALUE {
+                        reasons.push(format!(
+                            "seat {seat} wins {k} of {n} agaris by haitei or rinshan, \
+                            p = {p:.2e} against the rate {rate:.4}",
+                        ));
+                    }
+                }
+                (!reasons.is_empty()).then_some(FlaggedGame { game, reasons })
+            })
+            .collect();
+        stat.flagged.sort_by(|a, b| a.game.cmp(&b.game));
+        stat
+    }
+
+    pub fn from_dir(dir: &str, disable_progress_bar: bool) -> Result<Self> {
+        let bar = progress_bar(disable_progress_bar);
+
+        let tallies = find_logs(dir)?
+            .par_bridge()
+            .map(|path| {
+                bar.inc(1);
+                let path = path?;
+                let events = load_events(&path)?;
+                Ok((path.display().to_string(), AnomalyTally::from_game(&events)))
+            })
+            .collect::<Result<_>>()?;
+
+        bar.abandon();
+        Ok(Self::from_tallies(tallies))
+    }
+
+    /// Each log is given along with its name.
+    pub fn from_logs(logs: &[(&str, &str)]) -> Result<Self> {
+        let tallies = logs
+            .iter()
+            .map(|&(name, log)| {
+                let events =
+                    parse_log(log).with_context(|| format!("failed to parse log {name}"))?;
+                Ok((name.to_owned(), AnomalyTally::from_game(&events)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::from_tallies(tallies))
+    }
+
+    #[must_use]
+    pub fn haitei_rinshan_rate(&self) -> f64 {
+        if self.agari == 0 {
+            0.
+        } else {
+            self.haitei_rinshan as f64 / self.agari as f64
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let output = AnomalyStatOutput {
+            games: self.games,
+            agari: self.agari,
+            haitei_rinshan: self.haitei_rinshan,
+            haitei_rinshan_rate: self.haitei_rinshan_rate(),
+            flagged: &self.flagged,
+        };
+        Ok(json::to_string_pretty(&output)?)
+    }
+}
+
+/// The probability of at least `k` successes in `n` trials of probability
+/// `p`.
+fn binomial_tail(n: u32, k: u32, p: f64) -> f64 {
+    let mut coef = 1.;
+    let mut sum = 0.;
+    for i in 0..=n {
+        if i >= k {
+            sum += coef * p.powi(i as i32) * (1. - p).powi((n - i) as i32);
+        }
+        coef = coef * f64::from(n - i) / f64::from(i + 1);
+    }
+    sum
+}
+
+fn progress_bar(disable: bool) -> ProgressBar {
+    let bar = if disable {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner().with_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.cyan} [{elapsed_precise}] {pos} ({per_sec})")
+                .tick_chars(".oOo"),
+        )
+    };
+    bar.enable_steady_tick(150);
+    bar
+}
+
+fn load_events(path: &Path) -> Result<Vec<Event>> {
+    let mut raw_log = String::new();
+    open_log(path)?.read_to_string(&mut raw_log)?;
+    parse_log(&raw_log).with_context(|| format!("failed to parse {}", path.display()))
+}
+
 pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule) -> PyResult<()> {
     let m = PyModule::new(py, "stat")?;
     m.add_class::<Stat>()?;
     add_submodule(py, prefix, super_mod, m)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn riichi_ippatsu_tsumo_log() -> String {
+        let log = r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","2p","3p","5s","E"],["4p","4p","9s","9s","1s","1s","7s","7s","S","S","W","W","N"],["6p","6p","7p","7p","8p","8p","2s","2s","3s","3s","F","F","C"],["9p","9p","6s","6s","8s","8s","4s","4s","P","P","C","C","E"]]}
+            {"type":"tsumo","actor":1,"pai":"4p"}
+            {"type":"dahai","actor":1,"pai":"4p","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"N"}
+            {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"N"}
+            {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"5s"}
+            {"type":"dahai","actor":0,"pai":"E","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"2s"}
+            {"type":"dahai","actor":1,"pai":"2s","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"F"}
+            {"type":"dahai","actor":2,"pai":"F","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"5m"}
+            {"type":"dahai","actor":3,"pai":"5m","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"1p"}
+            {"type":"reach","actor":0}
+            {"type":"dahai","actor":0,"pai":"1p","tsumogiri":true}
+            {"type":"reach_accepted","actor":0}
+            {"type":"tsumo","actor":1,"pai":"3m"}
+            {"type":"dahai","actor":1,"pai":"4p","tsumogiri":false}
+            {"type":"tsumo","actor":2,"pai":"2m"}
+            {"type":"dahai","actor":2,"pai":"2m","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"3m"}
+            {"type":"dahai","actor":3,"pai":"3m","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"4p"}
+            {"type":"hora","actor":0,"target":0,"deltas":[9000,-4000,-2000,-2000],"ura_markers":["1s"]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#;
+        log.trim()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn hot_tiles_passed() {
+        // Player 0 becomes tenpai on 1p and 4p by a tedashi, then declares
+        // riichi with a furiten 1p, so that the 4p discarded by player 1
+        // afterwards is passed. The first 4p of player 1 is before player 0's
+        // last hand change and thus is not counted.
+        let log = riichi_ippatsu_tsumo_log();
+
+        let stat = Stat::from_log(&log, 1).unwrap();
+        assert_eq!(stat.hot_tiles_passed, 1);
+        for player_id in [0, 2, 3] {
+            let stat = Stat::from_log(&log, player_id).unwrap();
+            assert_eq!(stat.hot_tiles_passed, 0);
+        }
+    }
+
+    #[test]
+    fn hot_tiles_passed_double_ron() {
+        // Player 1 waits on 2m and 5m, and player 2 on 5m only. Player 0
+        // passes a 2m, then deals in to both with a 5m, which is excluded for
+        // each winner.
+        let log = r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["9m","9m","9m","N","N","N","W","W","S","S","C","C","F"],["1m","2m","3m","3m","4m","4p","5p","6p","P","P","P","1s","1s"],["2p","3p","4p","6s","7s","8s","E","E","E","9p","9p","4m","6m"],["1p","1p","1p","7p","7p","7p","8p","8p","2s","2s","3s","3s","5s"]]}
+            {"type":"tsumo","actor":0,"pai":"2m"}
+            {"type":"dahai","actor":0,"pai":"2m","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"W"}
+            {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"N"}
+            {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"C"}
+            {"type":"dahai","actor":3,"pai":"C","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"5m"}
+            {"type":"dahai","actor":0,"pai":"5m","tsumogiri":true}
+            {"type":"hora","actor":1,"target":0,"deltas":[-1300,1300,0,0]}
+            {"type":"hora","actor":2,"target":0,"deltas":[-1300,0,1300,0]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#;
+        let log = log
+            .trim()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let stat = Stat::from_log(&log, 0).unwrap();
+        assert_eq!(stat.houjuu, 1);
+        assert_eq!(stat.hot_tiles_passed, 1);
+        for player_id in 1..4 {
+            let stat = Stat::from_log(&log, player_id).unwrap();
+            assert_eq!(stat.hot_tiles_passed, 0);
+        }
+    }
+
+    #[test]
+    fn fuuro_stat() {
+        // Player 1 pons after one discard and chis afterwards in the first
+        // kyoku, and makes no call in the second one.
+        let log = r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["P","P","1m","2m","3m","4p","5p","6p","7s","8s","9s","8m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"S","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"E"}
+            {"type":"dahai","actor":1,"pai":"E","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"P","tsumogiri":true}
+            {"type":"pon","actor":1,"target":0,"pai":"P","consumed":["P","P"]}
+            {"type":"dahai","actor":1,"pai":"1m","tsumogiri":false}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"7m","tsumogiri":true}
+            {"type":"chi","actor":1,"target":0,"pai":"7m","consumed":["8m","9m"]}
+            {"type":"dahai","actor":1,"pai":"3m","tsumogiri":false}
+            {"type":"ryukyoku","deltas":[-1000,3000,-1000,-1000]}
+            {"type":"end_kyoku"}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":2,"honba":1,"kyotaku":0,"oya":1,"scores":[24000,28000,24000,24000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","9m","1p","9p","1s","9s","E","S","W","N","P","F","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":1,"pai":"2m"}
+            {"type":"ryukyoku","reason":"kyuushukyuuhai","deltas":[0,0,0,0]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#;
+        let log = log
+            .trim()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let stat = Stat::from_log(&log, 1).unwrap();
+        assert_eq!(stat.round, 2);
+        assert_eq!(stat.fuuro, 1);
+        assert_eq!(stat.fuuro_num, 2);
+        assert_eq!(stat.fuuro_jun, 1);
+        assert!((stat.fuuro_rate() - 0.5).abs() < 1e-6);
+        assert!((stat.avg_fuuro_jun() - 1.).abs() < 1e-6);
+        assert!(stat
+            .to_string()
+            .contains("Avg first call turn     1.000000"));
+
+        let stat: Stat = [stat.clone(), stat].into_iter().sum();
+        assert_eq!(stat.fuuro_jun, 2);
+        assert!((stat.avg_fuuro_jun() - 1.).abs() < 1e-6);
+
+        let stat = Stat::from_log(&log, 0).unwrap();
+        assert_eq!(stat.fuuro, 0);
+        assert_eq!(stat.fuuro_jun, 0);
+    }
+
+    fn parse_log(log: &str) -> String {
+        log.trim()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn double_ron() {
+        // Player 0 deals in to both player 1 and player 2 with one discard.
+        let log = parse_log(
+            r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"5m","tsumogiri":true}
+            {"type":"hora","actor":1,"target":0,"deltas":[-2000,2000,0,0]}
+            {"type":"hora","actor":2,"target":0,"deltas":[-3900,0,3900,0]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#,
+        );
+
+        let stat = Stat::from_log(&log, 0).unwrap();
+        assert_eq!(stat.houjuu, 1);
+        assert_eq!(stat.houjuu_jun, 1);
+        assert_eq!(stat.houjuu_to_oya, 0);
+        assert_eq!(stat.houjuu_point_to_ko, -5900);
+        assert!((stat.houjuu_rate() - 1.).abs() < 1e-6);
+        assert!((stat.avg_point_per_houjuu() + 5900.).abs() < 1e-6);
+        assert_eq!(stat.point, -5900);
+        assert_eq!(stat.rank_4, 1);
+
+        for (player_id, point) in [(1, 2000), (2, 3900)] {
+            let stat = Stat::from_log(&log, player_id).unwrap();
+            assert_eq!(stat.agari, 1);
+            assert_eq!(stat.agari_point_ko, point);
+            assert_eq!(stat.houjuu, 0);
+        }
+    }
+
+    #[test]
+    fn chankan() {
+        // Player 1 pons 3m and deals in to player 2 by a kakan of it.
+        let log = parse_log(
+            r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"3m","tsumogiri":true}
+            {"type":"pon","actor":1,"target":0,"pai":"3m","consumed":["3m","3m"]}
+            {"type":"dahai","actor":1,"pai":"W","tsumogiri":false}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"3m"}
+            {"type":"kakan","actor":1,"pai":"3m","consumed":["3m","3m","3m"]}
+            {"type":"hora","actor":2,"target":1,"deltas":[0,-2000,2000,0]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#,
+        );
+
+        let stat = Stat::from_log(&log, 1).unwrap();
+        assert_eq!(stat.houjuu, 1);
+        assert_eq!(stat.houjuu_jun, 1);
+        assert_eq!(stat.houjuu_point_to_ko, -2000);
+        assert_eq!(stat.fuuro, 1);
+        assert_eq!(stat.fuuro_houjuu, 1);
+        assert_eq!(stat.fuuro_point, -2000);
+
+        let stat = Stat::from_log(&log, 2).unwrap();
+        assert_eq!(stat.agari, 1);
+        assert_eq!(stat.dama_agari, 1);
+        assert_eq!(stat.agari_point_ko, 2000);
+
+        let stat = Stat::from_log(&log, 0).unwrap();
+        assert_eq!(stat.houjuu, 0);
+    }
+
+    #[test]
+    fn riichi_ryukyoku() {
+        // Player 0 riichis and is the only tenpai at the exhaustive draw, then
+        // declares another riichi whose sengenhai is ronned by the other three
+        // players, which is a sanchahou before the riichi is accepted.
+        let log = parse_log(
+            r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"reach","actor":0}
+            {"type":"dahai","actor":0,"pai":"9m","tsumogiri":true}
+            {"type":"reach_accepted","actor":0}
+            {"type":"ryukyoku","deltas":[3000,-1000,-1000,-1000]}
+            {"type":"end_kyoku"}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":1,"kyotaku":1,"oya":0,"scores":[27000,24000,24000,24000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"reach","actor":0}
+            {"type":"dahai","actor":0,"pai":"5p","tsumogiri":true}
+            {"type":"ryukyoku","reason":"sanchahou","deltas":[0,0,0,0]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#,
+        );
+
+        let stat = Stat::from_log(&log, 0).unwrap();
+        assert_eq!(stat.riichi, 2);
+        assert_eq!(stat.riichi_jun, 0);
+        assert_eq!(stat.ryukyoku, 2);
+        assert_eq!(stat.ryukyoku_point, 3000);
+        // Only the accepted riichi is counted as a riichi ending in ryukyoku,
+        // with the 1000 of its sengenhai taken out.
+        assert_eq!(stat.riichi_ryukyoku, 1);
+        assert_eq!(stat.riichi_point, 2000);
+        assert_eq!(stat.riichi_houjuu, 0);
+        // The kyotaku left goes to the top at the end of the game.
+        assert_eq!(stat.point, 3000);
+        assert_eq!(stat.rank_1, 1);
+    }
+
+    #[test]
+    fn yaku_stat() {
+        let log = riichi_ippatsu_tsumo_log();
+        let stat = YakuStat::from_log(&log).unwrap();
+        assert_eq!(stat.agari, 1);
+        assert_eq!(stat.skipped, 0);
+
+        let expected = [
+            (Yaku::Riichi, 1),
+            (Yaku::Ippatsu, 1),
+            (Yaku::MenzenTsumo, 1),
+            (Yaku::Pinfu, 1),
+            (Yaku::Ittsuu, 2),
+        ];
+        let entries = |map: &BTreeMap<Yaku, YakuEntry>, times| {
+            let actual = map
+                .iter()
+                .map(|(&yaku, e)| (yaku, e.count, e.total_han))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .iter()
+                .map(|&(yaku, han)| (yaku, times, han * times))
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected);
+        };
+        entries(&stat.global, 1);
+        entries(&stat.per_seat[0], 1);
+        assert!(stat.per_seat[1..].iter().all(BTreeMap::is_empty));
+        assert_eq!(stat.co_occurrence.len(), 10);
+        assert!(stat.co_occurrence.values().all(|&n| n == 1));
+
+        let stat: YakuStat = [stat.clone(), stat].into_iter().sum();
+        assert_eq!(stat.agari, 2);
+        entries(&stat.global, 2);
+        entries(&stat.per_seat[0], 2);
+        assert_eq!(stat.co_occurrence[&(Yaku::Riichi, Yaku::Ippatsu)], 2);
+
+        let output: json::Value = json::from_str(&stat.to_json().unwrap()).unwrap();
+        assert_eq!(output["global"]["ittsuu"]["count"], 2);
+        assert_eq!(output["global"]["ittsuu"]["avg_han"], 2.);
+        assert_eq!(output["per_seat"][0]["menzen_tsumo"]["total_han"], 2);
+        assert_eq!(output["co_occurrence"]["riichi+ippatsu"], 2);
+    }
+
+    #[test]
+    fn anomalies() {
+        // Player 1 wins by rinshan after a kakan, seen from a log in which no
+        // hand is known.
+        let rinshan = parse_log(
+            r#"
+            {"type":"start_game","names":["a","b","c","d"]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"3m","tsumogiri":true}
+            {"type":"pon","actor":1,"target":0,"pai":"3m","consumed":["3m","3m"]}
+            {"type":"dahai","actor":1,"pai":"W","tsumogiri":false}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"3m"}
+            {"type":"kakan","actor":1,"pai":"3m","consumed":["3m","3m","3m"]}
+            {"type":"dora","dora_marker":"5p"}
+            {"type":"tsumo","actor":1,"pai":"?"}
+            {"type":"hora","actor":1,"target":1,"deltas":[-1000,4000,-2000,-1000]}
+            {"type":"end_kyoku"}
+            {"type":"end_game"}
+        "#,
+        );
+        let events = parse_log(&rinshan).unwrap();
+        let tally = AnomalyTally::from_game(&events);
+        assert!(tally.violations.is_empty());
+        assert_eq!(tally.agari, [0, 1, 0, 0]);
+        assert_eq!(tally.haitei_rinshan, [0, 1, 0, 0]);
+
+        let clean = riichi_ippatsu_tsumo_log();
+        let stat =
+            AnomalyStat::from_logs(&[("clean", clean.as_str()), ("rinshan", rinshan.as_str())])
+                .unwrap();
+        assert_eq!(stat.games, 2);
+        assert_eq!(stat.agari, 2);
+        assert_eq!(stat.haitei_rinshan, 1);
+        assert!(stat.flagged.is_empty());
+
+        // All the four 4p are already revealed in the log, so a 2m drawn by
+        // player 2 turned into 4p is a fifth one.
+        let injected = clean.replace(r#""actor":2,"pai":"2m""#, r#""actor":2,"pai":"4p""#);
+        let stat =
+            AnomalyStat::from_logs(&[("clean", clean.as_str()), ("injected", injected.as_str())])
+                .unwrap();
+        assert_eq!(
+            stat.flagged,
+            [FlaggedGame {
+                game: "injected".to_owned(),
+                reasons: vec!["a fifth copy of 4p is revealed at line 23".to_owned()],
+            }],
+        );
+        let output: json::Value = json::from_str(&stat.to_json().unwrap()).unwrap();
+        assert_eq!(output["flagged"][0]["game"], "injected");
+
+        // Three rinshan agaris out of three in one game, against a baseline of
+        // three in a hundred agaris.
+        let mut tallies = vec![];
+        for i in 0..97 {
+            let tally = AnomalyTally {
+                agari: [1, 0, 0, 0],
+                ..Default::default()
+            };
+            tallies.push((format!("clean{i}"), tally));
+        }
+        let tally = AnomalyTally {
+            agari: [0, 3, 0, 0],
+            haitei_rinshan: [0, 3, 0, 0],
+            ..Default::default()
+        };
+        tallies.push(("lucky".to_owned(), tally));
+        let stat = AnomalyStat::from_tallies(tallies);
+        assert!((stat.haitei_rinshan_rate() - 0.03).abs() < 1e-6);
+        assert_eq!(stat.flagged.len(), 1);
+        assert_eq!(stat.flagged[0].game, "lucky");
+        assert!(stat.flagged[0].reasons[0].starts_with("seat 1 wins 3 of 3 agaris"));
+    }
+
+    #[test]
+    fn binomial_tail_probability() {
+        assert!((binomial_tail(4, 0, 0.3) - 1.).abs() < 1e-9);
+        assert!((binomial_tail(1, 1, 0.3) - 0.3).abs() < 1e-9);
+        assert!((binomial_tail(3, 2, 0.5) - 0.5).abs() < 1e-9);
+        assert!((binomial_tail(3, 3, 0.1) - 1e-3).abs() < 1e-12);
+    }
+}
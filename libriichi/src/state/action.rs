@@ -1,10 +1,12 @@
-use super::PlayerState;
+use super::{GameMode, PlayerState};
 use crate::chi_type::ChiType;
-use crate::mjai::Event;
+use crate::consts::{ACTION_SPACE, SANMA_ACTION_SPACE};
+use crate::mjai::{Event, RyukyokuReason};
 use crate::tile::Tile;
-use crate::tuz;
+use crate::{must_tile, t, tu8, tuz};
+use std::slice;
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use pyo3::prelude::*;
 use serde::Serialize;
 
@@ -33,13 +35,81 @@ pub struct ActionCandidate {
     pub can_tsumo_agari: bool,
     #[pyo3(get)]
     pub can_ron_agari: bool,
+    /// Whether the ron of `can_ron_agari` is on a kakan, which adds the han
+    /// of chankan.
+    #[pyo3(get)]
+    pub is_chankan: bool,
     #[pyo3(get)]
     pub can_ryukyoku: bool,
+    /// Whether a N in hand can be extracted as a dora, which is only the case
+    /// in sanma.
+    #[pyo3(get)]
+    pub can_nukidora: bool,
 
     #[pyo3(get)]
     pub target_actor: u8,
 }
 
+/// A legal reaction of the player, as enumerated by
+/// `PlayerState::legal_reactions`.
+///
+/// Tiles are aka-aware, so that for example a pon with and without the aka
+/// are two different reactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reaction {
+    Discard(Tile),
+    Chi {
+        consumed: [Tile; 2],
+    },
+    Pon {
+        consumed: [Tile; 2],
+    },
+    /// Daiminkan.
+    Kan {
+        consumed: [Tile; 3],
+    },
+    /// The tile in hand added to the pon.
+    Kakan(Tile),
+    Ankan {
+        consumed: [Tile; 4],
+    },
+    /// Extracts a N as a dora in sanma.
+    Nukidora,
+    Riichi,
+    Tsumo,
+    Ron,
+    /// Kyuushukyuuhai.
+    Ryukyoku,
+    Pass,
+}
+
+impl Reaction {
+    /// The key of the order of `PlayerState::legal_reactions`, see there.
+    fn order_key(&self) -> (u8, [u8; 4], [bool; 4]) {
+        let (kind, tiles): (u8, &[Tile]) = match self {
+            Self::Discard(tile) => (0, slice::from_ref(tile)),
+            Self::Riichi => (1, &[]),
+            Self::Chi { consumed } => (2, consumed),
+            Self::Pon { consumed } => (3, consumed),
+            Self::Kan { consumed } => (4, consumed),
+            Self::Kakan(tile) => (5, slice::from_ref(tile)),
+            Self::Ankan { consumed } => (6, consumed),
+            Self::Nukidora => (7, &[]),
+            Self::Tsumo => (8, &[]),
+            Self::Ron => (9, &[]),
+            Self::Ryukyoku => (10, &[]),
+            Self::Pass => (11, &[]),
+        };
+        let mut ids = [0; 4];
+        let mut akas = [false; 4];
+        for (i, tile) in tiles.iter().enumerate() {
+            ids[i] = tile.deaka().as_u8();
+            akas[i] = tile.is_aka();
+        }
+        (kind, ids, akas)
+    }
+}
+
 #[pymethods]
 impl ActionCandidate {
     #[getter]
@@ -63,6 +133,87 @@ impl ActionCandidate {
             || self.can_tsumo_agari
             || self.can_ron_agari
             || self.can_ryukyoku
+            || self.can_nukidora
+    }
+
+    /// Packs the candidates into a `u32`, which is cheap to log and compare.
+    ///
+    /// Bit assignment, from the least significant bit:
+    ///
+    /// | bit   | field             |
+    /// | ----- | ----------------- |
+    /// | 0     | `can_discard`     |
+    /// | 1     | `can_chi_low`     |
+    /// | 2     | `can_chi_mid`     |
+    /// | 3     | `can_chi_high`    |
+    /// | 4     | `can_pon`         |
+    /// | 5     | `can_daiminkan`   |
+    /// | 6     | `can_kakan`       |
+    /// | 7     | `can_ankan`       |
+    /// | 8     | `can_riichi`      |
+    /// | 9     | `can_tsumo_agari` |
+    /// | 10    | `can_ron_agari`   |
+    /// | 11    | `can_ryukyoku`    |
+    /// | 12    | `is_chankan`      |
+    /// | 13    | `can_nukidora`    |
+    /// | 16-23 | `target_actor`    |
+    ///
+    /// The other bits are always zero.
+    #[inline]
+    #[must_use]
+    #[pyo3(text_signature = "($self, /)")]
+    pub const fn as_bitflags(&self) -> u32 {
+        let flags = [
+            self.can_discard,
+            self.can_chi_low,
+            self.can_chi_mid,
+            self.can_chi_high,
+            self.can_pon,
+            self.can_daiminkan,
+            self.can_kakan,
+            self.can_ankan,
+            self.can_riichi,
+            self.can_tsumo_agari,
+            self.can_ron_agari,
+            self.can_ryukyoku,
+            self.is_chankan,
+            self.can_nukidora,
+        ];
+        let mut ret = (self.target_actor as u32) << 16;
+        let mut i = 0;
+        while i < flags.len() {
+            ret |= (flags[i] as u32) << i;
+            i += 1;
+        }
+        ret
+    }
+
+    /// The inverse of `as_bitflags`. Unassigned bits are ignored.
+    #[staticmethod]
+    #[inline]
+    #[must_use]
+    #[pyo3(text_signature = "(flags, /)")]
+    pub const fn from_bitflags(flags: u32) -> Self {
+        const fn bit(flags: u32, i: u32) -> bool {
+            flags >> i & 1 == 1
+        }
+        Self {
+            can_discard: bit(flags, 0),
+            can_chi_low: bit(flags, 1),
+            can_chi_mid: bit(flags, 2),
+            can_chi_high: bit(flags, 3),
+            can_pon: bit(flags, 4),
+            can_daiminkan: bit(flags, 5),
+            can_kakan: bit(flags, 6),
+            can_ankan: bit(flags, 7),
+            can_riichi: bit(flags, 8),
+            can_tsumo_agari: bit(flags, 9),
+            can_ron_agari: bit(flags, 10),
+            can_ryukyoku: bit(flags, 11),
+            is_chankan: bit(flags, 12),
+            can_nukidora: bit(flags, 13),
+            target_actor: (flags >> 16) as u8,
+        }
     }
 
     fn __repr__(&self) -> String {
@@ -71,6 +222,395 @@ impl ActionCandidate {
 }
 
 impl PlayerState {
+    /// Returns every reaction allowed in the current state, each of which
+    /// passes `validate_reaction` once turned into an event by
+    /// `reaction_to_event`.
+    ///
+    /// `Pass` is listed whenever the player can react to others, but not at
+    /// its own turn, where a discard is mandatory.
+    ///
+    /// The order is stable: discards, riichi, chi, pon, daiminkan, kakan,
+    /// ankan, nukidora, tsumo, ron, ryukyoku and pass, and within each kind in
+    /// ascending order of tile id, where an aka comes right after the plain
+    /// tile of the same kind.
+    #[must_use]
+    pub fn legal_reactions(&self) -> Vec<Reaction> {
+        let cans = self.last_cans;
+        let mut ret = vec![];
+
+        if cans.can_discard {
+            let candidates = self.discard_candidates_aka();
+            ret.extend(
+                candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &b)| b)
+                    .map(|(tid, _)| Reaction::Discard(must_tile!(tid))),
+            );
+        }
+        if cans.can_riichi {
+            ret.push(Reaction::Riichi);
+        }
+
+        if let Some(pai) = self.last_kawa_tile.filter(|_| cans.can_chi()) {
+            let pai = pai.deaka();
+            let chis = [
+                (cans.can_chi_low, [pai.next(), pai.next().next()]),
+                (cans.can_chi_mid, [pai.prev(), pai.next()]),
+                (cans.can_chi_high, [pai.prev().prev(), pai.prev()]),
+            ];
+            for (_, tiles) in chis.into_iter().filter(|&(can, _)| can) {
+                ret.extend(
+                    self.consumed_variants(tiles)
+                        .into_iter()
+                        .map(|consumed| Reaction::Chi { consumed }),
+                );
+            }
+        }
+        if let Some(pai) = self.last_kawa_tile {
+            let pai = pai.deaka();
+            if cans.can_pon {
+                ret.extend(
+                    self.consumed_variants([pai; 2])
+                        .into_iter()
+                        .map(|consumed| Reaction::Pon { consumed }),
+                );
+            }
+            if cans.can_daiminkan {
+                ret.extend(
+                    self.consumed_variants([pai; 3])
+                        .into_iter()
+                        .map(|consumed| Reaction::Kan { consumed }),
+                );
+            }
+        }
+
+        if cans.can_kakan {
+            for &tile in &self.kakan_candidates {
+                ret.extend(
+                    self.consumed_variants([tile])
+                        .into_iter()
+                        .map(|[tile]| Reaction::Kakan(tile)),
+                );
+            }
+        }
+        if cans.can_ankan {
+            for &tile in &self.ankan_candidates {
+                // All copies are in hand, so there is only one variant.
+                ret.extend(
+                    self.consumed_variants([tile; 4])
+                        .into_iter()
+                        .map(|consumed| Reaction::Ankan { consumed }),
+                );
+            }
+        }
+
+        if cans.can_nukidora {
+            ret.push(Reaction::Nukidora);
+        }
+        if cans.can_tsumo_agari {
+            ret.push(Reaction::Tsumo);
+        }
+        if cans.can_ron_agari {
+            ret.push(Reaction::Ron);
+        }
+        if cans.can_ryukyoku {
+            ret.push(Reaction::Ryukyoku);
+        }
+        if cans.can_act() && !cans.can_discard {
+            ret.push(Reaction::Pass);
+        }
+
+        ret.sort_unstable_by_key(Reaction::order_key);
+        ret
+    }
+
+    /// The safe default to send in place of a reaction that fails
+    /// `validate_reaction`: a tsumogiri if it is legal, otherwise the first
+    /// legal discard, or a pass if the player cannot discard.
+    #[must_use]
+    pub fn fallback_reaction(&self) -> Reaction {
+        if !self.last_cans.can_discard {
+            return Reaction::Pass;
+        }
+        let candidates = self.discard_candidates_aka();
+        if let Some(tile) = self.last_self_tsumo.filter(|t| candidates[t.as_usize()]) {
+            return Reaction::Discard(tile);
+        }
+        candidates
+            .iter()
+            .position(|&b| b)
+            .map_or(Reaction::Pass, |tid| Reaction::Discard(must_tile!(tid)))
+    }
+
+    /// Turns a reaction from `legal_reactions` into the mjai event to send.
+    #[must_use]
+    pub fn reaction_to_event(&self, reaction: Reaction) -> Event {
+        let actor = self.player_id;
+        let target = self.last_cans.target_actor;
+        let kawa_tile = || self.last_kawa_tile.unwrap_or_default();
+        match reaction {
+            Reaction::Discard(pai) => Event::Dahai {
+                actor,
+                pai,
+                tsumogiri: self.last_self_tsumo == Some(pai),
+            },
+            Reaction::Chi { consumed } => Event::Chi {
+                actor,
+                target,
+                pai: kawa_tile(),
+                consumed,
+            },
+            Reaction::Pon { consumed } => Event::Pon {
+                actor,
+                target,
+                pai: kawa_tile(),
+                consumed,
+            },
+            Reaction::Kan { consumed } => Event::Daiminkan {
+                actor,
+                target,
+                pai: kawa_tile(),
+                consumed,
+            },
+            Reaction::Kakan(pai) => {
+                // The pon holds the aka unless it is the one in hand.
+                let consumed = if pai.is_aka() {
+                    [pai.deaka(); 3]
+                } else {
+                    [pai.akaize(), pai, pai]
+                };
+                Event::Kakan {
+                    actor,
+                    pai,
+                    consumed,
+                }
+            }
+            Reaction::Ankan { consumed } => Event::Ankan { actor, consumed },
+            Reaction::Nukidora => Event::Nukidora { actor, pai: t!(N) },
+            Reaction::Riichi => Event::Reach { actor },
+            Reaction::Tsumo | Reaction::Ron => Event::Hora {
+                actor,
+                target,
+                deltas: None,
+                ura_markers: None,
+            },
+            Reaction::Ryukyoku => Event::Ryukyoku {
+                reason: Some(RyukyokuReason::Kyuushukyuuhai),
+                deltas: None,
+                tenpais: None,
+            },
+            Reaction::Pass => Event::None,
+        }
+    }
+
+    /// Returns the mask of the legal actions in the action space of
+    /// `encode_obs`, which is what `encode_obs` returns along with the obs.
+    ///
+    /// | index  | action                                   |
+    /// | ------ | ---------------------------------------- |
+    /// | 0-36   | discard, with 34-36 for the akas         |
+    /// | 37     | riichi                                   |
+    /// | 38-40  | chi low, mid and high                    |
+    /// | 41     | pon                                      |
+    /// | 42     | daiminkan, ankan or kakan                |
+    /// | 43     | tsumo or ron agari                       |
+    /// | 44     | ryukyoku                                 |
+    /// | 45     | pass                                     |
+    /// | 46     | nukidora, in sanma only                  |
+    ///
+    /// The mask is of `action_space`, that is `ACTION_SPACE`, or
+    /// `SANMA_ACTION_SPACE` in sanma. With `at_kan_choice`, only the tiles of
+    /// 0-33 are used, each being the tile to kan.
+    #[must_use]
+    pub fn legal_action_mask(&self, at_kan_choice: bool) -> Vec<bool> {
+        let cans = self.last_cans;
+        let mut mask = vec![false; self.action_space()];
+
+        if at_kan_choice {
+            if cans.can_daiminkan {
+                if let Some(tile) = self.last_kawa_tile {
+                    mask[tile.deaka().as_usize()] = true;
+                }
+            }
+            if cans.can_ankan {
+                for tile in &self.ankan_candidates {
+                    mask[tile.as_usize()] = true;
+                }
+            }
+            if cans.can_kakan {
+                for tile in &self.kakan_candidates {
+                    mask[tile.as_usize()] = true;
+                }
+            }
+            return mask;
+        }
+
+        if cans.can_discard {
+            mask[..37].copy_from_slice(&self.discard_candidates_aka());
+        }
+        mask[37] = cans.can_riichi;
+        mask[38] = cans.can_chi_low;
+        mask[39] = cans.can_chi_mid;
+        mask[40] = cans.can_chi_high;
+        mask[41] = cans.can_pon;
+        mask[42] = cans.can_daiminkan || cans.can_ankan || cans.can_kakan;
+        mask[43] = cans.can_tsumo_agari || cans.can_ron_agari;
+        mask[44] = cans.can_ryukyoku;
+        mask[45] = cans.can_chi() || cans.can_pon || cans.can_daiminkan || cans.can_ron_agari;
+        if self.rules.mode == GameMode::Sanma {
+            mask[46] = cans.can_nukidora;
+        }
+        mask
+    }
+
+    /// The length of `legal_action_mask`.
+    #[inline]
+    #[must_use]
+    pub const fn action_space(&self) -> usize {
+        match self.rules.mode {
+            GameMode::Yonma => ACTION_SPACE,
+            GameMode::Sanma => SANMA_ACTION_SPACE,
+        }
+    }
+
+    /// The inverse of `legal_action_mask`, which turns a legal action into
+    /// the mjai event to send.
+    ///
+    /// Out of the choices of consumed tiles, the one with the most akas is
+    /// taken if `prefer_aka`, or the one with the fewest otherwise. Action 42
+    /// outside of `at_kan_choice` kans the first candidate, daiminkan before
+    /// ankan before kakan.
+    ///
+    /// Err is returned if the action is not legal.
+    pub fn action_to_event(
+        &self,
+        action: usize,
+        at_kan_choice: bool,
+        prefer_aka: bool,
+    ) -> Result<Event> {
+        let mask = self.legal_action_mask(at_kan_choice);
+        ensure!(
+            mask.get(action).copied().unwrap_or_default(),
+            "action {action} is not legal{}\nstate:\n{}",
+            if at_kan_choice { " at kan choice" } else { "" },
+            self.brief_info(),
+        );
+        let cans = self.last_cans;
+        let kawa_tile = || self.last_kawa_tile.unwrap_or_default().deaka();
+
+        let reaction = if at_kan_choice || action == 42 {
+            let tile = if at_kan_choice {
+                must_tile!(action)
+            } else if cans.can_daiminkan {
+                kawa_tile()
+            } else if cans.can_ankan {
+                self.ankan_candidates[0]
+            } else {
+                self.kakan_candidates[0]
+            };
+            if cans.can_daiminkan {
+                Reaction::Kan {
+                    consumed: self.pick_variant([tile; 3], prefer_aka)?,
+                }
+            } else if cans.can_ankan && self.ankan_candidates.contains(&tile) {
+                Reaction::Ankan {
+                    consumed: self.pick_variant([tile; 4], prefer_aka)?,
+                }
+            } else {
+                let [pai] = self.pick_variant([tile], prefer_aka)?;
+                Reaction::Kakan(pai)
+            }
+        } else {
+            match action {
+                0..=36 => Reaction::Discard(must_tile!(action)),
+                37 => Reaction::Riichi,
+                38..=40 => {
+                    let pai = kawa_tile();
+                    let tiles = match action {
+                        38 => [pai.next(), pai.next().next()],
+                        39 => [pai.prev(), pai.next()],
+                        _ => [pai.prev().prev(), pai.prev()],
+                    };
+                    Reaction::Chi {
+                        consumed: self.pick_variant(tiles, prefer_aka)?,
+                    }
+                }
+                41 => Reaction::Pon {
+                    consumed: self.pick_variant([kawa_tile(); 2], prefer_aka)?,
+                },
+                43 if cans.can_tsumo_agari => Reaction::Tsumo,
+                43 => Reaction::Ron,
+                44 => Reaction::Ryukyoku,
+                46 => Reaction::Nukidora,
+                _ => Reaction::Pass,
+            }
+        };
+        Ok(self.reaction_to_event(reaction))
+    }
+
+    /// The variant of `consumed_variants` with the most or the fewest akas.
+    fn pick_variant<const N: usize>(
+        &self,
+        tiles: [Tile; N],
+        prefer_aka: bool,
+    ) -> Result<[Tile; N]> {
+        let akas = |v: &[Tile; N]| v.iter().filter(|t| t.is_aka()).count();
+        let variants = self.consumed_variants(tiles);
+        let picked = if prefer_aka {
+            variants.into_iter().max_by_key(akas)
+        } else {
+            variants.into_iter().min_by_key(akas)
+        };
+        picked.with_context(|| format!("{tiles:?} are not in hand"))
+    }
+
+    /// Returns the distinct ways to take `tiles` from the hand, choosing
+    /// between aka and plain fives. Each aka is placed before the plain ones
+    /// of the same kind.
+    fn consumed_variants<const N: usize>(&self, tiles: [Tile; N]) -> Vec<[Tile; N]> {
+        let mut ret = vec![];
+        'masks: for mask in 0..1_u32 << N {
+            let mut variant = tiles;
+            let mut used = [0_u8; 37];
+            for (i, tile) in variant.iter_mut().enumerate() {
+                let use_aka = mask >> i & 1 == 1;
+                if use_aka {
+                    if !matches!(tile.as_u8(), tu8!(5m) | tu8!(5p) | tu8!(5s)) {
+                        continue 'masks;
+                    }
+                    *tile = tile.akaize();
+                }
+                // An aka after a plain tile of the same kind is a duplicate.
+                if i > 0 && tiles[i - 1] == tiles[i] && use_aka && mask >> (i - 1) & 1 == 0 {
+                    continue 'masks;
+                }
+                used[tile.as_usize()] += 1;
+            }
+            let fits = used.iter().enumerate().all(|(tid, &n)| {
+                if n == 0 {
+                    return true;
+                }
+                let tile = must_tile!(tid);
+                let akas = match tile.deaka().as_u8() {
+                    tu8!(5m) => self.akas_in_hand[0],
+                    tu8!(5p) => self.akas_in_hand[1],
+                    tu8!(5s) => self.akas_in_hand[2],
+                    _ => 0,
+                };
+                if tile.is_aka() {
+                    n <= akas
+                } else {
+                    n <= self.tehai[tile.as_usize()] - akas
+                }
+            });
+            if fits {
+                ret.push(variant);
+            }
+        }
+        ret
+    }
+
     /// Check if `action` is a valid reaction to the current state.
     pub fn validate_reaction(&self, action: &Event) -> Result<()> {
         let cans = self.last_cans;
@@ -100,6 +640,14 @@ impl PlayerState {
             Event::Dahai { pai, tsumogiri, .. } => {
                 ensure!(cans.can_discard, "cannot discard");
                 self.ensure_tiles_in_hand(&[pai])?;
+                ensure!(
+                    !self.forbidden_tiles[pai.deaka().as_usize()],
+                    "cannot discard {pai} due to kuikae",
+                );
+                ensure!(
+                    !self.riichi_forbidden_discards()[pai.as_usize()],
+                    "cannot discard {pai} due to riichi",
+                );
                 if tsumogiri {
                     if let Some(tile) = self.last_self_tsumo {
                         ensure!(tile == pai, "cannot tsumogiri");
@@ -176,6 +724,12 @@ impl PlayerState {
                 self.ensure_tiles_in_hand(&consumed)?;
             }
 
+            Event::Nukidora { pai, .. } => {
+                ensure!(cans.can_nukidora, "cannot nukidora");
+                ensure!(pai == t!(N), "cannot nukidora {pai}");
+                self.ensure_tiles_in_hand(&[pai])?;
+            }
+
             Event::Hora { target, .. } => {
                 if target == self.player_id {
                     ensure!(cans.can_tsumo_agari, "cannot tsumo agari");
@@ -200,7 +754,7 @@ impl PlayerState {
             );
             if tile.is_aka() {
                 ensure!(
-                    self.akas_in_hand[tile.as_usize() - tuz!(5mr)],
+                    self.akas_in_hand[tile.as_usize() - tuz!(5mr)] > 0,
                     "{tile} is not in hand",
                 );
             }
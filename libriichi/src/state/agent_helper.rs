@@ -1,13 +1,302 @@
-use super::PlayerState;
-use crate::algo::agari::AgariCalculator;
+use super::{BlessingRule, KazoeRule, KuitanRule, MultiRonRule, PlayerState, YakumanRule};
+use crate::algo::agari::{Agari, AgariCalculator};
 use crate::algo::point::Point;
 use crate::algo::shanten;
+use crate::algo::yaku::Yaku;
 use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
-use crate::{must_tile, t, tuz};
+use crate::{must_tile, t, tu8, tuz};
+use std::collections::HashMap;
 
 use anyhow::{ensure, Context, Result};
-use tinyvec::array_vec;
+use numpy::PyArray1;
+use pyo3::prelude::*;
+use tinyvec::{array_vec, ArrayVec};
+
+/// A summary of the current tenpai from the player's perspective, returned by
+/// `PlayerState::tenpai_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenpaiInfo {
+    /// Same as `PlayerState::waits`, in ascending order of tile id.
+    pub waits: Vec<Tile>,
+    pub is_furiten: bool,
+    /// Every wait can make a ron with yaku, no matter which tile comes.
+    pub yaku_guaranteed: bool,
+    /// The highest (fu, han) among all the waits, assuming ron and no ura
+    /// dora. Yakuman is written as `(0, 13 * n)`. `None` if no wait has yaku.
+    pub highest_fu_han: Option<(u8, u8)>,
+}
+
+/// The breakdown of an agari, returned by `PlayerState::agari_detail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgariDetail {
+    /// Yakuman is written as `13 * n`.
+    pub han: u8,
+    /// May be 0 if `han` is greater than 4.
+    pub fu: u8,
+    /// Every yaku with its han, in the order of situational yakus, yakus of
+    /// the hand and then doras. Each yakuman is written as 13.
+    pub yakus: Vec<(Yaku, u8)>,
+    pub point: Point,
+}
+
+/// The doras owned by the player, returned by `PlayerState::dora_breakdown`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DoraBreakdown {
+    /// Including the nukidoras, each of which counts as a dora on its own.
+    pub dora: u8,
+    pub aka: u8,
+    pub ura: u8,
+}
+
+/// Why a riichi declarable discard is left out by
+/// `PlayerState::recommended_riichi_discards`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiichiExclusion {
+    /// One of the waits is in the player's own kawa, including the discard
+    /// itself.
+    Furiten,
+    /// Every copy of every wait has been seen by the player.
+    DeadWait,
+}
+
+impl RiichiExclusion {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Furiten => "furiten",
+            Self::DeadWait => "dead_wait",
+        }
+    }
+}
+
+/// Returned by `PlayerState::recommended_riichi_discards`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiichiDiscards {
+    /// Aka dora covered, in the same way as `discard_candidates_aka`.
+    pub discards: [bool; 37],
+    /// The reason of each riichi declarable discard not in `discards`.
+    pub excluded: [Option<RiichiExclusion>; 34],
+}
+
+/// The acceptance (ukeire) of the hand, returned by
+/// `PlayerState::acceptance_profile`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceProfile {
+    /// The discard with the lowest shanten and then the highest acceptance
+    /// afterwards, ties broken by the lower tile id. `None` at 3n+1.
+    pub discard: Option<Tile>,
+    /// The shanten after `discard`.
+    pub shanten: i8,
+    /// The number of unseen copies of each tile that lowers the shanten.
+    pub ukeire: [u8; 34],
+    /// The sum of `ukeire`.
+    pub total: u8,
+    /// The Shannon entropy of `ukeire` normalized by `total`, in nats. 0 if
+    /// `total` is 0.
+    pub entropy: f32,
+    /// How much `total` drops if every copy of the tile with the most
+    /// acceptance vanished from the wall, with the discard chosen again.
+    pub drop_without_best: u8,
+}
+
+/// The structural kind of a wait, see `PlayerState::wait_shapes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WaitKind {
+    Tanki,
+    Shanpon,
+    Kanchan,
+    Penchan,
+    Ryanmen,
+    Chiitoitsu,
+    Kokushi,
+}
+
+/// Returned by `PlayerState::wait_shapes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitShape {
+    pub tile: Tile,
+    /// Every kind the tile completes the hand as, across all the
+    /// decompositions, in the order of `WaitKind`'s variants.
+    pub kinds: Vec<WaitKind>,
+}
+
+/// A block of a decomposition, by its lowest tile id.
+#[derive(Debug, Clone, Copy)]
+enum Block {
+    Pair(usize),
+    Koutsu(usize),
+    Shuntsu(usize),
+}
+
+impl Block {
+    fn wait_kind(self, winning_tile: usize) -> Option<WaitKind> {
+        match self {
+            Self::Pair(tid) if tid == winning_tile => Some(WaitKind::Tanki),
+            Self::Koutsu(tid) if tid == winning_tile => Some(WaitKind::Shanpon),
+            Self::Shuntsu(low) if (low..low + 3).contains(&winning_tile) => {
+                let kind = if winning_tile == low + 1 {
+                    WaitKind::Kanchan
+                } else if (low % 9 == 0 && winning_tile == low + 2)
+                    || (low % 9 == 6 && winning_tile == low)
+                {
+                    WaitKind::Penchan
+                } else {
+                    WaitKind::Ryanmen
+                };
+                Some(kind)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The least dangerous way found to reach tenpai, returned by
+/// `PlayerState::safest_push_line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushLine {
+    /// The discards in order, the last of which leaves the hand tenpai.
+    pub discards: Vec<Tile>,
+    /// The draw assumed after each discard but the last.
+    pub draws: Vec<Tile>,
+    /// The sum of `danger_scores_against` of `discards`.
+    pub danger: u32,
+}
+
+/// Per tile kind safety against an opponent, returned by
+/// `PlayerState::safety_against`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyInfo {
+    /// In the opponent's kawa, or discarded by anyone after the opponent's
+    /// riichi is accepted.
+    pub genbutsu: [bool; 34],
+    /// Suhai whose every ryanmen wait is cut by genbutsu, for example 4m with
+    /// both 1m and 7m being genbutsu, or 1m with 4m being genbutsu.
+    pub suji: [bool; 34],
+    /// Suhai on which no ryanmen wait is possible, because all four copies
+    /// of a tile required by each ryanmen shape are visible (no chance).
+    pub kabe: [bool; 34],
+}
+
+/// An opponent one meld away from a yakuman made of visible melds, returned
+/// by `PlayerState::yakuman_threat_tiles`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YakumanThreat {
+    /// Seat of the opponent relative to the player.
+    pub rel_player: u8,
+    /// The tiles that may complete the yakuman, either by a call or by ron.
+    pub tiles: [bool; 34],
+}
+
+#[pymethods]
+impl PlayerState {
+    /// Returns `(genbutsu, suji, kabe)`, each a 34-D bool array.
+    #[pyo3(name = "safety_against")]
+    #[pyo3(text_signature = "($self, rel_player, /)")]
+    fn safety_against_py<'py>(
+        &self,
+        rel_player: u8,
+        py: Python<'py>,
+    ) -> (
+        &'py PyArray1<bool>,
+        &'py PyArray1<bool>,
+        &'py PyArray1<bool>,
+    ) {
+        let info = self.safety_against(rel_player);
+        (
+            PyArray1::from_slice(py, &info.genbutsu),
+            PyArray1::from_slice(py, &info.suji),
+            PyArray1::from_slice(py, &info.kabe),
+        )
+    }
+
+    /// Returns a 34-D bool array, see `riichi_declarable_discards`.
+    #[pyo3(name = "riichi_declarable_discards")]
+    #[pyo3(text_signature = "($self, *, require_live_wait=False)")]
+    #[args("*", require_live_wait = "false")]
+    fn riichi_declarable_discards_py<'py>(
+        &self,
+        require_live_wait: bool,
+        py: Python<'py>,
+    ) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.riichi_declarable_discards(require_live_wait))
+    }
+
+    /// Returns a 37-D bool array and a dict from each excluded tile to the
+    /// reason, either `furiten` or `dead_wait`, see
+    /// `recommended_riichi_discards`.
+    #[pyo3(name = "recommended_riichi_discards")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn recommended_riichi_discards_py<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> (&'py PyArray1<bool>, HashMap<String, &'static str>) {
+        let ret = self.recommended_riichi_discards();
+        let excluded = ret
+            .excluded
+            .iter()
+            .enumerate()
+            .filter_map(|(tid, reason)| Some((must_tile!(tid).to_string(), reason?.as_str())))
+            .collect();
+        (PyArray1::from_slice(py, &ret.discards), excluded)
+    }
+
+    /// Returns `(to_above, to_below)`, see `score_pressure`.
+    #[pyo3(name = "score_pressure")]
+    #[args(swing_per_hand, "*", game_length = "8")]
+    #[pyo3(text_signature = "($self, swing_per_hand, *, game_length = 8)")]
+    fn score_pressure_py(&self, swing_per_hand: f32, game_length: u8) -> (f32, f32) {
+        let [to_above, to_below] = self.score_pressure(swing_per_hand, game_length);
+        (to_above, to_below)
+    }
+
+    /// Returns `(discards, draws, danger)` with the tiles in mjai notation,
+    /// or `None` if tenpai cannot be reached, see `safest_push_line`.
+    #[pyo3(name = "safest_push_line")]
+    #[pyo3(text_signature = "($self, depth, rel_opponent, /)")]
+    fn safest_push_line_py(
+        &self,
+        depth: u8,
+        rel_opponent: u8,
+    ) -> Result<Option<(Vec<String>, Vec<String>, u32)>> {
+        ensure!(self.last_cans.can_discard, "tehai is not 3n+2");
+        ensure!(
+            (1..=3).contains(&depth),
+            "depth {depth} is not in range [1, 3]",
+        );
+        ensure!(rel_opponent < 4, "{rel_opponent} is not in range [0, 3]");
+        let names = |tiles: Vec<Tile>| tiles.iter().map(ToString::to_string).collect();
+        Ok(self
+            .safest_push_line(depth, rel_opponent)
+            .map(|line| (names(line.discards), names(line.draws), line.danger)))
+    }
+
+    /// Returns a list of `(rel_player, tiles)`, where `tiles` is a 34-D bool
+    /// array, see `yakuman_threat_tiles`.
+    #[pyo3(name = "yakuman_threat_tiles")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn yakuman_threat_tiles_py<'py>(&self, py: Python<'py>) -> Vec<(u8, &'py PyArray1<bool>)> {
+        self.yakuman_threat_tiles()
+            .into_iter()
+            .map(|threat| (threat.rel_player, PyArray1::from_slice(py, &threat.tiles)))
+            .collect()
+    }
+
+    /// Returns a 34-D uint8 array, see `discard_tenpai_waits`.
+    #[pyo3(name = "discard_tenpai_waits")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn discard_tenpai_waits_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<u8> {
+        PyArray1::from_slice(py, &self.discard_tenpai_waits())
+    }
+
+    /// Returns a 34-D int8 array, see `shanten_after_discard`.
+    #[pyo3(name = "shanten_after_discard")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn shanten_after_discard_py<'py>(&self, py: Python<'py>) -> Result<&'py PyArray1<i8>> {
+        ensure!(self.last_cans.can_discard, "tehai is not 3n+2");
+        Ok(PyArray1::from_slice(py, &self.shanten_after_discard()))
+    }
+}
 
 impl PlayerState {
     /// Used by `BoardState` to check if a player is making 4 kans on his own.
@@ -60,17 +349,146 @@ impl PlayerState {
             };
         }
 
-        if ret[tuz!(5m)] && self.akas_in_hand[0] {
+        if ret[tuz!(5m)] && self.akas_in_hand[0] > 0 {
             ret[tuz!(5mr)] = true;
-            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > 1;
+            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > self.akas_in_hand[0];
         }
-        if ret[tuz!(5p)] && self.akas_in_hand[1] {
+        if ret[tuz!(5p)] && self.akas_in_hand[1] > 0 {
             ret[tuz!(5pr)] = true;
-            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > 1;
+            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > self.akas_in_hand[1];
         }
-        if ret[tuz!(5s)] && self.akas_in_hand[2] {
+        if ret[tuz!(5s)] && self.akas_in_hand[2] > 0 {
             ret[tuz!(5sr)] = true;
-            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > 1;
+            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > self.akas_in_hand[2];
+        }
+
+        ret
+    }
+
+    /// The tiles in hand, aka dora covered, that the player's own riichi
+    /// forbids discarding now, that is, every tile but the tsumo after the
+    /// riichi is accepted, and every tile breaking the tenpai when declaring
+    /// it. All false if the player cannot discard or has not declared riichi.
+    #[must_use]
+    pub fn riichi_forbidden_discards(&self) -> [bool; 37] {
+        let mut ret = [false; 37];
+        if !self.last_cans.can_discard || !self.riichi_declared[0] {
+            return ret;
+        }
+
+        let candidates = self.discard_candidates_aka();
+        for (i, count) in self.tehai.iter().copied().enumerate() {
+            ret[i] = count > 0 && !candidates[i];
+        }
+        for (i, (five, aka)) in [
+            (tuz!(5m), tuz!(5mr)),
+            (tuz!(5p), tuz!(5pr)),
+            (tuz!(5s), tuz!(5sr)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let akas = self.akas_in_hand[i];
+            ret[five] &= self.tehai[five] > akas;
+            ret[aka] = akas > 0 && !candidates[aka];
+        }
+        ret
+    }
+
+    /// The tiles that can be discarded along with a riichi declaration, that
+    /// is, the ones leaving the closed hand at tenpai. All false if riichi
+    /// cannot be declared now.
+    ///
+    /// Unlike `discard_candidates_with_unconditional_tenpai`, the tenpai does
+    /// not need to have a yaku or to be free of furiten. If
+    /// `require_live_wait` is true, at least one of the waits must have a
+    /// copy the player has not seen, which rules out waiting only on tiles
+    /// already in the player's own hand, kawa and so on.
+    #[must_use]
+    pub fn riichi_declarable_discards(&self, require_live_wait: bool) -> [bool; 34] {
+        let mut ret = [false; 34];
+        if !self.last_cans.can_riichi {
+            return ret;
+        }
+
+        let tenpai_discards = if self.shanten == 1 {
+            self.next_shanten_discards
+        } else {
+            self.keep_shanten_discards
+        };
+        for (discard, _) in tenpai_discards.iter().enumerate().filter(|(_, &b)| b) {
+            if !require_live_wait {
+                ret[discard] = true;
+                continue;
+            }
+
+            let mut tehai_3n1 = self.tehai;
+            tehai_3n1[discard] -= 1;
+            ret[discard] = (0..34).any(|tsumo| {
+                if self.tiles_seen[tsumo] >= 4 {
+                    return false;
+                }
+                let mut tehai_3n2 = tehai_3n1;
+                tehai_3n2[tsumo] += 1;
+                shanten::calc_all(&tehai_3n2, self.tehai_len_div3) == -1
+            });
+        }
+        ret
+    }
+
+    /// The riichi discards an agent usually wants, that is, the ones whose
+    /// wait is not furiten and has at least one copy the player has not seen.
+    ///
+    /// Works both before the riichi declaration, as a subset of
+    /// `riichi_declarable_discards`, and right after it, when the discard
+    /// is yet to be made. All false otherwise.
+    #[must_use]
+    pub fn recommended_riichi_discards(&self) -> RiichiDiscards {
+        let mut ret = RiichiDiscards {
+            discards: [false; 37],
+            excluded: [None; 34],
+        };
+
+        let declarable = if self.last_cans.can_riichi {
+            self.riichi_declarable_discards(false)
+        } else if self.last_cans.can_discard && self.riichi_declared[0] && !self.riichi_accepted[0]
+        {
+            self.discard_candidates()
+        } else {
+            return ret;
+        };
+
+        for (discard, _) in declarable.iter().enumerate().filter(|(_, &b)| b) {
+            let mut tehai_3n1 = self.tehai;
+            tehai_3n1[discard] -= 1;
+            let waits: ArrayVec<[usize; 13]> = (0..34)
+                .filter(|&tsumo| {
+                    let mut tehai_3n2 = tehai_3n1;
+                    tehai_3n2[tsumo] += 1;
+                    tehai_3n2[tsumo] <= 4
+                        && shanten::calc_all(&tehai_3n2, self.tehai_len_div3) == -1
+                })
+                .collect();
+
+            let exclusion = if waits
+                .iter()
+                .any(|&w| w == discard || self.discarded_tiles[w])
+            {
+                Some(RiichiExclusion::Furiten)
+            } else if waits.iter().all(|&w| self.tiles_seen[w] >= 4) {
+                Some(RiichiExclusion::DeadWait)
+            } else {
+                None
+            };
+            ret.excluded[discard] = exclusion;
+            ret.discards[discard] = exclusion.is_none();
+        }
+
+        for (aka_idx, five) in [tuz!(5m), tuz!(5p), tuz!(5s)].into_iter().enumerate() {
+            if ret.discards[five] && self.akas_in_hand[aka_idx] > 0 {
+                ret.discards[tuz!(5mr) + aka_idx] = true;
+                ret.discards[five] = self.tehai[five] > self.akas_in_hand[aka_idx];
+            }
         }
 
         ret
@@ -124,18 +542,11 @@ impl PlayerState {
             return ret;
         }
 
-        let tenpai_discards = if self.shanten == 1 {
-            self.next_shanten_discards
-        } else {
-            self.keep_shanten_discards
-        };
-
         // Replace and test
-        tenpai_discards
+        self.tenpai_discards()
             .iter()
-            .copied()
             .enumerate()
-            .filter(|&(tid, b)| b && !self.forbidden_tiles[tid])
+            .filter(|(_, &b)| b)
             .for_each(|(discard, _)| {
                 let mut tehai_3n1 = self.tehai;
                 tehai_3n1[discard] -= 1;
@@ -173,27 +584,577 @@ impl PlayerState {
                         jikaze: self.jikaze.as_u8(),
                         winning_tile: tsumo as u8,
                         is_ron: true,
+                        allow_kuitan: self.rules.kuitan == KuitanRule::Allowed,
                     };
                     ret[discard] = agari_calc.has_yaku();
                 }
             });
 
-        if ret[tuz!(5m)] && self.akas_in_hand[0] {
+        if ret[tuz!(5m)] && self.akas_in_hand[0] > 0 {
             ret[tuz!(5mr)] = true;
-            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > 1;
+            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > self.akas_in_hand[0];
         }
-        if ret[tuz!(5p)] && self.akas_in_hand[1] {
+        if ret[tuz!(5p)] && self.akas_in_hand[1] > 0 {
             ret[tuz!(5pr)] = true;
-            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > 1;
+            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > self.akas_in_hand[1];
         }
-        if ret[tuz!(5s)] && self.akas_in_hand[2] {
+        if ret[tuz!(5s)] && self.akas_in_hand[2] > 0 {
             ret[tuz!(5sr)] = true;
-            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > 1;
+            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > self.akas_in_hand[2];
+        }
+
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// The return value is the number of kinds of tiles waited on after
+    /// discarding each tile, or 0 if the hand is not tenpai after that. Unlike
+    /// `discard_candidates_with_unconditional_tenpai`, yaku, furiten and the
+    /// tiles seen are not considered.
+    #[must_use]
+    pub fn discard_tenpai_waits(&self) -> [u8; 34] {
+        assert!(self.last_cans.can_discard, "tehai is not 3n+2");
+
+        let mut ret = [0; 34];
+        self.tenpai_discards()
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b)
+            .for_each(|(discard, _)| {
+                let mut tehai_3n1 = self.tehai;
+                tehai_3n1[discard] -= 1;
+
+                ret[discard] = (0..34)
+                    .filter(|&tsumo| {
+                        if tehai_3n1[tsumo] == 4 {
+                            return false;
+                        }
+                        let mut tehai_3n2 = tehai_3n1;
+                        tehai_3n2[tsumo] += 1;
+                        shanten::calc_all(&tehai_3n2, self.tehai_len_div3) == -1
+                    })
+                    .count() as u8;
+            });
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// The return value is the shanten after discarding each tile, or
+    /// `i8::MAX` for the tiles not in hand. Kuikae is not considered.
+    #[must_use]
+    pub fn shanten_after_discard(&self) -> [i8; 34] {
+        assert!(self.last_cans.can_discard, "tehai is not 3n+2");
+
+        let mut ret = [i8::MAX; 34];
+        for (tid, _) in self.tehai.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let mut tehai_3n1 = self.tehai;
+            tehai_3n1[tid] -= 1;
+            ret[tid] = shanten::calc_all(&tehai_3n1, self.tehai_len_div3);
+        }
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// The discards that may make the hand tenpai, kuikae considered.
+    fn tenpai_discards(&self) -> [bool; 34] {
+        let mut ret = match self.shanten {
+            0 => self.keep_shanten_discards,
+            1 => self.next_shanten_discards,
+            _ => return [false; 34],
+        };
+        ret.iter_mut()
+            .zip(self.forbidden_tiles)
+            .for_each(|(b, forbidden)| *b &= !forbidden);
+        ret
+    }
+
+    /// Must be called at 3n+1.
+    ///
+    /// `None` is returned if the hand is not tenpai.
+    #[must_use]
+    pub fn tenpai_info(&self) -> Option<TenpaiInfo> {
+        assert!(!self.last_cans.can_discard, "tehai is not 3n+1");
+
+        if self.shanten > 0 {
+            return None;
+        }
+
+        let additional_hans = [self.riichi_accepted[0], self.is_w_riichi]
+            .iter()
+            .filter(|&&b| b)
+            .count() as u8;
+
+        let mut waits = vec![];
+        let mut yaku_guaranteed = true;
+        let mut highest = None;
+        for (tid, _) in self.waits.iter().enumerate().filter(|(_, &b)| b) {
+            waits.push(must_tile!(tid));
+
+            let mut tehai = self.tehai;
+            tehai[tid] += 1;
+            let agari_calc = AgariCalculator {
+                tehai: &tehai,
+                is_menzen: self.is_menzen,
+                chis: &self.chis,
+                pons: &self.pons,
+                minkans: &self.minkans,
+                ankans: &self.ankans,
+                bakaze: self.bakaze.as_u8(),
+                jikaze: self.jikaze.as_u8(),
+                winning_tile: tid as u8,
+                is_ron: true,
+                allow_kuitan: self.rules.kuitan == KuitanRule::Allowed,
+            };
+            let doras = self.doras_owned[0] + self.dora_factor[tid];
+            match agari_calc.agari(additional_hans, doras) {
+                Some(agari) => highest = highest.max(Some(agari)),
+                None => yaku_guaranteed = false,
+            }
+        }
+
+        let highest_fu_han = highest.map(|agari| match agari {
+            Agari::Normal { fu, han } => (fu, han),
+            Agari::Yakuman(n) => (0, 13 * n),
+        });
+
+        Some(TenpaiInfo {
+            yaku_guaranteed: yaku_guaranteed && !waits.is_empty(),
+            waits,
+            is_furiten: self.at_furiten,
+            highest_fu_han,
+        })
+    }
+
+    /// Must be called at 3n+1.
+    ///
+    /// A wait is reported as every kind it can complete the hand as, for
+    /// example 3s of 2344445666678s is both a kanchan of 24s and a ryanmen of
+    /// 45s.
+    ///
+    /// The shapes are in ascending order of tile id, and the kinds of each
+    /// are sorted.
+    #[must_use]
+    pub fn wait_shapes(&self) -> Vec<WaitShape> {
+        assert!(!self.last_cans.can_discard, "tehai is not 3n+1");
+
+        self.waits
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b)
+            .map(|(tid, _)| {
+                let mut tehai = self.tehai;
+                tehai[tid] += 1;
+
+                let mut kinds = vec![];
+                collect_wait_kinds(&mut tehai, tid, false, &mut vec![], &mut kinds);
+                if self.tehai_len_div3 == 4 {
+                    if shanten::calc_chitoi(&tehai) == -1 {
+                        kinds.push(WaitKind::Chiitoitsu);
+                    }
+                    if shanten::calc_kokushi(&tehai) == -1 {
+                        kinds.push(WaitKind::Kokushi);
+                    }
+                }
+                kinds.sort_unstable();
+
+                WaitShape {
+                    tile: must_tile!(tid),
+                    kinds,
+                }
+            })
+            .collect()
+    }
+
+    /// Works at both 3n+1 and 3n+2. At 3n+2, the hand is measured after the
+    /// best one of `discard_candidates`, see `AcceptanceProfile::discard`.
+    ///
+    /// A hand with many overlapping waits stays wide when a tile runs out,
+    /// while one that depends on a few kanchan does not, which is what
+    /// `entropy` and `drop_without_best` tell apart.
+    #[must_use]
+    pub fn acceptance_profile(&self) -> AcceptanceProfile {
+        let unseen = self.tiles_unseen();
+        let ukeire_of = |tehai_3n1: &[u8; 34]| {
+            let shanten = shanten::calc_all(tehai_3n1, self.tehai_len_div3);
+            let mut ukeire = [0; 34];
+            for (tid, &count) in unseen.iter().enumerate().filter(|(_, &c)| c > 0) {
+                let mut tehai_3n2 = *tehai_3n1;
+                tehai_3n2[tid] += 1;
+                if shanten::calc_all(&tehai_3n2, self.tehai_len_div3) < shanten {
+                    ukeire[tid] = count;
+                }
+            }
+            (shanten, ukeire)
+        };
+
+        // (discard, shanten, ukeire) of every candidate.
+        let candidates: Vec<_> = if self.last_cans.can_discard {
+            self.discard_candidates()
+                .iter()
+                .enumerate()
+                .filter(|(_, &b)| b)
+                .map(|(tid, _)| {
+                    let mut tehai_3n1 = self.tehai;
+                    tehai_3n1[tid] -= 1;
+                    let (shanten, ukeire) = ukeire_of(&tehai_3n1);
+                    (Some(must_tile!(tid)), shanten, ukeire)
+                })
+                .collect()
+        } else {
+            let (shanten, ukeire) = ukeire_of(&self.tehai);
+            vec![(None, shanten, ukeire)]
+        };
+        let min_shanten = candidates
+            .iter()
+            .map(|&(_, shanten, _)| shanten)
+            .min()
+            .expect("no discard candidate");
+        let candidates: Vec<_> = candidates
+            .into_iter()
+            .filter(|&(_, shanten, _)| shanten == min_shanten)
+            .collect();
+        let sum = |ukeire: &[u8; 34]| ukeire.iter().sum::<u8>();
+
+        // `max_by_key` picks the last of the maximums, so iterate backwards to
+        // prefer the lower tile id.
+        let &(discard, shanten, ukeire) = candidates
+            .iter()
+            .rev()
+            .max_by_key(|(_, _, ukeire)| sum(ukeire))
+            .unwrap();
+        let total = sum(&ukeire);
+
+        let entropy = ukeire
+            .iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| {
+                let p = c as f32 / total as f32;
+                -p * p.ln()
+            })
+            .sum();
+
+        let best_tile = ukeire
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, &c)| c)
+            .map(|(tid, _)| tid)
+            .unwrap();
+        let total_without_best = candidates
+            .iter()
+            .map(|(_, _, ukeire)| sum(ukeire) - ukeire[best_tile])
+            .max()
+            .unwrap();
+
+        AcceptanceProfile {
+            discard,
+            shanten,
+            ukeire,
+            total,
+            entropy,
+            drop_without_best: total - total_without_best,
+        }
+    }
+
+    /// `rel_player` is the seat of the opponent relative to the player.
+    ///
+    /// Panics if `rel_player` is outside of range [0, 3].
+    #[must_use]
+    pub fn safety_against(&self, rel_player: u8) -> SafetyInfo {
+        assert!(rel_player < 4, "{rel_player} is not in range [0, 3]");
+        let rel = rel_player as usize;
+
+        let mut genbutsu = self.passed_after_riichi[rel];
+        for tile in &self.kawa_overview[rel] {
+            genbutsu[tile.deaka().as_usize()] = true;
+        }
+
+        let mut suji = [false; 34];
+        let mut kabe = [false; 34];
+        for kind in 0..3 {
+            let base = kind * 9;
+            // A ryanmen wait on `num` is either `(num - 2, num - 1)` waiting on
+            // `num` and `num - 3`, or `(num + 1, num + 2)` waiting on `num`
+            // and `num + 3`.
+            for num in 0..9 {
+                let tid = base + num;
+                let lower = (num >= 3).then(|| (base + num - 3, [tid - 2, tid - 1]));
+                let upper = (num <= 5).then(|| (base + num + 3, [tid + 1, tid + 2]));
+                let shapes = [lower, upper];
+
+                suji[tid] = shapes.iter().flatten().all(|&(other, _)| genbutsu[other]);
+                kabe[tid] = shapes
+                    .iter()
+                    .flatten()
+                    .all(|(_, shape)| shape.iter().any(|&t| self.tiles_seen[t] >= 4));
+            }
         }
 
+        SafetyInfo {
+            genbutsu,
+            suji,
+            kabe,
+        }
+    }
+
+    /// A rough score of how dangerous discarding each tile is against
+    /// `rel_player`, based on `safety_against`:
+    ///
+    /// | tile                        | score |
+    /// | --------------------------- | ----- |
+    /// | genbutsu                    | 0     |
+    /// | jihai with 3 copies visible | 1     |
+    /// | suji or kabe                | 2     |
+    /// | other jihai                 | 3     |
+    /// | other 1 and 9               | 4     |
+    /// | other 2 and 8               | 5     |
+    /// | other 3 to 7                | 6     |
+    /// | yakuman threat              | 10    |
+    ///
+    /// A yakuman threat is a tile of `yakuman_threat_tiles` of `rel_player`,
+    /// which takes precedence over the rest, since it can be called even if
+    /// it is genbutsu.
+    ///
+    /// Panics if `rel_player` is outside of range [0, 3].
+    #[must_use]
+    pub fn danger_scores_against(&self, rel_player: u8) -> [u8; 34] {
+        let info = self.safety_against(rel_player);
+        let mut ret = [0; 34];
+        for (tid, score) in ret.iter_mut().enumerate() {
+            *score = if info.genbutsu[tid] {
+                0
+            } else if tid >= tuz!(E) {
+                if self.tiles_seen[tid] >= 3 {
+                    1
+                } else {
+                    3
+                }
+            } else if info.suji[tid] || info.kabe[tid] {
+                2
+            } else {
+                match tid % 9 {
+                    0 | 8 => 4,
+                    1 | 7 => 5,
+                    _ => 6,
+                }
+            };
+        }
+
+        let threats = self.yakuman_threat_tiles();
+        if let Some(threat) = threats.iter().find(|t| t.rel_player == rel_player) {
+            for (score, _) in ret.iter_mut().zip(threat.tiles).filter(|(_, b)| *b) {
+                *score = 10;
+            }
+        }
         ret
     }
 
+    /// Returns the opponents who are one meld away from a yakuman made of
+    /// their visible melds, along with the tiles that may complete it:
+    ///
+    /// - 大三元: with two of the dragons ponned or kanned, the third one,
+    ///   unless two or more of its copies are visible.
+    /// - 小四喜 and 大四喜: with three of the winds ponned or kanned, the
+    ///   fourth one, unless three or more of its copies are visible.
+    ///
+    /// Concealed threats such as 四暗刻 are not considered. The opponents are
+    /// in ascending order of relative seat, and those with no threat are
+    /// omitted.
+    #[must_use]
+    pub fn yakuman_threat_tiles(&self) -> Vec<YakumanThreat> {
+        // (tile kinds, melds required, max copies visible)
+        let yakumans = [(tuz!(P)..=tuz!(C), 2, 1), (tuz!(E)..=tuz!(N), 3, 2)];
+
+        let mut ret = vec![];
+        for rel in 1..4 {
+            let mut melded = [false; 34];
+            for fuuro in &self.fuuro_overview[rel] {
+                let first = fuuro[0].deaka();
+                if fuuro.iter().all(|t| t.deaka() == first) {
+                    melded[first.as_usize()] = true;
+                }
+            }
+            for ankan in &self.ankan_overview[rel] {
+                melded[ankan.deaka().as_usize()] = true;
+            }
+
+            let mut tiles = [false; 34];
+            for (kinds, required, max_visible) in yakumans.clone() {
+                if kinds.clone().filter(|&tid| melded[tid]).count() < required {
+                    continue;
+                }
+                for tid in kinds.filter(|&tid| !melded[tid]) {
+                    tiles[tid] = self.tiles_seen[tid] <= max_visible;
+                }
+            }
+            if tiles.contains(&true) {
+                ret.push(YakumanThreat {
+                    rel_player: rel as u8,
+                    tiles,
+                });
+            }
+        }
+        ret
+    }
+
+    /// Must be called at 3n+2, with `depth` in range [1, 3].
+    ///
+    /// Searches the lines of at most `depth` discards that reach tenpai for
+    /// the one with the least total `danger_scores_against` `rel_opponent`,
+    /// which is the safest way to keep attacking. Each discard keeps the
+    /// lowest shanten, and each draw in between is an unseen tile that lowers
+    /// the shanten. The draws are the most convenient ones, not an
+    /// expectation over the wall. Ties are broken by the lower tile ids.
+    ///
+    /// Returns `None` if tenpai cannot be reached within `depth` discards.
+    /// This is expensive at depth 3 and meant to be called explicitly.
+    ///
+    /// Panics if `depth` or `rel_opponent` is out of range.
+    #[must_use]
+    pub fn safest_push_line(&self, depth: u8, rel_opponent: u8) -> Option<PushLine> {
+        assert!(self.last_cans.can_discard, "tehai is not 3n+2");
+        assert!(
+            (1..=3).contains(&depth),
+            "depth {depth} is not in range [1, 3]",
+        );
+
+        let danger = self.danger_scores_against(rel_opponent);
+        let mut tehai = self.tehai;
+        let mut unseen = self.tiles_unseen();
+        let mut line = self.search_push_line(
+            &mut tehai,
+            &mut unseen,
+            &self.discard_candidates(),
+            depth,
+            &danger,
+            u32::MAX,
+        )?;
+        line.discards.reverse();
+        line.draws.reverse();
+        Some(line)
+    }
+
+    /// Returns the line with `discards` and `draws` in reverse order, whose
+    /// danger is less than `bound`.
+    fn search_push_line(
+        &self,
+        tehai: &mut [u8; 34],
+        unseen: &mut [u8; 34],
+        candidates: &[bool; 34],
+        depth: u8,
+        danger: &[u8; 34],
+        bound: u32,
+    ) -> Option<PushLine> {
+        // (discard, shanten after it) of every candidate.
+        let in_hand: Vec<_> = (0..34)
+            .filter(|&tid| candidates[tid] && tehai[tid] > 0)
+            .collect();
+        let discards: Vec<_> = in_hand
+            .into_iter()
+            .map(|tid| {
+                tehai[tid] -= 1;
+                let shanten = shanten::calc_all(tehai, self.tehai_len_div3);
+                tehai[tid] += 1;
+                (tid, shanten)
+            })
+            .collect();
+        let min_shanten = discards.iter().map(|&(_, shanten)| shanten).min()?;
+        // Each draw lowers the shanten by at most one.
+        if min_shanten >= depth as i8 {
+            return None;
+        }
+
+        let mut best: Option<PushLine> = None;
+        for (discard, _) in discards.into_iter().filter(|&(_, s)| s == min_shanten) {
+            let bound = best.as_ref().map_or(bound, |line| line.danger);
+            let cost = u32::from(danger[discard]);
+            if cost >= bound {
+                continue;
+            }
+            if min_shanten == 0 {
+                best = Some(PushLine {
+                    discards: vec![must_tile!(discard)],
+                    draws: vec![],
+                    danger: cost,
+                });
+            } else {
+                tehai[discard] -= 1;
+                let draws: Vec<_> = (0..34).filter(|&tid| unseen[tid] > 0).collect();
+                for draw in draws {
+                    tehai[draw] += 1;
+                    if shanten::calc_all(tehai, self.tehai_len_div3) < min_shanten {
+                        unseen[draw] -= 1;
+                        let bound = best.as_ref().map_or(bound, |line| line.danger);
+                        let sub = self.search_push_line(
+                            tehai,
+                            unseen,
+                            &[true; 34],
+                            depth - 1,
+                            danger,
+                            bound - cost,
+                        );
+                        unseen[draw] += 1;
+                        if let Some(mut line) = sub {
+                            line.discards.push(must_tile!(discard));
+                            line.draws.push(must_tile!(draw));
+                            line.danger += cost;
+                            best = Some(line);
+                        }
+                    }
+                    tehai[draw] -= 1;
+                }
+                tehai[discard] += 1;
+            }
+        }
+        best
+    }
+
+    /// The number of chi, pon or daiminkan the player can still make in this
+    /// kyoku. After that many calls, only one tile is left in the concealed
+    /// part of tehai.
+    #[inline]
+    #[must_use]
+    pub const fn max_additional_calls(&self) -> u8 {
+        self.tehai_len_div3
+    }
+
+    /// Must be called at 3n+1, before making a chi, pon or daiminkan with
+    /// `consumed`.
+    ///
+    /// Returns true if the call will lock the hand, that is, the concealed
+    /// part left after the call can no longer be completed into sets plus a
+    /// pair, with the tiles that are still unseen and the number of draws
+    /// left in this kyoku. The number of draws is overestimated, so a hand
+    /// that is reported locked is definitely locked.
+    ///
+    /// Err is returned if `consumed` is not in tehai.
+    pub fn is_call_locking(&self, consumed: &[Tile]) -> Result<bool> {
+        ensure!(!self.last_cans.can_discard, "tehai is not 3n+1");
+        if self.max_additional_calls() == 0 {
+            return Ok(true);
+        }
+
+        let mut concealed = self.tehai;
+        for tile in consumed {
+            let count = &mut concealed[tile.deaka().as_usize()];
+            ensure!(*count > 0, "{tile} is not in tehai");
+            *count -= 1;
+        }
+        let mut unseen = self.tiles_unseen();
+
+        // Own draws left are at most ceil(tiles_left / players), plus a rinshan
+        // draw for daiminkan, and the final tile may also come from a ron.
+        let num_players = self.rules.mode.num_players();
+        let max_draws = self.tiles_left.div_ceil(num_players) + u8::from(consumed.len() == 3) + 1;
+        let sets = self.tehai_len_div3 - 1;
+        let completable = can_complete(&mut concealed, &mut unseen, 0, sets, true, max_draws);
+
+        Ok(!completable)
+    }
+
     #[inline]
     #[must_use]
     pub fn yaokyuu_kind_count(&self) -> u8 {
@@ -203,6 +1164,44 @@ impl PlayerState {
             .sum()
     }
 
+    /// The number of hands left including the current one in a game of
+    /// `game_length` kyokus, 8 for hanchan and 4 for tonpuu, assuming no
+    /// renchan. It is 1 at all-last, and in every kyoku of the extra round
+    /// after it, which is sudden death.
+    #[must_use]
+    pub fn hands_left(&self, game_length: u8) -> u8 {
+        // `kyoku` may exceed 3 in logs that count the repeated oya.
+        let played = self.bakaze.as_u8().saturating_sub(tu8!(E)) * 4 + self.kyoku.min(3);
+        game_length.saturating_sub(played).max(1)
+    }
+
+    /// Returns `[to_above, to_below]`, the point gaps from the player to the
+    /// players right above and right below in rank, normalized by
+    /// `swing_per_hand * hands_left(game_length)`, where `swing_per_hand` is
+    /// a rough number of points that can move in a hand, for example 8000.
+    ///
+    /// `to_above` is never positive and `to_below` is never negative. A gap
+    /// that does not exist, such as `to_above` of the top, is 0.
+    #[must_use]
+    pub fn score_pressure(&self, swing_per_hand: f32, game_length: u8) -> [f32; 2] {
+        // Same as `get_rank`, ties are broken by the absolute seat.
+        let mut by_rank: Vec<_> = (0..self.rules.mode.num_players())
+            .map(|abs| self.scores[self.rel(abs)])
+            .collect();
+        by_rank.sort_by_key(|&s| -s);
+        let rank = self.rank as usize;
+        let me = self.scores[0];
+
+        let scale = swing_per_hand * self.hands_left(game_length) as f32;
+        let to_above = rank
+            .checked_sub(1)
+            .map_or(0., |r| (me - by_rank[r]) as f32 / scale);
+        let to_below = by_rank
+            .get(rank + 1)
+            .map_or(0., |&s| (me - s) as f32 / scale);
+        [to_above, to_below]
+    }
+
     #[inline]
     #[must_use]
     pub fn rule_based_ryukyoku(&self) -> bool {
@@ -230,7 +1229,7 @@ impl PlayerState {
             // Ryukyoku if it is all-last and we are oya or we are not the last,
             // because it is hard to decide whether it is appropriate to not
             // ryukyoku.
-            if self.oya == 0 || self.rank < 3 {
+            if self.oya == 0 || self.rank < self.last_rank() {
                 return true;
             }
 
@@ -240,7 +1239,7 @@ impl PlayerState {
             scores[0] = 12000 + self.kyotaku as i32 * 1000 + self.honba as i32 * 300;
             scores[self.oya as usize] = -6000 - self.honba as i32 * 300;
             vec_add_assign(&mut scores, &self.scores);
-            return self.get_rank(&scores) < 3;
+            return self.get_rank(&scores) < self.last_rank();
         }
 
         // Do not ryukyoku if we have >= 10 yaokyuu tiles.
@@ -271,7 +1270,7 @@ impl PlayerState {
     fn rule_based_agari_slow(&self, is_ron: bool, target: u8) -> bool {
         // Agari if it is not yet all-last, or we are oya ourselves, or we are
         // not the last place at all.
-        if !self.is_all_last || self.oya == 0 || self.rank < 3 {
+        if !self.is_all_last || self.oya == 0 || self.rank < self.last_rank() {
             return true;
         }
 
@@ -355,7 +1354,12 @@ impl PlayerState {
 
         // Agari if the best post-hora situation in theory will make us avoid
         // taking the last place.
-        self.get_rank(&exp_scores) < 3
+        self.get_rank(&exp_scores) < self.last_rank()
+    }
+
+    /// The rank of the last place, 3 in yonma and 2 in sanma.
+    const fn last_rank(&self) -> u8 {
+        self.rules.mode.num_players() - 1
     }
 
     /// Err is returned if the hand cannot agari, or cannot retrieve the winning
@@ -366,17 +1370,158 @@ impl PlayerState {
     ///
     /// `ura_indicators` is only used when the actor has an accepted riichi.
     pub fn agari_points(&self, is_ron: bool, ura_indicators: &[Tile]) -> Result<Point> {
+        let (agari, _) = self.agari_with_yakus(is_ron, ura_indicators)?;
+        Ok(self.point_of(agari))
+    }
+
+    /// Same as `agari_points`, but returns the Δscore of each player in
+    /// relative seats, which includes the honba and kyotaku, and the payment
+    /// of the liable player if any, as in the `deltas` of `hora` by `Board`.
+    ///
+    /// `from` is the relative seat dealing in on ron, and is ignored on
+    /// tsumo. On a multi-ron, the winners are expected to be updated with the
+    /// `hora` of those before them in turn order after the discarder, as only
+    /// the first one takes the kyotaku, and the honba too unless
+    /// `MultiRonRule::HonbaToEach`.
+    ///
+    /// In sanma the empty seat neither pays nor receives, so a tsumo earns
+    /// only what the two others pay (ツモ損).
+    pub fn agari_deltas(
+        &self,
+        is_ron: bool,
+        from: u8,
+        ura_indicators: &[Tile],
+    ) -> Result<[i32; 4]> {
+        let point = self.agari_points(is_ron, ura_indicators)?;
+        let pao = self.pao_liable_seat();
+        let is_first = self.horas == 0;
+        let honba = if is_first || self.rules.multi_ron == MultiRonRule::HonbaToEach {
+            self.honba as i32
+        } else {
+            0
+        };
+        let kyotaku = if is_first {
+            self.kyotaku as i32 * 1000
+        } else {
+            0
+        };
+
+        let mut deltas = [0; 4];
+        if is_ron {
+            ensure!(
+                (1..4).contains(&from) && self.empty_seat() != Some(from as usize),
+                "ron from seat {from}",
+            );
+            if let Some(pao) = pao {
+                deltas[pao as usize] = -point.ron / 2 - honba * 300;
+                deltas[from as usize] -= point.ron / 2;
+            } else {
+                deltas[from as usize] = -point.ron - honba * 300;
+            }
+            deltas[0] = point.ron + kyotaku + honba * 300;
+        } else {
+            let payers = self.rules.mode.num_players() as i32 - 1;
+            if let Some(pao) = pao {
+                deltas[pao as usize] = -point.ron - honba * 100 * payers;
+            } else {
+                deltas.fill(-point.tsumo_ko - honba * 100);
+                if self.oya != 0 {
+                    deltas[self.oya as usize] = -point.tsumo_oya - honba * 100;
+                }
+                if let Some(empty) = self.empty_seat() {
+                    deltas[empty] = 0;
+                }
+            }
+            deltas[0] = -deltas[1..].iter().sum::<i32>() + kyotaku;
+        }
+        Ok(deltas)
+    }
+
+    /// Same as `agari_points`, but returns the han, fu and every yaku
+    /// including the situational ones and doras, in addition to the points.
+    ///
+    /// `None` is returned if the hand cannot agari, or cannot retrieve the
+    /// winning tile.
+    #[must_use]
+    pub fn agari_detail(&self, is_ron: bool, ura_indicators: &[Tile]) -> Option<AgariDetail> {
+        let (agari, yakus) = self.agari_with_yakus(is_ron, ura_indicators).ok()?;
+        let (fu, han) = match agari {
+            Agari::Normal { fu, han } => (fu, han),
+            Agari::Yakuman(n) => (0, 13 * n),
+        };
+        Some(AgariDetail {
+            han,
+            fu,
+            yakus,
+            point: self.point_of(agari),
+        })
+    }
+
+    /// The doras in the player's tehai, fuuro, ankans and nukidoras, with
+    /// the ura doras indicated by `ura_indicators`.
+    ///
+    /// Unlike `doras_owned`, which is updated on every move of a tile, this
+    /// recounts everything from the tiles and the dora indicators, so `dora +
+    /// aka` always equals `doras_owned` unless the bookkeeping goes wrong.
+    #[must_use]
+    pub fn dora_breakdown(&self, ura_indicators: &[Tile]) -> DoraBreakdown {
+        let mut tiles = self.tehai;
+        for tile in self.fuuro_overview[0].iter().flatten() {
+            tiles[tile.deaka().as_usize()] += 1;
+        }
+        for tile in &self.ankan_overview[0] {
+            tiles[tile.as_usize()] += 4;
+        }
+        tiles[tuz!(N)] += self.nukidoras[0];
+        let count = |indicators: &[Tile]| {
+            indicators
+                .iter()
+                .map(|&t| tiles[self.rules.mode.dora_of(t).as_usize()])
+                .sum::<u8>()
+        };
+
+        let aka = self.akas_in_hand.iter().sum::<u8>()
+            + self.fuuro_overview[0]
+                .iter()
+                .flatten()
+                .filter(|t| t.is_aka())
+                .count() as u8
+            + self.ankan_overview[0]
+                .iter()
+                .map(|&t| self.aka_count_of(t))
+                .sum::<u8>();
+
+        DoraBreakdown {
+            dora: count(&self.dora_indicators) + self.nukidoras[0],
+            aka,
+            ura: count(ura_indicators),
+        }
+    }
+
+    /// Applies `KazoeRule` and `YakumanRule` on top of `Agari::into_point`.
+    fn point_of(&self, agari: Agari) -> Point {
+        let is_oya = self.oya == 0;
+        match agari {
+            Agari::Normal { han, .. } if han >= 13 && self.rules.kazoe == KazoeRule::Sanbaiman => {
+                Point::sanbaiman(is_oya)
+            }
+            Agari::Yakuman(_) if self.rules.yakuman == YakumanRule::Single => {
+                Point::yakuman(is_oya, 1)
+            }
+            _ => agari.into_point(is_oya),
+        }
+    }
+
+    fn agari_with_yakus(
+        &self,
+        is_ron: bool,
+        ura_indicators: &[Tile],
+    ) -> Result<(Agari, Vec<(Yaku, u8)>)> {
         ensure!(
             is_ron && self.last_cans.can_ron_agari || self.last_cans.can_tsumo_agari,
             "cannot agari"
         );
 
-        // 天和, 地和 are special cases that are handled individually, and there
-        // is no multi yakuman for these two.
-        if !is_ron && self.can_w_riichi {
-            return Ok(Point::yakuman(self.oya == 0, 1));
-        }
-
         let winning_tile = if is_ron {
             self.last_kawa_tile
         } else {
@@ -384,57 +1529,118 @@ impl PlayerState {
         }
         .context("cannot find the winning tile")?;
 
-        let additional_hans = if is_ron {
+        // 天和 and 地和 take precedence over everything else in the hand,
+        // including a would-be kazoe yakuman. Only the yakumans of the hand
+        // may stack with them, see `BlessingRule`.
+        if !is_ron && self.can_w_riichi {
+            let yaku = if self.oya == 0 {
+                Yaku::Tenhou
+            } else {
+                Yaku::Chiihou
+            };
+            let mut yakus = vec![(yaku, 13)];
+            if self.rules.blessing == BlessingRule::StackYakuman {
+                let agari_calc = self.agari_calculator(&self.tehai, winning_tile, false);
+                if let Some((Agari::Yakuman(_), hand_yakus)) = agari_calc.search_yakus_detail() {
+                    yakus.extend(hand_yakus);
+                }
+            }
+            return Ok((Agari::Yakuman(yakus.len() as u8), yakus));
+        }
+
+        let mut yakus = vec![];
+        if self.is_w_riichi {
+            yakus.push((Yaku::DoubleRiichi, 2));
+        } else if self.riichi_accepted[0] {
+            yakus.push((Yaku::Riichi, 1));
+        }
+        let situational = if is_ron {
             [
-                self.riichi_accepted[0],       // 立直
-                self.is_w_riichi,              // 両立直
-                self.at_ippatsu,               // 一发
-                self.tiles_left == 0,          // 河底撈魚
-                self.chankan_chance.is_some(), // 槍槓
+                (Yaku::Ippatsu, self.at_ippatsu),
+                (Yaku::Houtei, self.tiles_left == 0),
+                (Yaku::Chankan, self.chankan_chance.is_some()),
             ]
-            .iter()
-            .filter(|&&b| b)
-            .count() as u8
+            .to_vec()
         } else {
             [
-                self.riichi_accepted[0],                  // 立直
-                self.is_w_riichi,                         // 両立直
-                self.at_ippatsu,                          // 一发
-                self.is_menzen,                           // 門前清自摸和
-                self.tiles_left == 0 && !self.at_rinshan, // 海底摸月
-                self.at_rinshan,                          // 嶺上開花
+                (Yaku::Ippatsu, self.at_ippatsu),
+                (Yaku::MenzenTsumo, self.is_menzen),
+                (Yaku::Haitei, self.tiles_left == 0 && !self.at_rinshan),
+                (Yaku::Rinshan, self.at_rinshan),
             ]
-            .iter()
-            .filter(|&&b| b)
-            .count() as u8
+            .to_vec()
         };
+        yakus.extend(
+            situational
+                .into_iter()
+                .filter(|&(_, b)| b)
+                .map(|(yaku, _)| (yaku, 1)),
+        );
+        let additional_hans = yakus.iter().map(|&(_, han)| han).sum();
 
         let mut tehai = self.tehai;
         let mut final_doras_owned = self.doras_owned[0];
+        let mut akas = self.dora_breakdown(&[]).aka;
         if is_ron {
             let tid = winning_tile.deaka().as_usize();
             tehai[tid] += 1;
             final_doras_owned += self.dora_factor[tid];
             if winning_tile.is_aka() {
                 final_doras_owned += 1;
+                akas += 1;
             };
         }
+        let mut uras = 0;
         if self.riichi_accepted[0] {
-            final_doras_owned += ura_indicators
+            uras = ura_indicators
                 .iter()
                 .map(|&ura| {
-                    let next = ura.next();
+                    let next = self.rules.mode.dora_of(ura);
                     let mut count = tehai[next.as_usize()];
                     if self.ankan_overview[0].contains(&next) {
                         count += 4;
                     }
+                    if next == t!(N) {
+                        count += self.nukidoras[0];
+                    }
                     count
                 })
                 .sum::<u8>();
         }
 
-        let agari_calc = AgariCalculator {
-            tehai: &tehai,
+        let agari_calc = self.agari_calculator(&tehai, winning_tile, is_ron);
+        let (agari, hand_yakus) = agari_calc
+            .agari_detail(additional_hans, final_doras_owned + uras)
+            .context("not a hora hand")?;
+
+        if let Agari::Yakuman(_) = agari {
+            return Ok((agari, hand_yakus.to_vec()));
+        }
+        yakus.extend(hand_yakus);
+        let nukis = self.nukidoras[0];
+        for (yaku, n) in [
+            (Yaku::Dora, final_doras_owned.saturating_sub(akas + nukis)),
+            (Yaku::AkaDora, akas),
+            (Yaku::UraDora, uras),
+            (Yaku::NukiDora, nukis),
+        ] {
+            if n > 0 {
+                yakus.push((yaku, n));
+            }
+        }
+
+        Ok((agari, yakus))
+    }
+
+    /// `tehai` must include `winning_tile`.
+    fn agari_calculator<'a>(
+        &'a self,
+        tehai: &'a [u8; 34],
+        winning_tile: Tile,
+        is_ron: bool,
+    ) -> AgariCalculator<'a> {
+        AgariCalculator {
+            tehai,
             is_menzen: self.is_menzen,
             chis: &self.chis,
             pons: &self.pons,
@@ -444,11 +1650,135 @@ impl PlayerState {
             jikaze: self.jikaze.as_u8(),
             winning_tile: winning_tile.deaka().as_u8(),
             is_ron,
-        };
-        let agari = agari_calc
-            .agari(additional_hans, final_doras_owned)
-            .context("not a hora hand")?;
+            allow_kuitan: self.rules.kuitan == KuitanRule::Allowed,
+        }
+    }
+
+    /// The number of aka doras in an ankan of `tile`, which is deaka'd.
+    pub(super) fn aka_count_of(&self, tile: Tile) -> u8 {
+        if !self.rules.mode.has_tile(tile) {
+            return 0;
+        }
+        match tile.as_u8() {
+            tu8!(5m) | tu8!(5s) => self.rules.aka.count().min(1),
+            tu8!(5p) => self.rules.aka.count().saturating_sub(2).min(2),
+            _ => 0,
+        }
+    }
+}
+
+/// Searches for a way to form `sets` sets plus a pair (if `need_pair`) out of
+/// `concealed`, drawing at most `budget` tiles from `unseen`, with only blocks
+/// starting from `from` considered.
+fn can_complete(
+    concealed: &mut [u8; 34],
+    unseen: &mut [u8; 34],
+    from: usize,
+    sets: u8,
+    need_pair: bool,
+    budget: u8,
+) -> bool {
+    if sets == 0 && !need_pair {
+        return true;
+    }
+
+    for tid in from..34 {
+        let same = [tid; 3];
+        let seq = [tid, tid + 1, tid + 2];
+        let mut candidates: ArrayVec<[(&[usize], bool); 3]> = array_vec!();
+        if need_pair {
+            candidates.push((&same[..2], true));
+        }
+        if sets > 0 {
+            candidates.push((&same, false));
+            if tid < 3 * 9 && tid % 9 < 7 {
+                candidates.push((&seq, false));
+            }
+        }
+
+        for (block, is_pair) in candidates {
+            // Take the tiles from the concealed part first, as they are free.
+            let mut taken: ArrayVec<[(usize, bool); 3]> = array_vec!();
+            let mut cost = 0;
+            for &t in block {
+                if concealed[t] > 0 {
+                    concealed[t] -= 1;
+                    taken.push((t, true));
+                } else if unseen[t] > 0 && cost < budget {
+                    unseen[t] -= 1;
+                    taken.push((t, false));
+                    cost += 1;
+                } else {
+                    break;
+                }
+            }
 
-        Ok(agari.into_point(self.oya == 0))
+            let found = taken.len() == block.len() && {
+                let (sets, need_pair) = if is_pair {
+                    (sets, false)
+                } else {
+                    (sets - 1, need_pair)
+                };
+                can_complete(concealed, unseen, tid, sets, need_pair, budget - cost)
+            };
+
+            for (t, from_concealed) in taken {
+                if from_concealed {
+                    concealed[t] += 1;
+                } else {
+                    unseen[t] += 1;
+                }
+            }
+            if found {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Walks through every decomposition of the 3n+2 `tehai` into mentsu and a
+/// pair, adding the kinds of the blocks containing `winning_tile` to `kinds`.
+/// `tehai` is restored on return.
+fn collect_wait_kinds(
+    tehai: &mut [u8; 34],
+    winning_tile: usize,
+    has_pair: bool,
+    blocks: &mut Vec<Block>,
+    kinds: &mut Vec<WaitKind>,
+) {
+    let tid = match tehai.iter().position(|&c| c > 0) {
+        Some(tid) => tid,
+        None => {
+            for kind in blocks.iter().filter_map(|b| b.wait_kind(winning_tile)) {
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+            return;
+        }
+    };
+
+    // The lowest tile must be in one of the blocks starting from it.
+    if !has_pair && tehai[tid] >= 2 {
+        tehai[tid] -= 2;
+        blocks.push(Block::Pair(tid));
+        collect_wait_kinds(tehai, winning_tile, true, blocks, kinds);
+        blocks.pop();
+        tehai[tid] += 2;
+    }
+    if tehai[tid] >= 3 {
+        tehai[tid] -= 3;
+        blocks.push(Block::Koutsu(tid));
+        collect_wait_kinds(tehai, winning_tile, has_pair, blocks, kinds);
+        blocks.pop();
+        tehai[tid] += 3;
+    }
+    if tid < 3 * 9 && tid % 9 < 7 && tehai[tid + 1] > 0 && tehai[tid + 2] > 0 {
+        tehai[tid..tid + 3].iter_mut().for_each(|c| *c -= 1);
+        blocks.push(Block::Shuntsu(tid));
+        collect_wait_kinds(tehai, winning_tile, has_pair, blocks, kinds);
+        blocks.pop();
+        tehai[tid..tid + 3].iter_mut().for_each(|c| *c += 1);
     }
 }
@@ -0,0 +1,224 @@
+use super::PlayerState;
+use crate::consts::{ACTION_SPACE, OBS_SHAPE, SCORE_PRESSURE_CHANNELS, TURN_ORDER_CHANNELS};
+
+use anyhow::{ensure, Context, Result};
+use ndarray::prelude::*;
+use numpy::{PyArray2, PyArray3};
+use pyo3::prelude::*;
+
+/// Encodes the obs of many states into one contiguous array, which saves the
+/// per-call overhead of `PlayerState.encode_obs` at inference.
+///
+/// The obs and mask of each state are identical to those of `encode_obs`, or
+/// `encode_obs_no_aka` if `with_aka` is false, followed by the planes of
+/// `encode_turn_order` if `turn_order` is true, and then those of
+/// `encode_score_pressure` if `score_pressure` is true. The states of a batch
+/// must be of the same game mode, as sanma has more planes and actions.
+#[pyclass]
+#[pyo3(text_signature = "(
+    *,
+    with_aka = True,
+    turn_order = False,
+    score_pressure = False,
+    swing_per_hand = 8000.,
+    game_length = 8,
+)")]
+#[derive(Debug, Clone, Copy)]
+pub struct BatchEncoder {
+    #[pyo3(get, set)]
+    pub with_aka: bool,
+    #[pyo3(get, set)]
+    pub turn_order: bool,
+    #[pyo3(get, set)]
+    pub score_pressure: bool,
+    /// Passed to `encode_score_pressure`.
+    #[pyo3(get, set)]
+    pub swing_per_hand: f32,
+    /// Passed to `encode_score_pressure`, 8 for hanchan and 4 for tonpuu.
+    #[pyo3(get, set)]
+    pub game_length: u8,
+}
+
+#[pymethods]
+impl BatchEncoder {
+    #[new]
+    #[args(
+        "*",
+        with_aka = "true",
+        turn_order = "false",
+        score_pressure = "false",
+        swing_per_hand = "8000.",
+        game_length = "8"
+    )]
+    #[must_use]
+    pub const fn new(
+        with_aka: bool,
+        turn_order: bool,
+        score_pressure: bool,
+        swing_per_hand: f32,
+        game_length: u8,
+    ) -> Self {
+        Self {
+            with_aka,
+            turn_order,
+            score_pressure,
+            swing_per_hand,
+            game_length,
+        }
+    }
+
+    /// Returns `(obs, masks)`, of shape `[len(states), *OBS_SHAPE]`, or
+    /// `SANMA_OBS_SHAPE` for sanma, with `TURN_ORDER_CHANNELS` more planes if
+    /// `turn_order` and `SCORE_PRESSURE_CHANNELS` more if `score_pressure`,
+    /// and `[len(states), ACTION_SPACE]`, or `SANMA_ACTION_SPACE` for sanma,
+    /// respectively.
+    #[pyo3(name = "encode")]
+    #[pyo3(text_signature = "($self, states, at_kan_select, /)")]
+    fn encode_py<'py>(
+        &self,
+        states: Vec<PyRef<'py, PlayerState>>,
+        at_kan_select: Vec<bool>,
+        py: Python<'py>,
+    ) -> Result<(&'py PyArray3<f32>, &'py PyArray2<bool>)> {
+        ensure!(
+            states.len() == at_kan_select.len(),
+            "{} states but {} at_kan_select",
+            states.len(),
+            at_kan_select.len(),
+        );
+        let states: Vec<&PlayerState> = states.iter().map(|s| &**s).collect();
+        let (channels, width) = self
+            .obs_shape_of(&states)
+            .context("states of mixed game modes")?;
+        let obs = PyArray3::zeros(py, [states.len(), channels, width], false);
+        let masks = PyArray2::zeros(py, [states.len(), action_space_of(&states)], false);
+        // SAFETY: the arrays are just created, nothing else refers to them.
+        unsafe {
+            self.encode_zeroed(
+                &states,
+                &at_kan_select,
+                obs.as_array_mut(),
+                masks.as_array_mut(),
+            );
+        }
+        Ok((obs, masks))
+    }
+}
+
+impl Default for BatchEncoder {
+    fn default() -> Self {
+        Self::new(true, false, false, 8000., 8)
+    }
+}
+
+impl BatchEncoder {
+    /// Returns `(obs, masks)` of `states`, with `at_kan_select` for each of
+    /// them.
+    ///
+    /// Panics if `at_kan_select` has a different length from `states`, or the
+    /// states are of different game modes.
+    #[must_use]
+    pub fn encode(
+        &self,
+        states: &[&PlayerState],
+        at_kan_select: &[bool],
+    ) -> (Array3<f32>, Array2<bool>) {
+        let (channels, width) = self
+            .obs_shape_of(states)
+            .expect("states of mixed game modes");
+        let mut obs = Array3::zeros((states.len(), channels, width));
+        let mut masks = Array2::default((states.len(), action_space_of(states)));
+        self.encode_zeroed(states, at_kan_select, obs.view_mut(), masks.view_mut());
+        (obs, masks)
+    }
+
+    /// Same as `encode`, but writes into `obs` and `masks`, overwriting them,
+    /// so that the buffers can be reused across batches.
+    ///
+    /// Panics in the same cases as `encode`, or if the buffers are not of the
+    /// shapes `encode` returns.
+    pub fn encode_into(
+        &self,
+        states: &[&PlayerState],
+        at_kan_select: &[bool],
+        mut obs: ArrayViewMut3<'_, f32>,
+        mut masks: ArrayViewMut2<'_, bool>,
+    ) {
+        obs.fill(0.);
+        masks.fill(false);
+        self.encode_zeroed(states, at_kan_select, obs, masks);
+    }
+
+    fn encode_zeroed(
+        &self,
+        states: &[&PlayerState],
+        at_kan_select: &[bool],
+        mut obs: ArrayViewMut3<'_, f32>,
+        mut masks: ArrayViewMut2<'_, bool>,
+    ) {
+        assert_eq!(states.len(), at_kan_select.len(), "length mismatch");
+        let (channels, width) = self
+            .obs_shape_of(states)
+            .expect("states of mixed game modes");
+        assert_eq!(
+            obs.shape(),
+            [states.len(), channels, width],
+            "unexpected obs shape",
+        );
+        assert_eq!(
+            masks.shape(),
+            [states.len(), action_space_of(states)],
+            "unexpected masks shape",
+        );
+
+        for (((state, &kan), obs), mask) in states
+            .iter()
+            .zip(at_kan_select)
+            .zip(obs.outer_iter_mut())
+            .zip(masks.outer_iter_mut())
+        {
+            let (obs, mut extra) = obs.split_at(Axis(0), channels - self.extra_channels());
+            state.encode_obs_into(kan, self.with_aka, obs, mask);
+            let mut idx = 0;
+            if self.turn_order {
+                extra
+                    .slice_mut(s![idx..idx + TURN_ORDER_CHANNELS, ..])
+                    .assign(&state.encode_turn_order());
+                idx += TURN_ORDER_CHANNELS;
+            }
+            if self.score_pressure {
+                extra
+                    .slice_mut(s![idx..idx + SCORE_PRESSURE_CHANNELS, ..])
+                    .assign(&state.encode_score_pressure(self.swing_per_hand, self.game_length));
+            }
+        }
+    }
+
+    /// The number of planes appended to those of `encode_obs`.
+    const fn extra_channels(&self) -> usize {
+        let mut ret = 0;
+        if self.turn_order {
+            ret += TURN_ORDER_CHANNELS;
+        }
+        if self.score_pressure {
+            ret += SCORE_PRESSURE_CHANNELS;
+        }
+        ret
+    }
+
+    /// The obs shape shared by `states`, or `None` if they differ. An empty
+    /// batch has `OBS_SHAPE`, plus the extra planes.
+    fn obs_shape_of(&self, states: &[&PlayerState]) -> Option<(usize, usize)> {
+        let shape = states.first().map_or(OBS_SHAPE, |s| s.obs_shape());
+        states
+            .iter()
+            .all(|s| s.obs_shape() == shape)
+            .then_some((shape.0 + self.extra_channels(), shape.1))
+    }
+}
+
+/// The action space of `states`, which is shared once `obs_shape_of` is
+/// `Some`. An empty batch has `ACTION_SPACE`.
+fn action_space_of(states: &[&PlayerState]) -> usize {
+    states.first().map_or(ACTION_SPACE, |s| s.action_space())
+}
@@ -0,0 +1,617 @@
+//! A compact fixed-layout binary encoding of `PlayerState`, mainly for
+//! checkpointing a large number of states. It is also how `validate_logs
+//! --dump-states` saves the state before a failing event for debugging.
+//!
+//! A restored state reproduces the exact same `encode_obs`.
+//!
+//! The layout is hand-rolled and follows the order of the fields of
+//! `PlayerState`, except for `lang`, which only affects `brief_info` and is
+//! left out, so that a restored state is in English until `set_lang`. Any
+//! change to the layout must bump `FORMAT_VERSION`.
+
+use super::action::ActionCandidate;
+use super::item::{ChiPon, FuritenKind, KawaItem, Sutehai};
+use super::{
+    AkaRule, BlessingRule, DaiminkanPaoRule, GameMode, KanDoraRule, KazoeRule, KuikaeRule,
+    KuitanRule, MultiRonRule, PlayerState, Rules, TripleRonRule, YakumanRule,
+};
+use crate::mjai::Event;
+use crate::tile::Tile;
+use std::str;
+
+use anyhow::{bail, ensure, Context, Result};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use tinyvec::{Array, ArrayVec};
+
+const FORMAT_VERSION: u8 = 20;
+
+#[pymethods]
+impl PlayerState {
+    #[pyo3(name = "to_bytes")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn to_bytes_py<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.to_bytes())
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_bytes")]
+    #[pyo3(text_signature = "(data, /)")]
+    fn from_bytes_py(data: &[u8]) -> Result<Self> {
+        Self::from_bytes(data)
+    }
+}
+
+impl PlayerState {
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::with_capacity(512));
+        w.u8(FORMAT_VERSION);
+
+        w.u8(self.player_id);
+        w.u8(match self.rules.kuikae {
+            KuikaeRule::Full => 0,
+            KuikaeRule::SameTileOnly => 1,
+        });
+        w.u8(match self.rules.kuitan {
+            KuitanRule::Allowed => 0,
+            KuitanRule::Forbidden => 1,
+        });
+        w.u8(match self.rules.aka {
+            AkaRule::None => 0,
+            AkaRule::Three => 1,
+            AkaRule::FourWithDoublePin => 2,
+        });
+        w.u8(match self.rules.blessing {
+            BlessingRule::Alone => 0,
+            BlessingRule::StackYakuman => 1,
+        });
+        w.u8(match self.rules.kazoe {
+            KazoeRule::Yakuman => 0,
+            KazoeRule::Sanbaiman => 1,
+        });
+        w.u8(match self.rules.yakuman {
+            YakumanRule::Multiple => 0,
+            YakumanRule::Single => 1,
+        });
+        w.u8(match self.rules.multi_ron {
+            MultiRonRule::HonbaToFirst => 0,
+            MultiRonRule::HonbaToEach => 1,
+            MultiRonRule::Atamahane => 2,
+        });
+        w.u8(match self.rules.triple_ron {
+            TripleRonRule::Abortive => 0,
+            TripleRonRule::Allowed => 1,
+        });
+        w.u8(match self.rules.daiminkan_pao {
+            DaiminkanPaoRule::None => 0,
+            DaiminkanPaoRule::RinshanKaihou => 1,
+        });
+        w.u8(match self.rules.kan_dora {
+            KanDoraRule::AfterDiscard => 0,
+            KanDoraRule::Immediate => 1,
+        });
+        w.u8(match self.rules.mode {
+            GameMode::Yonma => 0,
+            GameMode::Sanma => 1,
+        });
+
+        w.u8s(&self.tehai);
+        w.bools(&self.waits);
+        w.u8s(&self.dora_factor);
+        w.u8s(&self.tiles_seen);
+        w.u8s(&self.tiles_seen_at_last_decision);
+        w.u8s(&self.akas_seen);
+        w.bools(&self.keep_shanten_discards);
+        w.bools(&self.next_shanten_discards);
+        w.bools(&self.forbidden_tiles);
+        w.bools(&self.discarded_tiles);
+
+        w.tile(self.bakaze);
+        w.tile(self.jikaze);
+        w.u8(self.kyoku);
+        w.u8(self.honba);
+        w.u8(self.kyotaku);
+        self.scores.iter().for_each(|&s| w.i32(s));
+        w.u8(self.rank);
+        w.u8(self.oya);
+        w.bool(self.is_all_last);
+        w.bool(self.last_kyoku_ended_in_draw);
+        w.u8(self.horas);
+        w.tiles(&self.dora_indicators);
+
+        for kawa in &self.kawa {
+            w.u8(kawa.len() as u8);
+            kawa.iter().for_each(|item| w.kawa_item(item.as_ref()));
+        }
+        self.kawa_overview.iter().for_each(|k| w.tiles(k));
+        for fuuro in &self.fuuro_overview {
+            w.u8(fuuro.len() as u8);
+            fuuro.iter().for_each(|f| w.tiles(f));
+        }
+        self.ankan_overview.iter().for_each(|k| w.tiles(k));
+        self.paos.iter().for_each(|&p| w.opt_u8(p));
+        w.opt_u8(self.rinshan_pao);
+
+        w.bools(&self.riichi_declared);
+        w.bools(&self.riichi_accepted);
+        self.passed_after_riichi.iter().for_each(|p| w.bools(p));
+
+        w.u8(self.at_turn);
+        w.u8(self.tiles_left);
+        w.u8(self.next_draw);
+        w.tiles(&self.intermediate_kan);
+        w.chi_pon(self.intermediate_chi_pon.as_ref());
+
+        w.i8(self.shanten);
+
+        w.opt_tile(self.last_self_tsumo);
+        w.opt_tile(self.last_kawa_tile);
+        w.cans(self.last_cans);
+
+        w.tiles(&self.ankan_candidates);
+        w.tiles(&self.kakan_candidates);
+        w.bools(&[
+            self.chankan_chance.is_some(),
+            self.can_w_riichi,
+            self.is_w_riichi,
+            self.at_rinshan,
+            self.at_ippatsu,
+            self.at_furiten,
+            self.to_mark_same_cycle_furiten.is_some(),
+            self.is_menzen,
+            self.has_next_shanten_discard,
+        ]);
+        w.u8(self.furiten_kind as u8);
+        w.opt_u8(self.first_call_junme);
+
+        w.u8(self.kans_on_board);
+        w.small_u8s(&self.chis);
+        w.small_u8s(&self.pons);
+        w.small_u8s(&self.minkans);
+        w.small_u8s(&self.ankans);
+
+        w.u8s(&self.doras_owned);
+        w.u8(self.doras_seen);
+        w.u8s(&self.nukidoras);
+        w.u8s(&self.akas_in_hand);
+        w.u8(self.tehai_len_div3);
+
+        w.opt_event(self.last_event.as_ref());
+
+        w.0
+    }
+
+    /// Restores a `PlayerState` from the output of `to_bytes`.
+    ///
+    /// Err is returned if the data is malformed or is produced by another
+    /// version of the format.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut r = Reader(data);
+        let version = r.u8()?;
+        ensure!(
+            version == FORMAT_VERSION,
+            "unsupported format version {version}, expected {FORMAT_VERSION}",
+        );
+
+        let player_id = r.u8()?;
+        let rules = Rules {
+            kuikae: match r.u8()? {
+                0 => KuikaeRule::Full,
+                1 => KuikaeRule::SameTileOnly,
+                v => bail!("invalid kuikae rule {v}"),
+            },
+            kuitan: match r.u8()? {
+                0 => KuitanRule::Allowed,
+                1 => KuitanRule::Forbidden,
+                v => bail!("invalid kuitan rule {v}"),
+            },
+            aka: match r.u8()? {
+                0 => AkaRule::None,
+                1 => AkaRule::Three,
+                2 => AkaRule::FourWithDoublePin,
+                v => bail!("invalid aka rule {v}"),
+            },
+            blessing: match r.u8()? {
+                0 => BlessingRule::Alone,
+                1 => BlessingRule::StackYakuman,
+                v => bail!("invalid blessing rule {v}"),
+            },
+            kazoe: match r.u8()? {
+                0 => KazoeRule::Yakuman,
+                1 => KazoeRule::Sanbaiman,
+                v => bail!("invalid kazoe rule {v}"),
+            },
+            yakuman: match r.u8()? {
+                0 => YakumanRule::Multiple,
+                1 => YakumanRule::Single,
+                v => bail!("invalid yakuman rule {v}"),
+            },
+            multi_ron: match r.u8()? {
+                0 => MultiRonRule::HonbaToFirst,
+                1 => MultiRonRule::HonbaToEach,
+                2 => MultiRonRule::Atamahane,
+                v => bail!("invalid multi-ron rule {v}"),
+            },
+            triple_ron: match r.u8()? {
+                0 => TripleRonRule::Abortive,
+                1 => TripleRonRule::Allowed,
+                v => bail!("invalid triple ron rule {v}"),
+            },
+            daiminkan_pao: match r.u8()? {
+                0 => DaiminkanPaoRule::None,
+                1 => DaiminkanPaoRule::RinshanKaihou,
+                v => bail!("invalid daiminkan pao rule {v}"),
+            },
+            kan_dora: match r.u8()? {
+                0 => KanDoraRule::AfterDiscard,
+                1 => KanDoraRule::Immediate,
+                v => bail!("invalid kan dora rule {v}"),
+            },
+            mode: match r.u8()? {
+                0 => GameMode::Yonma,
+                1 => GameMode::Sanma,
+                v => bail!("invalid game mode {v}"),
+            },
+        };
+        ensure!(
+            player_id < rules.mode.num_players(),
+            "invalid player_id {player_id}",
+        );
+        let mut state = Self::with_rules(player_id, rules);
+
+        state.tehai = r.u8s()?;
+        state.waits = r.bools()?;
+        state.dora_factor = r.u8s()?;
+        state.tiles_seen = r.u8s()?;
+        state.tiles_seen_at_last_decision = r.u8s()?;
+        state.akas_seen = r.u8s()?;
+        state.keep_shanten_discards = r.bools()?;
+        state.next_shanten_discards = r.bools()?;
+        state.forbidden_tiles = r.bools()?;
+        state.discarded_tiles = r.bools()?;
+
+        state.bakaze = r.tile()?;
+        state.jikaze = r.tile()?;
+        state.kyoku = r.u8()?;
+        state.honba = r.u8()?;
+        state.kyotaku = r.u8()?;
+        for s in &mut state.scores {
+            *s = r.i32()?;
+        }
+        state.rank = r.u8()?;
+        state.oya = r.u8()?;
+        state.is_all_last = r.bool()?;
+        state.last_kyoku_ended_in_draw = r.bool()?;
+        state.horas = r.u8()?;
+        state.dora_indicators = r.tiles()?;
+
+        for kawa in &mut state.kawa {
+            let len = r.len(kawa.capacity())?;
+            for _ in 0..len {
+                kawa.push(r.kawa_item()?);
+            }
+        }
+        for kawa_overview in &mut state.kawa_overview {
+            *kawa_overview = r.tiles()?;
+        }
+        for fuuro in &mut state.fuuro_overview {
+            let len = r.len(fuuro.capacity())?;
+            for _ in 0..len {
+                fuuro.push(r.tiles()?);
+            }
+        }
+        for ankan_overview in &mut state.ankan_overview {
+            *ankan_overview = r.tiles()?;
+        }
+        for pao in &mut state.paos {
+            *pao = r.opt_u8()?;
+        }
+        state.rinshan_pao = r.opt_u8()?;
+
+        state.riichi_declared = r.bools()?;
+        state.riichi_accepted = r.bools()?;
+        for passed in &mut state.passed_after_riichi {
+            *passed = r.bools()?;
+        }
+
+        state.at_turn = r.u8()?;
+        state.tiles_left = r.u8()?;
+        state.next_draw = r.u8()?;
+        state.intermediate_kan = r.tiles()?;
+        state.intermediate_chi_pon = r.chi_pon()?;
+
+        state.shanten = r.i8()?;
+
+        state.last_self_tsumo = r.opt_tile()?;
+        state.last_kawa_tile = r.opt_tile()?;
+        state.last_cans = r.cans()?;
+
+        state.ankan_candidates = r.tiles()?;
+        state.kakan_candidates = r.tiles()?;
+        let flags: [bool; 9] = r.bools()?;
+        state.chankan_chance = flags[0].then_some(());
+        state.can_w_riichi = flags[1];
+        state.is_w_riichi = flags[2];
+        state.at_rinshan = flags[3];
+        state.at_ippatsu = flags[4];
+        state.at_furiten = flags[5];
+        state.to_mark_same_cycle_furiten = flags[6].then_some(());
+        state.is_menzen = flags[7];
+        state.has_next_shanten_discard = flags[8];
+        state.furiten_kind = match r.u8()? {
+            0 => FuritenKind::None,
+            1 => FuritenKind::Temporary,
+            2 => FuritenKind::Riichi,
+            3 => FuritenKind::Permanent,
+            v => bail!("invalid furiten kind {v}"),
+        };
+        state.first_call_junme = r.opt_u8()?;
+
+        state.kans_on_board = r.u8()?;
+        state.chis = r.small_u8s()?;
+        state.pons = r.small_u8s()?;
+        state.minkans = r.small_u8s()?;
+        state.ankans = r.small_u8s()?;
+
+        state.doras_owned = r.u8s()?;
+        state.doras_seen = r.u8()?;
+        state.nukidoras = r.u8s()?;
+        state.akas_in_hand = r.u8s()?;
+        state.tehai_len_div3 = r.u8()?;
+
+        state.last_event = r.opt_event()?;
+
+        ensure!(r.0.is_empty(), "{} trailing bytes", r.0.len());
+        Ok(state)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+    fn i8(&mut self, v: i8) {
+        self.0.push(v as u8);
+    }
+    fn bool(&mut self, v: bool) {
+        self.0.push(v as u8);
+    }
+    fn i32(&mut self, v: i32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u8s(&mut self, v: &[u8]) {
+        self.0.extend_from_slice(v);
+    }
+    /// Bit-packed, LSB first.
+    fn bools(&mut self, v: &[bool]) {
+        for chunk in v.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &b)| acc | (b as u8) << i);
+            self.0.push(byte);
+        }
+    }
+    fn small_u8s(&mut self, v: &[u8]) {
+        self.u8(v.len() as u8);
+        self.u8s(v);
+    }
+    fn opt_u8(&mut self, v: Option<u8>) {
+        self.0.push(v.unwrap_or(u8::MAX));
+    }
+
+    fn tile(&mut self, tile: Tile) {
+        self.0.push(tile.as_u8());
+    }
+    fn opt_tile(&mut self, tile: Option<Tile>) {
+        self.0.push(tile.map_or(u8::MAX, Tile::as_u8));
+    }
+    fn tiles(&mut self, tiles: &[Tile]) {
+        self.u8(tiles.len() as u8);
+        tiles.iter().for_each(|&t| self.tile(t));
+    }
+
+    fn chi_pon(&mut self, chi_pon: Option<&ChiPon>) {
+        if let Some(cp) = chi_pon {
+            self.bool(true);
+            self.tile(cp.consumed[0]);
+            self.tile(cp.consumed[1]);
+            self.tile(cp.target_tile);
+        } else {
+            self.bool(false);
+        }
+    }
+    fn kawa_item(&mut self, item: Option<&KawaItem>) {
+        if let Some(item) = item {
+            self.bools(&[
+                true,
+                item.sutehai.is_dora,
+                item.sutehai.is_tedashi,
+                item.sutehai.is_riichi,
+                item.sutehai.is_called,
+            ]);
+            self.tile(item.sutehai.tile);
+            self.tiles(&item.kan);
+            self.chi_pon(item.chi_pon.as_ref());
+        } else {
+            self.u8(0);
+        }
+    }
+    fn cans(&mut self, cans: ActionCandidate) {
+        self.bools(&[
+            cans.can_discard,
+            cans.can_chi_low,
+            cans.can_chi_mid,
+            cans.can_chi_high,
+            cans.can_pon,
+            cans.can_daiminkan,
+            cans.can_kakan,
+            cans.can_ankan,
+            cans.can_riichi,
+            cans.can_tsumo_agari,
+            cans.can_ron_agari,
+            cans.can_ryukyoku,
+            cans.is_chankan,
+            cans.can_nukidora,
+        ]);
+        self.u8(cans.target_actor);
+    }
+
+    /// The mjai line, prefixed by its length in `u16`, which is 0 for
+    /// `None`.
+    fn opt_event(&mut self, event: Option<&Event>) {
+        let line = event.map(Event::to_line).unwrap_or_default();
+        self.0.extend_from_slice(&(line.len() as u16).to_le_bytes());
+        self.0.extend_from_slice(line.as_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        ensure!(self.0.len() >= N, "unexpected end of data");
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let [v] = self.take()?;
+        Ok(v)
+    }
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => bail!("invalid bool {v}"),
+        }
+    }
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+    fn u8s<const N: usize>(&mut self) -> Result<[u8; N]> {
+        self.take()
+    }
+    fn bools<const N: usize>(&mut self) -> Result<[bool; N]> {
+        let mut ret = [false; N];
+        for chunk in ret.chunks_mut(8) {
+            let byte = self.u8()?;
+            chunk
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, b)| *b = (byte >> i) & 1 == 1);
+        }
+        Ok(ret)
+    }
+    fn len(&mut self, capacity: usize) -> Result<usize> {
+        let len = self.u8()? as usize;
+        ensure!(len <= capacity, "length {len} exceeds capacity {capacity}");
+        Ok(len)
+    }
+    fn small_u8s<A: Array<Item = u8>>(&mut self) -> Result<ArrayVec<A>> {
+        let len = self.len(A::CAPACITY)?;
+        let mut ret = ArrayVec::new();
+        for _ in 0..len {
+            ret.push(self.u8()?);
+        }
+        Ok(ret)
+    }
+    fn opt_u8(&mut self) -> Result<Option<u8>> {
+        match self.u8()? {
+            u8::MAX => Ok(None),
+            v => Ok(Some(v)),
+        }
+    }
+
+    fn tile(&mut self) -> Result<Tile> {
+        Ok(Tile::try_from(self.u8()?)?)
+    }
+    fn opt_tile(&mut self) -> Result<Option<Tile>> {
+        match self.u8()? {
+            u8::MAX => Ok(None),
+            v => Ok(Some(Tile::try_from(v)?)),
+        }
+    }
+    fn tiles<A: Array<Item = Tile>>(&mut self) -> Result<ArrayVec<A>> {
+        let len = self.len(A::CAPACITY)?;
+        let mut ret = ArrayVec::new();
+        for _ in 0..len {
+            ret.push(self.tile()?);
+        }
+        Ok(ret)
+    }
+
+    fn chi_pon(&mut self) -> Result<Option<ChiPon>> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        let consumed = [self.tile()?, self.tile()?];
+        let target_tile = self.tile()?;
+        Ok(Some(ChiPon {
+            consumed,
+            target_tile,
+        }))
+    }
+    fn kawa_item(&mut self) -> Result<Option<KawaItem>> {
+        let [present, is_dora, is_tedashi, is_riichi, is_called] = self.bools()?;
+        if !present {
+            return Ok(None);
+        }
+        let tile = self.tile()?;
+        let kan = self.tiles()?;
+        let chi_pon = self.chi_pon()?;
+        Ok(Some(KawaItem {
+            chi_pon,
+            kan,
+            sutehai: Sutehai {
+                tile,
+                is_dora,
+                is_tedashi,
+                is_riichi,
+                is_called,
+            },
+        }))
+    }
+    fn cans(&mut self) -> Result<ActionCandidate> {
+        let flags: [bool; 14] = self.bools()?;
+        let target_actor = self.u8()?;
+        Ok(ActionCandidate {
+            can_discard: flags[0],
+            can_chi_low: flags[1],
+            can_chi_mid: flags[2],
+            can_chi_high: flags[3],
+            can_pon: flags[4],
+            can_daiminkan: flags[5],
+            can_kakan: flags[6],
+            can_ankan: flags[7],
+            can_riichi: flags[8],
+            can_tsumo_agari: flags[9],
+            can_ron_agari: flags[10],
+            can_ryukyoku: flags[11],
+            is_chankan: flags[12],
+            can_nukidora: flags[13],
+            target_actor,
+        })
+    }
+
+    fn opt_event(&mut self) -> Result<Option<Event>> {
+        let len = u16::from_le_bytes(self.take()?) as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        ensure!(self.0.len() >= len, "unexpected end of data");
+        let (line, rest) = self.0.split_at(len);
+        self.0 = rest;
+        let line = str::from_utf8(line).context("invalid last event")?;
+        let mut events = Event::parse_line(line)?;
+        ensure!(events.len() == 1, "invalid last event {line}");
+        Ok(events.pop())
+    }
+}
@@ -0,0 +1,333 @@
+use super::{ActionCandidate, GameMode, MultiRonRule, PlayerState, TripleRonRule};
+use crate::mjai::Event;
+use crate::tile::Tile;
+use crate::tu8;
+use std::error::Error;
+use std::fmt;
+
+use anyhow::Result;
+
+/// Why `PlayerState::update_checked` rejects an event, as seen from the
+/// player. Seats are absolute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The player discards or calls with a tile it does not hold.
+    TileNotInHand { tile: Tile },
+    /// A fifth copy of the tile would be seen by the player.
+    TooManyCopies { tile: Tile },
+    /// A draw or discard by a player whose turn it is not.
+    OutOfTurn { actor: u8, expected: u8 },
+    /// A call on a tile which is not the last discard.
+    NotLastDiscard { pai: Tile },
+    /// A call whose tiles do not make the meld, or whose target is wrong.
+    InvalidMeld { actor: u8 },
+    /// A dora indicator revealed without a kan.
+    DoraWithoutKan,
+    /// A third hora on the same tile, which is a 三家和了 under
+    /// `TripleRonRule::Abortive`.
+    TripleRon { actor: u8 },
+    /// A second hora on the same tile, which is void under
+    /// `MultiRonRule::Atamahane`.
+    Atamahane { actor: u8 },
+    /// The event would overflow a fixed capacity of the state, which only a
+    /// malformed log can do. `update` saturates the capacity instead.
+    CapacityExceeded { capacity: Capacity },
+}
+
+/// A fixed capacity of `PlayerState`, sized for the maxima of a legal game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capacity {
+    /// A tsumo to a hand which is not of 3n+1 tiles, which could make more
+    /// kan candidates than the 3 a legal hand can have.
+    Tehai,
+    /// The kawa of a player, see `PlayerState::kawa`.
+    Kawa { actor: u8 },
+    /// The 4 melds of a player.
+    Melds { actor: u8 },
+    /// The 4 kans of a kyoku.
+    Kans,
+    /// The 5 dora indicators of a kyoku.
+    DoraIndicators,
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TileNotInHand { tile } => write!(f, "{tile} is not in hand"),
+            Self::TooManyCopies { tile } => write!(f, "more than 4 copies of {tile}"),
+            Self::OutOfTurn { actor, expected } => {
+                write!(f, "player {actor} acts out of turn, expected {expected}")
+            }
+            Self::NotLastDiscard { pai } => write!(f, "{pai} is not the last discard"),
+            Self::InvalidMeld { actor } => write!(f, "invalid meld by player {actor}"),
+            Self::DoraWithoutKan => f.write_str("dora indicator without a kan"),
+            Self::TripleRon { actor } => {
+                write!(f, "hora by player {actor} makes a triple ron")
+            }
+            Self::Atamahane { actor } => {
+                write!(f, "hora by player {actor} is void by atamahane")
+            }
+            Self::CapacityExceeded { capacity } => write!(f, "capacity exceeded: {capacity}"),
+        }
+    }
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Tehai => f.write_str("tsumo to a hand which is not of 3n+1 tiles"),
+            Self::Kawa { actor } => write!(f, "the kawa of player {actor} is full"),
+            Self::Melds { actor } => write!(f, "player {actor} already has 4 melds"),
+            Self::Kans => f.write_str("there are already 4 kans"),
+            Self::DoraIndicators => f.write_str("there are already 5 dora indicators"),
+        }
+    }
+}
+
+impl Error for UpdateError {}
+
+impl PlayerState {
+    /// Same as `update`, but rejects an event that is impossible from the
+    /// player's view, leaving the state untouched. The root cause of the
+    /// error is an `UpdateError` if the event breaks the game or the capacity
+    /// of the state, otherwise the event breaks the rules.
+    pub fn update_checked(&mut self, event: &Event) -> Result<ActionCandidate> {
+        self.update_checked_with_skip(event, false)
+    }
+
+    /// Checked version of `update_with_skip`.
+    pub fn update_checked_with_skip(
+        &mut self,
+        event: &Event,
+        skip_on_announce: bool,
+    ) -> Result<ActionCandidate> {
+        self.ensure_aka_allowed(event)?;
+        self.ensure_capacity(event)?;
+        self.check_event(event)?;
+        Ok(self.update_with_skip(event, skip_on_announce))
+    }
+
+    /// Rejects the events that would overflow a fixed capacity of the state,
+    /// which only malformed logs can do, instead of saturating it in
+    /// `update`.
+    pub(super) fn ensure_capacity(&self, event: &Event) -> Result<(), UpdateError> {
+        match *event {
+            // A hand of 3n+1 tiles holds at most 3 kans to declare after a
+            // tsumo, which is what `ankan_candidates` and `kakan_candidates`
+            // hold.
+            Event::Tsumo { actor, .. } if actor == self.player_id => {
+                let len: u8 = self.tehai.iter().sum();
+                if len % 3 != 1 {
+                    return Err(exceeded(Capacity::Tehai));
+                }
+            }
+            Event::Dahai { actor, .. } => self.ensure_kawa_capacity(actor)?,
+            Event::Chi { actor, .. } => self.ensure_meld_capacity(actor)?,
+            Event::Pon { actor, target, .. } | Event::Daiminkan { actor, target, .. } => {
+                self.ensure_meld_capacity(actor)?;
+                if matches!(event, Event::Daiminkan { .. }) {
+                    self.ensure_kan_capacity()?;
+                }
+                let mut i = self.next_seat(target);
+                while i != actor {
+                    self.ensure_kawa_capacity(i)?;
+                    i = self.next_seat(i);
+                }
+            }
+            Event::Kakan { actor, pai, .. } => {
+                self.ensure_kan_capacity()?;
+                if self.pon_index(self.rel(actor), pai).is_none() {
+                    return Err(UpdateError::InvalidMeld { actor });
+                }
+            }
+            Event::Ankan { actor, .. } => {
+                self.ensure_meld_capacity(actor)?;
+                self.ensure_kan_capacity()?;
+            }
+            Event::Dora { .. } => {
+                if self.dora_indicators.len() == self.dora_indicators.capacity() {
+                    return Err(exceeded(Capacity::DoraIndicators));
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn ensure_kawa_capacity(&self, actor: u8) -> Result<(), UpdateError> {
+        let kawa = &self.kawa[self.rel(actor)];
+        if kawa.len() == kawa.capacity() {
+            return Err(exceeded(Capacity::Kawa { actor }));
+        }
+        Ok(())
+    }
+
+    fn ensure_meld_capacity(&self, actor: u8) -> Result<(), UpdateError> {
+        let rel = self.rel(actor);
+        if self.fuuro_overview[rel].len() + self.ankan_overview[rel].len() >= 4 {
+            return Err(exceeded(Capacity::Melds { actor }));
+        }
+        Ok(())
+    }
+
+    fn ensure_kan_capacity(&self) -> Result<(), UpdateError> {
+        if self.kans_on_board >= 4 {
+            return Err(exceeded(Capacity::Kans));
+        }
+        Ok(())
+    }
+
+    fn check_event(&self, event: &Event) -> Result<(), UpdateError> {
+        match *event {
+            Event::Tsumo { actor, pai } => {
+                let expected = (self.player_id + self.next_draw) % 4;
+                if actor != expected {
+                    return Err(UpdateError::OutOfTurn { actor, expected });
+                }
+                if actor == self.player_id {
+                    self.check_unseen(&[pai])?;
+                }
+            }
+            Event::Dahai { actor, pai, .. } | Event::Nukidora { actor, pai } => {
+                // The actor is the last one to draw or call.
+                let expected = self.last_to_draw();
+                if actor != expected {
+                    return Err(UpdateError::OutOfTurn { actor, expected });
+                }
+                self.check_revealed(actor, &[pai])?;
+            }
+
+            Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                self.check_call(actor, target, pai)?;
+                let mut tiles = [consumed[0].deaka(), consumed[1].deaka(), pai.deaka()];
+                tiles.sort_unstable();
+                let is_sequence = !tiles[0].is_jihai()
+                    && tiles[0].as_u8() / 9 == tiles[2].as_u8() / 9
+                    && tiles[1].as_u8() == tiles[0].as_u8() + 1
+                    && tiles[2].as_u8() == tiles[1].as_u8() + 1;
+                if self.rules.mode == GameMode::Sanma
+                    || self.next_seat(target) != actor
+                    || !is_sequence
+                {
+                    return Err(UpdateError::InvalidMeld { actor });
+                }
+                self.check_revealed(actor, &consumed)?;
+            }
+            Event::Pon {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                self.check_call(actor, target, pai)?;
+                check_same_kind(actor, pai, &consumed)?;
+                self.check_revealed(actor, &consumed)?;
+            }
+            Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                self.check_call(actor, target, pai)?;
+                check_same_kind(actor, pai, &consumed)?;
+                self.check_revealed(actor, &consumed)?;
+            }
+            Event::Kakan {
+                actor,
+                pai,
+                consumed,
+            } => {
+                check_same_kind(actor, pai, &consumed)?;
+                self.check_revealed(actor, &[pai])?;
+            }
+            Event::Ankan { actor, consumed } => {
+                check_same_kind(actor, consumed[0], &consumed)?;
+                self.check_revealed(actor, &consumed)?;
+            }
+
+            Event::Dora { dora_marker } => {
+                if self.dora_indicators.len() > self.kans_on_board as usize {
+                    return Err(UpdateError::DoraWithoutKan);
+                }
+                self.check_unseen(&[dora_marker])?;
+            }
+            Event::Hora { actor, .. } => {
+                if self.horas >= 1 && self.rules.multi_ron == MultiRonRule::Atamahane {
+                    return Err(UpdateError::Atamahane { actor });
+                }
+                if self.horas >= 2 && self.rules.triple_ron == TripleRonRule::Abortive {
+                    return Err(UpdateError::TripleRon { actor });
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Checks that the tiles are in the hand of the player if `actor` is the
+    /// player, or have not all been seen otherwise.
+    fn check_revealed(&self, actor: u8, tiles: &[Tile]) -> Result<(), UpdateError> {
+        if actor != self.player_id {
+            return self.check_unseen(tiles);
+        }
+        for &tile in tiles {
+            let needed = tiles.iter().filter(|t| t.deaka() == tile.deaka()).count();
+            let mut held = self.tehai[tile.deaka().as_usize()] as usize;
+            if tile.is_aka() {
+                let aka_needed = tiles.iter().filter(|&&t| t == tile).count();
+                let akas = self.akas_in_hand[tile.as_usize() - 34] as usize;
+                held = held.min(akas + needed - aka_needed);
+            }
+            if needed > held {
+                return Err(UpdateError::TileNotInHand { tile });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that seeing the tiles does not make more than 4 copies seen.
+    fn check_unseen(&self, tiles: &[Tile]) -> Result<(), UpdateError> {
+        for &tile in tiles.iter().filter(|t| t.as_u8() < tu8!(?)) {
+            let tid = tile.deaka().as_usize();
+            let revealed = tiles.iter().filter(|t| t.deaka() == tile.deaka()).count();
+            if self.tiles_seen[tid] as usize + revealed > 4 {
+                return Err(UpdateError::TooManyCopies { tile });
+            }
+        }
+        Ok(())
+    }
+
+    /// The absolute seat right before the one to draw next.
+    const fn last_to_draw(&self) -> u8 {
+        let num_players = self.rules.mode.num_players();
+        let next = (self.player_id + self.next_draw) % 4;
+        (next + num_players - 1) % num_players
+    }
+
+    fn check_call(&self, actor: u8, target: u8, pai: Tile) -> Result<(), UpdateError> {
+        if actor == target {
+            return Err(UpdateError::InvalidMeld { actor });
+        }
+        if self.last_kawa_tile != Some(pai) {
+            return Err(UpdateError::NotLastDiscard { pai });
+        }
+        Ok(())
+    }
+}
+
+fn check_same_kind(actor: u8, pai: Tile, consumed: &[Tile]) -> Result<(), UpdateError> {
+    if consumed.iter().any(|t| t.deaka() != pai.deaka()) {
+        return Err(UpdateError::InvalidMeld { actor });
+    }
+    Ok(())
+}
+
+const fn exceeded(capacity: Capacity) -> UpdateError {
+    UpdateError::CapacityExceeded { capacity }
+}
@@ -1,5 +1,6 @@
-use super::{ActionCandidate, PlayerState};
+use super::{ActionCandidate, DaiminkanPaoRule, FuritenKind, KawaItemPy, PlayerState, Rules};
 use crate::tile::Tile;
+use crate::{must_tile, t, tu8, tuz};
 
 impl PlayerState {
     #[inline]
@@ -9,9 +10,62 @@ impl PlayerState {
     }
     #[inline]
     #[must_use]
+    pub const fn rules(&self) -> Rules {
+        self.rules
+    }
+    #[inline]
+    #[must_use]
     pub const fn is_oya(&self) -> bool {
         self.oya == 0
     }
+    /// Counted from 0 in the current bakaze.
+    #[inline]
+    #[must_use]
+    pub const fn kyoku(&self) -> u8 {
+        self.kyoku
+    }
+    #[inline]
+    #[must_use]
+    pub const fn honba(&self) -> u8 {
+        self.honba
+    }
+    #[inline]
+    #[must_use]
+    pub const fn kyotaku(&self) -> u8 {
+        self.kyotaku
+    }
+    /// In relative seats.
+    #[inline]
+    #[must_use]
+    pub const fn scores(&self) -> [i32; 4] {
+        self.scores
+    }
+    #[inline]
+    #[must_use]
+    pub const fn tiles_left(&self) -> u8 {
+        self.tiles_left
+    }
+    /// The number of tiles left in the live wall, the same as `tiles_left`.
+    #[inline]
+    #[must_use]
+    pub const fn wall_remaining(&self) -> u8 {
+        self.tiles_left
+    }
+    /// The wind of each absolute seat in the current kyoku. The empty seat 3
+    /// of sanma is given N, which nobody else has.
+    #[inline]
+    #[must_use]
+    pub fn seat_winds(&self) -> [Tile; 4] {
+        let n = self.rules.mode.num_players();
+        let oya = (self.player_id + self.oya) % 4;
+        [0, 1, 2, 3].map(|seat| {
+            if seat < n {
+                must_tile!(tu8!(E) + (seat + n - oya) % n)
+            } else {
+                t!(N)
+            }
+        })
+    }
     #[inline]
     #[must_use]
     pub const fn tehai(&self) -> [u8; 34] {
@@ -19,10 +73,154 @@ impl PlayerState {
     }
     #[inline]
     #[must_use]
-    pub const fn akas_in_hand(&self) -> [bool; 3] {
-        self.akas_in_hand
+    pub fn akas_in_hand(&self) -> [bool; 3] {
+        self.akas_in_hand.map(|n| n > 0)
+    }
+    /// In the order they are revealed.
+    #[inline]
+    #[must_use]
+    pub fn dora_indicators(&self) -> &[Tile] {
+        &self.dora_indicators
+    }
+    /// The number of doras the player has seen, including the akas.
+    #[inline]
+    #[must_use]
+    pub const fn doras_seen(&self) -> u8 {
+        self.doras_seen
+    }
+    /// The number of doras the player owns, including the akas and
+    /// nukidoras. See `dora_breakdown` for the breakdown.
+    #[inline]
+    #[must_use]
+    pub const fn doras_owned(&self) -> u8 {
+        self.doras_owned[0]
+    }
+    /// The doras indicated by `dora_indicators`, deaka'd and in the same
+    /// order.
+    #[inline]
+    #[must_use]
+    pub fn dora_tiles(&self) -> Vec<Tile> {
+        self.dora_indicators.iter().map(|t| t.next()).collect()
+    }
+    /// The ura doras indicated by `markers`, such as the `ura_markers` of a
+    /// `hora`, deaka'd.
+    #[inline]
+    #[must_use]
+    pub fn ura_dora_tiles(&self, markers: &[Tile]) -> Vec<Tile> {
+        markers.iter().map(|t| t.next()).collect()
+    }
+    /// The number of copies of `tile` the player has not seen yet, that is,
+    /// not in the dora indicators, any kawa, fuuro or ankan, or the player's
+    /// own hand.
+    ///
+    /// A non-aka `tile` counts all four copies including the akas, while an
+    /// aka `tile` only counts the copies of that aka under the rule. 2m to 8m
+    /// are never unseen in sanma.
+    ///
+    /// Panics if `tile` is unknown.
+    #[inline]
+    #[must_use]
+    pub fn unseen_count(&self, tile: Tile) -> u8 {
+        if tile.is_aka() {
+            let aka_id = tile.as_usize() - 34;
+            self.aka_count_of(tile.deaka())
+                .saturating_sub(self.akas_seen[aka_id])
+        } else if self.rules.mode.has_tile(tile) {
+            4_u8.saturating_sub(self.tiles_seen[tile.as_usize()])
+        } else {
+            0
+        }
+    }
+    /// The number of copies of each tile kind the player has seen, with the
+    /// akas counted as their normal fives. The complement of `tiles_unseen`.
+    #[inline]
+    #[must_use]
+    pub const fn tiles_seen(&self) -> [u8; 34] {
+        self.tiles_seen
+    }
+    /// The number of copies of each tile kind the player has newly seen since
+    /// the end of its last decision point, that is, the last time it could
+    /// act, such as by the discards, melds and dora indicators of others and
+    /// its own draws. At a decision point, this is what is revealed since the
+    /// previous one. Everything seen in the kyoku counts before the first.
+    ///
+    /// A `dora` skipped by `update_with_skip` right after a decision point
+    /// is taken as seen by the end of it, so it is not counted by the next
+    /// one.
+    #[must_use]
+    pub fn tiles_seen_since_last_decision(&self) -> [u8; 34] {
+        let mut ret = self.tiles_seen;
+        for (seen, before) in ret.iter_mut().zip(self.tiles_seen_at_last_decision) {
+            *seen -= before;
+        }
+        ret
+    }
+    /// The number of copies of the current waits among
+    /// `tiles_seen_since_last_decision`, that is, the wait tiles taken out of
+    /// reach in the meantime.
+    #[must_use]
+    pub fn waits_seen_since_last_decision(&self) -> u8 {
+        self.tiles_seen_since_last_decision()
+            .into_iter()
+            .zip(self.waits)
+            .filter(|&(_, is_wait)| is_wait)
+            .map(|(count, _)| count)
+            .sum()
+    }
+    /// `unseen_count` of every tile kind, including the akas.
+    #[inline]
+    #[must_use]
+    pub fn tiles_unseen(&self) -> [u8; 34] {
+        let mut ret = self.tiles_seen.map(|seen| 4_u8.saturating_sub(seen));
+        if !self.rules.mode.has_tile(t!(5m)) {
+            ret[tuz!(2m)..=tuz!(8m)].fill(0);
+        }
+        ret
+    }
+    /// Aka dora covered version of `tiles_unseen`, in which 5m, 5p and 5s
+    /// exclude the akas, which are counted on their own.
+    #[must_use]
+    pub fn tiles_unseen_aka(&self) -> [u8; 37] {
+        let mut ret = [0; 37];
+        ret[..34].copy_from_slice(&self.tiles_unseen());
+        for aka in [t!(5mr), t!(5pr), t!(5sr)] {
+            let count = self.unseen_count(aka);
+            ret[aka.as_usize()] = count;
+            // Saturated in case the aka counts are inconsistent with the
+            // normal ones.
+            ret[aka.deaka().as_usize()] = ret[aka.deaka().as_usize()].saturating_sub(count);
+        }
+        ret
     }
 
+    /// The discards of `rel_seat` in order, without the gaps left by calls.
+    #[must_use]
+    pub fn kawa(&self, rel_seat: u8) -> Vec<KawaItemPy> {
+        self.kawa[rel_seat as usize]
+            .iter()
+            .flatten()
+            .map(|item| KawaItemPy::from(&item.sutehai))
+            .collect()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_menzen(&self) -> bool {
+        self.is_menzen
+    }
+    #[inline]
+    #[must_use]
+    pub const fn first_call_junme(&self) -> Option<u8> {
+        self.first_call_junme
+    }
+    /// The number of chi, pon and daiminkan made by the player in this kyoku.
+    #[inline]
+    #[must_use]
+    pub fn call_count(&self) -> u8 {
+        self.fuuro_overview[0].len() as u8
+    }
+    /// The chis by the lowest tile of each, deaka'd and in ascending order of
+    /// tile id, the same as `pons`, `minkans` and `ankans`.
     #[inline]
     #[must_use]
     pub fn chis(&self) -> &[u8] {
@@ -59,11 +257,15 @@ impl PlayerState {
     pub const fn waits(&self) -> [bool; 34] {
         self.waits
     }
+    /// The tile drawn in the current turn of the player, including the
+    /// rinshan. `None` once the player discards, or after a chi or pon.
     #[inline]
     #[must_use]
     pub const fn last_self_tsumo(&self) -> Option<Tile> {
         self.last_self_tsumo
     }
+    /// The latest discard by anyone, which stays after it is called. `None`
+    /// after a tsumo of the player.
     #[inline]
     #[must_use]
     pub const fn last_kawa_tile(&self) -> Option<Tile> {
@@ -75,6 +277,8 @@ impl PlayerState {
     pub const fn last_cans(&self) -> ActionCandidate {
         self.last_cans
     }
+    /// Deaka'd and in ascending order of tile id, the same as
+    /// `kakan_candidates`.
     #[inline]
     #[must_use]
     pub fn ankan_candidates(&self) -> &[Tile] {
@@ -85,6 +289,24 @@ impl PlayerState {
     pub fn kakan_candidates(&self) -> &[Tile] {
         &self.kakan_candidates
     }
+    /// `ankan_candidates` as a mask of tile ids, empty unless `can_ankan`.
+    #[must_use]
+    pub fn ankan_tiles(&self) -> [bool; 34] {
+        let mut ret = [false; 34];
+        for tile in &self.ankan_candidates {
+            ret[tile.as_usize()] = true;
+        }
+        ret
+    }
+    /// `kakan_candidates` as a mask of tile ids, empty unless `can_kakan`.
+    #[must_use]
+    pub fn kakan_tiles(&self) -> [bool; 34] {
+        let mut ret = [false; 34];
+        for tile in &self.kakan_candidates {
+            ret[tile.as_usize()] = true;
+        }
+        ret
+    }
 
     #[inline]
     #[must_use]
@@ -101,10 +323,115 @@ impl PlayerState {
     pub const fn self_riichi_accepted(&self) -> bool {
         self.riichi_accepted[0]
     }
+    /// Whether the riichi of each player, in relative seats, has been
+    /// accepted.
+    #[inline]
+    #[must_use]
+    pub const fn riichi_accepted(&self) -> [bool; 4] {
+        self.riichi_accepted
+    }
+
+    /// The number of draws of the other players before the next draw of the
+    /// player if nobody calls, which is 0 if the player draws next, and 3
+    /// (2 in sanma) right after the player's own draw.
+    #[inline]
+    #[must_use]
+    pub const fn seats_until_my_turn(&self) -> u8 {
+        let seats = (4 - self.next_draw) % 4;
+        match self.empty_seat() {
+            Some(empty) if self.next_draw != 0 && empty as u8 >= self.next_draw => seats - 1,
+            _ => seats,
+        }
+    }
+    /// The number of draws `rel_seat` makes before the next draw of the
+    /// player if nobody calls, which is either 0 or 1.
+    ///
+    /// Panics if `rel_seat` is outside of range [0, 3].
+    #[inline]
+    #[must_use]
+    pub const fn draws_before_my_next_draw(&self, rel_seat: u8) -> u8 {
+        assert!(rel_seat < 4, "rel_seat is not in range [0, 3]");
+        let is_empty = matches!(self.empty_seat(), Some(empty) if empty == rel_seat as usize);
+        (rel_seat != 0 && !is_empty && self.next_draw != 0 && rel_seat >= self.next_draw) as u8
+    }
+    /// For each riichi opponent in relative seats, whether the next discard
+    /// of the player is the last one they see before their draw if nobody
+    /// calls, which is only the case for the shimocha, the next seat in turn
+    /// order.
+    #[inline]
+    #[must_use]
+    pub const fn discard_precedes_riichi_draw(&self) -> [bool; 4] {
+        let shimocha = self.rel(self.next_seat(self.player_id));
+        let mut ret = [false; 4];
+        ret[shimocha] = self.riichi_accepted[shimocha];
+        ret
+    }
+
+    /// Whether `rel_seat` qualifies for 流し満貫 if the kyoku ends in an
+    /// exhaustive draw now, which is the case if it has discarded, and all
+    /// of its discards are terminals or honors that nobody has called.
+    ///
+    /// Panics if `rel_seat` is outside of range [0, 3].
+    #[must_use]
+    pub fn nagashi_mangan(&self, rel_seat: u8) -> bool {
+        assert!(rel_seat < 4, "rel_seat is not in range [0, 3]");
+        let mut discards = self.kawa[rel_seat as usize]
+            .iter()
+            .flatten()
+            .map(|item| &item.sutehai)
+            .peekable();
+        discards.peek().is_some()
+            && discards.all(|sutehai| sutehai.tile.is_yaokyuu() && !sutehai.is_called)
+    }
 
     #[inline]
     #[must_use]
     pub const fn at_furiten(&self) -> bool {
         self.at_furiten
     }
+    /// Whether the latest kyoku that has ended, which is the current one
+    /// until the next `start_kyoku`, ended in a ryukyoku.
+    #[inline]
+    #[must_use]
+    pub const fn last_kyoku_ended_in_draw(&self) -> bool {
+        self.last_kyoku_ended_in_draw
+    }
+    #[inline]
+    #[must_use]
+    pub const fn furiten_kind(&self) -> FuritenKind {
+        self.furiten_kind
+    }
+
+    /// The number of rinshan tiles left in the dead wall. Each kan on the
+    /// board consumes one, and no kan can be made when it reaches 0, even if
+    /// all the four kans are made by the same player.
+    #[inline]
+    #[must_use]
+    pub const fn rinshan_tiles_left(&self) -> u8 {
+        4_u8.saturating_sub(self.kans_on_board)
+    }
+
+    /// Returns the relative seat liable (包) for the daisangen or daisuushii
+    /// of `rel_player`, if any.
+    #[inline]
+    #[must_use]
+    pub const fn pao_target(&self, rel_player: u8) -> Option<u8> {
+        self.paos[rel_player as usize]
+    }
+
+    /// Returns the relative seat liable (包) for the player's own agari if it
+    /// happens now, which is the `pao_target` of the player, or else under
+    /// `DaiminkanPaoRule::RinshanKaihou`, the feeder of the daiminkan on its
+    /// rinshan draw.
+    ///
+    /// As per Tenhou's rule, the liable player pays all of the ron points of
+    /// `agari_points` on tsumo and half of them on ron, plus all the honba in
+    /// both cases, as `agari_deltas` does.
+    #[must_use]
+    pub fn pao_liable_seat(&self) -> Option<u8> {
+        self.paos[0].or(match self.rules.daiminkan_pao {
+            DaiminkanPaoRule::RinshanKaihou if self.at_rinshan => self.rinshan_pao,
+            _ => None,
+        })
+    }
 }
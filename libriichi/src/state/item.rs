@@ -1,6 +1,7 @@
 use crate::tile::Tile;
 use std::fmt;
 
+use pyo3::prelude::*;
 use serde::Serialize;
 use tinyvec::ArrayVec;
 
@@ -17,6 +18,73 @@ pub(super) struct Sutehai {
     pub(super) is_dora: bool,
     pub(super) is_tedashi: bool,
     pub(super) is_riichi: bool,
+    /// Taken by someone for a chi, pon or daiminkan.
+    pub(super) is_called: bool,
+}
+
+/// Returned by `PlayerState::furiten_kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FuritenKind {
+    #[default]
+    None,
+    /// Same-cycle or no-yaku furiten, cleared by the player's next discard.
+    Temporary,
+    /// A winning tile was passed after riichi, lasting until the end of the
+    /// kyoku.
+    Riichi,
+    /// One of the waits is in the player's own kawa, lasting until the waits
+    /// change.
+    Permanent,
+}
+
+impl FuritenKind {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Temporary => "temporary",
+            Self::Riichi => "riichi",
+            Self::Permanent => "permanent",
+        }
+    }
+}
+
+/// A discard in a kawa, returned by `PlayerState::kawa`.
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KawaItemPy {
+    pub tile: Tile,
+    #[pyo3(get)]
+    pub tsumogiri: bool,
+    #[pyo3(get)]
+    pub called: bool,
+    /// The discard declaring riichi.
+    #[pyo3(get)]
+    pub riichi: bool,
+}
+
+#[pymethods]
+impl KawaItemPy {
+    #[getter]
+    #[pyo3(name = "tile")]
+    fn tile_py(&self) -> String {
+        self.tile.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl From<&Sutehai> for KawaItemPy {
+    fn from(sutehai: &Sutehai) -> Self {
+        Self {
+            tile: sutehai.tile,
+            tsumogiri: !sutehai.is_tedashi,
+            called: sutehai.is_called,
+            riichi: sutehai.is_riichi,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -1,23 +1,49 @@
 mod action;
 mod agent_helper;
+mod batch_encoder;
+mod bytes;
+mod checked;
 mod getter;
 mod item;
 mod obs_repr;
+mod oracle;
 mod player_state;
+mod replay;
+mod rule;
 mod update;
 
 #[cfg(test)]
 mod test;
 
 use crate::py_helper::add_submodule;
-pub use action::ActionCandidate;
+pub use action::{ActionCandidate, Reaction};
+pub use agent_helper::{
+    AcceptanceProfile, AgariDetail, DoraBreakdown, PushLine, RiichiDiscards, RiichiExclusion,
+    SafetyInfo, TenpaiInfo, WaitKind, WaitShape, YakumanThreat,
+};
+pub use batch_encoder::BatchEncoder;
+pub use checked::{Capacity, UpdateError};
+pub use item::{FuritenKind, KawaItemPy};
+pub use obs_repr::{
+    obs_plane_names, sanma_obs_plane_names, score_pressure_plane_names, seen_delta_plane_names,
+    turn_order_plane_names,
+};
+pub use oracle::{oracle_obs_plane_names, OracleState};
 pub use player_state::PlayerState;
+pub use rule::{
+    AkaRule, BlessingRule, DaiminkanPaoRule, GameMode, KanDoraRule, KazoeRule, KuikaeRule,
+    KuitanRule, MultiRonRule, Rules, TripleRonRule, YakumanRule,
+};
+pub use update::Effect;
 
 use pyo3::prelude::*;
 
 pub(crate) fn register_module(py: Python<'_>, prefix: &str, super_mod: &PyModule) -> PyResult<()> {
     let m = PyModule::new(py, "state")?;
     m.add_class::<ActionCandidate>()?;
+    m.add_class::<BatchEncoder>()?;
     m.add_class::<PlayerState>()?;
+    m.add_class::<KawaItemPy>()?;
+    m.add_class::<OracleState>()?;
     add_submodule(py, prefix, super_mod, m)
 }
@@ -1,12 +1,214 @@
-use super::PlayerState;
-use crate::consts::{ACTION_SPACE, OBS_SHAPE};
+use super::{GameMode, PlayerState};
+use crate::consts::{
+    OBS_SHAPE, SANMA_OBS_SHAPE, SCORE_PRESSURE_SHAPE, SEEN_DELTA_SHAPE, TURN_ORDER_SHAPE,
+};
 use crate::state::item::KawaItem;
 use crate::{tu8, tuz};
 
+use anyhow::{Context, Result};
 use ndarray::prelude::*;
 use numpy::{PyArray1, PyArray2};
+use once_cell::sync::Lazy;
 use pyo3::prelude::*;
 
+static OBS_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    const AKA: [&str; 3] = ["m", "p", "s"];
+
+    fn push_n(names: &mut Vec<String>, prefix: &str, n: usize) {
+        names.extend((1..=n).map(|i| format!("{prefix}_{i}")));
+    }
+
+    let mut names = vec![];
+
+    push_n(&mut names, "tehai", 4);
+    names.extend(AKA.map(|s| format!("aka_in_hand_{s}")));
+    names.extend((0..4).map(|i| format!("score_{i}")));
+    names.extend((0..4).map(|i| format!("rank_{i}")));
+    push_n(&mut names, "kyoku", 4);
+    push_n(&mut names, "honba", 10);
+    push_n(&mut names, "kyotaku", 10);
+    names.push("bakaze".to_owned());
+    names.push("jikaze".to_owned());
+    push_n(&mut names, "dora_indicator", 4);
+    names.extend(AKA.map(|s| format!("dora_indicator_aka_{s}")));
+
+    for (part, slots) in [("head", 6), ("tail", 18)] {
+        for slot in 0..slots {
+            for plane in ["kan", "tile", "aka", "dora"] {
+                names.push(format!("kawa_0_{part}_{slot}_{plane}"));
+            }
+        }
+    }
+    for rel in 1..4 {
+        for (part, slots) in [("head", 6), ("tail", 18)] {
+            for slot in 0..slots {
+                for plane in [
+                    "chi_pon_min",
+                    "chi_pon_max",
+                    "kan",
+                    "tile",
+                    "aka",
+                    "dora",
+                    "tedashi",
+                    "riichi",
+                ] {
+                    names.push(format!("kawa_{rel}_{part}_{slot}_{plane}"));
+                }
+            }
+        }
+    }
+
+    names.push("tiles_left".to_owned());
+    for rel in 0..4 {
+        push_n(&mut names, &format!("doras_owned_{rel}"), 12);
+    }
+    push_n(&mut names, "doras_unseen", 5 * 4 + 3);
+    for rel in 0..4 {
+        push_n(&mut names, &format!("kawa_overview_{rel}"), 4);
+        names.extend(AKA.map(|s| format!("kawa_overview_{rel}_aka_{s}")));
+    }
+    for rel in 0..4 {
+        for slot in 0..4 {
+            push_n(&mut names, &format!("fuuro_{rel}_{slot}"), 4);
+            names.push(format!("fuuro_{rel}_{slot}_aka"));
+        }
+    }
+    names.extend((0..4).map(|rel| format!("ankan_{rel}")));
+    names.extend((1..4).map(|rel| format!("riichi_declared_{rel}")));
+    names.extend((1..4).map(|rel| format!("riichi_accepted_{rel}")));
+
+    names.push("waits".to_owned());
+    names.push("at_furiten".to_owned());
+    push_n(&mut names, "shanten", 6);
+    names.push("self_riichi_accepted".to_owned());
+    names.push("at_kan_select".to_owned());
+
+    names.extend(
+        [
+            "last_kawa_tile",
+            "last_kawa_tile_aka",
+            "last_kawa_tile_dora",
+            "discard_candidates",
+            "keep_shanten_discards",
+            "next_shanten_discards",
+            "discard_candidates_with_unconditional_tenpai",
+            "self_riichi_declared",
+            "can_riichi",
+            "can_chi_low",
+            "can_chi_mid",
+            "can_chi_high",
+            "can_pon",
+            "can_daiminkan",
+            "ankan_candidates",
+            "kakan_candidates",
+            "can_agari",
+            "can_ryukyoku",
+        ]
+        .map(str::to_owned),
+    );
+
+    assert_eq!(names.len(), OBS_SHAPE.0);
+    names
+});
+
+static SANMA_OBS_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    let mut names = OBS_PLANE_NAMES.clone();
+    for rel in 0..4 {
+        names.extend((1..=4).map(|i| format!("nukidora_{rel}_{i}")));
+    }
+    names.push("can_nukidora".to_owned());
+
+    assert_eq!(names.len(), SANMA_OBS_SHAPE.0);
+    names
+});
+
+static SEEN_DELTA_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    let mut names: Vec<_> = (1..=4).map(|i| format!("seen_delta_{i}")).collect();
+    names.extend((1..=4).map(|i| format!("waits_seen_delta_{i}")));
+
+    assert_eq!(names.len(), SEEN_DELTA_SHAPE.0);
+    names
+});
+
+static TURN_ORDER_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    let names: Vec<_> = (1..=3)
+        .map(|i| format!("seats_until_my_turn_{i}"))
+        .collect();
+
+    assert_eq!(names.len(), TURN_ORDER_SHAPE.0);
+    names
+});
+
+static SCORE_PRESSURE_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    let names =
+        Vec::from(["score_pressure_to_above", "score_pressure_to_below"].map(str::to_owned));
+
+    assert_eq!(names.len(), SCORE_PRESSURE_SHAPE.0);
+    names
+});
+
+/// Returns the names of the planes (channels) of `encode_obs`, in the exact
+/// order they are written.
+///
+/// A name ending with `_{n}` without further suffix is usually a thermometer
+/// encoding, where the plane is filled iff the value is at least `n`.
+/// `kawa_{rel}_head_{slot}` is the `slot`-th item of the kawa counting from
+/// the first, while `kawa_{rel}_tail_{slot}` counts from the latest.
+/// `keep_shanten_discards` and `next_shanten_discards` are zero once the
+/// player's riichi is accepted.
+#[must_use]
+pub fn obs_plane_names() -> Vec<&'static str> {
+    OBS_PLANE_NAMES.iter().map(String::as_str).collect()
+}
+
+/// Same as `obs_plane_names`, but for the obs of sanma, which starts with the
+/// exact planes of yonma and appends:
+///
+/// - `nukidora_{rel}_{n}`, the thermometer of the number of N extracted by
+///   each relative seat.
+/// - `can_nukidora`, filled iff `can_nukidora` of the candidates is set.
+///
+/// The planes of the empty seat, such as its kawa and score, are left zero,
+/// and as there is no chi, so are the chi planes.
+#[must_use]
+pub fn sanma_obs_plane_names() -> Vec<&'static str> {
+    SANMA_OBS_PLANE_NAMES.iter().map(String::as_str).collect()
+}
+
+/// Returns the names of the planes of `encode_seen_delta`:
+///
+/// - `seen_delta_{n}`, the thermometer of `tiles_seen_since_last_decision`
+///   of each tile.
+/// - `waits_seen_delta_{n}`, filled iff `waits_seen_since_last_decision` is
+///   at least `n`.
+#[must_use]
+pub fn seen_delta_plane_names() -> Vec<&'static str> {
+    SEEN_DELTA_PLANE_NAMES.iter().map(String::as_str).collect()
+}
+
+/// Returns the names of the planes of `encode_turn_order`:
+///
+/// - `seats_until_my_turn_{n}`, filled iff `seats_until_my_turn` is at least
+///   `n`.
+///
+/// `draws_before_my_next_draw` and `discard_precedes_riichi_draw` have no
+/// planes of their own, as they follow from `seats_until_my_turn_{n}` and
+/// `riichi_accepted_1` of `encode_obs` respectively.
+#[must_use]
+pub fn turn_order_plane_names() -> Vec<&'static str> {
+    TURN_ORDER_PLANE_NAMES.iter().map(String::as_str).collect()
+}
+
+/// Returns the names of the planes of `encode_score_pressure`, each filled
+/// with one of the values of `score_pressure`.
+#[must_use]
+pub fn score_pressure_plane_names() -> Vec<&'static str> {
+    SCORE_PRESSURE_PLANE_NAMES
+        .iter()
+        .map(String::as_str)
+        .collect()
+}
+
 #[pymethods]
 impl PlayerState {
     /// Returns `(obs, mask)`
@@ -22,14 +224,175 @@ impl PlayerState {
         let mask = PyArray1::from_owned_array(py, mask);
         (obs, mask)
     }
+
+    /// Same as `encode_obs`, except that aka doras are encoded as if they
+    /// were plain fives.
+    #[pyo3(name = "encode_obs_no_aka")]
+    #[pyo3(text_signature = "($self, at_kan_select)")]
+    fn encode_obs_no_aka_py<'py>(
+        &self,
+        at_kan_select: bool,
+        py: Python<'py>,
+    ) -> (&'py PyArray2<f32>, &'py PyArray1<bool>) {
+        let (obs, mask) = self.encode_obs_no_aka(at_kan_select);
+        let obs = PyArray2::from_owned_array(py, obs);
+        let mask = PyArray1::from_owned_array(py, mask);
+        (obs, mask)
+    }
+
+    #[pyo3(name = "encode_seen_delta")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn encode_seen_delta_py<'py>(&self, py: Python<'py>) -> &'py PyArray2<f32> {
+        PyArray2::from_owned_array(py, self.encode_seen_delta())
+    }
+
+    #[pyo3(name = "encode_turn_order")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn encode_turn_order_py<'py>(&self, py: Python<'py>) -> &'py PyArray2<f32> {
+        PyArray2::from_owned_array(py, self.encode_turn_order())
+    }
+
+    #[pyo3(name = "encode_score_pressure")]
+    #[args(swing_per_hand, "*", game_length = "8")]
+    #[pyo3(text_signature = "($self, swing_per_hand, *, game_length = 8)")]
+    fn encode_score_pressure_py<'py>(
+        &self,
+        swing_per_hand: f32,
+        game_length: u8,
+        py: Python<'py>,
+    ) -> &'py PyArray2<f32> {
+        PyArray2::from_owned_array(py, self.encode_score_pressure(swing_per_hand, game_length))
+    }
+
+    /// Replays `events`, which are mjai lines, and returns `(obs, shape)` of
+    /// the flattened `encode_obs(false)` at every point where the player can
+    /// act, so that a whole game is encoded in one call. `shape` is
+    /// `[len(obs), *obs_shape()]`.
+    #[pyo3(text_signature = "($self, events, /)")]
+    pub fn encode_trajectory(
+        &mut self,
+        events: Vec<String>,
+    ) -> Result<(Vec<Vec<f32>>, Vec<usize>)> {
+        let mut obs = vec![];
+        for line in &events {
+            let cans = self
+                .update_json(line)
+                .with_context(|| format!("failed to update with {line}"))?;
+            if cans.can_act() {
+                let (encoded, _) = self.encode_obs_raw(false);
+                obs.push(encoded);
+            }
+        }
+        let (channels, width) = self.obs_shape();
+        let shape = vec![obs.len(), channels, width];
+        Ok((obs, shape))
+    }
 }
 
 impl PlayerState {
+    /// The shape of the obs of `encode_obs`, which is `SANMA_OBS_SHAPE` in
+    /// sanma and `OBS_SHAPE` otherwise.
+    #[inline]
+    #[must_use]
+    pub const fn obs_shape(&self) -> (usize, usize) {
+        match self.rules.mode {
+            GameMode::Yonma => OBS_SHAPE,
+            GameMode::Sanma => SANMA_OBS_SHAPE,
+        }
+    }
+
     /// Returns `(obs, mask)`
+    ///
+    /// The planes are listed by `obs_plane_names`, or
+    /// `sanma_obs_plane_names` in sanma.
     #[must_use]
     pub fn encode_obs(&self, at_kan_select: bool) -> (Array2<f32>, Array1<bool>) {
-        let mut arr = Array2::zeros(OBS_SHAPE);
-        let mut mask = Array1::default(ACTION_SPACE);
+        self.encode_obs_impl(at_kan_select, true)
+    }
+
+    /// Returns `(obs, shape)`, where `obs` is the same as the one from
+    /// `encode_obs` but flattened in row-major order, for consumers that do
+    /// not use ndarray.
+    #[must_use]
+    pub fn encode_obs_raw(&self, at_kan_select: bool) -> (Vec<f32>, Vec<usize>) {
+        let (obs, _) = self.encode_obs(at_kan_select);
+        let shape = obs.shape().to_vec();
+        (obs.into_raw_vec(), shape)
+    }
+
+    /// Returns `(obs, mask)` for models trained on rulesets without aka dora.
+    ///
+    /// The shape is the same as `encode_obs`, but all the aka planes are left
+    /// zero and aka doras are not counted in the dora planes, as if every red
+    /// five were a plain five. The mask is the same as `encode_obs`.
+    #[must_use]
+    pub fn encode_obs_no_aka(&self, at_kan_select: bool) -> (Array2<f32>, Array1<bool>) {
+        self.encode_obs_impl(at_kan_select, false)
+    }
+
+    /// Encodes what the player has newly seen since its last decision point
+    /// into `SEEN_DELTA_SHAPE`, to be appended to `encode_obs` by models that
+    /// do not see the sequence of events. The planes are listed by
+    /// `seen_delta_plane_names`.
+    #[must_use]
+    pub fn encode_seen_delta(&self) -> Array2<f32> {
+        let mut arr = Array2::zeros(SEEN_DELTA_SHAPE);
+
+        let seen = self.tiles_seen_since_last_decision();
+        for (tile_id, &count) in seen.iter().enumerate() {
+            let n = count.min(4) as usize;
+            arr.slice_mut(s![..n, tile_id]).fill(1.);
+        }
+
+        let n = self.waits_seen_since_last_decision().min(4) as usize;
+        arr.slice_mut(s![4..4 + n, ..]).fill(1.);
+
+        arr
+    }
+
+    /// Encodes `seats_until_my_turn` into `TURN_ORDER_SHAPE`, to be appended
+    /// to `encode_obs` by models that take it, see `BatchEncoder::turn_order`.
+    /// The planes are listed by `turn_order_plane_names`.
+    #[must_use]
+    pub fn encode_turn_order(&self) -> Array2<f32> {
+        let mut arr = Array2::zeros(TURN_ORDER_SHAPE);
+        let n = (self.seats_until_my_turn() as usize).min(TURN_ORDER_SHAPE.0);
+        arr.slice_mut(s![..n, ..]).fill(1.);
+        arr
+    }
+
+    /// Encodes `score_pressure` into `SCORE_PRESSURE_SHAPE`, to be appended
+    /// to `encode_obs` by models that take it, see
+    /// `BatchEncoder::score_pressure`. The planes are listed by
+    /// `score_pressure_plane_names`.
+    #[must_use]
+    pub fn encode_score_pressure(&self, swing_per_hand: f32, game_length: u8) -> Array2<f32> {
+        let mut arr = Array2::zeros(SCORE_PRESSURE_SHAPE);
+        for (mut plane, v) in arr
+            .outer_iter_mut()
+            .zip(self.score_pressure(swing_per_hand, game_length))
+        {
+            plane.fill(v);
+        }
+        arr
+    }
+
+    fn encode_obs_impl(&self, at_kan_select: bool, with_aka: bool) -> (Array2<f32>, Array1<bool>) {
+        let mut arr = Array2::zeros(self.obs_shape());
+        let mut mask = Array1::default(self.action_space());
+        self.encode_obs_into(at_kan_select, with_aka, arr.view_mut(), mask.view_mut());
+        (arr, mask)
+    }
+
+    /// Writes the obs and mask into `arr` and `mask`, where `arr` must be
+    /// zeroed.
+    pub(super) fn encode_obs_into(
+        &self,
+        at_kan_select: bool,
+        with_aka: bool,
+        mut arr: ArrayViewMut2<'_, f32>,
+        mut mask: ArrayViewMut1<'_, bool>,
+    ) {
         let mut idx = 0;
         let cans = self.last_cans;
 
@@ -46,7 +409,7 @@ impl PlayerState {
         self.akas_in_hand
             .into_iter()
             .enumerate()
-            .filter(|&(_, has_it)| has_it)
+            .filter(|&(_, count)| with_aka && count > 0)
             .for_each(|(i, _)| {
                 arr.slice_mut(s![idx + i, ..]).fill(1.);
             });
@@ -62,7 +425,8 @@ impl PlayerState {
         arr.slice_mut(s![idx + i, ..]).fill(1.);
         idx += 4;
 
-        let n = self.kyoku as usize;
+        // E5 and beyond share the last plane.
+        let n = self.kyoku.min(4) as usize;
         arr.slice_mut(s![idx..idx + n, ..]).fill(1.);
         idx += 4;
 
@@ -82,7 +446,7 @@ impl PlayerState {
             let tile_id = tile.deaka().as_usize();
             let i = (0..4).find(|&i| arr[[idx + i, tile_id]] == 0.).unwrap();
             arr[[idx + i, tile_id]] = 1.;
-            if tile.is_aka() {
+            if with_aka && tile.is_aka() {
                 let i = tile.as_usize() - 34;
                 arr.slice_mut(s![idx + 4 + i, ..]).fill(1.);
             }
@@ -100,7 +464,7 @@ impl PlayerState {
             let sutehai = &k.sutehai;
             let tile_id = sutehai.tile.deaka().as_usize();
             arr[[idx + 1, tile_id]] = 1.;
-            if sutehai.tile.is_aka() {
+            if with_aka && sutehai.tile.is_aka() {
                 arr.slice_mut(s![idx + 2, ..]).fill(1.);
             }
             if sutehai.is_dora {
@@ -145,7 +509,7 @@ impl PlayerState {
 
             let tile_id = k.sutehai.tile.deaka().as_usize();
             arr[[idx + 3, tile_id]] = 1.;
-            if k.sutehai.tile.is_aka() {
+            if with_aka && k.sutehai.tile.is_aka() {
                 arr.slice_mut(s![idx + 4, ..]).fill(1.);
             }
             if k.sutehai.is_dora {
@@ -181,13 +545,22 @@ impl PlayerState {
         arr.slice_mut(s![idx, ..]).fill(v);
         idx += 1;
 
-        for count in self.doras_owned {
+        let doras_owned = if with_aka {
+            self.doras_owned
+        } else {
+            self.doras_owned_no_aka()
+        };
+        for count in doras_owned {
             let n = count.min(12) as usize;
             arr.slice_mut(s![idx..idx + n, ..]).fill(1.);
             idx += 12;
         }
 
-        let doras_unseen = self.dora_indicators.len() as u8 * 4 + 3 - self.doras_seen;
+        let doras_unseen = if with_aka {
+            self.dora_indicators.len() as u8 * 4 + self.rules.aka.count() - self.doras_seen
+        } else {
+            self.dora_indicators.len() as u8 * 4 - self.doras_seen_no_aka()
+        };
         let n = doras_unseen.min(5 * 4 + 3) as usize;
         arr.slice_mut(s![idx..idx + n, ..]).fill(1.);
         idx += 5 * 4 + 3;
@@ -197,7 +570,7 @@ impl PlayerState {
                 let tile_id = tile.deaka().as_usize();
                 let i = (0..4).find(|&i| arr[[idx + i, tile_id]] == 0.).unwrap();
                 arr[[idx + i, tile_id]] = 1.;
-                if tile.is_aka() {
+                if with_aka && tile.is_aka() {
                     let i = tile.as_usize() - 34;
                     arr.slice_mut(s![idx + 4 + i, ..]).fill(1.);
                 }
@@ -214,7 +587,7 @@ impl PlayerState {
                     // It is not possible to have more than one aka in a fuuro
                     // set, at least in tenhou rule, so we simply use one
                     // channel here.
-                    if tile.is_aka() {
+                    if with_aka && tile.is_aka() {
                         arr.slice_mut(s![idx + 4, ..]).fill(1.);
                     }
                 }
@@ -277,19 +650,12 @@ impl PlayerState {
             let tile_id = tile.deaka().as_usize();
 
             arr[[idx, tile_id]] = 1.;
-            if tile.is_aka() {
+            if with_aka && tile.is_aka() {
                 arr.slice_mut(s![idx + 1, ..]).fill(1.);
             }
             if self.dora_factor[tile.deaka().as_usize()] > 0 {
                 arr.slice_mut(s![idx + 2, ..]).fill(1.);
             }
-
-            // pass
-            if !at_kan_select {
-                mask[ACTION_SPACE - 1] = true;
-            } else if cans.can_daiminkan {
-                mask[tile_id] = true;
-            }
         }
         idx += 3;
 
@@ -306,21 +672,22 @@ impl PlayerState {
                         _ => t,
                     };
                     arr[[idx, deaka_t]] = 1.;
-                    if !at_kan_select {
-                        mask[t] = true;
-                    }
                 });
 
-            self.keep_shanten_discards
-                .iter()
-                .enumerate()
-                .filter(|(_, &c)| c)
-                .for_each(|(t, _)| arr[[idx + 1, t]] = 1.);
-            self.next_shanten_discards
-                .iter()
-                .enumerate()
-                .filter(|(_, &c)| c)
-                .for_each(|(t, _)| arr[[idx + 2, t]] = 1.);
+            // Left zero once the hand is frozen by riichi, where the only
+            // discard is the tsumogiri anyways.
+            if !self.is_hand_frozen() {
+                self.keep_shanten_discards
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c)
+                    .for_each(|(t, _)| arr[[idx + 1, t]] = 1.);
+                self.next_shanten_discards
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c)
+                    .for_each(|(t, _)| arr[[idx + 2, t]] = 1.);
+            }
 
             if self.shanten <= 1 {
                 self.discard_candidates_with_unconditional_tenpai()
@@ -338,57 +705,33 @@ impl PlayerState {
 
         if cans.can_riichi {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[37] = true;
-            }
         }
         idx += 1;
 
         if cans.can_chi_low {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[38] = true;
-            }
         }
         if cans.can_chi_mid {
             arr.slice_mut(s![idx + 1, ..]).fill(1.);
-            if !at_kan_select {
-                mask[39] = true;
-            }
         }
         if cans.can_chi_high {
             arr.slice_mut(s![idx + 2, ..]).fill(1.);
-            if !at_kan_select {
-                mask[40] = true;
-            }
         }
         idx += 3;
 
         if cans.can_pon {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[41] = true;
-            }
         }
         idx += 1;
 
         if cans.can_daiminkan {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[42] = true;
-            }
         }
         idx += 1;
 
         if cans.can_ankan {
             for tile in self.ankan_candidates {
                 arr[[idx, tile.as_usize()]] = 1.;
-                if at_kan_select {
-                    mask[tile.as_usize()] = true;
-                }
-            }
-            if !at_kan_select {
-                mask[42] = true;
             }
         }
         idx += 1;
@@ -396,33 +739,71 @@ impl PlayerState {
         if cans.can_kakan {
             for tile in self.kakan_candidates {
                 arr[[idx, tile.as_usize()]] = 1.;
-                if at_kan_select {
-                    mask[tile.as_usize()] = true;
-                }
-            }
-            if !at_kan_select {
-                mask[42] = true;
             }
         }
         idx += 1;
 
         if cans.can_tsumo_agari || cans.can_ron_agari {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[43] = true;
-            }
         }
         idx += 1;
 
         if cans.can_ryukyoku {
             arr.slice_mut(s![idx, ..]).fill(1.);
-            if !at_kan_select {
-                mask[44] = true;
-            }
         }
         idx += 1;
 
-        assert_eq!(idx, OBS_SHAPE.0);
-        (arr, mask)
+        if self.rules.mode == GameMode::Sanma {
+            for count in self.nukidoras {
+                let n = count.min(4) as usize;
+                arr.slice_mut(s![idx..idx + n, ..]).fill(1.);
+                idx += 4;
+            }
+
+            if cans.can_nukidora {
+                arr.slice_mut(s![idx, ..]).fill(1.);
+            }
+            idx += 1;
+        }
+
+        assert_eq!(idx, self.obs_shape().0);
+
+        for (m, legal) in mask.iter_mut().zip(self.legal_action_mask(at_kan_select)) {
+            *m = legal;
+        }
+    }
+
+    /// `doras_owned` without counting aka doras.
+    fn doras_owned_no_aka(&self) -> [u8; 4] {
+        let mut ret = [0; 4];
+        for (i, count) in ret.iter_mut().enumerate() {
+            let in_fuuro = self.fuuro_overview[i]
+                .iter()
+                .flatten()
+                .map(|t| self.dora_factor[t.deaka().as_usize()])
+                .sum::<u8>();
+            let in_ankan = self.ankan_overview[i]
+                .iter()
+                .map(|t| self.dora_factor[t.as_usize()] * 4)
+                .sum::<u8>();
+            let in_nukidora = self.nukidoras[i] * (1 + self.dora_factor[tuz!(N)]);
+            *count = in_fuuro + in_ankan + in_nukidora;
+        }
+        ret[0] += self
+            .tehai
+            .iter()
+            .zip(self.dora_factor)
+            .map(|(&count, factor)| count * factor)
+            .sum::<u8>();
+        ret
+    }
+
+    /// `doras_seen` without counting aka doras.
+    fn doras_seen_no_aka(&self) -> u8 {
+        self.tiles_seen
+            .iter()
+            .zip(self.dora_factor)
+            .map(|(&count, factor)| count * factor)
+            .sum()
     }
 }
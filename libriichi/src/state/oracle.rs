@@ -0,0 +1,197 @@
+use super::PlayerState;
+use crate::consts::ORACLE_OBS_SHAPE;
+use crate::dataset::Invisible;
+use crate::logs::parse_log;
+use crate::mjai::Event;
+use crate::t;
+
+use anyhow::{ensure, Result};
+use ndarray::prelude::*;
+use numpy::PyArray2;
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+
+static ORACLE_OBS_PLANE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
+    const AKA: [&str; 3] = ["m", "p", "s"];
+
+    fn push_n(names: &mut Vec<String>, prefix: &str, n: usize) {
+        names.extend((1..=n).map(|i| format!("{prefix}_{i}")));
+    }
+    fn push_tiles(names: &mut Vec<String>, prefix: &str, n: usize) {
+        for i in 1..=n {
+            names.push(format!("{prefix}_{i}"));
+            names.push(format!("{prefix}_{i}_aka"));
+        }
+    }
+
+    let mut names = vec![];
+
+    for rel in 1..4 {
+        push_n(&mut names, &format!("tehai_{rel}"), 4);
+        names.extend(AKA.map(|s| format!("aka_in_hand_{rel}_{s}")));
+        push_n(&mut names, &format!("shanten_{rel}"), 6);
+        names.push(format!("waits_{rel}"));
+        names.push(format!("at_furiten_{rel}"));
+    }
+
+    push_tiles(&mut names, "yama", 69);
+    push_tiles(&mut names, "rinshan", 4);
+    push_tiles(&mut names, "dora_indicator", 5);
+    push_tiles(&mut names, "ura_indicator", 5);
+
+    assert_eq!(names.len(), ORACLE_OBS_SHAPE.0);
+    names
+});
+
+/// Returns the names of the planes (channels) of the oracle obs, written by
+/// both `Invisible::encode` and `OracleState::encode`, in the exact order
+/// they are written, the same way as `obs_plane_names`.
+///
+/// `{plane}_{rel}` is about the opponent at `rel` seats after the player.
+/// `yama_{i}` is the `i`-th tile left in the live wall, in the order they are
+/// drawn, with the drawn ones leaving their planes empty at the end.
+/// `rinshan_{i}` works the same way. `{plane}_aka` is filled if the tile of
+/// `{plane}` is an aka.
+#[must_use]
+pub fn oracle_obs_plane_names() -> Vec<&'static str> {
+    ORACLE_OBS_PLANE_NAMES.iter().map(String::as_str).collect()
+}
+
+/// `OracleState` encodes the information hidden from a specific seat, for
+/// critics that are allowed to peek during training, the same way as the
+/// oracle obs of `GameplayLoader`.
+///
+/// It is built from the perfect-information log of the whole game, in which
+/// the `tehais` of `start_kyoku` and the `pai` of every `tsumo` are not
+/// masked with `?`, and then fed with the events of that log one by one. A
+/// log recorded from a single player's perspective is rejected with an
+/// error.
+#[pyclass]
+#[pyo3(text_signature = "(player_id, log, *, trust_seed=False)")]
+#[derive(Debug, Clone)]
+pub struct OracleState {
+    #[pyo3(get)]
+    player_id: u8,
+    /// Indexed by kyoku.
+    invisibles: Vec<Invisible>,
+    opponent_states: [PlayerState; 3],
+    kyoku_idx: usize,
+    from_rinshan: bool,
+    yama_idx: usize,
+    rinshan_idx: usize,
+}
+
+#[pymethods]
+impl OracleState {
+    #[new]
+    #[args(player_id, log, "*", trust_seed = "false")]
+    fn new_py(player_id: u8, log: &str, trust_seed: bool) -> Result<Self> {
+        Self::new(player_id, &parse_log(log)?, trust_seed)
+    }
+
+    #[pyo3(name = "update")]
+    #[pyo3(text_signature = "($self, mjai_json, /)")]
+    fn update_json(&mut self, mjai_json: &str) -> Result<()> {
+        for event in Event::parse_line(mjai_json)? {
+            self.update(&event)?;
+        }
+        Ok(())
+    }
+
+    #[pyo3(name = "encode")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn encode_py<'py>(&self, py: Python<'py>) -> Result<&'py PyArray2<f32>> {
+        Ok(PyArray2::from_owned_array(py, self.encode()?))
+    }
+}
+
+impl OracleState {
+    /// Panics if `player_id` is outside of range [0, 3].
+    ///
+    /// `trust_seed` works the same way as in `GameplayLoader`.
+    pub fn new(player_id: u8, log: &[Event], trust_seed: bool) -> Result<Self> {
+        assert!(player_id < 4, "{player_id} is not in range [0, 3]");
+        for event in log {
+            ensure_unmasked(event)?;
+        }
+        let invisibles = Invisible::new(log, trust_seed)?;
+        let kyokus = log
+            .iter()
+            .filter(|ev| matches!(ev, Event::StartKyoku { .. }))
+            .count();
+        ensure!(
+            invisibles.len() == kyokus,
+            "{} of {kyokus} kyokus are ended with end_kyoku in the log",
+            invisibles.len(),
+        );
+
+        Ok(Self {
+            player_id,
+            invisibles,
+            opponent_states: [1, 2, 3].map(|rel| PlayerState::new((player_id + rel) % 4)),
+            kyoku_idx: 0,
+            from_rinshan: false,
+            yama_idx: 0,
+            rinshan_idx: 0,
+        })
+    }
+
+    /// `event` must be the next one of the log given to `new`.
+    pub fn update(&mut self, event: &Event) -> Result<()> {
+        ensure_unmasked(event)?;
+
+        match event {
+            Event::EndKyoku => {
+                self.kyoku_idx += 1;
+                self.from_rinshan = false;
+                self.yama_idx = 0;
+                self.rinshan_idx = 0;
+            }
+            Event::Tsumo { .. } => {
+                if self.from_rinshan {
+                    self.rinshan_idx += 1;
+                    self.from_rinshan = false;
+                } else {
+                    self.yama_idx += 1;
+                }
+            }
+            Event::Ankan { .. } | Event::Kakan { .. } | Event::Daiminkan { .. } => {
+                self.from_rinshan = true;
+            }
+            _ => (),
+        }
+        for state in &mut self.opponent_states {
+            state.update(event);
+        }
+        Ok(())
+    }
+
+    /// Returns the oracle obs of shape `ORACLE_OBS_SHAPE`, see
+    /// `oracle_obs_plane_names` for the layout.
+    ///
+    /// It can only be encoded in a kyoku after its first `tsumo`.
+    pub fn encode(&self) -> Result<Array2<f32>> {
+        ensure!(
+            self.kyoku_idx < self.invisibles.len() && self.yama_idx > 0,
+            "nothing to encode before the first tsumo of a kyoku",
+        );
+        Ok(self.invisibles[self.kyoku_idx].encode(
+            &self.opponent_states,
+            self.yama_idx,
+            self.rinshan_idx,
+        ))
+    }
+}
+
+fn ensure_unmasked(event: &Event) -> Result<()> {
+    let masked = match event {
+        Event::StartKyoku { tehais, .. } => tehais.iter().flatten().any(|&t| t == t!(?)),
+        Event::Tsumo { pai, .. } => *pai == t!(?),
+        _ => false,
+    };
+    ensure!(
+        !masked,
+        "{event:?} has masked tiles, which is not from a perfect-information log",
+    );
+    Ok(())
+}
@@ -1,13 +1,18 @@
 use super::action::ActionCandidate;
-use super::item::{ChiPon, KawaItem};
+use super::item::{ChiPon, FuritenKind, KawaItem, KawaItemPy};
+use super::rule::{GameMode, Rules};
 use crate::hand::tiles_to_string;
-use crate::must_tile;
+use crate::mjai::{Event, EventPy};
+use crate::names::Lang;
 use crate::tile::Tile;
+use crate::{must_tile, tu8};
 use std::iter;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use derivative::Derivative;
+use numpy::PyArray1;
 use pyo3::prelude::*;
+use serde::Serialize;
 use serde_json as json;
 use tinyvec::ArrayVec;
 
@@ -24,6 +29,9 @@ use tinyvec::ArrayVec;
 pub struct PlayerState {
     #[pyo3(get)]
     pub(super) player_id: u8,
+    pub(super) rules: Rules,
+    /// Only affects `brief_info`, and is not part of `to_bytes`.
+    pub(super) lang: Lang,
 
     /// Does not include aka.
     #[derivative(Default(value = "[0; 34]"))]
@@ -40,6 +48,12 @@ pub struct PlayerState {
     /// For calculating `waits` and `doras_seen`.
     #[derivative(Default(value = "[0; 34]"))]
     pub(super) tiles_seen: [u8; 34],
+    /// `tiles_seen` as of the end of the last decision point of the player,
+    /// for `tiles_seen_since_last_decision`.
+    #[derivative(Default(value = "[0; 34]"))]
+    pub(super) tiles_seen_at_last_decision: [u8; 34],
+    /// The number of red 5m, 5p and 5s witnessed, for `unseen_count`.
+    pub(super) akas_seen: [u8; 3],
 
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) keep_shanten_discards: [bool; 34],
@@ -47,6 +61,8 @@ pub struct PlayerState {
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) next_shanten_discards: [bool; 34],
 
+    /// Tiles forbidden to discard due to 喰い替え, only valid for the discard
+    /// right after a chi or pon.
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) forbidden_tiles: [bool; 34],
 
@@ -56,7 +72,7 @@ pub struct PlayerState {
 
     pub(super) bakaze: Tile,
     pub(super) jikaze: Tile,
-    /// Counts from 1, same as mjai.
+    /// Counts from 0, unlike mjai.
     pub(super) kyoku: u8,
     pub(super) honba: u8,
     pub(super) kyotaku: u8,
@@ -67,25 +83,53 @@ pub struct PlayerState {
     pub(super) oya: u8,
     /// Including 西入 sudden deatch.
     pub(super) is_all_last: bool,
+    /// Set by `ryukyoku` and cleared by `hora`, so it still refers to the
+    /// previous kyoku after a `start_kyoku`.
+    pub(super) last_kyoku_ended_in_draw: bool,
+    /// The number of `hora` in the kyoku so far, more than one only on a
+    /// multi-ron, see `MultiRonRule`.
+    pub(super) horas: u8,
     pub(super) dora_indicators: ArrayVec<[Tile; 5]>,
 
-    /// 24 is the theoretical max size of kawa.
+    /// The capacity used to be 24, the theoretical max size of kawa given by
+    /// <https://detail.chiebukuro.yahoo.co.jp/qa/question_detail/q1020002370>,
+    /// but the turns skipped by calls are padded with `None` here, and calls
+    /// make the turns come around faster, so a legal kyoku can exceed it, see
+    /// the test `kawa_capacity`.
     ///
-    /// Reference: <https://detail.chiebukuro.yahoo.co.jp/qa/question_detail/q1020002370>
-    pub(super) kawa: [ArrayVec<[Option<KawaItem>; 24]>; 4],
+    /// Every item is the turn passing the seat. Each of the 70 draws moves the
+    /// turn by one seat, each of the at most 16 calls by at most three, and at
+    /// most three seats are skipped at the start of the kyoku, so there can
+    /// never be more than ceil(121 / 4) = 31 items.
+    pub(super) kawa: [ArrayVec<[Option<KawaItem>; 32]>; 4],
 
     /// Using 34-D arrays here may be more efficient, but I don't want to mess up
     /// with aka doras.
-    pub(super) kawa_overview: [ArrayVec<[Tile; 24]>; 4],
+    pub(super) kawa_overview: [ArrayVec<[Tile; 32]>; 4],
     pub(super) fuuro_overview: [ArrayVec<[ArrayVec<[Tile; 4]>; 4]>; 4],
     /// In this field all `Tile` are deaka'd.
     pub(super) ankan_overview: [ArrayVec<[Tile; 4]>; 4],
+    /// The player liable (包) for each player's daisangen or daisuushii, set
+    /// on the pon or daiminkan of the third dragon or the fourth wind. Both
+    /// the index and the value are relative seats.
+    pub(super) paos: [Option<u8>; 4],
+    /// The relative seat that fed the player's daiminkan, kept until the
+    /// player discards or makes another kan, for the liability of a rinshan
+    /// kaihou under `DaiminkanPaoRule::RinshanKaihou`.
+    pub(super) rinshan_pao: Option<u8>,
 
     pub(super) riichi_declared: [bool; 4],
     pub(super) riichi_accepted: [bool; 4],
+    /// Tiles discarded by anyone after each player's riichi is accepted,
+    /// which are safe against that player due to riichi furiten.
+    #[derivative(Default(value = "[[false; 34]; 4]"))]
+    pub(super) passed_after_riichi: [[bool; 34]; 4],
 
     pub(super) at_turn: u8,
     pub(super) tiles_left: u8,
+    /// The relative seat to draw next if nobody calls, which is also the
+    /// actor itself after a kan or nukidora, for the rinshan draw.
+    pub(super) next_draw: u8,
     pub(super) intermediate_kan: ArrayVec<[Tile; 4]>,
     pub(super) intermediate_chi_pon: Option<ChiPon>,
 
@@ -95,7 +139,9 @@ pub struct PlayerState {
     pub(super) last_kawa_tile: Option<Tile>,
     pub(super) last_cans: ActionCandidate,
 
-    /// Both deaka'd
+    /// Both deaka'd, in ascending order of tile id. A hand after a tsumo
+    /// has at most 3 kans to declare, ankans and kakans combined, as every
+    /// pon that makes a kakan possible also takes 3 tiles away from tehai.
     pub(super) ankan_candidates: ArrayVec<[Tile; 3]>,
     pub(super) kakan_candidates: ArrayVec<[Tile; 3]>,
     pub(super) chankan_chance: Option<()>,
@@ -105,13 +151,20 @@ pub struct PlayerState {
     pub(super) at_rinshan: bool,
     pub(super) at_ippatsu: bool,
     pub(super) at_furiten: bool,
+    /// Why `at_furiten` is set, `FuritenKind::None` if it is not.
+    pub(super) furiten_kind: FuritenKind,
     pub(super) to_mark_same_cycle_furiten: Option<()>,
 
     /// Used for 4-kan check.
     pub(super) kans_on_board: u8,
 
+    /// No chi, pon or daiminkan has been made, while ankan does not count.
+    #[pyo3(get)]
     pub(super) is_menzen: bool,
-    /// For agari calc, all deaka'd.
+    /// The number of the player's own discards before their first chi, pon
+    /// or daiminkan in this kyoku, the same as the turn counted by `Stat`.
+    pub(super) first_call_junme: Option<u8>,
+    /// For agari calc, all deaka'd and kept in ascending order of tile id.
     pub(super) chis: ArrayVec<[u8; 4]>,
     pub(super) pons: ArrayVec<[u8; 4]>,
     pub(super) minkans: ArrayVec<[u8; 4]>,
@@ -121,14 +174,22 @@ pub struct PlayerState {
     /// feature to the obs.
     pub(super) doras_owned: [u8; 4],
     pub(super) doras_seen: u8,
+    /// The number of north tiles extracted by nukidora, which are also
+    /// counted in `doras_owned`.
+    pub(super) nukidoras: [u8; 4],
 
-    pub(super) akas_in_hand: [bool; 3],
+    /// The number of red 5m, 5p and 5s in tehai.
+    pub(super) akas_in_hand: [u8; 3],
 
     /// For shanten calc.
     pub(super) tehai_len_div3: u8,
 
     /// Used in can_riichi.
     pub(super) has_next_shanten_discard: bool,
+
+    /// The latest event passed to `update`, for mirroring the consumed
+    /// stream. `to_bytes` keeps it as its mjai line.
+    pub(super) last_event: Option<Event>,
 }
 
 #[pymethods]
@@ -137,19 +198,50 @@ impl PlayerState {
     #[new]
     #[must_use]
     pub fn new(player_id: u8) -> Self {
-        assert!(player_id < 4, "{player_id} is not in range [0, 3]");
-        Self {
-            player_id,
+        Self::with_rules(player_id, Rules::default())
+    }
+
+    /// A state of sanma with otherwise default rules, see `GameMode::Sanma`.
+    ///
+    /// Panics if `player_id` is outside of range [0, 2].
+    #[staticmethod]
+    #[must_use]
+    #[pyo3(text_signature = "(player_id, /)")]
+    pub fn new_sanma(player_id: u8) -> Self {
+        let rules = Rules {
+            mode: GameMode::Sanma,
             ..Default::default()
-        }
+        };
+        Self::with_rules(player_id, rules)
     }
 
     /// Returns an `ActionCandidate`.
+    ///
+    /// A `start_kyoku` with the oya's first tsumo included in its 14-tile
+    /// `tehais` is also accepted, see `Event::parse_line`.
+    ///
+    /// Raises an exception if the event has a red five while the rule says
+    /// there is none.
     #[pyo3(name = "update")]
     #[pyo3(text_signature = "($self, mjai_json, /)")]
     pub(super) fn update_json(&mut self, mjai_json: &str) -> Result<ActionCandidate> {
-        let event = json::from_str(mjai_json)?;
-        Ok(self.update(&event))
+        let mut cans = self.last_cans;
+        for event in Event::parse_line(mjai_json)? {
+            self.ensure_aka_allowed(&event)?;
+            self.ensure_capacity(&event)?;
+            cans = self.update(&event);
+        }
+        Ok(cans)
+    }
+
+    /// Same as `update`, but takes an `Event` from `mjai.Event.from_dict`
+    /// instead of a JSON line.
+    #[pyo3(name = "update_event")]
+    #[pyo3(text_signature = "($self, event, /)")]
+    fn update_event_py(&mut self, event: PyRef<'_, EventPy>) -> Result<ActionCandidate> {
+        self.ensure_aka_allowed(&event.event)?;
+        self.ensure_capacity(&event.event)?;
+        Ok(self.update(&event.event))
     }
 
     /// Raises an exception if the action is not valid.
@@ -160,19 +252,285 @@ impl PlayerState {
         self.validate_reaction(&action)
     }
 
+    /// Returns the mjai JSON of every legal reaction, see `legal_reactions`.
+    /// A pass is `{"type":"none"}`.
+    #[pyo3(name = "legal_reactions")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn legal_reactions_py(&self) -> Vec<String> {
+        self.legal_reactions()
+            .into_iter()
+            .map(|r| self.reaction_to_event(r).to_line())
+            .collect()
+    }
+
+    /// Returns a bool array of `action_space`, see `legal_action_mask`.
+    #[pyo3(name = "legal_action_mask")]
+    #[pyo3(text_signature = "($self, at_kan_choice, /)")]
+    fn legal_action_mask_py<'py>(
+        &self,
+        at_kan_choice: bool,
+        py: Python<'py>,
+    ) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.legal_action_mask(at_kan_choice))
+    }
+
+    /// Returns a bool array of 37, see `riichi_forbidden_discards`.
+    #[pyo3(name = "riichi_forbidden_discards")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn riichi_forbidden_discards_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.riichi_forbidden_discards())
+    }
+
+    /// Returns a bool array of 34, see `ankan_tiles`.
+    #[pyo3(name = "ankan_tiles")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn ankan_tiles_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.ankan_tiles())
+    }
+
+    /// Returns a bool array of 34, see `kakan_tiles`.
+    #[pyo3(name = "kakan_tiles")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn kakan_tiles_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.kakan_tiles())
+    }
+
+    /// Returns the mjai JSON of `fallback_reaction`.
+    #[pyo3(name = "fallback_reaction")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn fallback_reaction_py(&self) -> String {
+        self.reaction_to_event(self.fallback_reaction()).to_line()
+    }
+
+    /// Returns the mjai JSON of the event, see `action_to_event`.
+    #[pyo3(name = "action_to_event")]
+    #[pyo3(text_signature = "($self, action, at_kan_choice, *, prefer_aka=True)")]
+    #[args(action, at_kan_choice, "*", prefer_aka = "true")]
+    fn action_to_event_py(
+        &self,
+        action: usize,
+        at_kan_choice: bool,
+        prefer_aka: bool,
+    ) -> Result<String> {
+        Ok(self
+            .action_to_event(action, at_kan_choice, prefer_aka)?
+            .to_line())
+    }
+
+    /// Returns the wind of each absolute seat in the current kyoku, for
+    /// example `["S", "W", "N", "E"]` when seat 3 is the oya.
+    #[pyo3(name = "seat_winds")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn seat_winds_py(&self) -> Vec<String> {
+        self.seat_winds().iter().map(ToString::to_string).collect()
+    }
+
+    #[pyo3(name = "is_oya")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn is_oya_py(&self) -> bool {
+        self.is_oya()
+    }
+
+    /// Whether the player's own riichi has been accepted.
+    #[getter]
+    #[pyo3(name = "riichi_accepted")]
+    fn riichi_accepted_py(&self) -> bool {
+        self.self_riichi_accepted()
+    }
+
+    #[getter]
+    #[pyo3(name = "shanten")]
+    fn shanten_py(&self) -> i8 {
+        self.shanten()
+    }
+
+    #[getter]
+    #[pyo3(name = "at_furiten")]
+    fn at_furiten_py(&self) -> bool {
+        self.at_furiten()
+    }
+
+    #[getter]
+    #[pyo3(name = "pao_liable_seat")]
+    fn pao_liable_seat_py(&self) -> Option<u8> {
+        self.pao_liable_seat()
+    }
+
+    /// A bool array of 34, copied on every access.
+    #[getter]
+    #[pyo3(name = "waits")]
+    fn waits_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<bool> {
+        PyArray1::from_slice(py, &self.waits)
+    }
+
+    /// A u8 array of 34, copied on every access.
+    #[getter]
+    #[pyo3(name = "tiles_seen")]
+    fn tiles_seen_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<u8> {
+        PyArray1::from_slice(py, &self.tiles_seen)
+    }
+
+    /// A u8 array of 34, copied on every access.
+    #[getter]
+    #[pyo3(name = "tiles_seen_since_last_decision")]
+    fn tiles_seen_since_last_decision_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<u8> {
+        PyArray1::from_slice(py, &self.tiles_seen_since_last_decision())
+    }
+
+    #[getter]
+    #[pyo3(name = "waits_seen_since_last_decision")]
+    fn waits_seen_since_last_decision_py(&self) -> u8 {
+        self.waits_seen_since_last_decision()
+    }
+
+    /// A u8 array of 34 with the akas counted as their normal fives, copied
+    /// on every access. See `akas_in_hand` for the akas.
+    #[getter]
+    #[pyo3(name = "tehai")]
+    fn tehai_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<u8> {
+        PyArray1::from_slice(py, &self.tehai)
+    }
+
+    /// Whether each of 5mr, 5pr and 5sr is in hand.
+    #[getter]
+    #[pyo3(name = "akas_in_hand")]
+    fn akas_in_hand_py(&self) -> [bool; 3] {
+        self.akas_in_hand()
+    }
+
+    /// In relative seats.
+    #[getter]
+    #[pyo3(name = "scores")]
+    fn scores_py(&self) -> [i32; 4] {
+        self.scores()
+    }
+
+    /// Counted from 0 in the current bakaze.
+    #[getter]
+    #[pyo3(name = "kyoku")]
+    fn kyoku_py(&self) -> u8 {
+        self.kyoku()
+    }
+
+    #[getter]
+    #[pyo3(name = "honba")]
+    fn honba_py(&self) -> u8 {
+        self.honba()
+    }
+
+    #[getter]
+    #[pyo3(name = "kyotaku")]
+    fn kyotaku_py(&self) -> u8 {
+        self.kyotaku()
+    }
+
+    #[getter]
+    #[pyo3(name = "tiles_left")]
+    fn tiles_left_py(&self) -> u8 {
+        self.tiles_left()
+    }
+
+    #[getter]
+    #[pyo3(name = "doras_seen")]
+    fn doras_seen_py(&self) -> u8 {
+        self.doras_seen()
+    }
+
+    #[pyo3(name = "seats_until_my_turn")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn seats_until_my_turn_py(&self) -> u8 {
+        self.seats_until_my_turn()
+    }
+
+    #[pyo3(name = "draws_before_my_next_draw")]
+    #[pyo3(text_signature = "($self, rel_seat, /)")]
+    fn draws_before_my_next_draw_py(&self, rel_seat: u8) -> u8 {
+        self.draws_before_my_next_draw(rel_seat)
+    }
+
+    #[pyo3(name = "discard_precedes_riichi_draw")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn discard_precedes_riichi_draw_py(&self) -> [bool; 4] {
+        self.discard_precedes_riichi_draw()
+    }
+
+    /// One of `none`, `temporary`, `permanent` and `riichi`, see
+    /// `FuritenKind`.
+    #[pyo3(name = "furiten_kind")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn furiten_kind_py(&self) -> &'static str {
+        self.furiten_kind.as_str()
+    }
+
+    #[pyo3(name = "last_kyoku_ended_in_draw")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn last_kyoku_ended_in_draw_py(&self) -> bool {
+        self.last_kyoku_ended_in_draw()
+    }
+
+    /// Returns the doras, not the indicators, in mjai notation.
+    #[pyo3(name = "dora_tiles")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn dora_tiles_py(&self) -> Vec<String> {
+        self.dora_tiles().iter().map(ToString::to_string).collect()
+    }
+
+    /// The tile drawn in the current turn in mjai notation, see
+    /// `last_self_tsumo`.
+    #[getter]
+    #[pyo3(name = "last_self_tsumo")]
+    fn last_self_tsumo_py(&self) -> Option<String> {
+        self.last_self_tsumo().map(|t| t.to_string())
+    }
+
+    /// The latest discard in mjai notation, see `last_kawa_tile`.
+    #[getter]
+    #[pyo3(name = "last_kawa_tile")]
+    fn last_kawa_tile_py(&self) -> Option<String> {
+        self.last_kawa_tile().map(|t| t.to_string())
+    }
+
+    #[pyo3(name = "first_call_junme")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn first_call_junme_py(&self) -> Option<u8> {
+        self.first_call_junme()
+    }
+
+    #[pyo3(name = "call_count")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn call_count_py(&self) -> u8 {
+        self.call_count()
+    }
+
+    #[pyo3(name = "last_event_json")]
+    #[pyo3(text_signature = "($self, /)")]
+    fn last_event_json_py(&self) -> Option<String> {
+        self.last_event_json()
+    }
+
+    /// Returns the discards of `rel_seat` as a list of `KawaItemPy`.
+    #[pyo3(name = "kawa")]
+    #[pyo3(text_signature = "($self, rel_seat, /)")]
+    fn kawa_py(&self, rel_seat: u8) -> Vec<KawaItemPy> {
+        self.kawa(rel_seat)
+    }
+
+    /// Sets the language of tile names in `brief_info`, one of `en`, `ja`
+    /// and `romaji`.
+    #[pyo3(name = "set_lang")]
+    #[pyo3(text_signature = "($self, lang, /)")]
+    fn set_lang_py(&mut self, lang: &str) -> Result<()> {
+        self.set_lang(lang.parse()?);
+        Ok(())
+    }
+
     /// For debug only.
     ///
     /// Return a human readable description of the current state.
     #[pyo3(text_signature = "($self, /)")]
     #[must_use]
     pub fn brief_info(&self) -> String {
-        let waits = self
-            .waits
-            .iter()
-            .enumerate()
-            .filter(|(_, &b)| b)
-            .map(|(i, _)| must_tile!(i))
-            .collect::<Vec<_>>();
+        let waits = self.wait_tiles();
 
         let zipped_kawa = self.kawa[0]
             .iter()
@@ -198,21 +556,43 @@ impl PlayerState {
             .collect::<Vec<_>>()
             .join("\n");
 
+        let (bakaze, jikaze, tehai, dora_indicators) = if self.lang == Lang::En {
+            (
+                self.bakaze.to_string(),
+                self.jikaze.to_string(),
+                tiles_to_string(&self.tehai, self.akas_in_hand()),
+                format!("{:?}", self.dora_indicators),
+            )
+        } else {
+            (
+                self.bakaze.name(self.lang).to_owned(),
+                self.jikaze.name(self.lang).to_owned(),
+                self.tehai_names(),
+                format!(
+                    "{:?}",
+                    self.dora_indicators
+                        .iter()
+                        .map(|t| t.name(self.lang))
+                        .collect::<Vec<_>>(),
+                ),
+            )
+        };
+
         format!(
             r#"player (abs): {}
 oya (rel): {}
-kyoku: {}{}-{}
+kyoku: {bakaze}{}-{}
 turn: {}
-jikaze: {}
+jikaze: {jikaze}
 score (rel): {:?}
-tehai: {}
+tehai: {tehai}
 fuuro: {:?}
 ankan: {:?}
 tehai len: {}
 shanten: {}
 furiten: {}
 waits: {waits:?}
-dora indicators: {:?}
+dora indicators: {dora_indicators}
 doras owned: {:?}
 doras seen: {}
 action candidates: {:#?}
@@ -223,19 +603,15 @@ kawa:
 {zipped_kawa}"#,
             self.player_id,
             self.oya,
-            self.bakaze,
             self.kyoku + 1,
             self.honba,
             self.at_turn,
-            self.jikaze,
             self.scores,
-            tiles_to_string(&self.tehai, self.akas_in_hand),
             self.fuuro_overview[0],
             self.ankan_overview[0],
             self.tehai_len_div3,
             self.shanten,
             self.at_furiten,
-            self.dora_indicators,
             self.doras_owned,
             self.doras_seen,
             self.last_cans,
@@ -244,4 +620,283 @@ kawa:
             self.tiles_left,
         )
     }
+
+    /// For debug only.
+    ///
+    /// Returns the same information as `brief_info` in a `dict`, see
+    /// `dump_json`.
+    #[pyo3(text_signature = "($self, /)")]
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let dump = self.dump_json();
+        Ok(py.import("json")?.call_method1("loads", (dump,))?.into())
+    }
+}
+
+/// The schema of `PlayerState::dump_json`. Seats other than `player_id` are
+/// relative, and tiles are written in mjai notation.
+#[derive(Serialize)]
+struct StateDump<'a> {
+    /// Bumped on every incompatible change to the schema.
+    version: u32,
+    player_id: u8,
+    oya: u8,
+    bakaze: Tile,
+    jikaze: Tile,
+    /// 0-indexed.
+    kyoku: u8,
+    honba: u8,
+    kyotaku: u8,
+    scores: [i32; 4],
+    at_turn: u8,
+    tiles_left: u8,
+    tehai: Vec<Tile>,
+    fuuro: &'a [ArrayVec<[Tile; 4]>],
+    ankan: &'a [Tile],
+    shanten: i8,
+    furiten: bool,
+    waits: Vec<Tile>,
+    dora_indicators: &'a [Tile],
+    doras_owned: [u8; 4],
+    last_cans: ActionCandidate,
+    last_self_tsumo: Option<Tile>,
+    last_kawa_tile: Option<Tile>,
+    /// `null` for the gaps left by calls, so that the rows line up by turn
+    /// as in `brief_info`.
+    kawa: &'a [ArrayVec<[Option<KawaItem>; 32]>; 4],
+}
+
+impl PlayerState {
+    pub const DUMP_VERSION: u32 = 1;
+
+    /// Returns the information of `brief_info` in JSON for tooling, see
+    /// `StateDump` for the schema.
+    #[must_use]
+    pub fn dump_json(&self) -> String {
+        let dump = StateDump {
+            version: Self::DUMP_VERSION,
+            player_id: self.player_id,
+            oya: self.oya,
+            bakaze: self.bakaze,
+            jikaze: self.jikaze,
+            kyoku: self.kyoku,
+            honba: self.honba,
+            kyotaku: self.kyotaku,
+            scores: self.scores,
+            at_turn: self.at_turn,
+            tiles_left: self.tiles_left,
+            tehai: self.tehai_tiles(),
+            fuuro: &self.fuuro_overview[0],
+            ankan: &self.ankan_overview[0],
+            shanten: self.shanten,
+            furiten: self.at_furiten,
+            waits: self.wait_tiles(),
+            dora_indicators: &self.dora_indicators,
+            doras_owned: self.doras_owned,
+            last_cans: self.last_cans,
+            last_self_tsumo: self.last_self_tsumo,
+            last_kawa_tile: self.last_kawa_tile,
+            kawa: &self.kawa,
+        };
+        json::to_string(&dump).expect("failed to serialize the state")
+    }
+
+    /// Returns every field kept by `to_bytes` as a JSON object keyed by the
+    /// field name, which leaves out `lang`. It is far larger than `to_bytes`
+    /// and only meant for debugging, see also `diff`.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        json::Value::Object(self.json_fields()).to_string()
+    }
+
+    /// Lists the fields of `to_json` that differ between the two states, as
+    /// `field: self != other`, empty if the states are identical.
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> Vec<String> {
+        let theirs = other.json_fields();
+        self.json_fields()
+            .into_iter()
+            .filter(|(k, v)| theirs[k] != *v)
+            .map(|(k, v)| format!("{k}: {v} != {}", theirs[&k]))
+            .collect()
+    }
+
+    fn json_fields(&self) -> json::Map<String, json::Value> {
+        macro_rules! fields {
+            ($($key:ident: $value:expr),* $(,)?) => {{
+                let mut map = json::Map::new();
+                $(
+                    let value = json::to_value($value).expect("failed to serialize the state");
+                    map.insert(stringify!($key).to_owned(), value);
+                )*
+                map
+            }};
+        }
+        let passed_after_riichi: Vec<_> = self.passed_after_riichi.iter().map(|p| &p[..]).collect();
+        fields! {
+            player_id: self.player_id,
+            rules: format!("{:?}", self.rules),
+            tehai: &self.tehai[..],
+            waits: &self.waits[..],
+            dora_factor: &self.dora_factor[..],
+            tiles_seen: &self.tiles_seen[..],
+            tiles_seen_at_last_decision: &self.tiles_seen_at_last_decision[..],
+            akas_seen: self.akas_seen,
+            keep_shanten_discards: &self.keep_shanten_discards[..],
+            next_shanten_discards: &self.next_shanten_discards[..],
+            forbidden_tiles: &self.forbidden_tiles[..],
+            discarded_tiles: &self.discarded_tiles[..],
+            bakaze: self.bakaze,
+            jikaze: self.jikaze,
+            kyoku: self.kyoku,
+            honba: self.honba,
+            kyotaku: self.kyotaku,
+            scores: self.scores,
+            rank: self.rank,
+            oya: self.oya,
+            is_all_last: self.is_all_last,
+            last_kyoku_ended_in_draw: self.last_kyoku_ended_in_draw,
+            horas: self.horas,
+            dora_indicators: &self.dora_indicators,
+            kawa: &self.kawa,
+            kawa_overview: &self.kawa_overview,
+            fuuro_overview: &self.fuuro_overview,
+            ankan_overview: &self.ankan_overview,
+            paos: self.paos,
+            rinshan_pao: self.rinshan_pao,
+            riichi_declared: self.riichi_declared,
+            riichi_accepted: self.riichi_accepted,
+            passed_after_riichi: passed_after_riichi,
+            at_turn: self.at_turn,
+            tiles_left: self.tiles_left,
+            next_draw: self.next_draw,
+            intermediate_kan: &self.intermediate_kan,
+            intermediate_chi_pon: &self.intermediate_chi_pon,
+            shanten: self.shanten,
+            last_self_tsumo: self.last_self_tsumo,
+            last_kawa_tile: self.last_kawa_tile,
+            last_cans: self.last_cans,
+            ankan_candidates: &self.ankan_candidates,
+            kakan_candidates: &self.kakan_candidates,
+            chankan_chance: self.chankan_chance.is_some(),
+            can_w_riichi: self.can_w_riichi,
+            is_w_riichi: self.is_w_riichi,
+            at_rinshan: self.at_rinshan,
+            at_ippatsu: self.at_ippatsu,
+            at_furiten: self.at_furiten,
+            furiten_kind: format!("{:?}", self.furiten_kind),
+            to_mark_same_cycle_furiten: self.to_mark_same_cycle_furiten.is_some(),
+            kans_on_board: self.kans_on_board,
+            is_menzen: self.is_menzen,
+            first_call_junme: self.first_call_junme,
+            chis: &self.chis,
+            pons: &self.pons,
+            minkans: &self.minkans,
+            ankans: &self.ankans,
+            doras_owned: self.doras_owned,
+            doras_seen: self.doras_seen,
+            nukidoras: self.nukidoras,
+            akas_in_hand: self.akas_in_hand,
+            tehai_len_div3: self.tehai_len_div3,
+            has_next_shanten_discard: self.has_next_shanten_discard,
+            last_event: self.last_event_json(),
+        }
+    }
+
+    /// Serializes the latest event passed to `update` back into an mjai
+    /// line, `None` before any.
+    #[must_use]
+    pub fn last_event_json(&self) -> Option<String> {
+        self.last_event.as_ref().map(Event::to_line)
+    }
+
+    /// Panics if `player_id` is not a seat of `rules.mode`, that is outside of
+    /// range [0, 3] for yonma or [0, 2] for sanma.
+    #[must_use]
+    pub fn with_rules(player_id: u8, rules: Rules) -> Self {
+        let num_players = rules.mode.num_players();
+        assert!(
+            player_id < num_players,
+            "{player_id} is not in range [0, {}]",
+            num_players - 1,
+        );
+        Self {
+            player_id,
+            rules,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn set_lang(&mut self, lang: Lang) {
+        self.lang = lang;
+    }
+
+    /// Every tile in tehai in ascending order of tile id, each aka placed
+    /// before the plain ones of the same kind.
+    fn tehai_tiles(&self) -> Vec<Tile> {
+        let mut tiles = vec![];
+        for (tid, &count) in self.tehai.iter().enumerate() {
+            let tile = must_tile!(tid);
+            let akas = match tile.as_u8() {
+                tu8!(5m) => self.akas_in_hand[0],
+                tu8!(5p) => self.akas_in_hand[1],
+                tu8!(5s) => self.akas_in_hand[2],
+                _ => 0,
+            };
+            tiles.extend(iter::repeat(tile.akaize()).take(akas as usize));
+            tiles.extend(iter::repeat(tile).take((count - akas) as usize));
+        }
+        tiles
+    }
+
+    fn tehai_names(&self) -> String {
+        self.tehai_tiles()
+            .iter()
+            .map(|t| t.name(self.lang))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn wait_tiles(&self) -> Vec<Tile> {
+        self.waits
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b)
+            .map(|(i, _)| must_tile!(i))
+            .collect()
+    }
+
+    /// Errs if the event has a red five the rules do not have, which is any
+    /// of them under `AkaRule::None`, or 5mr in sanma.
+    pub(super) fn ensure_aka_allowed(&self, event: &Event) -> Result<()> {
+        let bad = |t: &Tile| t.is_aka() && self.aka_count_of(t.deaka()) == 0;
+        let has_bad_aka = match event {
+            Event::StartKyoku {
+                dora_marker,
+                tehais,
+                ..
+            } => bad(dora_marker) || tehais.iter().flatten().any(bad),
+            Event::Tsumo { pai, .. } | Event::Dahai { pai, .. } => bad(pai),
+            Event::Chi { pai, consumed, .. } | Event::Pon { pai, consumed, .. } => {
+                bad(pai) || consumed.iter().any(bad)
+            }
+            Event::Daiminkan { pai, consumed, .. } | Event::Kakan { pai, consumed, .. } => {
+                bad(pai) || consumed.iter().any(bad)
+            }
+            Event::Ankan { consumed, .. } => consumed.iter().any(bad),
+            Event::Dora { dora_marker } => bad(dora_marker),
+            Event::Hora {
+                ura_markers: Some(ura_markers),
+                ..
+            } => ura_markers.iter().any(bad),
+            _ => false,
+        };
+        ensure!(
+            !has_bad_aka,
+            "unexpected red five under {:?} in {:?}: {event:?}",
+            self.rules.aka,
+            self.rules.mode,
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,117 @@
+use super::{ActionCandidate, PlayerState, Rules};
+use crate::mjai::Event;
+
+use anyhow::{Context, Result};
+use pyo3::prelude::*;
+use serde_json as json;
+
+impl PlayerState {
+    /// Replays `events` from a fresh state of `player_id`, returning the final
+    /// state and the candidates of the last event.
+    ///
+    /// Every action made by `player_id` is checked against the state before it
+    /// is applied, in the same way as `validate_reaction`. On failure the
+    /// error names the 1-based index of the offending event, along with the
+    /// event itself and the state at that moment.
+    pub fn from_events<'a>(
+        player_id: u8,
+        events: impl IntoIterator<Item = &'a Event>,
+    ) -> Result<(Self, ActionCandidate)> {
+        Self::from_events_with_rules(player_id, Rules::default(), events)
+    }
+
+    /// Same as `from_events`, but with a fresh state of `with_rules`.
+    pub fn from_events_with_rules<'a>(
+        player_id: u8,
+        rules: Rules,
+        events: impl IntoIterator<Item = &'a Event>,
+    ) -> Result<(Self, ActionCandidate)> {
+        let events = events.into_iter().enumerate().map(|(i, ev)| (i + 1, ev));
+        Self::replay(Self::with_rules(player_id, rules), events)
+    }
+
+    /// Same as `from_log_str`, but with a fresh state of `with_rules`.
+    pub fn from_log_str_with_rules(
+        player_id: u8,
+        rules: Rules,
+        log: &str,
+    ) -> Result<(Self, ActionCandidate)> {
+        let mut events = vec![];
+        for (idx, l) in log.lines().enumerate() {
+            let line = idx + 1;
+            if l.trim().is_empty() {
+                continue;
+            }
+            let parsed =
+                Event::parse_line(l).with_context(|| format!("failed to parse line {line}"))?;
+            events.extend(parsed.into_iter().map(|ev| (line, ev)));
+        }
+        let state = Self::with_rules(player_id, rules);
+        Self::replay(state, events.iter().map(|(line, ev)| (*line, ev)))
+    }
+
+    /// Replays `events` for all the four seats in one pass, giving the same
+    /// states as updating a fresh state of each seat with them in turn.
+    /// Unlike `from_events`, nothing is checked.
+    #[must_use]
+    pub fn replay_all_seats(events: &[Event]) -> [Self; 4] {
+        let mut states = [0, 1, 2, 3].map(Self::new);
+        for ev in events {
+            for state in &mut states {
+                state.update(ev);
+            }
+        }
+        states
+    }
+
+    fn replay<'a>(
+        mut state: Self,
+        events: impl Iterator<Item = (usize, &'a Event)>,
+    ) -> Result<(Self, ActionCandidate)> {
+        let mut cans = state.last_cans;
+        for (line, ev) in events {
+            state.ensure_consistent(ev).with_context(|| {
+                format!(
+                    "inconsistent event at line {line}: {}\nstate:\n{}",
+                    json::to_string(ev).unwrap_or_else(|_| format!("{ev:?}")),
+                    state.brief_info(),
+                )
+            })?;
+            cans = state.update(ev);
+        }
+        Ok((state, cans))
+    }
+
+    fn ensure_consistent(&self, event: &Event) -> Result<()> {
+        self.ensure_aka_allowed(event)?;
+        let is_own_action = event.actor() == Some(self.player_id)
+            && matches!(
+                event,
+                Event::Dahai { .. }
+                    | Event::Reach { .. }
+                    | Event::Chi { .. }
+                    | Event::Pon { .. }
+                    | Event::Daiminkan { .. }
+                    | Event::Kakan { .. }
+                    | Event::Ankan { .. }
+                    | Event::Nukidora { .. }
+                    | Event::Hora { .. }
+            );
+        if is_own_action {
+            self.validate_reaction(event)?;
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PlayerState {
+    /// Same as `from_events`, but parses `log` as newline-delimited mjai
+    /// events. Blank lines are skipped, and the line numbers in errors are
+    /// those of `log`.
+    #[staticmethod]
+    #[pyo3(text_signature = "(player_id, log, /)")]
+    pub fn from_log_str(player_id: u8, log: &str) -> Result<(Self, ActionCandidate)> {
+        Self::from_log_str_with_rules(player_id, Rules::default(), log)
+    }
+}
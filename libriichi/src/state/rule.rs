@@ -0,0 +1,218 @@
+use crate::tile::Tile;
+use crate::{t, tu8};
+
+/// Rule variations that affect the state of a player.
+///
+/// The default is Tenhou's rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rules {
+    pub kuikae: KuikaeRule,
+    pub kuitan: KuitanRule,
+    pub aka: AkaRule,
+    pub blessing: BlessingRule,
+    pub kazoe: KazoeRule,
+    pub yakuman: YakumanRule,
+    pub multi_ron: MultiRonRule,
+    pub triple_ron: TripleRonRule,
+    pub daiminkan_pao: DaiminkanPaoRule,
+    pub kan_dora: KanDoraRule,
+    pub mode: GameMode,
+}
+
+/// Which tiles are forbidden to discard right after a chi or pon (喰い替え).
+///
+/// In either case the restriction only lasts for the discard right after the
+/// call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KuikaeRule {
+    /// Forbids the called tile, and for chi, also the tile on the other end of
+    /// the sequence, for example chi 3m with 45m forbids both 3m and 6m.
+    #[default]
+    Full,
+    /// Forbids the called tile only.
+    SameTileOnly,
+}
+
+/// Whether 断幺九 counts in an open hand (喰いタン).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KuitanRule {
+    #[default]
+    Allowed,
+    /// 断幺九 only counts in a closed hand, so an open hand with no other yaku
+    /// cannot agari.
+    Forbidden,
+}
+
+/// The number of red fives (赤ドラ) in the game. In sanma there is no 5mr,
+/// see `GameMode::has_tile`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AkaRule {
+    /// No red fives. A red five in an event is an error.
+    None,
+    /// One red five for each suit.
+    #[default]
+    Three,
+    /// One red 5m, two red 5p and one red 5s.
+    FourWithDoublePin,
+}
+
+impl AkaRule {
+    /// The number of red fives in a yonma game.
+    #[must_use]
+    pub const fn count(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Three => 3,
+            Self::FourWithDoublePin => 4,
+        }
+    }
+}
+
+/// How 天和 and 地和 combine with the rest of the hand.
+///
+/// Either way they replace every non-yakuman yaku and dora, so for example a
+/// double riichi can never stack with them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlessingRule {
+    /// Always a single yakuman on its own, which is what the arena plays.
+    #[default]
+    Alone,
+    /// Adds up with the yakumans of the hand, such as 天和 with 四暗刻 for a
+    /// double yakuman, subject to `YakumanRule`.
+    StackYakuman,
+}
+
+/// How a hand of 13 han or more without yakuman is scored (数え役満).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KazoeRule {
+    /// Scored as a yakuman.
+    #[default]
+    Yakuman,
+    /// Capped at sanbaiman.
+    Sanbaiman,
+}
+
+/// How several yakumans in one hand are scored (複合役満).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YakumanRule {
+    /// Each yakuman counts, for example 大三元 with 字一色 is a double
+    /// yakuman.
+    #[default]
+    Multiple,
+    /// Scored as a single yakuman no matter how many there are.
+    Single,
+}
+
+/// Whether several players can ron the same tile (ダブロン), and who takes
+/// the honba and kyotaku if so.
+///
+/// The kyotaku always go to the first winner in turn order after the
+/// discarder (上家取り), and every winner is paid the points of its own hand.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultiRonRule {
+    /// The honba also only go to the first winner, like the kyotaku.
+    #[default]
+    HonbaToFirst,
+    /// Every winner is paid the honba by the discarder.
+    HonbaToEach,
+    /// Only the first winner wins (頭ハネ), and the other rons are void.
+    Atamahane,
+}
+
+/// What happens when three players ron the same tile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TripleRonRule {
+    /// The kyoku ends in an abortive draw (三家和了) instead, and no hora
+    /// happens.
+    #[default]
+    Abortive,
+    /// All three win, scored as a multi-ron under `MultiRonRule`.
+    Allowed,
+}
+
+/// Whether the player who fed a daiminkan is liable (包) for a 嶺上開花 on
+/// its rinshan draw (大明槓責任払い).
+///
+/// The liability for daisangen and daisuushii applies either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DaiminkanPaoRule {
+    #[default]
+    None,
+    /// The feeder pays the points of the hand as if it were a ron, plus all
+    /// the honba, like the liable player of a daisangen on tsumo.
+    RinshanKaihou,
+}
+
+/// When the dora indicator of a daiminkan or kakan is revealed (槓ドラ).
+///
+/// The one of an ankan is always revealed right away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KanDoraRule {
+    /// After the discard that follows the rinshan draw, or before the next
+    /// kan if that comes first (後乗り).
+    #[default]
+    AfterDiscard,
+    /// Right after the kan, before the rinshan draw and any chankan (即乗り).
+    Immediate,
+}
+
+/// The number of players.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// 四人麻雀.
+    #[default]
+    Yonma,
+    /// 三人麻雀, played by seats 0, 1 and 2 while seat 3 stays empty. 2m to 8m
+    /// are removed, there is no chi, north tiles are extracted as doras (抜き
+    /// ドラ), and a tsumo is only paid by the two others (ツモ損).
+    ///
+    /// The obs and the action space have their own layouts, `SANMA_OBS_SHAPE`
+    /// and `SANMA_ACTION_SPACE`, with nukidora as action 46.
+    Sanma,
+}
+
+impl GameMode {
+    #[must_use]
+    pub const fn num_players(self) -> u8 {
+        match self {
+            Self::Yonma => 4,
+            Self::Sanma => 3,
+        }
+    }
+
+    /// The score every player starts the game with.
+    #[must_use]
+    pub const fn starting_score(self) -> i32 {
+        match self {
+            Self::Yonma => 25000,
+            Self::Sanma => 35000,
+        }
+    }
+
+    /// The number of tiles in the live wall right after the haipai.
+    #[must_use]
+    pub const fn tiles_left_at_start(self) -> u8 {
+        match self {
+            Self::Yonma => 70,
+            Self::Sanma => 55,
+        }
+    }
+
+    /// Whether `tile` is in the tile set of the mode. Sanma has no 2m to 8m,
+    /// and so no 5mr.
+    #[must_use]
+    pub const fn has_tile(self, tile: Tile) -> bool {
+        let tid = tile.deaka().as_u8();
+        !matches!(self, Self::Sanma) || tid < tu8!(2m) || tid > tu8!(8m)
+    }
+
+    /// The dora indicated by `indicator`. In sanma 1m indicates 9m, as 2m
+    /// to 8m do not exist.
+    #[must_use]
+    pub const fn dora_of(self, indicator: Tile) -> Tile {
+        match self {
+            Self::Sanma if indicator.deaka().as_u8() == tu8!(1m) => t!(9m),
+            _ => indicator.next(),
+        }
+    }
+}
@@ -1,24 +1,86 @@
-use super::{ActionCandidate, PlayerState};
+use super::{
+    obs_plane_names, oracle_obs_plane_names, sanma_obs_plane_names, score_pressure_plane_names,
+    turn_order_plane_names, ActionCandidate, AkaRule, BatchEncoder, BlessingRule, Capacity,
+    DaiminkanPaoRule, DoraBreakdown, Effect, FuritenKind, KawaItemPy, KazoeRule, KuikaeRule,
+    KuitanRule, MultiRonRule, OracleState, PlayerState, Reaction, RiichiExclusion, Rules,
+    UpdateError, WaitKind, YakumanRule,
+};
+use crate::algo::yaku::Yaku;
+use crate::arena::Board;
+use crate::consts::{
+    ACTION_SPACE, OBS_CHANNELS, OBS_SHAPE, ORACLE_OBS_SHAPE, SANMA_ACTION_SPACE,
+    SANMA_OBS_CHANNELS, SCORE_PRESSURE_CHANNELS, SEEN_DELTA_CHANNELS, TURN_ORDER_CHANNELS,
+};
 use crate::hand::{hand, hand_with_aka, tile37_to_vec};
-use crate::mjai::Event;
+use crate::logs::parse_log;
+use crate::mjai::{Event, EventExt};
+use crate::tile::Tile;
 use crate::{must_tile, t, tuz};
 use std::convert::TryInto;
 
+use ndarray::prelude::*;
+use numpy::PyArray1;
+use pyo3::prelude::*;
+use serde_json as json;
+use tinyvec::ArrayVec;
+
 // This is not only a helper but it also tests `encode_obs`.
 fn state_from_log(player_id: u8, log: &str) -> PlayerState {
-    let mut ps = PlayerState::new(player_id);
+    state_from_log_with_rules(player_id, Rules::default(), log)
+}
+
+fn state_from_log_with_rules(player_id: u8, rules: Rules, log: &str) -> PlayerState {
+    let mut ps = PlayerState::with_rules(player_id, rules);
     for line in log.trim().split('\n') {
         let cans = ps.update_json(line).unwrap();
+        assert_bytes_round_trip(&ps);
         if cans.can_act() {
             let _encoded = ps.encode_obs(false);
+            assert_actions_round_trip(&ps, false);
             if cans.can_daiminkan || cans.can_kakan || cans.can_ankan {
                 let _encoded = ps.encode_obs(true);
+                assert_actions_round_trip(&ps, true);
             }
         }
     }
     ps
 }
 
+/// Every legal action turns into a valid event, with or without aka.
+fn assert_actions_round_trip(ps: &PlayerState, at_kan_choice: bool) {
+    let mask = ps.legal_action_mask(at_kan_choice);
+    assert_eq!(
+        ps.encode_obs(at_kan_choice).1.as_slice().unwrap(),
+        mask.as_slice(),
+    );
+    for action in (0..mask.len()).filter(|&a| mask[a]) {
+        for prefer_aka in [true, false] {
+            let ev = ps
+                .action_to_event(action, at_kan_choice, prefer_aka)
+                .unwrap();
+            if let Err(err) = ps.validate_reaction(&ev) {
+                panic!(
+                    "action {action} as {ev:?} is invalid: {err}\n{}",
+                    ps.brief_info()
+                );
+            }
+        }
+    }
+    for action in (0..mask.len()).filter(|&a| !mask[a]) {
+        ps.action_to_event(action, at_kan_choice, true).unwrap_err();
+    }
+}
+
+fn assert_bytes_round_trip(ps: &PlayerState) {
+    let bytes = ps.to_bytes();
+    let restored = PlayerState::from_bytes(&bytes).unwrap();
+    assert_eq!(restored.to_bytes(), bytes);
+    let diff = restored.diff(ps);
+    assert!(diff.is_empty(), "{diff:#?}");
+    assert_eq!(restored.brief_info(), ps.brief_info());
+    assert_eq!(restored.encode_obs(false), ps.encode_obs(false));
+}
+
 #[test]
 fn waits() {
     let mut ps = PlayerState {
@@ -52,6 +114,91 @@ fn waits() {
     }
 }
 
+#[test]
+fn wait_shapes() {
+    use WaitKind::*;
+
+    let shapes_of = |tehai| {
+        let mut ps = PlayerState {
+            tehai: hand(tehai).unwrap(),
+            tehai_len_div3: 4,
+            ..Default::default()
+        };
+        ps.update_waits_and_furiten();
+        ps.wait_shapes()
+            .into_iter()
+            .map(|s| (s.tile, s.kinds))
+            .collect::<Vec<_>>()
+    };
+
+    let expected = vec![
+        (t!(1s), vec![Ryanmen]),
+        (t!(2s), vec![Tanki]),
+        (t!(3s), vec![Kanchan, Ryanmen]),
+        (t!(5s), vec![Tanki]),
+        (t!(7s), vec![Kanchan, Ryanmen]),
+        (t!(8s), vec![Tanki]),
+        (t!(9s), vec![Ryanmen]),
+    ];
+    assert_eq!(shapes_of("2344445666678s"), expected);
+
+    let expected = vec![(t!(3m), vec![Penchan])];
+    assert_eq!(shapes_of("12m 111p 456p 789s 55z"), expected);
+
+    let expected = vec![(t!(1m), vec![Shanpon]), (t!(P), vec![Shanpon])];
+    assert_eq!(shapes_of("11m 456p 789s 55z 666z"), expected);
+
+    let expected = vec![(t!(C), vec![Tanki, Chiitoitsu])];
+    assert_eq!(shapes_of("112233m 445566p 7z"), expected);
+
+    let expected: Vec<_> = t![1m, 9m, 1p, 9p, 1s, 9s, E, S, W, N, P, F, C]
+        .into_iter()
+        .map(|t| (t, vec![Kokushi]))
+        .collect();
+    assert_eq!(shapes_of("19m 19p 19s 1234567z"), expected);
+}
+
+#[test]
+fn tenpai_info() {
+    let mut ps = PlayerState {
+        tehai: hand("234m 5688p 345678s").unwrap(),
+        tehai_len_div3: 4,
+        is_menzen: true,
+        bakaze: t!(E),
+        jikaze: t!(S),
+        ..Default::default()
+    };
+    ps.update_shanten();
+    ps.update_waits_and_furiten();
+    let info = ps.tenpai_info().unwrap();
+    assert_eq!(info.waits, t![4p, 7p]);
+    assert!(!info.is_furiten);
+    assert!(info.yaku_guaranteed);
+    // 平和, 断幺九
+    assert_eq!(info.highest_fu_han, Some((30, 2)));
+
+    ps.tehai = hand("234m 5689p 345678s").unwrap();
+    ps.update_shanten();
+    ps.update_waits_and_furiten();
+    assert!(ps.tenpai_info().is_none());
+}
+
+#[test]
+fn obs_plane_names_in_lockstep() {
+    let names = obs_plane_names();
+    assert_eq!(names.len(), OBS_SHAPE.0);
+
+    let mut ps = PlayerState::new(0);
+    ps.tehai = hand("234m 5688p 345678s").unwrap();
+    let (obs, _) = ps.encode_obs(false);
+    assert_eq!(names.len(), obs.shape()[0]);
+
+    let mut dedup = names.clone();
+    dedup.sort_unstable();
+    dedup.dedup();
+    assert_eq!(dedup.len(), names.len());
+}
+
 #[test]
 fn can_chi() {
     let mut ps = PlayerState::new(0);
@@ -172,6 +319,180 @@ fn can_chi() {
     ));
 }
 
+#[test]
+fn kuikae() {
+    fn state_after_call(rules: Rules, call: &str) -> PlayerState {
+        let log = format!(
+            r#"
+            {{"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5m","6m","1p","2p","3p","7s","8s","9s","E","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}}
+            {call}
+        "#
+        );
+        let mut ps = PlayerState::with_rules(0, rules);
+        for line in log.trim().lines() {
+            ps.update_json(line).unwrap();
+        }
+        ps
+    }
+    let dahai = |pai: &str| -> Event {
+        json::from_str(&format!(
+            r#"{{"type":"dahai","actor":0,"pai":"{pai}","tsumogiri":false}}"#
+        ))
+        .unwrap()
+    };
+
+    // chi low
+    let call = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"3m","tsumogiri":true}
+        {"type":"chi","actor":0,"target":3,"pai":"3m","consumed":["4m","5m"]}
+    "#;
+    let mut ps = state_after_call(Rules::default(), call);
+    let discard_candidates = ps.discard_candidates();
+    assert!(!discard_candidates[tuz!(3m)]);
+    assert!(!discard_candidates[tuz!(6m)]);
+    assert!(discard_candidates[tuz!(1p)]);
+    ps.validate_reaction(&dahai("3m")).unwrap_err();
+    ps.validate_reaction(&dahai("6m")).unwrap_err();
+    ps.validate_reaction(&dahai("1p")).unwrap();
+
+    // The restriction does not persist to later turns.
+    let later = r#"
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    for line in later.trim().lines() {
+        ps.update_json(line).unwrap();
+    }
+    let discard_candidates = ps.discard_candidates();
+    assert!(discard_candidates[tuz!(3m)]);
+    assert!(discard_candidates[tuz!(6m)]);
+    ps.validate_reaction(&dahai("3m")).unwrap();
+    ps.validate_reaction(&dahai("6m")).unwrap();
+
+    // chi high
+    let call = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"6m","tsumogiri":true}
+        {"type":"chi","actor":0,"target":3,"pai":"6m","consumed":["4m","5m"]}
+    "#;
+    let ps = state_after_call(Rules::default(), call);
+    let discard_candidates = ps.discard_candidates();
+    assert!(!discard_candidates[tuz!(3m)]);
+    assert!(!discard_candidates[tuz!(6m)]);
+    ps.validate_reaction(&dahai("3m")).unwrap_err();
+    ps.validate_reaction(&dahai("6m")).unwrap_err();
+
+    // chi low, but only the same tile is forbidden by the rule
+    let call = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"3m","tsumogiri":true}
+        {"type":"chi","actor":0,"target":3,"pai":"3m","consumed":["4m","5m"]}
+    "#;
+    let rules = Rules {
+        kuikae: KuikaeRule::SameTileOnly,
+        ..Default::default()
+    };
+    let ps = state_after_call(rules, call);
+    let discard_candidates = ps.discard_candidates();
+    assert!(!discard_candidates[tuz!(3m)]);
+    assert!(discard_candidates[tuz!(6m)]);
+    ps.validate_reaction(&dahai("3m")).unwrap_err();
+    ps.validate_reaction(&dahai("6m")).unwrap();
+
+    // pon
+    let call = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"E","tsumogiri":true}
+        {"type":"pon","actor":0,"target":3,"pai":"E","consumed":["E","E"]}
+    "#;
+    for rules in [
+        Rules::default(),
+        Rules {
+            kuikae: KuikaeRule::SameTileOnly,
+            ..Default::default()
+        },
+    ] {
+        let ps = state_after_call(rules, call);
+        let discard_candidates = ps.discard_candidates();
+        assert!(!discard_candidates[tuz!(E)]);
+        assert!(discard_candidates[tuz!(3m)]);
+        ps.validate_reaction(&dahai("E")).unwrap_err();
+        ps.validate_reaction(&dahai("3m")).unwrap();
+    }
+}
+
+#[test]
+fn riichi_forbidden_discards() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5pr","6p","2s","3s","4s","7s","8s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"W"}
+    "#;
+    let dahai = |pai: &str| -> Event {
+        json::from_str(&format!(
+            r#"{{"type":"dahai","actor":0,"pai":"{pai}","tsumogiri":false}}"#
+        ))
+        .unwrap()
+    };
+
+    let mut ps = state_from_log(0, log);
+    assert_eq!(ps.riichi_forbidden_discards(), [false; 37]);
+    assert_eq!(ps.fallback_reaction(), Reaction::Discard(t!(W)));
+    ps.validate_reaction(&dahai("1m")).unwrap();
+
+    // Only the discards keeping the tenpai are allowed along with the
+    // declaration.
+    ps.update_json(r#"{"type":"reach","actor":0}"#).unwrap();
+    let forbidden = ps.riichi_forbidden_discards();
+    assert!(forbidden[tuz!(1m)]);
+    assert!(forbidden[tuz!(5pr)]);
+    assert!(!forbidden[tuz!(5p)]);
+    assert!(!forbidden[tuz!(W)]);
+    ps.validate_reaction(&dahai("1m")).unwrap_err();
+    ps.validate_reaction(&dahai("5pr")).unwrap_err();
+    ps.validate_reaction(&dahai("W")).unwrap();
+    assert_eq!(ps.fallback_reaction(), Reaction::Discard(t!(W)));
+
+    // Only the tsumo is allowed after the riichi is accepted.
+    let later = r#"
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+        {"type":"reach_accepted","actor":0,"deltas":[-1000,0,0,0],"scores":[24000,25000,25000,25000]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+    "#;
+    for line in later.trim().lines() {
+        ps.update_json(line).unwrap();
+        assert_eq!(ps.riichi_forbidden_discards(), [false; 37]);
+        if !ps.last_cans.can_act() {
+            assert_eq!(ps.fallback_reaction(), Reaction::Pass);
+        }
+    }
+    ps.update_json(r#"{"type":"tsumo","actor":0,"pai":"C"}"#)
+        .unwrap();
+    let forbidden = ps.riichi_forbidden_discards();
+    for (tid, &count) in ps.tehai.iter().enumerate() {
+        assert_eq!(
+            forbidden[tid],
+            count > 0 && tid != tuz!(C) && tid != tuz!(5p)
+        );
+    }
+    assert!(forbidden[tuz!(5pr)]);
+    ps.validate_reaction(&dahai("E")).unwrap_err();
+    ps.validate_reaction(&dahai("C")).unwrap();
+    assert_eq!(ps.fallback_reaction(), Reaction::Discard(t!(C)));
+}
+
 #[test]
 fn furiten() {
     let mut ps = PlayerState::new(0);
@@ -206,6 +527,7 @@ fn furiten() {
     assert!(ps.shanten == 0);
     assert!(ps.waits[tuz!(1m)] && ps.waits[tuz!(4m)] && ps.waits[tuz!(7m)]);
     assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
 
     ps.update(&Event::Tsumo {
         actor: 1,
@@ -217,6 +539,7 @@ fn furiten() {
         tsumogiri: false,
     });
     assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
     assert!(cans.can_ron_agari);
 
     ps.update(&Event::Tsumo {
@@ -224,6 +547,7 @@ fn furiten() {
         pai: t!(?),
     });
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Temporary);
     ps.update(&Event::Dahai {
         actor: 2,
         pai: t!(1s),
@@ -242,6 +566,7 @@ fn furiten() {
     assert!(ps.shanten == 0);
     assert!(ps.waits[tuz!(1m)] && ps.waits[tuz!(4m)] && ps.waits[tuz!(7m)]);
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Temporary);
     assert!(!cans.can_ron_agari);
 
     ps.update(&Event::Tsumo {
@@ -249,12 +574,14 @@ fn furiten() {
         pai: t!(3s),
     });
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Temporary);
     ps.update(&Event::Dahai {
         actor: 0,
         pai: t!(3s),
         tsumogiri: true,
     });
     assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
 
     ps.update(&Event::Tsumo {
         actor: 1,
@@ -285,6 +612,7 @@ fn furiten() {
         tsumogiri: false,
     });
     assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
     assert!(cans.can_ron_agari);
     assert_eq!(ps.agari_points(true, &[]).unwrap().ron, 5800);
 
@@ -337,6 +665,7 @@ fn furiten() {
     });
     assert!(ps.waits[tuz!(1m)] && ps.waits[tuz!(4m)] && ps.waits[tuz!(7m)]);
     assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
     assert!(cans.can_tsumo_agari);
     ps.update(&Event::Dahai {
         actor: 0,
@@ -344,6 +673,7 @@ fn furiten() {
         tsumogiri: true,
     });
     assert!(ps.at_furiten); // furiten forever from now on
+    assert_eq!(ps.furiten_kind(), FuritenKind::Riichi);
 
     ps.update(&Event::Tsumo {
         actor: 1,
@@ -374,6 +704,7 @@ fn furiten() {
     });
     assert!(ps.waits[tuz!(1m)] && ps.waits[tuz!(4m)] && ps.waits[tuz!(7m)]);
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Riichi);
     assert!(!cans.can_ron_agari);
 
     ps.update(&Event::Tsumo {
@@ -386,6 +717,7 @@ fn furiten() {
         tsumogiri: true,
     });
     assert!(ps.at_furiten); // still furiten
+    assert_eq!(ps.furiten_kind(), FuritenKind::Riichi);
 
     ps.update(&Event::Tsumo {
         actor: 1,
@@ -406,6 +738,7 @@ fn furiten() {
         tsumogiri: true,
     });
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Riichi);
     assert!(!cans.can_ron_agari);
     ps.update(&Event::Tsumo {
         actor: 3,
@@ -424,8 +757,73 @@ fn furiten() {
     });
     assert!(ps.waits[0] && ps.waits[3] && ps.waits[6]);
     assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Riichi);
     assert!(cans.can_tsumo_agari);
     assert_eq!(ps.agari_points(false, &[t!(3m)]).unwrap().tsumo_ko, 6000);
+
+    // discard furiten
+    ps.update(&Event::StartKyoku {
+        bakaze: t!(E),
+        kyoku: 1,
+        honba: 1,
+        kyotaku: 0,
+        oya: 0,
+        scores: [25000; 4],
+        dora_marker: t!(3p),
+        tehais: [
+            tile37_to_vec(&hand_with_aka("23406m 456789p 58s").unwrap())
+                .try_into()
+                .unwrap(),
+            [t!(?); 13],
+            [t!(?); 13],
+            [t!(?); 13],
+        ],
+    });
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
+    ps.update(&Event::Tsumo {
+        actor: 0,
+        pai: t!(7m),
+    });
+    ps.update(&Event::Dahai {
+        actor: 0,
+        pai: t!(7m),
+        tsumogiri: true,
+    });
+    assert!(!ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::None);
+    for actor in 1..4 {
+        ps.update(&Event::Tsumo { actor, pai: t!(?) });
+        ps.update(&Event::Dahai {
+            actor,
+            pai: t!(N),
+            tsumogiri: true,
+        });
+    }
+
+    ps.update(&Event::Tsumo {
+        actor: 0,
+        pai: t!(8s),
+    });
+    ps.update(&Event::Dahai {
+        actor: 0,
+        pai: t!(5s),
+        tsumogiri: false,
+    });
+    assert!(ps.waits[tuz!(1m)] && ps.waits[tuz!(4m)] && ps.waits[tuz!(7m)]);
+    assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Permanent);
+    ps.update(&Event::Tsumo {
+        actor: 1,
+        pai: t!(?),
+    });
+    let cans = ps.update(&Event::Dahai {
+        actor: 1,
+        pai: t!(1m),
+        tsumogiri: true,
+    });
+    assert!(!cans.can_ron_agari);
+    assert_eq!(ps.furiten_kind(), FuritenKind::Permanent);
+    assert_eq!(ps.furiten_kind().as_str(), "permanent");
 }
 
 #[test]
@@ -528,6 +926,251 @@ fn dora_count_after_kan() {
         dora_marker: t!(4p),
     });
     assert_eq!(ps.doras_owned[0], 8);
+    assert_eq!(
+        ps.dora_breakdown(&t![E, 9p]),
+        DoraBreakdown {
+            dora: 7,
+            aka: 1,
+            ura: 2,
+        },
+    );
+}
+
+#[test]
+fn dora_breakdown_after_daiminkan() {
+    let mut ps = PlayerState::new(0);
+    ps.update(&Event::StartKyoku {
+        bakaze: t!(E),
+        kyoku: 1,
+        honba: 0,
+        kyotaku: 0,
+        oya: 0,
+        scores: [25000; 4],
+        dora_marker: t!(E),
+        tehais: [
+            tile37_to_vec(&hand_with_aka("055m 123p 456s 789s 3z").unwrap())
+                .try_into()
+                .unwrap(),
+            [t!(?); 13],
+            [t!(?); 13],
+            [t!(?); 13],
+        ],
+    });
+    for ev in [
+        Event::Tsumo {
+            actor: 0,
+            pai: t!(N),
+        },
+        Event::Dahai {
+            actor: 0,
+            pai: t!(N),
+            tsumogiri: true,
+        },
+        Event::Tsumo {
+            actor: 1,
+            pai: t!(?),
+        },
+        Event::Dahai {
+            actor: 1,
+            pai: t!(5m),
+            tsumogiri: true,
+        },
+        Event::Daiminkan {
+            actor: 0,
+            target: 1,
+            pai: t!(5m),
+            consumed: t![5mr, 5m, 5m],
+        },
+    ] {
+        ps.update(&ev);
+    }
+    // The kan dora is not revealed yet.
+    assert_eq!(ps.doras_owned(), 1);
+    assert_eq!(ps.dora_breakdown(&[]).dora, 0);
+
+    ps.update(&Event::Dora {
+        dora_marker: t!(4m),
+    });
+    ps.update(&Event::Tsumo {
+        actor: 0,
+        pai: t!(W),
+    });
+    assert_eq!(ps.doras_owned(), 5);
+    assert_eq!(
+        ps.dora_breakdown(&t![S]),
+        DoraBreakdown {
+            dora: 4,
+            aka: 1,
+            ura: 2,
+        },
+    );
+}
+
+#[test]
+fn aka_dora_count_in_pon_and_kakan() {
+    // Player 0 calls with the 5pr, while player 1 watches.
+    let mut states = [PlayerState::new(0), PlayerState::new(1)];
+    let update = |states: &mut [PlayerState; 2], ev: Event| {
+        for ps in states {
+            let ev = match ev {
+                Event::Tsumo { actor, .. } if actor != ps.player_id => {
+                    Event::Tsumo { actor, pai: t!(?) }
+                }
+                _ => ev.clone(),
+            };
+            ps.update(&ev);
+        }
+    };
+    let doras_owned =
+        |states: &[PlayerState; 2]| (states[0].doras_owned[0], states[1].doras_owned[3]);
+
+    update(
+        &mut states,
+        Event::StartKyoku {
+            bakaze: t!(E),
+            kyoku: 4,
+            honba: 0,
+            kyotaku: 0,
+            oya: 3,
+            scores: [25000; 4],
+            dora_marker: t!(4p),
+            tehais: [
+                tile37_to_vec(&hand_with_aka("123789m 123s 0p5p 12z").unwrap())
+                    .try_into()
+                    .unwrap(),
+                tile37_to_vec(&hand_with_aka("1199p 19s 1234567z").unwrap())
+                    .try_into()
+                    .unwrap(),
+                [t!(?); 13],
+                [t!(?); 13],
+            ],
+        },
+    );
+    // The 5pr is both an aka and a dora, counted once as each.
+    assert_eq!(states[0].doras_owned[0], 3);
+    assert_eq!(states[1].doras_owned[3], 0);
+
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 3,
+            pai: t!(?),
+        },
+    );
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 3,
+            pai: t!(5p),
+            tsumogiri: false,
+        },
+    );
+    update(
+        &mut states,
+        Event::Pon {
+            actor: 0,
+            target: 3,
+            pai: t!(5p),
+            consumed: t![5pr, 5p],
+        },
+    );
+    assert_eq!(doras_owned(&states), (4, 4));
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 0,
+            pai: t!(E),
+            tsumogiri: false,
+        },
+    );
+    assert_eq!(doras_owned(&states), (4, 4));
+
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 1,
+            pai: t!(1m),
+        },
+    );
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 1,
+            pai: t!(1m),
+            tsumogiri: true,
+        },
+    );
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 2,
+            pai: t!(?),
+        },
+    );
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 2,
+            pai: t!(W),
+            tsumogiri: true,
+        },
+    );
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 3,
+            pai: t!(?),
+        },
+    );
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 3,
+            pai: t!(N),
+            tsumogiri: true,
+        },
+    );
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 0,
+            pai: t!(5p),
+        },
+    );
+    assert_eq!(doras_owned(&states), (5, 4));
+    update(
+        &mut states,
+        Event::Kakan {
+            actor: 0,
+            pai: t!(5p),
+            consumed: t![5pr, 5p, 5p],
+        },
+    );
+    assert_eq!(doras_owned(&states), (5, 5));
+
+    // A new indicator of 4p counts every 5p once more, but not the aka.
+    update(
+        &mut states,
+        Event::Tsumo {
+            actor: 0,
+            pai: t!(S),
+        },
+    );
+    update(
+        &mut states,
+        Event::Dahai {
+            actor: 0,
+            pai: t!(S),
+            tsumogiri: true,
+        },
+    );
+    update(
+        &mut states,
+        Event::Dora {
+            dora_marker: t!(4p),
+        },
+    );
+    assert_eq!(doras_owned(&states), (9, 9));
 }
 
 #[test]
@@ -618,48 +1261,560 @@ fn rule_based_agari_all_last_minogashi() {
     let should_hora = ps.rule_based_agari();
     assert!(!should_hora);
 
+    // 123m 777m 67p 567s 77s + 8p, riichi tsumo only.
+    let detail = ps.agari_detail(false, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Riichi, 1), (Yaku::MenzenTsumo, 1)]);
+    assert_eq!((detail.han, detail.fu), (2, 30));
+    assert_eq!(detail.point, ps.agari_points(false, &[]).unwrap());
+    let detail = ps.agari_detail(false, &[t!(6m)]).unwrap();
+    assert_eq!(
+        detail.yakus,
+        [
+            (Yaku::Riichi, 1),
+            (Yaku::MenzenTsumo, 1),
+            (Yaku::UraDora, 3)
+        ],
+    );
+    assert_eq!(detail.han, 5);
+
     ps.add_dora_indicator(t!(5m));
     let should_hora = ps.rule_based_agari();
     assert!(should_hora);
 }
 
 #[test]
-fn get_rank() {
-    let ps = PlayerState::new(0);
-    let rank = ps.get_rank(&[20000, 25000, 25000, 30000]);
-    assert_eq!(rank, 3);
+fn haitei_houtei() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","3s","4s","6s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+    "#;
 
-    let ps = PlayerState::new(3);
-    let rank = ps.get_rank(&[25000, 25000, 25000, 25000]);
-    assert_eq!(rank, 3);
+    // 123m 456m 789p 234s 6s tanki, menzen tsumo and haitei 30 fu.
+    let mut ps = state_from_log(0, log);
+    ps.tiles_left = 1;
+    ps.update_json(r#"{"type":"tsumo","actor":0,"pai":"6s"}"#)
+        .unwrap();
+    assert_eq!(ps.tiles_left, 0);
+    assert!(ps.last_cans.can_tsumo_agari);
+    let detail = ps.agari_detail(false, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::MenzenTsumo, 1), (Yaku::Haitei, 1)]);
+    assert_eq!((detail.han, detail.fu), (2, 30));
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap().tsumo_total(true),
+        1000 * 3,
+    );
 
-    let ps = PlayerState::new(1);
-    let rank = ps.get_rank(&[25000, 30000, 20000, 25000]);
-    assert_eq!(rank, 2);
+    // Houtei is the only yaku of the ron, 40 fu.
+    let mut ps = state_from_log(0, log);
+    ps.update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#)
+        .unwrap();
+    let cans = ps
+        .update_json(r#"{"type":"dahai","actor":1,"pai":"6s","tsumogiri":true}"#)
+        .unwrap();
+    assert!(!cans.can_ron_agari);
 
-    let ps = PlayerState::new(1);
-    let rank = ps.get_rank(&[32000, 32000, 18000, 18000]);
-    assert_eq!(rank, 0);
+    let mut ps = state_from_log(0, log);
+    ps.tiles_left = 1;
+    ps.update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#)
+        .unwrap();
+    let cans = ps
+        .update_json(r#"{"type":"dahai","actor":1,"pai":"6s","tsumogiri":true}"#)
+        .unwrap();
+    assert!(cans.can_ron_agari && !cans.is_chankan);
+    let detail = ps.agari_detail(true, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Houtei, 1)]);
+    assert_eq!((detail.han, detail.fu), (1, 40));
+    assert_eq!(ps.agari_points(true, &[]).unwrap().ron, 2000);
+}
 
-    let ps = PlayerState::new(2);
-    let rank = ps.get_rank(&[32000, 18000, 18000, 32000]);
-    assert_eq!(rank, 1);
+#[test]
+fn rinshan_kaihou() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","W","P","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"P"}
+    "#;
 
-    let ps = PlayerState::new(2);
-    let rank = ps.get_rank(&[5, 2, 5, 3]);
-    assert_eq!(rank, 1);
+    // 123m 456m 789p 2s tanki with a pon of haku, which has only one yaku.
+    let mut ps = state_from_log(0, log);
+    assert!(ps.last_cans.can_kakan);
+    assert_eq!(ps.kakan_candidates(), [t!(P)]);
+    ps.update_json(r#"{"type":"kakan","actor":0,"pai":"P","consumed":["P","P","P"]}"#)
+        .unwrap();
+    let cans = ps
+        .update_json(r#"{"type":"tsumo","actor":0,"pai":"2s"}"#)
+        .unwrap();
+    assert!(cans.can_tsumo_agari);
+
+    // Rinshan adds one han, and the fu is 20 + 16 for the kan of haku + 2 for
+    // tanki + 2 for tsumo.
+    let detail = ps.agari_detail(false, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Rinshan, 1), (Yaku::Haku, 1)]);
+    assert_eq!((detail.han, detail.fu), (2, 40));
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap().tsumo_total(true),
+        1300 * 3,
+    );
+
+    // The han comes from `at_rinshan` alone, without which it is 700 all.
+    let mut no_rinshan = ps.clone();
+    no_rinshan.at_rinshan = false;
+    let detail = no_rinshan.agari_detail(false, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Haku, 1)]);
+    assert_eq!((detail.han, detail.fu), (1, 40));
+    assert_eq!(detail.point.tsumo_total(true), 700 * 3);
+
+    // The same tile drawn from the wall without the kan is worth only the haku.
+    let mut ps = state_from_log(0, log);
+    ps.update_json(r#"{"type":"dahai","actor":0,"pai":"P","tsumogiri":true}"#)
+        .unwrap();
+    for line in [
+        r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":2,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":3,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":3,"pai":"9s","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"2s"}"#,
+    ] {
+        ps.update_json(line).unwrap();
+    }
+    assert!(ps.last_cans.can_tsumo_agari);
+    let detail = ps.agari_detail(false, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Haku, 1)]);
+    assert_eq!((detail.han, detail.fu), (1, 30));
 }
 
 #[test]
-fn kakan_from_hand() {
+fn tiles_seen() {
     let log = r#"
-        {"type":"start_kyoku","bakaze":"S","dora_marker":"6m","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[16100,36600,16800,30500],"tehais":[["5p","5s","1s","9m","9m","W","E","N","1p","F","9m","3p","6p"],["4s","9s","S","4s","1m","P","N","7s","F","2m","3s","2s","2s"],["6m","8p","8p","2p","8m","N","7p","C","1s","2p","N","9s","9p"],["2m","6s","7p","9s","2m","9s","6m","7s","8m","3m","S","5mr","C"]]}
-        {"type":"tsumo","actor":1,"pai":"S"}
-        {"type":"dahai","actor":1,"pai":"N","tsumogiri":false}
-        {"type":"tsumo","actor":2,"pai":"1s"}
-        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":false}
-        {"type":"tsumo","actor":3,"pai":"P"}
-        {"type":"dahai","actor":3,"pai":"S","tsumogiri":false}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","W","P","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"E","tsumogiri":true}
+        {"type":"pon","actor":3,"target":2,"pai":"E","consumed":["E","E"]}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"3s"}
+    "#;
+    let ps = state_from_log(0, log);
+
+    // 13 tiles of the haipai, the dora indicator, 2 draws, 4 discards by
+    // others and 2 tiles revealed by the pon of player 3.
+    let seen = ps.tiles_seen();
+    assert_eq!(seen.iter().sum::<u8>(), 13 + 1 + 2 + 4 + 2);
+    assert_eq!(seen[tuz!(P)], 3);
+    assert_eq!(seen[tuz!(E)], 3);
+    assert_eq!(seen[tuz!(9s)], 2);
+    assert_eq!(seen[tuz!(W)], 1);
+    for (seen, unseen) in seen.into_iter().zip(ps.tiles_unseen()) {
+        assert_eq!(seen + unseen, 4);
+    }
+
+    assert_eq!(ps.wall_remaining(), 70 - 5);
+    assert_eq!(ps.wall_remaining(), ps.tiles_left());
+}
+
+#[test]
+fn last_self_tsumo_and_kawa_tile() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","W","P","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"3s"}
+    "#;
+    // `(last_self_tsumo, last_kawa_tile)` after each event.
+    let expected = [
+        (None, None),
+        (Some(t!(1s)), None),
+        (None, Some(t!(1s))),
+        (None, Some(t!(1s))),
+        (None, Some(t!(P))),
+        (None, Some(t!(P))),
+        (None, Some(t!(W))),
+        (None, Some(t!(W))),
+        (None, Some(t!(9s))),
+        (None, Some(t!(9s))),
+        (None, Some(t!(E))),
+        (None, Some(t!(E))),
+        (None, Some(t!(N))),
+        (Some(t!(3s)), None),
+    ];
+
+    let mut ps = PlayerState::new(0);
+    let lines: Vec<_> = log.trim().lines().collect();
+    assert_eq!(lines.len(), expected.len());
+    for (line, expected) in lines.into_iter().zip(expected) {
+        ps.update_json(line).unwrap();
+        assert_eq!(
+            (ps.last_self_tsumo(), ps.last_kawa_tile()),
+            expected,
+            "after {}",
+            line.trim(),
+        );
+    }
+}
+
+#[test]
+fn tiles_seen_since_last_decision() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5m","6m","7m","2p","3p","4p","6s","7s","8s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"pon","actor":2,"target":1,"pai":"W","consumed":["W","W"]}
+        {"type":"dahai","actor":2,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"F"}
+    "#;
+    // The number of tiles the event shows to player 0.
+    let revealed = |ev: &Event| match *ev {
+        Event::StartKyoku { .. } => 13 + 1,
+        Event::Tsumo { actor: 0, .. } | Event::Dora { .. } => 1,
+        Event::Dahai { actor, .. } if actor != 0 => 1,
+        Event::Chi { actor, .. } | Event::Pon { actor, .. } if actor != 0 => 2,
+        _ => 0,
+    };
+
+    let mut ps = PlayerState::new(0);
+    let mut decisions = vec![];
+    let mut revealed_since = 0;
+    for line in log.trim().lines() {
+        let ev: Event = json::from_str(line).unwrap();
+        let cans = ps.update(&ev);
+        revealed_since += revealed(&ev);
+        if cans.can_act() {
+            let delta = ps.tiles_seen_since_last_decision();
+            assert_eq!(
+                delta.iter().map(|&n| n as usize).sum::<usize>(),
+                revealed_since,
+                "at {}",
+                line.trim(),
+            );
+            decisions.push(delta);
+            revealed_since = 0;
+        }
+    }
+    // The draws of 9p and F, the tanki on 9p having no yaku to ron with.
+    assert_eq!(decisions.len(), 2);
+
+    let delta = decisions[1];
+    assert_eq!(delta[tuz!(W)], 3);
+    assert_eq!(delta[tuz!(9p)], 2);
+    assert_eq!(delta[tuz!(F)], 1);
+    assert_eq!(delta[tuz!(N)], 0);
+    assert!(ps.waits[tuz!(9p)]);
+    assert_eq!(ps.waits.iter().filter(|&&w| w).count(), 1);
+    assert_eq!(ps.waits_seen_since_last_decision(), 2);
+
+    let restored = PlayerState::from_bytes(&ps.to_bytes()).unwrap();
+    assert_eq!(restored.tiles_seen_since_last_decision(), delta);
+
+    let arr = ps.encode_seen_delta();
+    assert_eq!(arr.shape(), [SEEN_DELTA_CHANNELS, 34]);
+    assert_eq!(arr.column(tuz!(W)).to_vec()[..4], [1., 1., 1., 0.]);
+    assert_eq!(arr.column(tuz!(F)).to_vec()[..4], [1., 0., 0., 0.]);
+    assert_eq!(arr.column(tuz!(1m)).to_vec()[..4], [0.; 4]);
+    assert_eq!(arr.column(0).to_vec()[4..], [1., 1., 0., 0.]);
+
+    // Nothing carries over to the next kyoku, where the haipai and the dora
+    // indicator are new.
+    ps.update_json(r#"{"type":"end_kyoku"}"#).unwrap();
+    ps.update_json(r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5m","6m","7m","2p","3p","4p","6s","7s","8s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#).unwrap();
+    let delta = ps.tiles_seen_since_last_decision();
+    assert_eq!(delta.iter().map(|&n| n as usize).sum::<usize>(), 14);
+    assert_eq!(delta[tuz!(C)], 1);
+}
+
+#[test]
+fn concurrent_kan_candidates() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","1m","2p","2p","2p","2p","P","P","3s","4s","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9s"}
+        {"type":"dahai","actor":0,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"5s","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"P"}
+    "#;
+
+    // Two ankans and a kakan, which is as many kans as a hand can have to
+    // declare at once.
+    let ps = state_from_log(0, log);
+    assert!(ps.last_cans.can_ankan);
+    assert!(ps.last_cans.can_kakan);
+    assert_eq!(ps.ankan_candidates(), t![1m, 2p]);
+    assert_eq!(ps.kakan_candidates(), [t!(P)]);
+
+    let mut expected = [false; 34];
+    expected[tuz!(1m)] = true;
+    expected[tuz!(2p)] = true;
+    assert_eq!(ps.ankan_tiles(), expected);
+    let mut expected = [false; 34];
+    expected[tuz!(P)] = true;
+    assert_eq!(ps.kakan_tiles(), expected);
+
+    let kans = ps
+        .legal_reactions()
+        .into_iter()
+        .filter(|r| matches!(r, Reaction::Ankan { .. } | Reaction::Kakan(_)))
+        .count();
+    assert_eq!(kans, 3);
+}
+
+#[test]
+fn agari_deltas() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":2,"honba":2,"kyotaku":1,"oya":1,"scores":[25000,25000,25000,24000],"tehais":[["2m","3m","4m","5m","5m","5p","6p","7p","6s","7s","P","P","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+    "#;
+
+    // Haku with 40 fu for 1300, plus 600 for the two honba and 1000 for the
+    // riichi stick.
+    let mut ps = state_from_log(0, log);
+    ps.update_json(r#"{"type":"dahai","actor":3,"pai":"8s","tsumogiri":true}"#)
+        .unwrap();
+    assert!(ps.last_cans.can_ron_agari);
+    let deltas = ps.agari_deltas(true, 3, &[]).unwrap();
+    assert_eq!(deltas, [1300 + 600 + 1000, 0, 0, -1300 - 600]);
+    ps.agari_deltas(true, 0, &[]).unwrap_err();
+
+    // Menzen tsumo and haku with 30 fu for 500/1000, plus 100 from each
+    // player for each honba.
+    let mut ps = state_from_log(0, log);
+    for line in [
+        r#"{"type":"dahai","actor":3,"pai":"N","tsumogiri":true}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"8s"}"#,
+    ] {
+        ps.update_json(line).unwrap();
+    }
+    assert!(ps.last_cans.can_tsumo_agari);
+    let deltas = ps.agari_deltas(false, 0, &[]).unwrap();
+    assert_eq!(
+        deltas,
+        [2000 + 600 + 1000, -1000 - 200, -500 - 200, -500 - 200]
+    );
+    assert_eq!(deltas.iter().sum::<i32>(), 1000);
+}
+
+#[test]
+fn update_checked() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","W","P","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"P"}
+        {"type":"kakan","actor":0,"pai":"P","consumed":["P","P","P"]}
+        {"type":"dora","dora_marker":"3p"}
+        {"type":"tsumo","actor":0,"pai":"2s"}
+    "#;
+    let lines: Vec<_> = log.trim().lines().map(str::trim).collect();
+    let parse = |line: &str| -> Event { json::from_str(line).unwrap() };
+
+    let mut ps = PlayerState::new(0);
+    let mut unchecked = PlayerState::new(0);
+    for &line in &lines {
+        let cans = ps.update_checked(&parse(line)).unwrap();
+        assert_eq!(
+            cans.as_bitflags(),
+            unchecked.update(&parse(line)).as_bitflags(),
+        );
+    }
+
+    let corruptions = [
+        (
+            3,
+            r#"{"type":"dahai","actor":0,"pai":"9m","tsumogiri":false}"#,
+            UpdateError::TileNotInHand { tile: t!(9m) },
+        ),
+        (
+            4,
+            r#"{"type":"tsumo","actor":2,"pai":"?"}"#,
+            UpdateError::OutOfTurn {
+                actor: 2,
+                expected: 1,
+            },
+        ),
+        (
+            5,
+            r#"{"type":"dahai","actor":2,"pai":"P","tsumogiri":true}"#,
+            UpdateError::OutOfTurn {
+                actor: 2,
+                expected: 1,
+            },
+        ),
+        (
+            6,
+            r#"{"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","F"]}"#,
+            UpdateError::InvalidMeld { actor: 0 },
+        ),
+        (
+            6,
+            r#"{"type":"pon","actor":0,"target":1,"pai":"W","consumed":["W","W"]}"#,
+            UpdateError::NotLastDiscard { pai: t!(W) },
+        ),
+        (
+            8,
+            r#"{"type":"dora","dora_marker":"1m"}"#,
+            UpdateError::DoraWithoutKan,
+        ),
+        (
+            16,
+            r#"{"type":"dora","dora_marker":"P"}"#,
+            UpdateError::TooManyCopies { tile: t!(P) },
+        ),
+    ];
+    for (idx, corrupted, expected) in corruptions {
+        let mut ps = PlayerState::new(0);
+        for &line in &lines[..idx] {
+            ps.update_checked(&parse(line)).unwrap();
+        }
+        let before = ps.to_bytes();
+        let err = ps.update_checked(&parse(corrupted)).unwrap_err();
+        assert_eq!(err.downcast_ref::<UpdateError>(), Some(&expected), "{err}");
+        assert_eq!(ps.to_bytes(), before, "the state is touched by {corrupted}");
+    }
+}
+
+#[test]
+fn py_getters() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"S","dora_marker":"9s","kyoku":4,"honba":1,"kyotaku":2,"oya":3,"scores":[30000,20000,22000,26000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","3s","4s","6s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+    "#;
+    let ps = state_from_log(0, log);
+
+    Python::with_gil(|py| {
+        let obj = PyCell::new(py, ps).unwrap();
+        let get = |name: &str| obj.getattr(name).unwrap();
+
+        assert_eq!(get("shanten").extract::<i8>().unwrap(), 0);
+        assert!(!get("at_furiten").extract::<bool>().unwrap());
+        assert_eq!(get("kyoku").extract::<u8>().unwrap(), 3);
+        assert_eq!(get("honba").extract::<u8>().unwrap(), 1);
+        assert_eq!(get("kyotaku").extract::<u8>().unwrap(), 2);
+        assert_eq!(get("tiles_left").extract::<u8>().unwrap(), 68);
+        assert_eq!(get("doras_seen").extract::<u8>().unwrap(), 0);
+        assert_eq!(
+            get("scores").extract::<[i32; 4]>().unwrap(),
+            [30000, 20000, 22000, 26000],
+        );
+        assert_eq!(
+            get("akas_in_hand").extract::<[bool; 3]>().unwrap(),
+            [false; 3],
+        );
+
+        let tehai = get("tehai").extract::<&PyArray1<u8>>().unwrap();
+        assert_eq!(tehai.to_vec().unwrap(), hand("123456m 789p 2346s").unwrap());
+
+        let mut expected = [false; 34];
+        expected[tuz!(6s)] = true;
+        let waits = get("waits").extract::<&PyArray1<bool>>().unwrap();
+        assert_eq!(waits.to_vec().unwrap(), expected);
+
+        // The arrays are copies, writing to them leaves the state unchanged.
+        waits.set_item(tuz!(1m), true).unwrap();
+        let waits = get("waits").extract::<&PyArray1<bool>>().unwrap();
+        assert_eq!(waits.to_vec().unwrap(), expected);
+        assert_eq!(obj.borrow().waits(), expected);
+    });
+}
+
+#[test]
+fn get_rank() {
+    let ps = PlayerState::new(0);
+    let rank = ps.get_rank(&[20000, 25000, 25000, 30000]);
+    assert_eq!(rank, 3);
+
+    let ps = PlayerState::new(3);
+    let rank = ps.get_rank(&[25000, 25000, 25000, 25000]);
+    assert_eq!(rank, 3);
+
+    let ps = PlayerState::new(1);
+    let rank = ps.get_rank(&[25000, 30000, 20000, 25000]);
+    assert_eq!(rank, 2);
+
+    let ps = PlayerState::new(1);
+    let rank = ps.get_rank(&[32000, 32000, 18000, 18000]);
+    assert_eq!(rank, 0);
+
+    let ps = PlayerState::new(2);
+    let rank = ps.get_rank(&[32000, 18000, 18000, 32000]);
+    assert_eq!(rank, 1);
+
+    let ps = PlayerState::new(2);
+    let rank = ps.get_rank(&[5, 2, 5, 3]);
+    assert_eq!(rank, 1);
+}
+
+#[test]
+fn kakan_from_hand() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"S","dora_marker":"6m","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[16100,36600,16800,30500],"tehais":[["5p","5s","1s","9m","9m","W","E","N","1p","F","9m","3p","6p"],["4s","9s","S","4s","1m","P","N","7s","F","2m","3s","2s","2s"],["6m","8p","8p","2p","8m","N","7p","C","1s","2p","N","9s","9p"],["2m","6s","7p","9s","2m","9s","6m","7s","8m","3m","S","5mr","C"]]}
+        {"type":"tsumo","actor":1,"pai":"S"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"1s"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"P"}
+        {"type":"dahai","actor":3,"pai":"S","tsumogiri":false}
         {"type":"pon","actor":1,"target":3,"pai":"S","consumed":["S","S"]}
         {"type":"dahai","actor":1,"pai":"P","tsumogiri":false}
         {"type":"tsumo","actor":2,"pai":"4p"}
@@ -735,6 +1890,58 @@ fn kakan_from_hand() {
     assert!(ps.last_cans.can_tsumo_agari);
 }
 
+#[test]
+fn discard_tenpai_waits() {
+    let mut ps = PlayerState {
+        tehai: hand("2345678m 234p 678p 9s").unwrap(),
+        tehai_len_div3: 4,
+        tiles_left: 60,
+        last_cans: ActionCandidate {
+            can_discard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    ps.update_shanten();
+    ps.update_shanten_discards();
+
+    let mut expected = [0; 34];
+    expected[tuz!(2m)] = 1;
+    expected[tuz!(5m)] = 1;
+    expected[tuz!(8m)] = 1;
+    expected[tuz!(9s)] = 3;
+    let waits = ps.discard_tenpai_waits();
+    assert_eq!(waits, expected);
+    assert_eq!(waits.iter().max(), Some(&waits[tuz!(9s)]));
+}
+
+#[test]
+fn shanten_after_discard() {
+    let mut ps = PlayerState {
+        tehai: hand("123456m 24p 6899s 15z").unwrap(),
+        tehai_len_div3: 4,
+        last_cans: ActionCandidate {
+            can_discard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    ps.update_shanten();
+    ps.update_shanten_discards();
+    assert_eq!(ps.shanten, 1);
+
+    let shantens = ps.shanten_after_discard();
+    assert_eq!(shantens[tuz!(E)], 1);
+    assert_eq!(shantens[tuz!(P)], 1);
+    assert_eq!(shantens[tuz!(1m)], 2);
+    assert_eq!(shantens[tuz!(9s)], 2);
+    assert_eq!(shantens[tuz!(3p)], i8::MAX);
+    for (tid, &shanten) in shantens.iter().enumerate() {
+        assert_eq!(ps.tehai[tid] == 0, shanten == i8::MAX);
+        assert_eq!(ps.keep_shanten_discards[tid], shanten == ps.shanten);
+    }
+}
+
 #[test]
 fn discard_candidates_with_unconditional_tenpai() {
     let log = r#"
@@ -1046,6 +2253,33 @@ fn discard_candidates_with_unconditional_tenpai() {
 
     let discard_candidates = ps.discard_candidates_with_unconditional_tenpai();
     assert_eq!(discard_candidates, [false; 34]);
+
+    // Every unseen tile is either in the live wall, in the dead wall but not
+    // an indicator, or concealed in the hands of others.
+    let unseen = ps.tiles_unseen();
+    let concealed_by_others = (1..4)
+        .map(|i| 13 - 3 * (ps.fuuro_overview[i].len() + ps.ankan_overview[i].len()))
+        .sum::<usize>();
+    assert_eq!(
+        unseen.iter().map(|&n| n as usize).sum::<usize>(),
+        ps.tiles_left as usize + 14 - ps.dora_indicators.len() + concealed_by_others,
+    );
+
+    // 5pr is in our chi, while 5mr and 5sr are not seen.
+    let unseen_aka = ps.tiles_unseen_aka();
+    assert_eq!(unseen_aka[tuz!(5mr)..], [1, 0, 1]);
+    assert_eq!(unseen_aka[tuz!(5m)] + 1, unseen[tuz!(5m)]);
+    assert_eq!(unseen_aka[tuz!(5p)], unseen[tuz!(5p)]);
+    assert_eq!(unseen_aka[tuz!(5s)] + 1, unseen[tuz!(5s)]);
+    assert_eq!(
+        unseen_aka.iter().map(|&n| n as usize).sum::<usize>(),
+        unseen.iter().map(|&n| n as usize).sum::<usize>(),
+    );
+
+    // A state this deep into the kyoku is at least 10x smaller in bytes than
+    // its `dump_json`, which holds far less.
+    let (bytes, json) = (ps.to_bytes().len(), ps.dump_json().len());
+    assert!(bytes * 10 <= json, "{bytes} bytes, {json} in JSON");
 }
 
 #[test]
@@ -1205,15 +2439,2830 @@ fn double_chankan_ron() {
     "#;
     let mut ps = state_from_log(2, log);
 
+    // Unknown events mixed into the log leave the state unchanged.
+    let unknown = r#"{"type":"dora_owned_change","actor":3,"doras":[0,0,0,1]}"#;
+    let log_with_unknown: Vec<_> = log
+        .trim()
+        .lines()
+        .flat_map(|l| [unknown, l.trim()])
+        .collect();
+    let ps_with_unknown = state_from_log(2, &log_with_unknown.join("\n"));
+    assert_eq!(ps_with_unknown.to_bytes(), ps.to_bytes());
+    assert_eq!(ps_with_unknown.brief_info(), ps.brief_info());
+
     let mut ps_kakan = ps.clone();
     let cans = ps_kakan
         .update_json(r#"{"type":"kakan","actor":3,"pai":"2m","consumed":["2m","2m","2m"]}"#)
         .unwrap();
-    assert!(cans.can_ron_agari);
+    assert!(cans.can_ron_agari && cans.is_chankan);
+    assert_eq!(cans.as_bitflags() >> 12 & 1, 1);
     assert_eq!(ps_kakan.agari_points(true, &[]).unwrap().ron, 1000);
+    let detail = ps_kakan.agari_detail(true, &[]).unwrap();
+    assert_eq!((detail.han, detail.fu), (1, 30));
+    assert_eq!(detail.yakus, [(Yaku::Chankan, 1)]);
+    // Chankan is the only yaku of the hand, so there is no agari without
+    // `chankan_chance`.
+    let mut no_chankan = ps_kakan.clone();
+    no_chankan.chankan_chance = None;
+    no_chankan.agari_points(true, &[]).unwrap_err();
+
+    // The dora of a previous kan revealed before the ron keeps the chankan.
+    let mut ps_kakan_dora = ps_kakan.clone();
+    let cans = ps_kakan_dora
+        .update_json(r#"{"type":"dora","dora_marker":"1s"}"#)
+        .unwrap();
+    assert!(cans.can_ron_agari && cans.is_chankan);
+    assert_eq!(ps_kakan_dora.dora_indicators.len(), 3);
+    assert_eq!(ps_kakan_dora.agari_points(true, &[]).unwrap().ron, 1000);
+    let cans = ps_kakan_dora
+        .update_json(r#"{"type":"tsumo","actor":3,"pai":"3p"}"#)
+        .unwrap();
+    assert!(!cans.can_ron_agari && !cans.is_chankan);
+    ps_kakan_dora.agari_points(true, &[]).unwrap_err();
+
+    // Player 0 also rons the kakan with 123m 789m 99m 789p WWWW, chankan
+    // chanta dora 1, 70 fu. Being first in turn order after the discarder,
+    // player 0 takes the kyotaku and the honba, which player 2 also takes
+    // under `MultiRonRule::HonbaToEach`.
+    let kakan = r#"{"type":"kakan","actor":3,"pai":"2m","consumed":["2m","2m","2m"]}"#;
+    for (multi_ron, honba_to_second) in [
+        (MultiRonRule::HonbaToFirst, 0),
+        (MultiRonRule::HonbaToEach, 600),
+    ] {
+        let rules = Rules {
+            multi_ron,
+            ..Default::default()
+        };
+        let [mut ps0, mut ps2] = [0, 2].map(|id| {
+            let mut ps = state_from_log_with_rules(id, rules, log);
+            ps.honba = 2;
+            ps.kyotaku = 1;
+            assert!(ps.update_json(kakan).unwrap().can_ron_agari);
+            ps
+        });
+        let detail = ps0.agari_detail(true, &[]).unwrap();
+        assert_eq!((detail.han, detail.fu), (4, 70));
+        assert_eq!(ps0.agari_points(true, &[]).unwrap().ron, 8000);
+
+        // Player 2 alone would take the honba and kyotaku.
+        let alone = ps2.agari_deltas(true, 1, &[]).unwrap();
+        assert_eq!(alone, [2600, -1600, 0, 0]);
+        let first = ps0.agari_deltas(true, 3, &[]).unwrap();
+        assert_eq!(first, [9600, 0, 0, -8600]);
+
+        let hora = |actor: u8, deltas| Event::Hora {
+            actor,
+            target: 3,
+            deltas: Some(deltas),
+            ura_markers: Some(vec![]),
+        };
+        let first = hora(0, first);
+        ps0.update_with_skip(&first, true);
+        ps2.update_with_skip(&first, true);
+        assert_eq!(ps2.kyotaku, 0);
+        assert!(ps2.last_cans.can_ron_agari);
+        let mut second = ps2.agari_deltas(true, 1, &[]).unwrap();
+        assert_eq!(
+            second,
+            [1000 + honba_to_second, -1000 - honba_to_second, 0, 0],
+            "{multi_ron:?}",
+        );
+        second.rotate_right(2);
+        let second = hora(2, second);
+        ps0.update_checked_with_skip(&second, true).unwrap();
+        ps2.update_checked_with_skip(&second, true).unwrap();
+
+        let scores = [
+            44400 + 9600,
+            1600,
+            25700 + 1000 + honba_to_second,
+            28300 - 8600 - 1000 - honba_to_second,
+        ];
+        assert_eq!(ps0.scores, scores);
+        assert_eq!(ps2.scores, [scores[2], scores[3], scores[0], scores[1]]);
+        assert_eq!((ps0.kyotaku, ps2.kyotaku), (0, 0));
+
+        // A third ron on the same tile is a 三家和了 instead.
+        let third = hora(1, [0; 4]);
+        let err = ps0.update_checked_with_skip(&third, true).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UpdateError>(),
+            Some(&UpdateError::TripleRon { actor: 1 }),
+        );
+    }
 
     let cans = ps
         .update_json(r#"{"type":"dahai","actor":3,"pai":"2m","tsumogiri":true}"#)
         .unwrap();
     assert!(!cans.can_ron_agari);
 }
+
+#[test]
+fn oya_tsumo_in_start_kyoku() {
+    let log_13 = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"6p"}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"9s"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"1p"}
+        {"type":"dahai","actor":3,"pai":"1p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"5pr"}
+    "#;
+    // Same as above, except that the oya's first tsumo is in `tehais`.
+    let log_14 = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m","1m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"6p"}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"9s"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"1p"}
+        {"type":"dahai","actor":3,"pai":"1p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"5pr"}
+    "#;
+
+    let mut ps = PlayerState::new(0);
+    let cans = ps
+        .update_json(log_14.trim().lines().next().unwrap())
+        .unwrap();
+    assert!(cans.can_discard);
+    assert_eq!(ps.last_self_tsumo(), Some(t!(1m)));
+
+    for player_id in 0..4 {
+        let ps_13 = state_from_log(player_id, log_13);
+        let ps_14 = state_from_log(player_id, log_14);
+        assert_eq!(ps_13.brief_info(), ps_14.brief_info());
+        assert_eq!(ps_13.encode_obs(false), ps_14.encode_obs(false));
+    }
+}
+
+#[test]
+fn oracle_state() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"6p"}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"9s"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"1p"}
+        {"type":"dahai","actor":3,"pai":"1p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"5pr"}
+        {"type":"hora","actor":0,"target":0,"ura_markers":["4p","5mr"]}
+        {"type":"end_kyoku"}
+    "#;
+    let events = parse_log(log).unwrap();
+    let names = oracle_obs_plane_names();
+    assert_eq!(names.len(), ORACLE_OBS_SHAPE.0);
+    let plane = |name: &str| names.iter().position(|&n| n == name).unwrap();
+    let filled = |obs: &Array2<f32>, name: &str, tile_id: usize| obs[[plane(name), tile_id]] > 0.;
+
+    let mut oracle = OracleState::new(1, &events, false).unwrap();
+    oracle.update(&events[0]).unwrap();
+    oracle.encode().unwrap_err();
+
+    // Right before the 5pr is drawn.
+    for event in &events[1..9] {
+        oracle.update(event).unwrap();
+    }
+    let obs = oracle.encode().unwrap();
+    assert_eq!(obs.shape(), [ORACLE_OBS_SHAPE.0, ORACLE_OBS_SHAPE.1]);
+
+    // The hands of seat 2, 3 and 0 in this order.
+    assert!(filled(&obs, "tehai_1_2", tuz!(5m)));
+    assert!(!filled(&obs, "tehai_1_3", tuz!(5m)));
+    assert!(filled(&obs, "tehai_2_2", tuz!(5p)));
+    assert!(filled(&obs, "tehai_3_1", tuz!(1m)));
+    assert!(!filled(&obs, "tehai_3_1", tuz!(S)));
+
+    // 70 tiles in the live wall, less 4 drawn.
+    assert!(filled(&obs, "yama_1", tuz!(5p)));
+    assert!(filled(&obs, "yama_1_aka", 0));
+    assert!(!filled(&obs, "yama_2_aka", 0));
+    let yama = (1..=69)
+        .filter(|i| obs.row(plane(&format!("yama_{i}"))).sum() > 0.)
+        .count();
+    assert_eq!(yama, 66);
+    assert!(filled(&obs, "dora_indicator_1", tuz!(5s)));
+    assert!(filled(&obs, "ura_indicator_1", tuz!(4p)));
+    assert!(filled(&obs, "ura_indicator_2", tuz!(5m)));
+    assert!(filled(&obs, "ura_indicator_2_aka", 0));
+
+    oracle.update(&events[9]).unwrap();
+    let obs = oracle.encode().unwrap();
+    assert!(filled(&obs, "tehai_3_1", tuz!(5p)));
+    assert!(filled(&obs, "aka_in_hand_3_p", 0));
+    assert!(!filled(&obs, "aka_in_hand_3_m", 0));
+
+    // A log from a single player's perspective is an error.
+    let mut masked = events.clone();
+    masked[1] = Event::Tsumo {
+        actor: 0,
+        pai: t!(?),
+    };
+    OracleState::new(0, &masked, false).unwrap_err();
+    oracle.update(&masked[1]).unwrap_err();
+
+    // So is a kyoku not ended.
+    OracleState::new(0, &events[..events.len() - 1], false).unwrap_err();
+}
+
+#[test]
+fn bytes_format_version() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"6p"}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":true}
+    "#;
+    let ps = state_from_log(1, log);
+    let mut bytes = ps.to_bytes();
+    PlayerState::from_bytes(&bytes).unwrap();
+
+    PlayerState::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+    bytes[0] = bytes[0].wrapping_add(1);
+    let err = PlayerState::from_bytes(&bytes).unwrap_err();
+    assert!(err.to_string().contains("version"));
+}
+
+#[test]
+fn bytes_last_event_and_lang() {
+    let lines = [
+        r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#,
+        r#"{"type":"tsumo","actor":0,"pai":"1m"}"#,
+        r#"{"type":"dora_owned_change","actor":3,"doras":[0,0,0,1]}"#,
+    ];
+
+    // `last_event` is kept, even an unknown one.
+    let mut ps = PlayerState::new(0);
+    let restored = PlayerState::from_bytes(&ps.to_bytes()).unwrap();
+    assert_eq!(restored.last_event, None);
+    for line in lines {
+        let before = ps.clone();
+        ps.update_json(line).unwrap();
+        assert!(ps
+            .diff(&before)
+            .iter()
+            .any(|d| d.starts_with("last_event:")));
+        let restored = PlayerState::from_bytes(&ps.to_bytes()).unwrap();
+        assert_eq!(restored.last_event, ps.last_event);
+        assert!(restored.diff(&ps).is_empty());
+    }
+    assert!(matches!(ps.last_event, Some(Event::Unknown(_))));
+
+    // `lang` is not, and is not compared by `diff` either.
+    ps.set_lang("ja".parse().unwrap());
+    let mut restored = PlayerState::from_bytes(&ps.to_bytes()).unwrap();
+    assert!(restored.diff(&ps).is_empty());
+    assert_ne!(restored.brief_info(), ps.brief_info());
+    restored.set_lang("ja".parse().unwrap());
+    assert_eq!(restored.brief_info(), ps.brief_info());
+}
+
+#[test]
+fn encode_obs_no_aka() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5mr","6m","7m","8m","9m","2p","3p","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = state_from_log(0, log);
+    assert_eq!(ps.doras_owned[0], 2);
+
+    let (obs, mask) = ps.encode_obs(false);
+    let (obs_no_aka, mask_no_aka) = ps.encode_obs_no_aka(false);
+    assert_eq!(mask, mask_no_aka);
+
+    let names = obs_plane_names();
+    for (i, name) in names.iter().enumerate() {
+        let plane = obs.row(i);
+        let plane_no_aka = obs_no_aka.row(i);
+        match *name {
+            // The red 5m is the only aka seen so far, which is also counted
+            // in doras_unseen.
+            "aka_in_hand_m" | "doras_owned_0_2" | "doras_unseen_4" | "doras_unseen_5" => {
+                assert!(plane.iter().all(|&v| v > 0.), "{name}");
+                assert!(plane_no_aka.iter().all(|&v| v == 0.), "{name}");
+            }
+            _ => assert_eq!(plane, plane_no_aka, "{name}"),
+        }
+    }
+}
+
+#[test]
+fn call_locking() {
+    let mut ps = PlayerState {
+        tehai: hand("46m 12345z").unwrap(),
+        tiles_seen: hand("456m 12345z").unwrap(),
+        tehai_len_div3: 2,
+        tiles_left: 40,
+        ..Default::default()
+    };
+    assert_eq!(ps.max_additional_calls(), 2);
+
+    // Chi with 46m leaves five isolated honors, which needs three more tiles
+    // to form a set and a pair.
+    assert!(!ps.is_call_locking(&t![4m, 6m]).unwrap());
+    ps.tiles_left = 8;
+    assert!(!ps.is_call_locking(&t![4m, 6m]).unwrap());
+    ps.tiles_left = 4;
+    assert!(ps.is_call_locking(&t![4m, 6m]).unwrap());
+    ps.is_call_locking(&t![5m, 6m]).unwrap_err();
+
+    // Pon leaving a single tile, whose all copies are visible.
+    let mut ps = PlayerState {
+        tehai: hand("111m 2z").unwrap(),
+        tiles_seen: hand("1111m 2z").unwrap(),
+        tehai_len_div3: 1,
+        tiles_left: 0,
+        ..Default::default()
+    };
+    assert!(!ps.is_call_locking(&t![1m, 1m]).unwrap());
+    ps.tiles_seen[tuz!(S)] = 4;
+    assert!(ps.is_call_locking(&t![1m, 1m]).unwrap());
+
+    ps.tehai_len_div3 = 0;
+    assert_eq!(ps.max_additional_calls(), 0);
+    assert!(ps.is_call_locking(&[]).unwrap());
+}
+
+#[test]
+fn encode_trajectory() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["P","P","1m","2m","3m","4p","5pr","6p","7s","8s","9s","8m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"E"}
+        {"type":"dahai","actor":1,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":1,"target":0,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":1,"pai":"1m","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"7m","tsumogiri":true}
+        {"type":"chi","actor":1,"target":0,"pai":"7m","consumed":["8m","9m"]}
+        {"type":"dahai","actor":1,"pai":"3m","tsumogiri":false}
+    "#;
+    let events: Vec<_> = log.trim().lines().map(|l| l.trim().to_owned()).collect();
+
+    let mut ps = PlayerState::new(1);
+    let mut expected = vec![];
+    for line in &events {
+        if ps.update_json(line).unwrap().can_act() {
+            let (obs, _) = ps.encode_obs(false);
+            expected.push(obs.iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+        }
+    }
+    // The tsumo, the pon chance, the pon itself, the chi chance and the chi.
+    assert_eq!(expected.len(), 5);
+
+    let mut batch_ps = PlayerState::new(1);
+    let (obs, shape) = batch_ps.encode_trajectory(events).unwrap();
+    assert_eq!(shape, [expected.len(), OBS_SHAPE.0, OBS_SHAPE.1]);
+    let actual: Vec<_> = obs
+        .iter()
+        .map(|o| o.iter().map(|v| v.to_bits()).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(actual, expected);
+    assert_eq!(batch_ps.to_bytes(), ps.to_bytes());
+
+    let mut ps = PlayerState::new(1);
+    let err = ps
+        .encode_trajectory(vec![r#"{"type":"tsumo"}"#.to_owned()])
+        .unwrap_err();
+    assert!(err.to_string().contains("failed to update"));
+}
+
+#[test]
+fn encode_obs_raw() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5mr","6m","7m","8m","9m","2p","3p","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = state_from_log(0, log);
+    let (obs, shape) = ps.encode_obs_raw(false);
+    assert_eq!(shape, [OBS_SHAPE.0, OBS_SHAPE.1]);
+    assert_eq!(obs.len(), OBS_SHAPE.0 * OBS_SHAPE.1);
+
+    let (expected, _) = ps.encode_obs(false);
+    assert_eq!(obs, expected.iter().copied().collect::<Vec<_>>());
+
+    // The first plane of tehai
+    let plane = &obs[..shape[1]];
+    assert!(plane[tuz!(E)] > 0.);
+    assert!(plane[tuz!(N)] > 0.);
+    assert!(plane[tuz!(5m)] > 0.);
+    assert_eq!(plane[tuz!(S)], 0.);
+    // The second plane of tehai
+    let plane = &obs[shape[1]..shape[1] * 2];
+    assert!(plane[tuz!(E)] > 0.);
+    assert_eq!(plane[tuz!(N)], 0.);
+}
+
+#[test]
+fn aka_rule() {
+    fn log_with_fives(fives: [&str; 2]) -> String {
+        let [a, b] = fives;
+        format!(
+            r#"
+            {{"type":"start_kyoku","bakaze":"E","dora_marker":"E","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["2m","3m","4m","6m","7m","8m","3s","4s","5s","{a}","{b}","6s","7s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}}
+            {{"type":"tsumo","actor":0,"pai":"?"}}
+            {{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}}
+            {{"type":"tsumo","actor":1,"pai":"N"}}
+            {{"type":"dahai","actor":1,"pai":"N","tsumogiri":true}}
+            {{"type":"tsumo","actor":2,"pai":"?"}}
+            {{"type":"dahai","actor":2,"pai":"W","tsumogiri":true}}
+            {{"type":"tsumo","actor":3,"pai":"?"}}
+            {{"type":"dahai","actor":3,"pai":"W","tsumogiri":true}}
+            {{"type":"tsumo","actor":0,"pai":"?"}}
+            {{"type":"dahai","actor":0,"pai":"C","tsumogiri":true}}
+            {{"type":"tsumo","actor":1,"pai":"8s"}}
+            "#,
+        )
+    }
+    fn state_with_rule(aka: AkaRule, log: &str) -> anyhow::Result<PlayerState> {
+        let mut ps = PlayerState::with_rules(
+            1,
+            Rules {
+                aka,
+                ..Default::default()
+            },
+        );
+        for line in log.trim().lines() {
+            ps.update_json(line)?;
+        }
+        Ok(ps)
+    }
+
+    // 234m 678m 345s 55p 678s, tsumo pinfu tanyao 20 fu
+    let cases = [
+        (AkaRule::None, ["5p", "5p"], 0, 700 * 2 + 1300),
+        (AkaRule::Three, ["5pr", "5p"], 1, 1300 * 2 + 2600),
+        (
+            AkaRule::FourWithDoublePin,
+            ["5pr", "5pr"],
+            2,
+            2000 * 2 + 4000,
+        ),
+    ];
+    for (aka, fives, doras, points) in cases {
+        let ps = state_with_rule(aka, &log_with_fives(fives)).unwrap();
+        assert_eq!(ps.doras_owned[0], doras);
+        assert_eq!(ps.akas_in_hand()[1], doras > 0);
+        let discard_candidates = ps.discard_candidates_aka();
+        assert_eq!(discard_candidates[tuz!(5pr)], doras > 0);
+        assert_eq!(discard_candidates[tuz!(5p)], doras < 2);
+        let point = ps.agari_points(false, &[]).unwrap();
+        assert_eq!(point.tsumo_total(false), points);
+    }
+
+    state_with_rule(AkaRule::None, &log_with_fives(["5pr", "5p"])).unwrap_err();
+}
+
+#[test]
+fn discard_unrecorded_aka() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5m","6m","7m","1p","2p","3p","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let mut ps = state_from_log(0, log);
+    let doras_owned = ps.doras_owned[0];
+
+    // A broken log discards a 5mr that was dealt as a normal 5m. The aka
+    // counts saturate instead of underflowing.
+    ps.update_json(r#"{"type":"dahai","actor":0,"pai":"5mr","tsumogiri":false}"#)
+        .unwrap();
+    assert_eq!(ps.akas_in_hand, [0, 0, 0]);
+    assert_eq!(ps.doras_owned[0], doras_owned);
+    assert_eq!(ps.tehai[tuz!(5m)], 0);
+}
+
+#[test]
+fn tiles_unseen_aka_inconsistent() {
+    // All four 5m are seen, but none of them as the 5mr.
+    let mut ps = PlayerState::new(0);
+    ps.tiles_seen[tuz!(5m)] = 4;
+    let unseen = ps.tiles_unseen_aka();
+    assert_eq!(unseen[tuz!(5m)], 0);
+    assert_eq!(unseen[tuz!(5mr)], 1);
+}
+
+#[test]
+fn action_candidate_bitflags() {
+    let cans = ActionCandidate {
+        can_chi_low: true,
+        can_pon: true,
+        can_riichi: true,
+        target_actor: 3,
+        ..Default::default()
+    };
+    let flags = cans.as_bitflags();
+    assert_eq!(flags, 1 << 1 | 1 << 4 | 1 << 8 | 3 << 16);
+
+    let restored = ActionCandidate::from_bitflags(flags);
+    assert!(restored.can_chi_low && restored.can_pon && restored.can_riichi);
+    assert_eq!(restored.as_bitflags(), flags);
+    assert_eq!(format!("{restored:?}"), format!("{cans:?}"));
+
+    assert_eq!(ActionCandidate::default().as_bitflags(), 0);
+    assert_eq!(
+        ActionCandidate::from_bitflags(u32::MAX).as_bitflags(),
+        0xff_3fff
+    );
+}
+
+#[test]
+fn safety_against() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["8s","8s","8s","8s","1m","2m","3m","5p","6p","7p","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9m"}
+        {"type":"dahai","actor":0,"pai":"9m","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"1p","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"2s","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"4m","tsumogiri":false}
+        {"type":"reach_accepted","actor":1}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"7s","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"E","tsumogiri":false}
+    "#;
+    let ps = state_from_log(0, log);
+
+    let info = ps.safety_against(1);
+    let genbutsu = t![4m, 1p, 7s, E];
+    for tid in 0..34 {
+        assert_eq!(
+            info.genbutsu[tid],
+            genbutsu.contains(&must_tile!(tid)),
+            "{}",
+            must_tile!(tid),
+        );
+    }
+    // Discarded before the riichi by others
+    assert!(!info.genbutsu[tuz!(9m)]);
+    assert!(!info.genbutsu[tuz!(2s)]);
+
+    // 4m is genbutsu, while 4p needs both 1p and 7p.
+    let suji = t![1m, 7m];
+    for tid in 0..34 {
+        assert_eq!(info.suji[tid], suji.contains(&must_tile!(tid)));
+    }
+
+    // All four 8s are in my hand.
+    let kabe = t![9s];
+    for tid in 0..34 {
+        assert_eq!(info.kabe[tid], kabe.contains(&must_tile!(tid)));
+    }
+
+    // Without riichi, only the kawa counts.
+    let info = ps.safety_against(2);
+    assert!(info.genbutsu[tuz!(2s)]);
+    assert!(info.genbutsu[tuz!(7s)]);
+    assert!(!info.genbutsu[tuz!(E)]);
+    assert_eq!(info.kabe, ps.safety_against(1).kabe);
+}
+
+#[test]
+fn yakuman_threat_tiles() {
+    let meld = |tiles: &[Tile]| tiles.iter().copied().collect::<ArrayVec<[Tile; 4]>>();
+    let mut ps = PlayerState::new(0);
+    assert!(ps.yakuman_threat_tiles().is_empty());
+
+    // Shimocha has ponned haku and kakanned hatsu, besides a chi.
+    ps.fuuro_overview[1].push(meld(&t![1m, 2m, 3m]));
+    ps.fuuro_overview[1].push(meld(&t![P, P, P]));
+    assert!(ps.yakuman_threat_tiles().is_empty());
+    ps.fuuro_overview[1].push(meld(&t![F, F, F, F]));
+    ps.tiles_seen[tuz!(P)] = 3;
+    ps.tiles_seen[tuz!(F)] = 4;
+
+    let threats = ps.yakuman_threat_tiles();
+    assert_eq!(threats.len(), 1);
+    assert_eq!(threats[0].rel_player, 1);
+    for tid in 0..34 {
+        assert_eq!(threats[0].tiles[tid], tid == tuz!(C));
+    }
+
+    // Chun is critical against shimocha only, even as genbutsu.
+    ps.kawa_overview[1].push(t!(C));
+    ps.tiles_seen[tuz!(C)] = 1;
+    assert_eq!(ps.danger_scores_against(1)[tuz!(C)], 10);
+    assert_eq!(ps.danger_scores_against(2)[tuz!(C)], 3);
+
+    // With two chuns visible, daisangen is no longer possible.
+    ps.tiles_seen[tuz!(C)] = 2;
+    assert!(ps.yakuman_threat_tiles().is_empty());
+    assert_eq!(ps.danger_scores_against(1)[tuz!(C)], 0);
+
+    // Toimen has ponned ton and nan and ankanned sha.
+    ps.fuuro_overview[2].push(meld(&t![E, E, E]));
+    ps.fuuro_overview[2].push(meld(&t![S, S, S]));
+    assert!(ps.yakuman_threat_tiles().is_empty());
+    ps.ankan_overview[2].push(t!(W));
+    ps.tiles_seen[tuz!(N)] = 2;
+
+    let threats = ps.yakuman_threat_tiles();
+    assert_eq!(threats.len(), 1);
+    assert_eq!(threats[0].rel_player, 2);
+    for tid in 0..34 {
+        assert_eq!(threats[0].tiles[tid], tid == tuz!(N));
+    }
+    assert_eq!(ps.danger_scores_against(2)[tuz!(N)], 10);
+
+    // Shousuushii needs a pair of pei at least.
+    ps.tiles_seen[tuz!(N)] = 3;
+    assert!(ps.yakuman_threat_tiles().is_empty());
+}
+
+#[test]
+fn from_log_str() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"6p"}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"9s"}
+    "#;
+    let events = log
+        .trim()
+        .lines()
+        .map(|l| json::from_str(l).unwrap())
+        .collect::<Vec<Event>>();
+    for player_id in 0..4 {
+        let expected = state_from_log(player_id, log);
+        let (ps, cans) = PlayerState::from_log_str(player_id, log).unwrap();
+        assert_eq!(ps.brief_info(), expected.brief_info());
+        assert_eq!(cans.as_bitflags(), expected.last_cans.as_bitflags());
+        let (ps, cans) = PlayerState::from_events(player_id, &events).unwrap();
+        assert_eq!(ps.brief_info(), expected.brief_info());
+        assert_eq!(cans.as_bitflags(), expected.last_cans.as_bitflags());
+    }
+    let (_, cans) = PlayerState::from_log_str(2, log).unwrap();
+    assert!(cans.can_discard);
+
+    // Player 0 discards a tile that is not in their hand.
+    let log = log.trim().replace(r#""pai":"S""#, r#""pai":"E""#);
+    let err = PlayerState::from_log_str(0, &log).unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{err}");
+    assert!(err.to_string().contains(r#""pai":"E""#), "{err}");
+    assert!(format!("{err:?}").contains("E is not in hand"), "{err:?}");
+    let events = log
+        .lines()
+        .map(|l| json::from_str(l).unwrap())
+        .collect::<Vec<Event>>();
+    let err = PlayerState::from_events(0, &events).unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{err}");
+    // Only the actions of the player itself are checked.
+    PlayerState::from_log_str(1, &log).unwrap();
+}
+
+#[test]
+fn replay_all_seats() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"],"id":0}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+        {"type":"dahai","actor":0,"pai":"C","tsumogiri":false}
+        {"type":"pon","actor":1,"target":0,"pai":"C","consumed":["C","C"]}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"9s"}
+        {"type":"dahai","actor":2,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"E"}
+    "#;
+    let events = log
+        .trim()
+        .lines()
+        .map(|l| json::from_str(l).unwrap())
+        .collect::<Vec<Event>>();
+
+    let states = PlayerState::replay_all_seats(&events);
+    for (player_id, ps) in (0..4).zip(&states) {
+        let mut expected = PlayerState::new(player_id);
+        for ev in &events {
+            expected.update(ev);
+        }
+        assert_eq!(ps.player_id(), player_id);
+        assert_eq!(ps.to_bytes(), expected.to_bytes());
+        assert_eq!(ps.brief_info(), expected.brief_info());
+    }
+    assert!(states[3].last_cans.can_discard);
+}
+
+#[test]
+fn seat_winds_with_renchan() {
+    let start_kyoku = |kyoku: u8, honba: u8, oya: u8| {
+        format!(
+            r#"{{"type":"start_kyoku","bakaze":"E","dora_marker":"F","kyoku":{kyoku},"honba":{honba},"kyotaku":0,"oya":{oya},"scores":[25000,25000,25000,25000],"tehais":[["E","E","E","1m","2m","3m","4p","5p","6p","7s","8s","9s","5s"],["2m","3m","4m","6p","7p","8p","2s","3s","4s","N","N","W","5s"],["5m","6m","7m","1p","2p","3p","6s","6s","S","S","P","P","F"],["7m","8m","9m","9p","9p","1s","1s","2s","3s","4s","C","C","F"]]}}"#,
+        )
+    };
+    let ryukyoku = r#"
+        {"type":"ryukyoku","deltas":[0,0,0,0]}
+        {"type":"end_kyoku"}
+    "#;
+
+    // Seat 0 keeps the dealership through E1-0 to E1-3, passes it to seat 1
+    // in E2, and gets it back in E5, a numbering some logs use instead of
+    // starting the south round.
+    let mut log = String::new();
+    for honba in 0..4 {
+        log += &start_kyoku(1, honba, 0);
+        log += ryukyoku;
+    }
+    let log_e1 = log.clone();
+    log += &start_kyoku(2, 0, 1);
+    log += ryukyoku;
+    let log_e2 = log.clone();
+    log += &start_kyoku(5, 5, 0);
+    log += r#"
+        {"type":"tsumo","actor":0,"pai":"9m"}
+        {"type":"dahai","actor":0,"pai":"9m","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"1m"}
+        {"type":"dahai","actor":1,"pai":"5s","tsumogiri":false}
+    "#;
+
+    let lines = |log: &str| {
+        log.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    for player_id in 0..4 {
+        let ps = state_from_log(player_id, &lines(&log_e1));
+        assert_eq!(ps.seat_winds(), [t!(E), t!(S), t!(W), t!(N)]);
+        assert_eq!(ps.jikaze, ps.seat_winds()[player_id as usize]);
+
+        let ps = state_from_log(player_id, &lines(&log_e2));
+        assert_eq!(ps.seat_winds(), [t!(N), t!(E), t!(S), t!(W)]);
+        assert_eq!(ps.jikaze, ps.seat_winds()[player_id as usize]);
+
+        let ps = state_from_log(player_id, &lines(&log));
+        assert_eq!(ps.seat_winds(), [t!(E), t!(S), t!(W), t!(N)]);
+        assert_eq!(ps.jikaze, ps.seat_winds()[player_id as usize]);
+        assert!(ps.brief_info().contains("kyoku: E5-5"));
+        assert!(!ps.is_all_last);
+    }
+
+    // Double east for the oya: 2 han 40 fu.
+    let ps = state_from_log(0, &lines(&log));
+    assert!(ps.last_cans.can_ron_agari);
+    let detail = ps.agari_detail(true, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Bakaze, 1), (Yaku::Jikaze, 1)]);
+    assert_eq!((detail.han, detail.fu), (2, 40));
+    assert_eq!(detail.point.ron, 3900);
+}
+
+#[test]
+fn nukidora() {
+    // A sanma log, the fourth seat never plays.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"E","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[35000,35000,35000,0],"tehais":[["N","1m","9m","1p","2p","3p","4p","5p","6p","7s","8s","9s","E"],["N","N","1p","2p","3p","4p","5p","6p","1s","2s","3s","P","P"],["1m","1m","9m","9m","7p","8p","9p","4s","5s","6s","C","C","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"nukidora","actor":0,"pai":"N"}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"nukidora","actor":0,"pai":"N"}
+        {"type":"tsumo","actor":0,"pai":"F"}
+        {"type":"dahai","actor":0,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"S"}
+        {"type":"nukidora","actor":1,"pai":"N"}
+        {"type":"tsumo","actor":1,"pai":"3s"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":false}
+    "#;
+    let rules = PlayerState::new_sanma(0).rules();
+    let ps = state_from_log_with_rules(0, rules, log);
+    assert_eq!(ps.nukidoras, [2, 1, 0, 0]);
+    assert_eq!(ps.tehai[tuz!(N)], 0);
+    assert_eq!(ps.tiles_seen[tuz!(N)], 3);
+    assert!(ps.is_menzen);
+    assert_eq!(ps.doras_owned, [2, 1, 0, 0]);
+    PlayerState::from_log_str_with_rules(0, rules, log).unwrap();
+
+    // There is no nukidora in yonma.
+    let err = PlayerState::from_log_str(0, log).unwrap_err();
+    assert!(err.to_string().contains("line 4"), "{err}");
+
+    let ps = state_from_log_with_rules(1, rules, log);
+    assert_eq!(ps.nukidoras, [1, 0, 0, 2]);
+    assert_eq!(ps.tehai[tuz!(N)], 1);
+    assert!(ps.is_menzen);
+    assert_eq!(ps.doras_owned, [1, 0, 0, 2]);
+
+    // Only north tiles can be extracted.
+    let bad_log = log.replacen(
+        r#""nukidora","actor":0,"pai":"N""#,
+        r#""nukidora","actor":0,"pai":"E""#,
+        1,
+    );
+    let err = PlayerState::from_log_str_with_rules(0, rules, &bad_log).unwrap_err();
+    assert!(err.to_string().contains("line 4"), "{err}");
+
+    // North becomes a dora, so every nukidora counts twice.
+    let log = format!("{}\n{}", log.trim(), r#"{"type":"dora","dora_marker":"W"}"#);
+    let ps = state_from_log_with_rules(0, rules, &log);
+    assert_eq!(ps.doras_owned, [4, 2, 0, 0]);
+    let ps = state_from_log_with_rules(1, rules, &log);
+    assert_eq!(ps.doras_owned, [3, 0, 0, 4]);
+}
+
+#[test]
+fn sanma() {
+    // Seat 3 is empty. Player 0 extracts two N and wins by rinshan.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"S","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[45000,-1000,61000,0],"tehais":[["1p","2p","3p","4p","5p","6p","7p","8p","9p","2s","3s","E","E"],["1m","1m","9m","9m","1s","5s","6s","7s","W","W","P","P","C"],["4s","6s","7p","7p","8p","8p","S","S","W","F","F","9s","9s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"nukidora","actor":0,"pai":"N"}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"C","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"5s"}
+        {"type":"dahai","actor":1,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"N"}
+        {"type":"nukidora","actor":2,"pai":"N"}
+        {"type":"tsumo","actor":2,"pai":"1m"}
+        {"type":"dahai","actor":2,"pai":"1m","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"nukidora","actor":0,"pai":"N"}
+        {"type":"tsumo","actor":0,"pai":"4s"}
+    "#;
+    let hora = r#"{"type":"hora","actor":0,"target":0,"deltas":[12000,-6000,-6000,0]}"#;
+    let rules = PlayerState::new_sanma(0).rules();
+    let lines: Vec<_> = log.trim().lines().collect();
+
+    // The turn goes from seat 2 back to seat 0.
+    for player_id in 0..3 {
+        let mut ps = PlayerState::new_sanma(player_id);
+        for &line in &lines {
+            for ev in Event::parse_line(line).unwrap() {
+                ps.update_checked(&ev).unwrap();
+            }
+        }
+    }
+    PlayerState::from_log_str_with_rules(0, rules, log).unwrap();
+
+    // Seat 2 could chi the 5s of seat 1 in yonma, but not in sanma, while
+    // seat 1 can still pon the 1m of seat 2.
+    let partial = lines[..7].join("\n");
+    assert!(state_from_log(2, &partial).last_cans.can_chi_mid);
+    let ps = state_from_log_with_rules(2, rules, &partial);
+    assert!(!ps.last_cans.can_act());
+    let ps = state_from_log_with_rules(1, rules, &lines[..11].join("\n"));
+    assert!(ps.last_cans.can_pon);
+
+    let mut ps = PlayerState::new_sanma(0);
+    let mut can_nukidora = vec![];
+    for (i, &line) in lines.iter().enumerate() {
+        let cans = ps.update_json(line).unwrap();
+        can_nukidora.push(cans.can_nukidora);
+        if cans.can_nukidora {
+            assert!(ps.legal_reactions().contains(&Reaction::Nukidora));
+            ps.validate_reaction(&ps.reaction_to_event(Reaction::Nukidora))
+                .unwrap();
+            // Nukidora is the last action of the sanma action space.
+            let mask = ps.legal_action_mask(false);
+            assert_eq!(mask.len(), SANMA_ACTION_SPACE);
+            assert!(mask[SANMA_ACTION_SPACE - 1]);
+            assert_eq!(
+                ps.action_to_event(SANMA_ACTION_SPACE - 1, false, true)
+                    .unwrap(),
+                Event::Nukidora {
+                    actor: 0,
+                    pai: t!(N)
+                },
+            );
+            assert_actions_round_trip(&ps, false);
+        }
+        if i == 4 {
+            // Seat 1 and 2 draw, the empty seat does not.
+            assert_eq!(ps.seats_until_my_turn(), 2);
+            assert_eq!(ps.draws_before_my_next_draw(3), 0);
+        }
+    }
+    let expected = [
+        false, true, false, false, false, false, false, false, false, false, false, true, false,
+        false,
+    ];
+    assert_eq!(can_nukidora, expected);
+    assert_eq!(ps.tiles_left, 55 - 7);
+    assert_eq!(ps.nukidoras, [2, 0, 1, 0]);
+    assert_eq!(ps.seat_winds(), t![E, S, W, N]);
+    assert_eq!(ps.rank, 1);
+
+    // 2m to 8m are not in the tile set, and so neither is 5mr, while the
+    // other akas are.
+    let unseen = ps.tiles_unseen();
+    assert!(unseen[tuz!(2m)..=tuz!(8m)].iter().all(|&n| n == 0));
+    assert_eq!(unseen[tuz!(9m)], 4);
+    assert_eq!(ps.unseen_count(t!(5m)), 0);
+    let unseen_aka = ps.tiles_unseen_aka();
+    assert_eq!(unseen_aka[tuz!(5mr)], 0);
+    assert_eq!(unseen_aka[tuz!(5pr)], 1);
+    assert_eq!(unseen_aka[tuz!(5sr)], 1);
+    let aka_log = log.replacen(r#"["4s","6s""#, r#"["5mr","6s""#, 1);
+    let err = PlayerState::from_log_str_with_rules(0, rules, &aka_log).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{err}");
+
+    // Kita counts as a dora of its own.
+    assert!(ps.last_cans.can_tsumo_agari);
+    let detail = ps.agari_detail(false, &[]).unwrap();
+    assert_eq!(
+        detail.yakus,
+        [
+            (Yaku::MenzenTsumo, 1),
+            (Yaku::Rinshan, 1),
+            (Yaku::Ittsuu, 2),
+            (Yaku::NukiDora, 2),
+        ],
+    );
+    assert_eq!(detail.han, 6);
+    // Oya haneman, 6000 all, without the share of the empty seat.
+    assert_eq!(
+        ps.agari_deltas(false, 0, &[]).unwrap(),
+        [12000, -6000, -6000, 0],
+    );
+
+    // The obs of yonma is followed by the nukidora planes, and the empty seat
+    // is all zero.
+    let names = sanma_obs_plane_names();
+    assert_eq!(names.len(), SANMA_OBS_CHANNELS);
+    assert_eq!(names[..OBS_CHANNELS], obs_plane_names());
+    let (obs, mask) = ps.encode_obs(false);
+    assert_eq!(obs.shape(), [SANMA_OBS_CHANNELS, 34]);
+    assert_eq!(mask.len(), SANMA_ACTION_SPACE);
+    let plane = |name: &str| obs.row(names.iter().position(|&n| n == name).unwrap());
+    assert!(plane("nukidora_0_2").iter().all(|&v| v == 1.));
+    assert!(plane("nukidora_0_3").iter().all(|&v| v == 0.));
+    assert!(plane("nukidora_2_1").iter().all(|&v| v == 1.));
+    assert!(plane("can_nukidora").iter().all(|&v| v == 0.));
+    assert!(plane("kawa_overview_1_1").iter().any(|&v| v == 1.));
+    assert!(plane("kawa_overview_3_1").iter().all(|&v| v == 0.));
+
+    // Seat 1 is last with a negative score, as the empty seat does not rank.
+    let mut others = [1, 2].map(|id| state_from_log_with_rules(id, rules, log));
+    assert_eq!([others[0].rank, others[1].rank], [2, 0]);
+
+    ps.update_json(hora).unwrap();
+    assert_eq!(ps.scores, [57000, -7000, 55000, 0]);
+    assert_eq!(ps.rank, 0);
+    for ps in &mut others {
+        ps.update_json(hora).unwrap();
+    }
+    assert_eq!([others[0].rank, others[1].rank], [2, 1]);
+    assert_bytes_round_trip(&ps);
+}
+
+#[test]
+fn brief_info_lang() {
+    let log = r#"
+        {"type":"start_game","names":["0","1","2","3"]}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"F","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","5mr","5m","9m","1p","9p","1s","9s","E","S","W","N","P"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let mut ps = state_from_log(0, log);
+    let en = ps.brief_info();
+    assert!(en.contains("tehai: 1059m 19p 19s 12345z"));
+
+    ps.set_lang("ja".parse().unwrap());
+    let ja = ps.brief_info();
+    assert!(ja.contains("kyoku: 東1-0"));
+    assert!(ja.contains("jikaze: 東"));
+    assert!(ja.contains("tehai: 一萬 赤五萬 五萬 九萬 一筒 九筒 一索 九索 東 南 西 北 白"));
+    assert!(ja.contains(r#"dora indicators: ["發"]"#));
+
+    ps.set_lang(Default::default());
+    assert_eq!(ps.brief_info(), en);
+}
+
+#[test]
+fn pao_daisangen() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["P","P","F","F","C","C","1m","2m","3m","4p","5p","9s","9s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":false}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"4p","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"F","tsumogiri":false}
+        {"type":"pon","actor":0,"target":1,"pai":"F","consumed":["F","F"]}
+        {"type":"dahai","actor":0,"pai":"5p","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"C","tsumogiri":false}
+    "#;
+    let mut ps = state_from_log(0, log);
+    assert_eq!(ps.pao_target(0), None);
+
+    let cans = ps
+        .update_json(r#"{"type":"pon","actor":0,"target":2,"pai":"C","consumed":["C","C"]}"#)
+        .unwrap();
+    assert!(cans.can_discard);
+    // Player 2 fed the third dragon.
+    assert_eq!(ps.pao_target(0), Some(2));
+    assert!((1..4).all(|p| ps.pao_target(p).is_none()));
+    assert_eq!(ps.pao_liable_seat(), Some(2));
+    assert_bytes_round_trip(&ps);
+
+    let log = r#"
+        {"type":"dahai","actor":0,"pai":"9s","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"2s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"3s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4s","tsumogiri":true}
+    "#;
+    for line in log.trim().lines() {
+        ps.update_json(line).unwrap();
+    }
+    let cans = ps
+        .update_json(r#"{"type":"tsumo","actor":0,"pai":"9s"}"#)
+        .unwrap();
+    assert!(cans.can_tsumo_agari);
+    let point = ps.agari_points(false, &[]).unwrap();
+    assert_eq!(point.ron, 48000);
+    assert_eq!(point.tsumo_total(true), 48000);
+    assert_eq!(ps.pao_liable_seat(), Some(2));
+    // Player 2 alone pays all of it on tsumo, the same as the ron points.
+    assert_eq!(
+        ps.agari_deltas(false, 0, &[]).unwrap(),
+        [48000, 0, -48000, 0],
+    );
+
+    // The liability is cleared in the next kyoku.
+    ps.update_json(r#"{"type":"end_kyoku"}"#).unwrap();
+    ps.update_json(r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"1m","kyoku":1,"honba":1,"kyotaku":0,"oya":0,"scores":[73000,25000,2000,0],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","4p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#).unwrap();
+    assert_eq!(ps.pao_target(0), None);
+    assert_eq!(ps.pao_liable_seat(), None);
+}
+
+#[test]
+fn pao_daiminkan_rinshan() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","4s","4s","4s","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"1p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"4s","tsumogiri":false}
+        {"type":"daiminkan","actor":0,"target":2,"pai":"4s","consumed":["4s","4s","4s"]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+
+    // 123m 456m 789p E tanki with a daiminkan of 4s, rinshan kaihou only, 1
+    // han 40 fu as the dealer.
+    let ps = state_from_log(0, log);
+    assert!(ps.last_cans.can_tsumo_agari);
+    assert_eq!(ps.pao_liable_seat(), None);
+    assert_eq!(
+        ps.agari_deltas(false, 0, &[]).unwrap(),
+        [2100, -700, -700, -700],
+    );
+
+    // Player 2 fed the kan and pays the ron points alone.
+    let rules = Rules {
+        daiminkan_pao: DaiminkanPaoRule::RinshanKaihou,
+        ..Default::default()
+    };
+    let mut ps = state_from_log_with_rules(0, rules, log);
+    assert_eq!(ps.pao_liable_seat(), Some(2));
+    assert_eq!(ps.pao_target(0), None);
+    assert_eq!(ps.agari_deltas(false, 0, &[]).unwrap(), [2000, 0, -2000, 0]);
+
+    // The liability ends with the discard after the rinshan draw.
+    ps.update_json(r#"{"type":"dahai","actor":0,"pai":"E","tsumogiri":true}"#)
+        .unwrap();
+    assert_eq!(ps.pao_liable_seat(), None);
+}
+
+#[test]
+fn unseen_count() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5mr","6m","7m","1p","2p","3p","9s","E","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"5pr","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"P","tsumogiri":false}
+        {"type":"pon","actor":1,"target":3,"pai":"P","consumed":["P","P"]}
+    "#;
+    let mut ps = state_from_log(0, log);
+    // The dora indicator, our own hand and the kawa of player 1.
+    assert_eq!(ps.unseen_count(t!(9s)), 1);
+    assert_eq!(ps.unseen_count(t!(P)), 1);
+    assert_eq!(ps.unseen_count(t!(N)), 3);
+    assert_eq!(ps.unseen_count(t!(E)), 1);
+    assert_eq!(ps.unseen_count(t!(1s)), 4);
+
+    // Akas are counted in both ways.
+    assert_eq!(ps.unseen_count(t!(5m)), 3);
+    assert_eq!(ps.unseen_count(t!(5mr)), 0);
+    assert_eq!(ps.unseen_count(t!(5p)), 3);
+    assert_eq!(ps.unseen_count(t!(5pr)), 0);
+    assert_eq!(ps.unseen_count(t!(5s)), 4);
+    assert_eq!(ps.unseen_count(t!(5sr)), 1);
+
+    ps.update_json(r#"{"type":"dahai","actor":1,"pai":"9s","tsumogiri":false}"#)
+        .unwrap();
+    assert_eq!(ps.unseen_count(t!(9s)), 0);
+    assert_bytes_round_trip(&ps);
+}
+
+#[test]
+fn update_effect() {
+    let mut ps = PlayerState::new(0);
+    let events = r#"
+        {"type":"start_game","names":["0","1","2","3"]}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"S"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"ryukyoku","deltas":[0,0,0,0]}
+        {"type":"end_kyoku"}
+        {"type":"end_game"}
+    "#;
+    let effects = events
+        .trim()
+        .lines()
+        .map(|l| ps.update_ex(&json::from_str(l).unwrap()).1)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        effects,
+        [
+            Effect::Ignored,
+            Effect::Mutated,
+            Effect::Mutated,
+            Effect::Mutated,
+            Effect::Mutated,
+            Effect::Mutated,
+            Effect::Mutated,
+            Effect::Ignored,
+            Effect::Ignored,
+        ],
+    );
+
+    let (cans, effect) = ps.update_ex(&Event::None);
+    assert_eq!(effect, Effect::Ignored);
+    assert!(!cans.can_act());
+}
+
+#[test]
+fn ryukyoku_deltas() {
+    let mut ps = PlayerState::new(1);
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"W"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+    "#;
+    for line in log.trim().lines() {
+        ps.update_json(line).unwrap();
+    }
+    assert!(!ps.last_kyoku_ended_in_draw());
+
+    ps.update_json(r#"{"type":"ryukyoku","reason":"fanpai","deltas":[-1000,3000,-1000,-1000],"tenpais":[false,true,false,false]}"#).unwrap();
+    assert!(ps.last_kyoku_ended_in_draw());
+    assert_eq!(ps.scores, [28000, 24000, 24000, 24000]);
+    assert_eq!(ps.rank, 0);
+    assert_bytes_round_trip(&ps);
+
+    ps.update_json(r#"{"type":"end_kyoku"}"#).unwrap();
+    ps.update_json(r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":2,"honba":1,"kyotaku":0,"oya":1,"scores":[24000,28000,24000,24000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#).unwrap();
+    assert!(ps.last_kyoku_ended_in_draw());
+    assert_eq!(ps.scores, [28000, 24000, 24000, 24000]);
+
+    ps.update_json(r#"{"type":"hora","actor":2,"target":3,"deltas":[0,0,1000,-1000]}"#)
+        .unwrap();
+    assert!(!ps.last_kyoku_ended_in_draw());
+}
+
+#[test]
+fn hora_deltas() {
+    let mut ps = PlayerState::new(1);
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":2,"honba":1,"kyotaku":1,"oya":1,"scores":[24000,27000,24000,24000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"W"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+    "#;
+    for line in log.trim().lines() {
+        ps.update_json(line).unwrap();
+    }
+    assert_eq!(ps.kyotaku, 1);
+
+    // A hora without deltas leaves the scores and the kyotaku as they are.
+    let mut no_deltas = ps.clone();
+    no_deltas
+        .update_json(r#"{"type":"hora","actor":2,"target":3}"#)
+        .unwrap();
+    assert_eq!(no_deltas.scores, [27000, 24000, 24000, 24000]);
+    assert_eq!(no_deltas.kyotaku, 1);
+
+    // The winner takes the riichi stick along with the agari.
+    ps.update_json(r#"{"type":"hora","actor":2,"target":3,"deltas":[0,0,2300,-1300]}"#)
+        .unwrap();
+    assert!(!ps.last_kyoku_ended_in_draw());
+    assert_eq!(ps.scores, [27000, 26300, 22700, 24000]);
+    assert_eq!(ps.rank, 0);
+    assert_eq!(ps.kyotaku, 0);
+    assert_bytes_round_trip(&ps);
+}
+
+#[test]
+fn score_pressure() {
+    let mut ps = PlayerState {
+        bakaze: t!(S),
+        kyoku: 2,
+        scores: [25000, 32000, 20000, 23000],
+        ..Default::default()
+    };
+    ps.update_rank();
+    let assert_close = |actual: [f32; 2], expected: [f32; 2]| {
+        for (a, e) in actual.into_iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+        }
+    };
+    assert_eq!(ps.hands_left(8), 2);
+    assert_close(ps.score_pressure(8000., 8), [-0.4375, 0.125]);
+    let obs = ps.encode_score_pressure(8000., 8);
+    assert_eq!(obs.shape(), [SCORE_PRESSURE_CHANNELS, 34]);
+    assert_eq!(score_pressure_plane_names().len(), SCORE_PRESSURE_CHANNELS);
+    assert!(obs.row(0).iter().all(|&v| v == -0.4375));
+    assert!(obs.row(1).iter().all(|&v| v == 0.125));
+
+    // S3 is in the extra round of tonpuu, which is sudden death.
+    assert_eq!(ps.hands_left(4), 1);
+    assert_close(ps.score_pressure(8000., 4), [-0.875, 0.25]);
+
+    // All-last.
+    ps.kyoku = 3;
+    assert_eq!(ps.hands_left(8), 1);
+    assert_close(ps.score_pressure(8000., 8), [-0.875, 0.25]);
+
+    ps.scores = [40000, 32000, 20000, 8000];
+    ps.update_rank();
+    assert_close(ps.score_pressure(8000., 8), [0., 1.]);
+
+    ps.bakaze = t!(E);
+    ps.kyoku = 1;
+    assert_eq!(ps.hands_left(8), 7);
+    assert_eq!(ps.hands_left(4), 3);
+}
+
+#[test]
+fn riichi_declarable_discards() {
+    let state_after_first_tsumo = |tehai: [&str; 13], tsumo: &str| {
+        let tehai = json::to_string(&tehai).unwrap();
+        let log = format!(
+            r#"
+            {{"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[{tehai},["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}}
+            {{"type":"tsumo","actor":0,"pai":"{tsumo}"}}
+            "#,
+        );
+        let ps = state_from_log(0, &log);
+        assert!(ps.last_cans.can_riichi);
+        ps
+    };
+    let expected = |tiles: &[Tile]| {
+        let mut ret = [false; 34];
+        tiles.iter().for_each(|t| ret[t.as_usize()] = true);
+        ret
+    };
+
+    // 56789p can be cut in several ways.
+    let ps = state_after_first_tsumo(
+        [
+            "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "5p", "6p", "7p", "8p",
+        ],
+        "9p",
+    );
+    let choices = expected(&t![5p, 6p, 8p, 9p]);
+    assert_eq!(ps.riichi_declarable_discards(false), choices);
+    assert_eq!(ps.riichi_declarable_discards(true), choices);
+
+    // Only tsumogiri keeps the hand at tenpai.
+    let ps = state_after_first_tsumo(
+        [
+            "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "3p", "4p", "E", "E",
+        ],
+        "9s",
+    );
+    let choices = expected(&[t!(9s)]);
+    assert_eq!(ps.riichi_declarable_discards(false), choices);
+    assert_eq!(ps.riichi_declarable_discards(true), choices);
+
+    // Discarding W waits only on the fifth 1p.
+    let ps = state_after_first_tsumo(
+        [
+            "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", "1p", "1p", "1p", "W",
+        ],
+        "1p",
+    );
+    assert_eq!(ps.riichi_declarable_discards(false), expected(&t![1p, W]));
+    assert_eq!(ps.riichi_declarable_discards(true), expected(&[t!(1p)]));
+
+    // Nothing to declare after the discard.
+    let mut ps = ps;
+    ps.update_json(r#"{"type":"dahai","actor":0,"pai":"W","tsumogiri":false}"#)
+        .unwrap();
+    assert_eq!(ps.riichi_declarable_discards(false), [false; 34]);
+}
+
+#[test]
+fn kawa() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["P","P","1m","2m","3m","4p","5p","6p","7s","8s","9s","9m","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":false}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"9m","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"reach_accepted","actor":1}
+    "#;
+    let ps = state_from_log(0, log);
+
+    let item = |tile, tsumogiri, called, riichi| KawaItemPy {
+        tile,
+        tsumogiri,
+        called,
+        riichi,
+    };
+    assert_eq!(
+        ps.kawa(0),
+        [
+            item(t!(1s), true, false, false),
+            item(t!(9m), false, false, false)
+        ],
+    );
+    assert_eq!(
+        ps.kawa(1),
+        [
+            item(t!(P), false, true, false),
+            item(t!(W), true, false, true)
+        ],
+    );
+    // The gaps left by the pon are skipped.
+    assert!(ps.kawa(2).is_empty());
+    assert!(ps.kawa(3).is_empty());
+}
+
+#[test]
+fn acceptance_profile() {
+    let state_after_first_tsumo = |tehai: [&str; 13]| {
+        let tehai = json::to_string(&tehai).unwrap();
+        let log = format!(
+            r#"
+            {{"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[{tehai},["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}}
+            {{"type":"tsumo","actor":0,"pai":"C"}}
+            "#,
+        );
+        state_from_log(0, &log)
+    };
+    let expected_ukeire = |tiles: &[Tile]| {
+        let mut ret = [0; 34];
+        tiles.iter().for_each(|t| ret[t.as_usize()] = 4);
+        ret
+    };
+
+    // Ryanmen-ryanmen 1-shanten.
+    let mut ps = state_after_first_tsumo([
+        "2m", "3m", "6p", "7p", "1s", "2s", "3s", "7s", "8s", "9s", "E", "E", "N",
+    ]);
+    let profile = ps.acceptance_profile();
+    assert_eq!(profile.discard, Some(t!(N)));
+    assert_eq!(profile.shanten, 1);
+    assert_eq!(profile.ukeire, expected_ukeire(&t![1m, 4m, 5p, 8p]));
+    assert_eq!(profile.total, 16);
+    assert!((profile.entropy - 4_f32.ln()).abs() < 1e-6);
+    assert_eq!(profile.drop_without_best, 4);
+
+    // The same after actually discarding it.
+    ps.update(&Event::Dahai {
+        actor: 0,
+        pai: t!(N),
+        tsumogiri: false,
+    });
+    let after_discard = ps.acceptance_profile();
+    assert_eq!(after_discard.discard, None);
+    assert_eq!(after_discard.ukeire, profile.ukeire);
+
+    // Kanchan-kanchan 1-shanten is half as wide and less spread out.
+    let ps = state_after_first_tsumo([
+        "1m", "3m", "5p", "7p", "1s", "2s", "3s", "7s", "8s", "9s", "E", "E", "N",
+    ]);
+    let profile = ps.acceptance_profile();
+    assert_eq!(profile.discard, Some(t!(N)));
+    assert_eq!(profile.shanten, 1);
+    assert_eq!(profile.ukeire, expected_ukeire(&t![2m, 6p]));
+    assert_eq!(profile.total, 8);
+    assert!((profile.entropy - 2_f32.ln()).abs() < 1e-6);
+    assert_eq!(profile.drop_without_best, 4);
+}
+
+#[test]
+fn menzen_and_riichi_getters() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5p","6p","7p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":true}
+    "#;
+    let mut ps = state_from_log(0, log);
+    assert!(ps.is_oya());
+    assert!(ps.is_menzen());
+    assert!(!ps.self_riichi_accepted());
+    ps.update(&Event::ReachAccepted { actor: 0 });
+    assert!(ps.self_riichi_accepted());
+    assert!(ps.is_menzen());
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["P","P","1m","2m","3m","4p","5p","6p","7s","8s","9s","9m","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"P","tsumogiri":true}
+    "#;
+    let mut ps = state_from_log(0, log);
+    assert!(!ps.is_oya());
+    assert!(ps.is_menzen());
+    ps.update(&Event::Pon {
+        actor: 0,
+        target: 3,
+        pai: t!(P),
+        consumed: t![P, P],
+    });
+    assert!(!ps.is_menzen());
+    assert!(!ps.self_riichi_accepted());
+}
+
+#[test]
+fn dump_json() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":1,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["P","P","1m","2m","3m","4p","5pr","6p","7s","8s","9s","9m","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":false}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"9m","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"reach_accepted","actor":1}
+    "#;
+    let ps = state_from_log(0, log);
+    let dump: json::Value = json::from_str(&ps.dump_json()).unwrap();
+    let tiles = |v: &json::Value| -> Vec<Tile> { json::from_value(v.clone()).unwrap() };
+
+    assert_eq!(dump["version"], PlayerState::DUMP_VERSION);
+    assert_eq!(dump["player_id"], ps.player_id());
+    assert_eq!(dump["oya"], 0);
+    assert_eq!(dump["bakaze"], "E");
+    assert_eq!(dump["jikaze"], "E");
+    assert_eq!(dump["honba"], 1);
+    assert_eq!(dump["scores"], json::json!([25000, 24000, 25000, 25000]));
+    assert_eq!(dump["at_turn"], ps.at_turn());
+    assert_eq!(dump["shanten"], ps.shanten());
+    assert_eq!(dump["furiten"], ps.at_furiten());
+
+    let tehai = tiles(&dump["tehai"]);
+    assert_eq!(tehai, t![1m, 2m, 3m, 4p, 5pr, 6p, 7s, 8s, 9s, E]);
+    let mut counts = [0; 34];
+    tehai.iter().for_each(|t| counts[t.deaka().as_usize()] += 1);
+    assert_eq!(counts, ps.tehai());
+
+    let waits = tiles(&dump["waits"]);
+    assert_eq!(waits, [t!(E)]);
+    assert!(waits.iter().all(|t| ps.waits()[t.as_usize()]));
+    assert_eq!(tiles(&dump["dora_indicators"]), [t!(2s)]);
+    assert_eq!(dump["fuuro"], json::json!([["P", "P", "P"]]));
+    assert_eq!(dump["last_cans"], json::to_value(ps.last_cans()).unwrap());
+    assert_eq!(dump["last_kawa_tile"], "W");
+
+    for seat in 0..4 {
+        let row: Vec<_> = dump["kawa"][seat]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|item| !item.is_null())
+            .map(|item| {
+                let sutehai = &item["sutehai"];
+                KawaItemPy {
+                    tile: json::from_value(sutehai["tile"].clone()).unwrap(),
+                    tsumogiri: !sutehai["is_tedashi"].as_bool().unwrap(),
+                    called: sutehai["is_called"].as_bool().unwrap(),
+                    riichi: sutehai["is_riichi"].as_bool().unwrap(),
+                }
+            })
+            .collect();
+        assert_eq!(row, ps.kawa(seat as u8));
+    }
+    // The gap left by the pon in the kawa of seat 2.
+    assert!(dump["kawa"][2][0].is_null());
+}
+
+#[test]
+fn recommended_riichi_discards() {
+    let expected = |tiles: &[Tile]| {
+        let mut ret = [false; 37];
+        tiles.iter().for_each(|t| ret[t.as_usize()] = true);
+        ret
+    };
+
+    // Holding both the aka and a plain 5p, either of them can be discarded.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5pr","5p","6p","7p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    let mut ps = state_from_log(0, log);
+    let ret = ps.recommended_riichi_discards();
+    assert_eq!(ret.discards, expected(&t![5p, 5pr, 6p, 9p]));
+    assert!(ret.excluded.iter().all(Option::is_none));
+    // Same after the declaration.
+    ps.update(&Event::Reach { actor: 0 });
+    assert_eq!(ps.recommended_riichi_discards(), ret);
+
+    // All four 9p are visible, so 5p with a tanki wait on 9p is dead.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5pr","5p","6p","7p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    let ps = state_from_log(0, log);
+    assert_eq!(ps.riichi_declarable_discards(false), {
+        let mut ret = [false; 34];
+        t![5p, 6p, 9p].iter().for_each(|t| ret[t.as_usize()] = true);
+        ret
+    });
+    let ret = ps.recommended_riichi_discards();
+    assert_eq!(ret.discards, expected(&t![6p, 9p]));
+    assert_eq!(ret.excluded[tuz!(5p)], Some(RiichiExclusion::DeadWait));
+    assert_eq!(ret.excluded.iter().flatten().count(), 1);
+
+    // 9p is in the kawa, which makes the waits on 9p furiten.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5p","6p","7p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    let ps = state_from_log(0, log);
+    let ret = ps.recommended_riichi_discards();
+    assert_eq!(ret.discards, expected(&t![6p, 9p]));
+    assert_eq!(ret.excluded[tuz!(5p)], Some(RiichiExclusion::Furiten));
+    assert_eq!(ret.excluded[tuz!(8p)], Some(RiichiExclusion::Furiten));
+    assert_eq!(ret.excluded.iter().flatten().count(), 2);
+
+    // Nothing to recommend when riichi cannot be declared.
+    let ps = state_from_log(
+        0,
+        r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5p","6p","7p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        "#,
+    );
+    assert_eq!(ps.recommended_riichi_discards().discards, [false; 37]);
+}
+
+#[test]
+fn snapshot_reproduces_obs() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"S","dora_marker":"4p","kyoku":2,"honba":1,"kyotaku":1,"oya":1,"scores":[24000,26000,25000,24000],"tehais":[["1m","2m","3m","5pr","5p","6p","7s","8s","9s","W","W","W","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"E","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"5p","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9m","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"ankan","actor":0,"consumed":["W","W","W","W"]}
+        {"type":"dora","dora_marker":"1s"}
+        {"type":"tsumo","actor":0,"pai":"8p"}
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4p","tsumogiri":true}
+    "#;
+    let obs_bits = |ps: &PlayerState, at_kan_select: bool| {
+        let (obs, mask) = ps.encode_obs(at_kan_select);
+        let bits: Vec<_> = obs.iter().map(|v| v.to_bits()).collect();
+        (bits, mask)
+    };
+
+    let mut ps = PlayerState::new(0);
+    let mut kan_selects = 0;
+    for line in log.trim().lines() {
+        let cans = ps.update_json(line).unwrap();
+        let restored = PlayerState::from_bytes(&ps.to_bytes()).unwrap();
+        assert_eq!(restored.last_cans().as_bitflags(), cans.as_bitflags());
+
+        let mut modes = vec![false];
+        if cans.can_ankan || cans.can_kakan || cans.can_daiminkan {
+            modes.push(true);
+            kan_selects += 1;
+        }
+        if cans.can_act() {
+            for at_kan_select in modes {
+                assert_eq!(
+                    obs_bits(&restored, at_kan_select),
+                    obs_bits(&ps, at_kan_select),
+                );
+            }
+        }
+    }
+    assert_eq!(kan_selects, 1);
+    // 4p can be chi'd with either 5pr or 5p.
+    assert!(ps.last_cans().can_chi());
+}
+
+#[test]
+fn last_event_json() {
+    let mut ps = PlayerState::new(0);
+    assert_eq!(ps.last_event_json(), None);
+
+    let log = r#"
+        {"type":"start_game","names":["a","b","c","d"]}
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5pr","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","5mr"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"ryukyoku","reason":"kyuushukyuuhai","deltas":[0,0,0,0]}
+        {"type":"end_kyoku"}
+    "#;
+    for line in log.trim().lines() {
+        for ev in Event::parse_line(line.trim()).unwrap() {
+            ps.update(&ev);
+            let mirrored = ps.last_event_json().unwrap();
+            assert_eq!(Event::parse_line(&mirrored).unwrap()[..], [ev]);
+        }
+    }
+}
+
+#[test]
+fn call_history() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["P","P","1m","2m","3m","4p","5p","6p","7s","8s","9s","8m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"E"}
+        {"type":"dahai","actor":1,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"P","tsumogiri":true}
+    "#;
+    let mut ps = state_from_log(1, log);
+    assert_eq!(ps.first_call_junme(), None);
+    assert_eq!(ps.call_count(), 0);
+
+    let log = r#"
+        {"type":"pon","actor":1,"target":0,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":1,"pai":"1m","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"7m","tsumogiri":true}
+    "#;
+    for line in log.trim().lines() {
+        ps.update_json(line.trim()).unwrap();
+        assert_bytes_round_trip(&ps);
+    }
+    // The pon is made after one discard of the player.
+    assert_eq!(ps.first_call_junme(), Some(1));
+    assert_eq!(ps.call_count(), 1);
+    assert!(!ps.is_menzen());
+
+    ps.update(&Event::Chi {
+        actor: 1,
+        target: 0,
+        pai: t!(7m),
+        consumed: t![8m, 9m],
+    });
+    assert_eq!(ps.first_call_junme(), Some(1));
+    assert_eq!(ps.call_count(), 2);
+
+    let start_kyoku = r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"2s","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["P","P","1m","2m","3m","4p","5p","6p","7s","8s","9s","8m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#;
+    ps.update_json(start_kyoku).unwrap();
+    assert_eq!(ps.first_call_junme(), None);
+    assert_eq!(ps.call_count(), 0);
+    assert!(ps.is_menzen());
+}
+
+#[test]
+fn dora_tiles() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","2s","3s","4s","E","E","S","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"C","tsumogiri":true}
+        {"type":"dora","dora_marker":"C"}
+        {"type":"dora","dora_marker":"N"}
+        {"type":"dora","dora_marker":"5pr"}
+    "#;
+    let ps = state_from_log(0, log);
+    assert_eq!(ps.dora_indicators(), t![9s, C, N, 5pr]);
+    assert_eq!(ps.dora_tiles(), t![1s, P, E, 6p]);
+    assert_eq!(ps.ura_dora_tiles(&t![F, 5mr, 9m]), t![C, 6m, 1m]);
+}
+
+#[test]
+fn batch_encoder() {
+    // Every state of every seat in a tsumogiri kyoku, until there are 64.
+    let mut board = Board {
+        scores: [25000; 4],
+        ..Default::default()
+    };
+    board.init_from_seed((1, 2));
+    let mut board_state = board.into_state();
+    let mut reactions: [EventExt; 4] = Default::default();
+    let mut states = vec![];
+    while states.len() < 64 {
+        board_state.poll(reactions).unwrap();
+        let player_states = board_state.agent_context().player_states;
+        states.extend(player_states.iter().cloned());
+        reactions = Default::default();
+        for (reaction, state) in reactions.iter_mut().zip(player_states) {
+            if state.last_cans().can_discard {
+                *reaction = EventExt::no_meta(Event::Dahai {
+                    actor: state.player_id(),
+                    pai: state.last_self_tsumo().unwrap(),
+                    tsumogiri: true,
+                });
+            }
+        }
+    }
+    states.truncate(64);
+    let states: Vec<_> = states.iter().collect();
+    let at_kan_select: Vec<_> = (0..states.len()).map(|i| i % 3 == 0).collect();
+
+    let bits = |obs: ArrayView2<'_, f32>| obs.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+    for with_aka in [true, false] {
+        let encoder = BatchEncoder {
+            with_aka,
+            ..Default::default()
+        };
+        let (obs, masks) = encoder.encode(&states, &at_kan_select);
+        assert_eq!(obs.shape(), [64, OBS_CHANNELS, OBS_SHAPE.1]);
+
+        // A dirty buffer is overwritten.
+        let mut obs_buf = Array3::from_elem(obs.raw_dim(), 1.);
+        let mut masks_buf = Array2::from_elem(masks.raw_dim(), true);
+        encoder.encode_into(
+            &states,
+            &at_kan_select,
+            obs_buf.view_mut(),
+            masks_buf.view_mut(),
+        );
+
+        for (i, (state, &kan)) in states.iter().zip(&at_kan_select).enumerate() {
+            let (expected_obs, expected_mask) = if with_aka {
+                state.encode_obs(kan)
+            } else {
+                state.encode_obs_no_aka(kan)
+            };
+            assert_eq!(bits(obs.index_axis(Axis(0), i)), bits(expected_obs.view()));
+            assert_eq!(masks.index_axis(Axis(0), i), expected_mask);
+            assert_eq!(
+                bits(obs_buf.index_axis(Axis(0), i)),
+                bits(expected_obs.view())
+            );
+            assert_eq!(masks_buf.index_axis(Axis(0), i), expected_mask);
+        }
+    }
+
+    // The score pressure planes follow the obs.
+    let encoder = BatchEncoder {
+        score_pressure: true,
+        game_length: 4,
+        ..Default::default()
+    };
+    let (obs, _) = encoder.encode(&states, &at_kan_select);
+    assert_eq!(
+        obs.shape(),
+        [64, OBS_CHANNELS + SCORE_PRESSURE_CHANNELS, OBS_SHAPE.1],
+    );
+    for (i, (state, &kan)) in states.iter().zip(&at_kan_select).enumerate() {
+        let obs = obs.index_axis(Axis(0), i);
+        let (expected_obs, _) = state.encode_obs(kan);
+        let expected_extra = state.encode_score_pressure(8000., 4);
+        assert_eq!(
+            bits(obs.slice(s![..OBS_CHANNELS, ..])),
+            bits(expected_obs.view())
+        );
+        assert_eq!(
+            bits(obs.slice(s![OBS_CHANNELS.., ..])),
+            bits(expected_extra.view())
+        );
+    }
+
+    // With both options, the turn order planes come first.
+    let encoder = BatchEncoder {
+        turn_order: true,
+        score_pressure: true,
+        game_length: 4,
+        ..Default::default()
+    };
+    let (obs, _) = encoder.encode(&states, &at_kan_select);
+    let turn_order_end = OBS_CHANNELS + TURN_ORDER_CHANNELS;
+    assert_eq!(
+        obs.shape(),
+        [64, turn_order_end + SCORE_PRESSURE_CHANNELS, OBS_SHAPE.1],
+    );
+    for (i, (state, &kan)) in states.iter().zip(&at_kan_select).enumerate() {
+        let obs = obs.index_axis(Axis(0), i);
+        let (expected_obs, _) = state.encode_obs(kan);
+        assert_eq!(
+            bits(obs.slice(s![..OBS_CHANNELS, ..])),
+            bits(expected_obs.view())
+        );
+        assert_eq!(
+            bits(obs.slice(s![OBS_CHANNELS..turn_order_end, ..])),
+            bits(state.encode_turn_order().view())
+        );
+        assert_eq!(
+            bits(obs.slice(s![turn_order_end.., ..])),
+            bits(state.encode_score_pressure(8000., 4).view())
+        );
+    }
+}
+
+#[test]
+fn kuitan_rule() {
+    // Chi 6s with 45s, then wait on 6m and 9m with 234m 567p 33s 78m, where
+    // only the 6m ron makes an open tanyao.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["2m","3m","4m","5p","6p","7p","3s","3s","4s","5s","7m","8m","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"6s","tsumogiri":true}
+        {"type":"chi","actor":1,"target":0,"pai":"6s","consumed":["4s","5s"]}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"6m","tsumogiri":true}
+    "#;
+
+    let ps = state_from_log(1, log);
+    assert!(ps.last_cans.can_ron_agari);
+    let detail = ps.agari_detail(true, &[]).unwrap();
+    assert_eq!(detail.yakus, [(Yaku::Tanyao, 1)]);
+    assert_eq!(detail.han, 1);
+    assert_eq!(detail.point.ron, 1000);
+
+    let rules = Rules {
+        kuitan: KuitanRule::Forbidden,
+        ..Default::default()
+    };
+    let ps = state_from_log_with_rules(1, rules, log);
+    assert_eq!(ps.waits, hand("69m").unwrap().map(|c| c > 0));
+    assert!(!ps.last_cans.can_ron_agari);
+    assert!(ps.agari_detail(true, &[]).is_none());
+    ps.agari_points(true, &[]).unwrap_err();
+}
+
+#[test]
+fn first_go_around_yakus() {
+    let sorted = |mut yakus: Vec<(Yaku, u8)>| {
+        yakus.sort_unstable();
+        yakus
+    };
+    let with_rules = |mut ps: PlayerState, rules: Rules| {
+        ps.rules = rules;
+        ps
+    };
+
+    // Oya declares double riichi on the first discard and tsumos on the next
+    // go-around, 123m 456m 789p 23s 55p + 4s.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","2s","3s","5p","5p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"4s"}
+    "#;
+    let ps = state_from_log(0, log);
+    assert!(ps.last_cans.can_tsumo_agari);
+    let detail = ps.agari_detail(false, &[t!(C)]).unwrap();
+    assert_eq!(
+        detail.yakus,
+        [
+            (Yaku::DoubleRiichi, 2),
+            (Yaku::Ippatsu, 1),
+            (Yaku::MenzenTsumo, 1),
+            (Yaku::Pinfu, 1),
+        ],
+    );
+    assert_eq!(detail.han, 5);
+    // Mangan, 4000 all.
+    assert_eq!(detail.point.tsumo_ko, 4000);
+    assert_eq!(detail.point.tsumo_total(true), 12000);
+    assert_eq!(detail.point, ps.agari_points(false, &[t!(C)]).unwrap());
+
+    // The same with 123456789m 22m 67m + 8m, which reaches 13 han with
+    // chinitsu and ittsuu.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","2m","2m","6m","7m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"8m"}
+    "#;
+    let ps = state_from_log(0, log);
+    let detail = ps.agari_detail(false, &[t!(C)]).unwrap();
+    assert_eq!(
+        sorted(detail.yakus),
+        [
+            (Yaku::DoubleRiichi, 2),
+            (Yaku::Ippatsu, 1),
+            (Yaku::MenzenTsumo, 1),
+            (Yaku::Pinfu, 1),
+            (Yaku::Ittsuu, 2),
+            (Yaku::Chinitsu, 6),
+        ],
+    );
+    assert_eq!(detail.han, 13);
+    assert_eq!(detail.point.tsumo_ko, 16000);
+    let ps = with_rules(
+        ps,
+        Rules {
+            kazoe: KazoeRule::Sanbaiman,
+            ..Default::default()
+        },
+    );
+    let detail = ps.agari_detail(false, &[t!(C)]).unwrap();
+    assert_eq!(detail.han, 13);
+    assert_eq!(detail.point.tsumo_ko, 12000);
+    assert_eq!(ps.agari_points(false, &[t!(C)]).unwrap().tsumo_ko, 12000);
+
+    // Tenhou with suuankou, 111m 222p 333s 444s E + E.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","2p","2p","2p","3s","3s","3s","4s","4s","4s","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    let ps = state_from_log(0, log);
+    assert!(ps.last_cans.can_tsumo_agari);
+    let cases = [
+        (Rules::default(), vec![(Yaku::Tenhou, 13)], 13, 16000),
+        (
+            Rules {
+                kazoe: KazoeRule::Sanbaiman,
+                ..Default::default()
+            },
+            vec![(Yaku::Tenhou, 13)],
+            13,
+            16000,
+        ),
+        (
+            Rules {
+                blessing: BlessingRule::StackYakuman,
+                ..Default::default()
+            },
+            vec![(Yaku::Tenhou, 13), (Yaku::Suuankou, 13)],
+            26,
+            32000,
+        ),
+        (
+            Rules {
+                blessing: BlessingRule::StackYakuman,
+                yakuman: YakumanRule::Single,
+                ..Default::default()
+            },
+            vec![(Yaku::Tenhou, 13), (Yaku::Suuankou, 13)],
+            26,
+            16000,
+        ),
+    ];
+    for (rules, yakus, han, tsumo_ko) in cases {
+        let ps = with_rules(ps.clone(), rules);
+        let detail = ps.agari_detail(false, &[]).unwrap();
+        assert_eq!(detail.yakus, yakus, "{rules:?}");
+        assert_eq!(detail.han, han, "{rules:?}");
+        assert_eq!(detail.point.tsumo_ko, tsumo_ko, "{rules:?}");
+        assert_eq!(
+            ps.agari_points(false, &[]).unwrap(),
+            detail.point,
+            "{rules:?}",
+        );
+    }
+
+    // Chiihou without any yakuman in the hand stays a single yakuman under
+    // either blessing rule, 123m 456p 789s EE NN + N.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"C","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","N","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"N"}
+    "#;
+    let ps = state_from_log(1, log);
+    assert!(ps.last_cans.can_tsumo_agari);
+    for blessing in [BlessingRule::Alone, BlessingRule::StackYakuman] {
+        let ps = with_rules(
+            ps.clone(),
+            Rules {
+                blessing,
+                ..Default::default()
+            },
+        );
+        let detail = ps.agari_detail(false, &[]).unwrap();
+        assert_eq!(detail.yakus, [(Yaku::Chiihou, 13)]);
+        assert_eq!(detail.han, 13);
+        assert_eq!(
+            (detail.point.tsumo_oya, detail.point.tsumo_ko),
+            (16000, 8000),
+        );
+    }
+}
+
+#[test]
+fn legal_reactions() {
+    let assert_all_valid = |ps: &PlayerState| {
+        for reaction in ps.legal_reactions() {
+            let ev = ps.reaction_to_event(reaction);
+            if let Err(err) = ps.validate_reaction(&ev) {
+                panic!("{reaction:?} as {ev:?} is invalid: {err}");
+            }
+        }
+    };
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["5mr","5m","6m","7m","1p","2p","3p","4s","5s","6s","9s","9s","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"5m","tsumogiri":false}
+    "#;
+    let mut ps = state_from_log(1, log);
+    assert!(ps.last_cans.can_pon);
+    assert!(ps.last_cans.can_chi_low);
+    let reactions = ps.legal_reactions();
+    // Only one plain 5m is left in hand besides the aka.
+    assert_eq!(
+        reactions,
+        [
+            Reaction::Chi {
+                consumed: [t!(6m), t!(7m)],
+            },
+            Reaction::Pon {
+                consumed: [t!(5mr), t!(5m)],
+            },
+            Reaction::Pass,
+        ],
+    );
+    assert_all_valid(&ps);
+    assert_eq!(
+        ps.reaction_to_event(reactions[1]),
+        Event::Pon {
+            actor: 1,
+            target: 0,
+            pai: t!(5m),
+            consumed: t![5mr, 5m],
+        },
+    );
+
+    // A discard is mandatory at its own turn, and every tile in hand can be
+    // discarded, the aka separately.
+    ps.update(&Event::Tsumo {
+        actor: 1,
+        pai: t!(E),
+    });
+    let reactions = ps.legal_reactions();
+    assert!(!reactions.contains(&Reaction::Pass));
+    let discards: Vec<_> = reactions
+        .iter()
+        .filter_map(|r| match *r {
+            Reaction::Discard(tile) => Some(tile),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(discards, t![5m, 5mr, 6m, 7m, 1p, 2p, 3p, 4s, 5s, 6s, 9s, E]);
+    assert_all_valid(&ps);
+    assert_eq!(
+        ps.reaction_to_event(Reaction::Discard(t!(E))),
+        Event::Dahai {
+            actor: 1,
+            pai: t!(E),
+            tsumogiri: true,
+        },
+    );
+
+    // Nothing to react to.
+    ps.update(&Event::Dahai {
+        actor: 1,
+        pai: t!(E),
+        tsumogiri: true,
+    });
+    assert!(ps.legal_reactions().is_empty());
+}
+
+/// The documented orders of tile lists: ascending in tile id, with an aka
+/// right after the plain tile of the same kind.
+fn assert_canonical_orders(ps: &PlayerState) {
+    let key = |tiles: &[Tile]| -> Vec<_> {
+        tiles
+            .iter()
+            .map(|t| (t.deaka().as_u8(), t.is_aka()))
+            .collect()
+    };
+    let reactions = ps.legal_reactions();
+    let keys: Vec<_> = reactions
+        .iter()
+        .map(|r| match *r {
+            Reaction::Discard(tile) => (0, key(&[tile])),
+            Reaction::Riichi => (1, vec![]),
+            Reaction::Chi { consumed } => (2, key(&consumed)),
+            Reaction::Pon { consumed } => (3, key(&consumed)),
+            Reaction::Kan { consumed } => (4, key(&consumed)),
+            Reaction::Kakan(tile) => (5, key(&[tile])),
+            Reaction::Ankan { consumed } => (6, key(&consumed)),
+            Reaction::Tsumo => (7, vec![]),
+            Reaction::Ron => (8, vec![]),
+            Reaction::Ryukyoku => (9, vec![]),
+            Reaction::Pass => (10, vec![]),
+        })
+        .collect();
+    assert!(
+        keys.windows(2).all(|w| w[0] < w[1]),
+        "{reactions:?}\n{}",
+        ps.brief_info(),
+    );
+
+    for tiles in [ps.ankan_candidates(), ps.kakan_candidates()] {
+        assert!(tiles.windows(2).all(|w| key(&w[..1]) < key(&w[1..])));
+    }
+    for ids in [ps.chis(), ps.pons(), ps.minkans(), ps.ankans()] {
+        assert!(ids.windows(2).all(|w| w[0] <= w[1]));
+    }
+    if ps.tehai.iter().sum::<u8>() % 3 == 1 {
+        let shapes = ps.wait_shapes();
+        assert!(shapes
+            .windows(2)
+            .all(|w| w[0].tile.as_u8() < w[1].tile.as_u8()));
+        assert!(shapes
+            .iter()
+            .all(|s| s.kinds.windows(2).all(|w| w[0] <= w[1])));
+    }
+}
+
+#[test]
+fn canonical_orders() {
+    // Greedy callers that never riichi, for plenty of calls and kans, with the
+    // orders checked for every seat at every decision point.
+    for seed in 0..8 {
+        let mut board = Board {
+            scores: [25000; 4],
+            ..Default::default()
+        };
+        board.init_from_seed((seed, 0));
+        let mut board_state = board.into_state();
+        let mut reactions: [EventExt; 4] = Default::default();
+        loop {
+            board_state.poll(reactions).unwrap();
+            let ctx = board_state.agent_context();
+            if matches!(ctx.log.last().map(|ev| &ev.event), Some(Event::EndKyoku)) {
+                break;
+            }
+
+            reactions = Default::default();
+            for (reaction, state) in reactions.iter_mut().zip(ctx.player_states) {
+                assert_canonical_orders(state);
+                let legal = state.legal_reactions();
+                let picked = legal
+                    .iter()
+                    .find(|r| {
+                        !matches!(r, Reaction::Discard(_) | Reaction::Riichi | Reaction::Pass)
+                    })
+                    .or_else(|| legal.first());
+                if let Some(&picked) = picked {
+                    *reaction = EventExt::no_meta(state.reaction_to_event(picked));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn safest_push_line() {
+    // 1-shanten after cutting N, with a symmetric 2s and 8s left to cut.
+    let tehai = hand("123456m 789p 28s 114z").unwrap();
+    let mut ps = PlayerState {
+        tehai,
+        tiles_seen: tehai,
+        tehai_len_div3: 4,
+        last_cans: ActionCandidate {
+            can_discard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    ps.kawa_overview[1].push(t!(8s));
+
+    let danger = ps.danger_scores_against(1);
+    assert_eq!(danger[tuz!(8s)], 0);
+    assert_eq!(danger[tuz!(2s)], 5);
+    assert_eq!(danger[tuz!(N)], 3);
+
+    assert_eq!(ps.safest_push_line(1, 1), None);
+    // Cutting the genbutsu 8s first or last are equally safe, and the one
+    // with the lower tile id comes first.
+    let line = ps.safest_push_line(2, 1).unwrap();
+    assert_eq!(line.discards, t![8s, N]);
+    assert_eq!(line.draws, t![1s]);
+    assert_eq!(line.danger, 3);
+    assert_eq!(ps.safest_push_line(3, 1), Some(line));
+
+    // The mirrored position.
+    ps.kawa_overview[1].clear();
+    ps.kawa_overview[1].push(t!(2s));
+    let line = ps.safest_push_line(2, 1).unwrap();
+    assert_eq!(line.discards, t![2s, N]);
+    assert_eq!(line.draws, t![6s]);
+    assert_eq!(line.danger, 3);
+
+    // Already tenpai after a discard.
+    ps.tehai = hand("123456m 789p 23s 114z").unwrap();
+    let line = ps.safest_push_line(1, 1).unwrap();
+    assert_eq!(line.discards, t![N]);
+    assert!(line.draws.is_empty());
+}
+
+#[test]
+fn legal_action_mask() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["5mr","5m","5m","6m","7m","1p","2p","3p","4s","5s","6s","9s","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"5m","tsumogiri":false}
+    "#;
+    let ps = state_from_log(1, log);
+    let legal = |at_kan_choice| -> Vec<_> {
+        let mask = ps.legal_action_mask(at_kan_choice);
+        (0..ACTION_SPACE).filter(|&a| mask[a]).collect()
+    };
+    assert_eq!(legal(false), [38, 41, 42, 45]);
+    assert_eq!(legal(true), [tuz!(5m)]);
+
+    let pon = |prefer_aka| ps.action_to_event(41, false, prefer_aka).unwrap();
+    assert_eq!(
+        pon(true),
+        Event::Pon {
+            actor: 1,
+            target: 0,
+            pai: t!(5m),
+            consumed: t![5mr, 5m],
+        },
+    );
+    assert_eq!(
+        pon(false),
+        Event::Pon {
+            actor: 1,
+            target: 0,
+            pai: t!(5m),
+            consumed: t![5m, 5m],
+        },
+    );
+    let daiminkan = Event::Daiminkan {
+        actor: 1,
+        target: 0,
+        pai: t!(5m),
+        consumed: t![5mr, 5m, 5m],
+    };
+    assert_eq!(ps.action_to_event(42, false, false).unwrap(), daiminkan);
+    assert_eq!(ps.action_to_event(tuz!(5m), true, true).unwrap(), daiminkan);
+    assert_eq!(ps.action_to_event(45, false, true).unwrap(), Event::None);
+    ps.action_to_event(tuz!(6m), true, true).unwrap_err();
+    ps.action_to_event(ACTION_SPACE, false, true).unwrap_err();
+}
+
+#[test]
+fn kawa_capacity() {
+    // Tiles are dealt from a pool so that no kind is seen more than 4 times.
+    // The 12 kinds to be ponned are set aside.
+    let mut pool = [4_u8; 34];
+    let mut pon_kinds = tuz!(5s)..34;
+    pon_kinds.clone().for_each(|tid| pool[tid] -= 3);
+    let mut cursor = 0;
+    let mut take = || {
+        while pool[cursor % 34] == 0 {
+            cursor += 1;
+        }
+        let tid = cursor % 34;
+        pool[tid] -= 1;
+        cursor += 1;
+        must_tile!(tid)
+    };
+
+    let mut tehais = [[t!(?); 13]; 4];
+    tehais[0].iter_mut().for_each(|t| *t = take());
+    let mut events = vec![Event::StartKyoku {
+        bakaze: t!(E),
+        dora_marker: take(),
+        kyoku: 1,
+        honba: 0,
+        kyotaku: 0,
+        oya: 0,
+        scores: [25000; 4],
+        tehais,
+    }];
+    let tsumo = |actor, pai| Event::Tsumo { actor, pai };
+    let dahai = |actor, pai| Event::Dahai {
+        actor,
+        pai,
+        tsumogiri: true,
+    };
+    let pon = |actor, target, pai| Event::Pon {
+        actor,
+        target,
+        pai,
+        consumed: [pai; 2],
+    };
+
+    // Player 3 pons the discards of player 0, skipping players 1 and 2.
+    for _ in 0..4 {
+        let pai = must_tile!(pon_kinds.next().unwrap());
+        events.extend([tsumo(0, pai), dahai(0, pai), pon(3, 0, pai)]);
+        events.push(dahai(3, take()));
+    }
+    // Player 2 pons the discards of player 0, skipping player 1.
+    for _ in 0..4 {
+        let pai = must_tile!(pon_kinds.next().unwrap());
+        events.extend([tsumo(0, pai), dahai(0, pai), pon(2, 0, pai)]);
+        events.extend([dahai(2, take()), tsumo(3, t!(?)), dahai(3, take())]);
+    }
+    // Player 1 pons the discards of player 3, skipping player 0.
+    for _ in 0..4 {
+        let pai = must_tile!(pon_kinds.next().unwrap());
+        let own = take();
+        events.extend([tsumo(0, own), dahai(0, own)]);
+        events.extend([tsumo(1, t!(?)), dahai(1, take())]);
+        events.extend([tsumo(2, t!(?)), dahai(2, take())]);
+        events.extend([tsumo(3, t!(?)), dahai(3, pai), pon(1, 3, pai)]);
+        events.push(dahai(1, take()));
+        events.extend([tsumo(2, t!(?)), dahai(2, take())]);
+        events.extend([tsumo(3, t!(?)), dahai(3, take())]);
+    }
+    for _ in 0..8 {
+        let own = take();
+        events.extend([tsumo(0, own), dahai(0, own)]);
+        for actor in 1..4 {
+            events.extend([tsumo(actor, t!(?)), dahai(actor, take())]);
+        }
+    }
+    // The last tile of the wall is drawn by player 1.
+    let own = take();
+    events.extend([tsumo(0, own), dahai(0, own)]);
+    events.extend([tsumo(1, t!(?)), dahai(1, take())]);
+
+    let mut ps = PlayerState::new(0);
+    for ev in &events {
+        ps.update_json(&ev.to_line()).unwrap();
+    }
+    assert_eq!(ps.tiles_left, 0);
+    assert_eq!(ps.kawa_overview[0].len(), 21);
+    assert_eq!(ps.kawa[0].len(), 25);
+    assert_eq!(ps.kawa[0].iter().filter(|item| item.is_none()).count(), 4);
+    assert_bytes_round_trip(&ps);
+}
+
+#[test]
+fn capacity_overflows() {
+    let start_kyoku = Event::StartKyoku {
+        bakaze: t!(E),
+        dora_marker: t!(9p),
+        kyoku: 1,
+        honba: 0,
+        kyotaku: 0,
+        oya: 0,
+        scores: [25000; 4],
+        tehais: [
+            t![1m, 1m, 1m, 1m, 2m, 2m, 2m, 2m, 3m, 3m, 3m, 3m, 4m],
+            [t!(?); 13],
+            [t!(?); 13],
+            [t!(?); 13],
+        ],
+    };
+    let new_state = || {
+        let mut ps = PlayerState::new(0);
+        ps.update_json(&start_kyoku.to_line()).unwrap();
+        ps
+    };
+    let update = |ps: &mut PlayerState, ev: Event| ps.update_json(&ev.to_line());
+    let exceeded = |ps: &mut PlayerState, ev: Event| {
+        let err = update(ps, ev).unwrap_err();
+        match err.downcast_ref::<UpdateError>() {
+            Some(&UpdateError::CapacityExceeded { capacity }) => capacity,
+            _ => panic!("unexpected error {err}"),
+        }
+    };
+
+    // Too many discards.
+    let mut ps = new_state();
+    for i in 0..32 {
+        let dahai = Event::Dahai {
+            actor: 1,
+            pai: must_tile!(tuz!(1p) + i % 18),
+            tsumogiri: true,
+        };
+        update(&mut ps, dahai).unwrap();
+    }
+    let dahai = Event::Dahai {
+        actor: 1,
+        pai: t!(E),
+        tsumogiri: true,
+    };
+    assert_eq!(exceeded(&mut ps, dahai), Capacity::Kawa { actor: 1 });
+    assert_eq!(ps.kawa[1].len(), 32);
+
+    // Too many melds.
+    let mut ps = new_state();
+    for pai in t![5p, 6p, 7p, 8p, 9p] {
+        let pon = Event::Pon {
+            actor: 1,
+            target: 3,
+            pai,
+            consumed: [pai; 2],
+        };
+        if pai == t!(9p) {
+            assert_eq!(exceeded(&mut ps, pon), Capacity::Melds { actor: 1 });
+        } else {
+            update(&mut ps, pon).unwrap();
+        }
+    }
+    assert_eq!(ps.fuuro_overview[1].len(), 4);
+
+    // Too many tsumos, which would make 4 ankan candidates.
+    let mut ps = new_state();
+    let tsumo = Event::Tsumo {
+        actor: 0,
+        pai: t!(4m),
+    };
+    update(&mut ps, tsumo.clone()).unwrap();
+    assert_eq!(ps.ankan_candidates.len(), 3);
+    assert_eq!(exceeded(&mut ps, tsumo), Capacity::Tehai);
+
+    // Too many kans and dora indicators.
+    let mut ps = new_state();
+    for (idx, pai) in t![5p, 6p, 7p, 8p, 9p].into_iter().enumerate() {
+        let ankan = Event::Ankan {
+            actor: idx as u8 % 3 + 1,
+            consumed: [pai; 4],
+        };
+        let dora = Event::Dora { dora_marker: pai };
+        if idx < 4 {
+            update(&mut ps, ankan).unwrap();
+            update(&mut ps, dora).unwrap();
+        } else {
+            assert_eq!(exceeded(&mut ps, ankan), Capacity::Kans);
+            assert_eq!(exceeded(&mut ps, dora), Capacity::DoraIndicators);
+        }
+    }
+    assert_eq!(ps.dora_indicators.len(), 5);
+
+    // Kakan without a pon, or on a chi starting with the same tile.
+    let mut ps = new_state();
+    let chi = Event::Chi {
+        actor: 1,
+        target: 0,
+        pai: t!(3s),
+        consumed: t![4s, 5s],
+    };
+    update(&mut ps, chi).unwrap();
+    let kakan = Event::Kakan {
+        actor: 1,
+        pai: t!(4s),
+        consumed: t![4s, 4s, 4s],
+    };
+    let err = update(&mut ps, kakan).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<UpdateError>(),
+        Some(&UpdateError::InvalidMeld { actor: 1 }),
+    );
+    assert_eq!(ps.fuuro_overview[1][0].len(), 3);
+}
+
+/// The overflows of `capacity_overflows` are saturated by the unchecked
+/// `update` instead of panicking.
+#[test]
+fn capacity_overflows_lenient() {
+    let mut ps = PlayerState::new(0);
+    ps.update(&Event::StartKyoku {
+        bakaze: t!(E),
+        dora_marker: t!(9p),
+        kyoku: 1,
+        honba: 0,
+        kyotaku: 0,
+        oya: 0,
+        scores: [25000; 4],
+        tehais: [
+            t![1m, 1m, 1m, 2m, 2m, 2m, 3m, 3m, 3m, 4m, 4m, 4m, 5m],
+            [t!(?); 13],
+            [t!(?); 13],
+            [t!(?); 13],
+        ],
+    });
+
+    for i in 0..40 {
+        ps.update(&Event::Dahai {
+            actor: 1,
+            pai: must_tile!(tuz!(1p) + i % 18),
+            tsumogiri: true,
+        });
+    }
+    assert_eq!(ps.kawa[1].len(), 32);
+    assert_eq!(ps.kawa_overview[1].len(), 32);
+
+    for pai in t![6s, 7s, 8s, 9s, E] {
+        ps.update(&Event::Pon {
+            actor: 2,
+            target: 1,
+            pai,
+            consumed: [pai; 2],
+        });
+    }
+    assert_eq!(ps.fuuro_overview[2].len(), 4);
+
+    for (idx, pai) in t![S, W, N, P, F].into_iter().enumerate() {
+        ps.update(&Event::Ankan {
+            actor: 3,
+            consumed: [pai; 4],
+        });
+        ps.update(&Event::Dora {
+            dora_marker: must_tile!(tuz!(1s) + idx),
+        });
+    }
+    assert_eq!(ps.ankan_overview[3].len(), 4);
+    assert_eq!(ps.dora_indicators.len(), 5);
+}
+
+#[test]
+fn turn_order() {
+    // Player 3 pons skipping player 2, player 2 pons skipping player 1, and
+    // player 3 ankans to draw again from the dead wall.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","1s","2s","3s","4s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1p"}
+        {"type":"dahai","actor":0,"pai":"1p","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":true}
+        {"type":"pon","actor":3,"target":1,"pai":"S","consumed":["S","S"]}
+        {"type":"dahai","actor":3,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"2p"}
+        {"type":"dahai","actor":0,"pai":"2p","tsumogiri":true}
+        {"type":"pon","actor":2,"target":0,"pai":"2p","consumed":["2p","2p"]}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"ankan","actor":3,"consumed":["N","N","N","N"]}
+        {"type":"dora","dora_marker":"1s"}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"8p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"3p"}
+        {"type":"dahai","actor":0,"pai":"3p","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"7s","tsumogiri":true}
+        {"type":"reach_accepted","actor":1}
+    "#;
+    // `seats_until_my_turn` after each line.
+    let expected = [
+        0, 3, 3, 2, 2, 0, 0, 3, 3, 1, 1, 0, 1, 1, 0, 0, 3, 3, 2, 2, 2, 2,
+    ];
+
+    let names = turn_order_plane_names();
+    let plane = |n: u8| {
+        let name = format!("seats_until_my_turn_{n}");
+        names.iter().position(|s| *s == name).unwrap()
+    };
+
+    let mut ps = PlayerState::new(0);
+    let lines: Vec<_> = log.trim().lines().collect();
+    assert_eq!(lines.len(), expected.len());
+    for (line, seats) in lines.into_iter().zip(expected) {
+        ps.update_json(line).unwrap();
+        assert_eq!(ps.seats_until_my_turn(), seats, "after {line}");
+        let obs = ps.encode_turn_order();
+        for n in 1..=3 {
+            assert_eq!(
+                obs[[plane(n), 0]] > 0.,
+                seats >= n,
+                "plane {n} after {line}"
+            );
+        }
+        for rel in 0..4 {
+            assert_eq!(
+                ps.draws_before_my_next_draw(rel),
+                u8::from(rel > 0 && rel + seats >= 4),
+                "draws of {rel} after {line}",
+            );
+        }
+    }
+    assert_eq!(ps.draws_before_my_next_draw(2), 1);
+    assert_eq!(ps.draws_before_my_next_draw(1), 0);
+    assert_eq!(
+        ps.discard_precedes_riichi_draw(),
+        [false, true, false, false],
+    );
+    assert_bytes_round_trip(&ps);
+}
+
+#[test]
+fn nagashi_mangan() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"5p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","9p","E","S","W","N","P","F","C","5s","6s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+        {"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"2m","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4m","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"7p"}
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":false}
+    "#;
+    let draw = r#"{"type":"ryukyoku","reason":"fanpai"}"#;
+
+    let mut ps = state_from_log(0, log);
+    ps.update_json(draw).unwrap();
+    assert!(ps.nagashi_mangan(0));
+    assert!(!ps.nagashi_mangan(1));
+    assert!(ps.nagashi_mangan(2));
+    // Seat 3 has only discarded 4m.
+    assert!(!ps.nagashi_mangan(3));
+
+    let mut ps = state_from_log(1, log);
+    ps.update_json(draw).unwrap();
+    assert!(ps.nagashi_mangan(3));
+    assert!(!ps.nagashi_mangan(0));
+
+    // Nobody has discarded yet.
+    let ps = state_from_log(0, log.trim().lines().next().unwrap());
+    assert!((0..4).all(|rel| !ps.nagashi_mangan(rel)));
+
+    // A called discard breaks it.
+    let log = format!(
+        "{}\n{}\n{}\n{}",
+        log.trim(),
+        r#"{"type":"pon","actor":1,"target":0,"pai":"E","consumed":["E","E"]}"#,
+        r#"{"type":"dahai","actor":1,"pai":"1p","tsumogiri":false}"#,
+        draw,
+    );
+    let ps = state_from_log(0, &log);
+    assert!(!ps.nagashi_mangan(0));
+    assert!(ps.nagashi_mangan(2));
+}
+
+#[test]
+fn ankan_after_riichi() {
+    // Player 0 riichis with the 13 tiles of `tehai`, then draws 1m.
+    let riichi_then_1m = |tehai: &str| {
+        let tehai: Vec<_> = tehai.split(' ').map(|t| format!(r#""{t}""#)).collect();
+        let log = format!(
+            r#"
+            {{"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[[{}],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}}
+            {{"type":"tsumo","actor":0,"pai":"N"}}
+            {{"type":"reach","actor":0}}
+            {{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}}
+            {{"type":"reach_accepted","actor":0}}
+            {{"type":"tsumo","actor":1,"pai":"?"}}
+            {{"type":"dahai","actor":1,"pai":"W","tsumogiri":true}}
+            {{"type":"tsumo","actor":2,"pai":"?"}}
+            {{"type":"dahai","actor":2,"pai":"W","tsumogiri":true}}
+            {{"type":"tsumo","actor":3,"pai":"?"}}
+            {{"type":"dahai","actor":3,"pai":"W","tsumogiri":true}}
+            {{"type":"tsumo","actor":0,"pai":"1m"}}
+            "#,
+            tehai.join(","),
+        );
+        state_from_log(0, &log)
+    };
+    let ankan: Event =
+        json::from_str(r#"{"type":"ankan","actor":0,"consumed":["1m","1m","1m","1m"]}"#).unwrap();
+    let dahai = |pai: &str| -> Event {
+        json::from_str(&format!(
+            r#"{{"type":"dahai","actor":0,"pai":"{pai}","tsumogiri":false}}"#
+        ))
+        .unwrap()
+    };
+
+    // A 9s tanki, which stays the same after the ankan of 1m.
+    let ps = riichi_then_1m("1m 1m 1m 4p 5p 6p 7p 8p 9p 5s 5s 5s 9s");
+    assert!(ps.last_cans.can_ankan);
+    assert_eq!(ps.ankan_candidates(), [t!(1m)]);
+    ps.validate_reaction(&ankan).unwrap();
+    let mut expected = [false; 34];
+    expected[tuz!(1m)] = true;
+    assert_eq!(ps.discard_candidates(), expected);
+    ps.validate_reaction(&dahai("1m")).unwrap();
+    ps.validate_reaction(&dahai("9s")).unwrap_err();
+
+    // 1113m waits on 2m and 3m, while the ankan of 1m would leave a 3m tanki.
+    let ps = riichi_then_1m("1m 1m 1m 3m 4p 5p 6p 7p 8p 9p 5s 5s 5s");
+    assert!(ps.waits[tuz!(2m)] && ps.waits[tuz!(3m)]);
+    assert!(!ps.last_cans.can_ankan);
+    assert!(ps.ankan_candidates().is_empty());
+    ps.validate_reaction(&ankan).unwrap_err();
+    assert_eq!(ps.discard_candidates(), expected);
+    ps.validate_reaction(&dahai("1m")).unwrap();
+    ps.validate_reaction(&dahai("3m")).unwrap_err();
+}
+
+#[test]
+fn frozen_hand_after_riichi() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","5p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9s"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"9s","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"5p","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"1s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"2s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    let lines: Vec<_> = log.trim().lines().collect();
+    let names = obs_plane_names();
+    let plane_idx = |name| names.iter().position(|&n| n == name).unwrap();
+    let keep = plane_idx("keep_shanten_discards");
+    let next = plane_idx("next_shanten_discards");
+
+    // Before the riichi, discarding either 9s or 5p keeps tenpai.
+    let ps = state_from_log(0, &lines[..2].join("\n"));
+    let (obs, _) = ps.encode_obs(false);
+    assert_eq!(obs[[keep, tuz!(9s)]], 1.);
+    assert_eq!(obs[[keep, tuz!(5p)]], 1.);
+
+    let mut ps = state_from_log(0, &lines[..5].join("\n"));
+    assert!(ps.keep_shanten_discards.iter().all(|&b| !b));
+    assert!(ps.next_shanten_discards.iter().all(|&b| !b));
+    let waits = ps.waits;
+    assert!(waits[tuz!(5p)]);
+
+    // Passing the ron on 5p makes a permanent furiten, while the waits and
+    // shanten stay the same.
+    for line in &lines[5..] {
+        let cans = ps.update_json(line).unwrap();
+        assert_eq!(ps.waits, waits, "after {line}");
+        assert_eq!(ps.shanten, 0, "after {line}");
+        if cans.can_act() {
+            let (obs, _) = ps.encode_obs(false);
+            assert!(obs.row(keep).iter().all(|&v| v == 0.), "after {line}");
+            assert!(obs.row(next).iter().all(|&v| v == 0.), "after {line}");
+        }
+    }
+    assert!(ps.last_cans.can_discard);
+    assert!(ps.at_furiten);
+    assert_eq!(ps.furiten_kind, FuritenKind::Riichi);
+    assert_bytes_round_trip(&ps);
+}
@@ -1,15 +1,42 @@
 use super::action::ActionCandidate;
-use super::item::{ChiPon, KawaItem, Sutehai};
-use super::PlayerState;
+use super::item::{ChiPon, FuritenKind, KawaItem, Sutehai};
+use super::{GameMode, KuikaeRule, KuitanRule, PlayerState};
 use crate::algo::agari::{self, AgariCalculator};
 use crate::algo::shanten;
 use crate::mjai::Event;
 use crate::tile::Tile;
-use crate::{must_tile, tu8};
+use crate::vec_ops::vec_add_assign;
+use crate::{matches_tu8, must_tile, t, tu8, tuz};
 use std::cmp::Ordering;
+use std::fmt;
 use std::mem;
 
-use tinyvec::array_vec;
+use tinyvec::{array_vec, Array, ArrayVec};
+
+/// How an event affects the state, returned by `PlayerState::update_ex`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// The event is tracked by the state.
+    Mutated,
+    /// The event carries nothing the state tracks, apart from clearing the
+    /// action candidates.
+    Ignored,
+}
+
+impl Effect {
+    /// Must be kept in sync with the match in `apply_event`.
+    #[must_use]
+    pub const fn of(event: &Event) -> Self {
+        match event {
+            Event::None
+            | Event::StartGame { .. }
+            | Event::EndKyoku
+            | Event::EndGame
+            | Event::Unknown(_) => Self::Ignored,
+            _ => Self::Mutated,
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub(super) enum MoveType {
@@ -19,18 +46,62 @@ pub(super) enum MoveType {
 }
 
 impl PlayerState {
+    /// Updates the state with `event` without checking it, which is the
+    /// lenient counterpart of `update_checked`. An event overflowing a fixed
+    /// capacity of the state, which only a malformed log can send, does not
+    /// panic; what does not fit is dropped with a warning.
     #[inline]
     pub fn update(&mut self, event: &Event) -> ActionCandidate {
         self.update_with_skip(event, false)
     }
 
+    /// Same as `update`, but also reports whether the event is tracked by
+    /// the state, which helps catching events a converter emits but the
+    /// state silently ignores.
+    #[inline]
+    pub fn update_ex(&mut self, event: &Event) -> (ActionCandidate, Effect) {
+        (self.update(event), Effect::of(event))
+    }
+
     pub fn update_with_skip(&mut self, event: &Event, skip_on_announce: bool) -> ActionCandidate {
-        if !skip_on_announce
-            || !matches!(
-                event,
-                Event::ReachAccepted { .. } | Event::Dora { .. } | Event::Hora { .. }
-            )
+        let cans = self.apply_event(event, skip_on_announce);
+
+        // `doras_owned` is bookkept on every move of a tile and on every new
+        // dora indicator, which is easy to get wrong around kans.
+        debug_assert_eq!(
+            {
+                let breakdown = self.dora_breakdown(&[]);
+                breakdown.dora + breakdown.aka
+            },
+            self.doras_owned[0],
+            "doras_owned drifts from the recount after {event:?}",
+        );
+
+        cans
+    }
+
+    fn apply_event(&mut self, event: &Event, skip_on_announce: bool) -> ActionCandidate {
+        self.last_event = Some(event.clone());
+        if matches!(event, Event::Unknown(_)) {
+            return self.last_cans;
+        }
+
+        // When a kakan follows another kan, the dora of the previous kan is
+        // revealed between the kakan and the chankan ron on it, which must
+        // not cancel the chance of the ron.
+        let is_dora_before_chankan =
+            matches!(event, Event::Dora { .. }) && self.chankan_chance.is_some();
+        if !is_dora_before_chankan
+            && (!skip_on_announce
+                || !matches!(
+                    event,
+                    Event::ReachAccepted { .. } | Event::Dora { .. } | Event::Hora { .. }
+                ))
         {
+            // The event ends the decision point the player was at, if any.
+            if self.last_cans.can_act() {
+                self.tiles_seen_at_last_decision = self.tiles_seen;
+            }
             self.last_cans = ActionCandidate {
                 target_actor: event.actor().unwrap_or(self.player_id),
                 ..Default::default()
@@ -38,6 +109,11 @@ impl PlayerState {
 
             if self.to_mark_same_cycle_furiten.take().is_some() {
                 self.at_furiten = true;
+                self.furiten_kind = if self.riichi_accepted[0] {
+                    FuritenKind::Riichi
+                } else {
+                    FuritenKind::Temporary
+                };
             }
             if self.chankan_chance.take().is_some() {
                 self.at_ippatsu = false;
@@ -46,6 +122,7 @@ impl PlayerState {
             self.ankan_candidates.clear();
             self.kakan_candidates.clear();
         }
+        self.track_next_draw(event);
 
         match *event {
             Event::StartKyoku {
@@ -62,6 +139,8 @@ impl PlayerState {
                 self.waits.fill(false);
                 self.dora_factor.fill(0);
                 self.tiles_seen.fill(0);
+                self.tiles_seen_at_last_decision.fill(0);
+                self.akas_seen.fill(0);
                 self.keep_shanten_discards.fill(false);
                 self.next_shanten_discards.fill(false);
                 self.forbidden_tiles.fill(false);
@@ -70,10 +149,11 @@ impl PlayerState {
                 self.bakaze = bakaze;
                 self.honba = honba;
                 self.kyotaku = kyotaku;
+                self.horas = 0;
                 self.oya = self.rel(oya) as u8;
-                self.jikaze = must_tile!(tu8!(E) + (4 - self.oya) % 4);
+                self.jikaze = self.seat_winds()[self.player_id as usize];
                 self.is_all_last = match self.bakaze.as_u8() {
-                    tu8!(S) => kyoku == 4,
+                    tu8!(S) => kyoku >= 4,
                     tu8!(W) => true,
                     _ => false,
                 };
@@ -85,7 +165,8 @@ impl PlayerState {
                 self.dora_indicators.clear();
                 self.doras_owned.fill(0);
                 self.doras_seen = 0;
-                self.akas_in_hand.fill(false);
+                self.nukidoras.fill(0);
+                self.akas_in_hand.fill(0);
 
                 self.ankan_candidates.clear();
                 self.kakan_candidates.clear();
@@ -94,9 +175,11 @@ impl PlayerState {
                 self.at_ippatsu = false;
                 self.at_rinshan = false;
                 self.at_furiten = false;
+                self.furiten_kind = FuritenKind::None;
                 self.to_mark_same_cycle_furiten = None;
 
                 self.is_menzen = true;
+                self.first_call_junme = None;
                 self.can_w_riichi = true;
                 self.is_w_riichi = false;
                 self.chis.clear();
@@ -107,18 +190,21 @@ impl PlayerState {
                 self.kans_on_board = 0;
                 self.tehai_len_div3 = 4;
                 self.has_next_shanten_discard = false;
-                self.tiles_left = 70;
+                self.tiles_left = self.rules.mode.tiles_left_at_start();
                 self.at_turn = 0;
 
                 self.kawa.iter_mut().for_each(|k| k.clear());
                 self.kawa_overview.iter_mut().for_each(|k| k.clear());
                 self.fuuro_overview.iter_mut().for_each(|k| k.clear());
                 self.ankan_overview.iter_mut().for_each(|k| k.clear());
+                self.paos.fill(None);
+                self.rinshan_pao = None;
                 self.intermediate_kan.clear();
                 self.intermediate_chi_pon = None;
 
                 self.riichi_declared.fill(false);
                 self.riichi_accepted.fill(false);
+                self.passed_after_riichi = [[false; 34]; 4];
 
                 self.last_self_tsumo = None;
                 self.last_kawa_tile = None;
@@ -153,7 +239,7 @@ impl PlayerState {
                     self.last_cans.can_ryukyoku = self.yaokyuu_kind_count() >= 9;
                 }
 
-                if !self.riichi_accepted[0] {
+                if !self.is_hand_frozen() {
                     // Does not update shanten
                     self.update_shanten_discards();
                 }
@@ -179,6 +265,7 @@ impl PlayerState {
                             jikaze: self.jikaze.as_u8(),
                             winning_tile: pai.deaka().as_u8(),
                             is_ron: false,
+                            allow_kuitan: self.rules.kuitan == KuitanRule::Allowed,
                         };
                         self.last_cans.can_tsumo_agari = agari_calc.has_yaku();
                     }
@@ -189,8 +276,18 @@ impl PlayerState {
                     return self.last_cans;
                 }
 
+                if self.rules.mode == GameMode::Sanma {
+                    // Under riichi the rest of the hand is locked, so only the
+                    // N just drawn can be extracted.
+                    self.last_cans.can_nukidora = if self.riichi_accepted[0] {
+                        pai.deaka() == t!(N)
+                    } else {
+                        self.tehai[tuz!(N)] > 0
+                    };
+                }
+
                 if self.riichi_accepted[0] {
-                    if self.kans_on_board < 4 {
+                    if self.rinshan_tiles_left() > 0 {
                         // Using Tenhou rule here.
                         self.last_cans.can_ankan = agari::check_ankan_after_riichi(
                             &self.tehai,
@@ -199,13 +296,13 @@ impl PlayerState {
                             false,
                         );
                         if self.last_cans.can_ankan {
-                            self.ankan_candidates.push(pai.deaka());
+                            push_saturating(&mut self.ankan_candidates, pai.deaka());
                         }
                     }
                     return self.last_cans;
                 }
 
-                if self.kans_on_board < 4 {
+                if self.rinshan_tiles_left() > 0 {
                     self.tehai
                         .iter()
                         .enumerate()
@@ -214,16 +311,16 @@ impl PlayerState {
                             let tile = must_tile!(tid);
                             if count == 4 {
                                 self.last_cans.can_ankan = true;
-                                self.ankan_candidates.push(tile);
+                                push_saturating(&mut self.ankan_candidates, tile);
                             } else if self.pons.contains(&(tid as u8)) {
                                 self.last_cans.can_kakan = true;
-                                self.kakan_candidates.push(tile);
+                                push_saturating(&mut self.kakan_candidates, tile);
                             }
                         });
                 }
 
                 self.last_cans.can_riichi = self.is_menzen
-                    && self.tiles_left >= 4
+                    && self.tiles_left >= self.rules.mode.num_players()
                     && self.scores[0] >= 1000
                     && (self.shanten == 0 || self.shanten == 1 && self.has_next_shanten_discard);
             }
@@ -234,8 +331,8 @@ impl PlayerState {
                 tsumogiri,
             } => {
                 let actor_rel = self.rel(actor);
-                self.kawa_overview[actor_rel].push(pai);
-                self.kawa[actor_rel].push(Some(KawaItem {
+                push_saturating(&mut self.kawa_overview[actor_rel], pai);
+                let item = Some(KawaItem {
                     kan: mem::take(&mut self.intermediate_kan),
                     chi_pon: self.intermediate_chi_pon.take(),
                     sutehai: Sutehai {
@@ -244,15 +341,30 @@ impl PlayerState {
                         is_tedashi: !tsumogiri,
                         is_riichi: self.riichi_declared[actor_rel]
                             && !self.riichi_accepted[actor_rel],
+                        is_called: false,
                     },
-                }));
+                });
+                push_saturating(&mut self.kawa[actor_rel], item);
                 self.last_kawa_tile = Some(pai);
+                for (passed, &accepted) in self
+                    .passed_after_riichi
+                    .iter_mut()
+                    .zip(&self.riichi_accepted)
+                {
+                    if accepted {
+                        passed[pai.deaka().as_usize()] = true;
+                    }
+                }
 
                 if actor_rel == 0 {
                     self.forbidden_tiles.fill(false);
                     self.move_tile(pai, MoveType::Discard);
+                    // The tile drawn is either discarded or kept in the hand
+                    // by now.
+                    self.last_self_tsumo = None;
 
                     self.at_rinshan = false;
+                    self.rinshan_pao = None;
                     self.at_ippatsu = false;
                     self.can_w_riichi = false;
 
@@ -261,7 +373,7 @@ impl PlayerState {
                     // Furiten state will be permanent once riichi is accepted,
                     // and of course, the shanten number will be frozen as well,
                     // so the calculations are skipped here.
-                    if !self.riichi_accepted[0] {
+                    if !self.is_hand_frozen() {
                         if self.next_shanten_discards[pai.deaka().as_usize()] {
                             self.shanten -= 1;
                         } else if !self.keep_shanten_discards[pai.deaka().as_usize()] {
@@ -275,6 +387,7 @@ impl PlayerState {
                     } else if !self.at_furiten && self.waits[pai.deaka().as_usize()] {
                         // Riichi furiten
                         self.at_furiten = true;
+                        self.furiten_kind = FuritenKind::Riichi;
                     }
 
                     return self.last_cans;
@@ -300,6 +413,7 @@ impl PlayerState {
                             jikaze: self.jikaze.as_u8(),
                             winning_tile: pai.deaka().as_u8(),
                             is_ron: true,
+                            allow_kuitan: self.rules.kuitan == KuitanRule::Allowed,
                         };
                         self.last_cans.can_ron_agari = agari_calc.has_yaku();
                     }
@@ -322,6 +436,7 @@ impl PlayerState {
                         // Mark as furiten immediately, following the behavior
                         // of Tenhou's furiten display.
                         self.at_furiten = true;
+                        self.furiten_kind = FuritenKind::Temporary;
                     }
                 }
 
@@ -329,25 +444,30 @@ impl PlayerState {
                     return self.last_cans;
                 }
 
-                if actor_rel == 3 && !pai.is_jihai() && self.tehai_len_div3 > 0 {
+                if self.rules.mode == GameMode::Yonma
+                    && actor_rel == 3
+                    && !pai.is_jihai()
+                    && self.tehai_len_div3 > 0
+                {
                     self.set_can_chi_from_tile(pai);
                 }
                 self.last_cans.can_pon = self.tehai[pai.deaka().as_usize()] >= 2;
                 self.last_cans.can_daiminkan =
-                    self.kans_on_board < 4 && self.tehai[pai.deaka().as_usize()] == 3;
+                    self.rinshan_tiles_left() > 0 && self.tehai[pai.deaka().as_usize()] == 3;
             }
 
             Event::Chi {
                 actor,
+                target,
                 consumed,
                 pai,
-                ..
             } => {
                 let actor_rel = self.rel(actor);
+                self.mark_called(target);
                 let mut result = array_vec!();
                 result.extend_from_slice(&consumed);
                 result.push(pai);
-                self.fuuro_overview[actor_rel].push(result);
+                push_saturating(&mut self.fuuro_overview[actor_rel], result);
                 self.intermediate_chi_pon = Some(ChiPon {
                     consumed,
                     target_tile: pai,
@@ -365,6 +485,8 @@ impl PlayerState {
 
                 self.last_cans.can_discard = true;
                 self.is_menzen = false;
+                self.first_call_junme
+                    .get_or_insert(self.kawa_overview[0].len() as u8);
                 self.tehai_len_div3 -= 1;
                 // Marked explicitly as `None` to let `Agent` impls set
                 // `tsumogiri` to false in the Dahai after Chi
@@ -380,25 +502,28 @@ impl PlayerState {
                 let min = a.min(b);
                 let max = a.max(b);
                 let deaka_tile_id = pai.deaka().as_usize();
-                self.chis.push(min.min(deaka_tile_id) as u8);
+                push_saturating(&mut self.chis, min.min(deaka_tile_id) as u8);
+                self.chis.sort_unstable();
 
                 // Forbid 喰い替え
                 if self.tehai[deaka_tile_id] > 0 {
                     self.forbidden_tiles[deaka_tile_id] = true;
                 }
-                if deaka_tile_id < min {
-                    if max % 9 < 8 {
-                        // Like 56s chi 4s, then 7s is not allowed to discard
-                        let bigger = max + 1;
-                        if self.tehai[bigger] > 0 {
-                            self.forbidden_tiles[bigger] = true;
+                if self.rules.kuikae == KuikaeRule::Full {
+                    if deaka_tile_id < min {
+                        if max % 9 < 8 {
+                            // Like 56s chi 4s, then 7s is not allowed to discard
+                            let bigger = max + 1;
+                            if self.tehai[bigger] > 0 {
+                                self.forbidden_tiles[bigger] = true;
+                            }
+                        }
+                    } else if deaka_tile_id > max && min % 9 > 0 {
+                        // Like 56s chi 7s, then 4s is not allowed to discard
+                        let smaller = min - 1;
+                        if self.tehai[smaller] > 0 {
+                            self.forbidden_tiles[smaller] = true;
                         }
-                    }
-                } else if deaka_tile_id > max && min % 9 > 0 {
-                    // Like 56s chi 7s, then 4s is not allowed to discard
-                    let smaller = min - 1;
-                    if self.tehai[smaller] > 0 {
-                        self.forbidden_tiles[smaller] = true;
                     }
                 }
 
@@ -418,12 +543,14 @@ impl PlayerState {
                 let mut result = array_vec!();
                 result.extend_from_slice(&consumed);
                 result.push(pai);
-                self.fuuro_overview[actor_rel].push(result);
+                push_saturating(&mut self.fuuro_overview[actor_rel], result);
                 self.intermediate_chi_pon = Some(ChiPon {
                     consumed,
                     target_tile: pai,
                 });
+                self.mark_called(target);
                 self.pad_kawa_for_pon_or_daiminkan(actor, target);
+                self.update_pao(actor_rel, target, pai);
 
                 if actor_rel != 0 {
                     consumed.iter().for_each(|&t| self.witness_tile(t));
@@ -437,6 +564,8 @@ impl PlayerState {
 
                 self.last_cans.can_discard = true;
                 self.is_menzen = false;
+                self.first_call_junme
+                    .get_or_insert(self.kawa_overview[0].len() as u8);
                 self.tehai_len_div3 -= 1;
                 // Marked explicitly as `None` to let `Agent` impls set
                 // `tsumogiri` to false in the Dahai after Pon
@@ -446,7 +575,8 @@ impl PlayerState {
                 consumed
                     .iter()
                     .for_each(|&t| self.move_tile(t, MoveType::FuuroConsume));
-                self.pons.push(pai.deaka().as_u8());
+                push_saturating(&mut self.pons, pai.deaka().as_u8());
+                self.pons.sort_unstable();
 
                 if self.tehai[pai.deaka().as_usize()] > 0 {
                     self.forbidden_tiles[pai.deaka().as_usize()] = true;
@@ -468,9 +598,11 @@ impl PlayerState {
                 let mut result = array_vec!();
                 result.extend_from_slice(&consumed);
                 result.push(pai);
-                self.fuuro_overview[actor_rel].push(result);
-                self.intermediate_kan.push(pai);
+                push_saturating(&mut self.fuuro_overview[actor_rel], result);
+                push_saturating(&mut self.intermediate_kan, pai);
+                self.mark_called(target);
                 self.pad_kawa_for_pon_or_daiminkan(actor, target);
+                self.update_pao(actor_rel, target, pai);
                 self.kans_on_board += 1;
 
                 if actor_rel != 0 {
@@ -484,14 +616,18 @@ impl PlayerState {
                 }
 
                 self.at_rinshan = true;
+                self.rinshan_pao = Some(self.rel(target) as u8);
                 self.is_menzen = false;
+                self.first_call_junme
+                    .get_or_insert(self.kawa_overview[0].len() as u8);
                 self.tehai_len_div3 -= 1;
 
                 self.update_doras_owned(0, pai);
                 consumed
                     .iter()
                     .for_each(|&t| self.move_tile(t, MoveType::FuuroConsume));
-                self.minkans.push(pai.deaka().as_u8());
+                push_saturating(&mut self.minkans, pai.deaka().as_u8());
+                self.minkans.sort_unstable();
 
                 // The shanten number and the shape of tenpai (if any) may be
                 // changed after a daiminkan.
@@ -503,13 +639,10 @@ impl PlayerState {
 
             Event::Kakan { actor, pai, .. } => {
                 let actor_rel = self.rel(actor);
-                for fuuro in &mut self.fuuro_overview[actor_rel] {
-                    if fuuro[0].deaka() == pai.deaka() {
-                        fuuro.push(pai);
-                        break;
-                    }
+                if let Some(idx) = self.pon_index(actor_rel, pai) {
+                    self.fuuro_overview[actor_rel][idx].push(pai);
                 }
-                self.intermediate_kan.push(pai);
+                push_saturating(&mut self.intermediate_kan, pai);
                 self.kans_on_board += 1;
 
                 if actor_rel != 0 {
@@ -520,6 +653,7 @@ impl PlayerState {
                     // 槍槓
                     if !self.at_furiten && self.waits[pai.deaka().as_usize()] {
                         self.last_cans.can_ron_agari = true;
+                        self.last_cans.is_chankan = true;
                         self.to_mark_same_cycle_furiten = Some(());
                         self.chankan_chance = Some(());
                     } else {
@@ -530,9 +664,11 @@ impl PlayerState {
                 }
 
                 self.at_rinshan = true;
+                self.rinshan_pao = None;
                 self.move_tile(pai, MoveType::FuuroConsume);
                 self.pons.retain(|&t| t != pai.deaka().as_u8());
-                self.minkans.push(pai.deaka().as_u8());
+                push_saturating(&mut self.minkans, pai.deaka().as_u8());
+                self.minkans.sort_unstable();
 
                 // The shanten number and the shape of tenpai (if any) may
                 // be changed after an kakan, because the kan'd tile may
@@ -548,8 +684,8 @@ impl PlayerState {
             Event::Ankan { actor, consumed } => {
                 let actor_rel = self.rel(actor);
                 let tile = consumed[0].deaka();
-                self.ankan_overview[actor_rel].push(tile);
-                self.intermediate_kan.push(tile);
+                push_saturating(&mut self.ankan_overview[actor_rel], tile);
+                push_saturating(&mut self.intermediate_kan, tile);
                 self.kans_on_board += 1;
 
                 self.can_w_riichi = false;
@@ -564,13 +700,15 @@ impl PlayerState {
                 }
 
                 self.at_rinshan = true;
+                self.rinshan_pao = None;
                 self.tehai_len_div3 -= 1;
                 consumed
                     .iter()
                     .for_each(|&t| self.move_tile(t, MoveType::FuuroConsume));
-                self.ankans.push(tile.as_u8());
+                push_saturating(&mut self.ankans, tile.as_u8());
+                self.ankans.sort_unstable();
 
-                if !self.riichi_accepted[0] {
+                if !self.is_hand_frozen() {
                     // The shanten number and the shape of tenpai (if any) may
                     // be changed after an ankan. See the example in daiminkan.
                     self.update_shanten();
@@ -578,6 +716,33 @@ impl PlayerState {
                 }
             }
 
+            Event::Nukidora { actor, pai } => {
+                let actor_rel = self.rel(actor);
+                self.nukidoras[actor_rel] += 1;
+
+                // Like a kan, it interrupts the turn order, but it is not a
+                // meld and does not break menzen.
+                self.can_w_riichi = false;
+                self.at_ippatsu = false;
+
+                if actor_rel != 0 {
+                    self.witness_tile(pai);
+                    self.update_doras_owned(actor_rel, pai);
+                    self.doras_owned[actor_rel] += 1;
+                    return self.last_cans;
+                }
+
+                self.at_rinshan = true;
+                self.rinshan_pao = None;
+                self.move_tile(pai, MoveType::FuuroConsume);
+                self.doras_owned[0] += 1;
+
+                if !self.is_hand_frozen() {
+                    self.update_shanten();
+                    self.update_waits_and_furiten();
+                }
+            }
+
             Event::Dora { dora_marker } => {
                 self.add_dora_indicator(dora_marker);
             }
@@ -602,10 +767,42 @@ impl PlayerState {
                 self.update_rank();
                 if actor_rel == 0 {
                     self.at_ippatsu = true;
+                    // The masks are of the hand before the riichi discard, and
+                    // are never computed again once the hand is frozen.
+                    self.keep_shanten_discards.fill(false);
+                    self.next_shanten_discards.fill(false);
+                    self.has_next_shanten_discard = false;
                 }
             }
 
-            _ => (),
+            Event::Hora { deltas, .. } => {
+                self.last_kyoku_ended_in_draw = false;
+                self.horas += 1;
+                if let Some(mut deltas) = deltas {
+                    // The first agari of a multi-ron takes the kyotakus, see
+                    // `MultiRonRule`.
+                    self.kyotaku = 0;
+                    deltas.rotate_left(self.player_id as usize);
+                    vec_add_assign(&mut self.scores, &deltas);
+                    self.update_rank();
+                }
+            }
+
+            Event::Ryukyoku { deltas, .. } => {
+                self.last_kyoku_ended_in_draw = true;
+                if let Some(mut deltas) = deltas {
+                    deltas.rotate_left(self.player_id as usize);
+                    vec_add_assign(&mut self.scores, &deltas);
+                    self.update_rank();
+                }
+            }
+
+            // See `Effect::of`.
+            Event::None
+            | Event::StartGame { .. }
+            | Event::EndKyoku
+            | Event::EndGame
+            | Event::Unknown(_) => (),
         };
 
         self.last_cans
@@ -615,32 +812,64 @@ impl PlayerState {
         ((actor + 4 - self.player_id) % 4) as usize
     }
 
-    /// Updates `tiles_seen` and `doras_seen`.
+    /// Whether the hand is frozen by the player's accepted riichi. From then
+    /// on the hand only changes by a tsumogiri or an ankan that keeps the
+    /// waits, so the shanten, waits and discard masks are never recomputed,
+    /// and only the furiten flags keep updating.
+    #[inline]
+    pub(super) const fn is_hand_frozen(&self) -> bool {
+        self.riichi_accepted[0]
+    }
+
+    /// The absolute seat that comes after `actor` in turn order, skipping the
+    /// empty seat 3 in sanma.
+    pub(super) const fn next_seat(&self, actor: u8) -> u8 {
+        (actor + 1) % self.rules.mode.num_players()
+    }
+
+    /// The relative seat of the empty seat 3 in sanma.
+    pub(super) const fn empty_seat(&self) -> Option<usize> {
+        match self.rules.mode {
+            GameMode::Yonma => None,
+            GameMode::Sanma => Some(self.rel(3)),
+        }
+    }
+
+    /// Updates `tiles_seen`, `akas_seen` and `doras_seen`.
     pub(super) fn witness_tile(&mut self, tile: Tile) {
         let tile_id = tile.deaka().as_usize();
         self.tiles_seen[tile_id] += 1;
         self.doras_seen += self.dora_factor[tile_id];
         if tile.is_aka() {
+            self.akas_seen[tile.as_usize() - 34] += 1;
             self.doras_seen += 1;
         }
     }
 
     /// Updates `akas_in_hand` and `doras_owned`, but does not update
     /// `tiles_seen` or `doras_seen`.
+    ///
+    /// An aka leaving the hand without having been recorded, which only a
+    /// broken log does and `update_checked` rejects as
+    /// `UpdateError::TileNotInHand`, saturates the counts at 0 instead of
+    /// underflowing.
     pub(super) fn move_tile(&mut self, tile: Tile, move_type: MoveType) {
         if tile.is_aka() {
             let aka_id = tile.as_usize() - 34;
+            let held = self.akas_in_hand[aka_id] > 0;
             match move_type {
                 MoveType::Tsumo => {
-                    self.akas_in_hand[aka_id] = true;
+                    self.akas_in_hand[aka_id] += 1;
                     self.doras_owned[0] += 1;
                 }
                 MoveType::Discard => {
-                    self.akas_in_hand[aka_id] = false;
-                    self.doras_owned[0] -= 1;
+                    self.akas_in_hand[aka_id] = self.akas_in_hand[aka_id].saturating_sub(1);
+                    if held {
+                        self.doras_owned[0] -= 1;
+                    }
                 }
                 MoveType::FuuroConsume => {
-                    self.akas_in_hand[aka_id] = false;
+                    self.akas_in_hand[aka_id] = self.akas_in_hand[aka_id].saturating_sub(1);
                 }
             }
         }
@@ -665,13 +894,15 @@ impl PlayerState {
     /// recounts doras (`doras_seen` and `doras_owned`) based on all the seen
     /// tiles.
     pub(super) fn add_dora_indicator(&mut self, tile: Tile) {
-        self.dora_indicators.push(tile);
+        if !push_saturating(&mut self.dora_indicators, tile) {
+            return;
+        }
 
         // Witness the tile so it can be added to `tiles_seen`, possibly also to
         // `doras_seen`. This must be done before adding `dora_factor`.
         self.witness_tile(tile);
 
-        let next = tile.next();
+        let next = self.rules.mode.dora_of(tile);
         self.dora_factor[next.as_usize()] += 1;
 
         // Count new dora in my tehai
@@ -687,6 +918,9 @@ impl PlayerState {
             if self.ankan_overview[i].contains(&next) {
                 new_dora_count_in_fuuro += 4;
             }
+            if next == t!(N) {
+                new_dora_count_in_fuuro += self.nukidoras[i];
+            }
             self.doras_owned[i] += new_dora_count_in_fuuro;
         }
 
@@ -694,20 +928,75 @@ impl PlayerState {
         self.doras_seen += self.tiles_seen[next.as_usize()];
     }
 
+    /// The index in `fuuro_overview[actor_rel]` of the pon `pai` can be added
+    /// to. A chi may start with the same tile, so all the tiles are compared.
+    pub(super) fn pon_index(&self, actor_rel: usize, pai: Tile) -> Option<usize> {
+        self.fuuro_overview[actor_rel]
+            .iter()
+            .position(|fuuro| fuuro.len() == 3 && fuuro.iter().all(|t| t.deaka() == pai.deaka()))
+    }
+
     pub(super) fn pad_kawa_for_pon_or_daiminkan(&mut self, abs_actor: u8, abs_target: u8) {
-        let mut i = (abs_target + 1) % 4;
+        let mut i = self.next_seat(abs_target);
         while i != abs_actor {
             let rel = self.rel(i);
-            self.kawa[rel].push(None);
-            i = (i + 1) % 4;
+            push_saturating(&mut self.kawa[rel], None);
+            i = self.next_seat(i);
+        }
+    }
+
+    /// Must be called after the meld is pushed into `fuuro_overview`.
+    fn update_pao(&mut self, actor_rel: usize, abs_target: u8, pai: Tile) {
+        if !pai.is_jihai() {
+            return;
+        }
+        let mut jihais = 0u8;
+        self.fuuro_overview[actor_rel]
+            .iter()
+            .map(|f| f[0].as_u8())
+            .filter(|&t| t >= tu8!(E))
+            .for_each(|t| jihais |= 1 << (t - tu8!(E)));
+        let daisangen_confirmed = (jihais & 0b1110000) == 0b1110000;
+        let daisuushii_confirmed = (jihais & 0b0001111) == 0b0001111;
+        if daisangen_confirmed && matches_tu8!(pai.as_u8(), P | F | C)
+            || daisuushii_confirmed && matches_tu8!(pai.as_u8(), E | S | W | N)
+        {
+            self.paos[actor_rel] = Some(self.rel(abs_target) as u8);
+        }
+    }
+
+    fn track_next_draw(&mut self, event: &Event) {
+        match *event {
+            Event::StartKyoku { oya, .. } => self.next_draw = self.rel(oya) as u8,
+            Event::Tsumo { actor, .. }
+            | Event::Dahai { actor, .. }
+            | Event::Chi { actor, .. }
+            | Event::Pon { actor, .. } => self.next_draw = self.rel(self.next_seat(actor)) as u8,
+            // Followed by a rinshan draw of the actor.
+            Event::Daiminkan { actor, .. }
+            | Event::Kakan { actor, .. }
+            | Event::Ankan { actor, .. }
+            | Event::Nukidora { actor, .. } => self.next_draw = self.rel(actor) as u8,
+            _ => (),
+        }
+    }
+
+    /// Marks the last discard of `abs_target` as called.
+    fn mark_called(&mut self, abs_target: u8) {
+        let target_rel = self.rel(abs_target);
+        if let Some(Some(item)) = self.kawa[target_rel].last_mut() {
+            item.sutehai.is_called = true;
         }
     }
 
     pub(super) fn pad_kawa_at_start(&mut self) {
+        let empty_seat = self.empty_seat();
         self.kawa
             .iter_mut()
+            .enumerate()
             .take(self.oya as usize)
-            .for_each(|kawa| kawa.push(None));
+            .filter(|&(rel, _)| Some(rel) != empty_seat)
+            .for_each(|(_, kawa)| push_saturating(kawa, None));
     }
 
     pub(super) fn set_can_chi_from_tile(&mut self, tile: Tile) {
@@ -725,7 +1014,7 @@ impl PlayerState {
             tehai_after[tile_id] = 0;
             tehai_after[tile_id + 1] -= 1;
             tehai_after[tile_id + 2] -= 1;
-            if literal_num < 7 {
+            if literal_num < 7 && self.rules.kuikae == KuikaeRule::Full {
                 tehai_after[tile_id + 3] = 0;
             }
             self.last_cans.can_chi_low = tehai_after.iter().any(|&t| t > 0);
@@ -747,7 +1036,7 @@ impl PlayerState {
             tehai_after[tile_id] = 0;
             tehai_after[tile_id - 2] -= 1;
             tehai_after[tile_id - 1] -= 1;
-            if literal_num > 3 {
+            if literal_num > 3 && self.rules.kuikae == KuikaeRule::Full {
                 tehai_after[tile_id - 3] = 0;
             }
             self.last_cans.can_chi_high = tehai_after.iter().any(|&t| t > 0);
@@ -805,6 +1094,7 @@ impl PlayerState {
         // 1. clearing same-cycle furiten
         // 2. the fact that furiten is nonsense if we are no longer tenpai
         self.at_furiten = false;
+        self.furiten_kind = FuritenKind::None;
         self.waits.fill(false);
 
         if self.shanten > 0 {
@@ -832,6 +1122,9 @@ impl PlayerState {
                 *v = self.tiles_seen[t] < 4;
             }
         }
+        if self.at_furiten {
+            self.furiten_kind = FuritenKind::Permanent;
+        }
     }
 
     pub(super) fn update_doras_owned(&mut self, actor_rel: usize, tile: Tile) {
@@ -850,7 +1143,7 @@ impl PlayerState {
             .iter()
             .chain(score_rel)
             .skip(self.rel(0))
-            .take(4)
+            .take(self.rules.mode.num_players() as usize)
             .copied()
             .enumerate()
             .collect();
@@ -863,3 +1156,23 @@ impl PlayerState {
             .unwrap() as u8
     }
 }
+
+/// Pushes `item` into a fixed capacity of the state, or drops it with a
+/// warning if it is full, see `UpdateError::CapacityExceeded`. Returns
+/// whether `item` is pushed.
+fn push_saturating<A>(vec: &mut ArrayVec<A>, item: A::Item) -> bool
+where
+    A: Array,
+    A::Item: fmt::Debug,
+{
+    match vec.try_push(item) {
+        None => true,
+        Some(item) => {
+            log::warn!(
+                "dropping {item:?} overflowing a capacity of {} of the state",
+                A::CAPACITY,
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Cross-checks between the planes of `PlayerState::encode_obs` and the
+//! accessors of `PlayerState`, so that the two cannot drift apart as either
+//! evolves.
+//!
+//! The planes are looked up by their names, such as those of
+//! `obs_plane_names`, rather than by fixed offsets. This module is built for
+//! the tests of this crate, and for downstream crates under the `testing`
+//! feature, so they can run the same checks against their own encoder
+//! options.
+
+use crate::mjai::Event;
+use crate::state::{PlayerState, Rules};
+use crate::tile::Tile;
+use crate::{must_tile, t};
+
+use anyhow::{ensure, Context, Result};
+use ndarray::prelude::*;
+
+/// Decodes the planes of an obs by name.
+pub struct ObsPlanes<'a> {
+    obs: ArrayView2<'a, f32>,
+    names: &'a [&'a str],
+}
+
+impl<'a> ObsPlanes<'a> {
+    /// `names` are the names of the planes of `obs` in order.
+    pub fn new(obs: ArrayView2<'a, f32>, names: &'a [&'a str]) -> Result<Self> {
+        ensure!(
+            obs.nrows() == names.len(),
+            "{} planes but {} names",
+            obs.nrows(),
+            names.len(),
+        );
+        Ok(Self { obs, names })
+    }
+
+    pub fn plane(&self, name: &str) -> Result<ArrayView1<'a, f32>> {
+        let idx = self
+            .names
+            .iter()
+            .position(|&n| n == name)
+            .with_context(|| format!("no plane named {name}"))?;
+        Ok(self.obs.row(idx))
+    }
+
+    /// Whether the plane is filled, for the planes that are either filled or
+    /// left zero as a whole.
+    pub fn flag(&self, name: &str) -> Result<bool> {
+        let filled = self.plane(name)?.iter().filter(|&&v| v != 0.).count();
+        ensure!(
+            filled == 0 || filled == 34,
+            "plane {name} is partially filled",
+        );
+        Ok(filled == 34)
+    }
+
+    /// The value a plane is filled with as a whole.
+    pub fn scalar(&self, name: &str) -> Result<f32> {
+        let plane = self.plane(name)?;
+        let v = plane[0];
+        ensure!(
+            plane.iter().all(|&x| (x - v).abs() < f32::EPSILON),
+            "plane {name} is not uniform",
+        );
+        Ok(v)
+    }
+
+    /// Decodes the thermometer of `{prefix}_1` to `{prefix}_{n}` of each
+    /// column, where the column of `{prefix}_{i}` is set iff the count of the
+    /// tile is at least `i`.
+    pub fn thermometer(&self, prefix: &str, n: usize) -> Result<[u8; 34]> {
+        let mut counts = [0; 34];
+        for i in 1..=n {
+            let name = format!("{prefix}_{i}");
+            for (tid, &v) in self.plane(&name)?.iter().enumerate() {
+                if v == 0. {
+                    continue;
+                }
+                ensure!(
+                    counts[tid] as usize == i - 1,
+                    "plane {name} is not a thermometer at {}",
+                    must_tile!(tid),
+                );
+                counts[tid] += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// The player's hand from `tehai_{n}`, with the akas counted as their
+    /// normal fives.
+    pub fn tehai(&self) -> Result<[u8; 34]> {
+        self.thermometer("tehai", 4)
+    }
+
+    pub fn waits(&self) -> Result<[bool; 34]> {
+        let mut waits = [false; 34];
+        for (w, &v) in waits.iter_mut().zip(self.plane("waits")?) {
+            *w = v != 0.;
+        }
+        Ok(waits)
+    }
+
+    /// In relative seats, from `self_riichi_accepted` and
+    /// `riichi_accepted_{rel}`.
+    pub fn riichi_accepted(&self) -> Result<[bool; 4]> {
+        Ok([
+            self.flag("self_riichi_accepted")?,
+            self.flag("riichi_accepted_1")?,
+            self.flag("riichi_accepted_2")?,
+            self.flag("riichi_accepted_3")?,
+        ])
+    }
+
+    /// The dora indicators sorted by tile id, where a five is an aka if the
+    /// aka plane of its suit is filled.
+    pub fn dora_indicators(&self) -> Result<Vec<Tile>> {
+        let counts = self.thermometer("dora_indicator", 4)?;
+        let mut tiles = vec![];
+        for (tid, &count) in counts.iter().enumerate() {
+            tiles.extend((0..count).map(|_| must_tile!(tid)));
+        }
+        for (suit, aka) in [("m", t!(5mr)), ("p", t!(5pr)), ("s", t!(5sr))] {
+            if self.flag(&format!("dora_indicator_aka_{suit}"))? {
+                let five = tiles
+                    .iter_mut()
+                    .find(|tile| **tile == aka.deaka())
+                    .with_context(|| format!("aka {aka} without any five of its suit"))?;
+                *five = aka;
+            }
+        }
+        tiles.sort_unstable_by_key(|tile| tile.as_u8());
+        Ok(tiles)
+    }
+
+    /// In relative seats, clamped to [0, 100000] as encoded.
+    pub fn scores(&self) -> Result<[i32; 4]> {
+        let mut scores = [0; 4];
+        for (i, score) in scores.iter_mut().enumerate() {
+            *score = (self.scalar(&format!("score_{i}"))? * 100_000.).round() as i32;
+        }
+        Ok(scores)
+    }
+}
+
+/// Checks that the planes of `obs`, encoded from `state` and named by
+/// `names`, decode to what the accessors of `state` return. `with_aka` is
+/// false if the akas are encoded as their normal fives, as by
+/// `encode_obs_no_aka`.
+pub fn check_obs(
+    state: &PlayerState,
+    obs: ArrayView2<'_, f32>,
+    names: &[&str],
+    with_aka: bool,
+) -> Result<()> {
+    let planes = ObsPlanes::new(obs, names)?;
+
+    let tehai = planes.tehai()?;
+    ensure!(
+        tehai == state.tehai(),
+        "tehai {tehai:?} in obs, {:?} by tehai()",
+        state.tehai(),
+    );
+
+    let waits = planes.waits()?;
+    ensure!(
+        waits == state.waits(),
+        "waits {waits:?} in obs, {:?} by waits()",
+        state.waits(),
+    );
+
+    let riichi_accepted = planes.riichi_accepted()?;
+    ensure!(
+        riichi_accepted == state.riichi_accepted(),
+        "riichi accepted {riichi_accepted:?} in obs, {:?} by riichi_accepted()",
+        state.riichi_accepted(),
+    );
+
+    let dora_indicators = planes.dora_indicators()?;
+    let mut expected: Vec<_> = state
+        .dora_indicators()
+        .iter()
+        .map(|&t| if with_aka { t } else { t.deaka() })
+        .collect();
+    expected.sort_unstable_by_key(|tile| tile.as_u8());
+    ensure!(
+        dora_indicators == expected,
+        "dora indicators {dora_indicators:?} in obs, {expected:?} by dora_indicators()",
+    );
+
+    let scores = planes.scores()?;
+    let expected = state.scores().map(|s| s.clamp(0, 100_000));
+    ensure!(
+        scores == expected,
+        "scores {scores:?} in obs, {expected:?} by scores()",
+    );
+
+    Ok(())
+}
+
+/// Replays `events` from every seat of `rules.mode`, and runs `check_obs` on
+/// the obs `encode` returns at every point where the player can act. Returns
+/// the number of obs checked.
+pub fn check_replay<F>(
+    events: &[Event],
+    rules: Rules,
+    names: &[&str],
+    with_aka: bool,
+    mut encode: F,
+) -> Result<usize>
+where
+    F: FnMut(&PlayerState) -> Array2<f32>,
+{
+    let mut checked = 0;
+    for player_id in 0..rules.mode.num_players() {
+        let mut state = PlayerState::with_rules(player_id, rules);
+        for (idx, ev) in events.iter().enumerate() {
+            if !state.update(ev).can_act() {
+                continue;
+            }
+            let obs = encode(&state);
+            check_obs(&state, obs.view(), names, with_aka).with_context(|| {
+                format!(
+                    "player {player_id} at #{idx} {ev:?}\nstate:\n{}",
+                    state.brief_info(),
+                )
+            })?;
+            checked += 1;
+        }
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::convert::tenhou::tenhou_to_mjai;
+    use crate::state::obs_plane_names;
+
+    const LOG: &str = include_str!("convert/data/tenhou_sample.json");
+
+    #[test]
+    fn tenhou_sample() {
+        let events = tenhou_to_mjai(LOG).unwrap();
+        let names = obs_plane_names();
+        let rules = Rules::default();
+
+        let checked = check_replay(&events, rules, &names, true, |state| {
+            state.encode_obs(false).0
+        })
+        .unwrap();
+        assert!(checked > 0);
+        let checked_no_aka = check_replay(&events, rules, &names, false, |state| {
+            state.encode_obs_no_aka(false).0
+        })
+        .unwrap();
+        assert_eq!(checked_no_aka, checked);
+
+        // A drift in any of the planes is caught.
+        for name in ["tehai_1", "waits", "dora_indicator_1", "score_0"] {
+            let idx = names.iter().position(|&n| n == name).unwrap();
+            check_replay(&events, rules, &names, true, |state| {
+                let mut obs = state.encode_obs(false).0;
+                let v = &mut obs[[idx, 33]];
+                *v = if *v == 0. { 1. } else { 0. };
+                obs
+            })
+            .unwrap_err();
+        }
+    }
+}
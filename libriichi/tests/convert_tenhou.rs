@@ -0,0 +1,66 @@
+use riichi::mjai::Event;
+use riichi::state::PlayerState;
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::process::Command;
+
+use flate2::read::GzDecoder;
+
+const SAMPLE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/convert/data/tenhou_sample.json",
+);
+
+/// The converted sample must replay through the states of all four seats.
+#[test]
+fn sample() {
+    let output = Command::new(env!("CARGO_BIN_EXE_convert_tenhou"))
+        .arg(SAMPLE)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "convert_tenhou failed:\n{}",
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let mjai = String::from_utf8(output.stdout).unwrap();
+
+    let events: Vec<_> = mjai
+        .lines()
+        .flat_map(|line| Event::parse_line(line).unwrap())
+        .collect();
+    assert!(matches!(events.first(), Some(Event::StartGame { .. })));
+    assert_eq!(events.last(), Some(&Event::EndGame));
+    let kyokus = events
+        .iter()
+        .filter(|ev| matches!(ev, Event::StartKyoku { .. }))
+        .count();
+    assert_eq!(kyokus, 4);
+
+    let mut states = [0, 1, 2, 3].map(PlayerState::new);
+    for (idx, ev) in events.iter().enumerate() {
+        for state in &mut states {
+            if let Err(err) = state.update_checked_with_skip(ev, true) {
+                panic!("{err:?} at #{idx} {ev:?}\nstate:\n{}", state.brief_info());
+            }
+        }
+    }
+
+    // The same lines are written to a gzip compressed file.
+    let path = env::temp_dir().join(format!("riichi-tenhou-{}.json.gz", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_convert_tenhou"))
+        .arg(SAMPLE)
+        .arg(&path)
+        .status()
+        .unwrap();
+    let mut written = String::new();
+    if status.success() {
+        GzDecoder::new(fs::File::open(&path).unwrap())
+            .read_to_string(&mut written)
+            .unwrap();
+    }
+    fs::remove_file(&path).ok();
+    assert!(status.success());
+    assert_eq!(written, mjai);
+}
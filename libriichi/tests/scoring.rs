@@ -0,0 +1,241 @@
+//! The scoring conformance pack in `data/scoring_cases.json`, run against
+//! `AgariCalculator` and `Point`.
+//!
+//! `sources` names the references the expected results were taken from, and
+//! each case cites one of them in `source`. Each case is a winning hand along
+//! with its context and the expected result:
+//!
+//! * `hand` is the closed part including the winning tile, and the melds
+//!   (`chis`, `pons`, `minkans` and `ankans`) list one group each, all in
+//!   tenhou notation such as `"234m 55z"`. Single tiles (`win`, `bakaze` and
+//!   `jikaze`) are in mjai notation. The seat is oya iff `jikaze` is E.
+//! * `flags` are the situational yakus, passed to the scorer as additional
+//!   hans. 門前清自摸和 is implied by a menzen `tsumo`.
+//! * `yakus` are the yakus found in the hand, in any order, and `han`
+//!   includes `flags` and `dora`. `fu` is only given for hands under 5 han.
+//!   A yakuman hand gives the number of yakumans in `yakuman` instead.
+//! * `points` is written as `"7700"` for a ron, `"1000/2000"` for a tsumo by
+//!   ko and `"2600 all"` for a tsumo by oya. Besides being compared with
+//!   `Point`, it is recomputed from `han`, `fu` and `yakuman` by the score
+//!   table in `table_points`, so that a typo in either side fails the case.
+
+use riichi::algo::agari::{Agari, AgariCalculator};
+use riichi::algo::yaku::Yaku;
+use riichi::hand::hand;
+use riichi::tile::Tile;
+use riichi::tu8;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+use serde_json as json;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Pack {
+    sources: HashMap<String, String>,
+    cases: Vec<Case>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    id: String,
+    source: String,
+    hand: String,
+    win: Tile,
+    #[serde(default)]
+    chis: Vec<String>,
+    #[serde(default)]
+    pons: Vec<String>,
+    #[serde(default)]
+    minkans: Vec<String>,
+    #[serde(default)]
+    ankans: Vec<String>,
+    bakaze: Tile,
+    jikaze: Tile,
+    tsumo: bool,
+    #[serde(default)]
+    flags: Vec<Yaku>,
+    #[serde(default)]
+    dora: u8,
+
+    yakus: Vec<Yaku>,
+    han: Option<u8>,
+    fu: Option<u8>,
+    yakuman: Option<u8>,
+    points: String,
+}
+
+impl Case {
+    fn check(&self) -> Result<()> {
+        let tehai = hand(&self.hand)?;
+        let winning_tile = self.win.deaka().as_u8();
+        ensure!(
+            tehai[winning_tile as usize] > 0,
+            "winning tile {} is not in hand",
+            self.win,
+        );
+        let chis = melds(&self.chis, 3, true)?;
+        let pons = melds(&self.pons, 3, false)?;
+        let minkans = melds(&self.minkans, 4, false)?;
+        let ankans = melds(&self.ankans, 4, false)?;
+        let is_menzen = chis.is_empty() && pons.is_empty() && minkans.is_empty();
+
+        let mut additional_hans = (self.tsumo && is_menzen) as u8;
+        for &flag in &self.flags {
+            additional_hans += match flag {
+                Yaku::DoubleRiichi => 2,
+                Yaku::Riichi
+                | Yaku::Ippatsu
+                | Yaku::Haitei
+                | Yaku::Houtei
+                | Yaku::Rinshan
+                | Yaku::Chankan => 1,
+                _ => bail!("{flag:?} is not a flag"),
+            };
+        }
+
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen,
+            chis: &chis,
+            pons: &pons,
+            minkans: &minkans,
+            ankans: &ankans,
+            bakaze: self.bakaze.as_u8(),
+            jikaze: self.jikaze.as_u8(),
+            winning_tile,
+            is_ron: !self.tsumo,
+            allow_kuitan: true,
+        };
+        let (agari, yakus) = calc
+            .agari_detail(additional_hans, self.dora)
+            .context("not a winning hand")?;
+
+        let mut got: Vec<_> = yakus.iter().map(|&(yaku, _)| yaku).collect();
+        let mut expected = self.yakus.clone();
+        got.sort_unstable();
+        expected.sort_unstable();
+        ensure!(got == expected, "yakus: got {got:?}, expected {expected:?}");
+
+        match (agari, self.yakuman, self.han) {
+            (Agari::Yakuman(n), Some(expected), None) => {
+                ensure!(n == expected, "yakuman: got {n}, expected {expected}");
+            }
+            (Agari::Normal { fu, han }, None, Some(expected)) => {
+                ensure!(han == expected, "han: got {han}, expected {expected}");
+                if let Some(expected) = self.fu {
+                    ensure!(fu == expected, "fu: got {fu}, expected {expected}");
+                }
+            }
+            (_, None, None) | (_, Some(_), Some(_)) => {
+                bail!("exactly one of han and yakuman must be given")
+            }
+            _ => bail!("got {agari:?}, expected {self:?}"),
+        }
+
+        let is_oya = self.jikaze.as_u8() == tu8!(E);
+        let point = agari.into_point(is_oya);
+        let got = if !self.tsumo {
+            point.ron.to_string()
+        } else if is_oya {
+            format!("{} all", point.tsumo_ko)
+        } else {
+            format!("{}/{}", point.tsumo_ko, point.tsumo_oya)
+        };
+        ensure!(
+            got == self.points,
+            "points: got {got}, expected {}",
+            self.points,
+        );
+
+        let table = self.table_points()?;
+        ensure!(
+            table == self.points,
+            "points: the score table gives {table}, expected {}",
+            self.points,
+        );
+
+        Ok(())
+    }
+
+    /// Looks up the expected points in the score table from the expected
+    /// `han`, `fu` and `yakuman` alone, independently of `Point`.
+    fn table_points(&self) -> Result<String> {
+        let basic = match (self.yakuman, self.han, self.fu) {
+            (Some(n), None, _) => 8000 * n as i32,
+            (None, Some(han), _) if han >= 13 => 8000,
+            (None, Some(han), _) if han >= 11 => 6000,
+            (None, Some(han), _) if han >= 8 => 4000,
+            (None, Some(han), _) if han >= 6 => 3000,
+            (None, Some(han), _) if han >= 5 => 2000,
+            (None, Some(han), Some(fu)) => ((fu as i32) << (han + 2)).min(2000),
+            _ => bail!("fu must be given for hands under 5 han"),
+        };
+
+        let ceil = |p: i32| (p + 99) / 100 * 100;
+        let is_oya = self.jikaze.as_u8() == tu8!(E);
+        let ret = match (self.tsumo, is_oya) {
+            (false, false) => ceil(basic * 4).to_string(),
+            (false, true) => ceil(basic * 6).to_string(),
+            (true, false) => format!("{}/{}", ceil(basic), ceil(basic * 2)),
+            (true, true) => format!("{} all", ceil(basic * 2)),
+        };
+        Ok(ret)
+    }
+}
+
+/// Parses each group into the tile id the scorer expects, which is the lowest
+/// tile for a chi.
+fn melds(groups: &[String], len: u8, is_chi: bool) -> Result<Vec<u8>> {
+    groups
+        .iter()
+        .map(|group| {
+            let tiles = hand(group)?;
+            let first = tiles.iter().position(|&n| n > 0).context("empty meld")? as u8;
+            let is_valid = if is_chi {
+                first < 3 * 9
+                    && first % 9 < 7
+                    && (first..first + 3).all(|t| tiles[t as usize] == 1)
+                    && tiles.iter().sum::<u8>() == 3
+            } else {
+                tiles[first as usize] == len && tiles.iter().sum::<u8>() == len
+            };
+            ensure!(is_valid, "invalid meld {group}");
+            Ok(first)
+        })
+        .collect()
+}
+
+#[test]
+fn scoring_cases() {
+    let Pack { sources, cases } = json::from_str(include_str!("data/scoring_cases.json")).unwrap();
+
+    let mut ids = HashSet::new();
+    for case in &cases {
+        assert!(ids.insert(&case.id), "duplicate case id {}", case.id);
+        assert!(
+            sources.contains_key(&case.source),
+            "case {} cites an unknown source {}",
+            case.id,
+            case.source,
+        );
+    }
+
+    let failures: Vec<_> = cases
+        .iter()
+        .filter_map(|case| {
+            case.check()
+                .err()
+                .map(|err| format!("{}: {err:#}", case.id))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} scoring cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n"),
+    );
+}
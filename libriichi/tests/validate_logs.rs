@@ -0,0 +1,90 @@
+use riichi::agent::{RuleBased, Tsumogiri};
+use riichi::mjai::Event;
+use riichi::simulate::simulate_game;
+use riichi::state::Rules;
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// Games simulated by the arena must pass every check of `validate_logs`,
+/// which are not run again with the index of the first run, except for a log
+/// touched since.
+#[test]
+fn simulated_logs() {
+    let dir = env::temp_dir().join(format!("riichi-simulated-logs-{}", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+
+    for seed in 0..4 {
+        let events = if seed % 2 == 0 {
+            simulate_game(|id| Ok(RuleBased(id)), (seed, 0), Rules::default())
+        } else {
+            simulate_game(|id| Ok(Tsumogiri(id)), (seed, 0), Rules::default())
+        }
+        .unwrap();
+        let log: String = events.iter().map(|ev| ev.to_line() + "\n").collect();
+        assert!(matches!(events.first(), Some(Event::StartGame { .. })));
+        assert!(matches!(events.last(), Some(Event::EndGame)));
+        fs::write(dir.join(format!("{seed}.json")), log).unwrap();
+    }
+
+    let index = dir.with_extension("index.jsonl");
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_validate_logs"))
+            .arg("--index")
+            .arg(&index)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    let first = run();
+    let second = run();
+
+    // Rewrite a log as is until its mtime changes.
+    let touched = dir.join("0.json");
+    let content = fs::read(&touched).unwrap();
+    let mtime = || fs::metadata(&touched).unwrap().modified().unwrap();
+    let before = mtime();
+    while mtime() == before {
+        thread::sleep(Duration::from_millis(10));
+        fs::write(&touched, &content).unwrap();
+    }
+    let third = run();
+
+    fs::remove_dir_all(&dir).ok();
+    fs::remove_file(&index).ok();
+
+    let [first, second, third] = [first, second, third].map(|output| {
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(
+            output.status.success(),
+            "validate_logs failed:\n{stdout}\n{}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+        stdout
+    });
+    assert!(first.contains("0 logs skipped, 4 parsed"), "{first}");
+    // Nothing is parsed again if the logs are unchanged.
+    assert!(second.contains("4 logs skipped, 0 parsed"), "{second}");
+    // Only the touched log is parsed again, and its outcome is reused.
+    assert!(
+        third.contains("3 logs skipped, 1 parsed, of which 1 unchanged"),
+        "{third}",
+    );
+}
+
+/// A misspelled option is an error rather than the directory to check.
+#[test]
+fn unknown_option() {
+    let output = Command::new(env!("CARGO_BIN_EXE_validate_logs"))
+        .arg("--strcit")
+        .arg(env::temp_dir())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains(r#"unknown option "--strcit""#), "{stderr}");
+    assert!(stderr.contains("Usage: validate_logs"), "{stderr}");
+}